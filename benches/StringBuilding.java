@@ -0,0 +1,15 @@
+package benches;
+
+class StringBuilding {
+    public static void main(String[] args) {
+        int length = 0;
+        for (int i = 0; i < 50; i++) {
+            StringBuilder builder = new StringBuilder();
+            for (int j = 0; j < 20; j++) {
+                builder.append("item ").append(j).append(", ");
+            }
+            length += builder.toString().length();
+        }
+        System.out.println(length);
+    }
+}
@@ -0,0 +1,104 @@
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use bumpalo::Bump;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use rusty_java::jimage::{self, JImage};
+use rusty_java::reader::ClassReader;
+use rusty_java::vm::Vm;
+
+/// Programs in this directory which are run as benchmarks, after being compiled with the JDK's
+/// compiler.
+const PROGRAMS: &[&str] = &["Fib", "NBody", "StringBuilding"];
+
+/// A subset of java.base which covers the classes most programs load on startup, and a few of
+/// the larger classes from the collections framework.
+const JAVA_BASE_CLASSES: &[&str] = &[
+    "java/lang/Object",
+    "java/lang/String",
+    "java/lang/StringBuilder",
+    "java/lang/Integer",
+    "java/lang/Character",
+    "java/lang/Math",
+    "java/lang/System",
+    "java/lang/Thread",
+    "java/util/ArrayList",
+    "java/util/HashMap",
+    "java/util/TreeMap",
+];
+
+/// Runs each program's main method repeatedly in the same vm, so the classes it uses are only
+/// loaded and initialized once, before measuring.
+fn programs(c: &mut Criterion) {
+    let benches_dir = Path::new(file!()).parent().unwrap();
+
+    for name in PROGRAMS {
+        let source_file_path = benches_dir.join(name).with_extension("java");
+        compile(&source_file_path);
+
+        let class_file_path = source_file_path.with_extension("class");
+        let arena = Bump::new();
+        let vm = Vm::new(&arena, io::sink());
+        let class = vm
+            .load_class_file(class_file_path.to_str().unwrap())
+            .unwrap();
+
+        c.bench_function(name, |b| b.iter(|| vm.run_main(class, &[]).unwrap()));
+    }
+}
+
+/// Reads and loads classes from the JDK, each time in a new vm.
+fn class_loading(c: &mut Criterion) {
+    let java_home = jimage::find_java_home().expect("failed to find java home");
+    let jimage = JImage::open_java_home(&java_home).unwrap();
+    let class_files: Vec<_> = JAVA_BASE_CLASSES
+        .iter()
+        .map(|name| jimage.find_class(name).unwrap().unwrap())
+        .collect();
+
+    c.bench_function("read_class_files", |b| {
+        b.iter_batched_ref(
+            Bump::new,
+            |arena| {
+                for bytes in &class_files {
+                    ClassReader::from_slice(arena, bytes)
+                        .read_class_file()
+                        .unwrap();
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    c.bench_function("load_classes", |b| {
+        b.iter_batched_ref(
+            Bump::new,
+            |arena| {
+                let vm = Vm::new(arena, io::sink()).with_java_home(&java_home);
+                for name in JAVA_BASE_CLASSES {
+                    vm.load_class_file(name).unwrap();
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+/// Compiles a program, unless it hasn't changed since it was last compiled.
+fn compile(source_file_path: &Path) {
+    let stamp_path = source_file_path.with_extension("stamp");
+    let is_stale = match (source_file_path.metadata(), stamp_path.metadata()) {
+        (Ok(source), Ok(stamp)) => source.modified().unwrap() >= stamp.modified().unwrap(),
+        _ => true,
+    };
+
+    if is_stale {
+        jdk_tools::compiler::compile(&[source_file_path], &[])
+            .unwrap_or_else(|e| panic!("failed to compile {source_file_path:?}: {e}"));
+        File::create(stamp_path).unwrap();
+    }
+}
+
+criterion_group!(benches, programs, class_loading);
+criterion_main!(benches);
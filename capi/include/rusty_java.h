@@ -0,0 +1,92 @@
+/*
+ * C API for embedding rusty-java, implemented by the rusty-java-capi crate.
+ *
+ * Functions which can fail return RJ_OK or RJ_ERROR, and rj_vm_last_error returns the message of
+ * the last error. A vm must not be used from several threads at once.
+ */
+
+#ifndef RUSTY_JAVA_H
+#define RUSTY_JAVA_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define RJ_OK 0
+#define RJ_ERROR (-1)
+
+/* The types of arguments and results. */
+#define RJ_VOID 0
+#define RJ_BOOLEAN 1
+#define RJ_INT 2
+#define RJ_LONG 3
+#define RJ_FLOAT 4
+#define RJ_DOUBLE 5
+#define RJ_STRING 6
+
+typedef struct rj_vm rj_vm;
+
+/*
+ * An argument or result of a Java method. Booleans are stored in i, as 0 or 1, and strings are
+ * NULL for Java's null.
+ */
+typedef struct rj_value {
+    int type;
+    union {
+        int32_t i;
+        int64_t j;
+        float f;
+        double d;
+        const char *s;
+    } as;
+} rj_value;
+
+/* Creates a vm, which must be freed with rj_vm_free. */
+rj_vm *rj_vm_new(void);
+
+void rj_vm_free(rj_vm *vm);
+
+/*
+ * Returns the message of the last error, or NULL if there hasn't been one. The message is valid
+ * until the next error, or until the vm is freed.
+ */
+const char *rj_vm_last_error(const rj_vm *vm);
+
+/* Sets the class path, in the platform's format. This fails once the vm has loaded a class. */
+int rj_vm_set_class_path(rj_vm *vm, const char *class_path);
+
+/* Loads and initializes a class, given its binary name (e.g. "java/lang/String"). */
+int rj_vm_load_class(rj_vm *vm, const char *class_name);
+
+/*
+ * Calls a static method, whose descriptor is made from the types of the arguments and
+ * return_type. The result is written to result if it isn't NULL, and a string result must be
+ * freed with rj_string_free.
+ */
+int rj_vm_invoke_static(rj_vm *vm, const char *class_name, const char *name, const rj_value *args,
+                        size_t num_args, int return_type, rj_value *result);
+
+/*
+ * Runs the main method of a class, and writes its exit status to status if it isn't NULL. The
+ * exit status is the one passed to System.exit, or 0 if main returns.
+ */
+int rj_vm_run_main(rj_vm *vm, const char *class_name, const char *const *args, size_t num_args,
+                   int32_t *status);
+
+/*
+ * Moves up to len bytes of the output written to System.out into buf, returning how many were
+ * moved. The output is kept by the vm until it's read. Nothing is moved if buf is NULL.
+ */
+size_t rj_vm_read_stdout(rj_vm *vm, char *buf, size_t len);
+
+/* Frees a string returned by the vm. */
+void rj_string_free(const char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,393 @@
+//! A C API for embedding the vm in programs written in other languages, which are declared in
+//! `include/rusty_java.h`.
+//!
+//! Functions which can fail return [`RJ_OK`] or [`RJ_ERROR`], and the message of the last error
+//! is returned by [`rj_vm_last_error`]. Panics are caught and reported as errors, since they
+//! can't unwind into the caller.
+
+use std::any::Any;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use std::sync::{Arc, Mutex};
+
+use rusty_java::call_frame::JvmValue;
+use rusty_java::class_path::ClassPath;
+use rusty_java::convert::{FromJvm, ToJvm};
+use rusty_java::error::{Context, Error, Result};
+use rusty_java::owned_vm::OwnedVm;
+use rusty_java::vm::Vm;
+
+pub const RJ_OK: c_int = 0;
+pub const RJ_ERROR: c_int = -1;
+
+pub const RJ_VOID: c_int = 0;
+pub const RJ_BOOLEAN: c_int = 1;
+pub const RJ_INT: c_int = 2;
+pub const RJ_LONG: c_int = 3;
+pub const RJ_FLOAT: c_int = 4;
+pub const RJ_DOUBLE: c_int = 5;
+pub const RJ_STRING: c_int = 6;
+
+/// A vm, which is only created when it's first used so that it can be configured beforehand.
+pub struct RjVm {
+    class_path: Option<ClassPath>,
+    vm: Option<OwnedVm<Stdout>>,
+    stdout: Stdout,
+    last_error: Option<CString>,
+}
+
+impl RjVm {
+    fn vm(&mut self) -> &OwnedVm<Stdout> {
+        let class_path = self.class_path.clone();
+        let stdout = self.stdout.clone();
+        self.vm.get_or_insert_with(|| {
+            OwnedVm::with_config(stdout, |vm| match class_path {
+                Some(class_path) => vm.with_class_path(class_path),
+                None => vm,
+            })
+        })
+    }
+}
+
+/// Output written to `System.out`, which is kept until it's read by [`rj_vm_read_stdout`].
+#[derive(Clone, Default)]
+struct Stdout(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for Stdout {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// An argument or result of a Java method, whose type is one of the `RJ_*` type constants.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct RjValue {
+    pub ty: c_int,
+    pub value: RjValueData,
+}
+
+/// Booleans are stored in `i`, as 0 or 1. Strings are null for Java's null.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub union RjValueData {
+    pub i: i32,
+    pub j: i64,
+    pub f: f32,
+    pub d: f64,
+    pub s: *const c_char,
+}
+
+impl RjValue {
+    fn to_jvm<'a>(self, vm: &Vm<'a>) -> Result<JvmValue<'a>> {
+        // SAFETY: The caller sets the field of the union which matches the type
+        unsafe {
+            match self.ty {
+                RJ_BOOLEAN => (self.value.i != 0).to_jvm(vm),
+                RJ_INT => self.value.i.to_jvm(vm),
+                RJ_LONG => self.value.j.to_jvm(vm),
+                RJ_FLOAT => self.value.f.to_jvm(vm),
+                RJ_DOUBLE => self.value.d.to_jvm(vm),
+                RJ_STRING if self.value.s.is_null() => Ok(JvmValue::Reference(0)),
+                RJ_STRING => Ok(vm.new_string(str_arg(self.value.s)?)),
+                ty => Err(Error::Message(format!("invalid argument type: {ty}"))),
+            }
+        }
+    }
+
+    fn from_jvm<'a>(vm: &Vm<'a>, ty: c_int, value: Option<JvmValue<'a>>) -> Result<RjValue> {
+        let value = match ty {
+            RJ_VOID => {
+                <()>::from_jvm(vm, value)?;
+                RjValueData { j: 0 }
+            }
+            RJ_BOOLEAN => RjValueData {
+                i: bool::from_jvm(vm, value)?.into(),
+            },
+            RJ_INT => RjValueData {
+                i: i32::from_jvm(vm, value)?,
+            },
+            RJ_LONG => RjValueData {
+                j: i64::from_jvm(vm, value)?,
+            },
+            RJ_FLOAT => RjValueData {
+                f: f32::from_jvm(vm, value)?,
+            },
+            RJ_DOUBLE => RjValueData {
+                d: f64::from_jvm(vm, value)?,
+            },
+            RJ_STRING => RjValueData {
+                s: match Option::<&str>::from_jvm(vm, value)? {
+                    Some(s) => CString::new(s)
+                        .wrap_err("string contains a nul character")?
+                        .into_raw(),
+                    None => ptr::null(),
+                },
+            },
+            ty => return Err(Error::Message(format!("invalid result type: {ty}"))),
+        };
+
+        Ok(RjValue { ty, value })
+    }
+}
+
+/// Returns the descriptor of a type, which for Java strings is `java.lang.String`.
+fn type_descriptor(ty: c_int) -> Result<&'static str> {
+    Ok(match ty {
+        RJ_VOID => "V",
+        RJ_BOOLEAN => "Z",
+        RJ_INT => "I",
+        RJ_LONG => "J",
+        RJ_FLOAT => "F",
+        RJ_DOUBLE => "D",
+        RJ_STRING => "Ljava/lang/String;",
+        ty => return Err(Error::Message(format!("invalid type: {ty}"))),
+    })
+}
+
+unsafe fn str_arg<'s>(s: *const c_char) -> Result<&'s str> {
+    if s.is_null() {
+        return Err("expected string, found null".into());
+    }
+
+    CStr::from_ptr(s)
+        .to_str()
+        .wrap_err("string isn't valid utf-8")
+}
+
+/// Calls a function with a vm, returning [`RJ_ERROR`] and saving the error if it fails or
+/// panics.
+unsafe fn with_vm(vm: *mut RjVm, f: impl FnOnce(&mut RjVm) -> Result<()>) -> c_int {
+    let Some(vm) = vm.as_mut() else {
+        return RJ_ERROR;
+    };
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| f(vm))).unwrap_or_else(|payload| {
+        Err(Error::Message(format!(
+            "panicked: {}",
+            panic_message(&*payload)
+        )))
+    });
+
+    match result {
+        Ok(()) => RJ_OK,
+        Err(e) => {
+            let message = e.full_message().replace('\0', "\\0");
+            vm.last_error = Some(CString::new(message).unwrap());
+            RJ_ERROR
+        }
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+/// Creates a vm, which must be freed with [`rj_vm_free`].
+#[no_mangle]
+pub extern "C" fn rj_vm_new() -> *mut RjVm {
+    Box::into_raw(Box::new(RjVm {
+        class_path: None,
+        vm: None,
+        stdout: Stdout::default(),
+        last_error: None,
+    }))
+}
+
+/// # Safety
+///
+/// `vm` must be null, or a vm returned by [`rj_vm_new`] which hasn't been freed.
+#[no_mangle]
+pub unsafe extern "C" fn rj_vm_free(vm: *mut RjVm) {
+    if !vm.is_null() {
+        drop(Box::from_raw(vm));
+    }
+}
+
+/// Returns the message of the last error, or null if there hasn't been one. The message is
+/// valid until the next error, or until the vm is freed.
+///
+/// # Safety
+///
+/// `vm` must be null, or a vm returned by [`rj_vm_new`] which hasn't been freed.
+#[no_mangle]
+pub unsafe extern "C" fn rj_vm_last_error(vm: *const RjVm) -> *const c_char {
+    vm.as_ref()
+        .and_then(|vm| vm.last_error.as_deref())
+        .map_or(ptr::null(), CStr::as_ptr)
+}
+
+/// Sets the class path, in the platform's format. This fails once the vm has loaded a class.
+///
+/// # Safety
+///
+/// `vm` must be a vm returned by [`rj_vm_new`] which hasn't been freed, and `class_path` must be
+/// a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rj_vm_set_class_path(vm: *mut RjVm, class_path: *const c_char) -> c_int {
+    with_vm(vm, |vm| {
+        if vm.vm.is_some() {
+            return Err("the class path can't be changed once the vm has started".into());
+        }
+
+        vm.class_path = Some(ClassPath::parse(str_arg(class_path)?));
+        Ok(())
+    })
+}
+
+/// Loads and initializes a class, given its binary name (e.g. `java/lang/String`).
+///
+/// # Safety
+///
+/// `vm` must be a vm returned by [`rj_vm_new`] which hasn't been freed, and `class_name` must be
+/// a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rj_vm_load_class(vm: *mut RjVm, class_name: *const c_char) -> c_int {
+    with_vm(vm, |vm| {
+        let class_name = str_arg(class_name)?;
+        vm.vm().with(|vm| {
+            vm.load_class_file(class_name)?;
+            Ok(())
+        })
+    })
+}
+
+/// Calls a static method, whose descriptor is made from the types of the arguments and
+/// `return_type`. The result is written to `result` if it isn't null, and a string result must
+/// be freed with [`rj_string_free`].
+///
+/// # Safety
+///
+/// `vm` must be a vm returned by [`rj_vm_new`] which hasn't been freed, `class_name` and `name`
+/// must be nul-terminated strings, and `args` must point to `num_args` values, or may be null if
+/// there are none.
+#[no_mangle]
+pub unsafe extern "C" fn rj_vm_invoke_static(
+    vm: *mut RjVm,
+    class_name: *const c_char,
+    name: *const c_char,
+    args: *const RjValue,
+    num_args: usize,
+    return_type: c_int,
+    result: *mut RjValue,
+) -> c_int {
+    with_vm(vm, |vm| {
+        let class_name = str_arg(class_name)?;
+        let name = str_arg(name)?;
+        let args = match num_args {
+            0 => &[],
+            _ => slice::from_raw_parts(args, num_args),
+        };
+
+        let mut descriptor = String::from("(");
+        for arg in args {
+            if arg.ty == RJ_VOID {
+                return Err("arguments can't be void".into());
+            }
+            descriptor += type_descriptor(arg.ty)?;
+        }
+        descriptor += ")";
+        descriptor += type_descriptor(return_type)?;
+
+        let value = vm.vm().with(|vm| {
+            let args = args
+                .iter()
+                .map(|arg| arg.to_jvm(vm))
+                .collect::<Result<Vec<_>>>()?;
+            let value = vm.invoke_static_method(class_name, name, &descriptor, &args)?;
+            RjValue::from_jvm(vm, return_type, value)
+        })?;
+
+        match result.as_mut() {
+            Some(result) => *result = value,
+            None if value.ty == RJ_STRING => rj_string_free(value.value.s),
+            None => {}
+        }
+
+        Ok(())
+    })
+}
+
+/// Runs the main method of a class, as [`OwnedVm::run_main`] does, and writes its exit status to
+/// `status` if it isn't null.
+///
+/// # Safety
+///
+/// `vm` must be a vm returned by [`rj_vm_new`] which hasn't been freed, `class_name` must be a
+/// nul-terminated string, and `args` must point to `num_args` nul-terminated strings, or may be
+/// null if there are none.
+#[no_mangle]
+pub unsafe extern "C" fn rj_vm_run_main(
+    vm: *mut RjVm,
+    class_name: *const c_char,
+    args: *const *const c_char,
+    num_args: usize,
+    status: *mut i32,
+) -> c_int {
+    with_vm(vm, |vm| {
+        let class_name = str_arg(class_name)?;
+        let args = match num_args {
+            0 => &[],
+            _ => slice::from_raw_parts(args, num_args),
+        };
+        let args = args
+            .iter()
+            .map(|arg| Ok(str_arg(*arg)?.to_owned()))
+            .collect::<Result<Vec<_>>>()?;
+
+        let exit_status = vm.vm().run_main(class_name, &args)?;
+        if let Some(status) = status.as_mut() {
+            *status = exit_status;
+        }
+
+        Ok(())
+    })
+}
+
+/// Moves up to `len` bytes of the output written to `System.out` into `buf`, returning how many
+/// were moved. The output is kept by the vm until it's read.
+///
+/// # Safety
+///
+/// `vm` must be a vm returned by [`rj_vm_new`] which hasn't been freed, and `buf` must point to
+/// `len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rj_vm_read_stdout(vm: *mut RjVm, buf: *mut c_char, len: usize) -> usize {
+    let Some(vm) = vm.as_mut() else {
+        return 0;
+    };
+
+    if buf.is_null() || len == 0 {
+        return 0;
+    }
+
+    let mut stdout = vm.stdout.0.lock().unwrap();
+    let len = len.min(stdout.len());
+    ptr::copy_nonoverlapping(stdout.as_ptr(), buf.cast(), len);
+    stdout.drain(..len);
+    len
+}
+
+/// Frees a string returned by the vm.
+///
+/// # Safety
+///
+/// `s` must be null, or a string returned by the vm which hasn't been freed.
+#[no_mangle]
+pub unsafe extern "C" fn rj_string_free(s: *const c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s.cast_mut()));
+    }
+}
@@ -0,0 +1,7 @@
+public class Hello {
+    public static void main(String[] args) {
+        System.out.print("Hello, ");
+        System.out.println(args[0]);
+        System.exit(args.length);
+    }
+}
@@ -0,0 +1,126 @@
+use std::env;
+use std::ffi::{c_char, CStr, CString};
+use std::fs;
+use std::path::Path;
+use std::process::{self, Command};
+use std::ptr;
+
+use rusty_java_capi::*;
+
+unsafe fn last_error(vm: *mut RjVm) -> String {
+    CStr::from_ptr(rj_vm_last_error(vm))
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn int(i: i32) -> RjValue {
+    RjValue {
+        ty: RJ_INT,
+        value: RjValueData { i },
+    }
+}
+
+/// Runs a program and calls JDK methods through the C API, as a C program would.
+#[test]
+fn embedding() {
+    let class_dir = env::temp_dir().join(format!("rusty-java-capi-{}", process::id()));
+    let source_file_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/Hello.java");
+    let status = Command::new("javac")
+        .arg("-d")
+        .arg(&class_dir)
+        .arg(source_file_path)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    unsafe {
+        let vm = rj_vm_new();
+        assert!(rj_vm_last_error(vm).is_null());
+
+        let class_path = CString::new(class_dir.to_str().unwrap()).unwrap();
+        assert_eq!(rj_vm_set_class_path(vm, class_path.as_ptr()), RJ_OK);
+
+        let mut status = -1;
+        let args = [c"world".as_ptr()];
+        assert_eq!(
+            rj_vm_run_main(vm, c"Hello".as_ptr(), args.as_ptr(), 1, &mut status),
+            RJ_OK
+        );
+        assert_eq!(status, 1);
+
+        // Reading into a null buffer doesn't consume any output
+        assert_eq!(rj_vm_read_stdout(vm, ptr::null_mut(), 8), 0);
+
+        // Output can be read in pieces
+        let mut buf = [0 as c_char; 8];
+        let mut output = Vec::new();
+        loop {
+            let len = rj_vm_read_stdout(vm, buf.as_mut_ptr(), buf.len());
+            if len == 0 {
+                break;
+            }
+            output.extend(buf[..len].iter().map(|c| *c as u8));
+        }
+        assert_eq!(output, b"Hello, world\n");
+
+        let mut result = int(0);
+        let args = [int(3), int(7)];
+        assert_eq!(
+            rj_vm_invoke_static(
+                vm,
+                c"java/lang/Math".as_ptr(),
+                c"max".as_ptr(),
+                args.as_ptr(),
+                args.len(),
+                RJ_INT,
+                &mut result
+            ),
+            RJ_OK
+        );
+        assert_eq!((result.ty, result.value.i), (RJ_INT, 7));
+
+        let args = [RjValue {
+            ty: RJ_STRING,
+            value: RjValueData {
+                s: c"java.class.path".as_ptr(),
+            },
+        }];
+        assert_eq!(
+            rj_vm_invoke_static(
+                vm,
+                c"java/lang/System".as_ptr(),
+                c"getProperty".as_ptr(),
+                args.as_ptr(),
+                args.len(),
+                RJ_STRING,
+                &mut result
+            ),
+            RJ_OK
+        );
+        assert_eq!(CStr::from_ptr(result.value.s), class_path.as_c_str());
+        rj_string_free(result.value.s);
+
+        assert_eq!(
+            rj_vm_invoke_static(
+                vm,
+                c"java/lang/Math".as_ptr(),
+                c"max".as_ptr(),
+                ptr::null(),
+                0,
+                RJ_INT,
+                ptr::null_mut()
+            ),
+            RJ_ERROR
+        );
+        assert_eq!(last_error(vm), "method not found: max()I");
+
+        assert_eq!(rj_vm_load_class(vm, c"Missing".as_ptr()), RJ_ERROR);
+        assert!(last_error(vm).contains("Missing"));
+
+        assert_eq!(rj_vm_set_class_path(vm, c".".as_ptr()), RJ_ERROR);
+
+        rj_vm_free(vm);
+    }
+
+    fs::remove_dir_all(class_dir).unwrap();
+}
@@ -0,0 +1,397 @@
+//! Runs a curated set of jtreg-style tests against the vm, and reports how many of them pass in
+//! each area of the JVM specification.
+//!
+//! Each test in `conformance/tests` is a single source file with a jtreg header, and passes if its
+//! main method returns without throwing, as under jtreg. The directory that a test is in is its
+//! area, e.g. `linking`. Tests which the vm is known to fail are listed in `expected_failures.txt`,
+//! so that the suite fails when another test starts failing, or when a known failure starts
+//! passing and should be taken off the list.
+//!
+//! The report is printed once the tests finish, and is written to the file named by
+//! `CONFORMANCE_REPORT` too, if it's set. Setting `CONFORMANCE_TESTS` to a directory runs the
+//! tests in it instead, e.g. `test/hotspot/jtreg/runtime` in a checkout of the JDK.
+
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bumpalo::Bump;
+use color_eyre::eyre::{self, bail, ContextCompat, WrapErr};
+use libtest_mimic::{Arguments, Trial};
+use rusty_java::budget::Budget;
+use rusty_java::class_path::ClassPath;
+use rusty_java::vm::Vm;
+
+/// How long each of a test's actions can run for.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Tags which need support from jtreg that the runner doesn't have, e.g. to build libraries or
+/// export internal packages. Tests with them are skipped.
+const UNSUPPORTED_TAGS: &[&str] = &["compile", "ignore", "library", "modules", "requires"];
+
+struct Test {
+    /// The path of the source relative to the tests directory, without its extension, e.g.
+    /// `linking/VirtualDispatch`.
+    name: String,
+    area: String,
+    source: PathBuf,
+    /// The `@run` actions, or why the test can't be run.
+    actions: Result<Vec<MainAction>, String>,
+}
+
+/// A `@run main` action, which calls the main method of a class.
+struct MainAction {
+    class: String,
+    args: Vec<String>,
+}
+
+#[derive(Clone)]
+enum Outcome {
+    Passed,
+    Failed(String),
+    Skipped(String),
+}
+
+type Results = Arc<Mutex<BTreeMap<String, (String, Outcome)>>>;
+
+fn main() -> eyre::Result<()> {
+    color_eyre::install()?;
+
+    let args = Arguments::from_args();
+    let suite_dir = Path::new(file!()).parent().unwrap();
+
+    // Tests from elsewhere, such as the jtreg tests in a checkout of the JDK, can be run in place
+    // of the curated ones. None of them are expected to fail.
+    let (tests_dir, expected_failures) = match env::var_os("CONFORMANCE_TESTS") {
+        Some(dir) => (PathBuf::from(dir), HashSet::new()),
+        None => (
+            suite_dir.join("tests"),
+            read_expected_failures(&suite_dir.join("expected_failures.txt"))?,
+        ),
+    };
+    let expected_failures = Arc::new(expected_failures);
+
+    let mut tests = vec![];
+    find_tests(&tests_dir, &tests_dir, &mut tests)?;
+
+    for name in expected_failures.iter() {
+        if !tests.iter().any(|test| test.name == *name) {
+            bail!("expected_failures.txt lists {name}, which isn't a test");
+        }
+    }
+
+    let results = Results::default();
+
+    let trials = tests
+        .into_iter()
+        .map(|test| create_trial(test, expected_failures.clone(), results.clone()))
+        .collect();
+
+    let conclusion = libtest_mimic::run(&args, trials);
+
+    if !args.list {
+        let report = report(&results.lock().unwrap());
+        println!("\n{report}");
+
+        if let Some(path) = env::var_os("CONFORMANCE_REPORT") {
+            fs::write(&path, report).wrap_err_with(|| format!("failed to write {path:?}"))?;
+        }
+    }
+
+    conclusion.exit();
+}
+
+fn create_trial(test: Test, expected_failures: Arc<HashSet<String>>, results: Results) -> Trial {
+    let name = test.name.clone();
+
+    let actions = match test.actions {
+        Ok(actions) => actions,
+        Err(reason) => {
+            results
+                .lock()
+                .unwrap()
+                .insert(test.name, (test.area, Outcome::Skipped(reason)));
+            return Trial::test(name, || Ok(())).with_ignored_flag(true);
+        }
+    };
+
+    Trial::test(name, move || {
+        // Bugs in the vm can make it panic, which should be reported like any other failure
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            run_test(&test.name, &test.source, &actions)
+        }));
+
+        let outcome = match result {
+            Ok(Ok(outcome)) => outcome,
+            Ok(Err(e)) => Outcome::Failed(format!("{e:?}")),
+            Err(payload) => {
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .copied()
+                    .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                    .unwrap_or("unknown panic");
+                Outcome::Failed(format!("the vm panicked: {message}"))
+            }
+        };
+
+        results
+            .lock()
+            .unwrap()
+            .insert(test.name.clone(), (test.area, outcome.clone()));
+
+        match (outcome, expected_failures.contains(&test.name)) {
+            (Outcome::Passed, false) | (Outcome::Failed(_), true) => Ok(()),
+            (Outcome::Failed(reason), false) => Err(reason.into()),
+            (Outcome::Passed, true) => {
+                Err("passes now, so it should be removed from expected_failures.txt".into())
+            }
+            (Outcome::Skipped(_), _) => unreachable!(),
+        }
+    })
+}
+
+/// Compiles a test on its own, and runs each of its actions in a new vm.
+fn run_test(name: &str, source: &Path, actions: &[MainAction]) -> eyre::Result<Outcome> {
+    let classes_dir = Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join("conformance")
+        .join(name);
+    match fs::remove_dir_all(&classes_dir) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+
+    let classes_dir_arg = classes_dir
+        .to_str()
+        .wrap_err("output directory is not valid unicode")?;
+    jdk_tools::compiler::compile(&[source], &["-d", classes_dir_arg])?;
+
+    for action in actions {
+        let arena = Bump::new();
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+
+        let vm = Vm::new(&arena, &mut stdout)
+            .with_stderr(&mut stderr)
+            .with_class_path(ClassPath::new([&classes_dir]))
+            .with_budget(Budget {
+                max_time: Some(TIMEOUT),
+                ..Budget::default()
+            });
+
+        let class = vm.load_class_file(&action.class.replace('.', "/"))?;
+        match vm.run_main(class, &action.args) {
+            Ok(0) => {}
+            Ok(status) => return Ok(Outcome::Failed(format!("exited with status {status}"))),
+            Err(e) => return Ok(Outcome::Failed(e.root().to_string())),
+        }
+    }
+
+    Ok(Outcome::Passed)
+}
+
+/// Finds the tests in a directory and its subdirectories. Sources without a jtreg header are
+/// left out, since they could be used by other tests.
+fn find_tests(tests_dir: &Path, dir: &Path, tests: &mut Vec<Test>) -> eyre::Result<()> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            find_tests(tests_dir, &path, tests)?;
+            continue;
+        }
+
+        if !path.extension().is_some_and(|ext| ext == "java") {
+            continue;
+        }
+
+        let source = fs::read_to_string(&path)?;
+        let class_name = path.file_stem().unwrap().to_str().unwrap();
+        let Some(actions) = parse_header(&source, class_name) else {
+            continue;
+        };
+
+        let relative_path = path.strip_prefix(tests_dir)?.with_extension("");
+        let area = relative_path
+            .parent()
+            .and_then(Path::to_str)
+            .filter(|area| !area.is_empty())
+            .unwrap_or("other");
+
+        tests.push(Test {
+            name: relative_path.to_str().unwrap().replace('\\', "/"),
+            area: area.replace('\\', "/"),
+            source: path,
+            actions,
+        });
+    }
+
+    Ok(())
+}
+
+/// Parses the jtreg header of a test, the comment with an `@test` tag, returning its actions or
+/// why it can't be run. Returns `None` if the source doesn't have a header.
+///
+/// Without any `@run` tags, the test's class is run, like jtreg does.
+fn parse_header(source: &str, class_name: &str) -> Option<Result<Vec<MainAction>, String>> {
+    let header = source
+        .split("/*")
+        .skip(1)
+        .filter_map(|comment| comment.split_once("*/").map(|(comment, _)| comment))
+        .find(|comment| comment.contains("@test"))?;
+
+    // Tags start at the beginning of a line, and continue until the next tag
+    let mut tags: Vec<(&str, String)> = vec![];
+    for line in header.lines() {
+        let line = line.trim().trim_start_matches('*').trim();
+        if let Some(tag) = line.strip_prefix('@') {
+            let (name, value) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+            tags.push((name, value.trim().to_owned()));
+        } else if let Some((_, value)) = tags.last_mut() {
+            value.push(' ');
+            value.push_str(line);
+        }
+    }
+
+    if let Some((name, _)) = tags
+        .iter()
+        .find(|(name, _)| UNSUPPORTED_TAGS.contains(name))
+    {
+        return Some(Err(format!("@{name} isn't supported")));
+    }
+
+    let mut actions = vec![];
+    for (_, run) in tags.iter().filter(|(name, _)| *name == "run") {
+        match parse_run(run) {
+            Ok(action) => actions.push(action),
+            Err(reason) => return Some(Err(reason)),
+        }
+    }
+
+    if actions.is_empty() {
+        actions.push(MainAction {
+            class: class_name.to_owned(),
+            args: vec![],
+        });
+    }
+
+    Some(Ok(actions))
+}
+
+/// Parses the value of a `@run` tag, e.g. `main/othervm Foo arg`. Every test is run in a new vm
+/// anyway, so `othervm` makes no difference, but options for the vm aren't supported.
+fn parse_run(run: &str) -> Result<MainAction, String> {
+    let mut words = run.split_whitespace();
+
+    let action = words.next().unwrap_or_default();
+    let mut options = action.split('/');
+    if options.next() != Some("main") {
+        return Err(format!("@run {action} isn't supported"));
+    }
+    if let Some(option) = options.find(|option| *option != "othervm") {
+        return Err(format!("@run main/{option} isn't supported"));
+    }
+
+    let class = match words.next() {
+        Some(option) if option.starts_with('-') => {
+            return Err(format!("vm option {option} isn't supported"));
+        }
+        Some(class) => class.to_owned(),
+        None => return Err("@run main is missing a class".to_owned()),
+    };
+
+    Ok(MainAction {
+        class,
+        args: words.map(str::to_owned).collect(),
+    })
+}
+
+/// Reads the names of the tests which are expected to fail, one per line, followed by optional
+/// comments starting with `#`.
+fn read_expected_failures(path: &Path) -> eyre::Result<HashSet<String>> {
+    let contents = fs::read_to_string(path).wrap_err_with(|| format!("failed to read {path:?}"))?;
+    Ok(contents
+        .lines()
+        .map(|line| line.split('#').next().unwrap().trim())
+        .filter(|line| !line.is_empty())
+        .map(str::to_owned)
+        .collect())
+}
+
+/// Summarizes the results of the tests which ran, with the number which passed in each area
+/// followed by the reasons for each failure, e.g.
+///
+/// ```text
+/// Conformance: 5 of 6 tests passed (83.33%), 1 skipped
+///   instructions       3/4
+///   linking            2/2
+///
+/// Failed:
+///   instructions/IntMultiply: unimplemented instruction mul
+///
+/// Skipped:
+///   linking/Modules: @modules isn't supported
+/// ```
+fn report(results: &BTreeMap<String, (String, Outcome)>) -> String {
+    let mut areas = BTreeMap::<&str, (usize, usize)>::new();
+    let mut failed = vec![];
+    let mut skipped = vec![];
+
+    for (name, (area, outcome)) in results {
+        let (passed, total) = areas.entry(area).or_default();
+        match outcome {
+            Outcome::Passed => {
+                *passed += 1;
+                *total += 1;
+            }
+            Outcome::Failed(reason) => {
+                *total += 1;
+                failed.push((name, reason));
+            }
+            Outcome::Skipped(reason) => skipped.push((name, reason)),
+        }
+    }
+
+    let passed = areas.values().map(|(passed, _)| passed).sum::<usize>();
+    let total = areas.values().map(|(_, total)| total).sum::<usize>();
+    let percent = match total {
+        0 => 100.0,
+        total => passed as f64 / total as f64 * 100.0,
+    };
+
+    let mut report = String::new();
+    writeln!(
+        report,
+        "Conformance: {passed} of {total} tests passed ({percent:.2}%), {} skipped",
+        skipped.len()
+    )
+    .unwrap();
+
+    for (area, (passed, total)) in areas {
+        if total > 0 {
+            let counts = format!("{passed}/{total}");
+            writeln!(report, "  {area:<16} {counts:>5}").unwrap();
+        }
+    }
+
+    for (heading, tests) in [("Failed", failed), ("Skipped", skipped)] {
+        if !tests.is_empty() {
+            writeln!(report, "\n{heading}:").unwrap();
+            for (name, reason) in tests {
+                // Only the first line, since errors can include the frames they came from
+                let reason = reason.lines().next().unwrap_or_default();
+                writeln!(report, "  {name}: {reason}").unwrap();
+            }
+        }
+    }
+
+    report
+}
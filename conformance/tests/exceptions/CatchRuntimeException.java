@@ -0,0 +1,30 @@
+/*
+ * @test
+ * @summary Exceptions thrown by the vm are caught by the nearest matching handler (JVMS 2.10)
+ * @run main CatchRuntimeException
+ */
+public class CatchRuntimeException {
+    public static void main(String[] args) {
+        int[] array = new int[1];
+        int caught = 0;
+
+        try {
+            array[1] = 1;
+        } catch (NullPointerException e) {
+            throw new RuntimeException("caught by the wrong handler");
+        } catch (ArrayIndexOutOfBoundsException e) {
+            caught++;
+        }
+
+        try {
+            Object o = null;
+            o.hashCode();
+        } catch (RuntimeException e) {
+            caught++;
+        }
+
+        if (caught != 2) {
+            throw new RuntimeException("an exception wasn't caught");
+        }
+    }
+}
@@ -0,0 +1,32 @@
+/*
+ * @test
+ * @summary Exceptions thrown by athrow unwind to a handler in a calling method, running finally
+ *          blocks on the way (JVMS 2.10, 6.5 athrow)
+ * @run main ThrowAndCatch
+ */
+public class ThrowAndCatch {
+    static boolean finallyRan;
+
+    public static void main(String[] args) {
+        try {
+            fail();
+            throw new RuntimeException("fail() returned normally");
+        } catch (IllegalStateException e) {
+            if (!"thrown".equals(e.getMessage())) {
+                throw new RuntimeException("wrong message: " + e.getMessage());
+            }
+        }
+
+        if (!finallyRan) {
+            throw new RuntimeException("finally block didn't run");
+        }
+    }
+
+    static void fail() {
+        try {
+            throw new IllegalStateException("thrown");
+        } finally {
+            finallyRan = true;
+        }
+    }
+}
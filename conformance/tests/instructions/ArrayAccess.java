@@ -0,0 +1,29 @@
+/*
+ * @test
+ * @summary Arrays are created with default values and accessed by index (JVMS 6.5 newarray,
+ *          anewarray, arraylength, iaload, iastore, aaload, aastore)
+ * @run main ArrayAccess
+ */
+public class ArrayAccess {
+    public static void main(String[] args) {
+        int[] ints = new int[4];
+        if (ints.length != 4 || ints[3] != 0) {
+            throw new RuntimeException("int array isn't zeroed");
+        }
+
+        ints[2] = 5;
+        if (ints[2] != 5) {
+            throw new RuntimeException("iastore wasn't seen by iaload");
+        }
+
+        String[] strings = new String[2];
+        if (strings[0] != null) {
+            throw new RuntimeException("reference array isn't null");
+        }
+
+        strings[1] = "b";
+        if (!strings[1].equals("b")) {
+            throw new RuntimeException("aastore wasn't seen by aaload");
+        }
+    }
+}
@@ -0,0 +1,28 @@
+/*
+ * @test
+ * @summary Integer addition, subtraction and remainder wrap around on overflow (JVMS 6.5 iadd,
+ *          isub, irem)
+ * @run main IntArithmetic
+ */
+public class IntArithmetic {
+    public static void main(String[] args) {
+        // Locals, so that javac doesn't fold the constants
+        int max = Integer.MAX_VALUE;
+        int min = Integer.MIN_VALUE;
+        int seven = 7;
+        int three = 3;
+
+        check(max + 1, min);
+        check(min - 1, max);
+        check(seven % three, 1);
+        check(-seven % three, -1);
+        check(seven % -three, 1);
+        check(min % -1, 0);
+    }
+
+    static void check(int actual, int expected) {
+        if (actual != expected) {
+            throw new RuntimeException("expected " + expected + ", got " + actual);
+        }
+    }
+}
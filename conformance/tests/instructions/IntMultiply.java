@@ -0,0 +1,24 @@
+/*
+ * @test
+ * @summary Integer multiplication and division wrap around on overflow (JVMS 6.5 imul, idiv)
+ * @run main IntMultiply
+ */
+public class IntMultiply {
+    public static void main(String[] args) {
+        // Locals, so that javac doesn't fold the constants
+        int max = Integer.MAX_VALUE;
+        int min = Integer.MIN_VALUE;
+        int seven = 7;
+
+        check(max * 2, -2);
+        check(-seven * 3, -21);
+        check(-seven / 2, -3);
+        check(min / -1, min);
+    }
+
+    static void check(int actual, int expected) {
+        if (actual != expected) {
+            throw new RuntimeException("expected " + expected + ", got " + actual);
+        }
+    }
+}
@@ -0,0 +1,24 @@
+/*
+ * @test
+ * @summary Shift distances only use their low bits (JVMS 6.5 ishl, ishr, iushr, lshl)
+ * @run main Shifts
+ */
+public class Shifts {
+    public static void main(String[] args) {
+        // Locals, so that javac doesn't fold the constants
+        int one = 1;
+        int minusSixteen = -16;
+        long oneLong = 1;
+
+        check(one << 33, 2);
+        check(minusSixteen >> 2, -4);
+        check(-one >>> 28, 15);
+        check(oneLong << 65, 2L);
+    }
+
+    static void check(long actual, long expected) {
+        if (actual != expected) {
+            throw new RuntimeException("expected " + expected + ", got " + actual);
+        }
+    }
+}
@@ -0,0 +1,37 @@
+/*
+ * @test
+ * @summary A class is initialized on its first active use, after its super class (JVMS 5.5)
+ * @run main StaticInitialization
+ */
+public class StaticInitialization {
+    static int initialized;
+    static int baseOrder;
+    static int derivedOrder;
+
+    static class Base {
+        static {
+            baseOrder = ++initialized;
+        }
+
+        static int value = 1;
+    }
+
+    static class Derived extends Base {
+        static {
+            derivedOrder = ++initialized;
+        }
+
+        static int other = 2;
+    }
+
+    public static void main(String[] args) {
+        // Referring to an inherited static field only initializes the class that declares it
+        if (Derived.value != 1 || baseOrder != 1 || derivedOrder != 0) {
+            throw new RuntimeException("Derived was initialized by an inherited field");
+        }
+
+        if (Derived.other != 2 || derivedOrder != 2) {
+            throw new RuntimeException("Derived wasn't initialized after Base");
+        }
+    }
+}
@@ -0,0 +1,49 @@
+/*
+ * @test
+ * @summary Virtual and interface calls select the most specific override, and super calls
+ *          don't (JVMS 5.4.6, 6.5 invokevirtual, invokeinterface, invokespecial)
+ * @run main VirtualDispatch
+ */
+public class VirtualDispatch {
+    interface Named {
+        default String name() {
+            return "named";
+        }
+    }
+
+    static class Animal implements Named {
+        String sound() {
+            return "...";
+        }
+    }
+
+    static class Dog extends Animal {
+        @Override
+        String sound() {
+            return "woof";
+        }
+
+        @Override
+        public String name() {
+            return "dog";
+        }
+
+        String superSound() {
+            return super.sound();
+        }
+    }
+
+    public static void main(String[] args) {
+        Animal animal = new Dog();
+        check(animal.sound(), "woof");
+        check(((Named) animal).name(), "dog");
+        check(new Animal().name(), "named");
+        check(((Dog) animal).superSound(), "...");
+    }
+
+    static void check(String actual, String expected) {
+        if (!actual.equals(expected)) {
+            throw new RuntimeException("expected " + expected + ", got " + actual);
+        }
+    }
+}
@@ -0,0 +1,82 @@
+package integration_tests;
+
+import java.lang.annotation.ElementType;
+import java.lang.annotation.Retention;
+import java.lang.annotation.RetentionPolicy;
+import java.lang.annotation.Target;
+import java.util.ArrayList;
+import java.util.List;
+
+// Checks that classes with every kind of annotation element can be loaded
+// requires: jdk
+public class Annotations {
+    @Retention(RetentionPolicy.RUNTIME)
+    @interface Visible {
+        byte b() default 1;
+        char c() default 'c';
+        double d() default 1.5;
+        float f() default 2.5f;
+        int i() default 3;
+        long j() default 4L;
+        short s() default 5;
+        boolean z() default true;
+        String string() default "string";
+        ElementType type() default ElementType.METHOD;
+        Class<?> cls() default void.class;
+        Invisible nested() default @Invisible;
+        int[] array() default {1, 2, 3};
+    }
+
+    @Retention(RetentionPolicy.CLASS)
+    @interface Invisible {
+        String value() default "";
+    }
+
+    @Retention(RetentionPolicy.RUNTIME)
+    @Target({ElementType.TYPE_USE, ElementType.TYPE_PARAMETER})
+    @interface TypeUse {
+        int value() default 0;
+    }
+
+    @Target(ElementType.TYPE_USE)
+    @interface InvisibleTypeUse {}
+
+    static class Generic<@TypeUse T extends @TypeUse Comparable<T>>
+            extends @TypeUse Object implements @InvisibleTypeUse Runnable {
+        List<@TypeUse(1) String> @InvisibleTypeUse [] strings;
+
+        @TypeUse
+        <@TypeUse U> List<? extends @TypeUse Object> method(@TypeUse Object value)
+                throws @TypeUse RuntimeException {
+            @TypeUse ArrayList<@InvisibleTypeUse String> list = new @TypeUse ArrayList<>();
+            if (value instanceof @TypeUse String) {
+                String string = (@TypeUse String) value;
+            }
+            return list;
+        }
+
+        public void run() {}
+    }
+
+    @Visible(string = "class", cls = Annotations.class, nested = @Invisible("nested"))
+    static class Annotated {
+        @Invisible("field")
+        @Deprecated
+        int field;
+
+        @Visible(array = {}, type = ElementType.FIELD)
+        @Invisible
+        void method(@Visible(i = 1) int first, int second, @Invisible @Visible String third) {}
+
+        class Inner {
+            Inner(@Invisible("inner") int value) {}
+        }
+    }
+
+    public static void main(String[] args) {
+        new Annotated().method(1, 2, "3");
+        new Annotated().new Inner(4);
+        new Generic<String>().method("string");
+        System.out.println("loaded");
+    }
+}
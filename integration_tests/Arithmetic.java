@@ -5,12 +5,56 @@ class Arithmetic {
 
     static native void print(int v);
 
+    static native void print(long v);
+
     public static void main(String[] args) {
         print("1 + 2 = ");
         print(add(1, 2));
+
+        print("\nMAX_VALUE + 1 = ");
+        print(add(Integer.MAX_VALUE, 1));
+
+        print("\n7 / -2 = ");
+        print(div(7, -2));
+
+        print("\n-7 % 2 = ");
+        print(rem(-7, 2));
+
+        print("\nMIN_VALUE / -1 = ");
+        print(div(Integer.MIN_VALUE, -1));
+
+        print("\nMIN_VALUE % -1 = ");
+        print(rem(Integer.MIN_VALUE, -1));
+
+        print("\nLong.MIN_VALUE / -1 = ");
+        print(div(Long.MIN_VALUE, -1));
+
+        print("\nLong.MIN_VALUE % -1 = ");
+        print(rem(Long.MIN_VALUE, -1));
+
+        int i = Integer.MAX_VALUE;
+        i += 10;
+        print("\nMAX_VALUE += 10: ");
+        print(i);
     }
 
     private static int add(int a, int b) {
         return a + b;
     }
+
+    private static int div(int a, int b) {
+        return a / b;
+    }
+
+    private static int rem(int a, int b) {
+        return a % b;
+    }
+
+    private static long div(long a, long b) {
+        return a / b;
+    }
+
+    private static long rem(long a, long b) {
+        return a % b;
+    }
 }
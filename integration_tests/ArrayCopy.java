@@ -0,0 +1,31 @@
+package integration_tests;
+
+public class ArrayCopy {
+    private static native void print(String s);
+
+    private static native void print(int[] vs);
+
+    private static native void print(boolean v);
+
+    public static void main(String[] args) {
+        int[] src = new int[10];
+        for (int i = 0; i < src.length; i++) {
+            src[i] = i + 1;
+        }
+
+        int[] dest = new int[5];
+        System.arraycopy(src, 3, dest, 1, 4);
+        print(dest);
+        print("\n");
+
+        // Overlapping ranges within the same array
+        System.arraycopy(src, 0, src, 2, 8);
+        print(src);
+        print("\n");
+
+        print(System.identityHashCode(src) == System.identityHashCode(src));
+        print("\n");
+        print(System.identityHashCode(null) == 0);
+        print("\n");
+    }
+}
@@ -0,0 +1,13 @@
+package integration_tests;
+
+public class AssertionStatus {
+    public static void main(String[] args) {
+        // Assertions are disabled unless the vm is run with -ea
+        boolean enabled = false;
+        assert enabled = true;
+        System.out.println(enabled);
+
+        System.out.println(AssertionStatus.class.desiredAssertionStatus());
+        System.out.println(String.class.desiredAssertionStatus());
+    }
+}
@@ -0,0 +1,37 @@
+package integration_tests;
+
+public class Boxing {
+    public static void main(String[] args) {
+        Integer a = 127;
+        Integer b = 127;
+        System.out.println(a == b);
+
+        Integer c = 128;
+        Integer d = 128;
+        System.out.println(c == d);
+        System.out.println(c.intValue() == d.intValue());
+
+        Integer e = -128;
+        System.out.println(e == Integer.valueOf(-128));
+        System.out.println(a + e);
+
+        Long f = 5L;
+        System.out.println(f == Long.valueOf(5L));
+        System.out.println(f + 1);
+
+        Character g = 'a';
+        Character h = 'a';
+        System.out.println(g == h);
+        System.out.println(g.charValue());
+
+        Short i = 1;
+        Byte j = 2;
+        System.out.println(i == Short.valueOf((short) 1));
+        System.out.println(j == Byte.valueOf((byte) 2));
+        System.out.println(i + j);
+
+        Boolean k = true;
+        System.out.println(k == Boolean.TRUE);
+        System.out.println(k.booleanValue());
+    }
+}
@@ -0,0 +1,34 @@
+package integration_tests;
+
+// requires: jdk
+public class ClassForName {
+    static class Lazy {
+        static {
+            System.out.println("Lazy initialized");
+        }
+
+        static int value = 42;
+    }
+
+    public static void main(String[] args) throws Exception {
+        Class<?> integer = Class.forName("java.lang.Integer");
+        System.out.println(integer == Integer.class);
+
+        // Classes aren't initialized unless requested
+        Class<?> lazy = Class.forName(
+                "integration_tests.ClassForName$Lazy", false, ClassForName.class.getClassLoader());
+        System.out.println(lazy.getName());
+        System.out.println("loaded");
+
+        System.out.println(Class.forName("integration_tests.ClassForName$Lazy") == lazy);
+        System.out.println(Lazy.value);
+
+        Class<?> ints = Class.forName("[I");
+        System.out.println(ints == int[].class);
+        System.out.println(ints.getComponentType().getName());
+
+        Class<?> strings = Class.forName("[[Ljava.lang.String;");
+        System.out.println(strings.getName());
+        System.out.println(strings.getSimpleName());
+    }
+}
@@ -0,0 +1,49 @@
+package integration_tests;
+
+// requires: jdk
+public class ClassLoaders {
+    static class Other {
+    }
+
+    static class AliasLoader extends ClassLoader {
+        AliasLoader(ClassLoader parent) {
+            super(parent);
+        }
+
+        @Override
+        protected Class<?> findClass(String name) throws ClassNotFoundException {
+            if (name.equals("Alias")) {
+                return Other.class;
+            }
+            return super.findClass(name);
+        }
+    }
+
+    public static void main(String[] args) throws Exception {
+        ClassLoader app = ClassLoaders.class.getClassLoader();
+        System.out.println(app == ClassLoader.getSystemClassLoader());
+        System.out.println(app.getName());
+
+        ClassLoader platform = app.getParent();
+        System.out.println(platform == ClassLoader.getPlatformClassLoader());
+        System.out.println(platform.getName());
+        System.out.println(platform.getParent() == null);
+
+        // Classes from the JDK are defined by the bootstrap loader
+        System.out.println(String.class.getClassLoader() == null);
+        System.out.println(int.class.getClassLoader() == null);
+        System.out.println(ClassLoaders[].class.getClassLoader() == app);
+
+        Class<?> other = app.loadClass("integration_tests.ClassLoaders$Other");
+        System.out.println(other == Other.class);
+        System.out.println(other.getClassLoader() == app);
+        System.out.println(app.loadClass("java.lang.String") == String.class);
+
+        // Loaders delegate to their parent before finding classes themselves
+        AliasLoader alias = new AliasLoader(app);
+        System.out.println(alias.getParent() == app);
+        System.out.println(alias.loadClass("integration_tests.ClassLoaders$Other") == Other.class);
+        System.out.println(alias.loadClass("java.lang.Integer") == Integer.class);
+        System.out.println(alias.loadClass("Alias") == Other.class);
+    }
+}
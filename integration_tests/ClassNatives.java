@@ -0,0 +1,37 @@
+package integration_tests;
+
+public class ClassNatives {
+    interface Shape {
+    }
+
+    static class Square implements Shape {
+    }
+
+    private static native void print(String s);
+
+    private static native void print(boolean v);
+
+    public static void main(String[] args) {
+        print(Square.class.getName());
+        print("\n");
+        print(Square.class.getSimpleName());
+        print("\n");
+        print(Shape.class.isInterface());
+        print("\n");
+        print(Square.class.isInterface());
+        print("\n");
+        print(Square.class.isArray());
+        print("\n");
+        print(int[].class.isArray());
+        print("\n");
+        print(int[].class.getComponentType().getName());
+        print("\n");
+        print(String[][].class.getName());
+        print("\n");
+        print(String[][].class.getSimpleName());
+        print("\n");
+
+        assert false : "assertions should be disabled";
+        print("done\n");
+    }
+}
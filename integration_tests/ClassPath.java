@@ -0,0 +1,18 @@
+package integration_tests;
+
+// requires: jdk
+public class ClassPath {
+    static class Entry {
+    }
+
+    public static void main(String[] args) throws Exception {
+        System.out.println(System.getProperty("java.class.path"));
+        System.out.println(System.getProperty("path.separator"));
+
+        // Classes in packages are found in the matching directory of a class path entry
+        ClassLoader loader = ClassLoader.getSystemClassLoader();
+        Class<?> entry = loader.loadClass("integration_tests.ClassPath$Entry");
+        System.out.println(entry == Entry.class);
+        System.out.println(entry.getClassLoader() == loader);
+    }
+}
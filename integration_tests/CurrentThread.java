@@ -0,0 +1,27 @@
+package integration_tests;
+
+// requires: jdk
+public class CurrentThread {
+    private static native void print(String s);
+
+    private static native void print(int v);
+
+    private static native void print(boolean v);
+
+    public static void main(String[] args) {
+        Thread thread = Thread.currentThread();
+
+        print(thread == Thread.currentThread());
+        print("\n");
+        print(thread.getName());
+        print("\n");
+        print(thread.isDaemon());
+        print("\n");
+        print(thread.getPriority());
+        print("\n");
+
+        thread.setPriority(Thread.MAX_PRIORITY);
+        print(thread.getPriority());
+        print("\n");
+    }
+}
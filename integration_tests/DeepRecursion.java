@@ -0,0 +1,23 @@
+package integration_tests;
+
+// requires: jdk
+public class DeepRecursion {
+    private static int depth;
+
+    private static void recurse() {
+        depth++;
+        recurse();
+    }
+
+    public static void main(String[] args) {
+        for (int i = 0; i < 2; i++) {
+            depth = 0;
+            try {
+                recurse();
+            } catch (StackOverflowError e) {
+                System.out.println("caught StackOverflowError");
+            }
+        }
+        System.out.println(depth > 100);
+    }
+}
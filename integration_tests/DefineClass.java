@@ -0,0 +1,57 @@
+package integration_tests;
+
+import java.lang.reflect.Method;
+
+// requires: jdk
+public class DefineClass {
+    // The class file for:
+    //
+    //     package integration_tests;
+    //
+    //     public class Generated {
+    //         public static String greet() {
+    //             return "hello from a generated class";
+    //         }
+    //     }
+    static final byte[] GENERATED = {
+            -54, -2, -70, -66, 0, 0, 0, 61, 0, 14, 10, 0, 2, 0, 3, 7,
+            0, 4, 12, 0, 5, 0, 6, 1, 0, 16, 106, 97, 118, 97, 47, 108,
+            97, 110, 103, 47, 79, 98, 106, 101, 99, 116, 1, 0, 6, 60, 105, 110,
+            105, 116, 62, 1, 0, 3, 40, 41, 86, 8, 0, 8, 1, 0, 28, 104,
+            101, 108, 108, 111, 32, 102, 114, 111, 109, 32, 97, 32, 103, 101, 110, 101,
+            114, 97, 116, 101, 100, 32, 99, 108, 97, 115, 115, 7, 0, 10, 1, 0,
+            27, 105, 110, 116, 101, 103, 114, 97, 116, 105, 111, 110, 95, 116, 101, 115,
+            116, 115, 47, 71, 101, 110, 101, 114, 97, 116, 101, 100, 1, 0, 4, 67,
+            111, 100, 101, 1, 0, 5, 103, 114, 101, 101, 116, 1, 0, 20, 40, 41,
+            76, 106, 97, 118, 97, 47, 108, 97, 110, 103, 47, 83, 116, 114, 105, 110,
+            103, 59, 0, 33, 0, 9, 0, 2, 0, 0, 0, 0, 0, 2, 0, 1,
+            0, 5, 0, 6, 0, 1, 0, 11, 0, 0, 0, 17, 0, 1, 0, 1,
+            0, 0, 0, 5, 42, -73, 0, 1, -79, 0, 0, 0, 0, 0, 9, 0,
+            12, 0, 13, 0, 1, 0, 11, 0, 0, 0, 15, 0, 1, 0, 0, 0,
+            0, 0, 3, 18, 7, -80, 0, 0, 0, 0, 0, 0,
+    };
+
+    static class BytesLoader extends ClassLoader {
+        BytesLoader(ClassLoader parent) {
+            super(parent);
+        }
+
+        Class<?> define(String name, byte[] bytes) {
+            return defineClass(name, bytes, 0, bytes.length);
+        }
+    }
+
+    public static void main(String[] args) throws Exception {
+        BytesLoader loader = new BytesLoader(DefineClass.class.getClassLoader());
+        Class<?> generated = loader.define("integration_tests.Generated", GENERATED);
+        System.out.println(generated.getName());
+        System.out.println(generated.getClassLoader() == loader);
+
+        Method greet = generated.getDeclaredMethod("greet");
+        System.out.println((String) greet.invoke(null));
+
+        // Defined classes can be found by name through their loader
+        System.out.println(loader.loadClass("integration_tests.Generated") == generated);
+        System.out.println(Class.forName("integration_tests.Generated", true, loader) == generated);
+    }
+}
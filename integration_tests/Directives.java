@@ -0,0 +1,19 @@
+package integration_tests;
+
+import java.io.IOException;
+
+// args: first second
+// stdin: Directives.txt
+// expect-exception: java.lang.ArrayIndexOutOfBoundsException
+public class Directives {
+    public static void main(String[] args) throws IOException {
+        int c;
+        while ((c = System.in.read()) != -1) {
+            System.out.write(c);
+        }
+
+        System.out.println(args[0]);
+        System.out.println(args[1]);
+        System.out.println(args[2]);
+    }
+}
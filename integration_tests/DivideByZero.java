@@ -0,0 +1,17 @@
+package integration_tests;
+
+// expect-exception: java.lang.ArithmeticException
+class DivideByZero {
+    static native void print(String v);
+
+    static native void print(int v);
+
+    public static void main(String[] args) {
+        print("1 % 0 = ");
+        print(rem(1, 0));
+    }
+
+    private static int rem(int a, int b) {
+        return a % b;
+    }
+}
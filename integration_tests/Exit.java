@@ -0,0 +1,58 @@
+package integration_tests;
+
+// exit-code: 3
+// requires: jdk
+public class Exit {
+    static class Hook extends Thread {
+        private final String message;
+
+        Hook(String message) {
+            this.message = message;
+        }
+
+        @Override
+        public void run() {
+            print(message);
+        }
+    }
+
+    static class FailingHook extends Thread {
+        FailingHook() {
+            super("failing");
+        }
+
+        @Override
+        public void run() {
+            throw new IllegalStateException("failing hook");
+        }
+    }
+
+    private static native void print(String s);
+
+    private static native void print(boolean v);
+
+    public static void main(String[] args) {
+        Runtime runtime = Runtime.getRuntime();
+
+        Hook removed = new Hook("removed hook\n");
+        runtime.addShutdownHook(new Hook("first hook\n"));
+        runtime.addShutdownHook(removed);
+        // A hook which throws is reported, without changing the exit status or stopping the
+        // other hooks
+        runtime.addShutdownHook(new FailingHook());
+        runtime.addShutdownHook(new Hook("second hook\n"));
+
+        print(runtime.removeShutdownHook(removed));
+        print("\n");
+        print(runtime.removeShutdownHook(removed));
+        print("\n");
+
+        exit();
+        print("unreachable\n");
+    }
+
+    private static void exit() {
+        print("exiting\n");
+        System.exit(3);
+    }
+}
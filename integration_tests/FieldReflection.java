@@ -0,0 +1,57 @@
+package integration_tests;
+
+import java.lang.reflect.Field;
+
+// requires: jdk
+public class FieldReflection {
+    public static int counter = 5;
+
+    public String label;
+    public double ratio;
+    public final int id;
+
+    FieldReflection(int id) {
+        this.id = id;
+    }
+
+    public static void main(String[] args) throws Exception {
+        Field[] fields = FieldReflection.class.getDeclaredFields();
+        System.out.println(fields.length);
+
+        Field counterField = FieldReflection.class.getDeclaredField("counter");
+        System.out.println(counterField.getName());
+        System.out.println(counterField.getType().getName());
+        System.out.println(((Integer) counterField.get(null)).intValue());
+        counterField.set(null, 7);
+        System.out.println(counter);
+
+        FieldReflection object = new FieldReflection(1);
+
+        Field labelField = FieldReflection.class.getDeclaredField("label");
+        System.out.println(labelField.get(object) == null);
+        labelField.set(object, "hello");
+        System.out.println(object.label);
+        System.out.println((String) labelField.get(object));
+
+        // Values are unboxed and widened to the type of the field
+        Field ratioField = FieldReflection.class.getDeclaredField("ratio");
+        ratioField.set(object, 3);
+        System.out.println(object.ratio);
+
+        // Final instance fields can be set once access checks are suppressed
+        Field idField = FieldReflection.class.getDeclaredField("id");
+        idField.setAccessible(true);
+        idField.set(object, 2);
+        System.out.println(((Integer) idField.get(object)).intValue());
+
+        Field secretField = Holder.class.getDeclaredField("secret");
+        secretField.setAccessible(true);
+        Holder holder = new Holder();
+        secretField.set(holder, 'x');
+        System.out.println(((Character) secretField.get(holder)).charValue());
+    }
+}
+
+class Holder {
+    private char secret = 'a';
+}
@@ -0,0 +1,28 @@
+package integration_tests;
+
+public class FloatBits {
+    private static native void print(String s);
+
+    private static native void print(int v);
+
+    private static native void print(long v);
+
+    private static native void print(float v);
+
+    private static native void print(double v);
+
+    public static void main(String[] args) {
+        print(Float.floatToRawIntBits(1.0f));
+        print("\n");
+        print(Float.floatToRawIntBits(-2.5f));
+        print("\n");
+        print(Float.intBitsToFloat(0x40490fdb));
+        print("\n");
+        print(Double.doubleToRawLongBits(1.0));
+        print("\n");
+        print(Double.doubleToRawLongBits(-2.5));
+        print("\n");
+        print(Double.longBitsToDouble(0x400921fb54442d18L));
+        print("\n");
+    }
+}
@@ -0,0 +1,39 @@
+package integration_tests;
+
+import java.lang.reflect.Field;
+import java.lang.reflect.Method;
+import java.util.ArrayList;
+import java.util.List;
+import java.util.Map;
+
+// requires: jdk
+public class GenericSignatures {
+    static class Box<T extends Comparable<T>> extends ArrayList<T> {
+        Map<String, ? extends List<T>> entries;
+        int count;
+
+        <U> U convert(List<? super T> values, U fallback) {
+            return fallback;
+        }
+    }
+
+    public static void main(String[] args) throws Exception {
+        // The signatures are parsed by the JDK's reflection code, so only the raw strings are
+        // checked here
+        Method getGenericSignature = Class.class.getDeclaredMethod("getGenericSignature0");
+        getGenericSignature.setAccessible(true);
+        System.out.println(getGenericSignature.invoke(Box.class));
+        System.out.println(getGenericSignature.invoke(GenericSignatures.class));
+
+        Field fieldSignature = Field.class.getDeclaredField("signature");
+        fieldSignature.setAccessible(true);
+        System.out.println(fieldSignature.get(Box.class.getDeclaredField("entries")));
+        System.out.println(fieldSignature.get(Box.class.getDeclaredField("count")));
+
+        Field methodSignature = Method.class.getDeclaredField("signature");
+        methodSignature.setAccessible(true);
+        for (Method method : Box.class.getDeclaredMethods()) {
+            System.out.println(methodSignature.get(method));
+        }
+    }
+}
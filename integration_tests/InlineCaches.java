@@ -0,0 +1,39 @@
+package integration_tests;
+
+public class InlineCaches {
+    static class Animal {
+        String sound() {
+            return "...";
+        }
+    }
+
+    static class Dog extends Animal {
+        String sound() {
+            return "woof";
+        }
+    }
+
+    static class Cat extends Animal {
+        String sound() {
+            return "meow";
+        }
+    }
+
+    static int twice(int value) {
+        return value + value;
+    }
+
+    public static void main(String[] args) {
+        // The same call site sees a different receiver class on each iteration
+        Animal[] animals = {new Dog(), new Dog(), new Cat(), new Animal(), new Dog()};
+        for (Animal animal : animals) {
+            System.out.println(animal.sound());
+        }
+
+        int total = 0;
+        for (int i = 0; i < 5; i++) {
+            total += twice(i);
+        }
+        System.out.println(total);
+    }
+}
@@ -0,0 +1,93 @@
+package integration_tests;
+
+public class Interfaces {
+    interface Shape {
+        int area();
+
+        default String describe() {
+            return "shape";
+        }
+    }
+
+    interface Named {
+        String name();
+    }
+
+    interface NamedShape extends Shape, Named {
+        default String describe() {
+            return name();
+        }
+    }
+
+    interface Counter {
+        int next();
+
+        static Counter from(int start) {
+            return new StepCounter(start, 1);
+        }
+    }
+
+    static class Square implements Shape {
+        private final int side;
+
+        Square(int side) {
+            this.side = side;
+        }
+
+        public int area() {
+            return side + side;
+        }
+    }
+
+    static class Rectangle implements NamedShape {
+        public int area() {
+            return 6;
+        }
+
+        public String name() {
+            return "rectangle";
+        }
+    }
+
+    static class Base {
+        public String name() {
+            return "inherited";
+        }
+    }
+
+    static class Child extends Base implements Named {}
+
+    static class StepCounter implements Counter {
+        private int value;
+        private final int step;
+
+        StepCounter(int value, int step) {
+            this.value = value;
+            this.step = step;
+        }
+
+        public int next() {
+            value += step;
+            return value;
+        }
+    }
+
+    public static void main(String[] args) {
+        Shape[] shapes = {new Square(3), new Rectangle()};
+        for (Shape shape : shapes) {
+            System.out.println(shape.area());
+            System.out.println(shape.describe());
+        }
+
+        Named named = new Child();
+        System.out.println(named.name());
+
+        Counter counter = Counter.from(10);
+        counter.next();
+        System.out.println(counter.next());
+
+        Object rectangle = new Rectangle();
+        System.out.println(((NamedShape) rectangle).describe());
+        System.out.println(((Named) rectangle).name());
+    }
+}
@@ -0,0 +1,57 @@
+package integration_tests;
+
+public class Jit {
+    static int fib(int n) {
+        if (n < 2) {
+            return n;
+        }
+        return fib(n - 1) + fib(n - 2);
+    }
+
+    static int add(int a, int b) {
+        return a + b;
+    }
+
+    // Calls back into the interpreter from a loop
+    static int sumTo(int n) {
+        int total = 0;
+        for (int i = 1; i <= n; i++) {
+            total = add(total, i);
+        }
+        return total;
+    }
+
+    static int lastDigit(int value) {
+        return value % 10;
+    }
+
+    static int mask(int value, int bits) {
+        return value & ((1 << bits) - 1);
+    }
+
+    // Overflows, and uses each of the operations which are compiled
+    static long mix(int a, long b) {
+        int x = a * 0x9E3779B9 + (a >> 3) - (a >>> 5);
+        x ^= -x | (byte) a;
+        x += (short) (x * 31) + (char) x;
+        long y = b * x / (a | 1) + b % 7 - (b >> 2) + (b >>> 60);
+        return (y ^ ~y << 13) + (y < b ? 1 : 0) + Integer.MIN_VALUE / (a % 2 - 2);
+    }
+
+    public static void main(String[] args) {
+        // Each method is called enough times to be compiled partway through
+        System.out.println(fib(20));
+
+        int total = 0;
+        for (int i = 0; i < 3000; i++) {
+            total = total + sumTo(mask(i, 3)) + lastDigit(0 - i);
+        }
+        System.out.println(total);
+
+        long hash = 0;
+        for (int i = 0; i < 3000; i++) {
+            hash = hash * 31 + mix(i * 0x01000193, hash);
+        }
+        System.out.println(hash);
+    }
+}
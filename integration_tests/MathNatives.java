@@ -0,0 +1,39 @@
+package integration_tests;
+
+// requires: jdk
+public class MathNatives {
+    public static void main(String[] args) {
+        System.out.println(Math.sqrt(2.0));
+        System.out.println(Math.sqrt(-1.0));
+        System.out.println(Math.sin(0.0));
+        System.out.println(Math.cos(0.0));
+        System.out.println(Math.log(1.0));
+        System.out.println(Math.log(0.0));
+        System.out.println(Math.exp(0.0));
+        System.out.println(Math.pow(2.0, 10.0));
+        System.out.println(Math.pow(1.0, Double.NaN));
+        System.out.println(Math.pow(-1.0, Double.POSITIVE_INFINITY));
+        System.out.println(Math.floor(-1.5));
+        System.out.println(Math.ceil(-1.5));
+        System.out.println(Math.ceil(-0.5));
+        System.out.println(Math.rint(2.5));
+        System.out.println(Math.rint(3.5));
+        System.out.println(Math.cbrt(27.0));
+        System.out.println(Math.cbrt(-2.0));
+        System.out.println(Math.cbrt(1.0E-310));
+        System.out.println(Math.hypot(3.0, 4.0));
+        System.out.println(StrictMath.sqrt(16.0));
+        System.out.println(StrictMath.log10(1000.0));
+        System.out.println(StrictMath.exp(1.0));
+        System.out.println(StrictMath.cosh(1.0));
+        System.out.println(StrictMath.tan(2.4375));
+        System.out.println(StrictMath.atan(Math.PI));
+        System.out.println(StrictMath.acos(0.4375));
+        System.out.println(StrictMath.log(2.356194490192345));
+        System.out.println(StrictMath.sin(1.0E300));
+        System.out.println(StrictMath.cos(1.0E22));
+        System.out.println(StrictMath.atan2(-1.0, -0.0));
+        System.out.println(StrictMath.IEEEremainder(5.0, 3.0));
+        System.out.println(StrictMath.pow(2.0, 0.5));
+    }
+}
@@ -0,0 +1,18 @@
+package integration_tests;
+
+public class ModifiedUtf8 {
+    public static void main(String[] args) {
+        // Class files encode nul and supplementary characters differently to UTF-8
+        String nul = "a\0b";
+        System.out.println(nul.length());
+        System.out.println((int) nul.charAt(1));
+
+        String emoji = "\uD83D\uDE00!";
+        System.out.println(emoji);
+        System.out.println(emoji.length());
+        System.out.println((int) emoji.charAt(0));
+        System.out.println((int) emoji.charAt(1));
+
+        System.out.println("caf\u00e9 \u20ac");
+    }
+}
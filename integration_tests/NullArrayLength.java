@@ -0,0 +1,14 @@
+package integration_tests;
+
+// expect-exception: java.lang.NullPointerException
+class NullArrayLength {
+    static native void print(String v);
+
+    static native void print(int v);
+
+    public static void main(String[] args) {
+        int[] values = args.length > 0 ? new int[1] : null;
+        print("length: ");
+        print(values.length);
+    }
+}
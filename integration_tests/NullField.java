@@ -0,0 +1,16 @@
+package integration_tests;
+
+// expect-exception: java.lang.NullPointerException
+class NullField {
+    static native void print(String v);
+
+    static native void print(int v);
+
+    int value = 1;
+
+    public static void main(String[] args) {
+        NullField field = args.length > 0 ? new NullField() : null;
+        print("value: ");
+        print(field.value);
+    }
+}
@@ -0,0 +1,17 @@
+package integration_tests;
+
+import java.util.concurrent.atomic.AtomicInteger;
+
+// requires: jdk
+public class PrintStreams {
+    public static void main(String[] args) {
+        System.out.println("na\u00efve caf\u00e9 \u2713 \ud83d\ude00");
+        System.out.print('x');
+        System.out.print(2.5);
+        System.out.println(new AtomicInteger(7).incrementAndGet());
+        System.out.write('!');
+        System.out.println();
+        System.out.println(System.out.checkError());
+        System.err.println("to stderr \u00fc");
+    }
+}
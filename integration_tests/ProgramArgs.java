@@ -0,0 +1,11 @@
+package integration_tests;
+
+public class ProgramArgs {
+    public static void main(String[] args) {
+        System.out.println(args.length);
+
+        for (int i = 0; i < args.length; i++) {
+            System.out.println(args[i]);
+        }
+    }
+}
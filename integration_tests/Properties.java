@@ -0,0 +1,26 @@
+package integration_tests;
+
+public class Properties {
+    private static native void print(String s);
+
+    public static void main(String[] args) {
+        print(System.getProperty("file.encoding"));
+        print(System.lineSeparator());
+        print(System.getProperty("line.separator"));
+        print(System.getProperty("missing"));
+        print("\n");
+        print(System.getProperty("missing", "default"));
+        print("\n");
+
+        print(System.setProperty("custom", "first"));
+        print("\n");
+        print(System.setProperty("custom", "second"));
+        print("\n");
+        print(System.getProperty("custom"));
+        print("\n");
+        print(System.clearProperty("custom"));
+        print("\n");
+        print(System.getProperty("custom"));
+        print("\n");
+    }
+}
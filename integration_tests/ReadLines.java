@@ -0,0 +1,27 @@
+package integration_tests;
+
+import java.io.BufferedReader;
+import java.io.IOException;
+import java.io.InputStreamReader;
+
+// requires: jdk
+public class ReadLines {
+    private static native void print(String s);
+
+    private static native void print(int i);
+
+    public static void main(String[] args) throws IOException {
+        BufferedReader reader = new BufferedReader(new InputStreamReader(System.in));
+
+        int count = 0;
+        String line;
+        while ((line = reader.readLine()) != null) {
+            print(line.toUpperCase());
+            print("\n");
+            count++;
+        }
+
+        print(count);
+        print("\n");
+    }
+}
@@ -0,0 +1,75 @@
+package integration_tests;
+
+import java.lang.reflect.Method;
+
+// requires: jdk
+public class Reflection {
+    public static int add(int a, int b) {
+        return a + b;
+    }
+
+    public static int twice(int value) {
+        return value + value;
+    }
+
+    public static String greet(String name) {
+        return name;
+    }
+
+    public static void nothing() {
+    }
+
+    public static class Base {
+        public String name() {
+            return "base";
+        }
+    }
+
+    public static class Derived extends Base {
+        @Override
+        public String name() {
+            return "derived";
+        }
+    }
+
+    public static void main(String[] args) throws Exception {
+        Method[] methods = Reflection.class.getDeclaredMethods();
+        System.out.println(methods.length);
+        // The order of declared methods isn't specified, so only one is checked
+        for (Method method : methods) {
+            if (method.getName().equals("add")) {
+                System.out.println(method.getParameterTypes().length);
+            }
+        }
+
+        Method add = Reflection.class.getDeclaredMethod("add", int.class, int.class);
+        Integer sum = (Integer) add.invoke(null, 2, 3);
+        System.out.println(sum.intValue());
+
+        // Arguments are unboxed and widened to the parameter types
+        Method twice = Reflection.class.getDeclaredMethod("twice", int.class);
+        System.out.println(((Integer) twice.invoke(null, 'a')).intValue());
+        System.out.println(((Integer) twice.invoke(null, (short) 4)).intValue());
+
+        Method greet = Reflection.class.getDeclaredMethod("greet", String.class);
+        System.out.println((String) greet.invoke(null, "hello"));
+
+        Method nothing = Reflection.class.getDeclaredMethod("nothing");
+        System.out.println(nothing.invoke(null) == null);
+
+        // Instance methods are selected from the class of the receiver
+        Method name = Base.class.getDeclaredMethod("name");
+        System.out.println((String) name.invoke(new Base()));
+        System.out.println((String) name.invoke(new Derived()));
+
+        Method secret = Hidden.class.getDeclaredMethod("secret", int.class);
+        secret.setAccessible(true);
+        System.out.println(((Integer) secret.invoke(null, 41)).intValue());
+    }
+}
+
+class Hidden {
+    private static int secret(int value) {
+        return value + 1;
+    }
+}
@@ -0,0 +1,25 @@
+package integration_tests;
+
+import java.util.Scanner;
+
+// requires: jdk
+public class ScanInput {
+    private static native void print(String s);
+
+    private static native void print(int i);
+
+    public static void main(String[] args) {
+        Scanner scanner = new Scanner(System.in);
+
+        String name = scanner.nextLine();
+        print(name);
+        print("\n");
+
+        int sum = 0;
+        while (scanner.hasNextInt()) {
+            sum += scanner.nextInt();
+        }
+        print(sum);
+        print("\n");
+    }
+}
@@ -0,0 +1,35 @@
+package integration_tests;
+
+// exit-code: 4
+// requires: jdk
+public class ShutdownHookExit {
+    static class Hook extends Thread {
+        private final String message;
+        private final int status;
+
+        Hook(String message, int status) {
+            this.message = message;
+            this.status = status;
+        }
+
+        @Override
+        public void run() {
+            print(message);
+            if (status >= 0) {
+                System.exit(status);
+            }
+        }
+    }
+
+    private static native void print(String s);
+
+    public static void main(String[] args) {
+        Runtime runtime = Runtime.getRuntime();
+        runtime.addShutdownHook(new Hook("first hook\n", -1));
+        // Exiting from a hook decides the exit status, and the remaining hooks don't run
+        runtime.addShutdownHook(new Hook("exiting hook\n", 4));
+        runtime.addShutdownHook(new Hook("unreachable hook\n", -1));
+
+        print("main returns\n");
+    }
+}
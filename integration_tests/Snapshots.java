@@ -0,0 +1,59 @@
+package integration_tests;
+
+public class Snapshots {
+    static class Node {
+        final int value;
+        final Node next;
+
+        Node(int value, Node next) {
+            this.value = value;
+            this.next = next;
+        }
+    }
+
+    static final int[] TRIANGLES = new int[8];
+    static final String NAME = "snapshots";
+    static String greeting = "hello";
+    static Integer boxed = 100;
+    static Node list;
+    static int calls;
+
+    static {
+        System.out.println("initializing");
+
+        int sum = 0;
+        for (int i = 0; i < TRIANGLES.length; i++) {
+            sum += i;
+            TRIANGLES[i] = sum;
+        }
+
+        for (int i = 0; i < 3; i++) {
+            list = new Node(i, list);
+        }
+    }
+
+    public static void call() {
+        calls++;
+    }
+
+    public static void print() {
+        System.out.println(calls);
+
+        for (int triangle : TRIANGLES) {
+            System.out.println(triangle);
+        }
+
+        for (Node node = list; node != null; node = node.next) {
+            System.out.println(node.value);
+        }
+
+        System.out.println(NAME == "snapshots");
+        System.out.println(boxed == Integer.valueOf(100));
+        System.out.println(list.getClass() == Node.class);
+    }
+
+    public static void main(String[] args) {
+        call();
+        print();
+    }
+}
@@ -0,0 +1,32 @@
+package integration_tests;
+
+import java.io.IOException;
+
+public class StdIn {
+    public static void main(String[] args) throws IOException {
+        int first = System.in.read();
+        System.out.println(first);
+
+        byte[] buffer = new byte[5];
+        int n = System.in.read(buffer);
+        System.out.println(n);
+        System.out.write(buffer, 0, n);
+        System.out.println();
+
+        System.out.println(System.in.read(buffer, 1, 0));
+
+        int lines = 0;
+        int c;
+        while ((c = System.in.read()) != -1) {
+            if (c == '\n') {
+                lines++;
+            } else {
+                System.out.write(c);
+            }
+        }
+        System.out.println();
+
+        System.out.println(lines);
+        System.out.println(System.in.read(buffer));
+    }
+}
@@ -0,0 +1,29 @@
+package integration_tests;
+
+// requires: jdk
+public class StringBuilderFields {
+    public static void main(String[] args) {
+        StringBuilder sb = new StringBuilder("hello");
+        sb.append(' ').append("world");
+
+        // These aren't replaced by the vm, so the JDK implementation reads the builder's fields
+        System.out.println(sb.capacity() >= sb.length());
+        sb.insert(0, "> ");
+        System.out.println(sb);
+
+        // The methods which are replaced keep working once the fields are used
+        sb.append('!').append(42);
+        System.out.println(sb.toString());
+        System.out.println(sb.length());
+        System.out.println(sb.charAt(2));
+
+        System.out.println(sb.reverse());
+        sb.setLength(3);
+        System.out.println(sb);
+
+        StringBuilder unicode = new StringBuilder("caf\u00e9 \u2603");
+        unicode.insert(4, '\u00e0');
+        System.out.println(unicode.indexOf("\u2603"));
+        System.out.println(new String(unicode).length());
+    }
+}
@@ -0,0 +1,35 @@
+package integration_tests;
+
+public class StringBuilders {
+    static class Point {
+        @Override
+        public String toString() {
+            return new StringBuilder("(").append(1).append(", ").append(2).append(')').toString();
+        }
+    }
+
+    public static void main(String[] args) {
+        StringBuilder sb = new StringBuilder();
+        sb.append("int: ").append(42).append(", long: ").append(-7L);
+        sb.append(", bool: ").append(true).append(", char: ").append('x');
+        sb.append(", float: ").append(1.5f).append(", double: ").append(1e10);
+        System.out.println(sb.toString());
+        System.out.println(sb.length());
+
+        String nothing = null;
+        StringBuilder point = new StringBuilder(16);
+        point.append("point: ").append(new Point());
+        point.append(", null: ").append(nothing);
+        System.out.println(point);
+
+        char[] chars = {'a', 'b', 'c'};
+        StringBuilder other = new StringBuilder("chars: ").append(chars);
+        System.out.println(other);
+        System.out.println(other.charAt(7));
+
+        other.setLength(5);
+        System.out.println(other);
+
+        System.out.println(new StringBuilder((CharSequence) "seq").append((CharSequence) other));
+    }
+}
@@ -0,0 +1,49 @@
+package integration_tests;
+
+public class StringIntern {
+    static class Symbols {
+        static final String FOO = "foo";
+    }
+
+    public static void main(String[] args) {
+        String a = "hello";
+        String b = "hello";
+        System.out.println(a == b);
+        System.out.println(Symbols.FOO == "foo");
+
+        String built = new StringBuilder("hel").append("lo").toString();
+        System.out.println(built == a);
+        System.out.println(built.equals(a));
+        System.out.println(built.intern() == a);
+
+        String fresh = new StringBuilder("unique").append(1).toString();
+        String interned = fresh.intern();
+        System.out.println(interned == "unique1");
+        System.out.println(interned.intern() == interned);
+
+        System.out.println(a.hashCode());
+        System.out.println("".hashCode());
+        System.out.println(a.length());
+        System.out.println(a.isEmpty());
+        System.out.println(a.charAt(1));
+        System.out.println(a.equals("world"));
+        System.out.println(a.equals(null));
+
+        // Indexed by UTF-16 code units, so the emoji is two chars
+        String emoji = "a\uD83D\uDE00";
+        System.out.println(emoji.length());
+        System.out.println((int) emoji.charAt(1));
+
+        // Indexing a long string in a loop doesn't slow down with its length
+        StringBuilder builder = new StringBuilder();
+        for (int i = 0; i < 100000; i++) {
+            builder.append((char) ('a' + i % 26));
+        }
+        String text = builder.toString();
+        int sum = 0;
+        for (int i = 0; i < text.length(); i++) {
+            sum += text.charAt(i);
+        }
+        System.out.println(sum);
+    }
+}
@@ -0,0 +1,48 @@
+package integration_tests;
+
+public class Superinstructions {
+    private final int width;
+    private final int height;
+
+    Superinstructions(int width, int height) {
+        this.width = width;
+        this.height = height;
+    }
+
+    int perimeter() {
+        // aload_0; getfield
+        int width = this.width;
+        int height = this.height;
+        // iload; iload; iadd
+        int sum = width + height;
+        return sum + sum;
+    }
+
+    public static void main(String[] args) {
+        System.out.println(new Superinstructions(3, 4).perimeter());
+
+        // iload; bipush; if_icmpge
+        int total = 0;
+        for (int i = 0; i < 20; i++) {
+            total = total + i;
+        }
+        System.out.println(total);
+
+        // iload; sipush; if_icmple, and iload; iconst; if_icmpne
+        int steps = 0;
+        for (int i = 1000; i > 300; i -= 100) {
+            steps++;
+        }
+        System.out.println(steps);
+        if (steps != 5) {
+            System.out.println("not five");
+        }
+
+        // Negative constants
+        int n = -3;
+        while (n < -1) {
+            n++;
+        }
+        System.out.println(n);
+    }
+}
@@ -0,0 +1,37 @@
+package integration_tests;
+
+public class SystemOut {
+    static class Point {
+        private final int x;
+        private final int y;
+
+        Point(int x, int y) {
+            this.x = x;
+            this.y = y;
+        }
+
+        @Override
+        public String toString() {
+            return "Point";
+        }
+    }
+
+    public static void main(String[] args) {
+        System.out.println("Hello, world!");
+        System.out.print("no newline, ");
+        System.out.println(42);
+        System.out.println(-7L);
+        System.out.println(true);
+        System.out.println('c');
+        System.out.println(1.5f);
+        System.out.println(0.1);
+        System.out.println(1e10);
+        System.out.println(1.25e-5f);
+        System.out.println(new Point(1, 2));
+        System.out.println((Object) null);
+        System.out.println();
+        System.out.write('!');
+        System.out.write('\n');
+        System.err.println("to stderr");
+    }
+}
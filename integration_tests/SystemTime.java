@@ -12,5 +12,7 @@ public class SystemTime {
     public static void main(String[] args) {
         print("Current time: ");
         print(System.currentTimeMillis());
+        print("\nNano time: ");
+        print(System.nanoTime());
     }
 }
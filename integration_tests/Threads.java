@@ -0,0 +1,38 @@
+package integration_tests;
+
+// requires: jdk
+public class Threads {
+    private static native void print(String s);
+
+    private static native void print(boolean b);
+
+    public static void main(String[] args) throws InterruptedException {
+        print("sleeping\n");
+        Thread.sleep(10);
+        Thread.yield();
+        print("awake\n");
+
+        // An interrupt before sleeping makes the sleep throw straight away
+        Thread.currentThread().interrupt();
+        print(Thread.currentThread().isInterrupted());
+        print("\n");
+        try {
+            Thread.sleep(60_000);
+            print("not interrupted\n");
+        } catch (InterruptedException e) {
+            print(e.getMessage());
+            print("\n");
+        }
+
+        // Throwing clears the interrupt status
+        print(Thread.currentThread().isInterrupted());
+        print("\n");
+
+        // So does Thread.interrupted, after which sleeping doesn't throw
+        Thread.currentThread().interrupt();
+        print(Thread.interrupted());
+        print("\n");
+        Thread.sleep(10);
+        print("slept\n");
+    }
+}
@@ -0,0 +1,23 @@
+package integration_tests;
+
+public class WideArguments {
+    private final long base;
+
+    WideArguments(long base) {
+        this.base = base;
+    }
+
+    // The int arguments come after longs, which take up two locals each
+    static long sum(long a, int b, long c, int d) {
+        return a + b + c + d;
+    }
+
+    long offset(int a, long b, int c) {
+        return base + a + b + c;
+    }
+
+    public static void main(String[] args) {
+        System.out.println(sum(10000000000L, 2, 30000000000L, 4));
+        System.out.println(new WideArguments(100).offset(1, 20000000000L, 3));
+    }
+}
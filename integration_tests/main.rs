@@ -1,14 +1,56 @@
-#![feature(exit_status_error)]
-
+use std::collections::{HashMap, VecDeque};
+use std::env;
 use std::fs::{self, File};
-use std::path::Path;
-use std::process::Command;
-use std::time::{Duration, SystemTime};
+use std::io::{self, Cursor, Read, Write};
+use std::mem;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
 use bumpalo::Bump;
-use color_eyre::eyre::{self, ContextCompat};
+use byteorder::{BigEndian, ReadBytesExt};
+use color_eyre::eyre::{self, bail, ContextCompat, WrapErr};
+use jdk_tools::dependencies;
 use libtest_mimic::{Arguments, Failed, Trial};
-use rusty_java::vm::{TimeProvider, Vm};
+use rusty_java::assembler::ClassBuilder;
+use rusty_java::budget::{Budget, Limit};
+use rusty_java::call_frame::{JvmValue, LocalVariables, Operands};
+use rusty_java::cfg::ControlFlowGraph;
+use rusty_java::class::decode_instructions;
+use rusty_java::class_archive::ClassArchive;
+use rusty_java::class_file::{
+    AttributeInfo, ClassFile, CodeAttribute, CustomAttribute, ExceptionTableEntry,
+    FieldAccessFlags, MethodAccessFlags,
+};
+use rusty_java::class_path::ClassPath;
+use rusty_java::convert::{Reference, ToJvm};
+use rusty_java::coverage::Coverage;
+use rusty_java::debugger::Debugger;
+use rusty_java::disassembler::disassemble;
+use rusty_java::error::Error;
+use rusty_java::hooks::{Allocation, AllocationKind, Hook, MethodRef};
+use rusty_java::instructions::{
+    ArrayType, Condition, Instruction, InvokeKind, LoadStoreType, NumberType, ReturnType,
+};
+use rusty_java::ir::Function;
+use rusty_java::jdwp::JdwpAgent;
+use rusty_java::jimage::{self, JImage};
+use rusty_java::object::{Array, Object};
+use rusty_java::owned_vm::OwnedVm;
+use rusty_java::profiler::{MethodSamples, Profiler};
+use rusty_java::reader::ClassReader;
+use rusty_java::snapshot::Snapshot;
+use rusty_java::stats::ExecutionStats;
+use rusty_java::trace::{CallTracer, InstructionTracer};
+use rusty_java::verifier::Verify;
+use rusty_java::vm::{Output, TimeProvider, Vm, VmHandle};
+use rusty_java::writer::ClassWriter;
 
 fn main() -> eyre::Result<()> {
     color_eyre::install()?;
@@ -16,10 +58,20 @@ fn main() -> eyre::Result<()> {
     let args = Arguments::from_args();
     let tests_dir = Path::new(file!()).parent().unwrap();
 
-    let tests = fs::read_dir(tests_dir)?
+    // Without a JDK, the tests which need its classes are skipped, and the others run with the
+    // vm's shims instead
+    let has_jdk = has_jdk();
+
+    let mut tests: Vec<_> = fs::read_dir(tests_dir)?
         .flatten()
         .filter_map(|entry| {
             let path = entry.path();
+
+            // Tests with several classes can be put in a directory, with main in Main.java
+            if path.join("Main.java").is_file() {
+                return Some(path.file_name()?.to_str()?.to_owned());
+            }
+
             let ext = path.extension()?.to_str()?;
 
             if ext == "java" {
@@ -28,25 +80,350 @@ fn main() -> eyre::Result<()> {
                 None
             }
         })
-        .map(create_trial)
+        .map(|name| create_trial(name, has_jdk))
         .collect();
 
+    tests.push(
+        Trial::test("class_file_round_trip", || {
+            class_file_round_trip().map_err(|e| format!("{e:?}").into())
+        })
+        .with_ignored_flag(!has_jdk),
+    );
+
+    tests.push(Trial::test("attribute_lengths", || {
+        attribute_lengths().map_err(|e| format!("{e:?}").into())
+    }));
+
+    tests.push(Trial::test("lenient_attributes", || {
+        lenient_attributes().map_err(|e| format!("{e:?}").into())
+    }));
+
+    tests.push(
+        Trial::test("jimage_classes", || {
+            jimage_classes().map_err(|e| format!("{e:?}").into())
+        })
+        .with_ignored_flag(!has_jdk),
+    );
+
+    tests.push(
+        Trial::test("class_dependencies", || {
+            class_dependencies().map_err(|e| format!("{e:?}").into())
+        })
+        .with_ignored_flag(!has_jdk),
+    );
+
+    tests.push(Trial::test("dependency_closure", || {
+        dependency_closure().map_err(|e| format!("{e:?}").into())
+    }));
+
+    tests.push(
+        Trial::test("assembled_class", || {
+            assembled_class().map_err(|e| format!("{e:?}").into())
+        })
+        .with_ignored_flag(!has_jdk),
+    );
+
+    tests.push(
+        Trial::test("owned_vm", || {
+            owned_vm().map_err(|e| format!("{e:?}").into())
+        })
+        .with_ignored_flag(!has_jdk),
+    );
+
+    tests.push(
+        Trial::test("invoke_methods", || {
+            invoke_methods().map_err(|e| format!("{e:?}").into())
+        })
+        .with_ignored_flag(!has_jdk),
+    );
+
+    tests.push(
+        Trial::test("value_conversions", || {
+            value_conversions().map_err(|e| format!("{e:?}").into())
+        })
+        .with_ignored_flag(!has_jdk),
+    );
+
+    tests.push(
+        Trial::test("objects", || objects().map_err(|e| format!("{e:?}").into()))
+            .with_ignored_flag(!has_jdk),
+    );
+
+    tests.push(
+        Trial::test("arrays", || arrays().map_err(|e| format!("{e:?}").into()))
+            .with_ignored_flag(!has_jdk),
+    );
+
+    tests.push(
+        Trial::test("strings", || strings().map_err(|e| format!("{e:?}").into()))
+            .with_ignored_flag(!has_jdk),
+    );
+
+    tests.push(
+        Trial::test("hooks", || hooks().map_err(|e| format!("{e:?}").into()))
+            .with_ignored_flag(!has_jdk),
+    );
+
+    tests.push(
+        Trial::test("instruction_trace", || {
+            instruction_trace().map_err(|e| format!("{e:?}").into())
+        })
+        .with_ignored_flag(!has_jdk),
+    );
+
+    tests.push(Trial::test("call_trace", || {
+        call_trace().map_err(|e| format!("{e:?}").into())
+    }));
+
+    tests.push(Trial::test("execution_stats", || {
+        execution_stats().map_err(|e| format!("{e:?}").into())
+    }));
+
+    tests.push(Trial::test("sampling_profiler", || {
+        sampling_profiler().map_err(|e| format!("{e:?}").into())
+    }));
+
+    tests.push(Trial::test("coverage", || {
+        coverage().map_err(|e| format!("{e:?}").into())
+    }));
+
+    tests.push(Trial::test("output_redirection", || {
+        output_redirection().map_err(|e| format!("{e:?}").into())
+    }));
+
+    tests.push(Trial::test("owned_writers", || {
+        owned_writers().map_err(|e| format!("{e:?}").into())
+    }));
+
+    tests.push(Trial::test("snapshots", || {
+        snapshots().map_err(|e| format!("{e:?}").into())
+    }));
+
+    tests.push(
+        Trial::test("class_archive_round_trip", || {
+            class_archive_round_trip().map_err(|e| format!("{e:?}").into())
+        })
+        .with_ignored_flag(!has_jdk),
+    );
+
+    tests.push(Trial::test("heap_dump", || {
+        heap_dump().map_err(|e| format!("{e:?}").into())
+    }));
+
+    tests.push(Trial::test("debugger_session", || {
+        debugger_session().map_err(|e| format!("{e:?}").into())
+    }));
+
+    tests.push(Trial::test("jdwp_breakpoint", || {
+        jdwp_breakpoint().map_err(|e| format!("{e:?}").into())
+    }));
+
+    tests.push(
+        Trial::test("budgets", || budgets().map_err(|e| format!("{e:?}").into()))
+            .with_ignored_flag(!has_jdk),
+    );
+
+    tests.push(
+        Trial::test("interrupt_sleep", || {
+            interrupt_sleep().map_err(|e| format!("{e:?}").into())
+        })
+        .with_ignored_flag(!has_jdk),
+    );
+
+    tests.push(Trial::test("timeouts", || {
+        timeouts().map_err(|e| format!("{e:?}").into())
+    }));
+
+    tests.push(Trial::test("disassembled_class", || {
+        disassembled_class().map_err(|e| format!("{e:?}").into())
+    }));
+
+    tests.push(Trial::test("class_file_json", || {
+        class_file_json().map_err(|e| format!("{e:?}").into())
+    }));
+
+    tests.push(Trial::test("control_flow_graph", || {
+        control_flow_graph().map_err(|e| format!("{e:?}").into())
+    }));
+
+    tests.push(Trial::test("register_ir", || {
+        register_ir().map_err(|e| format!("{e:?}").into())
+    }));
+
+    tests.push(
+        Trial::test("verify_error", || {
+            verify_error().map_err(|e| format!("{e:?}").into())
+        })
+        .with_ignored_flag(!has_jdk),
+    );
+
+    tests.push(
+        Trial::test("invalid_code", || {
+            invalid_code().map_err(|e| format!("{e:?}").into())
+        })
+        .with_ignored_flag(!has_jdk),
+    );
+
+    tests.push(
+        Trial::test("error_frames", || {
+            error_frames().map_err(|e| format!("{e:?}").into())
+        })
+        .with_ignored_flag(!has_jdk),
+    );
+
+    tests.push(
+        Trial::test("missing_field", || {
+            missing_field().map_err(|e| format!("{e:?}").into())
+        })
+        .with_ignored_flag(!has_jdk),
+    );
+
+    tests.push(
+        Trial::test("stack_overflow", || {
+            stack_overflow().map_err(|e| format!("{e:?}").into())
+        })
+        .with_ignored_flag(!has_jdk),
+    );
+
+    tests.push(
+        Trial::test("class_circularity", || {
+            class_circularity().map_err(|e| format!("{e:?}").into())
+        })
+        .with_ignored_flag(!has_jdk),
+    );
+
+    tests.push(
+        Trial::test("unsupported_class_version", || {
+            unsupported_class_version().map_err(|e| format!("{e:?}").into())
+        })
+        .with_ignored_flag(!has_jdk),
+    );
+
+    tests.push(
+        Trial::test("hotness", || hotness().map_err(|e| format!("{e:?}").into()))
+            .with_ignored_flag(!has_jdk),
+    );
+
+    tests.push(
+        Trial::test("symbols", || symbols().map_err(|e| format!("{e:?}").into()))
+            .with_ignored_flag(!has_jdk),
+    );
+
     libtest_mimic::run(&args, tests).exit();
 }
 
-fn create_trial(name: String) -> Trial {
+/// How long a test program can run for, unless it sets its own timeout. This can be changed with
+/// the `RUSTY_JAVA_TEST_TIMEOUT` environment variable, in seconds.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+fn create_trial(name: String, has_jdk: bool) -> Trial {
+    let directives = Directives::for_test(&name);
+    let requires_jdk = directives
+        .as_ref()
+        .is_ok_and(|directives| directives.requires_jdk);
+
     Trial::test(name.clone(), move || {
-        if let Err(e) = run_trial(&name) {
+        let result = directives.and_then(|directives| {
+            let timeout = match directives.timeout {
+                Some(timeout) => timeout,
+                None => default_timeout()?,
+            };
+            let name = name.clone();
+            with_timeout(timeout, move |stopper| {
+                run_trial(&name, directives, stopper)
+            })?
+        });
+
+        if let Err(e) = result {
             eprintln!("{e:?}");
             return Err(Failed::without_message());
         }
         Ok(())
     })
+    .with_ignored_flag(requires_jdk && !has_jdk)
+}
+
+fn has_jdk() -> bool {
+    jimage::find_java_home().is_some_and(|java_home| jimage::jimage_path(java_home).exists())
+}
+
+fn default_timeout() -> eyre::Result<Duration> {
+    match env::var("RUSTY_JAVA_TEST_TIMEOUT") {
+        Ok(secs) => Ok(Duration::from_secs(secs.parse().wrap_err_with(|| {
+            format!("invalid RUSTY_JAVA_TEST_TIMEOUT: {secs}")
+        })?)),
+        Err(_) => Ok(DEFAULT_TIMEOUT),
+    }
+}
+
+/// How long to wait for a function which timed out to return once its vm has been stopped.
+const STOP_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Runs a function on another thread, failing if it doesn't return within the timeout, so that a
+/// bug which makes the interpreter loop forever fails its test instead of hanging the test run.
+/// The function's vm is stopped when it times out, so that the thread doesn't keep running. The
+/// thread is only left running if it's stuck outside of Java code.
+fn with_timeout<T: Send + 'static>(
+    timeout: Duration,
+    f: impl FnOnce(&Stopper) -> T + Send + 'static,
+) -> eyre::Result<T> {
+    let (tx, rx) = mpsc::channel();
+    let stopper = Stopper::default();
+    let thread = thread::spawn({
+        let stopper = stopper.clone();
+        move || {
+            // The receiver is gone if the vm couldn't be stopped in time
+            let _ = tx.send(f(&stopper));
+        }
+    });
+
+    match rx.recv_timeout(timeout) {
+        Ok(value) => Ok(value),
+        Err(RecvTimeoutError::Timeout) => {
+            stopper.stop();
+            let _ = rx.recv_timeout(STOP_TIMEOUT);
+            bail!("timed out after {timeout:?}")
+        }
+        // Failed snapshot assertions panic, which should fail the test in the same way
+        Err(RecvTimeoutError::Disconnected) => panic::resume_unwind(thread.join().unwrap_err()),
+    }
+}
+
+/// Stops the vm of a function run by [`with_timeout`] once it times out. The function gives it its
+/// vm with [`Stopper::watch`] once it has created it.
+#[derive(Clone, Default)]
+struct Stopper(Arc<Mutex<StopperState>>);
+
+#[derive(Default)]
+struct StopperState {
+    handle: Option<VmHandle>,
+    stopped: bool,
+}
+
+impl Stopper {
+    /// Stops the vm if the function has already timed out, and otherwise when it does.
+    fn watch(&self, vm: &Vm) {
+        let mut state = self.0.lock().unwrap();
+        let handle = vm.handle();
+        if state.stopped {
+            handle.stop();
+        }
+        state.handle = Some(handle);
+    }
+
+    fn stop(&self) {
+        let mut state = self.0.lock().unwrap();
+        state.stopped = true;
+        if let Some(handle) = &state.handle {
+            handle.stop();
+        }
+    }
 }
 
-fn run_trial(name: &str) -> eyre::Result<()> {
+fn run_trial(name: &str, directives: Directives, stopper: &Stopper) -> eyre::Result<()> {
     let arena = Bump::new();
     let mut stdout = Vec::new();
+    let mut stderr = Vec::new();
 
     struct MockTimeProvider;
 
@@ -55,52 +432,2963 @@ fn run_trial(name: &str) -> eyre::Result<()> {
             // ~30 years after EPOCH
             SystemTime::UNIX_EPOCH + Duration::from_secs(60 * 60 * 24 * 30 * 12 * 30)
         }
+
+        fn monotonic_time(&self) -> Duration {
+            Duration::from_nanos(123_456_789)
+        }
     }
 
-    let mut vm = Vm::new(&arena, &mut stdout).with_time_provider(Box::new(MockTimeProvider));
+    let test_path = test_path(name);
+    let source_file_path = source_file_path(&test_path);
 
-    let source_file_path = Path::new(file!())
-        .parent()
-        .unwrap()
-        .join(name)
-        .with_extension("java");
+    // Input for System.in can be provided in a file next to the test
+    let input = match &directives.stdin {
+        Some(path) => {
+            let path = source_file_path.with_file_name(path);
+            fs::read(&path).wrap_err_with(|| format!("failed to read {path:?}"))?
+        }
+        None => fs::read(source_file_path.with_extension("stdin")).unwrap_or_default(),
+    };
+    let stdin = input.as_slice();
+
+    // Arguments for main can be provided in a file next to the test, one per line, which allows
+    // arguments with spaces
+    let args: Vec<String> = match directives.args {
+        Some(args) => args,
+        None => fs::read_to_string(source_file_path.with_extension("args"))
+            .map(|args| args.lines().map(str::to_owned).collect())
+            .unwrap_or_default(),
+    };
 
-    if !check_stamp(&source_file_path) {
-        eprintln!("{source_file_path:?} was modified, recompiling");
-        Command::new("javac")
-            .arg(&source_file_path)
-            .status()?
-            .exit_ok()?;
-        File::create(source_file_path.with_extension("stamp"))?;
+    let vm = Vm::new(&arena, &mut stdout)
+        .with_stdin(stdin)
+        .with_stderr(&mut stderr)
+        .with_time_provider(Box::new(MockTimeProvider));
+    stopper.watch(&vm);
+
+    if test_path.is_dir() {
+        compile_dir(&test_path)?;
+    } else {
+        compile(&source_file_path)?;
     }
 
     let class_file_path = source_file_path.with_extension("class");
     let class = vm.load_class_file(class_file_path.to_str().unwrap())?;
 
-    vm.call_method(
-        class,
-        class
-            .method("main", "([Ljava/lang/String;)V")
-            .wrap_err("main method not found")?,
-    )?;
+    let (status, exception) = match vm.run_main(class, &args) {
+        Ok(status) => (status, None),
+        Err(e) => {
+            let (Some(expected), Some(exception)) = (&directives.expect_exception, e.exception())
+            else {
+                return Err(e.into());
+            };
+            if exception.class_name.replace('/', ".") != *expected {
+                bail!("expected {expected} to be thrown, but {exception} was thrown");
+            }
+            // Like java, the program exits with a status of 1
+            (1, Some(exception.to_string()))
+        }
+    };
+
+    drop(vm);
+
+    if let (Some(expected), None) = (&directives.expect_exception, &exception) {
+        bail!("expected {expected} to be thrown, but the program exited with {status}");
+    }
+
+    if let Some(expected) = directives.exit_code {
+        if status != expected {
+            bail!("expected the program to exit with {expected}, but it exited with {status}");
+        }
+    }
+
+    let mut stdout = String::from_utf8(stdout)?;
+    let stderr = String::from_utf8(stderr)?;
+
+    if !stderr.is_empty() {
+        if !stdout.is_empty() && !stdout.ends_with('\n') {
+            stdout.push('\n');
+        }
+        stdout += "[stderr]\n";
+        stdout += &stderr;
+    }
 
-    let stdout = String::from_utf8(stdout)?;
+    if let Some(exception) = exception {
+        if !stdout.is_empty() && !stdout.ends_with('\n') {
+            stdout.push('\n');
+        }
+        stdout += &format!("[exception: {exception}]");
+    } else if status != 0 {
+        if !stdout.is_empty() && !stdout.ends_with('\n') {
+            stdout.push('\n');
+        }
+        stdout += &format!("[exit status: {status}]");
+    }
 
     insta::assert_snapshot!(name, stdout);
 
     Ok(())
 }
 
-fn check_stamp(path: impl AsRef<Path>) -> bool {
-    let path = path.as_ref();
-    let stamp_path = path.with_extension("stamp");
+/// Returns the path of a test program, which is either a java source or a directory of them.
+fn test_path(name: &str) -> PathBuf {
+    let path = Path::new(file!()).parent().unwrap().join(name);
+    if path.is_dir() {
+        path
+    } else {
+        path.with_extension("java")
+    }
+}
+
+/// Returns the path of the source with the main method of a test program.
+fn source_file_path(test_path: &Path) -> PathBuf {
+    if test_path.is_dir() {
+        test_path.join("Main.java")
+    } else {
+        test_path.to_owned()
+    }
+}
+
+/// Options for a test program, given by comments in its source, e.g.
+///
+/// ```java
+/// // args: first second
+/// // stdin: Input.txt
+/// // exit-code: 2
+/// // expect-exception: java.lang.ArrayIndexOutOfBoundsException
+/// // timeout: 120
+/// // requires: jdk
+/// ```
+#[derive(Default)]
+struct Directives {
+    /// Arguments for main, separated by whitespace.
+    args: Option<Vec<String>>,
+    /// A file next to the test, which is given to the program as System.in.
+    stdin: Option<String>,
+    /// The status which the program must exit with.
+    exit_code: Option<i32>,
+    /// The binary name of an exception which the program must fail with.
+    expect_exception: Option<String>,
+    /// How long the program can run for, given in seconds.
+    timeout: Option<Duration>,
+    /// Whether the program uses classes from the JDK which the vm's shims don't have, so that it
+    /// is skipped when there isn't a JDK.
+    requires_jdk: bool,
+}
+
+impl Directives {
+    fn for_test(name: &str) -> eyre::Result<Directives> {
+        let source_file_path = source_file_path(&test_path(name));
+        Directives::parse(&fs::read_to_string(&source_file_path)?)
+    }
+
+    fn parse(source: &str) -> eyre::Result<Directives> {
+        let mut directives = Directives::default();
+
+        for line in source.lines() {
+            let Some((key, value)) = line
+                .trim()
+                .strip_prefix("//")
+                .and_then(|comment| comment.split_once(':'))
+            else {
+                continue;
+            };
+
+            let value = value.trim();
+            match key.trim() {
+                "args" => {
+                    directives.args = Some(value.split_whitespace().map(str::to_owned).collect())
+                }
+                "stdin" => directives.stdin = Some(value.to_owned()),
+                "exit-code" => {
+                    directives.exit_code = Some(
+                        value
+                            .parse()
+                            .wrap_err_with(|| format!("invalid exit code: {value}"))?,
+                    )
+                }
+                "expect-exception" => directives.expect_exception = Some(value.to_owned()),
+                "timeout" => {
+                    directives.timeout = Some(Duration::from_secs(
+                        value
+                            .parse()
+                            .wrap_err_with(|| format!("invalid timeout: {value}"))?,
+                    ))
+                }
+                "requires" => match value {
+                    "jdk" => directives.requires_jdk = true,
+                    _ => bail!("unknown requirement: {value}"),
+                },
+                // Any other comment with a colon
+                _ => {}
+            }
+        }
+
+        Ok(directives)
+    }
+}
+
+/// Classes from the JDK which are read and written again, covering most kinds of constants and
+/// attributes.
+const ROUND_TRIP_CLASSES: &[&str] = &[
+    "/java.base/java/lang/Object.class",
+    "/java.base/java/lang/String.class",
+    "/java.base/java/lang/Character.class",
+    "/java.base/java/lang/Math.class",
+    "/java.base/java/lang/Thread.class",
+    "/java.base/java/lang/invoke/MethodHandles$Lookup.class",
+    "/java.base/java/util/HashMap.class",
+    "/java.base/java/util/concurrent/ConcurrentHashMap.class",
+    "/java.base/java/util/stream/Collectors.class",
+    "/java.base/jdk/internal/vm/annotation/Stable.class",
+    "/java.base/module-info.class",
+];
+
+/// Checks that reading a class file and writing it again produces the same bytes.
+fn class_file_round_trip() -> eyre::Result<()> {
+    let java_home = jimage::find_java_home().wrap_err("failed to find a JDK")?;
+    let jimage = JImage::open_java_home(java_home)?;
+
+    for name in ROUND_TRIP_CLASSES {
+        let bytes = jimage
+            .find_resource(name)?
+            .ok_or_else(|| eyre::eyre!("{name} not found"))?;
+
+        let arena = Bump::new();
+        let class_file = ClassReader::from_slice(&arena, bytes).read_class_file()?;
+
+        let mut written = vec![];
+        ClassWriter::new(&mut written).write_class_file(&class_file)?;
+
+        if written != bytes {
+            eyre::bail!("{name} was written differently after being read");
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that attributes which are shorter or longer than their parser reads, or which are cut
+/// off by the end of the file, are rejected with the offset of the attribute.
+fn attribute_lengths() -> eyre::Result<()> {
+    // A SourceFile attribute is a single constant pool index
+    let (bytes, offset) = class_with_source_file(&[0])?;
+    let arena = Bump::new();
+    let e = ClassReader::from_slice(&arena, &bytes)
+        .read_class_file()
+        .unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        format!("SourceFile attribute at offset {offset:#x} has length 1, but read 2")
+    );
+
+    let (bytes, offset) = class_with_source_file(&[0, 1, 0])?;
+    let e = ClassReader::from_slice(&arena, &bytes)
+        .read_class_file()
+        .unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        format!("SourceFile attribute at offset {offset:#x} has length 3, but read 2")
+    );
+
+    // The attribute's header is 6 bytes, so this cuts off the second byte of its contents
+    let (bytes, offset) = class_with_source_file(&[0, 1])?;
+    let e = ClassReader::from_slice(&arena, &bytes[..offset + 7])
+        .read_class_file()
+        .unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        format!("invalid SourceFile attribute at offset {offset:#x}")
+    );
+    assert!(
+        matches!(e.root(), Error::Io(e) if e.kind() == io::ErrorKind::UnexpectedEof),
+        "{}",
+        e.full_message()
+    );
+
+    Ok(())
+}
+
+/// Checks that malformed attributes which fail strict parsing are kept as raw bytes in lenient
+/// mode, without affecting the rest of the class file.
+fn lenient_attributes() -> eyre::Result<()> {
+    // Strict parsing reads past the end of an attribute which is too short, but lenient parsing
+    // only has the attribute's own bytes
+    for (info, error) in [
+        (
+            &[0][..],
+            "invalid SourceFile attribute at offset {offset}: failed to fill whole buffer",
+        ),
+        (
+            &[0, 1, 0],
+            "SourceFile attribute at offset {offset} has length 3, but read 2",
+        ),
+    ] {
+        let (bytes, offset) = class_with_source_file(info)?;
+        let error = error.replace("{offset}", &format!("{offset:#x}"));
+
+        let arena = Bump::new();
+        assert!(ClassReader::from_slice(&arena, &bytes)
+            .read_class_file()
+            .is_err());
+
+        let class_file = ClassReader::from_slice(&arena, &bytes)
+            .lenient(true)
+            .read_class_file()?;
+        assert_eq!(class_file.fields.len(), 2);
+        assert!(class_file.fields[1].attributes.is_empty());
+
+        let [AttributeInfo::Malformed(attribute)] = class_file.fields[0].attributes.as_slice()
+        else {
+            eyre::bail!("expected a malformed attribute");
+        };
+        assert_eq!(attribute.info, info);
+        assert_eq!(attribute.error.as_str(), error);
+
+        // Malformed attributes are written back unchanged
+        let mut written = vec![];
+        ClassWriter::new(&mut written).write_class_file(&class_file)?;
+        assert_eq!(written, bytes);
+    }
+
+    Ok(())
+}
+
+/// Assembles a class whose first field has a `SourceFile` attribute with the given contents,
+/// followed by another field. Returns the class file and the offset of the attribute.
+fn class_with_source_file(info: &[u8]) -> eyre::Result<(Vec<u8>, usize)> {
+    let arena = Bump::new();
+    let mut builder = ClassBuilder::new(&arena, "integration_tests/Attributes");
+    let name_index = builder.constant_pool().utf8("SourceFile");
+    builder
+        .field(FieldAccessFlags::PRIVATE, "a", "I")
+        .field(FieldAccessFlags::PRIVATE, "b", "I");
+
+    let mut class_file = builder.build();
+    class_file.fields[0]
+        .attributes
+        .push(AttributeInfo::Custom(CustomAttribute {
+            attribute_name_index: name_index,
+            info: arena.alloc_slice_copy(info),
+        }));
+
+    let mut bytes = vec![];
+    ClassWriter::new(&mut bytes).write_class_file(&class_file)?;
+
+    let mut attribute = name_index.to_be_bytes().to_vec();
+    attribute.extend_from_slice(&u32::try_from(info.len())?.to_be_bytes());
+    attribute.extend_from_slice(info);
+    let offset = bytes
+        .windows(attribute.len())
+        .position(|window| window == attribute)
+        .wrap_err("the attribute wasn't written")?;
+
+    Ok((bytes, offset))
+}
+
+/// Checks the classes which jdk-tools finds in the constant pools of JDK classes against the ones
+/// rusty-java's reader finds, and that they can all be found in the jimage.
+fn class_dependencies() -> eyre::Result<()> {
+    let java_home = jimage::find_java_home().wrap_err("failed to find a JDK")?;
+
+    let jimage = JImage::open_java_home(java_home)?;
+
+    for name in ROUND_TRIP_CLASSES {
+        let bytes = jimage
+            .find_resource(name)?
+            .ok_or_else(|| eyre::eyre!("{name} not found"))?;
+
+        let referenced = dependencies::referenced_classes(bytes)?;
+
+        let arena = Bump::new();
+        let class_file = ClassReader::from_slice(&arena, bytes).read_class_file()?;
+        let this_class = class_file.this_class_name().unwrap();
+        let pool = &class_file.constant_pool;
+
+        assert!(!referenced.contains(this_class));
+        if let Some(super_class) = class_file.super_class_name() {
+            assert!(referenced.contains(super_class), "{super_class} in {name}");
+        }
+
+        for constant in (1..=u16::MAX).map_while(|index| pool.get(index)) {
+            let Some(class) = constant.try_as_class_ref() else {
+                continue;
+            };
+            let class_name = *pool[class.name_index].try_as_utf_8_ref().unwrap();
+            if class_name != this_class && !class_name.starts_with('[') {
+                assert!(referenced.contains(class_name), "{class_name} in {name}");
+            }
+        }
+
+        for class_name in &referenced {
+            assert!(
+                jimage.find_class(class_name)?.is_some(),
+                "{class_name} in {name}"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that the dependency closure of a class includes the classes it refers to through its
+/// super class, descriptors and constant pool, and the classes those refer to in turn.
+fn dependency_closure() -> eyre::Result<()> {
+    let arena = Bump::new();
+    let mut classes = HashMap::new();
+
+    let mut main = ClassBuilder::new(&arena, "graph/Main");
+    main.super_class("graph/Base")
+        .field(FieldAccessFlags::PRIVATE, "items", "[Lgraph/Item;");
+    main.constant_pool()
+        .method_ref("graph/Util", "helper", "(Lgraph/Arg;)Lgraph/Result;");
+
+    // Refers back to Main, which is only visited once
+    let mut base = ClassBuilder::new(&arena, "graph/Base");
+    base.field(FieldAccessFlags::PRIVATE, "main", "Lgraph/Main;");
+
+    let builders = [
+        main,
+        base,
+        ClassBuilder::new(&arena, "graph/Item"),
+        ClassBuilder::new(&arena, "graph/Util"),
+        ClassBuilder::new(&arena, "graph/Arg"),
+    ];
+    for builder in builders {
+        let class_file = builder.build();
+        let mut bytes = vec![];
+        ClassWriter::new(&mut bytes).write_class_file(&class_file)?;
+        classes.insert(class_file.this_class_name().unwrap().to_owned(), bytes);
+    }
+
+    assert_eq!(
+        dependencies::referenced_classes(&classes["graph/Main"])?,
+        [
+            "graph/Arg",
+            "graph/Base",
+            "graph/Item",
+            "graph/Result",
+            "graph/Util",
+            "java/lang/Object",
+        ]
+        .map(str::to_owned)
+        .into()
+    );
+
+    let closure = dependencies::closure(["graph/Main".to_owned()], |name| {
+        Ok(classes.get(name).cloned())
+    })?;
+    assert_eq!(
+        closure.classes.keys().collect::<Vec<_>>(),
+        [
+            "graph/Arg",
+            "graph/Base",
+            "graph/Item",
+            "graph/Main",
+            "graph/Util"
+        ]
+    );
+    assert_eq!(closure.classes["graph/Base"], classes["graph/Base"]);
+
+    let mut missing = closure.missing;
+    missing.sort();
+    assert_eq!(missing, ["graph/Result", "java/lang/Object"]);
+
+    Ok(())
+}
+
+/// Checks that the classes in the JDK's jimage can be listed, and found again by name, and that
+/// the JDK's version can be read.
+fn jimage_classes() -> eyre::Result<()> {
+    let java_home = jimage::find_java_home().wrap_err("failed to find a JDK")?;
+
+    let version = jimage::java_version(&java_home).wrap_err("the JDK has no version")?;
+    assert!(
+        version.starts_with(|c: char| c.is_ascii_digit()),
+        "{version}"
+    );
+
+    let jimage = JImage::open_java_home(java_home)?;
+    let classes = jimage.classes()?;
+
+    assert!(classes.contains(&("java.base".to_owned(), "java/lang/Object".to_owned())));
+    assert!(classes.contains(&(
+        "java.logging".to_owned(),
+        "java/util/logging/Logger".to_owned()
+    )));
+    assert!(classes.windows(2).all(|pair| pair[0] < pair[1]));
 
-    if !stamp_path.exists() {
-        return false;
+    // Packages, modules and module-info aren't classes
+    assert!(classes
+        .iter()
+        .all(|(module, name)| module != "packages" && module != "modules" && name.contains('/')));
+
+    for (module, name) in classes
+        .iter()
+        .filter(|(_, name)| name.starts_with("java/util/function/"))
+    {
+        assert_eq!(
+            jimage.package_module("java/util/function")?.as_deref(),
+            Some(module.as_str())
+        );
+        assert!(jimage.find_class(name)?.is_some(), "{name} not found");
     }
 
-    let mtime = path.metadata().unwrap().modified().unwrap();
-    let stamp_mtime = stamp_path.metadata().unwrap().modified().unwrap();
+    Ok(())
+}
+
+/// Runs a class built with the assembler rather than javac.
+/// Assembles a class whose main method prints the numbers from 0 to 2.
+fn assemble_counting_class(arena: &Bump) -> eyre::Result<ClassFile> {
+    let mut builder = ClassBuilder::new(arena, "integration_tests/Assembled");
+
+    let constant_pool = builder.constant_pool();
+    let out = constant_pool.field_ref("java/lang/System", "out", "Ljava/io/PrintStream;");
+    let println = constant_pool.method_ref("java/io/PrintStream", "println", "(I)V");
+
+    // for (int i = 0; i < 3; i++) System.out.println(i);
+    builder.method(
+        MethodAccessFlags::PUBLIC | MethodAccessFlags::STATIC,
+        "main",
+        "([Ljava/lang/String;)V",
+        2,
+        2,
+        &[
+            Instruction::iconst(0),
+            Instruction::istore(1),
+            Instruction::iload(1),
+            Instruction::iconst(3),
+            Instruction::if_icmp {
+                condition: Condition::Ge,
+                branch: 6,
+            },
+            Instruction::getstatic { index: out },
+            Instruction::iload(1),
+            Instruction::invoke {
+                kind: InvokeKind::Virtual,
+                index: println,
+            },
+            Instruction::inc { index: 1, value: 1 },
+            Instruction::goto { branch: -7 },
+            Instruction::r#return {
+                data_type: ReturnType::Void,
+            },
+        ],
+    )?;
+
+    Ok(builder.build())
+}
+
+fn assembled_class() -> eyre::Result<()> {
+    let arena = Bump::new();
+    let class_file = assemble_counting_class(&arena)?;
+
+    let mut bytes = vec![];
+    ClassWriter::new(&mut bytes).write_class_file(&class_file)?;
+
+    let arena = Bump::new();
+    let mut stdout = Vec::new();
+    let vm = Vm::new(&arena, &mut stdout);
+    let class = vm.define_class(&bytes)?;
+    vm.run_main(class, &[])?;
+    drop(vm);
+
+    insta::assert_snapshot!("assembled_class", String::from_utf8(stdout)?);
+
+    Ok(())
+}
+
+/// Runs the assembled class in a vm which owns its arena and stdout.
+fn owned_vm() -> eyre::Result<()> {
+    let arena = Bump::new();
+    let class_file = assemble_counting_class(&arena)?;
+
+    let mut bytes = vec![];
+    ClassWriter::new(&mut bytes).write_class_file(&class_file)?;
+
+    let vm = OwnedVm::with_config(Vec::new(), |vm| vm.with_verify(Verify::Remote));
+    vm.with(|vm| {
+        let class = vm.define_class(&bytes)?;
+        vm.run_main(class, &[])
+    })?;
+
+    assert_eq!(String::from_utf8(vm.into_stdout())?, "0\n1\n2\n");
+
+    Ok(())
+}
+
+/// Calls methods of JDK classes from Rust, with arguments and results converted from and to
+/// Rust values.
+fn invoke_methods() -> eyre::Result<()> {
+    let vm = OwnedVm::with_stdout(Vec::new());
+    vm.with(|vm| -> eyre::Result<()> {
+        assert_eq!(
+            vm.invoke_static::<_, i32>("java/lang/Math", "max", (3, 7))?,
+            7
+        );
+        assert_eq!(
+            vm.invoke_static::<_, i64>("java/lang/Long", "sum", (1i64 << 40, 2i64))?,
+            (1 << 40) + 2
+        );
+        assert!(!vm.invoke_static::<_, bool>("java/lang/Boolean", "logicalAnd", (true, false))?);
 
-    stamp_mtime > mtime
+        let hello = JvmValue::StringConst("hello");
+        assert_eq!(
+            vm.invoke_instance::<_, i32>(hello.clone(), "length", ())?,
+            5
+        );
+        assert!(!vm.invoke_instance::<_, bool>(hello, "isEmpty", ())?);
+
+        // Java exceptions are returned as errors
+        let e = vm
+            .invoke_instance::<_, i32>(JvmValue::Reference(0), "hashCode", ())
+            .unwrap_err();
+        assert_eq!(
+            e.exception().unwrap().class_name,
+            "java/lang/NullPointerException"
+        );
+
+        // The descriptor comes from the types, so the wrong types don't find the method
+        assert!(vm
+            .invoke_static::<_, i32>("java/lang/Math", "max", (3i64, 7i64))
+            .is_err());
+
+        Ok(())
+    })
+}
+
+/// Converts Rust values to and from Java values, including arrays, and implements natives with
+/// typed Rust functions.
+fn value_conversions() -> eyre::Result<()> {
+    let vm = OwnedVm::with_stdout(Vec::new());
+    vm.with(|vm| -> eyre::Result<()> {
+        assert_eq!(
+            vm.invoke_static::<_, i32>("java/lang/Byte", "toUnsignedInt", (-1i8,))?,
+            255
+        );
+        assert_eq!(
+            vm.invoke_static::<_, i32>("java/lang/Float", "floatToRawIntBits", (1.0f32,))?,
+            0x3f80_0000
+        );
+        assert_eq!(
+            vm.invoke_instance::<_, char>(JvmValue::StringConst("héllo"), "charAt", (1,))?,
+            'é'
+        );
+        assert_eq!(
+            vm.invoke_instance::<_, String>(JvmValue::StringConst("hi"), "toString", ())?,
+            "hi"
+        );
+        assert_eq!(
+            vm.invoke_static::<_, Vec<i32>>("java/util/Arrays", "copyOf", (vec![1, 2, 3], 2))?,
+            [1, 2]
+        );
+        assert!('😀'.to_jvm(vm).is_err());
+
+        // Natives registered as Rust functions get their descriptors from the function's types
+        vm.register_fn("java/lang/Math", "max", |_: &Vm, a: i32, b: i32| {
+            Ok(a.min(b))
+        });
+        assert_eq!(
+            vm.invoke_static::<_, i32>("java/lang/Math", "max", (3, 7))?,
+            3
+        );
+
+        vm.register_method("java/lang/String", "length", |_: &Vm, this: &str| {
+            Ok(this.chars().count() as i32 * 2)
+        });
+        assert_eq!(
+            vm.invoke_instance::<_, i32>(JvmValue::StringConst("abc"), "length", ())?,
+            6
+        );
+
+        vm.register_fn(
+            "java/lang/Math",
+            "sqrt",
+            |_: &Vm, a: f64| -> Result<f64, Error> {
+                Err(Error::Message(format!("not implemented: sqrt({a})")))
+            },
+        );
+        assert!(vm
+            .invoke_static::<_, f64>("java/lang/Math", "sqrt", (2.0,))
+            .is_err());
+
+        // References are typed too, with null rejected unless the parameter is an Option
+        vm.register_native(
+            "java/util/Objects",
+            "requireNonNull",
+            "(Ljava/lang/Object;)Ljava/lang/Object;",
+            |_: &Vm, value: Reference| Ok(value),
+        )?;
+        let list = vm.new_object("java/util/ArrayList", "()V", ())?;
+        let list = Reference(list.reference());
+        assert_eq!(
+            vm.invoke_static::<_, Reference>("java/util/Objects", "requireNonNull", (list,))?,
+            list
+        );
+        let e = vm
+            .invoke_static::<_, JvmValue>(
+                "java/util/Objects",
+                "requireNonNull",
+                (JvmValue::Reference(0),),
+            )
+            .unwrap_err();
+        assert_eq!(
+            e.exception().unwrap().class_name,
+            "java/lang/NullPointerException"
+        );
+
+        // Descriptors which don't match the function's types are rejected when registering
+        let e = vm
+            .register_native("java/lang/Math", "abs", "(J)J", |_: &Vm, a: i32| Ok(a))
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "native java/lang/Math.abs(J)J doesn't match its function, which has type (I)I"
+        );
+        assert!(vm
+            .register_native("java/lang/Math", "abs", "(I", |_: &Vm, a: i32| Ok(a))
+            .is_err());
+        assert!(vm
+            .register_native(
+                "java/lang/String",
+                "isEmpty",
+                "()Z",
+                |_: &Vm, this: &str| Ok(this.is_empty())
+            )
+            .is_ok());
+        assert!(vm
+            .register_native(
+                "java/lang/Integer",
+                "bitCount",
+                "(I)I",
+                |_: &Vm, a: &str| { Ok(a.len() as i32) }
+            )
+            .is_err());
+
+        Ok(())
+    })
+}
+
+/// Creates JDK objects from Rust, and reads and writes their fields.
+fn objects() -> eyre::Result<()> {
+    let vm = OwnedVm::with_stdout(Vec::new());
+    vm.with(|vm| -> eyre::Result<()> {
+        let list = vm.new_object("java/util/ArrayList", "(I)V", (4,))?;
+        assert_eq!(list.class().name(), "java/util/ArrayList");
+        assert_eq!(list.get::<Vec<JvmValue>>("elementData")?.len(), 4);
+        list.set("size", 2)?;
+        assert_eq!(list.call::<_, i32>("size", ())?, 2);
+
+        // Fields may be declared by superclasses
+        list.set("modCount", 3)?;
+        assert_eq!(list.get::<i32>("modCount")?, 3);
+
+        // Fields must be assigned values of their own type
+        assert!(list.set("size", 1i64).is_err());
+        assert!(list.get::<i32>("missing").is_err());
+
+        let entry = vm.new_object(
+            "java/util/AbstractMap$SimpleEntry",
+            "(Ljava/lang/Object;Ljava/lang/Object;)V",
+            ("key", list),
+        )?;
+        assert_eq!(entry.get::<&str>("key")?, "key");
+        let value = entry.call::<_, JvmValue>("getValue", ())?;
+        assert_eq!(Object::new(vm, value)?.call::<_, i32>("size", ())?, 2);
+
+        entry.set("value", "value")?;
+        assert_eq!(entry.call::<_, String>("toString", ())?, "key=value");
+
+        // Abstract classes can't be instantiated
+        let e = vm.new_object("java/lang/Number", "()V", ()).unwrap_err();
+        assert_eq!(
+            e.exception().unwrap().class_name,
+            "java/lang/InstantiationError"
+        );
+        assert!(vm.new_object("java/util/ArrayList", "(I)V", ()).is_err());
+
+        Ok(())
+    })
+}
+
+/// Creates arrays from Rust slices, and reads them back after they're modified by Java code.
+fn arrays() -> eyre::Result<()> {
+    let vm = OwnedVm::with_stdout(Vec::new());
+    vm.with(|vm| -> eyre::Result<()> {
+        let numbers = vm.new_array(&[3, 1, 2])?;
+        vm.invoke_static::<_, ()>("java/util/Arrays", "fill", (numbers, 7))?;
+        assert_eq!(numbers.as_slice(), [7, 7, 7]);
+
+        let bytes = vm.new_array(&[1i8, 2, 3])?;
+        bytes.set(0, -1)?;
+        assert_eq!(bytes.get(0)?, -1);
+        assert_eq!(bytes.to_vec()?, [-1, 2, 3]);
+        bytes.copy_from_slice(&[4, 5, 6])?;
+        assert_eq!(bytes.as_slice(), [4, 5, 6]);
+
+        let e = bytes.get(3).unwrap_err();
+        assert_eq!(
+            e.exception().unwrap().class_name,
+            "java/lang/ArrayIndexOutOfBoundsException"
+        );
+
+        // Handles are checked against the element type of the array
+        let value = JvmValue::Reference(numbers.reference());
+        assert!(Array::<i64>::new(vm, value.clone()).is_err());
+        assert_eq!(Array::<i32>::new(vm, value)?.len(), 3);
+        assert_eq!(
+            vm.invoke_static::<_, Vec<i32>>("java/util/Arrays", "copyOf", (numbers, 2))?,
+            [7, 7]
+        );
+
+        Ok(())
+    })?;
+
+    // String arrays can be passed to main methods
+    let source_file_path = Path::new(file!())
+        .parent()
+        .unwrap()
+        .join("ProgramArgs.java");
+    compile(&source_file_path)?;
+    vm.with(|vm| -> eyre::Result<()> {
+        let args = vm.new_array(&["a", "b"])?;
+        assert_eq!(args.get(1)?, "b");
+        vm.invoke_static::<_, ()>("integration_tests/ProgramArgs", "main", (args,))?;
+        Ok(())
+    })?;
+    assert_eq!(String::from_utf8(vm.into_stdout())?, "2\na\nb\n");
+
+    Ok(())
+}
+
+/// Passes strings between Rust and Java code, including null strings.
+fn strings() -> eyre::Result<()> {
+    let vm = OwnedVm::with_stdout(Vec::new());
+    vm.with(|vm| -> eyre::Result<()> {
+        let hello = vm.new_string("héllo");
+        assert_eq!(vm.string_value(&hello)?, Some("héllo"));
+        assert_eq!(vm.string_value(&JvmValue::Reference(0))?, None);
+        assert!(vm.string_value(&JvmValue::Int(1)).is_err());
+
+        assert_eq!(vm.invoke_instance::<_, i32>(hello, "length", ())?, 5);
+        assert_eq!(
+            vm.invoke_static::<_, Option<String>>(
+                "java/lang/System",
+                "getProperty",
+                ("java.version",)
+            )?
+            .as_deref(),
+            Some("17")
+        );
+        assert_eq!(
+            vm.invoke_static::<_, Option<&str>>("java/lang/System", "getProperty", ("missing",))?,
+            None
+        );
+
+        // Null strings can be passed as `None`
+        assert!(!vm.invoke_static::<_, bool>(
+            "java/lang/Boolean",
+            "parseBoolean",
+            (None::<&str>,)
+        )?);
+        assert!(vm
+            .invoke_static::<_, String>("java/lang/System", "getProperty", ("missing",))
+            .is_err());
+
+        Ok(())
+    })
+}
+
+/// Records the events that hooks are called for while calling methods and allocating objects.
+fn hooks() -> eyre::Result<()> {
+    #[derive(Default)]
+    struct Recorder {
+        events: Mutex<Vec<String>>,
+        instructions: AtomicUsize,
+    }
+
+    impl Recorder {
+        fn take_events(&self) -> Vec<String> {
+            mem::take(&mut self.events.lock().unwrap())
+        }
+    }
+
+    impl<'a> Hook<'a> for Recorder {
+        fn on_method_enter(&self, _: &Vm<'a>, method: MethodRef<'a>, _: &[JvmValue<'a>]) {
+            let event = format!("enter {}.{}", method.class.name(), method.name());
+            self.events.lock().unwrap().push(event);
+        }
+
+        fn on_method_exit(
+            &self,
+            _: &Vm<'a>,
+            method: MethodRef<'a>,
+            result: &Result<Option<JvmValue<'a>>, Error>,
+        ) {
+            let event = format!(
+                "exit {}.{} {:?}",
+                method.class.name(),
+                method.name(),
+                result.as_ref().ok()
+            );
+            self.events.lock().unwrap().push(event);
+        }
+
+        fn on_instruction(
+            &self,
+            _: &Vm<'a>,
+            _: MethodRef<'a>,
+            _: usize,
+            _: &'a Instruction,
+            _: LocalVariables<'_, 'a>,
+            _: Operands<'_, 'a>,
+        ) {
+            self.instructions.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn on_allocation(&self, _: &Vm<'a>, allocation: &Allocation<'a>) {
+            let event = match allocation.kind {
+                AllocationKind::Object(class) => format!("new {}", class.name()),
+                AllocationKind::Array {
+                    element_type,
+                    length,
+                } => format!("new {element_type:?}[{length}]"),
+            };
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    let recorder = Arc::new(Recorder::default());
+    let hook = recorder.clone();
+    let vm = OwnedVm::with_config(Vec::new(), |vm| vm.with_hook(hook));
+    vm.with(|vm| -> eyre::Result<()> {
+        vm.load_class_file("java/lang/Math")?;
+        vm.load_class_file("java/lang/String")?;
+        recorder.take_events();
+        recorder.instructions.store(0, Ordering::Relaxed);
+
+        assert_eq!(
+            vm.invoke_static::<_, i32>("java/lang/Math", "max", (3, 7))?,
+            7
+        );
+        assert_eq!(
+            recorder.take_events(),
+            [
+                "enter java/lang/Math.max",
+                "exit java/lang/Math.max Some(Some(Int(7)))"
+            ]
+        );
+        assert!(recorder.instructions.load(Ordering::Relaxed) > 0);
+
+        // Natives are entered too
+        assert_eq!(
+            vm.invoke_instance::<_, i32>(vm.new_string("abc"), "length", ())?,
+            3
+        );
+        assert_eq!(
+            recorder.take_events(),
+            [
+                "enter java/lang/String.length",
+                "exit java/lang/String.length Some(Some(Int(3)))"
+            ]
+        );
+
+        vm.new_array(&[1, 2])?;
+        vm.new_array(&["a"])?;
+        assert_eq!(recorder.take_events(), ["new Some(Int)[2]", "new None[1]"]);
+
+        Ok(())
+    })
+}
+
+/// Traces the instructions of a method, along with the operand stack before each one. Instructions
+/// are shown at their bytecode offsets, so the ones after the invoke skip ahead.
+fn instruction_trace() -> eyre::Result<()> {
+    let arena = Bump::new();
+    let mut builder = ClassBuilder::new(&arena, "integration_tests/Trace");
+    let length = builder
+        .constant_pool()
+        .method_ref("java/lang/String", "length", "()I");
+
+    // return s.length() + n;
+    builder.method(
+        MethodAccessFlags::PUBLIC | MethodAccessFlags::STATIC,
+        "add",
+        "(Ljava/lang/String;I)I",
+        2,
+        2,
+        &[
+            Instruction::load {
+                data_type: LoadStoreType::Reference,
+                index: 0,
+            },
+            Instruction::invoke {
+                kind: InvokeKind::Virtual,
+                index: length,
+            },
+            Instruction::load {
+                data_type: LoadStoreType::Int,
+                index: 1,
+            },
+            Instruction::add {
+                data_type: NumberType::Int,
+            },
+            Instruction::r#return {
+                data_type: ReturnType::Int,
+            },
+        ],
+    )?;
+
+    let mut bytes = vec![];
+    ClassWriter::new(&mut bytes).write_class_file(&builder.build())?;
+
+    let tracer = Arc::new(InstructionTracer::new(Vec::new()));
+    let hook = tracer.clone();
+    let vm = OwnedVm::with_config(Vec::new(), |vm| vm.with_hook(hook));
+    vm.with(|vm| -> eyre::Result<()> {
+        vm.define_class(&bytes)?;
+        let result = vm.invoke_static::<_, i32>("integration_tests/Trace", "add", ("abc", 4))?;
+        assert_eq!(result, 7);
+        Ok(())
+    })?;
+    drop(vm);
+
+    let Ok(tracer) = Arc::try_unwrap(tracer) else {
+        bail!("the tracer is still in use");
+    };
+    let trace = String::from_utf8(tracer.into_inner())?;
+
+    // Code in the JDK is traced too, so only the lines for the method are checked
+    let lines = trace
+        .lines()
+        .filter(|line| line.starts_with("integration_tests/Trace."))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        lines,
+        [
+            "integration_tests/Trace.add(Ljava/lang/String;I)I 0: \
+             load { data_type: Reference, index: 0 } []",
+            &format!(
+                "integration_tests/Trace.add(Ljava/lang/String;I)I 1: \
+                 invoke {{ kind: Virtual, index: {length} }} [\"abc\"]"
+            ),
+            "integration_tests/Trace.add(Ljava/lang/String;I)I 4: \
+             load { data_type: Int, index: 1 } [3]",
+            "integration_tests/Trace.add(Ljava/lang/String;I)I 5: add { data_type: Int } [3, 4]",
+            "integration_tests/Trace.add(Ljava/lang/String;I)I 6: return { data_type: Int } [7]",
+        ]
+    );
+
+    Ok(())
+}
+
+/// Traces the calls to methods matching a pattern, indented by how deeply they are nested.
+fn call_trace() -> eyre::Result<()> {
+    let source_file_path = Path::new(file!()).parent().unwrap().join("Jit.java");
+    compile(&source_file_path)?;
+
+    let tracer = Arc::new(CallTracer::new(
+        Vec::new(),
+        ["integration_tests.Jit.fib", "*.Jit.ad?"],
+    ));
+    let hook = tracer.clone();
+    let vm = OwnedVm::with_config(Vec::new(), |vm| vm.with_hook(hook));
+    vm.with(|vm| -> eyre::Result<()> {
+        assert_eq!(
+            vm.invoke_static::<_, i32>("integration_tests/Jit", "fib", (3,))?,
+            2
+        );
+        assert_eq!(
+            vm.invoke_static::<_, i32>("integration_tests/Jit", "sumTo", (2,))?,
+            3
+        );
+        Ok(())
+    })?;
+    drop(vm);
+
+    let Ok(tracer) = Arc::try_unwrap(tracer) else {
+        bail!("the tracer is still in use");
+    };
+    let trace = String::from_utf8(tracer.into_inner())?;
+
+    assert_eq!(
+        trace.lines().collect::<Vec<_>>(),
+        [
+            "-> integration_tests.Jit.fib(3)",
+            "  -> integration_tests.Jit.fib(2)",
+            "    -> integration_tests.Jit.fib(1)",
+            "    <- integration_tests.Jit.fib = 1",
+            "    -> integration_tests.Jit.fib(0)",
+            "    <- integration_tests.Jit.fib = 0",
+            "  <- integration_tests.Jit.fib = 1",
+            "  -> integration_tests.Jit.fib(1)",
+            "  <- integration_tests.Jit.fib = 1",
+            "<- integration_tests.Jit.fib = 2",
+            "-> integration_tests.Jit.add(0, 1)",
+            "<- integration_tests.Jit.add = 1",
+            "-> integration_tests.Jit.add(1, 2)",
+            "<- integration_tests.Jit.add = 3",
+        ]
+    );
+
+    Ok(())
+}
+
+/// Stops at breakpoints in the built-in debugger, and steps out of a method after inspecting it.
+/// Positions are bytecode offsets, which differ from instruction indices after the loop condition.
+fn debugger_session() -> eyre::Result<()> {
+    let source_file_path = Path::new(file!()).parent().unwrap().join("Jit.java");
+    compile(&source_file_path)?;
+
+    let input = "\
+        break integration_tests/Jit.add(II)I@2
+        continue
+        where
+        locals
+        operands
+        constant 1
+        finish
+        delete 2
+        break integration_tests.Jit.sumTo@11
+        continue
+        delete 3
+        continue
+    ";
+
+    let debugger = Arc::new(Debugger::new(input.as_bytes(), Vec::new()));
+    debugger.add_breakpoint("integration_tests.Jit.sumTo")?;
+
+    let hook = debugger.clone();
+    let vm = OwnedVm::with_config(Vec::new(), |vm| vm.with_hook(hook));
+    vm.with(|vm| -> eyre::Result<()> {
+        assert_eq!(
+            vm.invoke_static::<_, i32>("integration_tests/Jit", "sumTo", (2,))?,
+            3
+        );
+        Ok(())
+    })?;
+    drop(vm);
+
+    let Ok(debugger) = Arc::try_unwrap(debugger) else {
+        bail!("the debugger is still in use");
+    };
+    let output = String::from_utf8(debugger.into_inner())?;
+
+    assert_eq!(
+        output.split("(debug) ").collect::<Vec<_>>(),
+        [
+            "Breakpoint 1, integration_tests/Jit.sumTo(I)I 0: const { data_type: Int, value: 0 }\n",
+            "Breakpoint 2 set\n",
+            "Breakpoint 2, integration_tests/Jit.add(II)I 2: add { data_type: Int }\n",
+            "#0 integration_tests/Jit.add(II)I 2\n#1 integration_tests/Jit.sumTo(I)I 11\n",
+            "0: 0\n1: 1\n",
+            "[0, 1]\n",
+            "#1 = Methodref #2.#3 // java/lang/Object.\"<init>\":()V\n",
+            "integration_tests/Jit.sumTo(I)I 14: store { data_type: Int, index: 1 }\n",
+            "Deleted breakpoint 2\n",
+            "Breakpoint 3 set\n",
+            "Breakpoint 3, integration_tests/Jit.sumTo(I)I 11: invoke { kind: Static, index: 13 }\n",
+            "Deleted breakpoint 3\n",
+            "",
+        ]
+    );
+
+    Ok(())
+}
+
+/// Counts the instructions, calls, allocations and class loads of a program.
+fn execution_stats() -> eyre::Result<()> {
+    let source_file_path = Path::new(file!()).parent().unwrap().join("Jit.java");
+    compile(&source_file_path)?;
+
+    let stats = Arc::new(ExecutionStats::new());
+    let hook = stats.clone();
+    let vm = OwnedVm::with_config(Vec::new(), |vm| vm.with_hook(hook));
+    vm.with(|vm| -> eyre::Result<()> {
+        assert_eq!(
+            vm.invoke_static::<_, i32>("integration_tests/Jit", "fib", (3,))?,
+            2
+        );
+        vm.new_array::<i32>(&[1, 2, 3])?;
+        Ok(())
+    })?;
+    drop(vm);
+
+    let mut report = Vec::new();
+    stats.report(&mut report)?;
+    let report = String::from_utf8(report)?;
+
+    assert!(report.starts_with(
+        "Instructions executed: 41
+  load                           12  29.27%
+  const                           9  21.95%
+  if_icmp                         5  12.20%
+  return                          5  12.20%
+  sub                             4   9.76%
+  invoke                          4   9.76%
+  add                             2   4.88%
+
+Method calls: 5 (1 methods)
+             5 integration_tests.Jit.fib(I)I
+"
+    ));
+
+    // Classes from the JDK are loaded and allocated too, so only the entries for the test are
+    // checked
+    let lines = report.lines().collect::<Vec<_>>();
+    assert!(lines.contains(&"             1           40 bytes  int[]"));
+    assert!(lines.contains(&"             1 integration_tests"));
+
+    Ok(())
+}
+
+/// Samples every instruction of a method which calls another, and finds the time spent in each.
+fn sampling_profiler() -> eyre::Result<()> {
+    let source_file_path = Path::new(file!()).parent().unwrap().join("Jit.java");
+    compile(&source_file_path)?;
+
+    let profiler = Arc::new(Profiler::new(1));
+    let hook = profiler.clone();
+    let vm = OwnedVm::with_config(Vec::new(), |vm| vm.with_hook(hook));
+    vm.with(|vm| -> eyre::Result<()> {
+        assert_eq!(
+            vm.invoke_static::<_, i32>("integration_tests/Jit", "sumTo", (3,))?,
+            6
+        );
+        Ok(())
+    })?;
+    drop(vm);
+
+    // `add` runs 4 instructions for each of the 3 calls
+    let total = profiler.sample_count();
+    assert_eq!(
+        profiler.hot_methods(),
+        [
+            MethodSamples {
+                name: "integration_tests.Jit.sumTo(I)I".to_owned(),
+                exclusive: total - 12,
+                inclusive: total,
+            },
+            MethodSamples {
+                name: "integration_tests.Jit.add(II)I".to_owned(),
+                exclusive: 12,
+                inclusive: 12,
+            },
+        ]
+    );
+
+    let mut report = Vec::new();
+    profiler.report(&mut report)?;
+    assert!(String::from_utf8(report)?.starts_with(&format!(
+        "Samples: {total} (one every 1 instructions)\n    Self    Total  Method\n"
+    )));
+
+    let mut folded = Vec::new();
+    profiler.write_folded(&mut folded)?;
+    assert_eq!(
+        String::from_utf8(folded)?,
+        format!(
+            "integration_tests.Jit.sumTo {}\n\
+             integration_tests.Jit.sumTo;integration_tests.Jit.add 12\n",
+            total - 12
+        )
+    );
+
+    Ok(())
+}
+
+/// Swaps the writers of a running vm, and captures the output of a call.
+/// Counts the instructions of a class which ran, and writes them as an lcov tracefile.
+fn coverage() -> eyre::Result<()> {
+    let source_file_path = Path::new(file!()).parent().unwrap().join("Jit.java");
+    compile(&source_file_path)?;
+
+    let coverage = Arc::new(Coverage::new(["integration_tests.Jit"]));
+    let hook = coverage.clone();
+    let vm = OwnedVm::with_config(Vec::new(), |vm| vm.with_hook(hook));
+    vm.with(|vm| -> eyre::Result<()> {
+        assert_eq!(
+            vm.invoke_static::<_, i32>("integration_tests/Jit", "sumTo", (2,))?,
+            3
+        );
+        Ok(())
+    })?;
+    drop(vm);
+
+    let methods = coverage.methods();
+    let summary = methods
+        .iter()
+        .map(|m| (m.name.as_str(), m.calls, m.covered, m.instructions))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        [
+            ("integration_tests.Jit.<init>()V", 0, 0, 3),
+            ("integration_tests.Jit.add(II)I", 2, 4, 4),
+            ("integration_tests.Jit.fib(I)I", 0, 0, 15),
+            ("integration_tests.Jit.lastDigit(I)I", 0, 0, 4),
+            ("integration_tests.Jit.main([Ljava/lang/String;)V", 0, 0, 51),
+            ("integration_tests.Jit.mask(II)I", 0, 0, 8),
+            ("integration_tests.Jit.mix(IJ)J", 0, 0, 78),
+            ("integration_tests.Jit.sumTo(I)I", 1, 15, 15),
+        ]
+    );
+    assert_eq!(methods[2].missed_lines, [5, 6, 8]);
+
+    let mut lcov = Vec::new();
+    coverage.write_lcov(&mut lcov)?;
+    let lcov = String::from_utf8(lcov)?;
+    let lines = lcov.lines().collect::<Vec<_>>();
+    assert_eq!(lines[..2], ["TN:", "SF:integration_tests/Jit.java"]);
+    assert!(lines.contains(&"FNDA:2,integration_tests.Jit.add(II)I"));
+    // The loop condition of sumTo is checked once more than the loop runs
+    assert!(lines.contains(&"DA:18,3"));
+    assert!(lines.contains(&"DA:19,2"));
+    assert!(lines.contains(&"DA:5,0"));
+    assert_eq!(lines.last(), Some(&"end_of_record"));
+
+    Ok(())
+}
+
+fn output_redirection() -> eyre::Result<()> {
+    let source_file_path = Path::new(file!()).parent().unwrap().join("SystemOut.java");
+    compile(&source_file_path)?;
+
+    let main = |vm: &Vm| {
+        let args = vm.new_array::<&str>(&[])?;
+        vm.invoke_static::<_, ()>("integration_tests/SystemOut", "main", (args,))
+    };
+
+    let vm = OwnedVm::with_stdout(Vec::new());
+    let captured = vm.with(|vm| -> eyre::Result<Output> {
+        vm.set_stderr(io::sink())?;
+
+        let (result, output) = vm.capture_output(main)?;
+        result?;
+        assert!(output
+            .stdout
+            .starts_with(b"Hello, world!\nno newline, 42\n"));
+        assert_eq!(output.stderr, b"to stderr\n");
+
+        let previous = vm.set_stdout(io::sink())?;
+        main(vm)?;
+        vm.set_stdout(previous)?;
+
+        main(vm)?;
+        Ok(output)
+    })?;
+
+    // Only the output of the last call was written to the vm's original stdout
+    assert_eq!(vm.into_stdout(), captured.stdout);
+
+    // Output written before capturing is flushed first, rather than being held back by a buffered
+    // writer, and the writers are restored even if the capturing function panics
+    let stdout = SharedWriter::default();
+    let arena = Bump::new();
+    let vm = Vm::new(&arena, io::BufWriter::new(stdout.clone())).with_stderr(io::sink());
+    main(&vm)?;
+
+    let mut flushed = vec![];
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        vm.capture_output(|_| {
+            flushed = stdout.0.lock().unwrap().clone();
+            panic!("capturing output panicked");
+        })
+    }));
+    assert!(result.is_err());
+    assert_eq!(flushed, captured.stdout);
+
+    main(&vm)?;
+    drop(vm);
+    assert_eq!(*stdout.0.lock().unwrap(), captured.stdout.repeat(2));
+
+    Ok(())
+}
+
+/// A writer whose output can be read while the vm still has it.
+#[derive(Clone, Default)]
+struct SharedWriter(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Gives the vm ownership of its streams, rather than borrowing them.
+fn owned_writers() -> eyre::Result<()> {
+    let source_file_path = Path::new(file!()).parent().unwrap().join("SystemOut.java");
+    compile(&source_file_path)?;
+
+    let output_path = env::temp_dir().join(format!("rusty-java-{}.out", process::id()));
+
+    let arena = Bump::new();
+    let vm = Vm::new(&arena, File::create(&output_path)?)
+        .with_stdin(io::empty())
+        .with_stderr(io::sink());
+    let args = vm.new_array::<&str>(&[])?;
+    vm.invoke_static::<_, ()>("integration_tests/SystemOut", "main", (args,))?;
+
+    // Dropping the vm closes the file
+    drop(vm);
+
+    let output = fs::read_to_string(&output_path)?;
+    fs::remove_file(&output_path)?;
+    assert!(output.starts_with("Hello, world!\n"));
+
+    Ok(())
+}
+
+/// Restores a snapshot of a vm into a new vm, which continues from the state of the first one
+/// without running static initializers again.
+fn snapshots() -> eyre::Result<()> {
+    let source_file_path = Path::new(file!()).parent().unwrap().join("Snapshots.java");
+    compile(&source_file_path)?;
+
+    let snapshot_path = env::temp_dir().join(format!("rusty-java-{}.snapshot", process::id()));
+
+    let vm = OwnedVm::with_stdout(Vec::new());
+    let expected = vm.with(|vm| -> eyre::Result<Output> {
+        vm.invoke_static::<_, ()>("integration_tests/Snapshots", "call", ())?;
+        vm.snapshot()?.write(&snapshot_path)?;
+
+        let (result, output) = vm.capture_output(|vm| {
+            vm.invoke_static::<_, ()>("integration_tests/Snapshots", "print", ())
+        })?;
+        result?;
+        Ok(output)
+    })?;
+    assert!(String::from_utf8(vm.into_stdout())?.starts_with("initializing\n"));
+
+    let snapshot = Snapshot::read(&snapshot_path)?;
+    fs::remove_file(&snapshot_path)?;
+
+    let vm = OwnedVm::with_stdout(Vec::new());
+    vm.with(|vm| -> eyre::Result<()> {
+        vm.restore_snapshot(&snapshot)?;
+
+        let (result, output) = vm.capture_output(|vm| {
+            vm.invoke_static::<_, ()>("integration_tests/Snapshots", "print", ())
+        })?;
+        result?;
+        assert_eq!(
+            String::from_utf8(output.stdout)?,
+            String::from_utf8(expected.stdout.clone())?
+        );
+
+        // Snapshots can only be restored into a vm that hasn't loaded any classes
+        assert!(vm.restore_snapshot(&snapshot).is_err());
+
+        Ok(())
+    })?;
+    assert!(vm.into_stdout().is_empty());
+
+    Ok(())
+}
+
+/// Writes an archive of the JDK classes used by a program, and runs it again with its classes
+/// loaded from the archive.
+fn class_archive_round_trip() -> eyre::Result<()> {
+    let source_file_path = Path::new(file!()).parent().unwrap().join("Snapshots.java");
+    compile(&source_file_path)?;
+
+    let archive_path = env::temp_dir().join(format!("rusty-java-{}.archive", process::id()));
+
+    let arena = Bump::new();
+    let vm = Vm::new(&arena, io::sink());
+    let (result, expected) = vm.capture_output(|vm| {
+        vm.invoke_static::<_, ()>("integration_tests/Snapshots", "print", ())
+    })?;
+    result?;
+    vm.write_class_archive(&archive_path)?;
+
+    let archive = ClassArchive::read(&archive_path);
+    fs::remove_file(&archive_path)?;
+
+    let archived_arena = Bump::new();
+    let archived_vm = Vm::new(&archived_arena, io::sink())
+        .with_class_archive(archive?)
+        .with_verbose_class(true);
+    let (result, output) = archived_vm.capture_output(|vm| {
+        vm.invoke_static::<_, ()>("integration_tests/Snapshots", "print", ())
+    })?;
+    result?;
+
+    let output = String::from_utf8(output.stdout)?;
+    let (log, output): (Vec<_>, Vec<_>) = output
+        .lines()
+        .partition(|line| line.starts_with("[info][class,load] "));
+    assert_eq!(
+        output.join("\n") + "\n",
+        String::from_utf8(expected.stdout)?
+    );
+
+    // Only the classes of the program itself aren't in the archive
+    let mut archived_classes = vec![];
+    for line in log {
+        let (name, source) = line["[info][class,load] ".len()..]
+            .split_once(" source: ")
+            .wrap_err("invalid class load log")?;
+        if source == "class archive" {
+            archived_classes.push(name.replace('.', "/"));
+        } else {
+            assert!(name.starts_with("integration_tests."), "{line}");
+        }
+    }
+    assert!(archived_classes
+        .iter()
+        .any(|name| name == "java/lang/Object"));
+
+    // The archived classes are the same as when they're read from the jimage
+    for name in &archived_classes {
+        let class = vm.find_class(name).wrap_err("class wasn't archived")?;
+        let archived_class = archived_vm
+            .find_class(name)
+            .wrap_err("class wasn't loaded")?;
+        assert_eq!(
+            format!("{:?}", class.class_file()),
+            format!("{:?}", archived_class.class_file())
+        );
+
+        for ((name, descriptor, method), (_, _, archived_method)) in class
+            .declared_methods()
+            .zip(archived_class.declared_methods())
+        {
+            let code = method.body.as_ref().map(|body| (&body.code, &body.offsets));
+            let archived_code = archived_method
+                .body
+                .as_ref()
+                .map(|body| (&body.code, &body.offsets));
+            assert_eq!(
+                format!("{code:?}"),
+                format!("{archived_code:?}"),
+                "{name}{descriptor}"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes a heap dump after running a static initializer, and reads its objects back.
+fn heap_dump() -> eyre::Result<()> {
+    let source_file_path = Path::new(file!()).parent().unwrap().join("Snapshots.java");
+    compile(&source_file_path)?;
+
+    let vm = OwnedVm::with_stdout(Vec::new());
+    let mut bytes = Vec::new();
+    vm.with(|vm| -> eyre::Result<()> {
+        vm.invoke_static::<_, ()>("integration_tests/Snapshots", "call", ())?;
+        vm.write_heap_dump(&mut bytes)?;
+        Ok(())
+    })?;
+
+    let dump = HeapDump::parse(&bytes)?;
+    let class = dump.class("integration_tests/Snapshots")?;
+    let node_class = dump.class("integration_tests/Snapshots$Node")?;
+
+    let calls = dump.static_field(class, "calls")?;
+    assert_eq!(i32::from_be_bytes(calls.try_into()?), 1);
+
+    let triangles = dump.reference(dump.static_field(class, "TRIANGLES")?)?;
+    let (element_type, elements) = &dump.primitive_arrays[&triangles];
+    assert_eq!(*element_type, 10);
+    let triangles = elements
+        .chunks(4)
+        .map(|element| i32::from_be_bytes(element.try_into().unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(triangles, [0, 1, 3, 6, 10, 15, 21, 28]);
+
+    // The list is built by prepending, so the last node has the first value
+    let mut values = Vec::new();
+    let mut node = dump.reference(dump.static_field(class, "list")?)?;
+    while node != 0 {
+        let (class, fields) = &dump.instances[&node];
+        assert_eq!(*class, node_class);
+        // Fields are in the order they're declared, which is `value` and then `next`
+        values.push(i32::from_be_bytes(fields[..4].try_into()?));
+        node = dump.reference(&fields[4..])?;
+    }
+    assert_eq!(values, [2, 1, 0]);
+
+    // Strings are made up from their contents, in a backing array. `NAME` is a constant, so it's
+    // inlined rather than read from the field.
+    let greeting = dump.reference(dump.static_field(class, "greeting")?)?;
+    let (string_class, fields) = &dump.instances[&greeting];
+    assert_eq!(*string_class, dump.class("java/lang/String")?);
+    let value = dump.primitive_arrays.iter().find(|(id, _)| {
+        fields
+            .windows(8)
+            .any(|field| u64::from_be_bytes(field.try_into().unwrap()) == **id)
+    });
+    let value = match value.wrap_err("missing string value")? {
+        // char[], like in the vm's shims
+        (_, (5, bytes)) => bytes
+            .chunks(2)
+            .map(|c| char::from_u32(u16::from_be_bytes([c[0], c[1]]).into()).unwrap())
+            .collect::<String>(),
+        // Latin-1 byte[], like in the JDK
+        (_, (_, bytes)) => bytes.iter().map(|b| char::from(*b)).collect(),
+    };
+    assert_eq!(value, "hello");
+
+    Ok(())
+}
+
+/// The parts of an HPROF heap dump which are checked by tests. Ids must be 8 bytes.
+#[derive(Default)]
+struct HeapDump {
+    names: HashMap<u64, String>,
+    /// Class ids by name.
+    classes: HashMap<String, u64>,
+    /// The name id, basic type and value of each static field of each class.
+    static_fields: HashMap<u64, Vec<(u64, u8, Vec<u8>)>>,
+    /// The class and field values of each object.
+    instances: HashMap<u64, (u64, Vec<u8>)>,
+    primitive_arrays: HashMap<u64, (u8, Vec<u8>)>,
+}
+
+impl HeapDump {
+    fn parse(bytes: &[u8]) -> eyre::Result<HeapDump> {
+        let mut dump = HeapDump::default();
+        let mut class_names = HashMap::new();
+
+        let mut reader = Cursor::new(bytes);
+        let mut header = [0; 19];
+        reader.read_exact(&mut header)?;
+        assert_eq!(&header, b"JAVA PROFILE 1.0.2\0");
+        assert_eq!(reader.read_u32::<BigEndian>()?, 8);
+        reader.read_u64::<BigEndian>()?;
+
+        while (reader.position() as usize) < bytes.len() {
+            let tag = reader.read_u8()?;
+            reader.read_u32::<BigEndian>()?;
+            let length = reader.read_u32::<BigEndian>()? as usize;
+            let start = reader.position() as usize;
+            let body = bytes
+                .get(start..start + length)
+                .wrap_err("truncated record")?;
+            reader.set_position((start + length) as u64);
+
+            let mut body = Cursor::new(body);
+            match tag {
+                // UTF8
+                0x01 => {
+                    let id = body.read_u64::<BigEndian>()?;
+                    let mut name = String::new();
+                    body.read_to_string(&mut name)?;
+                    dump.names.insert(id, name);
+                }
+                // LOAD CLASS
+                0x02 => {
+                    body.read_u32::<BigEndian>()?;
+                    let id = body.read_u64::<BigEndian>()?;
+                    body.read_u32::<BigEndian>()?;
+                    class_names.insert(id, body.read_u64::<BigEndian>()?);
+                }
+                // HEAP DUMP SEGMENT
+                0x1c => dump.parse_heap(&mut body)?,
+                _ => {}
+            }
+        }
+
+        for (id, name) in class_names {
+            let name = dump.names.get(&name).wrap_err("missing class name")?;
+            dump.classes.insert(name.clone(), id);
+        }
+
+        Ok(dump)
+    }
+
+    fn parse_heap(&mut self, body: &mut Cursor<&[u8]>) -> eyre::Result<()> {
+        let value_size = |basic_type| match basic_type {
+            2 => 8,
+            4 | 8 => 1,
+            5 | 9 => 2,
+            6 | 10 => 4,
+            _ => 8,
+        };
+        let read_bytes = |body: &mut Cursor<&[u8]>, length| -> io::Result<Vec<u8>> {
+            let mut bytes = vec![0; length];
+            body.read_exact(&mut bytes)?;
+            Ok(bytes)
+        };
+
+        while (body.position() as usize) < body.get_ref().len() {
+            match body.read_u8()? {
+                // ROOT UNKNOWN and ROOT STICKY CLASS
+                0xff | 0x05 => {
+                    body.read_u64::<BigEndian>()?;
+                }
+                // CLASS DUMP
+                0x20 => {
+                    let id = body.read_u64::<BigEndian>()?;
+                    read_bytes(body, 4 + 6 * 8 + 4)?;
+                    assert_eq!(body.read_u16::<BigEndian>()?, 0);
+
+                    let mut static_fields = Vec::new();
+                    for _ in 0..body.read_u16::<BigEndian>()? {
+                        let name = body.read_u64::<BigEndian>()?;
+                        let basic_type = body.read_u8()?;
+                        let value = read_bytes(body, value_size(basic_type))?;
+                        static_fields.push((name, basic_type, value));
+                    }
+                    self.static_fields.insert(id, static_fields);
+
+                    for _ in 0..body.read_u16::<BigEndian>()? {
+                        read_bytes(body, 9)?;
+                    }
+                }
+                // INSTANCE DUMP
+                0x21 => {
+                    let id = body.read_u64::<BigEndian>()?;
+                    body.read_u32::<BigEndian>()?;
+                    let class = body.read_u64::<BigEndian>()?;
+                    let length = body.read_u32::<BigEndian>()? as usize;
+                    self.instances
+                        .insert(id, (class, read_bytes(body, length)?));
+                }
+                // OBJECT ARRAY DUMP
+                0x22 => {
+                    body.read_u64::<BigEndian>()?;
+                    body.read_u32::<BigEndian>()?;
+                    let length = body.read_u32::<BigEndian>()? as usize;
+                    read_bytes(body, 8 + length * 8)?;
+                }
+                // PRIMITIVE ARRAY DUMP
+                0x23 => {
+                    let id = body.read_u64::<BigEndian>()?;
+                    body.read_u32::<BigEndian>()?;
+                    let length = body.read_u32::<BigEndian>()? as usize;
+                    let element_type = body.read_u8()?;
+                    let elements = read_bytes(body, length * value_size(element_type))?;
+                    self.primitive_arrays.insert(id, (element_type, elements));
+                }
+                tag => bail!("unexpected heap dump tag {tag:#x}"),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn class(&self, name: &str) -> eyre::Result<u64> {
+        self.classes.get(name).copied().wrap_err("missing class")
+    }
+
+    fn static_field(&self, class: u64, name: &str) -> eyre::Result<&[u8]> {
+        let fields = self
+            .static_fields
+            .get(&class)
+            .wrap_err("missing class dump")?;
+        let (_, _, value) = fields
+            .iter()
+            .find(|(field, _, _)| self.names[field] == name)
+            .wrap_err("missing static field")?;
+        Ok(value)
+    }
+
+    fn reference(&self, value: &[u8]) -> eyre::Result<u64> {
+        Ok(u64::from_be_bytes(value[..8].try_into()?))
+    }
+}
+
+/// Attaches a debugger over JDWP, which stops at a breakpoint once the class is loaded and reads
+/// the stack and locals of the suspended thread.
+fn jdwp_breakpoint() -> eyre::Result<()> {
+    let source_file_path = Path::new(file!()).parent().unwrap().join("Jit.java");
+    compile(&source_file_path)?;
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let address = listener.local_addr()?;
+    let debugger = thread::spawn(move || debug_sum_to(address));
+
+    let agent = Arc::new(JdwpAgent::accept(&listener, true)?);
+    let hook = agent.clone();
+    let vm = OwnedVm::with_config(Vec::new(), |vm| vm.with_hook(hook));
+    vm.with(|vm| -> eyre::Result<()> {
+        assert_eq!(
+            vm.invoke_static::<_, i32>("integration_tests/Jit", "sumTo", (3,))?,
+            6
+        );
+        Ok(())
+    })?;
+    agent.vm_death();
+    drop(vm);
+
+    debugger.join().unwrap()
+}
+
+/// Debugs a call to `Jit.sumTo(3)`, stopping at the first call to `add`.
+fn debug_sum_to(address: SocketAddr) -> eyre::Result<()> {
+    let mut client = JdwpClient::connect(address)?;
+
+    // The vm starts with every thread suspended
+    let mut event = client.event()?;
+    assert_eq!(event.read_u8()?, 2);
+    assert_eq!(event.read_i32::<BigEndian>()?, 1);
+    assert_eq!(event.read_u8()?, 90);
+
+    // Suspends the thread which loads the class, so that a breakpoint can be set in it
+    let mut request = vec![8, 1];
+    request.extend(1i32.to_be_bytes());
+    request.push(5);
+    request.extend(jdwp_string("integration_tests.Jit"));
+    client.command(15, 1, &request)?;
+    client.command(1, 9, &[])?;
+
+    let mut event = client.event()?;
+    assert_eq!(event.read_u8()?, 1);
+    assert_eq!(event.read_i32::<BigEndian>()?, 1);
+    assert_eq!(event.read_u8()?, 8);
+    event.read_i32::<BigEndian>()?;
+    let thread = event.read_u64::<BigEndian>()?;
+    assert_eq!(event.read_u8()?, 1);
+    let class = event.read_u64::<BigEndian>()?;
+    assert_eq!(read_jdwp_string(&mut event)?, "Lintegration_tests/Jit;");
+
+    let mut reply = client.command(11, 1, &thread.to_be_bytes())?;
+    assert_eq!(read_jdwp_string(&mut reply)?, "main");
+
+    let mut methods = HashMap::new();
+    let mut reply = client.command(2, 5, &class.to_be_bytes())?;
+    for _ in 0..reply.read_i32::<BigEndian>()? {
+        let id = reply.read_u64::<BigEndian>()?;
+        let name = read_jdwp_string(&mut reply)?;
+        read_jdwp_string(&mut reply)?;
+        reply.read_i32::<BigEndian>()?;
+        methods.insert(name, id);
+    }
+
+    // A breakpoint at the first instruction of `add`
+    let mut request = vec![2, 1];
+    request.extend(1i32.to_be_bytes());
+    request.push(7);
+    request.push(1);
+    request.extend(class.to_be_bytes());
+    request.extend(methods["add"].to_be_bytes());
+    request.extend(0u64.to_be_bytes());
+    let breakpoint = client.command(15, 1, &request)?.read_i32::<BigEndian>()?;
+    client.command(11, 3, &thread.to_be_bytes())?;
+
+    let mut event = client.event()?;
+    assert_eq!(event.read_u8()?, 1);
+    assert_eq!(event.read_i32::<BigEndian>()?, 1);
+    assert_eq!(event.read_u8()?, 2);
+    assert_eq!(event.read_i32::<BigEndian>()?, breakpoint);
+    assert_eq!(event.read_u64::<BigEndian>()?, thread);
+
+    // The stack has `add` on top of `sumTo`, since `sumTo` was called from Rust
+    let mut frames_request = thread.to_be_bytes().to_vec();
+    frames_request.extend(0i32.to_be_bytes());
+    frames_request.extend((-1i32).to_be_bytes());
+    let mut reply = client.command(11, 6, &frames_request)?;
+    assert_eq!(reply.read_i32::<BigEndian>()?, 2);
+    let mut frames = Vec::new();
+    for _ in 0..2 {
+        let frame = reply.read_u64::<BigEndian>()?;
+        assert_eq!(reply.read_u8()?, 1);
+        assert_eq!(reply.read_u64::<BigEndian>()?, class);
+        let method = reply.read_u64::<BigEndian>()?;
+        let index = reply.read_u64::<BigEndian>()?;
+        frames.push((frame, method, index));
+    }
+    assert_eq!(frames[0].1, methods["add"]);
+    assert_eq!(frames[0].2, 0);
+    assert_eq!(frames[1].1, methods["sumTo"]);
+
+    // The first call is `add(0, 1)`
+    let mut values_request = thread.to_be_bytes().to_vec();
+    values_request.extend(frames[0].0.to_be_bytes());
+    values_request.extend(2i32.to_be_bytes());
+    for slot in 0..2i32 {
+        values_request.extend(slot.to_be_bytes());
+        values_request.push(b'I');
+    }
+    let mut reply = client.command(16, 1, &values_request)?;
+    assert_eq!(reply.read_i32::<BigEndian>()?, 2);
+    for expected in [0, 1] {
+        assert_eq!(reply.read_u8()?, b'I');
+        assert_eq!(reply.read_i32::<BigEndian>()?, expected);
+    }
+
+    let mut clear = vec![2];
+    clear.extend(breakpoint.to_be_bytes());
+    client.command(15, 2, &clear)?;
+    client.command(1, 9, &[])?;
+
+    let mut event = client.event()?;
+    assert_eq!(event.read_u8()?, 0);
+    assert_eq!(event.read_i32::<BigEndian>()?, 1);
+    assert_eq!(event.read_u8()?, 99);
+
+    Ok(())
+}
+
+/// A minimal JDWP client, which sends commands and keeps the events which arrive while it waits
+/// for their replies.
+struct JdwpClient {
+    stream: TcpStream,
+    next_id: u32,
+    events: VecDeque<Vec<u8>>,
+}
+
+impl JdwpClient {
+    fn connect(address: SocketAddr) -> eyre::Result<JdwpClient> {
+        let mut stream = TcpStream::connect(address)?;
+        // A test which fails shouldn't leave the vm suspended forever
+        stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+
+        stream.write_all(b"JDWP-Handshake")?;
+        let mut handshake = [0; 14];
+        stream.read_exact(&mut handshake)?;
+        assert_eq!(&handshake, b"JDWP-Handshake");
+
+        Ok(JdwpClient {
+            stream,
+            next_id: 1,
+            events: VecDeque::new(),
+        })
+    }
+
+    /// Sends a command and returns the data of its reply, failing if the reply is an error.
+    fn command(
+        &mut self,
+        command_set: u8,
+        command: u8,
+        data: &[u8],
+    ) -> eyre::Result<Cursor<Vec<u8>>> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let mut packet = (11 + data.len() as u32).to_be_bytes().to_vec();
+        packet.extend(id.to_be_bytes());
+        packet.extend([0, command_set, command]);
+        packet.extend(data);
+        self.stream.write_all(&packet)?;
+
+        loop {
+            if let Some(reply) = self.read_packet()?.filter(|reply| reply.id == id) {
+                if reply.error != 0 {
+                    bail!(
+                        "command {command_set}/{command} failed with error {}",
+                        reply.error
+                    );
+                }
+                return Ok(Cursor::new(reply.data));
+            }
+        }
+    }
+
+    /// Returns the data of the next composite event.
+    fn event(&mut self) -> eyre::Result<Cursor<Vec<u8>>> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(Cursor::new(event));
+            }
+            self.read_packet()?;
+        }
+    }
+
+    /// Reads a packet, returning it if it's a reply. Events are queued instead.
+    fn read_packet(&mut self) -> eyre::Result<Option<JdwpReply>> {
+        let length = self.stream.read_u32::<BigEndian>()?;
+        let id = self.stream.read_u32::<BigEndian>()?;
+        let flags = self.stream.read_u8()?;
+        let header = self.stream.read_u16::<BigEndian>()?;
+
+        let mut data = vec![0; length as usize - 11];
+        self.stream.read_exact(&mut data)?;
+
+        if flags & 0x80 != 0 {
+            return Ok(Some(JdwpReply {
+                id,
+                error: header,
+                data,
+            }));
+        }
+
+        // The command set and command of the composite event
+        assert_eq!(header, 64 << 8 | 100);
+        self.events.push_back(data);
+        Ok(None)
+    }
+}
+
+struct JdwpReply {
+    id: u32,
+    error: u16,
+    data: Vec<u8>,
+}
+
+fn jdwp_string(s: &str) -> Vec<u8> {
+    let mut bytes = (s.len() as i32).to_be_bytes().to_vec();
+    bytes.extend(s.as_bytes());
+    bytes
+}
+
+fn read_jdwp_string(reader: &mut impl Read) -> eyre::Result<String> {
+    let mut bytes = vec![0; reader.read_i32::<BigEndian>()? as usize];
+    reader.read_exact(&mut bytes)?;
+    Ok(String::from_utf8(bytes)?)
+}
+
+/// Checks that code which loops forever, or allocates forever, is aborted once it exceeds its
+/// budget.
+fn budgets() -> eyre::Result<()> {
+    let arena = Bump::new();
+    let mut builder = ClassBuilder::new(&arena, "integration_tests/Spin");
+
+    // while (true) {}
+    builder.method(
+        MethodAccessFlags::PUBLIC | MethodAccessFlags::STATIC,
+        "spin",
+        "()V",
+        0,
+        0,
+        &[Instruction::goto { branch: 0 }],
+    )?;
+
+    // while (true) { int[] array = new int[100]; }
+    builder.method(
+        MethodAccessFlags::PUBLIC | MethodAccessFlags::STATIC,
+        "allocate",
+        "()V",
+        1,
+        0,
+        &[
+            Instruction::bipush(100),
+            Instruction::newarray {
+                atype: ArrayType::Int,
+            },
+            Instruction::pop,
+            Instruction::goto { branch: -3 },
+        ],
+    )?;
+
+    let mut bytes = vec![];
+    ClassWriter::new(&mut bytes).write_class_file(&builder.build())?;
+
+    let limits = [
+        Budget {
+            max_instructions: Some(10_000),
+            ..Budget::default()
+        },
+        Budget {
+            max_time: Some(Duration::from_millis(10)),
+            ..Budget::default()
+        },
+        Budget {
+            max_allocated_bytes: Some(1 << 20),
+            ..Budget::default()
+        },
+    ];
+
+    let expected = [
+        ("spin", Limit::Instructions(10_000)),
+        ("allocate", Limit::Instructions(10_000)),
+        ("spin", Limit::Time(Duration::from_millis(10))),
+        ("allocate", Limit::Time(Duration::from_millis(10))),
+        ("allocate", Limit::AllocatedBytes(1 << 20)),
+    ];
+
+    for (method, limit) in expected {
+        let budget = limits
+            .iter()
+            .find(|budget| match limit {
+                Limit::Instructions(_) => budget.max_instructions.is_some(),
+                Limit::Time(_) => budget.max_time.is_some(),
+                Limit::AllocatedBytes(_) => budget.max_allocated_bytes.is_some(),
+            })
+            .unwrap();
+
+        let vm = OwnedVm::with_config(Vec::new(), |vm| vm.with_budget(budget.clone()));
+        vm.with(|vm| -> eyre::Result<()> {
+            vm.define_class(&bytes)?;
+
+            // The budget can be reset and used again
+            for _ in 0..2 {
+                vm.reset_budget();
+                let e = vm
+                    .invoke_static::<_, ()>("integration_tests/Spin", method, ())
+                    .unwrap_err();
+                let Error::BudgetExceeded(e) = e else {
+                    bail!("expected the budget to be exceeded, got {e}");
+                };
+                assert_eq!(e.limit, limit);
+            }
+
+            Ok(())
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Interrupts a sleeping vm from another host thread, which wakes it, and then stops it.
+fn interrupt_sleep() -> eyre::Result<()> {
+    let vm = OwnedVm::with_stdout(Vec::new());
+    vm.with(|vm| -> eyre::Result<()> {
+        let handle = vm.handle();
+        let start = Instant::now();
+        let interrupter = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            handle.interrupt();
+        });
+
+        let e = vm
+            .invoke_static::<_, ()>("java/lang/Thread", "sleep", (60_000i64,))
+            .unwrap_err();
+        interrupter.join().unwrap();
+
+        assert_eq!(
+            e.exception().wrap_err("expected an exception")?.class_name,
+            "java/lang/InterruptedException"
+        );
+        assert!(start.elapsed() < Duration::from_secs(30));
+
+        // The interrupt status was cleared when the exception was thrown
+        vm.invoke_static::<_, ()>("java/lang/Thread", "sleep", (1i64,))?;
+
+        // Stopping the vm wakes it too, but it can't be caught as an exception
+        let handle = vm.handle();
+        let start = Instant::now();
+        let stopper = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            handle.stop();
+        });
+
+        let e = vm
+            .invoke_static::<_, ()>("java/lang/Thread", "sleep", (60_000i64,))
+            .unwrap_err();
+        stopper.join().unwrap();
+
+        assert!(matches!(e, Error::Stopped), "{e}");
+        assert!(start.elapsed() < Duration::from_secs(30));
+
+        Ok(())
+    })
+}
+
+/// A test program which doesn't finish in time fails, rather than hanging the test run, and its
+/// vm is stopped rather than left running.
+fn timeouts() -> eyre::Result<()> {
+    let e = with_timeout(Duration::from_millis(50), |_| loop {
+        thread::park();
+    })
+    .unwrap_err();
+    assert_eq!(e.to_string(), "timed out after 50ms");
+
+    assert_eq!(with_timeout(Duration::from_secs(10), |_| 42)?, 42);
+
+    let arena = Bump::new();
+    let mut builder = ClassBuilder::new(&arena, "integration_tests/Spin");
+
+    // while (true) {}
+    builder.method(
+        MethodAccessFlags::PUBLIC | MethodAccessFlags::STATIC,
+        "spin",
+        "()V",
+        0,
+        0,
+        &[Instruction::goto { branch: 0 }],
+    )?;
+
+    let mut bytes = vec![];
+    ClassWriter::new(&mut bytes).write_class_file(&builder.build())?;
+
+    let stopped = Arc::new(AtomicBool::new(false));
+    let e = with_timeout(Duration::from_millis(50), {
+        let stopped = stopped.clone();
+        move |stopper| {
+            let vm = OwnedVm::with_stdout(Vec::new());
+            vm.with(|vm| {
+                stopper.watch(vm);
+                vm.define_class(&bytes).unwrap();
+
+                let result = vm.invoke_static::<_, ()>("integration_tests/Spin", "spin", ());
+                stopped.store(matches!(result, Err(Error::Stopped)), Ordering::Relaxed);
+            })
+        }
+    })
+    .unwrap_err();
+
+    assert_eq!(e.to_string(), "timed out after 50ms");
+    assert!(stopped.load(Ordering::Relaxed), "the vm wasn't stopped");
+
+    Ok(())
+}
+
+fn disassembled_class() -> eyre::Result<()> {
+    let arena = Bump::new();
+    let class_file = assemble_counting_class(&arena)?;
+
+    let mut output = vec![];
+    disassemble(&mut output, &class_file)?;
+
+    insta::assert_snapshot!("disassembled_class", String::from_utf8(output)?);
+
+    Ok(())
+}
+
+fn class_file_json() -> eyre::Result<()> {
+    let arena = Bump::new();
+    let class_file = assemble_counting_class(&arena)?;
+
+    let json = serde_json::to_value(&class_file)?;
+    let constant_pool = json["constant_pool"].as_array().unwrap();
+    let this_class = json["this_class"].as_u64().unwrap() as usize;
+    let name_index = constant_pool[this_class - 1]["Class"]["name_index"]
+        .as_u64()
+        .unwrap() as usize;
+    let method = &json["methods"][0];
+
+    assert_eq!(json["major_version"], 52);
+    assert_eq!(
+        constant_pool[name_index - 1]["Utf8"],
+        "integration_tests/Assembled"
+    );
+    assert_eq!(method["access_flags"], "PUBLIC | STATIC");
+    assert_eq!(method["attributes"][0]["Code"]["max_locals"], 2);
+
+    Ok(())
+}
+
+fn register_ir() -> eyre::Result<()> {
+    let arena = Bump::new();
+    let class_file = assemble_counting_class(&arena)?;
+    let code = class_file.methods[0].attributes[0]
+        .try_as_code_ref()
+        .unwrap();
+
+    let (instructions, offsets) = decode_instructions(&arena, code)?;
+    let function = Function::translate(&class_file.constant_pool, code, &instructions, &offsets)?;
+
+    insta::assert_snapshot!("register_ir", function.to_string());
+
+    Ok(())
+}
+
+/// Checks that a class which adds an int to a reference is rejected when it's loaded.
+fn verify_error() -> eyre::Result<()> {
+    let arena = Bump::new();
+    let mut builder = ClassBuilder::new(&arena, "integration_tests/Unverifiable");
+
+    builder.method(
+        MethodAccessFlags::PUBLIC | MethodAccessFlags::STATIC,
+        "main",
+        "([Ljava/lang/String;)V",
+        2,
+        1,
+        &[
+            Instruction::aload(0),
+            Instruction::iconst(1),
+            Instruction::add(NumberType::Int),
+            Instruction::r#return {
+                data_type: ReturnType::Void,
+            },
+        ],
+    )?;
+
+    let mut bytes = vec![];
+    ClassWriter::new(&mut bytes).write_class_file(&builder.build())?;
+
+    let arena = Bump::new();
+    let mut stdout = Vec::new();
+
+    // Without verification the class is loaded as it is
+    let vm = Vm::new(&arena, &mut stdout);
+    vm.define_class(&bytes)?;
+    drop(vm);
+
+    let vm = Vm::new(&arena, &mut stdout).with_verify(Verify::Remote);
+    let Err(e) = vm.define_class(&bytes) else {
+        eyre::bail!("expected a VerifyError");
+    };
+
+    let Error::Verification(message) = e else {
+        eyre::bail!("expected a VerifyError, got {e}");
+    };
+    assert_eq!(
+        message,
+        "integration_tests.Unverifiable.main([Ljava/lang/String;)V at pc 2: \
+         expected Int on the stack, found Reference"
+    );
+
+    Ok(())
+}
+
+/// Checks that code with out of bounds locals, or branches and exception handlers that don't
+/// land on an instruction, is rejected when it's loaded.
+fn invalid_code() -> eyre::Result<()> {
+    let arena = Bump::new();
+
+    let error = define_invalid_code(
+        &arena,
+        &[
+            Instruction::iload(1),
+            Instruction::pop,
+            Instruction::r#return {
+                data_type: ReturnType::Void,
+            },
+        ],
+        |_| {},
+    )?;
+    assert_eq!(
+        error,
+        "invalid code in main([Ljava/lang/String;)V: local 1 used at pc 0 is out of bounds \
+         (max_locals is 1)"
+    );
+
+    let instructions = [
+        Instruction::goto { branch: 1 },
+        Instruction::sipush(1),
+        Instruction::pop,
+        Instruction::r#return {
+            data_type: ReturnType::Void,
+        },
+    ];
+
+    // The goto_w at pc 0 targets the sipush at pc 5, so this targets its operand instead
+    let error = define_invalid_code(&arena, &instructions, |code| {
+        let mut bytes = code.code.to_vec();
+        bytes[4] = 6;
+        code.code = arena.alloc_slice_copy(&bytes);
+    })?;
+    assert_eq!(
+        error,
+        "invalid code in main([Ljava/lang/String;)V: branch at pc 0 targets pc 6, which is in \
+         the middle of an instruction"
+    );
+
+    let error = define_invalid_code(&arena, &instructions, |code| {
+        code.exception_table.push(ExceptionTableEntry {
+            start_pc: 0,
+            end_pc: 5,
+            handler_pc: 6,
+            catch_type: 0,
+        });
+    })?;
+    assert_eq!(
+        error,
+        "invalid code in main([Ljava/lang/String;)V: exception table entry 0 has invalid \
+         handler_pc 6"
+    );
+
+    Ok(())
+}
+
+/// Checks the blocks and edges of the graph for a loop with an exception handler.
+fn control_flow_graph() -> eyre::Result<()> {
+    let arena = Bump::new();
+    let mut class_file = assemble_counting_class(&arena)?;
+    let code = class_file.methods[0].attributes[0]
+        .try_as_code_mut()
+        .unwrap();
+
+    // Covers the body of the loop, up to the goto
+    code.exception_table.push(ExceptionTableEntry {
+        start_pc: 7,
+        end_pc: 17,
+        handler_pc: 22,
+        catch_type: 0,
+    });
+
+    let (instructions, offsets) = decode_instructions(&arena, code)?;
+    let cfg = ControlFlowGraph::new(code, &instructions, &offsets)?;
+
+    let blocks = cfg
+        .blocks
+        .iter()
+        .map(|block| {
+            (
+                block.start..block.end,
+                block.successors.clone(),
+                block.predecessors.clone(),
+                block.handlers.clone(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        blocks,
+        [
+            (0..2, vec![1], vec![], vec![]),
+            (2..5, vec![2, 4], vec![0, 3], vec![]),
+            (5..9, vec![3], vec![1], vec![4]),
+            (9..10, vec![1], vec![2], vec![]),
+            (10..11, vec![], vec![1], vec![]),
+        ]
+    );
+    assert_eq!(cfg.block_index(7), 2);
+    assert_eq!(cfg.reverse_postorder(), [0, 1, 2, 4, 3]);
+
+    Ok(())
+}
+
+/// Assembles a class with a main method, which is modified by `patch` before the class is
+/// defined. Returns the message of the `ClassFormatError` that defining it fails with.
+fn define_invalid_code<'a>(
+    arena: &'a Bump,
+    instructions: &[Instruction],
+    patch: impl FnOnce(&mut CodeAttribute<'a>),
+) -> eyre::Result<String> {
+    let mut builder = ClassBuilder::new(arena, "integration_tests/InvalidCode");
+    builder.method(
+        MethodAccessFlags::PUBLIC | MethodAccessFlags::STATIC,
+        "main",
+        "([Ljava/lang/String;)V",
+        1,
+        1,
+        instructions,
+    )?;
+
+    let mut class_file = builder.build();
+    let code = class_file.methods[0].attributes[0]
+        .try_as_code_mut()
+        .unwrap();
+    patch(code);
+
+    let mut bytes = vec![];
+    ClassWriter::new(&mut bytes).write_class_file(&class_file)?;
+
+    let vm_arena = Bump::new();
+    let mut stdout = Vec::new();
+    let vm = Vm::new(&vm_arena, &mut stdout);
+    let Err(e) = vm.define_class(&bytes) else {
+        eyre::bail!("expected a ClassFormatError");
+    };
+
+    let Error::ClassFormat(message) = e else {
+        eyre::bail!("expected a ClassFormatError, got {e}");
+    };
+    Ok(message)
+}
+
+/// Checks that errors raised by the interpreter list the Java frames they unwound, from the
+/// innermost.
+fn error_frames() -> eyre::Result<()> {
+    let arena = Bump::new();
+    let mut builder = ClassBuilder::new(&arena, "integration_tests/Frames");
+    let grid = builder
+        .constant_pool()
+        .method_ref("integration_tests/Frames", "grid", "(I)[[I");
+    let int_grid = builder.constant_pool().class("[[I");
+
+    // multianewarray isn't implemented by the interpreter
+    builder.method(
+        MethodAccessFlags::PUBLIC | MethodAccessFlags::STATIC,
+        "grid",
+        "(I)[[I",
+        2,
+        1,
+        &[
+            Instruction::load {
+                data_type: LoadStoreType::Int,
+                index: 0,
+            },
+            Instruction::dup,
+            Instruction::multianewarray {
+                index: int_grid,
+                dimensions: 2,
+            },
+            Instruction::r#return {
+                data_type: ReturnType::Reference,
+            },
+        ],
+    )?;
+
+    builder.method(
+        MethodAccessFlags::PUBLIC | MethodAccessFlags::STATIC,
+        "main",
+        "([Ljava/lang/String;)V",
+        1,
+        1,
+        &[
+            Instruction::bipush { value: 3 },
+            Instruction::invoke {
+                kind: InvokeKind::Static,
+                index: grid,
+            },
+            Instruction::pop,
+            Instruction::r#return {
+                data_type: ReturnType::Void,
+            },
+        ],
+    )?;
+
+    let mut bytes = vec![];
+    ClassWriter::new(&mut bytes).write_class_file(&builder.build())?;
+
+    let arena = Bump::new();
+    let mut stdout = Vec::new();
+    let vm = Vm::new(&arena, &mut stdout);
+    let class = vm.define_class(&bytes)?;
+
+    let Err(e) = vm.run_main(class, &[]) else {
+        eyre::bail!("expected multianewarray to fail");
+    };
+
+    let Error::InJava { frames, source } = &e else {
+        eyre::bail!("expected the error to have Java frames, got {e}");
+    };
+    assert_eq!(
+        frames,
+        &[
+            "at integration_tests.Frames.grid(I)[[I (pc 2)",
+            "at integration_tests.Frames.main([Ljava/lang/String;)V (pc 2)",
+        ]
+    );
+    assert_eq!(
+        source.to_string(),
+        format!("unimplemented instruction: multianewarray {{ index: {int_grid}, dimensions: 2 }}")
+    );
+
+    Ok(())
+}
+
+/// Checks that a field which doesn't exist throws `NoSuchFieldError`, even for a null reference,
+/// since the field is resolved first.
+fn missing_field() -> eyre::Result<()> {
+    let arena = Bump::new();
+    let mut builder = ClassBuilder::new(&arena, "integration_tests/MissingField");
+    let field = builder
+        .constant_pool()
+        .field_ref("integration_tests/MissingField", "missing", "I");
+
+    builder.method(
+        MethodAccessFlags::PUBLIC | MethodAccessFlags::STATIC,
+        "main",
+        "([Ljava/lang/String;)V",
+        1,
+        1,
+        &[
+            Instruction::aconst_null,
+            Instruction::getfield { index: field },
+            Instruction::pop,
+            Instruction::r#return {
+                data_type: ReturnType::Void,
+            },
+        ],
+    )?;
+
+    let mut bytes = vec![];
+    ClassWriter::new(&mut bytes).write_class_file(&builder.build())?;
+
+    let arena = Bump::new();
+    let mut stdout = Vec::new();
+    let vm = Vm::new(&arena, &mut stdout);
+    let class = vm.define_class(&bytes)?;
+
+    let e = vm.run_main(class, &[]).unwrap_err();
+    assert_eq!(
+        e.exception().map(ToString::to_string).as_deref(),
+        Some("java.lang.NoSuchFieldError: integration_tests.MissingField.missing")
+    );
+
+    Ok(())
+}
+
+/// Checks that unbounded recursion throws `StackOverflowError` once the frame stack is full, and
+/// that the frame stack can be used again afterwards.
+fn stack_overflow() -> eyre::Result<()> {
+    let arena = Bump::new();
+    let mut builder = ClassBuilder::new(&arena, "integration_tests/Recursive");
+    let recurse =
+        builder
+            .constant_pool()
+            .method_ref("integration_tests/Recursive", "recurse", "()V");
+
+    for name in ["main", "recurse"] {
+        let descriptor = if name == "main" {
+            "([Ljava/lang/String;)V"
+        } else {
+            "()V"
+        };
+
+        builder.method(
+            MethodAccessFlags::PUBLIC | MethodAccessFlags::STATIC,
+            name,
+            descriptor,
+            1,
+            1,
+            &[
+                Instruction::invoke {
+                    kind: InvokeKind::Static,
+                    index: recurse,
+                },
+                Instruction::r#return {
+                    data_type: ReturnType::Void,
+                },
+            ],
+        )?;
+    }
+
+    let mut bytes = vec![];
+    ClassWriter::new(&mut bytes).write_class_file(&builder.build())?;
+
+    let arena = Bump::new();
+    let mut stdout = Vec::new();
+    let vm = Vm::new(&arena, &mut stdout).with_stack_size(4096);
+    let class = vm.define_class(&bytes)?;
+
+    for _ in 0..2 {
+        let Err(e) = vm.run_main(class, &[]) else {
+            eyre::bail!("expected a StackOverflowError");
+        };
+
+        let exception = e.exception().wrap_err("expected an exception")?;
+        assert_eq!(exception.class_name, "java/lang/StackOverflowError");
+
+        // Only the innermost frames are listed
+        let Error::InJava { frames, .. } = &e else {
+            eyre::bail!("expected the error to have Java frames, got {e}");
+        };
+        assert_eq!(frames.len(), 65);
+        assert_eq!(frames.last().unwrap(), "...");
+    }
+
+    Ok(())
+}
+
+/// Checks that loading a class which is its own super class, through another class on the class
+/// path, throws `ClassCircularityError` naming the classes involved.
+fn class_circularity() -> eyre::Result<()> {
+    let dir = env::temp_dir().join(format!("rusty-java-{}-circular", process::id()));
+    fs::create_dir_all(dir.join("integration_tests"))?;
+
+    for (name, super_class) in [("CircularA", "CircularB"), ("CircularB", "CircularA")] {
+        let arena = Bump::new();
+        let mut builder = ClassBuilder::new(&arena, &format!("integration_tests/{name}"));
+        builder.super_class(&format!("integration_tests/{super_class}"));
+
+        let mut bytes = vec![];
+        ClassWriter::new(&mut bytes).write_class_file(&builder.build())?;
+        fs::write(dir.join(format!("integration_tests/{name}.class")), bytes)?;
+    }
+
+    let arena = Bump::new();
+    let mut stdout = Vec::new();
+    let vm = Vm::new(&arena, &mut stdout).with_class_path(ClassPath::new([&dir]));
+    let results = [
+        vm.load_class_file("integration_tests/CircularA"),
+        // The classes stay unloaded and aren't left marked as loading, so the error is thrown
+        // again
+        vm.load_class_file("integration_tests/CircularB"),
+    ];
+    fs::remove_dir_all(&dir)?;
+
+    let [Err(a), Err(b)] = results else {
+        eyre::bail!("expected a ClassCircularityError");
+    };
+
+    for (e, chain) in [
+        (a, ["CircularA", "CircularB", "CircularA"]),
+        (b, ["CircularB", "CircularA", "CircularB"]),
+    ] {
+        let exception = e.exception().wrap_err("expected an exception")?;
+        assert_eq!(exception.class_name, "java/lang/ClassCircularityError");
+        assert_eq!(
+            exception.message.as_deref(),
+            Some(
+                chain
+                    .map(|name| format!("integration_tests/{name}"))
+                    .join(" -> ")
+            )
+            .as_deref()
+        );
+    }
+
+    Ok(())
+}
+
+/// Checks that class files with versions the vm doesn't support are rejected with
+/// `UnsupportedClassVersionError`, rather than `ClassFormatError`.
+fn unsupported_class_version() -> eyre::Result<()> {
+    let arena = Bump::new();
+    let builder = ClassBuilder::new(&arena, "integration_tests/Versioned");
+    let mut bytes = vec![];
+    ClassWriter::new(&mut bytes).write_class_file(&builder.build())?;
+
+    let arena = Bump::new();
+    let mut stdout = Vec::new();
+    let vm = Vm::new(&arena, &mut stdout);
+
+    // The minor and major versions follow the magic number
+    for (major, minor, message) in [
+        (
+            62,
+            0,
+            "class file version 62.0, this VM supports up to 61.0",
+        ),
+        (
+            44,
+            0,
+            "class file version 44.0, this VM supports 45.0 and up",
+        ),
+        (
+            61,
+            0xffff,
+            "class file version 61.65535 uses preview features",
+        ),
+        (
+            61,
+            3,
+            "class file version 61.3 has an invalid minor version",
+        ),
+    ] {
+        let mut bytes = bytes.clone();
+        bytes[4..6].copy_from_slice(&u16::to_be_bytes(minor));
+        bytes[6..8].copy_from_slice(&u16::to_be_bytes(major));
+
+        let Err(e) = vm.define_class(&bytes) else {
+            eyre::bail!("expected version {major}.{minor} to be rejected");
+        };
+
+        let exception = e.exception().wrap_err("expected an exception")?;
+        assert_eq!(
+            exception.class_name,
+            "java/lang/UnsupportedClassVersionError"
+        );
+        assert_eq!(exception.message.as_deref(), Some(message));
+    }
+
+    // Minor versions are only checked since Java 12
+    bytes[4..6].copy_from_slice(&u16::to_be_bytes(3));
+    bytes[6..8].copy_from_slice(&u16::to_be_bytes(55));
+    vm.define_class(&bytes)?;
+
+    Ok(())
+}
+
+/// Checks that a method's invocations and loop iterations are counted, and that its instructions
+/// are only fused into superinstructions once it's hot enough.
+fn hotness() -> eyre::Result<()> {
+    let arena = Bump::new();
+    let class_file = assemble_counting_class(&arena)?;
+
+    let mut bytes = vec![];
+    ClassWriter::new(&mut bytes).write_class_file(&class_file)?;
+
+    let arena = Bump::new();
+    let mut stdout = Vec::new();
+    let vm = Vm::new(&arena, &mut stdout).with_superinstruction_threshold(5);
+    let class = vm.define_class(&bytes)?;
+    let method = class.method("main", "([Ljava/lang/String;)V").unwrap();
+    let body = method.body.as_ref().unwrap();
+
+    // One invocation and three iterations of the loop
+    vm.run_main(class, &[])?;
+    assert_eq!(method.hotness.invocations(), 1);
+    assert_eq!(method.hotness.backward_branches(), 3);
+    assert!(body.superinstructions.get().is_none());
+
+    // The next invocation reaches the threshold, and the rest of it runs the superinstructions
+    vm.run_main(class, &[])?;
+    assert_eq!(method.hotness.total(), 8);
+    assert!(body.superinstructions.get().is_some());
+
+    drop(vm);
+    assert_eq!(String::from_utf8(stdout)?, "0\n1\n2\n0\n1\n2\n");
+
+    Ok(())
+}
+
+fn symbols() -> eyre::Result<()> {
+    let arena = Bump::new();
+    let class_file = assemble_counting_class(&arena)?;
+
+    let mut bytes = vec![];
+    ClassWriter::new(&mut bytes).write_class_file(&class_file)?;
+
+    let arena = Bump::new();
+    let mut stdout = Vec::new();
+    let vm = Vm::new(&arena, &mut stdout);
+    let class = vm.define_class(&bytes)?;
+    let object = class.super_class().unwrap();
+    let string = vm.load_class_file("java/lang/String")?;
+
+    // Names from different classes are the same symbol
+    assert_eq!(object.name(), "java/lang/Object");
+    assert!(std::ptr::eq(
+        object.name(),
+        string.super_class().unwrap().name()
+    ));
+    assert!(std::ptr::eq(
+        object.name(),
+        vm.symbols().get("java/lang/Object").unwrap()
+    ));
+
+    Ok(())
+}
+
+/// Compiles a test program, unless it hasn't changed since it was last compiled.
+fn compile(source_file_path: &Path) -> eyre::Result<()> {
+    compile_sources(
+        &[source_file_path.to_owned()],
+        &source_file_path.with_extension("stamp"),
+    )
+}
+
+/// Compiles all of the sources in a test directory together, unless none of them have changed
+/// since they were last compiled.
+fn compile_dir(dir: &Path) -> eyre::Result<()> {
+    let mut files = vec![];
+    find_files(dir, &mut files)?;
+
+    // Subdirectories are packages
+    let mut sources = files
+        .into_iter()
+        .filter(|path| path.extension().is_some_and(|ext| ext == "java"))
+        .collect::<Vec<_>>();
+    sources.sort();
+
+    compile_sources(&sources, &dir.with_extension("stamp"))
+}
+
+/// Compiles sources with the JDK's compiler, unless the stamp file shows that they haven't
+/// changed since they were last compiled. The stamp holds a hash of the sources rather than
+/// relying on modification times, which aren't always updated by tools that restore files, and
+/// the sources are compiled again if any of their class files are missing.
+///
+/// The class files are saved as fixtures in `integration_tests/fixtures`, which are used instead
+/// of compiling when there isn't a JDK, or if `RUSTY_JAVA_TEST_FIXTURES` is set.
+fn compile_sources(sources: &[PathBuf], stamp_path: &Path) -> eyre::Result<()> {
+    // FNV-1a, since the hashes are saved with the fixtures, and std's hasher can change between
+    // versions of Rust
+    let mut hash = 0xcbf29ce484222325u64;
+    for source in sources {
+        let contents = fs::read(source).wrap_err_with(|| format!("failed to read {source:?}"))?;
+        for byte in contents {
+            hash = (hash ^ byte as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+    let hash = format!("{hash:016x}");
+
+    let up_to_date = fs::read_to_string(stamp_path).is_ok_and(|stamp| stamp == hash)
+        && sources
+            .iter()
+            .all(|source| source.with_extension("class").exists());
+
+    if up_to_date {
+        return Ok(());
+    }
+
+    let tests_dir = Path::new(file!()).parent().unwrap();
+    let fixtures_dir = tests_dir.join("fixtures");
+    let manifest_path = fixtures_dir.join(stamp_path.file_name().unwrap());
+
+    if env::var_os("RUSTY_JAVA_TEST_FIXTURES").is_some() || !has_jdk() {
+        install_fixtures(&hash, tests_dir, &fixtures_dir, &manifest_path)?;
+    } else {
+        eprintln!("{stamp_path:?} is out of date, recompiling");
+        update_fixtures(sources, &hash, tests_dir, &fixtures_dir, &manifest_path)?;
+    }
+
+    fs::write(stamp_path, hash)?;
+
+    Ok(())
+}
+
+/// Copies the fixtures of a test program next to its sources, as if it had been compiled. Each
+/// program has a manifest in the fixtures directory, with the hash of the sources its class files
+/// were compiled from, followed by their paths.
+fn install_fixtures(
+    hash: &str,
+    tests_dir: &Path,
+    fixtures_dir: &Path,
+    manifest_path: &Path,
+) -> eyre::Result<()> {
+    let manifest = fs::read_to_string(manifest_path)
+        .wrap_err_with(|| format!("failed to read the fixture manifest {manifest_path:?}"))?;
+    let mut lines = manifest.lines();
+
+    if lines.next() != Some(hash) {
+        bail!("the fixtures in {manifest_path:?} are out of date, run the tests with a JDK to update them");
+    }
+
+    for class_file in lines {
+        let path = tests_dir.join(class_file);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::copy(fixtures_dir.join(class_file), path)?;
+    }
+
+    Ok(())
+}
+
+/// Compiles a test program and saves its class files as fixtures, replacing the ones from the
+/// last time it was compiled.
+fn update_fixtures(
+    sources: &[PathBuf],
+    hash: &str,
+    tests_dir: &Path,
+    fixtures_dir: &Path,
+    manifest_path: &Path,
+) -> eyre::Result<()> {
+    // Tests can compile the same program at the same time, so each is compiled to a separate
+    // directory, in which the class files are found afterwards
+    static NEXT_OUTPUT_DIR: AtomicUsize = AtomicUsize::new(0);
+    let output_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!(
+        "classes-{}-{}",
+        process::id(),
+        NEXT_OUTPUT_DIR.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let output_dir_arg = output_dir
+        .to_str()
+        .wrap_err("output directory is not valid unicode")?;
+    jdk_tools::compiler::compile(sources, &["-d", output_dir_arg])?;
+
+    // Every test program is in the integration_tests package, so that its classes can be loaded
+    // from the crate's directory
+    let package_dir = output_dir.join("integration_tests");
+    let mut class_files = vec![];
+    find_files(&package_dir, &mut class_files)?;
+    class_files.sort();
+
+    if let Ok(manifest) = fs::read_to_string(manifest_path) {
+        for class_file in manifest.lines().skip(1) {
+            let _ = fs::remove_file(fixtures_dir.join(class_file));
+        }
+    }
+
+    let mut manifest = format!("{hash}\n");
+    for class_file in &class_files {
+        let name = class_file.strip_prefix(&package_dir)?;
+        let name = name
+            .to_str()
+            .wrap_err_with(|| format!("class file name is not valid unicode: {name:?}"))?;
+
+        for dir in [tests_dir, fixtures_dir] {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::copy(class_file, path)?;
+        }
+
+        manifest += name;
+        manifest += "\n";
+    }
+
+    fs::write(manifest_path, manifest)?;
+    fs::remove_dir_all(output_dir)?;
+
+    Ok(())
+}
+
+/// Finds the files in a directory and its subdirectories.
+fn find_files(dir: &Path, files: &mut Vec<PathBuf>) -> eyre::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+
+    Ok(())
 }
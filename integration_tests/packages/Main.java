@@ -0,0 +1,24 @@
+package integration_tests.packages;
+
+import integration_tests.packages.shapes.Polygon;
+import integration_tests.packages.shapes.Shape;
+import integration_tests.packages.shapes.Square;
+import integration_tests.packages.shapes.Triangle;
+import integration_tests.packages.util.Counter;
+
+public class Main {
+    public static void main(String[] args) {
+        Shape[] shapes = {new Square(3), new Triangle(3, 4, 5)};
+
+        for (Shape shape : shapes) {
+            System.out.println(shape.name());
+            System.out.println(shape.perimeter());
+
+            if (shape instanceof Polygon) {
+                System.out.println(((Polygon) shape).sides());
+            }
+        }
+
+        System.out.println(Counter.count());
+    }
+}
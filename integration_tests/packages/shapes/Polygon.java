@@ -0,0 +1,16 @@
+package integration_tests.packages.shapes;
+
+import integration_tests.packages.util.Counter;
+
+public abstract class Polygon implements Shape {
+    protected final int sides;
+
+    protected Polygon(int sides) {
+        this.sides = sides;
+        Counter.increment();
+    }
+
+    public int sides() {
+        return sides;
+    }
+}
@@ -0,0 +1,9 @@
+package integration_tests.packages.shapes;
+
+public interface Shape {
+    int perimeter();
+
+    default String name() {
+        return "shape";
+    }
+}
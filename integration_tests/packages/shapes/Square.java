@@ -0,0 +1,20 @@
+package integration_tests.packages.shapes;
+
+public class Square extends Polygon {
+    private final int side;
+
+    public Square(int side) {
+        super(4);
+        this.side = side;
+    }
+
+    @Override
+    public int perimeter() {
+        return side + side + side + side;
+    }
+
+    @Override
+    public String name() {
+        return "square";
+    }
+}
@@ -0,0 +1,19 @@
+package integration_tests.packages.shapes;
+
+public class Triangle extends Polygon {
+    private final int a;
+    private final int b;
+    private final int c;
+
+    public Triangle(int a, int b, int c) {
+        super(3);
+        this.a = a;
+        this.b = b;
+        this.c = c;
+    }
+
+    @Override
+    public int perimeter() {
+        return a + b + c;
+    }
+}
@@ -0,0 +1,13 @@
+package integration_tests.packages.util;
+
+public class Counter {
+    private static int count;
+
+    public static void increment() {
+        count++;
+    }
+
+    public static int count() {
+        return count;
+    }
+}
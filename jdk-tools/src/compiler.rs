@@ -0,0 +1,107 @@
+//! Compiles java sources with the JDK's compiler, which is run in a JVM started over JNI, so that
+//! `javac` doesn't need to be on the `PATH`.
+
+use std::path::Path;
+use std::sync::OnceLock;
+
+use color_eyre::eyre::{self, bail, eyre, ContextCompat};
+use jni::objects::{JObject, JString, JValue};
+use jni::{InitArgsBuilder, JNIEnv, JNIVersion, JavaVM};
+
+/// A process can only create one JVM, even after destroying it, so it is shared by all
+/// compilations.
+static JVM: OnceLock<Result<JavaVM, String>> = OnceLock::new();
+
+fn jvm() -> eyre::Result<&'static JavaVM> {
+    JVM.get_or_init(|| {
+        let args = InitArgsBuilder::new()
+            .version(JNIVersion::V8)
+            // Leave signals like SIGINT to the process which started the JVM
+            .option("-Xrs")
+            .build()
+            .map_err(|e| e.to_string())?;
+        JavaVM::new(args).map_err(|e| e.to_string())
+    })
+    .as_ref()
+    .map_err(|e| eyre!("failed to start a JVM: {e}"))
+}
+
+/// Compiles java sources together, like `javac [OPTIONS] SOURCES...`. Class files are written
+/// next to their sources unless the options say otherwise, e.g. with `-d`. The JVM is started by
+/// the first call, using the JDK found from `JAVA_HOME` or the `java` executable on the `PATH`.
+///
+/// Fails with the compiler's diagnostics if a source doesn't compile.
+pub fn compile(sources: &[impl AsRef<Path>], options: &[&str]) -> eyre::Result<()> {
+    let mut env = jvm()?.attach_current_thread()?;
+
+    let mut args = options
+        .iter()
+        .map(|option| option.to_string())
+        .collect::<Vec<_>>();
+    for source in sources {
+        let source = source.as_ref();
+        let source = source
+            .to_str()
+            .wrap_err_with(|| format!("source path is not valid unicode: {source:?}"))?;
+        args.push(source.to_owned());
+    }
+
+    let result = run_compiler(&mut env, &args);
+
+    // Leave the thread without an exception pending, in case it stays attached
+    if env.exception_check()? {
+        env.exception_describe()?;
+        env.exception_clear()?;
+    }
+
+    let (status, output) = result?;
+    if status != 0 {
+        bail!("javac failed with status {status}:\n{output}");
+    }
+
+    Ok(())
+}
+
+/// Runs `JavaCompiler.run`, returning its status along with the diagnostics it wrote.
+fn run_compiler(env: &mut JNIEnv, args: &[String]) -> eyre::Result<(i32, String)> {
+    let compiler = env
+        .call_static_method(
+            "javax/tools/ToolProvider",
+            "getSystemJavaCompiler",
+            "()Ljavax/tools/JavaCompiler;",
+            &[],
+        )?
+        .l()?;
+    if compiler.is_null() {
+        bail!("the JDK doesn't include a compiler, it may be a JRE");
+    }
+
+    let arg_array = env.new_object_array(args.len() as i32, "java/lang/String", JObject::null())?;
+    for (i, arg) in args.iter().enumerate() {
+        let arg = env.new_string(arg)?;
+        env.set_object_array_element(&arg_array, i as i32, arg)?;
+    }
+
+    let output = env.new_object("java/io/ByteArrayOutputStream", "()V", &[])?;
+
+    let status = env
+        .call_method(
+            &compiler,
+            "run",
+            "(Ljava/io/InputStream;Ljava/io/OutputStream;Ljava/io/OutputStream;[Ljava/lang/String;)I",
+            &[
+                JValue::Object(&JObject::null()),
+                JValue::Object(&output),
+                JValue::Object(&output),
+                JValue::Object(&arg_array),
+            ],
+        )?
+        .i()?;
+
+    let output = env
+        .call_method(&output, "toString", "()Ljava/lang/String;", &[])?
+        .l()?;
+    let output = env.get_string(&JString::from(output))?.into();
+
+    Ok((status, output))
+}
@@ -0,0 +1,149 @@
+//! Finds the classes which a class file refers to, so that the classes needed by a program can be
+//! extracted from a JDK without extracting whole modules.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use bumpalo::Bump;
+use color_eyre::eyre::{self, eyre, WrapErr};
+use rusty_java::class_file::constant_pool::ConstantInfo;
+use rusty_java::descriptor::{
+    parse_field_descriptor, parse_method_descriptor, BaseType, FieldType,
+};
+use rusty_java::reader::ClassReader;
+
+/// Returns the binary names of the classes which a class file refers to, not including itself.
+/// These are the classes in its constant pool, such as its super class and the classes whose
+/// members it uses, along with the classes in the descriptors of its fields and methods and of
+/// the members it uses. Arrays are replaced by their element types.
+pub fn referenced_classes(class_file: &[u8]) -> eyre::Result<BTreeSet<String>> {
+    let arena = Bump::new();
+    let class_file = ClassReader::from_slice(&arena, class_file).read_class_file()?;
+    let pool = &class_file.constant_pool;
+
+    let utf8_at = |index: u16| match pool.get(index) {
+        Some(ConstantInfo::Utf8(value)) => Ok(*value),
+        _ => Err(eyre!("invalid utf8 constant index {index}")),
+    };
+
+    let mut classes = BTreeSet::new();
+    let mut descriptors = vec![];
+
+    for constant in (1..=u16::MAX).map_while(|index| pool.get(index)) {
+        match constant {
+            ConstantInfo::Class(class) => {
+                let name = utf8_at(class.name_index)?;
+                // Array classes are named by their descriptors
+                match name.starts_with('[') {
+                    true => descriptors.push(name),
+                    false => {
+                        classes.insert(name.to_owned());
+                    }
+                }
+            }
+            ConstantInfo::NameAndType(name_and_type) => {
+                descriptors.push(utf8_at(name_and_type.descriptor_index)?);
+            }
+            ConstantInfo::MethodType(method_type) => {
+                descriptors.push(utf8_at(method_type.descriptor_index)?);
+            }
+            _ => {}
+        }
+    }
+
+    // The descriptors of the class's own fields and methods
+    for index in class_file
+        .fields
+        .iter()
+        .map(|field| field.descriptor_index)
+        .chain(
+            class_file
+                .methods
+                .iter()
+                .map(|method| method.descriptor_index),
+        )
+    {
+        descriptors.push(utf8_at(index)?);
+    }
+
+    for descriptor in descriptors {
+        add_descriptor_classes(&arena, descriptor, &mut classes)
+            .wrap_err_with(|| format!("invalid descriptor {descriptor}"))?;
+    }
+
+    // A class often refers to itself in descriptors
+    if let Some(name) = class_file.this_class_name() {
+        classes.remove(name);
+    }
+
+    Ok(classes)
+}
+
+/// Adds the classes in a field or method descriptor, e.g. `(I[Ljava/lang/String;)V`.
+fn add_descriptor_classes(
+    arena: &Bump,
+    descriptor: &str,
+    classes: &mut BTreeSet<String>,
+) -> eyre::Result<()> {
+    let types = match descriptor.starts_with('(') {
+        true => {
+            let descriptor = parse_method_descriptor(arena, arena.alloc_str(descriptor))?;
+            descriptor
+                .params
+                .iter()
+                .chain(&descriptor.return_type)
+                .cloned()
+                .collect()
+        }
+        false => vec![parse_field_descriptor(descriptor)?.field_type],
+    };
+
+    for field_type in types {
+        if let FieldType::Base(BaseType::Object(name))
+        | FieldType::Array(_, BaseType::Object(name)) = field_type
+        {
+            classes.insert(name.to_owned());
+        }
+    }
+
+    Ok(())
+}
+
+/// The classes found by [`closure`].
+#[derive(Debug, Default)]
+pub struct Closure {
+    /// The contents of each class which was found, by binary name.
+    pub classes: BTreeMap<String, Vec<u8>>,
+    /// The classes which were referred to but not found, in the order they were reached.
+    pub missing: Vec<String>,
+}
+
+/// Finds the classes which the given classes refer to, and every class those refer to, using
+/// `find_class` to look up the contents of each class. The given classes are included too, if
+/// they're found.
+pub fn closure(
+    classes: impl IntoIterator<Item = String>,
+    mut find_class: impl FnMut(&str) -> eyre::Result<Option<Vec<u8>>>,
+) -> eyre::Result<Closure> {
+    let mut closure = Closure::default();
+    let mut seen = BTreeSet::new();
+    let mut queue = classes.into_iter().collect::<VecDeque<_>>();
+
+    while let Some(class_name) = queue.pop_front() {
+        if !seen.insert(class_name.clone()) {
+            continue;
+        }
+
+        let Some(bytes) = find_class(&class_name)? else {
+            closure.missing.push(class_name);
+            continue;
+        };
+
+        let references = referenced_classes(&bytes)
+            .wrap_err_with(|| format!("failed to read class {class_name}"))?;
+        queue.extend(references.into_iter().filter(|name| !seen.contains(name)));
+
+        closure.classes.insert(class_name, bytes);
+    }
+
+    Ok(closure)
+}
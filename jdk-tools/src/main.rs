@@ -1,35 +1,381 @@
-use std::io::Write;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
 
 use clap::Parser;
-use color_eyre::eyre::{self, ContextCompat};
-use jdk_tools::Jvm;
+use color_eyre::eyre::{self, bail, ContextCompat, WrapErr};
+use jdk_tools::dependencies;
+use rusty_java::jimage::{self, JImage};
 
 #[derive(Parser)]
+#[clap(subcommand_negates_reqs = true)]
 struct Args {
-    class: String,
+    #[clap(subcommand)]
+    command: Option<Command>,
+    /// The binary names of the classes to extract, e.g. `java/lang/Object`
+    #[clap(required_unless_present = "list")]
+    classes: Vec<String>,
+    /// A file listing more classes to extract, one per line, or `-` for stdin
+    #[clap(long)]
+    list: Option<PathBuf>,
+    /// Where to write a single class, or `-` for stdout [default: its simple name]. Several
+    /// classes are written to this directory, in directories for their packages
     #[clap(short, long)]
     out: Option<String>,
+    /// The JDK to extract the class from [default: $JAVA_HOME]
+    #[clap(long, global = true)]
+    java_home: Option<PathBuf>,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Extracts every class in a module or a package, including its subpackages, into a
+    /// directory which can be used as a class path
+    ExtractAll {
+        /// A module (e.g. `java.base`), a package (e.g. `java/util`), or a package in a module
+        /// (e.g. `java.base/java/util`)
+        target: String,
+        /// The directory to write the classes to, in directories for their packages
+        #[clap(short, long)]
+        out_dir: PathBuf,
+    },
+    /// Extracts a class along with every class it refers to, and every class those refer to, so
+    /// that a program can be run with only the classes it could need
+    ExtractDeps {
+        /// The binary name of a class in the JDK, or the path to a class file such as a compiled
+        /// test program, which isn't extracted itself
+        class: String,
+        /// The directory to write the classes to, in directories for their packages
+        #[clap(short, long)]
+        out_dir: PathBuf,
+    },
+    /// Extracts the classes named on each line of stdin, writing a line for each one to stdout
+    /// with `ok` and the path it was written to, or `error` and the reason it wasn't. The JDK is
+    /// only opened once, so this is faster than running jdk-tools for every class.
+    Serve {
+        /// The directory to write the classes to, in directories for their packages
+        #[clap(short, long)]
+        out_dir: PathBuf,
+    },
+    /// Lists the modules in the JDK, the packages in a module, or the classes in a package
+    List {
+        /// A module (e.g. `java.base`), or a package in a module (e.g. `java.base/java/util`)
+        target: Option<String>,
+    },
 }
 
 fn main() -> eyre::Result<()> {
     color_eyre::install()?;
 
     let args = Args::parse();
+
+    let java_home = args
+        .java_home
+        .or_else(jimage::find_java_home)
+        .wrap_err("failed to find a JDK, set JAVA_HOME or use --java-home")?;
+
+    let jimage = JImage::open_java_home(&java_home)?;
+
+    if let Some(command) = args.command {
+        return match command {
+            Command::ExtractAll { target, out_dir } => {
+                extract_all(&jimage, &java_home, &target, &out_dir)
+            }
+            Command::ExtractDeps { class, out_dir } => {
+                extract_deps(&jimage, &java_home, &class, &out_dir)
+            }
+            Command::Serve { out_dir } => serve(&jimage, &java_home, &out_dir),
+            Command::List { target } => list(&jimage, target.as_deref()),
+        };
+    }
+
+    let mut classes = args.classes;
+    if let Some(list) = &args.list {
+        let list = if list == Path::new("-") {
+            io::read_to_string(io::stdin())?
+        } else {
+            fs::read_to_string(list).wrap_err_with(|| format!("failed to read {list:?}"))?
+        };
+
+        classes.extend(
+            list.lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_owned),
+        );
+    }
+
+    if classes.len() != 1 || args.list.is_some() {
+        let out_dir = args
+            .out
+            .wrap_err("the directory to write the classes to must be given with --out")?;
+        return extract_classes(&jimage, &java_home, &classes, Path::new(&out_dir));
+    }
+
+    let class = classes.pop().unwrap();
     let out_path = args
         .out
         .or_else(|| {
-            let class_name = args.class.split('/').next_back()?;
+            let class_name = class.split('/').next_back()?;
             Some(format!("{class_name}.class"))
         })
         .wrap_err("could not determine a suitable output path, please specify one")?;
 
-    let bytes = Jvm::new()?.extract_jrt_class(&args.class)?;
+    let bytes = jimage
+        .find_class(&class)?
+        .wrap_err_with(|| format!("class not found: {class}"))?;
 
     if out_path == "-" {
-        std::io::stdout().write_all(&bytes)?;
+        std::io::stdout().write_all(bytes)?;
+    } else {
+        fs::write(out_path, bytes)?;
+    }
+
+    Ok(())
+}
+
+fn extract_classes(
+    jimage: &JImage,
+    java_home: &Path,
+    classes: &[String],
+    out_dir: &Path,
+) -> eyre::Result<()> {
+    let mut count = 0;
+    let mut missing = vec![];
+
+    for class_name in classes {
+        match jimage.find_class(class_name)? {
+            Some(bytes) => {
+                write_class(out_dir, class_name, bytes)?;
+                count += 1;
+            }
+            None => missing.push(class_name.as_str()),
+        }
+    }
+
+    if count > 0 {
+        finish_extraction(java_home, out_dir, count)?;
+    }
+
+    if !missing.is_empty() {
+        bail!("classes not found: {}", missing.join(", "));
+    }
+
+    Ok(())
+}
+
+fn extract_all(
+    jimage: &JImage,
+    java_home: &Path,
+    target: &str,
+    out_dir: &Path,
+) -> eyre::Result<()> {
+    let classes = jimage.classes()?;
+
+    // The first part of the target is a module if there's a module with that name, and
+    // otherwise the target is just a package, which may be written with dots
+    let (module, package) = match target.split_once('/').unwrap_or((target, "")) {
+        (module, package) if classes.iter().any(|(m, _)| m == module) => (
+            Some(module),
+            Some(package).filter(|package| !package.is_empty()),
+        ),
+        _ => (None, Some(target.trim_end_matches('/'))),
+    };
+    let package = package.map(|package| package.replace('.', "/"));
+
+    let mut count = 0;
+    for (class_module, class_name) in &classes {
+        if module.is_some_and(|module| module != class_module) {
+            continue;
+        }
+
+        if let Some(package) = &package {
+            let in_package = class_name
+                .strip_prefix(package.as_str())
+                .is_some_and(|rest| rest.starts_with('/'));
+            if !in_package {
+                continue;
+            }
+        }
+
+        let bytes = jimage
+            .find_resource(&format!("/{class_module}/{class_name}.class"))?
+            .wrap_err_with(|| format!("class not found: {class_name}"))?;
+
+        write_class(out_dir, class_name, bytes)?;
+
+        count += 1;
+    }
+
+    if count == 0 {
+        bail!("no classes found in {target}");
+    }
+
+    finish_extraction(java_home, out_dir, count)
+}
+
+fn extract_deps(
+    jimage: &JImage,
+    java_home: &Path,
+    class: &str,
+    out_dir: &Path,
+) -> eyre::Result<()> {
+    let roots = if class.ends_with(".class") && Path::new(class).is_file() {
+        let bytes = fs::read(class)?;
+        dependencies::referenced_classes(&bytes)?
+            .into_iter()
+            .collect()
     } else {
-        std::fs::write(out_path, &bytes)?;
+        vec![class.to_owned()]
+    };
+
+    // Classes outside the JDK, like the rest of a program, are left for the user to provide
+    let closure = dependencies::closure(roots, |class_name| {
+        Ok(jimage.find_class(class_name)?.map(<[u8]>::to_vec))
+    })?;
+
+    if closure.classes.is_empty() {
+        bail!("class not found: {class}");
+    }
+
+    for (class_name, bytes) in &closure.classes {
+        write_class(out_dir, class_name, bytes)?;
+    }
+
+    finish_extraction(java_home, out_dir, closure.classes.len())?;
+
+    if !closure.missing.is_empty() {
+        println!("Not found in the JDK: {}", closure.missing.join(", "));
+    }
+
+    Ok(())
+}
+
+fn serve(jimage: &JImage, java_home: &Path, out_dir: &Path) -> eyre::Result<()> {
+    fs::create_dir_all(out_dir)?;
+    write_release(java_home, out_dir)?;
+
+    let mut stdout = io::stdout().lock();
+
+    // Lines are read as bytes, so that a request which isn't UTF-8 gets an error response
+    // rather than stopping the server
+    for line in io::stdin().lock().split(b'\n') {
+        let line = line?;
+        let Ok(class_name) = std::str::from_utf8(&line) else {
+            writeln!(stdout, "error: request is not valid UTF-8")?;
+            stdout.flush()?;
+            continue;
+        };
+
+        let class_name = class_name.trim();
+        if class_name.is_empty() {
+            continue;
+        }
+
+        let result = jimage
+            .find_class(class_name)
+            .map_err(eyre::Report::from)
+            .and_then(|bytes| bytes.wrap_err("class not found"))
+            .and_then(|bytes| write_class(out_dir, class_name, bytes));
+
+        match result {
+            Ok(path) => writeln!(stdout, "ok {}", path.display())?,
+            Err(e) => writeln!(stdout, "error {class_name}: {e}")?,
+        }
+
+        // The client waits for each response before sending the next request
+        stdout.flush()?;
+    }
+
+    Ok(())
+}
+
+/// Writes a class to its package's directory in `out_dir`, returning its path.
+fn write_class(out_dir: &Path, class_name: &str, bytes: &[u8]) -> eyre::Result<PathBuf> {
+    let path = out_dir.join(format!("{class_name}.class"));
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(&path, bytes)?;
+    Ok(path)
+}
+
+/// Copies the JDK's release file, which has its version, next to the classes extracted from it,
+/// so that it's known which JDK they came from once it has been updated or removed.
+fn write_release(java_home: &Path, out_dir: &Path) -> eyre::Result<()> {
+    let release = jimage::release_path(java_home);
+    if release.is_file() {
+        fs::copy(release, out_dir.join("release"))?;
+    }
+    Ok(())
+}
+
+/// Records which JDK the classes in `out_dir` were extracted from, and reports them.
+fn finish_extraction(java_home: &Path, out_dir: &Path, count: usize) -> eyre::Result<()> {
+    write_release(java_home, out_dir)?;
+
+    match jimage::java_version(java_home) {
+        Some(version) => println!(
+            "Extracted {count} classes from JDK {version} to {}",
+            out_dir.display()
+        ),
+        None => println!("Extracted {count} classes to {}", out_dir.display()),
     }
 
     Ok(())
 }
+
+fn list(jimage: &JImage, target: Option<&str>) -> eyre::Result<()> {
+    let classes = jimage.classes()?;
+
+    let names = match target {
+        None => classes
+            .iter()
+            .map(|(module, _)| module.clone())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect(),
+        Some(target) => {
+            let (module, package) = target.split_once('/').unwrap_or((target, ""));
+            let module_classes = classes
+                .iter()
+                .filter(|(class_module, _)| class_module == module)
+                .map(|(_, class_name)| class_name.rsplit_once('/').unwrap())
+                .collect::<Vec<_>>();
+
+            if module_classes.is_empty() {
+                bail!("module not found: {module}");
+            }
+
+            if package.is_empty() {
+                module_classes
+                    .iter()
+                    .map(|(package, _)| package.to_string())
+                    .collect::<BTreeSet<_>>()
+                    .into_iter()
+                    .collect()
+            } else {
+                let package = package.trim_end_matches('/').replace('.', "/");
+                let names = module_classes
+                    .iter()
+                    .filter(|(class_package, _)| *class_package == package)
+                    .map(|(_, class_name)| format!("{package}/{class_name}"))
+                    .collect::<Vec<_>>();
+
+                if names.is_empty() {
+                    bail!("package not found: {target}");
+                }
+
+                names
+            }
+        }
+    };
+
+    let mut output = names.join("\n");
+    output.push('\n');
+
+    // The list is often piped to another program like `head`, which can exit before reading it all
+    match std::io::stdout().write_all(output.as_bytes()) {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => Ok(result?),
+    }
+}
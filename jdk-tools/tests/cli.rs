@@ -0,0 +1,493 @@
+//! Runs jdk-tools against a fake JDK, whose jimage has a few classes in two modules, so that the
+//! commands can be tested without a real JDK.
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Output, Stdio};
+
+use bumpalo::Bump;
+use rusty_java::assembler::ClassBuilder;
+use rusty_java::writer::ClassWriter;
+
+const MAGIC: u32 = 0xCAFEDADA;
+const HASH_MULTIPLIER: u32 = 0x01000193;
+
+const CLASSES: &[(&str, &str)] = &[
+    ("java.base", "java/lang/Object"),
+    ("java.base", "java/lang/String"),
+    ("java.base", "java/util/List"),
+    ("java.base", "java/util/concurrent/Future"),
+    ("java.logging", "java/util/logging/Logger"),
+];
+
+/// A JDK in a temporary directory, with a release file and a jimage containing [`CLASSES`].
+struct FakeJdk {
+    dir: PathBuf,
+    classes: HashMap<&'static str, Vec<u8>>,
+}
+
+impl FakeJdk {
+    fn new(name: &str) -> FakeJdk {
+        let dir = env::temp_dir().join(format!("jdk-tools-{}-{name}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("jdk/lib")).unwrap();
+        fs::write(dir.join("jdk/release"), "JAVA_VERSION=\"17.0.99\"\n").unwrap();
+
+        let arena = Bump::new();
+        let classes = CLASSES
+            .iter()
+            .map(|&(_, class_name)| {
+                let mut bytes = vec![];
+                ClassWriter::new(&mut bytes)
+                    .write_class_file(&ClassBuilder::new(&arena, class_name).build())
+                    .unwrap();
+                (class_name, bytes)
+            })
+            .collect::<HashMap<_, _>>();
+
+        let mut resources = CLASSES
+            .iter()
+            .map(|&(module, class_name)| {
+                let name = format!("/{module}/{class_name}.class");
+                (name, Resource::Bytes(classes[class_name].clone()))
+            })
+            .collect::<Vec<_>>();
+
+        // Each module has a module-info, which isn't a class in a package
+        resources.push((
+            "/java.base/module-info.class".to_owned(),
+            Resource::Bytes(vec![]),
+        ));
+        resources.push((
+            "/java.logging/module-info.class".to_owned(),
+            Resource::Bytes(vec![]),
+        ));
+
+        let mut packages = CLASSES
+            .iter()
+            .map(|&(module, class_name)| {
+                let (package, _) = class_name.rsplit_once('/').unwrap();
+                (package.replace('/', "."), module)
+            })
+            .collect::<Vec<_>>();
+        packages.dedup();
+        resources.extend(
+            packages.into_iter().map(|(package, module)| {
+                (format!("/packages/{package}"), Resource::Package(module))
+            }),
+        );
+
+        write_jimage(&dir.join("jdk/lib/modules"), &resources);
+
+        FakeJdk { dir, classes }
+    }
+
+    /// Runs jdk-tools with this JDK, in its directory.
+    fn run(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_jdk-tools"))
+            .current_dir(&self.dir)
+            .arg("--java-home")
+            .arg(self.dir.join("jdk"))
+            .args(args)
+            .output()
+            .unwrap()
+    }
+
+    /// Runs jdk-tools with this JDK and returns its stdout, panicking if it fails.
+    fn run_ok(&self, args: &[&str]) -> String {
+        let output = self.run(args);
+        assert!(
+            output.status.success(),
+            "jdk-tools {args:?} failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// Runs jdk-tools with this JDK and returns its stderr, panicking if it succeeds.
+    fn run_err(&self, args: &[&str]) -> String {
+        let output = self.run(args);
+        assert!(!output.status.success(), "jdk-tools {args:?} succeeded");
+        String::from_utf8(output.stderr).unwrap()
+    }
+
+    fn path(&self, path: &str) -> PathBuf {
+        self.dir.join(path)
+    }
+
+    /// Returns the classes in a directory of extracted classes, checking that they're the same
+    /// as the ones in the jimage.
+    fn extracted_classes(&self, out_dir: &str) -> Vec<String> {
+        let out_dir = self.path(out_dir);
+        let mut classes = CLASSES
+            .iter()
+            .filter_map(|&(_, class_name)| {
+                let bytes = fs::read(out_dir.join(format!("{class_name}.class"))).ok()?;
+                assert_eq!(bytes, self.classes[class_name], "{class_name}");
+                Some(class_name.to_owned())
+            })
+            .collect::<Vec<_>>();
+        classes.sort();
+        classes
+    }
+}
+
+impl Drop for FakeJdk {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+enum Resource {
+    Bytes(Vec<u8>),
+    /// A package in `/packages`, which lists the module that contains it.
+    Package(&'static str),
+}
+
+/// Writes a little endian jimage with the given resources, which aren't compressed.
+fn write_jimage(path: &Path, resources: &[(String, Resource)]) {
+    let mut strings = vec![0];
+    let mut string_offsets = HashMap::from([(String::new(), 0)]);
+    let mut string = |value: &str| -> u64 {
+        *string_offsets.entry(value.to_owned()).or_insert_with(|| {
+            let offset = strings.len();
+            strings.extend_from_slice(value.as_bytes());
+            strings.push(0);
+            offset as u64
+        })
+    };
+
+    let mut locations = vec![];
+    let mut location_offsets = vec![];
+    let mut contents = vec![];
+
+    for (name, resource) in resources {
+        let content = match resource {
+            Resource::Bytes(bytes) => bytes.clone(),
+            Resource::Package(module) => [0u32, string(module) as u32]
+                .iter()
+                .flat_map(|value| value.to_le_bytes())
+                .collect(),
+        };
+
+        let (module, path) = name[1..].split_once('/').unwrap();
+        let (parent, file_name) = path.rsplit_once('/').unwrap_or(("", path));
+        let (base, extension) = file_name.rsplit_once('.').unwrap_or((file_name, ""));
+
+        location_offsets.push(locations.len() as u32);
+        let attributes = [
+            (1, string(module)),
+            (2, string(parent)),
+            (3, string(base)),
+            (4, string(extension)),
+            (5, contents.len() as u64),
+            (7, content.len() as u64),
+        ];
+        for (kind, value) in attributes.into_iter().filter(|&(_, value)| value != 0) {
+            let length = (u64::BITS - value.leading_zeros()).div_ceil(8) as usize;
+            locations.push(kind << 3 | (length - 1) as u8);
+            locations.extend_from_slice(&value.to_be_bytes()[8 - length..]);
+        }
+        locations.push(0);
+
+        contents.extend(content);
+    }
+
+    // Builds a perfect hash table, where the names in each bucket are either given a slot
+    // directly, or a seed which rehashes them into free slots. Buckets with several names are
+    // placed first, while there are more free slots.
+    let length = resources.len();
+    let mut buckets = vec![vec![]; length];
+    for (index, (name, _)) in resources.iter().enumerate() {
+        buckets[hash(name, HASH_MULTIPLIER) as usize % length].push(index);
+    }
+
+    let mut order = (0..length).collect::<Vec<_>>();
+    order.sort_by_key(|&bucket| Reverse(buckets[bucket].len()));
+
+    let mut redirects = vec![0i32; length];
+    let mut slots = vec![None; length];
+    for bucket in order {
+        match buckets[bucket].as_slice() {
+            [] => {}
+            &[index] => {
+                let slot = slots.iter().position(Option::is_none).unwrap();
+                slots[slot] = Some(index);
+                redirects[bucket] = -1 - slot as i32;
+            }
+            indices => {
+                let slot = |seed, index: usize| hash(&resources[index].0, seed) as usize % length;
+                let seed = (1..)
+                    .find(|&seed| {
+                        let mut taken = vec![];
+                        indices.iter().all(|&index| {
+                            let slot = slot(seed, index);
+                            let free = slots[slot].is_none() && !taken.contains(&slot);
+                            taken.push(slot);
+                            free
+                        })
+                    })
+                    .unwrap();
+                for &index in indices {
+                    slots[slot(seed, index)] = Some(index);
+                }
+                redirects[bucket] = seed as i32;
+            }
+        }
+    }
+
+    let header = [
+        MAGIC,
+        1 << 16,
+        0,
+        length as u32,
+        length as u32,
+        locations.len() as u32,
+        strings.len() as u32,
+    ];
+
+    let mut bytes = vec![];
+    bytes.extend(header.iter().flat_map(|value| value.to_le_bytes()));
+    bytes.extend(redirects.iter().flat_map(|value| value.to_le_bytes()));
+    bytes.extend(
+        slots
+            .iter()
+            .flat_map(|index| location_offsets[index.unwrap()].to_le_bytes()),
+    );
+    bytes.extend(locations);
+    bytes.extend(strings);
+    bytes.extend(contents);
+
+    fs::write(path, bytes).unwrap();
+}
+
+fn hash(name: &str, seed: u32) -> u32 {
+    name.bytes().fold(seed, |hash, byte| {
+        hash.wrapping_mul(HASH_MULTIPLIER) ^ byte as u32
+    }) & 0x7fffffff
+}
+
+#[test]
+fn extract_all_module() {
+    let jdk = FakeJdk::new("extract-all-module");
+
+    let stdout = jdk.run_ok(&["extract-all", "java.logging", "--out-dir", "out"]);
+    assert_eq!(
+        stdout,
+        format!(
+            "Extracted 1 classes from JDK 17.0.99 to {}\n",
+            Path::new("out").display()
+        )
+    );
+    assert_eq!(jdk.extracted_classes("out"), ["java/util/logging/Logger"]);
+    assert_eq!(
+        fs::read_to_string(jdk.path("out/release")).unwrap(),
+        "JAVA_VERSION=\"17.0.99\"\n"
+    );
+}
+
+#[test]
+fn extract_all_package() {
+    let jdk = FakeJdk::new("extract-all-package");
+
+    // Subpackages are included, even from other modules
+    jdk.run_ok(&["extract-all", "java/util", "--out-dir", "util"]);
+    assert_eq!(
+        jdk.extracted_classes("util"),
+        [
+            "java/util/List",
+            "java/util/concurrent/Future",
+            "java/util/logging/Logger"
+        ]
+    );
+
+    jdk.run_ok(&["extract-all", "java.base/java/util", "--out-dir", "base"]);
+    assert_eq!(
+        jdk.extracted_classes("base"),
+        ["java/util/List", "java/util/concurrent/Future"]
+    );
+
+    jdk.run_ok(&["extract-all", "java.util.concurrent", "--out-dir", "dotted"]);
+    assert_eq!(
+        jdk.extracted_classes("dotted"),
+        ["java/util/concurrent/Future"]
+    );
+}
+
+#[test]
+fn extract_all_not_found() {
+    let jdk = FakeJdk::new("extract-all-not-found");
+
+    let stderr = jdk.run_err(&["extract-all", "java/nothing", "--out-dir", "out"]);
+    assert!(
+        stderr.contains("no classes found in java/nothing"),
+        "{stderr}"
+    );
+    assert!(!jdk.path("out").exists());
+}
+
+#[test]
+fn list_modules() {
+    let jdk = FakeJdk::new("list-modules");
+
+    assert_eq!(jdk.run_ok(&["list"]), "java.base\njava.logging\n");
+}
+
+#[test]
+fn list_packages() {
+    let jdk = FakeJdk::new("list-packages");
+
+    assert_eq!(
+        jdk.run_ok(&["list", "java.base"]),
+        "java/lang\njava/util\njava/util/concurrent\n"
+    );
+    assert_eq!(jdk.run_ok(&["list", "java.logging"]), "java/util/logging\n");
+
+    let stderr = jdk.run_err(&["list", "java.nothing"]);
+    assert!(
+        stderr.contains("module not found: java.nothing"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn list_classes() {
+    let jdk = FakeJdk::new("list-classes");
+
+    assert_eq!(
+        jdk.run_ok(&["list", "java.base/java/lang"]),
+        "java/lang/Object\njava/lang/String\n"
+    );
+
+    // Subpackages aren't included, and the package may be written with dots
+    assert_eq!(
+        jdk.run_ok(&["list", "java.base/java.util/"]),
+        "java/util/List\n"
+    );
+
+    // The package is in another module
+    let stderr = jdk.run_err(&["list", "java.base/java/util/logging"]);
+    assert!(
+        stderr.contains("package not found: java.base/java/util/logging"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn extract_classes() {
+    let jdk = FakeJdk::new("extract-classes");
+
+    jdk.run_ok(&["java/lang/String", "java/util/List", "--out", "out"]);
+    assert_eq!(
+        jdk.extracted_classes("out"),
+        ["java/lang/String", "java/util/List"]
+    );
+
+    // Blank lines and comments are skipped
+    fs::write(
+        jdk.path("classes.txt"),
+        "# Loggers\njava/util/logging/Logger\n\n  java/lang/Object  \n",
+    )
+    .unwrap();
+    let stdout = jdk.run_ok(&["java/lang/String", "--list", "classes.txt", "--out", "list"]);
+    assert_eq!(
+        stdout,
+        format!(
+            "Extracted 3 classes from JDK 17.0.99 to {}\n",
+            Path::new("list").display()
+        )
+    );
+    assert_eq!(
+        jdk.extracted_classes("list"),
+        [
+            "java/lang/Object",
+            "java/lang/String",
+            "java/util/logging/Logger"
+        ]
+    );
+}
+
+#[test]
+fn extract_classes_not_found() {
+    let jdk = FakeJdk::new("extract-classes-not-found");
+
+    // The classes which are found are still extracted
+    let stderr = jdk.run_err(&[
+        "java/lang/Missing",
+        "java/lang/Object",
+        "Missing",
+        "--out",
+        "out",
+    ]);
+    assert!(
+        stderr.contains("classes not found: java/lang/Missing, Missing"),
+        "{stderr}"
+    );
+    assert_eq!(jdk.extracted_classes("out"), ["java/lang/Object"]);
+
+    let stderr = jdk.run_err(&["java/lang/Object", "java/util/List"]);
+    assert!(
+        stderr.contains("the directory to write the classes to must be given with --out"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn serve() {
+    let jdk = FakeJdk::new("serve");
+
+    let mut server = Command::new(env!("CARGO_BIN_EXE_jdk-tools"))
+        .current_dir(&jdk.dir)
+        .arg("--java-home")
+        .arg(jdk.dir.join("jdk"))
+        .args(["serve", "--out-dir", "out"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stdin = server.stdin.take().unwrap();
+    let mut stdout = BufReader::new(server.stdout.take().unwrap());
+
+    // Each response is read before sending the next request, like the test harness does
+    let mut request = |request: &[u8]| {
+        stdin.write_all(request).unwrap();
+        stdin.flush().unwrap();
+        let mut response = String::new();
+        stdout.read_line(&mut response).unwrap();
+        response
+    };
+
+    let out_dir = Path::new("out");
+    assert_eq!(
+        request(b"java/lang/String\n"),
+        format!("ok {}\n", out_dir.join("java/lang/String.class").display())
+    );
+    assert_eq!(
+        request(b"java/lang/Missing\n"),
+        "error java/lang/Missing: class not found\n"
+    );
+    assert_eq!(
+        request(b"java/util/\xff\xfe\n"),
+        "error: request is not valid UTF-8\n"
+    );
+
+    // Blank lines don't get a response, and the server still works after the errors
+    assert_eq!(
+        request(b"\n  java/util/List  \n"),
+        format!("ok {}\n", out_dir.join("java/util/List.class").display())
+    );
+
+    drop(stdin);
+    assert!(server.wait().unwrap().success());
+
+    assert_eq!(
+        jdk.extracted_classes("out"),
+        ["java/lang/String", "java/util/List"]
+    );
+    assert!(jdk.path("out/release").is_file());
+}
@@ -0,0 +1,7 @@
+#!/bin/sh
+# Compiles the shims into shims/classes, which are embedded in the vm. This needs to be rerun
+# after changing the sources.
+set -e
+cd "$(dirname "$0")"
+rm -rf classes
+javac --patch-module java.base=src -XDstringConcat=inline -g:none -d classes $(find src -name '*.java')
@@ -0,0 +1,11 @@
+package java.io;
+
+public final class FileDescriptor {
+    private int fd;
+    private long handle;
+
+    public FileDescriptor() {
+        fd = -1;
+        handle = -1;
+    }
+}
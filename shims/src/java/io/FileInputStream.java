@@ -0,0 +1,36 @@
+package java.io;
+
+/**
+ * Only used for the standard input stream, which is created by the vm.
+ */
+public class FileInputStream extends InputStream {
+    private final FileDescriptor fd;
+
+    private FileInputStream(FileDescriptor fd) {
+        this.fd = fd;
+    }
+
+    public int read() {
+        return read0();
+    }
+
+    private native int read0();
+
+    public int read(byte[] b, int off, int len) {
+        return readBytes(b, off, len);
+    }
+
+    private native int readBytes(byte[] b, int off, int len);
+
+    public long skip(long n) {
+        return skip0(n);
+    }
+
+    private native long skip0(long n);
+
+    public int available() {
+        return available0();
+    }
+
+    private native int available0();
+}
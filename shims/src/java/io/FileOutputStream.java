@@ -0,0 +1,24 @@
+package java.io;
+
+/**
+ * Only used for the standard output and error streams, which are created by the vm.
+ */
+public class FileOutputStream extends OutputStream {
+    private final FileDescriptor fd;
+
+    private FileOutputStream(FileDescriptor fd) {
+        this.fd = fd;
+    }
+
+    private native void write(int b, boolean append);
+
+    public native void write(int b);
+
+    private native void writeBytes(byte[] b, int off, int len, boolean append);
+
+    public native void write(byte[] b);
+
+    public native void write(byte[] b, int off, int len);
+
+    public native void flush();
+}
@@ -0,0 +1,17 @@
+package java.io;
+
+public class FilterOutputStream extends OutputStream {
+    protected OutputStream out;
+
+    public FilterOutputStream(OutputStream out) {
+        this.out = out;
+    }
+
+    public void write(int b) {
+        out.write(b);
+    }
+
+    public void flush() {
+        out.flush();
+    }
+}
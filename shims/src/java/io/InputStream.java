@@ -0,0 +1,28 @@
+package java.io;
+
+public abstract class InputStream {
+    public InputStream() {
+    }
+
+    public abstract int read();
+
+    public int read(byte[] b, int off, int len) {
+        int i = 0;
+        for (; i < len; i++) {
+            int c = read();
+            if (c == -1) {
+                break;
+            }
+            b[off + i] = (byte) c;
+        }
+        return i == 0 && len > 0 ? -1 : i;
+    }
+
+    public int read(byte[] b) {
+        return read(b, 0, b.length);
+    }
+
+    public int available() {
+        return 0;
+    }
+}
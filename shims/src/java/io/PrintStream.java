@@ -0,0 +1,155 @@
+package java.io;
+
+/**
+ * Text is encoded as UTF-8, since there are no charsets without a JDK, and written to the
+ * underlying stream.
+ */
+public class PrintStream extends FilterOutputStream {
+    private final boolean autoFlush;
+    private boolean trouble;
+
+    public PrintStream(OutputStream out, boolean autoFlush) {
+        super(out);
+        this.autoFlush = autoFlush;
+    }
+
+    public PrintStream(OutputStream out) {
+        this(out, false);
+    }
+
+    public boolean checkError() {
+        return trouble;
+    }
+
+    public void flush() {
+        if (out != null) {
+            out.flush();
+        }
+    }
+
+    public void write(int b) {
+        if (out == null) {
+            trouble = true;
+            return;
+        }
+        out.write(b);
+        if (autoFlush && b == '\n') {
+            out.flush();
+        }
+    }
+
+    public void write(byte[] buf, int off, int len) {
+        if (out == null) {
+            trouble = true;
+            return;
+        }
+        out.write(buf, off, len);
+        if (autoFlush) {
+            out.flush();
+        }
+    }
+
+    public void print(boolean b) {
+        print(String.valueOf(b));
+    }
+
+    public void print(char c) {
+        print(String.valueOf(c));
+    }
+
+    public void print(int i) {
+        print(String.valueOf(i));
+    }
+
+    public void print(long l) {
+        print(String.valueOf(l));
+    }
+
+    public void print(float f) {
+        print(String.valueOf(f));
+    }
+
+    public void print(double d) {
+        print(String.valueOf(d));
+    }
+
+    public void print(String s) {
+        byte[] bytes = encode(String.valueOf(s));
+        write(bytes, 0, bytes.length);
+    }
+
+    public void print(Object obj) {
+        print(String.valueOf(obj));
+    }
+
+    public void println() {
+        print(System.lineSeparator());
+    }
+
+    public void println(boolean x) {
+        print(String.valueOf(x) + System.lineSeparator());
+    }
+
+    public void println(char x) {
+        print(String.valueOf(x) + System.lineSeparator());
+    }
+
+    public void println(int x) {
+        print(String.valueOf(x) + System.lineSeparator());
+    }
+
+    public void println(long x) {
+        print(String.valueOf(x) + System.lineSeparator());
+    }
+
+    public void println(float x) {
+        print(String.valueOf(x) + System.lineSeparator());
+    }
+
+    public void println(double x) {
+        print(String.valueOf(x) + System.lineSeparator());
+    }
+
+    public void println(String x) {
+        print(String.valueOf(x) + System.lineSeparator());
+    }
+
+    public void println(Object x) {
+        print(String.valueOf(x) + System.lineSeparator());
+    }
+
+    private static byte[] encode(String s) {
+        byte[] bytes = new byte[s.length() * 3];
+        int n = 0;
+        for (int i = 0; i < s.length(); i++) {
+            int c = s.charAt(i);
+            if (c >= 0xD800 && c < 0xDC00 && i + 1 < s.length()) {
+                int low = s.charAt(i + 1);
+                if (low >= 0xDC00 && low < 0xE000) {
+                    c = 0x10000 + ((c - 0xD800) << 10) + (low - 0xDC00);
+                    i++;
+                }
+            }
+
+            if (c < 0x80) {
+                bytes[n++] = (byte) c;
+            } else if (c < 0x800) {
+                bytes[n++] = (byte) (0xC0 | c >> 6);
+                bytes[n++] = (byte) (0x80 | c & 0x3F);
+            } else if (c < 0x10000) {
+                bytes[n++] = (byte) (0xE0 | c >> 12);
+                bytes[n++] = (byte) (0x80 | c >> 6 & 0x3F);
+                bytes[n++] = (byte) (0x80 | c & 0x3F);
+            } else {
+                bytes[n++] = (byte) (0xF0 | c >> 18);
+                bytes[n++] = (byte) (0x80 | c >> 12 & 0x3F);
+                bytes[n++] = (byte) (0x80 | c >> 6 & 0x3F);
+                bytes[n++] = (byte) (0x80 | c & 0x3F);
+            }
+        }
+
+        byte[] result = new byte[n];
+        System.arraycopy(bytes, 0, result, 0, n);
+        return result;
+    }
+}
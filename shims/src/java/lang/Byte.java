@@ -0,0 +1,48 @@
+package java.lang;
+
+public final class Byte extends Number {
+    public static final byte MIN_VALUE = -128;
+    public static final byte MAX_VALUE = 127;
+
+    private final byte value;
+
+    private Byte(byte value) {
+        this.value = value;
+    }
+
+    public static native Byte valueOf(byte b);
+
+    public static String toString(byte b) {
+        return String.valueOf(b);
+    }
+
+    public native byte byteValue();
+
+    public int intValue() {
+        return (int) value;
+    }
+
+    public long longValue() {
+        return (long) value;
+    }
+
+    public float floatValue() {
+        return (float) value;
+    }
+
+    public double doubleValue() {
+        return (double) value;
+    }
+
+    public int hashCode() {
+        return (int) value;
+    }
+
+    public boolean equals(Object obj) {
+        return obj instanceof Byte && value == ((Byte) obj).byteValue();
+    }
+
+    public String toString() {
+        return toString(value);
+    }
+}
@@ -0,0 +1,32 @@
+package java.lang;
+
+public final class Character {
+    public static final char MIN_VALUE = '\u0000';
+    public static final char MAX_VALUE = '\uffff';
+
+    private final char value;
+
+    private Character(char value) {
+        this.value = value;
+    }
+
+    public static native Character valueOf(char c);
+
+    public static String toString(char c) {
+        return String.valueOf(c);
+    }
+
+    public native char charValue();
+
+    public int hashCode() {
+        return value;
+    }
+
+    public boolean equals(Object obj) {
+        return obj instanceof Character && value == ((Character) obj).charValue();
+    }
+
+    public String toString() {
+        return toString(value);
+    }
+}
@@ -0,0 +1,29 @@
+package java.lang;
+
+public final class Class<T> {
+    private Class() {
+    }
+
+    public static native Class<?> forName(String className);
+
+    static native Class<?> getPrimitiveClass(String name);
+
+    public native String getName();
+
+    public native String getSimpleName();
+
+    public native boolean isInterface();
+
+    public native boolean isArray();
+
+    public native boolean isPrimitive();
+
+    public native Class<?> getComponentType();
+
+    public native boolean desiredAssertionStatus();
+
+    public String toString() {
+        String kind = isInterface() ? "interface " : isPrimitive() ? "" : "class ";
+        return kind + getName();
+    }
+}
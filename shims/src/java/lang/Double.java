@@ -0,0 +1,41 @@
+package java.lang;
+
+public final class Double extends Number {
+    private final double value;
+
+    private Double(double value) {
+        this.value = value;
+    }
+
+    public static Double valueOf(double d) {
+        return new Double(d);
+    }
+
+    public static String toString(double d) {
+        return String.valueOf(d);
+    }
+
+    public static native long doubleToRawLongBits(double value);
+
+    public static native double longBitsToDouble(long bits);
+
+    public int intValue() {
+        return (int) value;
+    }
+
+    public long longValue() {
+        return (long) value;
+    }
+
+    public float floatValue() {
+        return (float) value;
+    }
+
+    public double doubleValue() {
+        return (double) value;
+    }
+
+    public String toString() {
+        return toString(value);
+    }
+}
@@ -0,0 +1,44 @@
+package java.lang;
+
+public final class Integer extends Number {
+    public static final int MIN_VALUE = 0x80000000;
+    public static final int MAX_VALUE = 0x7fffffff;
+
+    private final int value;
+
+    private Integer(int value) {
+        this.value = value;
+    }
+
+    public static native Integer valueOf(int i);
+
+    public static String toString(int i) {
+        return String.valueOf(i);
+    }
+
+    public native int intValue();
+
+    public long longValue() {
+        return (long) value;
+    }
+
+    public float floatValue() {
+        return (float) value;
+    }
+
+    public double doubleValue() {
+        return (double) value;
+    }
+
+    public int hashCode() {
+        return value;
+    }
+
+    public boolean equals(Object obj) {
+        return obj instanceof Integer && value == ((Integer) obj).intValue();
+    }
+
+    public String toString() {
+        return toString(value);
+    }
+}
@@ -0,0 +1,44 @@
+package java.lang;
+
+public final class Long extends Number {
+    public static final long MIN_VALUE = 0x8000000000000000L;
+    public static final long MAX_VALUE = 0x7fffffffffffffffL;
+
+    private final long value;
+
+    private Long(long value) {
+        this.value = value;
+    }
+
+    public static native Long valueOf(long l);
+
+    public static String toString(long l) {
+        return String.valueOf(l);
+    }
+
+    public native long longValue();
+
+    public int intValue() {
+        return (int) value;
+    }
+
+    public float floatValue() {
+        return (float) value;
+    }
+
+    public double doubleValue() {
+        return (double) value;
+    }
+
+    public int hashCode() {
+        return (int) (value ^ (value >>> 32));
+    }
+
+    public boolean equals(Object obj) {
+        return obj instanceof Long && value == ((Long) obj).longValue();
+    }
+
+    public String toString() {
+        return toString(value);
+    }
+}
@@ -0,0 +1,48 @@
+package java.lang;
+
+public final class Short extends Number {
+    public static final short MIN_VALUE = -32768;
+    public static final short MAX_VALUE = 32767;
+
+    private final short value;
+
+    private Short(short value) {
+        this.value = value;
+    }
+
+    public static native Short valueOf(short s);
+
+    public static String toString(short s) {
+        return String.valueOf(s);
+    }
+
+    public native short shortValue();
+
+    public int intValue() {
+        return (int) value;
+    }
+
+    public long longValue() {
+        return (long) value;
+    }
+
+    public float floatValue() {
+        return (float) value;
+    }
+
+    public double doubleValue() {
+        return (double) value;
+    }
+
+    public int hashCode() {
+        return (int) value;
+    }
+
+    public boolean equals(Object obj) {
+        return obj instanceof Short && value == ((Short) obj).shortValue();
+    }
+
+    public String toString() {
+        return toString(value);
+    }
+}
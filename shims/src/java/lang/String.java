@@ -0,0 +1,52 @@
+package java.lang;
+
+/**
+ * Strings are represented by the vm itself, so every method is either native or implemented in
+ * terms of other strings.
+ */
+public final class String implements CharSequence {
+    private String() {
+    }
+
+    public native int length();
+
+    public native boolean isEmpty();
+
+    public native char charAt(int index);
+
+    public native boolean equals(Object anObject);
+
+    public native int hashCode();
+
+    public native String toString();
+
+    public native String intern();
+
+    public static String valueOf(Object obj) {
+        return obj == null ? "null" : obj.toString();
+    }
+
+    public static String valueOf(boolean b) {
+        return new StringBuilder().append(b).toString();
+    }
+
+    public static String valueOf(char c) {
+        return new StringBuilder().append(c).toString();
+    }
+
+    public static String valueOf(int i) {
+        return new StringBuilder().append(i).toString();
+    }
+
+    public static String valueOf(long l) {
+        return new StringBuilder().append(l).toString();
+    }
+
+    public static String valueOf(float f) {
+        return new StringBuilder().append(f).toString();
+    }
+
+    public static String valueOf(double d) {
+        return new StringBuilder().append(d).toString();
+    }
+}
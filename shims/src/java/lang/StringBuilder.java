@@ -0,0 +1,47 @@
+package java.lang;
+
+/**
+ * The contents are kept by the vm, so this requires the StringBuilder intrinsic to be enabled.
+ */
+public final class StringBuilder implements CharSequence {
+    // Constructors can't be declared native, but these are replaced by the vm too
+    public StringBuilder() {
+    }
+
+    public StringBuilder(int capacity) {
+    }
+
+    public StringBuilder(String str) {
+    }
+
+    public StringBuilder(CharSequence seq) {
+    }
+
+    public native StringBuilder append(Object obj);
+
+    public native StringBuilder append(String str);
+
+    public native StringBuilder append(CharSequence s);
+
+    public native StringBuilder append(char[] str);
+
+    public native StringBuilder append(boolean b);
+
+    public native StringBuilder append(char c);
+
+    public native StringBuilder append(int i);
+
+    public native StringBuilder append(long lng);
+
+    public native StringBuilder append(float f);
+
+    public native StringBuilder append(double d);
+
+    public native int length();
+
+    public native char charAt(int index);
+
+    public native void setLength(int newLength);
+
+    public native String toString();
+}
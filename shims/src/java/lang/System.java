@@ -0,0 +1,36 @@
+package java.lang;
+
+import java.io.InputStream;
+import java.io.PrintStream;
+
+/**
+ * The standard streams are created by the vm once this class is initialized.
+ */
+public final class System {
+    public static final InputStream in = null;
+    public static final PrintStream out = null;
+    public static final PrintStream err = null;
+
+    private System() {
+    }
+
+    public static native long currentTimeMillis();
+
+    public static native long nanoTime();
+
+    public static native void arraycopy(Object src, int srcPos, Object dest, int destPos, int length);
+
+    public static native int identityHashCode(Object x);
+
+    public static native String getProperty(String key);
+
+    public static native String getProperty(String key, String def);
+
+    public static native String setProperty(String key, String value);
+
+    public static native String clearProperty(String key);
+
+    public static native String lineSeparator();
+
+    public static native void exit(int status);
+}
@@ -0,0 +1,41 @@
+package java.lang;
+
+public class Throwable {
+    private String detailMessage;
+    private Throwable cause = this;
+
+    public Throwable() {
+    }
+
+    public Throwable(String message) {
+        detailMessage = message;
+    }
+
+    public Throwable(String message, Throwable cause) {
+        detailMessage = message;
+        this.cause = cause;
+    }
+
+    public Throwable(Throwable cause) {
+        detailMessage = cause == null ? null : cause.toString();
+        this.cause = cause;
+    }
+
+    public String getMessage() {
+        return detailMessage;
+    }
+
+    public String getLocalizedMessage() {
+        return getMessage();
+    }
+
+    public Throwable getCause() {
+        return cause == this ? null : cause;
+    }
+
+    public String toString() {
+        String name = getClass().getName();
+        String message = getLocalizedMessage();
+        return message != null ? name + ": " + message : name;
+    }
+}
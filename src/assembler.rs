@@ -0,0 +1,609 @@
+//! Builds class files from instructions, so that small classes can be generated without javac.
+//! Constants are added to the constant pool as they're needed, and branches are converted from
+//! instruction offsets to bytecode offsets.
+
+use bumpalo::collections::Vec;
+use bumpalo::{vec, Bump};
+
+use crate::class_file::constant_pool::{self, ConstantInfo, ConstantPool};
+use crate::class_file::{
+    AttributeInfo, ClassAccessFlags, ClassFile, CodeAttribute, FieldAccessFlags, FieldInfo,
+    MethodAccessFlags, MethodInfo,
+};
+use crate::error::{bail, format_err, Context, Result};
+use crate::instructions::{
+    ArrayLoadStoreType, Condition, EqCondition, Instruction, IntegerType, InvokeKind,
+    LoadStoreType, NumberType, OrdCondition, ReturnType,
+};
+use crate::opcodes::OpCode;
+
+pub struct ClassBuilder<'a> {
+    arena: &'a Bump,
+    constant_pool: ConstantPoolBuilder<'a>,
+    access_flags: ClassAccessFlags,
+    this_class: u16,
+    super_class: u16,
+    interfaces: Vec<'a, u16>,
+    fields: Vec<'a, FieldInfo<'a>>,
+    methods: Vec<'a, MethodInfo<'a>>,
+}
+
+impl<'a> ClassBuilder<'a> {
+    /// Creates a builder for a public class with the given binary name, which extends
+    /// `java/lang/Object`.
+    pub fn new(arena: &'a Bump, name: &str) -> ClassBuilder<'a> {
+        let mut constant_pool = ConstantPoolBuilder::new(arena);
+        let this_class = constant_pool.class(name);
+        let super_class = constant_pool.class("java/lang/Object");
+
+        ClassBuilder {
+            arena,
+            constant_pool,
+            access_flags: ClassAccessFlags::PUBLIC | ClassAccessFlags::SUPER,
+            this_class,
+            super_class,
+            interfaces: vec![in arena],
+            fields: vec![in arena],
+            methods: vec![in arena],
+        }
+    }
+
+    pub fn access_flags(&mut self, access_flags: ClassAccessFlags) -> &mut Self {
+        self.access_flags = access_flags;
+        self
+    }
+
+    pub fn super_class(&mut self, name: &str) -> &mut Self {
+        self.super_class = self.constant_pool.class(name);
+        self
+    }
+
+    pub fn interface(&mut self, name: &str) -> &mut Self {
+        let index = self.constant_pool.class(name);
+        self.interfaces.push(index);
+        self
+    }
+
+    /// Returns the constant pool, for adding the constants that instructions refer to.
+    pub fn constant_pool(&mut self) -> &mut ConstantPoolBuilder<'a> {
+        &mut self.constant_pool
+    }
+
+    pub fn field(
+        &mut self,
+        access_flags: FieldAccessFlags,
+        name: &str,
+        descriptor: &str,
+    ) -> &mut Self {
+        let field = FieldInfo {
+            access_flags,
+            name_index: self.constant_pool.utf8(name),
+            descriptor_index: self.constant_pool.utf8(descriptor),
+            attributes: vec![in self.arena],
+        };
+        self.fields.push(field);
+        self
+    }
+
+    /// Adds a method with the given code. Branches in the code are relative to the index of the
+    /// instruction, like in decoded instructions.
+    pub fn method(
+        &mut self,
+        access_flags: MethodAccessFlags,
+        name: &str,
+        descriptor: &str,
+        max_stack: u16,
+        max_locals: u16,
+        code: &[Instruction],
+    ) -> Result<&mut Self> {
+        let code = encode_instructions(code)
+            .wrap_err_with(|| format_err!("failed to encode method {name}{descriptor}"))?;
+
+        let code = CodeAttribute {
+            max_stack,
+            max_locals,
+            code: self.arena.alloc_slice_copy(&code),
+            exception_table: vec![in self.arena],
+            attributes: vec![in self.arena],
+        };
+
+        // The writer finds the names of attributes in the constant pool
+        self.constant_pool.utf8("Code");
+
+        let method = MethodInfo {
+            access_flags,
+            name_index: self.constant_pool.utf8(name),
+            descriptor_index: self.constant_pool.utf8(descriptor),
+            attributes: vec![in self.arena; AttributeInfo::Code(code)],
+        };
+        self.methods.push(method);
+
+        Ok(self)
+    }
+
+    /// Adds a method without code, which must be abstract or native.
+    pub fn method_without_code(
+        &mut self,
+        access_flags: MethodAccessFlags,
+        name: &str,
+        descriptor: &str,
+    ) -> &mut Self {
+        let method = MethodInfo {
+            access_flags,
+            name_index: self.constant_pool.utf8(name),
+            descriptor_index: self.constant_pool.utf8(descriptor),
+            attributes: vec![in self.arena],
+        };
+        self.methods.push(method);
+        self
+    }
+
+    pub fn build(self) -> ClassFile<'a> {
+        ClassFile {
+            // Java 8, which is the newest version that doesn't require stack map frames
+            minor_version: 0,
+            major_version: 52,
+            constant_pool: ConstantPool(self.constant_pool.constants),
+            access_flags: self.access_flags,
+            this_class: self.this_class,
+            super_class: self.super_class,
+            interfaces: self.interfaces,
+            fields: self.fields,
+            methods: self.methods,
+            attributes: vec![in self.arena],
+        }
+    }
+}
+
+/// Adds constants to a constant pool, reusing existing constants where possible. Each method
+/// returns the index of the constant.
+pub struct ConstantPoolBuilder<'a> {
+    arena: &'a Bump,
+    constants: Vec<'a, ConstantInfo<'a>>,
+}
+
+impl<'a> ConstantPoolBuilder<'a> {
+    fn new(arena: &'a Bump) -> ConstantPoolBuilder<'a> {
+        ConstantPoolBuilder {
+            arena,
+            constants: vec![in arena],
+        }
+    }
+
+    pub fn utf8(&mut self, string: &str) -> u16 {
+        // Checked first to avoid copying the string if it's already there
+        if let Some(index) = self
+            .constants
+            .iter()
+            .position(|c| *c == ConstantInfo::Utf8(string))
+        {
+            return index as u16 + 1;
+        }
+
+        let string = self.arena.alloc_str(string);
+        self.add(ConstantInfo::Utf8(string))
+    }
+
+    pub fn integer(&mut self, value: i32) -> u16 {
+        self.add(ConstantInfo::Integer(value))
+    }
+
+    pub fn float(&mut self, value: f32) -> u16 {
+        self.add(ConstantInfo::Float(value))
+    }
+
+    pub fn long(&mut self, value: i64) -> u16 {
+        self.add(ConstantInfo::Long(value))
+    }
+
+    pub fn double(&mut self, value: f64) -> u16 {
+        self.add(ConstantInfo::Double(value))
+    }
+
+    pub fn class(&mut self, name: &str) -> u16 {
+        let name_index = self.utf8(name);
+        self.add(ConstantInfo::Class(constant_pool::Class { name_index }))
+    }
+
+    pub fn string(&mut self, string: &str) -> u16 {
+        let string_index = self.utf8(string);
+        self.add(ConstantInfo::String(constant_pool::String { string_index }))
+    }
+
+    pub fn name_and_type(&mut self, name: &str, descriptor: &str) -> u16 {
+        let name_index = self.utf8(name);
+        let descriptor_index = self.utf8(descriptor);
+        self.add(ConstantInfo::NameAndType(constant_pool::NameAndType {
+            name_index,
+            descriptor_index,
+        }))
+    }
+
+    pub fn field_ref(&mut self, class: &str, name: &str, descriptor: &str) -> u16 {
+        let class_index = self.class(class);
+        let name_and_type_index = self.name_and_type(name, descriptor);
+        self.add(ConstantInfo::FieldRef(constant_pool::FieldRef {
+            class_index,
+            name_and_type_index,
+        }))
+    }
+
+    pub fn method_ref(&mut self, class: &str, name: &str, descriptor: &str) -> u16 {
+        let class_index = self.class(class);
+        let name_and_type_index = self.name_and_type(name, descriptor);
+        self.add(ConstantInfo::MethodRef(constant_pool::MethodRef {
+            class_index,
+            name_and_type_index,
+        }))
+    }
+
+    pub fn interface_method_ref(&mut self, class: &str, name: &str, descriptor: &str) -> u16 {
+        let class_index = self.class(class);
+        let name_and_type_index = self.name_and_type(name, descriptor);
+        self.add(ConstantInfo::InterfaceMethodRef(constant_pool::MethodRef {
+            class_index,
+            name_and_type_index,
+        }))
+    }
+
+    fn add(&mut self, constant: ConstantInfo<'a>) -> u16 {
+        if let Some(index) = self.constants.iter().position(|c| *c == constant) {
+            return index as u16 + 1;
+        }
+
+        let is_wide = matches!(constant, ConstantInfo::Long(_) | ConstantInfo::Double(_));
+
+        self.constants.push(constant);
+        let index = self.constants.len() as u16;
+
+        // Longs and doubles take up two entries
+        if is_wide {
+            self.constants.push(ConstantInfo::Unused);
+        }
+
+        index
+    }
+}
+
+/// Encodes instructions into bytecode, the inverse of
+/// [`decode_instructions`](crate::class::decode_instructions). Branches are converted from
+/// instruction offsets to bytecode offsets.
+pub fn encode_instructions(instructions: &[Instruction]) -> Result<std::vec::Vec<u8>> {
+    // The size of each instruction doesn't depend on its branch, so the offsets of all of the
+    // instructions can be found before encoding any of them
+    let mut offsets = std::vec::Vec::with_capacity(instructions.len());
+    let mut len = 0;
+    for instruction in instructions {
+        offsets.push(len);
+        len += encoded_len(instruction)?;
+    }
+
+    let mut bytes = std::vec::Vec::with_capacity(len);
+
+    for (i, instruction) in instructions.iter().enumerate() {
+        let branch_offset = |branch: isize| -> Result<i32> {
+            let target = i
+                .checked_add_signed(branch)
+                .filter(|&target| target < instructions.len())
+                .ok_or_else(|| format_err!("invalid branch target at instruction {i}"))?;
+            Ok((offsets[target] as isize - offsets[i] as isize) as i32)
+        };
+
+        let branch16 = |branch: isize| -> Result<[u8; 2]> {
+            let offset = i16::try_from(branch_offset(branch)?)
+                .wrap_err_with(|| format_err!("branch at instruction {i} is too far"))?;
+            Ok(offset.to_be_bytes())
+        };
+
+        match instruction {
+            Instruction::nop => bytes.push(OpCode::nop as u8),
+            Instruction::aconst_null => bytes.push(OpCode::aconst_null as u8),
+            Instruction::r#const { data_type, value } => {
+                let (first, min, max) = match data_type {
+                    NumberType::Int => (OpCode::iconst_m1, -1, 5),
+                    NumberType::Long => (OpCode::lconst_0, 0, 1),
+                    NumberType::Float => (OpCode::fconst_0, 0, 2),
+                    NumberType::Double => (OpCode::dconst_0, 0, 1),
+                };
+                if !(min..=max).contains(value) {
+                    bail!("invalid {data_type:?} constant: {value}");
+                }
+                bytes.push(first as u8 + (value - min) as u8);
+            }
+            Instruction::bipush { value } => {
+                bytes.push(OpCode::bipush as u8);
+                bytes.push(*value as u8);
+            }
+            Instruction::sipush { value } => {
+                bytes.push(OpCode::sipush as u8);
+                bytes.extend(value.to_be_bytes());
+            }
+            Instruction::ldc { index } => match u8::try_from(*index) {
+                Ok(index) => bytes.extend([OpCode::ldc as u8, index]),
+                Err(_) => {
+                    bytes.push(OpCode::ldc_w as u8);
+                    bytes.extend(index.to_be_bytes());
+                }
+            },
+            Instruction::ldc2 { index } => {
+                bytes.push(OpCode::ldc2_w as u8);
+                bytes.extend(index.to_be_bytes());
+            }
+            Instruction::load { data_type, index } => {
+                let (opcode, first) = match data_type {
+                    LoadStoreType::Int => (OpCode::iload, OpCode::iload_0),
+                    LoadStoreType::Long => (OpCode::lload, OpCode::lload_0),
+                    LoadStoreType::Float => (OpCode::fload, OpCode::fload_0),
+                    LoadStoreType::Double => (OpCode::dload, OpCode::dload_0),
+                    LoadStoreType::Reference => (OpCode::aload, OpCode::aload_0),
+                };
+                encode_local(&mut bytes, opcode, first, *index);
+            }
+            Instruction::arrayload { data_type } => {
+                bytes.push(OpCode::iaload as u8 + array_type_offset(data_type));
+            }
+            Instruction::store { data_type, index } => {
+                let (opcode, first) = match data_type {
+                    LoadStoreType::Int => (OpCode::istore, OpCode::istore_0),
+                    LoadStoreType::Long => (OpCode::lstore, OpCode::lstore_0),
+                    LoadStoreType::Float => (OpCode::fstore, OpCode::fstore_0),
+                    LoadStoreType::Double => (OpCode::dstore, OpCode::dstore_0),
+                    LoadStoreType::Reference => (OpCode::astore, OpCode::astore_0),
+                };
+                encode_local(&mut bytes, opcode, first, *index);
+            }
+            Instruction::arraystore { data_type } => {
+                bytes.push(OpCode::iastore as u8 + array_type_offset(data_type));
+            }
+            Instruction::pop => bytes.push(OpCode::pop as u8),
+            Instruction::pop2 => bytes.push(OpCode::pop2 as u8),
+            Instruction::dup => bytes.push(OpCode::dup as u8),
+            Instruction::dup_x1 => bytes.push(OpCode::dup_x1 as u8),
+            Instruction::dup_x2 => bytes.push(OpCode::dup_x2 as u8),
+            Instruction::dup2 => bytes.push(OpCode::dup2 as u8),
+            Instruction::dup2_x1 => bytes.push(OpCode::dup2_x1 as u8),
+            Instruction::dup2_x2 => bytes.push(OpCode::dup2_x2 as u8),
+            Instruction::swap => bytes.push(OpCode::swap as u8),
+            Instruction::add { data_type } => {
+                bytes.push(OpCode::iadd as u8 + number_type_offset(data_type))
+            }
+            Instruction::sub { data_type } => {
+                bytes.push(OpCode::isub as u8 + number_type_offset(data_type))
+            }
+            Instruction::mul { data_type } => {
+                bytes.push(OpCode::imul as u8 + number_type_offset(data_type))
+            }
+            Instruction::div { data_type } => {
+                bytes.push(OpCode::idiv as u8 + number_type_offset(data_type))
+            }
+            Instruction::rem { data_type } => {
+                bytes.push(OpCode::irem as u8 + number_type_offset(data_type))
+            }
+            Instruction::neg { data_type } => {
+                bytes.push(OpCode::ineg as u8 + number_type_offset(data_type))
+            }
+            Instruction::shl { data_type } => {
+                bytes.push(OpCode::ishl as u8 + integer_type_offset(data_type))
+            }
+            Instruction::shr { data_type } => {
+                bytes.push(OpCode::ishr as u8 + integer_type_offset(data_type))
+            }
+            Instruction::ushr { data_type } => {
+                bytes.push(OpCode::iushr as u8 + integer_type_offset(data_type))
+            }
+            Instruction::and { data_type } => {
+                bytes.push(OpCode::iand as u8 + integer_type_offset(data_type))
+            }
+            Instruction::or { data_type } => {
+                bytes.push(OpCode::ior as u8 + integer_type_offset(data_type))
+            }
+            Instruction::xor { data_type } => {
+                bytes.push(OpCode::ixor as u8 + integer_type_offset(data_type))
+            }
+            Instruction::inc { index, value } => {
+                bytes.extend([OpCode::iinc as u8, *index, *value as u8]);
+            }
+            Instruction::i2l => bytes.push(OpCode::i2l as u8),
+            Instruction::i2f => bytes.push(OpCode::i2f as u8),
+            Instruction::i2d => bytes.push(OpCode::i2d as u8),
+            Instruction::l2i => bytes.push(OpCode::l2i as u8),
+            Instruction::l2f => bytes.push(OpCode::l2f as u8),
+            Instruction::l2d => bytes.push(OpCode::l2d as u8),
+            Instruction::f2i => bytes.push(OpCode::f2i as u8),
+            Instruction::f2l => bytes.push(OpCode::f2l as u8),
+            Instruction::f2d => bytes.push(OpCode::f2d as u8),
+            Instruction::d2i => bytes.push(OpCode::d2i as u8),
+            Instruction::d2l => bytes.push(OpCode::d2l as u8),
+            Instruction::d2f => bytes.push(OpCode::d2f as u8),
+            Instruction::i2b => bytes.push(OpCode::i2b as u8),
+            Instruction::i2c => bytes.push(OpCode::i2c as u8),
+            Instruction::i2s => bytes.push(OpCode::i2s as u8),
+            Instruction::lcmp => bytes.push(OpCode::lcmp as u8),
+            Instruction::fcmp { condition } => bytes.push(match condition {
+                OrdCondition::Lt => OpCode::fcmpl as u8,
+                OrdCondition::Gt => OpCode::fcmpg as u8,
+            }),
+            Instruction::dcmp { condition } => bytes.push(match condition {
+                OrdCondition::Lt => OpCode::dcmpl as u8,
+                OrdCondition::Gt => OpCode::dcmpg as u8,
+            }),
+            Instruction::r#if { condition, branch } => {
+                bytes.push(OpCode::ifeq as u8 + condition_offset(condition));
+                bytes.extend(branch16(*branch as isize)?);
+            }
+            Instruction::if_icmp { condition, branch } => {
+                bytes.push(OpCode::if_icmpeq as u8 + condition_offset(condition));
+                bytes.extend(branch16(*branch as isize)?);
+            }
+            Instruction::if_acmp { condition, branch } => {
+                bytes.push(match condition {
+                    EqCondition::Eq => OpCode::if_acmpeq as u8,
+                    EqCondition::Ne => OpCode::if_acmpne as u8,
+                });
+                bytes.extend(branch16(*branch as isize)?);
+            }
+            Instruction::getstatic { index } => encode_index(&mut bytes, OpCode::getstatic, *index),
+            Instruction::putstatic { index } => encode_index(&mut bytes, OpCode::putstatic, *index),
+            Instruction::getfield { index } => encode_index(&mut bytes, OpCode::getfield, *index),
+            Instruction::putfield { index } => encode_index(&mut bytes, OpCode::putfield, *index),
+            Instruction::invoke { kind, index } => match kind {
+                InvokeKind::Virtual => encode_index(&mut bytes, OpCode::invokevirtual, *index),
+                InvokeKind::Special => encode_index(&mut bytes, OpCode::invokespecial, *index),
+                InvokeKind::Static => encode_index(&mut bytes, OpCode::invokestatic, *index),
+                InvokeKind::Interface { count } => {
+                    encode_index(&mut bytes, OpCode::invokeinterface, *index);
+                    bytes.extend([count.get(), 0]);
+                }
+                InvokeKind::Dynamic => {
+                    encode_index(&mut bytes, OpCode::invokedynamic, *index);
+                    bytes.extend([0, 0]);
+                }
+            },
+            Instruction::new { index } => encode_index(&mut bytes, OpCode::new, *index),
+            Instruction::newarray { atype } => bytes.extend([OpCode::newarray as u8, *atype as u8]),
+            Instruction::anewarray { index } => encode_index(&mut bytes, OpCode::anewarray, *index),
+            Instruction::arraylength => bytes.push(OpCode::arraylength as u8),
+            Instruction::athrow => bytes.push(OpCode::athrow as u8),
+            Instruction::checkcast { index } => encode_index(&mut bytes, OpCode::checkcast, *index),
+            Instruction::instanceof { index } => {
+                encode_index(&mut bytes, OpCode::instanceof, *index)
+            }
+            Instruction::monitorenter => bytes.push(OpCode::monitorenter as u8),
+            Instruction::monitorexit => bytes.push(OpCode::monitorexit as u8),
+            // The wide forms are always used, so that the size doesn't depend on the branch
+            Instruction::goto { branch } => {
+                bytes.push(OpCode::goto_w as u8);
+                bytes.extend(branch_offset(*branch as isize)?.to_be_bytes());
+            }
+            Instruction::jsr { branch } => {
+                bytes.push(OpCode::jsr_w as u8);
+                bytes.extend(branch_offset(*branch as isize)?.to_be_bytes());
+            }
+            Instruction::ret { index } => bytes.extend([OpCode::ret as u8, *index]),
+            Instruction::r#return { data_type } => bytes.push(match data_type {
+                ReturnType::Int => OpCode::ireturn as u8,
+                ReturnType::Long => OpCode::lreturn as u8,
+                ReturnType::Float => OpCode::freturn as u8,
+                ReturnType::Double => OpCode::dreturn as u8,
+                ReturnType::Reference => OpCode::areturn as u8,
+                ReturnType::Void => OpCode::r#return as u8,
+            }),
+            Instruction::multianewarray { index, dimensions } => {
+                encode_index(&mut bytes, OpCode::multianewarray, *index);
+                bytes.push(*dimensions);
+            }
+            Instruction::ifnull { branch } => {
+                bytes.push(OpCode::ifnull as u8);
+                bytes.extend(branch16(*branch as isize)?);
+            }
+            Instruction::ifnonnull { branch } => {
+                bytes.push(OpCode::ifnonnull as u8);
+                bytes.extend(branch16(*branch as isize)?);
+            }
+            Instruction::tableswitch {}
+            | Instruction::lookupswitch {}
+            | Instruction::breakpoint
+            | Instruction::impdep1
+            | Instruction::impdep2
+            | Instruction::unknown { .. } => bail!("can't encode instruction: {instruction:?}"),
+        }
+    }
+
+    Ok(bytes)
+}
+
+/// Returns the number of bytes that an instruction is encoded as.
+fn encoded_len(instruction: &Instruction) -> Result<usize> {
+    Ok(match instruction {
+        Instruction::bipush { .. } | Instruction::newarray { .. } | Instruction::ret { .. } => 2,
+        Instruction::ldc { index } if *index <= u8::MAX as u16 => 2,
+        Instruction::load { index, .. } | Instruction::store { index, .. } if *index > 3 => 2,
+        Instruction::sipush { .. }
+        | Instruction::ldc { .. }
+        | Instruction::ldc2 { .. }
+        | Instruction::inc { .. }
+        | Instruction::r#if { .. }
+        | Instruction::if_icmp { .. }
+        | Instruction::if_acmp { .. }
+        | Instruction::getstatic { .. }
+        | Instruction::putstatic { .. }
+        | Instruction::getfield { .. }
+        | Instruction::putfield { .. }
+        | Instruction::new { .. }
+        | Instruction::anewarray { .. }
+        | Instruction::checkcast { .. }
+        | Instruction::instanceof { .. }
+        | Instruction::ifnull { .. }
+        | Instruction::ifnonnull { .. } => 3,
+        Instruction::invoke {
+            kind: InvokeKind::Interface { .. } | InvokeKind::Dynamic,
+            ..
+        } => 5,
+        Instruction::invoke { .. } => 3,
+        Instruction::multianewarray { .. } => 4,
+        Instruction::goto { .. } | Instruction::jsr { .. } => 5,
+        Instruction::tableswitch {}
+        | Instruction::lookupswitch {}
+        | Instruction::breakpoint
+        | Instruction::impdep1
+        | Instruction::impdep2
+        | Instruction::unknown { .. } => bail!("can't encode instruction: {instruction:?}"),
+        _ => 1,
+    })
+}
+
+/// Encodes a load or store, using the short form without an operand for the first 4 locals.
+fn encode_local(bytes: &mut std::vec::Vec<u8>, opcode: OpCode, first: OpCode, index: u8) {
+    if index <= 3 {
+        bytes.push(first as u8 + index);
+    } else {
+        bytes.extend([opcode as u8, index]);
+    }
+}
+
+fn encode_index(bytes: &mut std::vec::Vec<u8>, opcode: OpCode, index: u16) {
+    bytes.push(opcode as u8);
+    bytes.extend(index.to_be_bytes());
+}
+
+// The opcodes for each type of an operation are consecutive, in the order of these enums
+
+fn number_type_offset(data_type: &NumberType) -> u8 {
+    match data_type {
+        NumberType::Int => 0,
+        NumberType::Long => 1,
+        NumberType::Float => 2,
+        NumberType::Double => 3,
+    }
+}
+
+fn integer_type_offset(data_type: &IntegerType) -> u8 {
+    match data_type {
+        IntegerType::Int => 0,
+        IntegerType::Long => 1,
+    }
+}
+
+fn array_type_offset(data_type: &ArrayLoadStoreType) -> u8 {
+    match data_type {
+        ArrayLoadStoreType::Int => 0,
+        ArrayLoadStoreType::Long => 1,
+        ArrayLoadStoreType::Float => 2,
+        ArrayLoadStoreType::Double => 3,
+        ArrayLoadStoreType::Reference => 4,
+        ArrayLoadStoreType::Byte => 5,
+        ArrayLoadStoreType::Char => 6,
+        ArrayLoadStoreType::Short => 7,
+    }
+}
+
+fn condition_offset(condition: &Condition) -> u8 {
+    match condition {
+        Condition::Eq => 0,
+        Condition::Ne => 1,
+        Condition::Lt => 2,
+        Condition::Ge => 3,
+        Condition::Gt => 4,
+        Condition::Le => 5,
+    }
+}
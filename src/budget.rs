@@ -0,0 +1,127 @@
+//! Limits on the work done by Java code, so that untrusted classes can be run from a host
+//! application without hanging it or exhausting its memory.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The limits of a vm's budget, which are all unlimited by default.
+#[derive(Clone, Debug, Default)]
+pub struct Budget {
+    /// The number of instructions which can be executed, counting all threads.
+    pub max_instructions: Option<u64>,
+    /// How long Java code can run for, starting from its first instruction.
+    pub max_time: Option<Duration>,
+    /// The total size in bytes of the objects and arrays which can be allocated, including those
+    /// which are no longer reachable.
+    pub max_allocated_bytes: Option<usize>,
+}
+
+/// Raised when Java code exceeds the vm's budget, to abort execution. Java code can't catch this.
+#[derive(Debug)]
+pub struct BudgetExceeded {
+    pub limit: Limit,
+}
+
+/// A limit of a [`Budget`], along with its value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limit {
+    Instructions(u64),
+    Time(Duration),
+    AllocatedBytes(usize),
+}
+
+impl fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.limit {
+            Limit::Instructions(max) => write!(f, "exceeded the limit of {max} instructions"),
+            Limit::Time(max) => write!(f, "exceeded the time limit of {max:?}"),
+            Limit::AllocatedBytes(max) => write!(f, "exceeded the limit of {max} allocated bytes"),
+        }
+    }
+}
+
+impl std::error::Error for BudgetExceeded {}
+
+/// The clock is only read once per this many instructions, since reading it takes much longer
+/// than executing an instruction.
+const TIME_CHECK_INTERVAL: u64 = 1024;
+
+/// The work counted against a budget so far.
+#[derive(Debug, Default)]
+pub(crate) struct BudgetUsage {
+    budget: Budget,
+    instructions: AtomicU64,
+    allocated_bytes: AtomicUsize,
+    /// When the first instruction was executed.
+    started: Mutex<Option<Instant>>,
+}
+
+impl BudgetUsage {
+    pub fn new(budget: Budget) -> BudgetUsage {
+        BudgetUsage {
+            budget,
+            ..BudgetUsage::default()
+        }
+    }
+
+    /// Counts an executed instruction, failing if the instruction or time limit is exceeded.
+    pub fn record_instruction(&self) -> Result<(), BudgetExceeded> {
+        let instructions = self.instructions.fetch_add(1, Ordering::Relaxed) + 1;
+
+        if let Some(max) = self
+            .budget
+            .max_instructions
+            .filter(|max| instructions > *max)
+        {
+            return Err(BudgetExceeded {
+                limit: Limit::Instructions(max),
+            });
+        }
+
+        if let Some(max) = self.budget.max_time {
+            if instructions == 1 {
+                *self.started.lock().unwrap() = Some(Instant::now());
+            } else if instructions % TIME_CHECK_INTERVAL == 0 {
+                let started = *self
+                    .started
+                    .lock()
+                    .unwrap()
+                    .get_or_insert_with(Instant::now);
+
+                if started.elapsed() > max {
+                    return Err(BudgetExceeded {
+                        limit: Limit::Time(max),
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Counts an allocation, failing if the allocation limit would be exceeded.
+    pub fn record_allocation(&self, bytes: usize) -> Result<(), BudgetExceeded> {
+        let Some(max) = self.budget.max_allocated_bytes else {
+            return Ok(());
+        };
+
+        self.allocated_bytes
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |allocated| {
+                allocated.checked_add(bytes).filter(|total| *total <= max)
+            })
+            .map_err(|_| BudgetExceeded {
+                limit: Limit::AllocatedBytes(max),
+            })?;
+
+        Ok(())
+    }
+
+    /// Forgets the work counted so far.
+    pub fn reset(&self) {
+        self.instructions.store(0, Ordering::Relaxed);
+        self.allocated_bytes.store(0, Ordering::Relaxed);
+        *self.started.lock().unwrap() = None;
+    }
+}
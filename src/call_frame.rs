@@ -1,501 +1,2308 @@
 use std::alloc::Layout;
-use std::cell::UnsafeCell;
-use std::mem;
-use std::ptr::NonNull;
-use std::time::SystemTime;
+use std::cell::{Cell, RefCell};
+use std::cmp;
+use std::fmt::{self, Display};
+use std::marker::PhantomData;
+use std::mem::{self, MaybeUninit};
+use std::ptr::{self, NonNull};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
+use std::{slice, str};
+
+use strum::{EnumCount, EnumTryAs};
+
+use crate::class::{switch_target, Class, Method, MethodBody};
+use crate::class_file::constant_pool::{self, ConstantInfo};
+use crate::class_file::{ClassAccessFlags, MethodAccessFlags};
+use crate::error::{bail, format_err, ContextCompat, Error, Result};
+use crate::hooks::MethodRef;
+use crate::instructions::{
+    ArrayLoadStoreType, ArrayType, Condition, EqCondition, Instruction, InstructionKind,
+    IntegerType, InvokeKind, LoadStoreType, NumberType, OrdCondition, ReturnType,
+};
+use crate::natives::NativeMethod;
+use crate::superinstructions::Superinstruction;
+use crate::vm::Vm;
+
+#[derive(Clone, Debug, EnumTryAs)]
+pub enum JvmValue<'a> {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Char(u16),
+    Float(f32),
+    Double(f64),
+    Boolean(bool),
+    ReturnAddress(usize),
+    Reference(usize),
+    StringConst(&'a str),
+}
+
+const _: () = {
+    assert!(mem::size_of::<Option<JvmValue>>() == 24);
+};
+
+/// A Java exception that was thrown and not caught by any frame.
+#[derive(Debug)]
+pub struct JavaException {
+    /// Binary name of the exception class, e.g. `java/lang/InterruptedException`.
+    pub class_name: String,
+    pub message: Option<String>,
+    /// The exception object, if it was thrown by `athrow`. Exceptions raised by the vm itself
+    /// are only created as objects if a handler catches them.
+    pub(crate) object: Option<usize>,
+}
+
+impl JavaException {
+    pub fn new(class_name: impl Into<String>, message: impl Into<String>) -> JavaException {
+        JavaException {
+            class_name: class_name.into(),
+            message: Some(message.into()),
+            object: None,
+        }
+    }
+
+    /// The exception thrown when a null reference is used as an object or array.
+    pub fn null_pointer() -> JavaException {
+        JavaException {
+            class_name: "java/lang/NullPointerException".to_owned(),
+            message: None,
+            object: None,
+        }
+    }
+
+    /// The exception thrown when a call would run out of frame stack or native stack.
+    pub fn stack_overflow() -> JavaException {
+        JavaException {
+            class_name: "java/lang/StackOverflowError".to_owned(),
+            message: None,
+            object: None,
+        }
+    }
+}
+
+impl Display for JavaException {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.class_name.replace('/', "."))?;
+        if let Some(message) = &self.message {
+            write!(f, ": {message}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for JavaException {}
+
+/// The method called by an invoke instruction the last time it was executed.
+#[derive(Default)]
+pub struct InlineCache<'a>(Mutex<Option<CachedCall<'a>>>);
+
+impl<'a> InlineCache<'a> {
+    fn get(&self) -> Option<CachedCall<'a>> {
+        *self.0.lock().unwrap()
+    }
+
+    fn set(&self, call: CachedCall<'a>) {
+        *self.0.lock().unwrap() = Some(call);
+    }
+}
+
+impl fmt::Debug for InlineCache<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.get() {
+            Some(call) => write!(f, "{}.{}{}", call.class.name(), call.name, call.descriptor),
+            None => write!(f, "empty"),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct CachedCall<'a> {
+    /// The class of the receiver the method was selected for, or `None` if the method doesn't
+    /// depend on the receiver, as for static, special and private calls.
+    receiver: Option<&'a Class<'a>>,
+    class: &'a Class<'a>,
+    method: &'a Method<'a>,
+    name: &'a str,
+    descriptor: &'a str,
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub(crate) enum RefTypeHeader {
+    Object(ObjectHeader),
+    Array(ArrayHeader),
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub(crate) struct ObjectHeader {
+    pub class: NonNull<Class<'static>>,
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub(crate) struct ArrayHeader {
+    pub element_type: ArrayElementType,
+    pub length: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ArrayElementType {
+    Primitive(ArrayType),
+    /// Reference arrays store their elements as `JvmValue`s.
+    Reference,
+}
+
+impl ArrayElementType {
+    /// Layout of a single element of an array with this element type.
+    pub fn element_layout(&self) -> Layout {
+        match self {
+            Self::Primitive(ArrayType::Boolean | ArrayType::Byte) => Layout::new::<i8>(),
+            Self::Primitive(ArrayType::Char | ArrayType::Short) => Layout::new::<i16>(),
+            Self::Primitive(ArrayType::Int | ArrayType::Float) => Layout::new::<i32>(),
+            Self::Primitive(ArrayType::Long | ArrayType::Double) => Layout::new::<i64>(),
+            Self::Reference => Layout::new::<JvmValue>(),
+        }
+    }
+}
+
+const _: () = {
+    assert!(mem::size_of::<RefTypeHeader>() == 24);
+};
+
+impl RefTypeHeader {
+    pub unsafe fn array_data<'a, T>(&mut self) -> Result<&'a mut [T]> {
+        let length = match self {
+            Self::Object(_) => bail!("expected an array"),
+            Self::Array(header) => header.length,
+        };
+
+        let header_layout = Layout::new::<RefTypeHeader>();
+        let array_data_layout = Layout::array::<T>(length)?;
+
+        let (array_layout, _) = header_layout.extend(array_data_layout)?;
+        let offset = array_layout.size() - array_data_layout.size();
+
+        let header_ptr = self as *mut RefTypeHeader;
+        let data_ptr = (header_ptr as usize + offset) as *mut T;
+
+        Ok(unsafe { std::slice::from_raw_parts_mut(data_ptr, length) })
+    }
+
+    /// Returns a pointer to the first element of an array, regardless of its element type.
+    pub unsafe fn array_data_ptr(&mut self) -> Result<*mut u8> {
+        let element_type = match self {
+            Self::Object(_) => bail!("expected an array"),
+            Self::Array(header) => header.element_type,
+        };
+
+        let (_, offset) = Layout::new::<RefTypeHeader>().extend(element_type.element_layout())?;
+
+        Ok(unsafe { (self as *mut RefTypeHeader).cast::<u8>().add(offset) })
+    }
+
+    pub unsafe fn object_data<'a>(&mut self) -> Result<&'a mut [JvmValue]> {
+        let target_class = match self {
+            Self::Object(object) => object.class,
+            Self::Array(_) => bail!("expected an object"),
+        };
+
+        let fields_layout = Layout::array::<JvmValue>((*target_class.as_ptr()).fields().len())?;
+        let (object_layout, _) = Layout::new::<RefTypeHeader>().extend(fields_layout)?;
+
+        let offset = object_layout.size() - fields_layout.size();
+
+        let header_ptr = self as *mut RefTypeHeader;
+        let data_ptr = (header_ptr as usize + offset) as *mut JvmValue;
+
+        Ok(unsafe {
+            std::slice::from_raw_parts_mut(data_ptr, (*target_class.as_ptr()).fields().len())
+        })
+    }
+}
+
+thread_local! {
+    /// Class of the method executing on this thread.
+    static CURRENT_CLASS: Cell<Option<NonNull<Class<'static>>>> = const { Cell::new(None) };
+    /// Class of the method which called the one executing on this thread.
+    static PREVIOUS_CLASS: Cell<Option<NonNull<Class<'static>>>> = const { Cell::new(None) };
+}
+
+/// Returns the class of the method executing on the current thread. When called from a native
+/// method, this is the class of its caller.
+pub(crate) fn caller_class<'a>() -> Option<&'a Class<'a>> {
+    CURRENT_CLASS
+        .get()
+        .map(|class| unsafe { class.cast().as_ref() })
+}
+
+/// Returns the class of the method which called the one executing on the current thread. When
+/// called from a native method, this is the class of its caller's caller, like
+/// `Reflection.getCallerClass`.
+pub(crate) fn callers_caller_class<'a>() -> Option<&'a Class<'a>> {
+    PREVIOUS_CLASS
+        .get()
+        .map(|class| unsafe { class.cast().as_ref() })
+}
+
+/// Sets the current class while a frame executes, and restores the previous one when dropped.
+struct CurrentClassGuard {
+    current: Option<NonNull<Class<'static>>>,
+    previous: Option<NonNull<Class<'static>>>,
+}
+
+impl CurrentClassGuard {
+    fn enter(class: &Class) -> CurrentClassGuard {
+        let current = CURRENT_CLASS.replace(Some(NonNull::from(class).cast()));
+        let previous = PREVIOUS_CLASS.replace(current);
+        CurrentClassGuard { current, previous }
+    }
+}
+
+impl Drop for CurrentClassGuard {
+    fn drop(&mut self) {
+        CURRENT_CLASS.set(self.current);
+        PREVIOUS_CLASS.set(self.previous);
+    }
+}
+
+/// The size of each thread's frame stack if none is set, which matches the default `-Xss` of
+/// HotSpot on most platforms.
+pub const DEFAULT_STACK_SIZE: usize = 1024 * 1024;
+
+/// The native stack which is left when calls start throwing `StackOverflowError`. Each Java call
+/// also recurses on the native stack, so deep recursion can run out of it before the frame stack
+/// is full. What's left is enough to unwind, create the error and run the code which catches it.
+const NATIVE_STACK_RESERVE: usize = 256 * 1024;
+
+/// The type of the value in a slot, which is needed to turn its bits back into a [`JvmValue`].
+/// String constants also store their length here, since the slot only has room for the pointer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SlotTag {
+    /// A local which hasn't been stored to yet, whose bits are uninitialized.
+    Empty,
+    Byte,
+    Short,
+    Int,
+    Long,
+    Char,
+    Float,
+    Double,
+    Boolean,
+    ReturnAddress,
+    Reference,
+    StringConst(u32),
+}
+
+const _: () = {
+    assert!(mem::size_of::<SlotTag>() == 8);
+};
+
+impl<'a> JvmValue<'a> {
+    /// Splits a value into the tag and bits stored in a slot.
+    fn into_slot(self) -> (SlotTag, u64) {
+        match self {
+            JvmValue::Byte(v) => (SlotTag::Byte, v as u64),
+            JvmValue::Short(v) => (SlotTag::Short, v as u64),
+            JvmValue::Int(v) => (SlotTag::Int, v as u64),
+            JvmValue::Long(v) => (SlotTag::Long, v as u64),
+            JvmValue::Char(v) => (SlotTag::Char, v as u64),
+            JvmValue::Float(v) => (SlotTag::Float, v.to_bits() as u64),
+            JvmValue::Double(v) => (SlotTag::Double, v.to_bits()),
+            JvmValue::Boolean(v) => (SlotTag::Boolean, v as u64),
+            JvmValue::ReturnAddress(v) => (SlotTag::ReturnAddress, v as u64),
+            JvmValue::Reference(v) => (SlotTag::Reference, v as u64),
+            JvmValue::StringConst(v) => {
+                let len = u32::try_from(v.len()).expect("string constant is too long");
+                (SlotTag::StringConst(len), v.as_ptr() as u64)
+            }
+        }
+    }
+
+    /// Reassembles a value from the tag and bits of a slot, or returns `None` for an empty slot.
+    ///
+    /// # Safety
+    ///
+    /// The bits must have come from [`JvmValue::into_slot`] with the same tag, unless the slot is
+    /// empty.
+    unsafe fn from_slot(tag: SlotTag, bits: *const u64) -> Option<JvmValue<'a>> {
+        let bits = match tag {
+            SlotTag::Empty => return None,
+            _ => unsafe { bits.read() },
+        };
+
+        Some(match tag {
+            SlotTag::Empty => unreachable!(),
+            SlotTag::Byte => JvmValue::Byte(bits as i8),
+            SlotTag::Short => JvmValue::Short(bits as i16),
+            SlotTag::Int => JvmValue::Int(bits as i32),
+            SlotTag::Long => JvmValue::Long(bits as i64),
+            SlotTag::Char => JvmValue::Char(bits as u16),
+            SlotTag::Float => JvmValue::Float(f32::from_bits(bits as u32)),
+            SlotTag::Double => JvmValue::Double(f64::from_bits(bits)),
+            SlotTag::Boolean => JvmValue::Boolean(bits != 0),
+            SlotTag::ReturnAddress => JvmValue::ReturnAddress(bits as usize),
+            SlotTag::Reference => JvmValue::Reference(bits as usize),
+            SlotTag::StringConst(len) => JvmValue::StringConst(unsafe {
+                str::from_utf8_unchecked(slice::from_raw_parts(bits as *const u8, len as usize))
+            }),
+        })
+    }
+}
+
+/// Memory for the locals and operand stacks of the frames executing on a thread. Each frame
+/// reserves its slots above its caller's when it's created and releases them when it returns, so
+/// the memory is reused between calls instead of being allocated for each one.
+///
+/// Like the slots of the JVM, each slot is a plain word. The types of their values are kept in a
+/// separate array of tags, which is only read when a value is taken out of a slot.
+struct FrameStack {
+    /// Never resized while any slots are reserved, since frames point into them.
+    slots: Vec<MaybeUninit<u64>>,
+    tags: Vec<MaybeUninit<SlotTag>>,
+    top: usize,
+}
+
+thread_local! {
+    static FRAME_STACK: RefCell<FrameStack> = const {
+        RefCell::new(FrameStack {
+            slots: Vec::new(),
+            tags: Vec::new(),
+            top: 0,
+        })
+    };
+}
+
+/// Slots reserved on the current thread's frame stack, which are released when this is dropped.
+struct FrameSlots {
+    slots: NonNull<u64>,
+    tags: NonNull<SlotTag>,
+    len: usize,
+}
+
+impl FrameSlots {
+    /// Reserves slots on the current thread's frame stack, throwing `StackOverflowError` if
+    /// there isn't enough space left. The stack is allocated with `stack_size` bytes when it's
+    /// first used, or when a vm with a different stack size uses it next.
+    fn reserve(len: usize, stack_size: usize) -> Result<FrameSlots> {
+        FRAME_STACK.with_borrow_mut(|stack| {
+            let capacity = stack_size / (mem::size_of::<u64>() + mem::size_of::<SlotTag>());
+            if stack.top == 0 && stack.slots.len() != capacity {
+                stack.slots = Vec::with_capacity(capacity);
+                stack.slots.resize_with(capacity, MaybeUninit::uninit);
+                stack.tags = Vec::with_capacity(capacity);
+                stack.tags.resize_with(capacity, MaybeUninit::uninit);
+            }
+
+            if stack.slots.len() - stack.top < len {
+                bail!(JavaException::stack_overflow());
+            }
+
+            // SAFETY: The slots from `top` to `top + len` are in bounds
+            let (slots, tags) = unsafe {
+                (
+                    NonNull::new_unchecked(stack.slots.as_mut_ptr().add(stack.top)).cast(),
+                    NonNull::new_unchecked(stack.tags.as_mut_ptr().add(stack.top)).cast(),
+                )
+            };
+            stack.top += len;
+
+            Ok(FrameSlots { slots, tags, len })
+        })
+    }
+}
+
+impl Drop for FrameSlots {
+    fn drop(&mut self) {
+        // Frames return in the reverse order that they're created in, so these are the top slots
+        FRAME_STACK.with_borrow_mut(|stack| stack.top -= self.len);
+    }
+}
+
+/// The locals of a frame, which are stored in slots on the frame stack. Values which take up two
+/// locals, like longs, are only stored in the first one.
+struct Locals<'a> {
+    slots: NonNull<u64>,
+    tags: NonNull<SlotTag>,
+    len: usize,
+    _values: PhantomData<JvmValue<'a>>,
+}
+
+impl<'a> Locals<'a> {
+    /// Returns the value of a local, or `None` if it hasn't been stored to.
+    fn get(&self, index: usize) -> Option<JvmValue<'a>> {
+        assert!(index < self.len, "local {index} is out of bounds");
+        // SAFETY: The tags were initialized when the frame was created, and the slots when they
+        // were stored to
+        unsafe { JvmValue::from_slot(self.tags.add(index).read(), self.slots.add(index).as_ptr()) }
+    }
+
+    fn set(&mut self, index: usize, value: JvmValue<'a>) {
+        assert!(index < self.len, "local {index} is out of bounds");
+        let (tag, bits) = value.into_slot();
+        // SAFETY: The slot is in bounds
+        unsafe {
+            self.tags.add(index).write(tag);
+            self.slots.add(index).write(bits);
+        }
+    }
+}
+
+/// The operand stack of a frame, which is stored in slots on the frame stack above its locals.
+/// Its capacity is the method's `max_stack`.
+struct OperandStack<'a> {
+    slots: NonNull<u64>,
+    tags: NonNull<SlotTag>,
+    len: usize,
+    capacity: usize,
+    _values: PhantomData<JvmValue<'a>>,
+}
+
+impl<'a> OperandStack<'a> {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns the value at an index from the bottom of the stack.
+    fn get(&self, index: usize) -> Option<JvmValue<'a>> {
+        if index >= self.len {
+            return None;
+        }
+        // SAFETY: The slots up to `len` were initialized when they were pushed
+        unsafe { JvmValue::from_slot(self.tags.add(index).read(), self.slots.add(index).as_ptr()) }
+    }
+
+    fn last(&self) -> Option<JvmValue<'a>> {
+        self.get(self.len.checked_sub(1)?)
+    }
+
+    /// Returns the values from an index to the top of the stack.
+    fn iter_from(&self, start: usize) -> impl Iterator<Item = JvmValue<'a>> + '_ {
+        (start..self.len).map(|index| self.get(index).unwrap())
+    }
+
+    fn push(&mut self, value: JvmValue<'a>) {
+        assert!(
+            self.len < self.capacity,
+            "operand stack overflow (max_stack is {})",
+            self.capacity
+        );
+        let (tag, bits) = value.into_slot();
+        // SAFETY: The slot is within the stack's capacity
+        unsafe {
+            self.tags.add(self.len).write(tag);
+            self.slots.add(self.len).write(bits);
+        }
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Option<JvmValue<'a>> {
+        let value = self.last()?;
+        self.len -= 1;
+        Some(value)
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+}
+
+/// The locals of a frame, which are passed to
+/// [`Hook::on_instruction`](crate::hooks::Hook::on_instruction).
+#[derive(Clone, Copy)]
+pub struct LocalVariables<'f, 'a>(&'f Locals<'a>);
+
+impl<'f, 'a> LocalVariables<'f, 'a> {
+    /// The number of locals, which is the method's `max_locals`.
+    pub fn len(&self) -> usize {
+        self.0.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.len == 0
+    }
+
+    /// Returns the value of a local, or `None` if it's out of bounds or hasn't been stored to.
+    /// Values which take up two locals, like longs, are only in the first one.
+    pub fn get(&self, index: usize) -> Option<JvmValue<'a>> {
+        (index < self.0.len).then(|| self.0.get(index)).flatten()
+    }
+}
+
+/// The values on the operand stack of a frame, from the bottom of the stack to the top, which are
+/// passed to [`Hook::on_instruction`](crate::hooks::Hook::on_instruction).
+#[derive(Clone, Copy)]
+pub struct Operands<'f, 'a>(&'f OperandStack<'a>);
+
+impl<'f, 'a> Operands<'f, 'a> {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.len() == 0
+    }
+
+    /// Returns the value at an index from the bottom of the stack.
+    pub fn get(&self, index: usize) -> Option<JvmValue<'a>> {
+        self.0.get(index)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = JvmValue<'a>> + 'f {
+        self.0.iter_from(0)
+    }
+}
+
+/// The index in the handler table of the handler for superinstructions, which comes after the
+/// handlers for each instruction kind.
+pub(crate) const SUPERINSTRUCTION_HANDLER: usize = InstructionKind::COUNT;
+
+/// The number of Java frames added to an error before the rest are left out.
+const MAX_ERROR_FRAMES: usize = 64;
+
+const _: () = {
+    assert!(SUPERINSTRUCTION_HANDLER <= u8::MAX as usize);
+};
+
+/// Returns the index in the interpreter's handler table of the handler which executes each
+/// instruction of a method, before any instructions are fused into superinstructions.
+pub(crate) fn handler_indices(code: &[Instruction]) -> Vec<AtomicU8> {
+    code.iter()
+        .map(|instruction| AtomicU8::new(InstructionKind::from(instruction) as u8))
+        .collect()
+}
+
+/// How execution continues after an instruction.
+enum Step<'a> {
+    Next,
+    /// Jumps by an offset from the current instruction.
+    Jump(isize),
+    Return(Option<JvmValue<'a>>),
+}
+
+/// Executes an instruction of a particular kind, given the instruction and its index.
+type Handler<'a, 'b> = fn(&mut CallFrame<'a, 'b>, &'a Instruction, usize) -> Result<Step<'a>>;
+
+pub struct CallFrame<'a, 'b> {
+    class: &'a Class<'a>,
+    method: &'a Method<'a>,
+    locals: Locals<'a>,
+    operand_stack: OperandStack<'a>,
+    /// Released after the locals and operand stack are dropped.
+    _slots: FrameSlots,
+    vm: &'b Vm<'a>,
+}
+
+impl<'a, 'b> CallFrame<'a, 'b> {
+    pub fn new(
+        class: &'a Class<'a>,
+        method: &'a Method<'a>,
+        args: impl Iterator<Item = JvmValue<'a>>,
+        vm: &'b Vm<'a>,
+    ) -> Result<CallFrame<'a, 'b>> {
+        let mut frame = CallFrame::reserve(class, method, vm)?;
+
+        for (i, arg) in args.enumerate() {
+            let index = frame.arg_index(i);
+            frame.locals.set(index, arg);
+        }
+
+        Ok(frame)
+    }
+
+    /// Creates a frame for a method called from another frame, whose arguments are at the top of
+    /// the caller's operand stack from `args_start`. The slots are copied into the locals as they
+    /// are, rather than being converted to values and back.
+    fn from_operand_stack(
+        class: &'a Class<'a>,
+        method: &'a Method<'a>,
+        caller: &OperandStack<'a>,
+        args_start: usize,
+        vm: &'b Vm<'a>,
+    ) -> Result<CallFrame<'a, 'b>> {
+        let frame = CallFrame::reserve(class, method, vm)?;
+        let nargs = caller.len - args_start;
+
+        // SAFETY: The arguments were pushed to the caller's operand stack, and the locals have
+        // room for them, since they're within max_locals. The callee's slots are reserved above
+        // the caller's, so they don't overlap.
+        unsafe {
+            let (slots, tags) = (caller.slots.add(args_start), caller.tags.add(args_start));
+            if nargs == frame.arg_slots() {
+                // Without any longs or doubles, the arguments are in consecutive locals
+                ptr::copy_nonoverlapping(slots.as_ptr(), frame.locals.slots.as_ptr(), nargs);
+                ptr::copy_nonoverlapping(tags.as_ptr(), frame.locals.tags.as_ptr(), nargs);
+            } else {
+                for i in 0..nargs {
+                    let index = frame.arg_index(i);
+                    frame.locals.slots.add(index).write(slots.add(i).read());
+                    frame.locals.tags.add(index).write(tags.add(i).read());
+                }
+            }
+        }
+
+        Ok(frame)
+    }
+
+    /// Creates a frame with empty locals and operand stack.
+    fn reserve(
+        class: &'a Class<'a>,
+        method: &'a Method<'a>,
+        vm: &'b Vm<'a>,
+    ) -> Result<CallFrame<'a, 'b>> {
+        let body = method.body.as_ref().wrap_err("missing method body")?;
+
+        let slots = FrameSlots::reserve(body.locals + body.stack_size, vm.stack_size())?;
+
+        let locals = Locals {
+            slots: slots.slots,
+            tags: slots.tags,
+            len: body.locals,
+            _values: PhantomData,
+        };
+
+        for index in 0..body.locals {
+            // SAFETY: The locals are the first slots reserved for the frame
+            unsafe { slots.tags.add(index).write(SlotTag::Empty) };
+        }
+
+        Ok(CallFrame {
+            class,
+            method,
+            locals,
+            // SAFETY: The operand stack is in the slots after the locals
+            operand_stack: unsafe {
+                OperandStack {
+                    slots: slots.slots.add(body.locals),
+                    tags: slots.tags.add(body.locals),
+                    len: 0,
+                    capacity: body.stack_size,
+                    _values: PhantomData,
+                }
+            },
+            _slots: slots,
+            vm,
+        })
+    }
+
+    /// The number of locals taken up by the method's arguments, including the receiver.
+    fn arg_slots(&self) -> usize {
+        self.receiver_slots() + self.method.descriptor.arg_slots
+    }
+
+    fn receiver_slots(&self) -> usize {
+        usize::from(!self.method.access_flags.contains(MethodAccessFlags::STATIC))
+    }
+
+    /// Returns the index in the locals of an argument. Longs and doubles take up two locals, so
+    /// the arguments aren't always in consecutive locals.
+    fn arg_index(&self, arg: usize) -> usize {
+        let receiver_slots = self.receiver_slots();
+        match arg.checked_sub(receiver_slots) {
+            Some(param) => receiver_slots + self.method.descriptor.param_slots[param] as usize,
+            None => arg,
+        }
+    }
+
+    pub fn execute(mut self) -> Result<Option<JvmValue<'a>>> {
+        let body = self.method.body.as_ref().wrap_err("missing method body")?;
+        let _current_class = CurrentClassGuard::enter(self.class);
+
+        self.vm.check_stopped()?;
+        if stacker::remaining_stack().is_some_and(|remaining| remaining < NATIVE_STACK_RESERVE) {
+            bail!(JavaException::stack_overflow());
+        }
+        self.method.hotness.record_invocation();
+
+        if self.vm.is_instrumented() {
+            return self.execute_instrumented(body);
+        }
+
+        self.fuse_when_hot(body);
+
+        #[cfg(feature = "jit")]
+        if let Some(compiled) = self
+            .vm
+            .jit()
+            .and_then(|jit| jit.compiled(self.class, self.method))
+        {
+            // SAFETY: The arguments are in the first slots of the locals
+            let result = unsafe { compiled.call(self.vm, self.locals.slots.as_ptr().cast()) };
+            return result.map_err(|e| self.in_frame(e, None));
+        }
+
+        let mut pc = 0;
+
+        loop {
+            let instruction = &body.code[pc];
+            let handler = Self::HANDLERS[body.handlers[pc].load(Ordering::Relaxed) as usize];
+
+            let step = match handler(&mut self, instruction, pc) {
+                Ok(step) => step,
+                Err(e) => {
+                    pc = self.catch(e, pc).map_err(|e| self.in_frame(e, Some(pc)))?;
+                    continue;
+                }
+            };
+            match step {
+                Step::Next => pc += 1,
+                Step::Jump(offset) => {
+                    // A jump to the same instruction, like `while (true) {}`, is a loop too
+                    if offset <= 0 {
+                        self.vm.check_stopped()?;
+                        self.method.hotness.record_backward_branch();
+                        self.fuse_when_hot(body);
+                    }
+
+                    pc = pc
+                        .checked_add_signed(offset)
+                        .wrap_err("program counter overflowed")?;
+                }
+                Step::Return(value) => return Ok(value),
+            }
+        }
+    }
+
+    /// Executes the method like [`CallFrame::execute`], calling the vm's hooks for the method and
+    /// each of its instructions, and counting the instructions against the vm's budget.
+    /// Instructions are dispatched by their kind, since they're never fused into superinstructions
+    /// while the vm is instrumented.
+    fn execute_instrumented(mut self, body: &'a MethodBody<'a>) -> Result<Option<JvmValue<'a>>> {
+        let hooks = self.vm.hooks();
+        let method = MethodRef {
+            class: self.class,
+            method: self.method,
+        };
+
+        if !hooks.is_empty() {
+            let args = (0..self.receiver_slots() + self.method.descriptor.param_slots.len())
+                .filter_map(|arg| self.locals.get(self.arg_index(arg)))
+                .collect::<Vec<_>>();
+
+            for hook in hooks {
+                hook.on_method_enter(self.vm, method, &args);
+            }
+        }
+
+        let mut pc = 0;
+        let result = loop {
+            if let Err(e) = self.vm.record_instruction() {
+                break Err(e.into());
+            }
+
+            let instruction = &body.code[pc];
+            let locals = LocalVariables(&self.locals);
+            let operands = Operands(&self.operand_stack);
+            for hook in hooks {
+                hook.on_instruction(self.vm, method, pc, instruction, locals, operands);
+            }
+
+            let handler = Self::HANDLERS[InstructionKind::from(instruction) as usize];
+            match handler(&mut self, instruction, pc) {
+                Ok(Step::Next) => pc += 1,
+                Ok(Step::Jump(offset)) => {
+                    if offset <= 0 {
+                        if let Err(e) = self.vm.check_stopped() {
+                            break Err(e);
+                        }
+                        self.method.hotness.record_backward_branch();
+                    }
+
+                    match pc.checked_add_signed(offset) {
+                        Some(target) => pc = target,
+                        None => break Err(format_err!("program counter overflowed")),
+                    }
+                }
+                Ok(Step::Return(value)) => break Ok(value),
+                Err(e) => match self.catch(e, pc) {
+                    Ok(handler) => pc = handler,
+                    Err(e) => break Err(self.in_frame(e, Some(pc))),
+                },
+            }
+        };
+
+        for hook in hooks {
+            hook.on_method_exit(self.vm, method, &result);
+        }
+
+        result
+    }
+
+    /// Finds the handler in the method's exception table which catches an error raised by the
+    /// instruction at `pc`, and clears the operand stack apart from the exception, so that the
+    /// handler can run. Returns the index of the handler's first instruction, or the error if it
+    /// isn't a Java exception or no handler catches it.
+    fn catch(&mut self, error: Error, pc: usize) -> Result<usize> {
+        let Some(exception) = error.exception() else {
+            return Err(error);
+        };
+
+        let body = self.method.body.as_ref().wrap_err("missing method body")?;
+        let offset = body.offsets[pc];
+
+        for entry in self.class.exception_table(self.method.slot) {
+            if !(u32::from(entry.start_pc)..u32::from(entry.end_pc)).contains(&offset) {
+                continue;
+            }
+
+            if entry.catch_type != 0 {
+                // Exceptions raised by the vm whose classes aren't available, e.g. without a
+                // JDK, can't be caught
+                let Ok(class) = self.vm.load_class(&exception.class_name) else {
+                    return Err(error);
+                };
+                if !self
+                    .vm
+                    .is_subclass_of(class, self.class_name(entry.catch_type)?)?
+                {
+                    continue;
+                }
+            }
+
+            let handler = body
+                .instruction_index(entry.handler_pc.into())
+                .wrap_err("exception handler isn't at an instruction")?;
+            let object = self.vm.exception_object(exception)?;
+
+            self.operand_stack.truncate(0);
+            self.operand_stack.push(JvmValue::Reference(object));
+
+            return Ok(handler);
+        }
+
+        Err(error)
+    }
+
+    /// Adds the frame to the Java frames of an error raised while it ran, so that errors show
+    /// where in the program they happened. `pc` is the index of the instruction which failed, or
+    /// `None` in compiled code. Errors which stop the program on purpose, like exceeding the
+    /// budget, exiting or being stopped, are returned as they are.
+    fn in_frame(&self, error: Error, pc: Option<usize>) -> Error {
+        if matches!(
+            error.root(),
+            Error::BudgetExceeded(_) | Error::Exit(_) | Error::Stopped
+        ) {
+            return error;
+        }
+
+        let (mut frames, source) = match error {
+            Error::InJava { frames, source } => (frames, source),
+            error => (Vec::new(), Box::new(error)),
+        };
+
+        // The frames of deep recursion would bury the error, so they're cut short
+        match frames.len().cmp(&MAX_ERROR_FRAMES) {
+            cmp::Ordering::Less => frames.push(self.describe_position(pc)),
+            cmp::Ordering::Equal => frames.push("...".to_owned()),
+            cmp::Ordering::Greater => {}
+        }
+
+        Error::InJava { frames, source }
+    }
+
+    /// Describes the frame's method and the position of an instruction in it like a line of a
+    /// Java stack trace, with the bytecode offset of the instruction.
+    fn describe_position(&self, pc: Option<usize>) -> String {
+        let method = MethodRef {
+            class: self.class,
+            method: self.method,
+        };
+
+        let offset = pc.and_then(|pc| self.method.body.as_ref()?.offsets.get(pc).copied());
+        let line = offset.and_then(|offset| self.class.line_number(self.method.slot, offset));
+        let mut position = match (self.class.source_file(), line) {
+            (Some(file), Some(line)) => vec![format!("{file}:{line}")],
+            (Some(file), None) => vec![file.to_owned()],
+            (None, Some(line)) => vec![format!("line {line}")],
+            (None, None) => vec![],
+        };
+        position.push(match offset {
+            Some(offset) => format!("pc {offset}"),
+            None => "compiled".to_owned(),
+        });
+
+        format!(
+            "at {}.{}{} ({})",
+            self.class.name().replace('/', "."),
+            method.name(),
+            method.descriptor(),
+            position.join(", ")
+        )
+    }
+
+    /// Fuses the method's superinstructions once it has run enough.
+    fn fuse_when_hot(&self, body: &MethodBody) {
+        if body.superinstructions.get().is_none()
+            && self.method.hotness.total() >= self.vm.superinstruction_threshold()
+        {
+            body.fuse_superinstructions();
+        }
+    }
+
+    /// The function which executes each kind of instruction, indexed by [`InstructionKind`], and
+    /// then the function which executes superinstructions. Dispatching through a table compiles
+    /// to a single indirect call per instruction, rather than a chain of comparisons on the
+    /// instruction's fields.
+    const HANDLERS: [Handler<'a, 'b>; InstructionKind::COUNT + 1] = {
+        let mut handlers =
+            [Self::execute_unimplemented as Handler<'a, 'b>; InstructionKind::COUNT + 1];
+        handlers[InstructionKind::r#return as usize] = Self::execute_return;
+        handlers[InstructionKind::r#const as usize] = Self::execute_const;
+        handlers[InstructionKind::store as usize] = Self::execute_store;
+        handlers[InstructionKind::load as usize] = Self::execute_load;
+        handlers[InstructionKind::ldc as usize] = Self::execute_ldc;
+        handlers[InstructionKind::ldc2 as usize] = Self::execute_ldc2;
+        handlers[InstructionKind::invoke as usize] = Self::execute_invoke;
+        handlers[InstructionKind::add as usize] = Self::execute_add;
+        handlers[InstructionKind::sub as usize] = Self::execute_sub;
+        handlers[InstructionKind::mul as usize] = Self::execute_mul;
+        handlers[InstructionKind::neg as usize] = Self::execute_neg;
+        handlers[InstructionKind::shl as usize] = Self::execute_shl;
+        handlers[InstructionKind::shr as usize] = Self::execute_shr;
+        handlers[InstructionKind::ushr as usize] = Self::execute_shr;
+        handlers[InstructionKind::and as usize] = Self::execute_and;
+        handlers[InstructionKind::or as usize] = Self::execute_or;
+        handlers[InstructionKind::xor as usize] = Self::execute_xor;
+        handlers[InstructionKind::i2l as usize] = Self::execute_i2l;
+        handlers[InstructionKind::l2i as usize] = Self::execute_l2i;
+        handlers[InstructionKind::i2b as usize] = Self::execute_narrow_int;
+        handlers[InstructionKind::i2c as usize] = Self::execute_narrow_int;
+        handlers[InstructionKind::i2s as usize] = Self::execute_narrow_int;
+        handlers[InstructionKind::i2f as usize] = Self::execute_convert;
+        handlers[InstructionKind::i2d as usize] = Self::execute_convert;
+        handlers[InstructionKind::l2f as usize] = Self::execute_convert;
+        handlers[InstructionKind::l2d as usize] = Self::execute_convert;
+        handlers[InstructionKind::f2i as usize] = Self::execute_convert;
+        handlers[InstructionKind::f2l as usize] = Self::execute_convert;
+        handlers[InstructionKind::f2d as usize] = Self::execute_convert;
+        handlers[InstructionKind::d2i as usize] = Self::execute_convert;
+        handlers[InstructionKind::d2l as usize] = Self::execute_convert;
+        handlers[InstructionKind::d2f as usize] = Self::execute_convert;
+        handlers[InstructionKind::lcmp as usize] = Self::execute_lcmp;
+        handlers[InstructionKind::fcmp as usize] = Self::execute_float_cmp;
+        handlers[InstructionKind::dcmp as usize] = Self::execute_float_cmp;
+        handlers[InstructionKind::bipush as usize] = Self::execute_bipush;
+        handlers[InstructionKind::sipush as usize] = Self::execute_sipush;
+        handlers[InstructionKind::if_icmp as usize] = Self::execute_if_icmp;
+        handlers[InstructionKind::div as usize] = Self::execute_div;
+        handlers[InstructionKind::rem as usize] = Self::execute_rem;
+        handlers[InstructionKind::r#if as usize] = Self::execute_if;
+        handlers[InstructionKind::if_acmp as usize] = Self::execute_if_acmp;
+        handlers[InstructionKind::ifnull as usize] = Self::execute_if_null;
+        handlers[InstructionKind::ifnonnull as usize] = Self::execute_if_null;
+        handlers[InstructionKind::checkcast as usize] = Self::execute_checkcast;
+        handlers[InstructionKind::instanceof as usize] = Self::execute_instanceof;
+        handlers[InstructionKind::goto as usize] = Self::execute_goto;
+        handlers[InstructionKind::inc as usize] = Self::execute_inc;
+        handlers[InstructionKind::newarray as usize] = Self::execute_newarray;
+        handlers[InstructionKind::anewarray as usize] = Self::execute_anewarray;
+        handlers[InstructionKind::arraylength as usize] = Self::execute_arraylength;
+        handlers[InstructionKind::arrayload as usize] = Self::execute_arrayload;
+        handlers[InstructionKind::arraystore as usize] = Self::execute_arraystore;
+        handlers[InstructionKind::putstatic as usize] = Self::execute_putstatic;
+        handlers[InstructionKind::getstatic as usize] = Self::execute_getstatic;
+        handlers[InstructionKind::aconst_null as usize] = Self::execute_aconst_null;
+        handlers[InstructionKind::new as usize] = Self::execute_new;
+        handlers[InstructionKind::putfield as usize] = Self::execute_putfield;
+        handlers[InstructionKind::getfield as usize] = Self::execute_getfield;
+        handlers[InstructionKind::pop as usize] = Self::execute_pop;
+        handlers[InstructionKind::dup as usize] = Self::execute_dup;
+        handlers[InstructionKind::nop as usize] = Self::execute_nop;
+        handlers[InstructionKind::pop2 as usize] = Self::execute_stack;
+        handlers[InstructionKind::dup_x1 as usize] = Self::execute_stack;
+        handlers[InstructionKind::dup_x2 as usize] = Self::execute_stack;
+        handlers[InstructionKind::dup2 as usize] = Self::execute_stack;
+        handlers[InstructionKind::dup2_x1 as usize] = Self::execute_stack;
+        handlers[InstructionKind::dup2_x2 as usize] = Self::execute_stack;
+        handlers[InstructionKind::swap as usize] = Self::execute_stack;
+        handlers[InstructionKind::tableswitch as usize] = Self::execute_switch;
+        handlers[InstructionKind::lookupswitch as usize] = Self::execute_switch;
+        handlers[InstructionKind::athrow as usize] = Self::execute_athrow;
+        handlers[InstructionKind::monitorenter as usize] = Self::execute_monitor;
+        handlers[InstructionKind::monitorexit as usize] = Self::execute_monitor;
+        handlers[SUPERINSTRUCTION_HANDLER] = Self::execute_superinstruction;
+        handlers
+    };
+
+    fn execute_return(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let Instruction::r#return { data_type } = instruction else {
+            unreachable!()
+        };
+
+        let ret = match data_type {
+            ReturnType::Void => None,
+            ReturnType::Int
+            | ReturnType::Long
+            | ReturnType::Float
+            | ReturnType::Double
+            | ReturnType::Reference => {
+                Some(self.operand_stack.pop().wrap_err("missing return value")?)
+            }
+        };
+
+        Ok(Step::Return(ret))
+    }
+
+    fn execute_const(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let Instruction::r#const { data_type, value } = instruction else {
+            unreachable!()
+        };
+
+        let operand = match data_type {
+            NumberType::Int => JvmValue::Int(*value as i32),
+            NumberType::Long => JvmValue::Long(*value as i64),
+            NumberType::Float => JvmValue::Float(*value as f32),
+            NumberType::Double => JvmValue::Double(*value as f64),
+        };
+        self.operand_stack.push(operand);
+
+        Ok(Step::Next)
+    }
+
+    fn execute_store(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        match instruction {
+            Instruction::store {
+                data_type: LoadStoreType::Int,
+                index,
+            } => {
+                let operand = self
+                    .operand_stack
+                    .pop()
+                    .wrap_err("no operand provided to istore")?;
+
+                self.locals.set(
+                    *index as usize,
+                    match operand {
+                        JvmValue::Byte(v) => JvmValue::Byte(v),
+                        JvmValue::Int(v) => JvmValue::Int(v),
+                        arg => bail!("invalid operand for Int store: {arg:?}"),
+                    },
+                );
+            }
+            Instruction::store {
+                data_type: LoadStoreType::Reference,
+                index,
+            } => {
+                let operand = self
+                    .operand_stack
+                    .pop()
+                    .wrap_err("no operand provided to istore")?;
+
+                self.locals.set(
+                    *index as usize,
+                    match operand {
+                        JvmValue::Reference(v) => JvmValue::Reference(v),
+                        JvmValue::ReturnAddress(v) => JvmValue::ReturnAddress(v),
+                        JvmValue::StringConst(v) => JvmValue::StringConst(v),
+                        arg => unreachable!("unsupported operand for astore: {arg:?}"),
+                    },
+                );
+            }
+            Instruction::store {
+                data_type:
+                    data_type @ (LoadStoreType::Long | LoadStoreType::Float | LoadStoreType::Double),
+                index,
+            } => {
+                let operand = self
+                    .operand_stack
+                    .pop()
+                    .wrap_err("no operand provided to store")?;
+
+                match (data_type, operand) {
+                    (LoadStoreType::Long, operand @ JvmValue::Long(_))
+                    | (LoadStoreType::Float, operand @ JvmValue::Float(_))
+                    | (LoadStoreType::Double, operand @ JvmValue::Double(_)) => {
+                        self.locals.set(*index as usize, operand)
+                    }
+                    (_, operand) => bail!("invalid operand for {data_type:?} store: {operand:?}"),
+                }
+            }
+            _ => bail!("unimplemented instruction: {instruction:?}"),
+        }
+
+        Ok(Step::Next)
+    }
+
+    fn execute_load(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        match instruction {
+            Instruction::load {
+                data_type: LoadStoreType::Int,
+                index,
+            } => {
+                let val = self.int_local(*index)?;
+                self.operand_stack.push(JvmValue::Int(val));
+            }
+            Instruction::load {
+                data_type: LoadStoreType::Reference,
+                index,
+            } => {
+                let val = match self.locals.get(*index as usize) {
+                    None => JvmValue::Reference(0),
+                    Some(JvmValue::Reference(v)) => JvmValue::Reference(v),
+                    Some(JvmValue::ReturnAddress(v)) => JvmValue::ReturnAddress(v),
+                    Some(JvmValue::StringConst(v)) => JvmValue::StringConst(v),
+                    local => bail!("aload called with invalid local: {local:?}"),
+                };
+
+                self.operand_stack.push(val);
+            }
+            Instruction::load {
+                data_type:
+                    data_type @ (LoadStoreType::Long | LoadStoreType::Float | LoadStoreType::Double),
+                index,
+            } => {
+                let val = match (data_type, self.locals.get(*index as usize)) {
+                    (LoadStoreType::Long, None) => JvmValue::Long(0),
+                    (LoadStoreType::Float, None) => JvmValue::Float(0.0),
+                    (LoadStoreType::Double, None) => JvmValue::Double(0.0),
+                    (LoadStoreType::Long, Some(v @ JvmValue::Long(_)))
+                    | (LoadStoreType::Float, Some(v @ JvmValue::Float(_)))
+                    | (LoadStoreType::Double, Some(v @ JvmValue::Double(_))) => v,
+                    (_, local) => bail!("{data_type:?} load called with invalid local: {local:?}"),
+                };
+
+                self.operand_stack.push(val);
+            }
+            _ => bail!("unimplemented instruction: {instruction:?}"),
+        }
+
+        Ok(Step::Next)
+    }
+
+    fn execute_ldc(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let Instruction::ldc { index } = instruction else {
+            unreachable!()
+        };
+
+        match &self.class.constant_pool()[*index] {
+            ConstantInfo::String(constant_pool::String { string_index }) => {
+                // String literals are interned, so equal literals are identical
+                self.operand_stack.push(JvmValue::StringConst(
+                    self.vm.intern(
+                        self.class.constant_pool()[*string_index]
+                            .try_as_utf_8_ref()
+                            .wrap_err("expected utf8")?,
+                    ),
+                ))
+            }
+            ConstantInfo::Class(constant_pool::Class { name_index }) => {
+                let name = self.class.constant_pool()[*name_index]
+                    .try_as_utf_8_ref()
+                    .wrap_err("expected utf8")?;
+
+                self.operand_stack
+                    .push(JvmValue::Reference(self.vm.class_mirror(name)?));
+            }
+            ConstantInfo::Integer(v) => self.operand_stack.push(JvmValue::Int(*v)),
+            ConstantInfo::Float(v) => self.operand_stack.push(JvmValue::Float(*v)),
+            constant => bail!("unimplemented: ldc of {constant:?}"),
+        };
+
+        Ok(Step::Next)
+    }
+
+    fn execute_ldc2(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let Instruction::ldc2 { index } = instruction else {
+            unreachable!()
+        };
+
+        let value = match &self.class.constant_pool()[*index] {
+            ConstantInfo::Long(v) => JvmValue::Long(*v),
+            ConstantInfo::Double(v) => JvmValue::Double(*v),
+            constant => bail!("invalid constant for ldc2: {constant:?}"),
+        };
+        self.operand_stack.push(value);
+
+        Ok(Step::Next)
+    }
+
+    fn execute_add(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let Instruction::add { data_type } = instruction else {
+            unreachable!()
+        };
+
+        let a = self.operand_stack.pop().wrap_err("missing add operand")?;
+        let b = self.operand_stack.pop().wrap_err("missing add operand")?;
+        match data_type {
+            NumberType::Int => self.operand_stack.push(JvmValue::Int(
+                a.try_as_int()
+                    .wrap_err("invalid type")?
+                    .wrapping_add(b.try_as_int().wrap_err("invalid type")?),
+            )),
+            NumberType::Long => self.operand_stack.push(JvmValue::Long(
+                a.try_as_long()
+                    .wrap_err("invalid type")?
+                    .wrapping_add(b.try_as_long().wrap_err("invalid type")?),
+            )),
+            NumberType::Float => self.operand_stack.push(JvmValue::Float(
+                a.try_as_float().wrap_err("invalid type")?
+                    + b.try_as_float().wrap_err("invalid type")?,
+            )),
+            NumberType::Double => self.operand_stack.push(JvmValue::Double(
+                a.try_as_double().wrap_err("invalid type")?
+                    + b.try_as_double().wrap_err("invalid type")?,
+            )),
+        }
+
+        Ok(Step::Next)
+    }
+
+    fn execute_sub(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let Instruction::sub { data_type } = instruction else {
+            unreachable!()
+        };
+
+        let b = self.operand_stack.pop().wrap_err("missing sub operand")?;
+        let a = self.operand_stack.pop().wrap_err("missing sub operand")?;
+        match data_type {
+            NumberType::Int => self.operand_stack.push(JvmValue::Int(
+                a.try_as_int()
+                    .wrap_err("invalid type")?
+                    .wrapping_sub(b.try_as_int().wrap_err("invalid type")?),
+            )),
+            NumberType::Long => self.operand_stack.push(JvmValue::Long(
+                a.try_as_long()
+                    .wrap_err("invalid type")?
+                    .wrapping_sub(b.try_as_long().wrap_err("invalid type")?),
+            )),
+            NumberType::Float => self.operand_stack.push(JvmValue::Float(
+                a.try_as_float().wrap_err("invalid type")?
+                    - b.try_as_float().wrap_err("invalid type")?,
+            )),
+            NumberType::Double => self.operand_stack.push(JvmValue::Double(
+                a.try_as_double().wrap_err("invalid type")?
+                    - b.try_as_double().wrap_err("invalid type")?,
+            )),
+        }
+
+        Ok(Step::Next)
+    }
+
+    fn execute_mul(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let Instruction::mul { data_type } = instruction else {
+            unreachable!()
+        };
+
+        let a = self.operand_stack.pop().wrap_err("missing mul operand")?;
+        let b = self.operand_stack.pop().wrap_err("missing mul operand")?;
+        match data_type {
+            NumberType::Int => self.operand_stack.push(JvmValue::Int(
+                a.try_as_int()
+                    .wrap_err("invalid type")?
+                    .wrapping_mul(b.try_as_int().wrap_err("invalid type")?),
+            )),
+            NumberType::Long => self.operand_stack.push(JvmValue::Long(
+                a.try_as_long()
+                    .wrap_err("invalid type")?
+                    .wrapping_mul(b.try_as_long().wrap_err("invalid type")?),
+            )),
+            NumberType::Float => self.operand_stack.push(JvmValue::Float(
+                a.try_as_float().wrap_err("invalid type")?
+                    * b.try_as_float().wrap_err("invalid type")?,
+            )),
+            NumberType::Double => self.operand_stack.push(JvmValue::Double(
+                a.try_as_double().wrap_err("invalid type")?
+                    * b.try_as_double().wrap_err("invalid type")?,
+            )),
+        }
+
+        Ok(Step::Next)
+    }
+
+    fn execute_neg(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let Instruction::neg { data_type } = instruction else {
+            unreachable!()
+        };
+
+        let value = self.operand_stack.pop().wrap_err("missing neg operand")?;
+        // Negating the minimum value overflows, and wraps back to the minimum value
+        match data_type {
+            NumberType::Int => self.operand_stack.push(JvmValue::Int(
+                value.try_as_int().wrap_err("invalid type")?.wrapping_neg(),
+            )),
+            NumberType::Long => self.operand_stack.push(JvmValue::Long(
+                value.try_as_long().wrap_err("invalid type")?.wrapping_neg(),
+            )),
+            NumberType::Float => self.operand_stack.push(JvmValue::Float(
+                -value.try_as_float().wrap_err("invalid type")?,
+            )),
+            NumberType::Double => self.operand_stack.push(JvmValue::Double(
+                -value.try_as_double().wrap_err("invalid type")?,
+            )),
+        }
+
+        Ok(Step::Next)
+    }
+
+    fn execute_shl(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let Instruction::shl { data_type } = instruction else {
+            unreachable!()
+        };
+
+        let shift = self
+            .operand_stack
+            .pop()
+            .and_then(|v| v.try_as_int())
+            .wrap_err("missing shl operand")?;
+        let value = self.operand_stack.pop().wrap_err("missing shl operand")?;
+        // Only the low 5 bits (6 for longs) of the shift distance are used, which
+        // matches wrapping_shl
+        match data_type {
+            IntegerType::Int => self.operand_stack.push(JvmValue::Int(
+                value
+                    .try_as_int()
+                    .wrap_err("invalid type")?
+                    .wrapping_shl(shift as u32),
+            )),
+            IntegerType::Long => self.operand_stack.push(JvmValue::Long(
+                value
+                    .try_as_long()
+                    .wrap_err("invalid type")?
+                    .wrapping_shl(shift as u32),
+            )),
+        }
+
+        Ok(Step::Next)
+    }
+
+    fn execute_shr(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let (Instruction::shr { data_type } | Instruction::ushr { data_type }) = instruction else {
+            unreachable!()
+        };
+        let unsigned = matches!(instruction, Instruction::ushr { .. });
+
+        let shift = self
+            .operand_stack
+            .pop()
+            .and_then(|v| v.try_as_int())
+            .wrap_err("missing shr operand")?;
+        let value = self.operand_stack.pop().wrap_err("missing shr operand")?;
+        // ushr shifts in zeros, which is a shift of the unsigned value
+        match data_type {
+            IntegerType::Int => {
+                let value = value.try_as_int().wrap_err("invalid type")?;
+                self.operand_stack.push(JvmValue::Int(if unsigned {
+                    (value as u32).wrapping_shr(shift as u32) as i32
+                } else {
+                    value.wrapping_shr(shift as u32)
+                }))
+            }
+            IntegerType::Long => {
+                let value = value.try_as_long().wrap_err("invalid type")?;
+                self.operand_stack.push(JvmValue::Long(if unsigned {
+                    (value as u64).wrapping_shr(shift as u32) as i64
+                } else {
+                    value.wrapping_shr(shift as u32)
+                }))
+            }
+        }
+
+        Ok(Step::Next)
+    }
+
+    fn execute_and(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let Instruction::and { data_type } = instruction else {
+            unreachable!()
+        };
+
+        let a = self.operand_stack.pop().wrap_err("missing and operand")?;
+        let b = self.operand_stack.pop().wrap_err("missing and operand")?;
+        match data_type {
+            IntegerType::Int => self.operand_stack.push(JvmValue::Int(
+                a.try_as_int().wrap_err("invalid type")?
+                    & b.try_as_int().wrap_err("invalid type")?,
+            )),
+            IntegerType::Long => self.operand_stack.push(JvmValue::Long(
+                a.try_as_long().wrap_err("invalid type")?
+                    & b.try_as_long().wrap_err("invalid type")?,
+            )),
+        }
+
+        Ok(Step::Next)
+    }
+
+    fn execute_or(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let Instruction::or { data_type } = instruction else {
+            unreachable!()
+        };
+
+        let a = self.operand_stack.pop().wrap_err("missing or operand")?;
+        let b = self.operand_stack.pop().wrap_err("missing or operand")?;
+        match data_type {
+            IntegerType::Int => self.operand_stack.push(JvmValue::Int(
+                a.try_as_int().wrap_err("invalid type")?
+                    | b.try_as_int().wrap_err("invalid type")?,
+            )),
+            IntegerType::Long => self.operand_stack.push(JvmValue::Long(
+                a.try_as_long().wrap_err("invalid type")?
+                    | b.try_as_long().wrap_err("invalid type")?,
+            )),
+        }
+
+        Ok(Step::Next)
+    }
+
+    fn execute_xor(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let Instruction::xor { data_type } = instruction else {
+            unreachable!()
+        };
+
+        let a = self.operand_stack.pop().wrap_err("missing xor operand")?;
+        let b = self.operand_stack.pop().wrap_err("missing xor operand")?;
+        match data_type {
+            IntegerType::Int => self.operand_stack.push(JvmValue::Int(
+                a.try_as_int().wrap_err("invalid type")?
+                    ^ b.try_as_int().wrap_err("invalid type")?,
+            )),
+            IntegerType::Long => self.operand_stack.push(JvmValue::Long(
+                a.try_as_long().wrap_err("invalid type")?
+                    ^ b.try_as_long().wrap_err("invalid type")?,
+            )),
+        }
+
+        Ok(Step::Next)
+    }
+
+    fn execute_i2l(&mut self, _: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let value = self
+            .operand_stack
+            .pop()
+            .and_then(|v| v.try_as_int())
+            .wrap_err("missing i2l operand")?;
+        self.operand_stack.push(JvmValue::Long(value as i64));
+
+        Ok(Step::Next)
+    }
+
+    fn execute_l2i(&mut self, _: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let value = self
+            .operand_stack
+            .pop()
+            .and_then(|v| v.try_as_long())
+            .wrap_err("missing l2i operand")?;
+        self.operand_stack.push(JvmValue::Int(value as i32));
+
+        Ok(Step::Next)
+    }
+
+    /// Executes `i2b`, `i2c` and `i2s`, which truncate an int and then extend it to an int again.
+    fn execute_narrow_int(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let value = self
+            .operand_stack
+            .pop()
+            .and_then(|v| v.try_as_int())
+            .wrap_err("missing int operand")?;
+        let value = match instruction {
+            Instruction::i2b => value as i8 as i32,
+            Instruction::i2c => value as u16 as i32,
+            Instruction::i2s => value as i16 as i32,
+            _ => unreachable!(),
+        };
+        self.operand_stack.push(JvmValue::Int(value));
+
+        Ok(Step::Next)
+    }
+
+    fn execute_lcmp(&mut self, _: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let b = self
+            .operand_stack
+            .pop()
+            .and_then(|v| v.try_as_long())
+            .wrap_err("missing lcmp operand")?;
+        let a = self
+            .operand_stack
+            .pop()
+            .and_then(|v| v.try_as_long())
+            .wrap_err("missing lcmp operand")?;
+        self.operand_stack.push(JvmValue::Int(a.cmp(&b) as i32));
+
+        Ok(Step::Next)
+    }
+
+    /// Executes the conversions to and from floating point types. Rust's casts saturate and map
+    /// NaN to zero, as Java's do.
+    fn execute_convert(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let value = self
+            .operand_stack
+            .pop()
+            .wrap_err("missing conversion operand")?;
+        let value = match (instruction, value) {
+            (Instruction::i2f, JvmValue::Int(v)) => JvmValue::Float(v as f32),
+            (Instruction::i2d, JvmValue::Int(v)) => JvmValue::Double(v as f64),
+            (Instruction::l2f, JvmValue::Long(v)) => JvmValue::Float(v as f32),
+            (Instruction::l2d, JvmValue::Long(v)) => JvmValue::Double(v as f64),
+            (Instruction::f2i, JvmValue::Float(v)) => JvmValue::Int(v as i32),
+            (Instruction::f2l, JvmValue::Float(v)) => JvmValue::Long(v as i64),
+            (Instruction::f2d, JvmValue::Float(v)) => JvmValue::Double(v as f64),
+            (Instruction::d2i, JvmValue::Double(v)) => JvmValue::Int(v as i32),
+            (Instruction::d2l, JvmValue::Double(v)) => JvmValue::Long(v as i64),
+            (Instruction::d2f, JvmValue::Double(v)) => JvmValue::Float(v as f32),
+            (_, value) => bail!("invalid operand for {instruction:?}: {value:?}"),
+        };
+        self.operand_stack.push(value);
+
+        Ok(Step::Next)
+    }
+
+    /// Executes `fcmpl`, `fcmpg`, `dcmpl` and `dcmpg`, which only differ in whether NaN compares
+    /// as less than or greater than everything.
+    fn execute_float_cmp(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let (Instruction::fcmp { condition } | Instruction::dcmp { condition }) = instruction
+        else {
+            unreachable!()
+        };
+
+        let b = self.operand_stack.pop().wrap_err("missing cmp operand")?;
+        let a = self.operand_stack.pop().wrap_err("missing cmp operand")?;
+        let ordering = match (a, b) {
+            (JvmValue::Float(a), JvmValue::Float(b)) => a.partial_cmp(&b),
+            (JvmValue::Double(a), JvmValue::Double(b)) => a.partial_cmp(&b),
+            (a, b) => bail!("invalid operands for {instruction:?}: {a:?}, {b:?}"),
+        };
+        let result = match (ordering, condition) {
+            (Some(ordering), _) => ordering as i32,
+            (None, OrdCondition::Lt) => -1,
+            (None, OrdCondition::Gt) => 1,
+        };
+        self.operand_stack.push(JvmValue::Int(result));
+
+        Ok(Step::Next)
+    }
+
+    fn execute_bipush(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let Instruction::bipush { value } = instruction else {
+            unreachable!()
+        };
+
+        self.operand_stack.push(JvmValue::Int(*value as i32));
+
+        Ok(Step::Next)
+    }
+
+    fn execute_sipush(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let Instruction::sipush { value } = instruction else {
+            unreachable!()
+        };
+
+        self.operand_stack.push(JvmValue::Int(*value as i32));
+
+        Ok(Step::Next)
+    }
+
+    fn execute_if_icmp(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let Instruction::if_icmp { condition, branch } = instruction else {
+            unreachable!()
+        };
+
+        let v2 = self.operand_stack.pop().unwrap().try_as_int().unwrap();
+        let v1 = self.operand_stack.pop().unwrap().try_as_int().unwrap();
+
+        if compare(*condition, v1, v2) {
+            return Ok(Step::Jump(*branch as isize));
+        }
+
+        Ok(Step::Next)
+    }
+
+    /// Returns the value of an int local, as `iload` would load it.
+    fn int_local(&self, index: u8) -> Result<i32> {
+        match self.locals.get(index as usize) {
+            None => Ok(0),
+            Some(JvmValue::Int(v)) => Ok(v),
+            Some(JvmValue::Byte(v)) => Ok(v as i32),
+            local => bail!("iload called with invalid local: {local:?}"),
+        }
+    }
+
+    fn execute_div(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let Instruction::div { data_type } = instruction else {
+            unreachable!()
+        };
+
+        let b = self.operand_stack.pop().wrap_err("missing div operand")?;
+        let a = self.operand_stack.pop().wrap_err("missing div operand")?;
+        // Dividing the minimum value by -1 overflows, and wraps back to the minimum value
+        let result = match data_type {
+            NumberType::Int => {
+                let b = b.try_as_int().wrap_err("invalid type")?;
+                if b == 0 {
+                    bail!(division_by_zero());
+                }
+                JvmValue::Int(a.try_as_int().wrap_err("invalid type")?.wrapping_div(b))
+            }
+            NumberType::Long => {
+                let b = b.try_as_long().wrap_err("invalid type")?;
+                if b == 0 {
+                    bail!(division_by_zero());
+                }
+                JvmValue::Long(a.try_as_long().wrap_err("invalid type")?.wrapping_div(b))
+            }
+            NumberType::Float => JvmValue::Float(
+                a.try_as_float().wrap_err("invalid type")?
+                    / b.try_as_float().wrap_err("invalid type")?,
+            ),
+            NumberType::Double => JvmValue::Double(
+                a.try_as_double().wrap_err("invalid type")?
+                    / b.try_as_double().wrap_err("invalid type")?,
+            ),
+        };
+
+        self.operand_stack.push(result);
+
+        Ok(Step::Next)
+    }
+
+    fn execute_rem(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let Instruction::rem { data_type } = instruction else {
+            unreachable!()
+        };
+
+        let b = self.operand_stack.pop().wrap_err("missing rem operand")?;
+        let a = self.operand_stack.pop().wrap_err("missing rem operand")?;
+        // The remainder of the minimum value divided by -1 is 0, which wrapping_rem returns
+        // rather than overflowing. Rust's float remainder truncates like Java's does.
+        let result = match data_type {
+            NumberType::Int => {
+                let b = b.try_as_int().wrap_err("invalid type")?;
+                if b == 0 {
+                    bail!(division_by_zero());
+                }
+                JvmValue::Int(a.try_as_int().wrap_err("invalid type")?.wrapping_rem(b))
+            }
+            NumberType::Long => {
+                let b = b.try_as_long().wrap_err("invalid type")?;
+                if b == 0 {
+                    bail!(division_by_zero());
+                }
+                JvmValue::Long(a.try_as_long().wrap_err("invalid type")?.wrapping_rem(b))
+            }
+            NumberType::Float => JvmValue::Float(
+                a.try_as_float().wrap_err("invalid type")?
+                    % b.try_as_float().wrap_err("invalid type")?,
+            ),
+            NumberType::Double => JvmValue::Double(
+                a.try_as_double().wrap_err("invalid type")?
+                    % b.try_as_double().wrap_err("invalid type")?,
+            ),
+        };
+
+        self.operand_stack.push(result);
+
+        Ok(Step::Next)
+    }
+
+    fn execute_if(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let Instruction::r#if { condition, branch } = instruction else {
+            unreachable!()
+        };
+
+        let value = self
+            .operand_stack
+            .pop()
+            .wrap_err("missing operand for if comparison")?
+            .try_as_int()
+            .wrap_err("expected int")?;
+
+        if compare(*condition, value, 0) {
+            return Ok(Step::Jump(*branch as isize));
+        }
+
+        Ok(Step::Next)
+    }
+
+    fn execute_if_acmp(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let Instruction::if_acmp { condition, branch } = instruction else {
+            unreachable!()
+        };
+
+        let v2 = self.operand_stack.pop().wrap_err("missing operand")?;
+        let v1 = self.operand_stack.pop().wrap_err("missing operand")?;
+
+        let same = match (&v1, &v2) {
+            (JvmValue::Reference(v1), JvmValue::Reference(v2)) => v1 == v2,
+            // Strings are identical if they are the same instance in memory
+            (JvmValue::StringConst(v1), JvmValue::StringConst(v2)) => ptr::eq(*v1, *v2),
+            (
+                JvmValue::Reference(_) | JvmValue::StringConst(_),
+                JvmValue::Reference(_) | JvmValue::StringConst(_),
+            ) => false,
+            _ => bail!("invalid operands for if_acmp: {v1:?}, {v2:?}"),
+        };
+
+        let condition = match condition {
+            EqCondition::Eq => same,
+            EqCondition::Ne => !same,
+        };
+
+        if condition {
+            return Ok(Step::Jump(*branch as isize));
+        }
+
+        Ok(Step::Next)
+    }
+
+    fn execute_if_null(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let (Instruction::ifnull { branch } | Instruction::ifnonnull { branch }) = instruction
+        else {
+            unreachable!()
+        };
+
+        let value = self
+            .operand_stack
+            .pop()
+            .wrap_err("missing operand for null comparison")?;
+
+        let is_null = match value {
+            JvmValue::Reference(v) => v == 0,
+            JvmValue::StringConst(_) => false,
+            value => bail!("invalid operand for null comparison: {value:?}"),
+        };
+
+        if is_null == matches!(instruction, Instruction::ifnull { .. }) {
+            return Ok(Step::Jump(*branch as isize));
+        }
+
+        Ok(Step::Next)
+    }
+
+    fn execute_checkcast(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let Instruction::checkcast { index } = instruction else {
+            unreachable!()
+        };
+
+        let value = self
+            .operand_stack
+            .last()
+            .wrap_err("operand stack is empty")?;
+        let class_name = self.class_name(*index)?;
+
+        // Null can be cast to any type
+        if !matches!(value, JvmValue::Reference(0))
+            && !self.vm.is_instance_of(&value, class_name)?
+        {
+            let value_class = match value {
+                JvmValue::Reference(object) => {
+                    match unsafe { &*(object as *const RefTypeHeader) } {
+                        RefTypeHeader::Object(object) => unsafe { object.class.as_ref() }.name(),
+                        RefTypeHeader::Array(_) => "array",
+                    }
+                }
+                _ => "java/lang/String",
+            };
+
+            bail!(JavaException::new(
+                "java/lang/ClassCastException",
+                format!(
+                    "class {} cannot be cast to class {}",
+                    value_class.replace('/', "."),
+                    class_name.replace('/', ".")
+                )
+            ));
+        }
+
+        Ok(Step::Next)
+    }
+
+    fn execute_instanceof(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let Instruction::instanceof { index } = instruction else {
+            unreachable!()
+        };
+
+        let value = self
+            .operand_stack
+            .pop()
+            .wrap_err("operand stack is empty")?;
+        let class_name = self.class_name(*index)?;
+        let is_instance = self.vm.is_instance_of(&value, class_name)?;
+        self.operand_stack.push(JvmValue::Int(is_instance as i32));
+
+        Ok(Step::Next)
+    }
+
+    fn execute_goto(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let Instruction::goto { branch } = instruction else {
+            unreachable!()
+        };
+
+        Ok(Step::Jump(*branch as isize))
+    }
+
+    fn execute_inc(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let Instruction::inc { index, value } = instruction else {
+            unreachable!()
+        };
+
+        let local = self
+            .locals
+            .get(*index as usize)
+            .unwrap()
+            .try_as_int()
+            .unwrap();
+        self.locals.set(
+            *index as usize,
+            JvmValue::Int(local.wrapping_add(*value as i32)),
+        );
+
+        Ok(Step::Next)
+    }
+
+    fn execute_newarray(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let Instruction::newarray { atype } = instruction else {
+            unreachable!()
+        };
+
+        let length = self
+            .operand_stack
+            .pop()
+            .wrap_err("missing count operand for newarray")?
+            .try_as_int()
+            .wrap_err("expected int")? as usize;
+
+        let array = self
+            .vm
+            .alloc_array(ArrayElementType::Primitive(*atype), length)?;
+
+        self.operand_stack.push(JvmValue::Reference(array));
+
+        Ok(Step::Next)
+    }
+
+    fn execute_anewarray(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let Instruction::anewarray { .. } = instruction else {
+            unreachable!()
+        };
+
+        let length = self
+            .operand_stack
+            .pop()
+            .wrap_err("missing count operand for anewarray")?
+            .try_as_int()
+            .wrap_err("expected int")? as usize;
+
+        // TODO: Resolve the component class
+        let array = self.vm.alloc_array(ArrayElementType::Reference, length)?;
+
+        self.operand_stack.push(JvmValue::Reference(array));
+
+        Ok(Step::Next)
+    }
+
+    fn execute_arraylength(&mut self, _: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let reference = self
+            .operand_stack
+            .pop()
+            .and_then(|v| v.try_as_reference())
+            .wrap_err("expected array reference")?;
+
+        let Some(header) = (unsafe { (reference as *const RefTypeHeader).as_ref() }) else {
+            bail!(JavaException::null_pointer());
+        };
+        let RefTypeHeader::Array(array) = header else {
+            bail!("invalid header: {header:?}")
+        };
+
+        self.operand_stack.push(JvmValue::Int(array.length as i32));
+
+        Ok(Step::Next)
+    }
+
+    fn execute_arrayload(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let Instruction::arrayload { data_type } = instruction else {
+            unreachable!()
+        };
+
+        let (header, index) = self.pop_array_index()?;
+        let RefTypeHeader::Array(array) = header else {
+            bail!("invalid header: {header:?}")
+        };
+
+        let value = unsafe {
+            match (&array.element_type, data_type) {
+                (ArrayElementType::Primitive(ArrayType::Int), ArrayLoadStoreType::Int) => {
+                    JvmValue::Int(header.array_data::<i32>()?[index])
+                }
+                (
+                    ArrayElementType::Primitive(ArrayType::Boolean | ArrayType::Byte),
+                    ArrayLoadStoreType::Byte,
+                ) => JvmValue::Int(header.array_data::<i8>()?[index] as i32),
+                (ArrayElementType::Primitive(ArrayType::Char), ArrayLoadStoreType::Char) => {
+                    JvmValue::Int(header.array_data::<u16>()?[index] as i32)
+                }
+                (ArrayElementType::Primitive(ArrayType::Short), ArrayLoadStoreType::Short) => {
+                    JvmValue::Int(header.array_data::<i16>()?[index] as i32)
+                }
+                (ArrayElementType::Primitive(ArrayType::Long), ArrayLoadStoreType::Long) => {
+                    JvmValue::Long(header.array_data::<i64>()?[index])
+                }
+                (ArrayElementType::Primitive(ArrayType::Float), ArrayLoadStoreType::Float) => {
+                    JvmValue::Float(header.array_data::<f32>()?[index])
+                }
+                (ArrayElementType::Primitive(ArrayType::Double), ArrayLoadStoreType::Double) => {
+                    JvmValue::Double(header.array_data::<f64>()?[index])
+                }
+                (ArrayElementType::Reference, ArrayLoadStoreType::Reference) => {
+                    header.array_data::<JvmValue>()?[index].clone()
+                }
+                (t, _) => bail!("invalid array type: {t:?}"),
+            }
+        };
+
+        self.operand_stack.push(value);
+
+        Ok(Step::Next)
+    }
+
+    fn execute_arraystore(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let Instruction::arraystore { data_type } = instruction else {
+            unreachable!()
+        };
+
+        let value = self
+            .operand_stack
+            .pop()
+            .wrap_err("missing value to store")?;
+        let (header, index) = self.pop_array_index()?;
+        let RefTypeHeader::Array(array) = header else {
+            bail!("invalid header: {header:?}")
+        };
+
+        let int = || value.try_as_int_ref().copied().wrap_err("expected int");
+
+        unsafe {
+            match (&array.element_type, data_type) {
+                (ArrayElementType::Primitive(ArrayType::Int), ArrayLoadStoreType::Int) => {
+                    header.array_data::<i32>()?[index] = int()?
+                }
+                (
+                    ArrayElementType::Primitive(ArrayType::Boolean | ArrayType::Byte),
+                    ArrayLoadStoreType::Byte,
+                ) => header.array_data::<i8>()?[index] = int()? as i8,
+                (ArrayElementType::Primitive(ArrayType::Char), ArrayLoadStoreType::Char) => {
+                    header.array_data::<u16>()?[index] = int()? as u16
+                }
+                (ArrayElementType::Primitive(ArrayType::Short), ArrayLoadStoreType::Short) => {
+                    header.array_data::<i16>()?[index] = int()? as i16
+                }
+                (ArrayElementType::Primitive(ArrayType::Long), ArrayLoadStoreType::Long) => {
+                    header.array_data::<i64>()?[index] =
+                        value.try_as_long().wrap_err("expected long")?
+                }
+                (ArrayElementType::Primitive(ArrayType::Float), ArrayLoadStoreType::Float) => {
+                    header.array_data::<f32>()?[index] =
+                        value.try_as_float().wrap_err("expected float")?
+                }
+                (ArrayElementType::Primitive(ArrayType::Double), ArrayLoadStoreType::Double) => {
+                    header.array_data::<f64>()?[index] =
+                        value.try_as_double().wrap_err("expected double")?
+                }
+                (ArrayElementType::Reference, ArrayLoadStoreType::Reference) => {
+                    header.array_data::<JvmValue>()?[index] = value
+                }
+                (t, _) => bail!("invalid array type: {t:?}"),
+            }
+        }
+
+        Ok(Step::Next)
+    }
+
+    fn execute_putstatic(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let Instruction::putstatic { index } = instruction else {
+            unreachable!()
+        };
+
+        *self.get_static_field(*index)?.lock().unwrap() = self.operand_stack.pop().unwrap();
+
+        Ok(Step::Next)
+    }
+
+    fn execute_getstatic(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let Instruction::getstatic { index } = instruction else {
+            unreachable!()
+        };
+
+        let value = self.get_static_field(*index)?.lock().unwrap().clone();
+        self.operand_stack.push(value);
+
+        Ok(Step::Next)
+    }
+
+    fn execute_aconst_null(&mut self, _: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        self.operand_stack.push(JvmValue::Reference(0));
+
+        Ok(Step::Next)
+    }
+
+    fn execute_new(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let Instruction::new { index } = instruction else {
+            unreachable!()
+        };
+
+        let target_class = self.class.constant_pool()[*index]
+            .try_as_class_ref()
+            .wrap_err("expected class")?;
+
+        let target_class_name = self.class.constant_pool()[target_class.name_index]
+            .try_as_utf_8_ref()
+            .wrap_err("expected utf8")?;
+
+        let target_class = self.vm.load_class_file(target_class_name)?;
+        let object = self.vm.alloc_object(target_class)?;
+
+        self.operand_stack.push(JvmValue::Reference(object));
+
+        Ok(Step::Next)
+    }
+
+    fn execute_putfield(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let Instruction::putfield { index } = instruction else {
+            unreachable!()
+        };
+
+        let value = self
+            .operand_stack
+            .pop()
+            .wrap_err("missing putfield operand")?;
+        *self.get_instance_field(*index)? = value;
+
+        Ok(Step::Next)
+    }
+
+    fn execute_getfield(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let Instruction::getfield { index } = instruction else {
+            unreachable!()
+        };
+
+        let value = self.get_instance_field(*index)?;
+        self.operand_stack.push((*value).clone());
+
+        Ok(Step::Next)
+    }
+
+    fn execute_pop(&mut self, _: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        self.operand_stack
+            .pop()
+            .wrap_err("operand stack is empty")?;
+
+        Ok(Step::Next)
+    }
 
-use color_eyre::eyre::{self, bail, eyre, ContextCompat};
-use strum::EnumTryAs;
+    /// Executes `tableswitch` and `lookupswitch`, whose targets are read from the method's code
+    /// rather than stored in the instructions.
+    fn execute_switch(&mut self, _: &'a Instruction, pc: usize) -> Result<Step<'a>> {
+        let key = self
+            .operand_stack
+            .pop()
+            .and_then(JvmValue::try_as_int)
+            .wrap_err("expected int")?;
 
-use crate::class::{Class, Method};
-use crate::class_file::constant_pool::{self, ConstantInfo};
-use crate::class_file::MethodAccessFlags;
-use crate::descriptor::{BaseType, FieldType};
-use crate::instructions::{
-    ArrayLoadStoreType, ArrayType, Condition, Instruction, InvokeKind, LoadStoreType, NumberType,
-    ReturnType,
-};
-use crate::vm::Vm;
+        let body = self.method.body.as_ref().wrap_err("missing method body")?;
+        let code = self
+            .class
+            .code(self.method.slot)
+            .wrap_err("missing code attribute")?;
 
-#[derive(Clone, Debug, EnumTryAs)]
-pub enum JvmValue<'a> {
-    Byte(i8),
-    Short(i16),
-    Int(i32),
-    Long(i64),
-    Char(u16),
-    Float(f32),
-    Double(f64),
-    Boolean(bool),
-    ReturnAddress(usize),
-    Reference(usize),
-    StringConst(&'a str),
-}
+        let target = switch_target(code.code, body.offsets[pc], key)?;
+        let target = body
+            .instruction_index(target)
+            .wrap_err_with(|| format_err!("invalid switch target: {target}"))?;
 
-const _: () = {
-    assert!(mem::size_of::<Option<JvmValue>>() == 24);
-};
+        Ok(Step::Jump(target as isize - pc as isize))
+    }
 
-#[derive(Debug)]
-#[repr(C)]
-enum RefTypeHeader {
-    Object(ObjectHeader),
-    Array(ArrayHeader),
-}
+    fn execute_athrow(&mut self, _: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let object = self
+            .operand_stack
+            .pop()
+            .and_then(JvmValue::try_as_reference)
+            .wrap_err("expected reference")?;
 
-#[derive(Debug)]
-#[repr(C)]
-struct ObjectHeader {
-    class: NonNull<Class<'static>>,
-}
+        if object == 0 {
+            bail!(JavaException::null_pointer());
+        }
 
-#[derive(Debug)]
-#[repr(C)]
-struct ArrayHeader {
-    atype: ArrayType,
-    length: usize,
-}
+        bail!(self.vm.thrown_exception(object)?)
+    }
 
-const _: () = {
-    assert!(mem::size_of::<RefTypeHeader>() == 24);
-};
+    /// Executes `monitorenter` and `monitorexit`. Each vm runs Java code on a single thread, so
+    /// monitors are never contended, and entering and exiting them only checks for null.
+    fn execute_monitor(&mut self, _: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let object = self
+            .operand_stack
+            .pop()
+            .wrap_err("operand stack is empty")?;
 
-impl RefTypeHeader {
-    unsafe fn array_data<'a, T>(&mut self) -> eyre::Result<&'a mut [T]> {
-        let length = match self {
-            Self::Object(_) => bail!("expected an array"),
-            Self::Array(header) => header.length,
-        };
+        if matches!(object, JvmValue::Reference(0)) {
+            bail!(JavaException::null_pointer());
+        }
 
-        let header_layout = Layout::new::<RefTypeHeader>();
-        let array_data_layout = Layout::array::<T>(length)?;
+        Ok(Step::Next)
+    }
 
-        let (array_layout, _) = header_layout.extend(array_data_layout)?;
-        let offset = array_layout.size() - array_data_layout.size();
+    fn execute_dup(&mut self, _: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        let value = self
+            .operand_stack
+            .last()
+            .wrap_err("operand stack is empty")?;
+        self.operand_stack.push(value);
 
-        let header_ptr = self as *mut RefTypeHeader;
-        let data_ptr = (header_ptr as usize + offset) as *mut T;
+        Ok(Step::Next)
+    }
 
-        Ok(unsafe { std::slice::from_raw_parts_mut(data_ptr, length) })
+    fn execute_nop(&mut self, _: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        Ok(Step::Next)
     }
 
-    unsafe fn object_data<'a>(&mut self) -> eyre::Result<&'a mut [JvmValue]> {
-        let target_class = match self {
-            Self::Object(object) => object.class,
-            Self::Array(_) => bail!("expected an object"),
+    /// Executes the stack instructions other than `pop` and `dup`, which work on words of the
+    /// stack, where longs and doubles take up two words. The top values are duplicated below the
+    /// values under them, or swapped with them.
+    fn execute_stack(&mut self, instruction: &'a Instruction, _: usize) -> Result<Step<'a>> {
+        // The number of words at the top, and the number under them which they are moved below
+        let (top, under) = match instruction {
+            Instruction::pop2 => (2, 0),
+            Instruction::dup_x1 | Instruction::swap => (1, 1),
+            Instruction::dup_x2 => (1, 2),
+            Instruction::dup2 => (2, 0),
+            Instruction::dup2_x1 => (2, 1),
+            Instruction::dup2_x2 => (2, 2),
+            _ => unreachable!(),
         };
 
-        let fields_layout = Layout::array::<JvmValue>((*target_class.as_ptr()).fields().len())?;
-        let (object_layout, _) = Layout::new::<RefTypeHeader>().extend(fields_layout)?;
+        let top = self.pop_words(top)?;
+        let under = self.pop_words(under)?;
 
-        let offset = object_layout.size() - fields_layout.size();
+        if matches!(instruction, Instruction::pop2) {
+            return Ok(Step::Next);
+        }
 
-        let header_ptr = self as *mut RefTypeHeader;
-        let data_ptr = (header_ptr as usize + offset) as *mut JvmValue;
+        let swap = matches!(instruction, Instruction::swap);
+        for value in top.iter().chain(&under).chain(top.iter().filter(|_| !swap)) {
+            self.operand_stack.push(value.clone());
+        }
 
-        Ok(unsafe {
-            std::slice::from_raw_parts_mut(data_ptr, (*target_class.as_ptr()).fields().len())
-        })
+        Ok(Step::Next)
     }
-}
-
-pub struct CallFrame<'a, 'b> {
-    class: &'a Class<'a>,
-    method: &'a Method<'a>,
-    locals: Vec<Option<JvmValue<'a>>>,
-    operand_stack: Vec<JvmValue<'a>>,
-    vm: &'b mut Vm<'a>,
-}
-
-impl<'a, 'b> CallFrame<'a, 'b> {
-    pub fn new(
-        class: &'a Class<'a>,
-        method: &'a Method<'a>,
-        args: impl Iterator<Item = JvmValue<'a>>,
-        vm: &'b mut Vm<'a>,
-    ) -> eyre::Result<CallFrame<'a, 'b>> {
-        let body = method.body.as_ref().wrap_err("missing method body")?;
 
-        let mut locals = vec![None; body.locals];
+    /// Pops values taking up a number of words from the operand stack, returning them in the
+    /// order they were pushed.
+    fn pop_words(&mut self, words: usize) -> Result<Vec<JvmValue<'a>>> {
+        let mut values = Vec::new();
+        let mut popped = 0;
+        while popped < words {
+            let value = self
+                .operand_stack
+                .pop()
+                .wrap_err("operand stack is empty")?;
+            popped += match value {
+                JvmValue::Long(_) | JvmValue::Double(_) => 2,
+                _ => 1,
+            };
+            values.push(value);
+        }
 
-        for (i, arg) in args.enumerate() {
-            locals[i] = Some(arg);
+        if popped != words {
+            bail!("stack instruction would split a long or double");
         }
 
-        Ok(CallFrame {
-            class,
-            method,
-            locals,
-            operand_stack: Vec::with_capacity(body.stack_size),
-            vm,
-        })
+        values.reverse();
+        Ok(values)
     }
 
-    pub fn execute(mut self) -> eyre::Result<Option<JvmValue<'a>>> {
+    /// Executes a sequence of instructions which were fused when the method was loaded, and
+    /// skips to the instruction after it.
+    fn execute_superinstruction(
+        &mut self,
+        instruction: &'a Instruction,
+        pc: usize,
+    ) -> Result<Step<'a>> {
         let body = self.method.body.as_ref().wrap_err("missing method body")?;
 
-        if self
-            .method
-            .access_flags
-            .contains(MethodAccessFlags::SYNCHRONIZED)
-        {
-            todo!("synchronized methods")
+        // Another thread may have switched the handler before this thread sees the fused
+        // instructions, in which case the instruction is executed on its own
+        let Some(superinstruction) = body.superinstructions.get().and_then(|s| s[pc]) else {
+            let handler = Self::HANDLERS[InstructionKind::from(instruction) as usize];
+            return handler(self, instruction, pc);
+        };
+
+        match superinstruction {
+            Superinstruction::AddLocals { a, b } => {
+                let result = self.int_local(a)?.wrapping_add(self.int_local(b)?);
+                self.operand_stack.push(JvmValue::Int(result));
+            }
+            Superinstruction::CompareLocal {
+                index,
+                value,
+                condition,
+                branch,
+            } => {
+                if compare(condition, self.int_local(index)?, value as i32) {
+                    // The branch is relative to the if_icmp at the end of the sequence
+                    return Ok(Step::Jump(2 + branch as isize));
+                }
+            }
+            Superinstruction::GetThisField { .. } => {
+                self.execute_load(instruction, pc)?;
+                self.execute_getfield(&body.code[pc + 1], pc + 1)?;
+            }
         }
 
-        let mut pc = 0;
+        Ok(Step::Jump(superinstruction.instruction_count() as isize))
+    }
 
-        loop {
-            let instruction = &body.code[pc];
-            let mut next_instruction_offset = 1isize;
-            match instruction {
-                Instruction::r#return { data_type } => {
-                    if self
-                        .method
-                        .access_flags
-                        .contains(MethodAccessFlags::SYNCHRONIZED)
-                    {
-                        todo!("synchronized methods")
-                    }
+    fn execute_unimplemented(
+        &mut self,
+        instruction: &'a Instruction,
+        _: usize,
+    ) -> Result<Step<'a>> {
+        bail!("unimplemented instruction: {instruction:?}")
+    }
 
-                    let ret = match data_type {
-                        ReturnType::Void => None,
-                        ReturnType::Int => {
-                            return Ok(Some(
-                                self.operand_stack.pop().wrap_err("missing return value")?,
-                            ))
-                        }
-                        ReturnType::Long => todo!(),
-                        ReturnType::Float => todo!(),
-                        ReturnType::Double => todo!(),
-                        ReturnType::Reference => todo!(),
-                    };
+    /// Returns the name of a class referenced by the constant pool.
+    fn class_name(&self, index: u16) -> Result<&'a str> {
+        let class = self.class.constant_pool()[index]
+            .try_as_class_ref()
+            .wrap_err("expected class")?;
 
-                    return Ok(ret);
-                }
-                Instruction::r#const { data_type, value } => {
-                    let operand = match data_type {
-                        NumberType::Int => JvmValue::Int(*value as i32),
-                        NumberType::Long => todo!(),
-                        NumberType::Float => todo!(),
-                        NumberType::Double => todo!(),
-                    };
-                    self.operand_stack.push(operand);
-                }
-                Instruction::store {
-                    data_type: LoadStoreType::Int,
-                    index,
-                } => {
-                    let operand = self
-                        .operand_stack
-                        .pop()
-                        .wrap_err("no operand provided to istore")?;
-
-                    self.locals[*index as usize] = Some(match operand {
-                        JvmValue::Byte(v) => JvmValue::Byte(v),
-                        JvmValue::StringConst(_) => todo!(),
-                        JvmValue::Int(v) => JvmValue::Int(v),
-                        arg => todo!("{arg:?}"),
-                    });
-                }
-                Instruction::store {
-                    data_type: LoadStoreType::Reference,
-                    index,
-                } => {
-                    let operand = self
-                        .operand_stack
-                        .pop()
-                        .wrap_err("no operand provided to istore")?;
-
-                    self.locals[*index as usize] = Some(match operand {
-                        JvmValue::Reference(v) => JvmValue::Reference(v),
-                        JvmValue::ReturnAddress(v) => JvmValue::ReturnAddress(v),
-                        arg => unreachable!("unsupported operand for astore: {arg:?}"),
-                    });
-                }
-                Instruction::load {
-                    data_type: LoadStoreType::Int,
-                    index,
-                } => {
-                    let val = match &self.locals[*index as usize] {
-                        None => 0,
-                        Some(JvmValue::Int(v)) => *v,
-                        Some(JvmValue::Byte(v)) => *v as i32,
-                        local => bail!("iload called with invalid local: {local:?}"),
-                    };
-
-                    self.operand_stack.push(JvmValue::Int(val));
-                }
-                Instruction::load {
-                    data_type: LoadStoreType::Reference,
-                    index,
-                } => {
-                    let val = match &self.locals[*index as usize] {
-                        None => JvmValue::Reference(0),
-                        Some(JvmValue::Reference(v)) => JvmValue::Reference(*v),
-                        Some(JvmValue::ReturnAddress(v)) => JvmValue::ReturnAddress(*v),
-                        Some(JvmValue::StringConst(v)) => JvmValue::StringConst(v),
-                        local => bail!("aload called with invalid local: {local:?}"),
-                    };
-
-                    self.operand_stack.push(val);
-                }
-                Instruction::ldc { index } => {
-                    match &self.class.constant_pool()[*index] {
-                        ConstantInfo::String(constant_pool::String { string_index }) => {
-                            self.operand_stack.push(JvmValue::StringConst(
-                                self.class.constant_pool()[*string_index]
-                                    .try_as_utf_8_ref()
-                                    .wrap_err("expected utf8")?,
-                            ))
-                        }
-                        _ => todo!(),
-                    };
-                }
-                Instruction::invoke { kind, index } => {
-                    self.execute_invoke(*index, *kind)?;
-                }
-                Instruction::add { data_type } => {
-                    let a = self.operand_stack.pop().wrap_err("missing add operand")?;
-                    let b = self.operand_stack.pop().wrap_err("missing add operand")?;
-                    match data_type {
-                        NumberType::Int => self.operand_stack.push(JvmValue::Int(
-                            a.try_as_int().wrap_err("invalid type")?
-                                + b.try_as_int().wrap_err("invalid type")?,
-                        )),
-                        NumberType::Long => todo!(),
-                        NumberType::Float => todo!(),
-                        NumberType::Double => todo!(),
-                    }
-                }
-                Instruction::bipush { value } => {
-                    self.operand_stack.push(JvmValue::Int(*value as i32));
-                }
-                Instruction::if_icmp { condition, branch } => {
-                    let v2 = self.operand_stack.pop().unwrap().try_as_int().unwrap();
-                    let v1 = self.operand_stack.pop().unwrap().try_as_int().unwrap();
-
-                    let condition = match condition {
-                        Condition::Eq => v1 == v2,
-                        Condition::Ne => v1 != v2,
-                        Condition::Lt => v1 < v2,
-                        Condition::Le => v1 <= v2,
-                        Condition::Gt => v1 > v2,
-                        Condition::Ge => v1 >= v2,
-                    };
-
-                    if condition {
-                        next_instruction_offset = *branch as isize;
-                    }
-                }
-                Instruction::rem { data_type } => {
-                    let result = match data_type {
-                        NumberType::Int => {
-                            let v2 = self.operand_stack.pop().unwrap().try_as_int().unwrap();
-                            let v1 = self.operand_stack.pop().unwrap().try_as_int().unwrap();
-                            JvmValue::Int(v1 % v2)
-                        }
-                        NumberType::Long => todo!(),
-                        NumberType::Float => todo!(),
-                        NumberType::Double => todo!(),
-                    };
+        Ok(self.class.constant_pool()[class.name_index]
+            .try_as_utf_8_ref()
+            .wrap_err("expected utf8")?)
+    }
 
-                    self.operand_stack.push(result);
-                }
-                Instruction::r#if { condition, branch } => {
-                    let value = self
-                        .operand_stack
-                        .pop()
-                        .wrap_err("missing operand for if comparison")?
-                        .try_as_int()
-                        .wrap_err("expected int")?;
-
-                    let condition = match condition {
-                        Condition::Eq => value == 0,
-                        Condition::Ne => value != 0,
-                        Condition::Lt => value < 0,
-                        Condition::Le => value <= 0,
-                        Condition::Gt => value > 0,
-                        Condition::Ge => value >= 0,
-                    };
-
-                    if condition {
-                        next_instruction_offset = *branch as isize;
-                    }
-                }
-                Instruction::goto { branch } => {
-                    next_instruction_offset = *branch as isize;
-                }
-                Instruction::inc { index, value } => {
-                    *self.locals[*index as usize]
-                        .as_mut()
-                        .unwrap()
-                        .try_as_int_mut()
-                        .unwrap() += *value as i32;
-                }
-                Instruction::newarray { atype } => {
-                    let length = self
-                        .operand_stack
-                        .pop()
-                        .wrap_err("missing count operand for newarray")?
-                        .try_as_int()
-                        .wrap_err("expected int")? as usize;
-
-                    let array_data_layout = match atype {
-                        ArrayType::Int => Layout::array::<i32>(length)?,
-                        atype => todo!("{atype:?}"),
-                    };
-
-                    let (array_layout, _) =
-                        Layout::new::<RefTypeHeader>().extend(array_data_layout)?;
-                    let layout = array_layout.pad_to_align();
-                    let ptr = self.vm.heap.alloc_layout(layout);
-
-                    unsafe {
-                        std::ptr::write_bytes(ptr.as_ptr(), 0, layout.size());
-
-                        *(ptr.as_ptr() as *mut RefTypeHeader) = RefTypeHeader::Array(ArrayHeader {
-                            atype: *atype,
-                            length,
-                        });
-                    }
+    /// Pops an array reference and an index into it from the operand stack, for the array load
+    /// and store instructions.
+    fn pop_array_index(&mut self) -> Result<(&'a mut RefTypeHeader, usize)> {
+        let index = self
+            .operand_stack
+            .pop()
+            .and_then(|v| v.try_as_int())
+            .wrap_err("expected array index")?;
 
-                    self.operand_stack
-                        .push(JvmValue::Reference(ptr.as_ptr() as _));
-                }
-                Instruction::arraylength => {
-                    let reference = self
-                        .operand_stack
-                        .pop()
-                        .unwrap()
-                        .try_as_reference()
-                        .unwrap();
-
-                    let header = unsafe { &*(reference as *mut RefTypeHeader) };
-                    let RefTypeHeader::Array(array) = header else {
-                        bail!("invalid header: {header:?}")
-                    };
-
-                    self.operand_stack.push(JvmValue::Int(array.length as i32));
-                }
-                Instruction::arraystore { data_type } => {
-                    let value = self.operand_stack.pop().unwrap();
-                    let index = self.operand_stack.pop().unwrap().try_as_int().unwrap();
-                    let ptr = self
-                        .operand_stack
-                        .pop()
-                        .unwrap()
-                        .try_as_reference()
-                        .unwrap();
-
-                    let header = unsafe { (ptr as *mut RefTypeHeader).as_mut().unwrap() };
-                    let RefTypeHeader::Array(array) = header else {
-                        bail!("invalid header: {header:?}")
-                    };
-
-                    match array.atype {
-                        ArrayType::Int => {
-                            if *data_type != ArrayLoadStoreType::Int {
-                                bail!("invalid array type: {:?}", array.atype);
-                            }
-
-                            unsafe {
-                                header.array_data::<i32>()?[index as usize] =
-                                    value.try_as_int().unwrap();
-                            }
-                        }
-                        t => todo!("{t:?}"),
-                    }
-                }
-                Instruction::putstatic { index } => unsafe {
-                    // This *should* be safe as long as no other references to the field value exist
-                    *self.get_static_field(*index)?.get() = self.operand_stack.pop().unwrap()
-                },
-                Instruction::getstatic { index } => unsafe {
-                    let value = self.get_static_field(*index)?;
-                    self.operand_stack.push((*value.get()).clone());
-                },
-                Instruction::aconst_null => {
-                    self.operand_stack.push(JvmValue::Reference(0));
-                }
-                Instruction::new { index } => {
-                    let target_class = self.class.constant_pool()[*index]
-                        .try_as_class_ref()
-                        .wrap_err("expected class")?;
-
-                    let target_class_name = self.class.constant_pool()[target_class.name_index]
-                        .try_as_utf_8_ref()
-                        .wrap_err("expected utf8")?;
-
-                    let target_class = self.vm.load_class_file(target_class_name)?;
-
-                    let fields_layout = Layout::array::<JvmValue>(target_class.fields().len())?;
-                    let (object_layout, _) =
-                        Layout::new::<RefTypeHeader>().extend(fields_layout)?;
-
-                    let layout = object_layout.pad_to_align();
-                    let ptr = self.vm.heap.alloc_layout(layout);
-
-                    unsafe {
-                        ptr.as_ptr()
-                            .cast::<RefTypeHeader>()
-                            .write(RefTypeHeader::Object(ObjectHeader {
-                                class: mem::transmute::<&Class<'_>, NonNull<Class<'_>>>(
-                                    target_class,
-                                ),
-                            }));
-
-                        let fields = ptr
-                            .as_ptr()
-                            .add(object_layout.size() - fields_layout.size())
-                            .cast::<JvmValue>();
-
-                        for (i, field) in target_class.fields().iter().enumerate() {
-                            fields.add(i).write(match &field.descriptor.field_type {
-                                FieldType::Base(t) => match t {
-                                    BaseType::Byte => todo!(),
-                                    BaseType::Char => todo!(),
-                                    BaseType::Double => todo!(),
-                                    BaseType::Float => todo!(),
-                                    BaseType::Int => JvmValue::Int(0),
-                                    BaseType::Long => todo!(),
-                                    BaseType::Short => todo!(),
-                                    BaseType::Boolean => JvmValue::Boolean(false),
-                                    BaseType::Object(_) => JvmValue::Reference(0),
-                                },
-                                FieldType::Array(_, _) => JvmValue::Reference(0),
-                            });
-                        }
-                    }
+        let array = self
+            .operand_stack
+            .pop()
+            .and_then(|v| v.try_as_reference())
+            .wrap_err("expected array reference")?;
 
-                    self.operand_stack
-                        .push(JvmValue::Reference(ptr.as_ptr() as usize));
-                }
-                Instruction::putfield { index } => {
-                    let value = self.operand_stack.pop().unwrap();
-                    *self.get_instance_field(*index)? = value;
-                }
-                Instruction::getfield { index } => {
-                    let value = self.get_instance_field(*index)?;
-                    self.operand_stack.push((*value).clone());
-                }
-                Instruction::dup => {
-                    self.operand_stack.push(
-                        self.operand_stack
-                            .last()
-                            .wrap_err("operand stack is empty")?
-                            .clone(),
-                    );
-                }
-                _ => todo!("unimplemented instruction: {instruction:?}"),
-            }
+        let Some(header) = (unsafe { (array as *mut RefTypeHeader).as_mut() }) else {
+            bail!(JavaException::null_pointer());
+        };
 
-            pc = pc
-                .checked_add_signed(next_instruction_offset)
-                .wrap_err("program counter overflowed")?;
+        let RefTypeHeader::Array(ArrayHeader { length, .. }) = header else {
+            bail!("invalid header: {header:?}")
+        };
+
+        if index < 0 || index as usize >= *length {
+            bail!(JavaException::new(
+                "java/lang/ArrayIndexOutOfBoundsException",
+                format!("Index {index} out of bounds for length {length}")
+            ));
         }
+
+        Ok((header, index as usize))
     }
 
-    fn get_static_field(&mut self, index: u16) -> eyre::Result<&'a UnsafeCell<JvmValue<'a>>> {
+    fn get_static_field(&mut self, index: u16) -> Result<&'a Mutex<JvmValue<'a>>> {
         let field_ref = self.class.constant_pool()[index]
             .try_as_field_ref_ref()
             .unwrap();
@@ -523,21 +2330,28 @@ impl<'a, 'b> CallFrame<'a, 'b> {
                 .try_as_utf_8_ref()
                 .wrap_err("expected utf8")?;
 
-            self.vm.load_class_file(target_class_name)?
+            self.vm.load_class(target_class_name)?
         };
 
-        target_class
-            .static_field(name, descriptor)
-            .wrap_err_with(|| {
-                let class_name = target_class.name();
-                eyre!("field {name}({descriptor}) does not exist on {class_name}")
-            })
+        // Only the class which declares the field is initialized, rather than the class it's
+        // referred to through
+        let Some((declaring_class, field)) =
+            self.vm.find_static_field(target_class, name, descriptor)?
+        else {
+            bail!(JavaException::new(
+                "java/lang/NoSuchFieldError",
+                format!("{}.{name}", target_class.name().replace('/', "."))
+            ));
+        };
+        self.vm.initialize_class(declaring_class)?;
+
+        Ok(field)
     }
 
-    fn get_instance_field(&mut self, index: u16) -> eyre::Result<&'b mut JvmValue<'a>> {
+    fn get_instance_field(&mut self, index: u16) -> Result<&'b mut JvmValue<'a>> {
         let field_ref = self.class.constant_pool()[index]
             .try_as_field_ref_ref()
-            .wrap_err_with(|| eyre!("unexpected: {:?}", self.class.constant_pool()[index]))?;
+            .wrap_err_with(|| format_err!("unexpected: {:?}", self.class.constant_pool()[index]))?;
 
         let name_and_type = self.class.constant_pool()[field_ref.name_and_type_index]
             .try_as_name_and_type_ref()
@@ -565,14 +2379,29 @@ impl<'a, 'b> CallFrame<'a, 'b> {
             self.vm.load_class_file(target_class_name)?
         };
 
-        let objectref = self
-            .operand_stack
-            .pop()
-            .unwrap()
-            .try_as_reference()
-            .unwrap();
+        // The field is resolved before the reference is checked, as it is by the JVM
+        let Some(field_index) = target_class.field_ordinal(name, descriptor) else {
+            bail!(JavaException::new(
+                "java/lang/NoSuchFieldError",
+                format!("{}.{name}", target_class.name().replace('/', "."))
+            ));
+        };
+
+        let objectref = match self.operand_stack.pop() {
+            Some(JvmValue::Reference(0)) => bail!(JavaException::null_pointer()),
+            Some(JvmValue::Reference(objectref)) => objectref,
+            // The JDK's implementation of String reads its fields
+            Some(JvmValue::StringConst(s)) => self.vm.string_object(s)?,
+            _ => bail!("expected object reference"),
+        };
 
-        let field_index = target_class.field_ordinal(name, descriptor).unwrap();
+        // The StringBuilder intrinsic keeps the contents of builders outside of their fields
+        if matches!(
+            target_class.name(),
+            "java/lang/AbstractStringBuilder" | "java/lang/StringBuilder"
+        ) {
+            self.vm.materialize_string_builder(objectref)?;
+        }
 
         let data = unsafe {
             std::slice::from_raw_parts_mut(
@@ -584,10 +2413,57 @@ impl<'a, 'b> CallFrame<'a, 'b> {
         Ok(&mut data[field_index])
     }
 
-    fn execute_invoke(&mut self, const_index: u16, kind: InvokeKind) -> eyre::Result<()> {
-        let method_ref = &self.class.constant_pool()[const_index]
-            .try_as_method_ref_ref()
-            .wrap_err("expected methodref")?;
+    /// Executes an invoke instruction. The method it calls is cached for the instruction, along
+    /// with the class of the receiver it was selected for, so calls which select the same method
+    /// as the last one skip resolution and selection entirely.
+    fn execute_invoke(&mut self, instruction: &'a Instruction, pc: usize) -> Result<Step<'a>> {
+        let Instruction::invoke { kind, index } = instruction else {
+            unreachable!()
+        };
+
+        let body = self.method.body.as_ref().wrap_err("missing method body")?;
+        let cache = &body.inline_caches[pc];
+
+        let cached = match cache.get() {
+            Some(call) => match call.receiver {
+                Some(receiver) => {
+                    let receiver_class = self.receiver_class(call.method)?;
+                    ptr::eq(receiver, receiver_class).then_some(call)
+                }
+                None => Some(call),
+            },
+            None => None,
+        };
+
+        let call = match cached {
+            Some(call) => call,
+            None => {
+                let call = self.resolve_invoke(*index, *kind)?;
+
+                // Static calls aren't cached until the class is initialized, so that other
+                // threads still wait for its initialization to finish
+                if !matches!(kind, InvokeKind::Static) || self.vm.is_initialized(call.class) {
+                    cache.set(call);
+                }
+
+                call
+            }
+        };
+
+        self.invoke(call.class, call.method, call.name, call.descriptor)?;
+
+        Ok(Step::Next)
+    }
+
+    /// Resolves the method referenced by an invoke instruction, and selects the method to call
+    /// for the receiver on the operand stack.
+    fn resolve_invoke(&self, const_index: u16, kind: InvokeKind) -> Result<CachedCall<'a>> {
+        let method_ref = match &self.class.constant_pool()[const_index] {
+            ConstantInfo::MethodRef(method_ref) | ConstantInfo::InterfaceMethodRef(method_ref) => {
+                method_ref
+            }
+            _ => bail!("expected methodref"),
+        };
 
         let name_and_type = self.class.constant_pool()[method_ref.name_and_type_index]
             .try_as_name_and_type_ref()
@@ -601,7 +2477,7 @@ impl<'a, 'b> CallFrame<'a, 'b> {
             .try_as_utf_8_ref()
             .wrap_err("expected utf8")?;
 
-        let mut target_class = if method_ref.class_index == self.class.index() {
+        let target_class = if method_ref.class_index == self.class.index() {
             self.class
         } else {
             let target_class = self.class.constant_pool()[method_ref.class_index]
@@ -612,188 +2488,211 @@ impl<'a, 'b> CallFrame<'a, 'b> {
                 .try_as_utf_8_ref()
                 .wrap_err("expected utf8")?;
 
-            self.vm.load_class_file(target_class_name)?
+            // Only invokestatic initializes the class (JVMS §5.5). Instance methods can only be
+            // called on objects, whose classes have already been initialized.
+            match kind {
+                InvokeKind::Static => self.vm.load_class_file(target_class_name)?,
+                _ => self.vm.load_class(target_class_name)?,
+            }
         };
 
         // TODO: Do we need to ignore super class for static methods?
-        let method = loop {
-            let method = target_class.method(name, descriptor);
-            if let Some(method) = method {
-                break method;
-            }
-
-            target_class = target_class
-                .super_class()
-                .wrap_err_with(|| eyre!("method not found: {name}{descriptor}"))?;
+        let (target_class, method) = match self.vm.find_method(target_class, name, descriptor) {
+            Ok(found) => found,
+            // Methods can also be inherited from superinterfaces (JVMS §5.4.3.3, §5.4.3.4)
+            Err(e) => self
+                .vm
+                .superinterfaces(target_class)?
+                .into_iter()
+                .find_map(|interface| Some((interface, interface.method(name, descriptor)?)))
+                .ok_or(e)?,
         };
 
         match kind {
-            InvokeKind::Static => {
-                if method.access_flags.contains(MethodAccessFlags::NATIVE) {
-                    match name.as_str() {
-                        "registerNatives" => {
-                            // TODO
-                        }
-                        "print" => {
-                            let arg = self
-                                .operand_stack
-                                .pop()
-                                .wrap_err("missing argument to print")?;
+            InvokeKind::Static | InvokeKind::Special => Ok(CachedCall {
+                receiver: None,
+                class: target_class,
+                method,
+                name,
+                descriptor,
+            }),
+            InvokeKind::Virtual | InvokeKind::Interface { .. } => {
+                // TODO: Handle signature polymorphic methods (https://docs.oracle.com/javase/specs/jvms/se21/html/jvms-6.html#jvms-6.5.invokevirtual)
 
-                            self.print_jvm_value(&arg)?;
-                        }
-                        "currentTimeMillis" => self.operand_stack.push(JvmValue::Long(
-                            self.vm
-                                .time
-                                .system_time()
-                                .duration_since(SystemTime::UNIX_EPOCH)?
-                                .as_millis()
-                                .try_into()?,
-                        )),
-                        _ => unimplemented!("{name}{descriptor}"),
-                    }
-                } else {
-                    let args = method
-                        .descriptor
-                        .params
-                        .iter()
-                        .map(|_| self.operand_stack.pop().unwrap())
-                        .map(|op| match op {
-                            JvmValue::Int(v) => JvmValue::Int(v),
-                            op => todo!("{op:?}"),
-                        });
-
-                    if let Some(ret) =
-                        CallFrame::new(self.class, method, args, self.vm)?.execute()?
-                    {
-                        self.operand_stack.push(ret);
-                    }
+                if method.access_flags.contains(MethodAccessFlags::PRIVATE) {
+                    return Ok(CachedCall {
+                        receiver: None,
+                        class: target_class,
+                        method,
+                        name,
+                        descriptor,
+                    });
                 }
-            }
-            InvokeKind::Special => {
-                let nargs = method.descriptor.params.len() + 1; // args + objectref
-                let args_start = self.operand_stack.len() - nargs;
 
-                let args = &self.operand_stack[args_start..];
-                let args = args.iter().cloned();
+                let object_class = self.receiver_class(method)?;
+                let (class, method) = if target_class
+                    .access_flags()
+                    .contains(ClassAccessFlags::INTERFACE)
+                {
+                    self.select_interface_method(
+                        object_class,
+                        target_class,
+                        method,
+                        name,
+                        descriptor,
+                    )?
+                } else {
+                    self.vm.find_method(object_class, name, descriptor)?
+                };
 
-                let ret_value = CallFrame::new(target_class, method, args, self.vm)?.execute()?;
+                Ok(CachedCall {
+                    receiver: Some(object_class),
+                    class,
+                    method,
+                    name,
+                    descriptor,
+                })
+            }
+            InvokeKind::Dynamic => {
+                bail!(
+                    "unimplemented: invokedynamic of {}.{name}{descriptor}",
+                    target_class.name()
+                )
+            }
+        }
+    }
 
-                self.operand_stack
-                    .truncate(self.operand_stack.len() - nargs);
+    /// Returns the class of the receiver of a call to an instance method, which is below the
+    /// method's arguments on the operand stack.
+    fn receiver_class(&self, method: &Method<'a>) -> Result<&'a Class<'a>> {
+        let receiver = self
+            .operand_stack
+            .len()
+            .checked_sub(method.descriptor.params.len() + 1)
+            .and_then(|index| self.operand_stack.get(index))
+            .wrap_err("missing receiver")?;
 
-                if let Some(ret) = ret_value {
-                    self.operand_stack.push(ret);
-                }
-            }
-            InvokeKind::Virtual => {
-                // TODO: Handle signature polymorphic methods (https://docs.oracle.com/javase/specs/jvms/se21/html/jvms-6.html#jvms-6.5.invokevirtual)
+        self.vm.runtime_class(&receiver)
+    }
 
-                let nargs = method.descriptor.params.len() + 1; // args + objectref
-                let args_start = self.operand_stack.len() - nargs;
+    /// Calls a method which has been selected, popping its arguments (including the receiver for
+    /// instance methods) from the operand stack and pushing its return value, if any.
+    fn invoke(
+        &mut self,
+        class: &'a Class<'a>,
+        method: &'a Method<'a>,
+        name: &str,
+        descriptor: &str,
+    ) -> Result<()> {
+        if let Some(native) = self.vm.resolve_native(class, method, name, descriptor)? {
+            return self.invoke_native(&*native, class, method);
+        }
 
-                let args = &self.operand_stack[args_start..];
+        let mut nargs = method.descriptor.params.len();
+        if !method.access_flags.contains(MethodAccessFlags::STATIC) {
+            nargs += 1;
+        }
 
-                // TODO: Resolve interface methods
+        let args_start = self
+            .operand_stack
+            .len()
+            .checked_sub(nargs)
+            .wrap_err("missing arguments to method")?;
 
-                let (selected_class, selected_method) = if method
-                    .access_flags
-                    .contains(MethodAccessFlags::PRIVATE)
-                {
-                    (target_class, method)
-                } else {
-                    let objectref = args[0].try_as_reference_ref().copied().unwrap();
-                    let header = objectref as *mut RefTypeHeader;
-
-                    let mut object_class: &'a Class<'a> = unsafe {
-                        match header.as_ref().unwrap() {
-                            RefTypeHeader::Object(header) => {
-                                mem::transmute::<&Class<'_>, &'a Class<'a>>(header.class.as_ref())
-                            }
-                            RefTypeHeader::Array(_) => todo!(),
-                        }
-                    };
+        let ret_value =
+            CallFrame::from_operand_stack(class, method, &self.operand_stack, args_start, self.vm)?
+                .execute()?;
 
-                    loop {
-                        let method = object_class.method(name, descriptor);
-                        if let Some(method) = method {
-                            break (object_class, method);
-                        }
+        self.operand_stack.truncate(args_start);
 
-                        object_class = object_class
-                            .super_class()
-                            .wrap_err_with(|| eyre!("method not found: {name}{descriptor}"))?;
-                    }
-                };
+        if let Some(ret) = ret_value {
+            self.operand_stack.push(ret);
+        }
+
+        Ok(())
+    }
 
-                let args = args.iter().cloned();
+    /// Selects the implementation of an interface method for the class of the receiver, using
+    /// the class's interface method table.
+    fn select_interface_method(
+        &self,
+        object_class: &'a Class<'a>,
+        interface: &'a Class<'a>,
+        method: &'a Method<'a>,
+        name: &str,
+        descriptor: &str,
+    ) -> Result<(&'a Class<'a>, &'a Method<'a>)> {
+        let Some(itable) = self.vm.itable(object_class, interface.name())? else {
+            bail!(JavaException::new(
+                "java/lang/IncompatibleClassChangeError",
+                format!(
+                    "Class {} does not implement the requested interface {}",
+                    object_class.name().replace('/', "."),
+                    interface.name().replace('/', ".")
+                )
+            ));
+        };
 
-                let ret_value =
-                    CallFrame::new(selected_class, selected_method, args, self.vm)?.execute()?;
+        let Some(entry) = itable[method.slot] else {
+            bail!(JavaException::new(
+                "java/lang/AbstractMethodError",
+                format!(
+                    "Receiver class {} does not define or inherit an implementation of the \
+                     resolved method '{name}{descriptor}' of interface {}.",
+                    object_class.name().replace('/', "."),
+                    interface.name().replace('/', ".")
+                )
+            ));
+        };
 
-                self.operand_stack
-                    .truncate(self.operand_stack.len() - nargs);
+        Ok((entry.class, entry.method))
+    }
 
-                if let Some(ret) = ret_value {
-                    self.operand_stack.push(ret);
-                }
-            }
-            _ => {
-                todo!("{}::{name}({descriptor}) ({kind:?})", target_class.name())
-            }
+    /// Executes a native method, popping its arguments (including the receiver for instance
+    /// methods) from the operand stack and pushing its return value, if any.
+    fn invoke_native(
+        &mut self,
+        native: &NativeMethod<'a>,
+        class: &'a Class<'a>,
+        method: &'a Method<'a>,
+    ) -> Result<()> {
+        let mut nargs = method.descriptor.params.len();
+        if !method.access_flags.contains(MethodAccessFlags::STATIC) {
+            nargs += 1;
         }
 
-        Ok(())
-    }
+        let args_start = self
+            .operand_stack
+            .len()
+            .checked_sub(nargs)
+            .wrap_err("missing arguments to native method")?;
 
-    fn print_jvm_value(&mut self, value: &JvmValue) -> eyre::Result<()> {
-        match value {
-            JvmValue::StringConst(v) => write!(self.vm.stdout, "{v}")?,
-            JvmValue::Byte(v) => write!(self.vm.stdout, "{v}")?,
-            JvmValue::Int(v) => write!(self.vm.stdout, "{v}")?,
-            JvmValue::Long(v) => write!(self.vm.stdout, "{v}")?,
-            JvmValue::Reference(ptr) => {
-                let header = unsafe { (*ptr as *mut RefTypeHeader).as_mut() };
+        let args = self.operand_stack.iter_from(args_start).collect::<Vec<_>>();
+        let ret = self.vm.call_native(native, class, method, &args)?;
 
-                match header {
-                    None => {
-                        write!(self.vm.stdout, "null")?;
-                    }
-                    Some(header) => match header {
-                        RefTypeHeader::Array(array) => match array.atype {
-                            ArrayType::Int => {
-                                let elements = unsafe { header.array_data::<i32>()? };
-                                write!(self.vm.stdout, "{elements:?}")?
-                            }
-                            t => todo!("{t:?}"),
-                        },
-                        RefTypeHeader::Object(object) => {
-                            let class = unsafe { object.class.as_ref() };
-                            let fields = unsafe { header.object_data() }?;
-
-                            write!(self.vm.stdout, "{} {{", class.name())?;
-
-                            for (i, field) in class.fields().iter().enumerate() {
-                                let name = field.name;
-                                let value = &fields[i];
-
-                                write!(self.vm.stdout, "{name}: ")?;
-
-                                self.print_jvm_value(value)?;
-
-                                if i < fields.len() - 1 {
-                                    write!(self.vm.stdout, ", ")?;
-                                }
-                            }
-
-                            write!(self.vm.stdout, "}}")?;
-                        }
-                    },
-                };
-            }
-            arg => todo!("{arg:?}"),
+        self.operand_stack.truncate(args_start);
+
+        if let Some(ret) = ret {
+            self.operand_stack.push(ret);
         }
 
         Ok(())
     }
 }
+
+/// The exception thrown when an integer is divided by zero.
+fn division_by_zero() -> JavaException {
+    JavaException::new("java/lang/ArithmeticException", "/ by zero")
+}
+
+/// Compares two ints, as the `if` and `if_icmp` instructions do.
+fn compare(condition: Condition, v1: i32, v2: i32) -> bool {
+    match condition {
+        Condition::Eq => v1 == v2,
+        Condition::Ne => v1 != v2,
+        Condition::Lt => v1 < v2,
+        Condition::Le => v1 <= v2,
+        Condition::Gt => v1 > v2,
+        Condition::Ge => v1 >= v2,
+    }
+}
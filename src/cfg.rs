@@ -0,0 +1,234 @@
+//! Splits the decoded instructions of a method into basic blocks, and connects them into a
+//! control-flow graph.
+//!
+//! A basic block is a run of instructions which is only entered at its first instruction, and
+//! which only transfers control elsewhere at its last instruction. Exception handlers are
+//! treated as separate edges, since any instruction covered by a handler can throw.
+
+use crate::class::switch_targets;
+use crate::class_file::CodeAttribute;
+use crate::error::{bail, format_err, ContextCompat, Result};
+use crate::instructions::Instruction;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BasicBlock {
+    /// The index of the first instruction in the block.
+    pub start: usize,
+    /// The index after the last instruction in the block.
+    pub end: usize,
+    /// The blocks which can be executed after this one, with the fallthrough block first.
+    pub successors: Vec<usize>,
+    /// The blocks which have this one as a successor, in ascending order.
+    pub predecessors: Vec<usize>,
+    /// The handler blocks of the exception table entries which cover this block, in the order
+    /// they're checked.
+    pub handlers: Vec<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ControlFlowGraph {
+    /// The blocks of the method, in the order of their instructions. The entry block comes first.
+    pub blocks: Vec<BasicBlock>,
+    /// The index of the block containing each instruction.
+    block_indices: Vec<usize>,
+}
+
+impl ControlFlowGraph {
+    /// Builds the graph for a method's code, from the instructions and offsets returned by
+    /// [`decode_instructions`](crate::class::decode_instructions).
+    pub fn new(
+        code: &CodeAttribute,
+        instructions: &[Instruction],
+        offsets: &[u32],
+    ) -> Result<ControlFlowGraph> {
+        if instructions.is_empty() {
+            bail!("method has no instructions");
+        }
+
+        let instruction_index = |pc: u32| {
+            if pc as usize == code.code.len() {
+                return Ok(instructions.len());
+            }
+            offsets
+                .binary_search(&pc)
+                .map_err(|_| format_err!("pc {pc} isn't at an instruction"))
+        };
+
+        let mut targets = Vec::with_capacity(instructions.len());
+        for (index, instruction) in instructions.iter().enumerate() {
+            let branch = |branch: isize| {
+                index
+                    .checked_add_signed(branch)
+                    .filter(|target| *target < instructions.len())
+                    .wrap_err_with(|| format_err!("invalid branch target at instruction {index}"))
+            };
+
+            targets.push(match instruction {
+                Instruction::r#if { branch: offset, .. }
+                | Instruction::if_icmp { branch: offset, .. }
+                | Instruction::if_acmp { branch: offset, .. }
+                | Instruction::ifnull { branch: offset }
+                | Instruction::ifnonnull { branch: offset } => {
+                    Some((true, vec![branch(*offset as isize)?]))
+                }
+                Instruction::goto { branch: offset } => {
+                    Some((false, vec![branch(*offset as isize)?]))
+                }
+                Instruction::tableswitch {} | Instruction::lookupswitch {} => {
+                    let targets = switch_targets(code.code, offsets[index])?
+                        .into_iter()
+                        .map(|pc| {
+                            instruction_index(pc)
+                                .ok()
+                                .filter(|target| *target < instructions.len())
+                                .wrap_err_with(|| format_err!("invalid switch target: {pc}"))
+                        })
+                        .collect::<Result<_>>()?;
+                    Some((false, targets))
+                }
+                Instruction::r#return { .. } | Instruction::athrow => Some((false, vec![])),
+                Instruction::jsr { .. } | Instruction::ret { .. } => {
+                    bail!("jsr and ret aren't supported")
+                }
+                _ => None,
+            });
+        }
+
+        let mut handlers = vec![];
+        for entry in &code.exception_table {
+            let start = instruction_index(entry.start_pc as u32)?;
+            let end = instruction_index(entry.end_pc as u32)?;
+            let handler = instruction_index(entry.handler_pc as u32)?;
+            if handler == instructions.len() {
+                bail!("exception handler pc {} is out of bounds", entry.handler_pc);
+            }
+            handlers.push((start, end, handler));
+        }
+
+        // Blocks start at the entry point, at every jump target, after every jump, and at the
+        // boundaries of the ranges covered by exception handlers
+        let mut leaders = vec![false; instructions.len() + 1];
+        leaders[0] = true;
+        for (index, targets) in targets.iter().enumerate() {
+            if let Some((_, targets)) = targets {
+                leaders[index + 1] = true;
+                for target in targets {
+                    leaders[*target] = true;
+                }
+            }
+        }
+        for (start, end, handler) in &handlers {
+            leaders[*start] = true;
+            leaders[*end] = true;
+            leaders[*handler] = true;
+        }
+
+        let mut blocks = vec![];
+        let mut block_indices = Vec::with_capacity(instructions.len());
+        for (index, is_leader) in leaders[..instructions.len()].iter().enumerate() {
+            if *is_leader {
+                blocks.push(BasicBlock {
+                    start: index,
+                    end: index,
+                    successors: vec![],
+                    predecessors: vec![],
+                    handlers: vec![],
+                });
+            }
+            blocks.last_mut().unwrap().end = index + 1;
+            block_indices.push(blocks.len() - 1);
+        }
+
+        for i in 0..blocks.len() {
+            let last = blocks[i].end - 1;
+            let successors = match &targets[last] {
+                Some((falls_through, targets)) => {
+                    let mut successors = vec![];
+                    if *falls_through {
+                        successors.push(next_block(&block_indices, last)?);
+                    }
+                    for target in targets {
+                        let target = block_indices[*target];
+                        if !successors.contains(&target) {
+                            successors.push(target);
+                        }
+                    }
+                    successors
+                }
+                None => vec![next_block(&block_indices, last)?],
+            };
+
+            for successor in &successors {
+                if !blocks[*successor].predecessors.contains(&i) {
+                    blocks[*successor].predecessors.push(i);
+                }
+            }
+
+            blocks[i].successors = successors;
+            blocks[i].handlers = handlers
+                .iter()
+                .filter(|(start, end, _)| (*start..*end).contains(&blocks[i].start))
+                .map(|(_, _, handler)| block_indices[*handler])
+                .collect();
+        }
+
+        for block in &mut blocks {
+            block.predecessors.sort_unstable();
+        }
+
+        Ok(ControlFlowGraph {
+            blocks,
+            block_indices,
+        })
+    }
+
+    /// Returns the index of the block containing an instruction.
+    pub fn block_index(&self, instruction: usize) -> usize {
+        self.block_indices[instruction]
+    }
+
+    /// Returns the indices of the blocks which can be reached from the entry block, either
+    /// directly or through an exception handler, in reverse postorder. This visits each block
+    /// before its successors, except along the back edges of loops.
+    pub fn reverse_postorder(&self) -> Vec<usize> {
+        let mut visited = vec![false; self.blocks.len()];
+        let mut order = Vec::with_capacity(self.blocks.len());
+        let mut stack = vec![(0, 0)];
+        visited[0] = true;
+
+        while let Some((block, next)) = stack.last_mut() {
+            let edges = &self.blocks[*block];
+            let successor = edges
+                .successors
+                .iter()
+                .chain(&edges.handlers)
+                .nth(*next)
+                .copied();
+
+            match successor {
+                Some(successor) => {
+                    *next += 1;
+                    if !visited[successor] {
+                        visited[successor] = true;
+                        stack.push((successor, 0));
+                    }
+                }
+                None => {
+                    order.push(*block);
+                    stack.pop();
+                }
+            }
+        }
+
+        order.reverse();
+        order
+    }
+}
+
+/// Returns the block which execution falls through to after an instruction.
+fn next_block(block_indices: &[usize], index: usize) -> Result<usize> {
+    block_indices
+        .get(index + 1)
+        .copied()
+        .wrap_err("execution falls off the end of the code")
+}
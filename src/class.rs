@@ -1,26 +1,33 @@
-use std::cell::UnsafeCell;
 use std::fmt::Debug;
 use std::io::{self, Cursor};
 use std::num::NonZeroU8;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::{Mutex, OnceLock};
 
 use bumpalo::collections::Vec;
 use bumpalo::{vec, Bump};
 use byteorder::{BigEndian, ReadBytesExt};
-use color_eyre::eyre::{self, bail, eyre, Context, ContextCompat};
-use hashbrown::HashMap;
+use hashbrown::{Equivalent, HashMap};
 
-use crate::call_frame::JvmValue;
+use crate::call_frame::{self, InlineCache, JvmValue};
+use crate::class_archive::DecodedCode;
 use crate::class_file::constant_pool::ConstantPool;
-use crate::class_file::{ClassFile, FieldAccessFlags, MethodAccessFlags};
+use crate::class_file::{
+    AttributeInfo, ClassAccessFlags, ClassFile, CodeAttribute, ExceptionTableEntry,
+    FieldAccessFlags, LineNumberTableAttribute, MethodAccessFlags,
+};
 use crate::descriptor::{
     parse_field_descriptor, parse_method_descriptor, BaseType, FieldDescriptor, FieldType,
     MethodDescriptor,
 };
+use crate::error::{bail, format_err, Context, ContextCompat, Error, Result};
 use crate::instructions::{
     ArrayLoadStoreType, ArrayType, Condition, EqCondition, Instruction, IntegerType, InvokeKind,
-    NumberType, OrdCondition, ReturnType,
+    LoadStoreType, NumberType, OrdCondition, ReturnType,
 };
 use crate::opcodes::OpCode;
+use crate::superinstructions::{self, Superinstruction};
+use crate::symbols;
 
 #[derive(Debug)]
 pub struct Class<'a> {
@@ -28,7 +35,10 @@ pub struct Class<'a> {
     class_file: &'a ClassFile<'a>,
     super_class: Option<&'a Class<'a>>,
     methods: HashMap<MethodId<'a>, Method<'a>>,
-    static_fields: HashMap<(&'a str, &'a str), UnsafeCell<JvmValue<'a>>>,
+    /// The interface method table for each interface the class implements, by interface name.
+    /// These are built by the vm the first time an interface method is called on an instance.
+    itables: OnceLock<HashMap<&'a str, Itable<'a>>>,
+    static_fields: HashMap<(&'a str, &'a str), Mutex<JvmValue<'a>>>,
     fields: std::vec::Vec<Field<'a>>,
     field_ordinals: HashMap<(&'a str, &'a str), usize>,
 }
@@ -38,6 +48,62 @@ pub struct Method<'a> {
     pub descriptor: MethodDescriptor<'a>,
     pub access_flags: MethodAccessFlags,
     pub body: Option<MethodBody<'a>>,
+    /// The index of the method in its class file, which is also its index in the class's
+    /// [`Class::declared_methods`] and in interface method tables.
+    pub slot: usize,
+    pub hotness: Hotness,
+    #[cfg(feature = "jit")]
+    pub(crate) jit: crate::jit::MethodState<'a>,
+}
+
+/// Counts how often a method runs, which decides when the vm optimizes it.
+#[derive(Debug, Default)]
+pub struct Hotness {
+    invocations: AtomicU64,
+    /// Backward branches taken, which is roughly the number of iterations of the method's loops.
+    backward_branches: AtomicU64,
+}
+
+impl Hotness {
+    pub fn invocations(&self) -> u64 {
+        self.invocations.load(Ordering::Relaxed)
+    }
+
+    pub fn backward_branches(&self) -> u64 {
+        self.backward_branches.load(Ordering::Relaxed)
+    }
+
+    /// The invocations and backward branches together, which measures how much of the method has
+    /// been executed regardless of whether it's called often or loops for a long time.
+    pub fn total(&self) -> u64 {
+        self.invocations() + self.backward_branches()
+    }
+
+    pub(crate) fn record_invocation(&self) {
+        self.invocations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_backward_branch(&self) {
+        self.backward_branches.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// The methods selected to implement each method of an interface for a class, indexed by the
+/// interface method's slot. Slots are `None` for static and private interface methods, and for
+/// abstract methods which the class doesn't implement.
+pub type Itable<'a> = std::vec::Vec<Option<ItableEntry<'a>>>;
+
+#[derive(Clone, Copy)]
+pub struct ItableEntry<'a> {
+    pub class: &'a Class<'a>,
+    pub method: &'a Method<'a>,
+}
+
+impl Debug for ItableEntry<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Only the name of the class is shown, since its itables can refer back to itself
+        write!(f, "{}", self.class.name())
+    }
 }
 
 #[derive(Debug)]
@@ -45,6 +111,42 @@ pub struct MethodBody<'a> {
     pub locals: usize,
     pub stack_size: usize,
     pub code: Vec<'a, Instruction>,
+    /// The bytecode offset of each instruction, which the exception table and attributes like
+    /// `LineNumberTable` refer to instructions by.
+    pub offsets: Vec<'a, u32>,
+    /// The cache for each instruction, although only those of invoke instructions are used.
+    pub inline_caches: std::vec::Vec<InlineCache<'a>>,
+    /// The superinstruction which starts at each instruction, if any. Instructions are only fused
+    /// once the method is hot, by [`MethodBody::fuse_superinstructions`].
+    pub superinstructions: OnceLock<std::vec::Vec<Option<Superinstruction>>>,
+    /// The index of the interpreter's handler for each instruction, which is changed to the
+    /// superinstruction handler where a sequence is fused. Frames which are already executing the
+    /// method pick up the change at their next instruction.
+    pub handlers: std::vec::Vec<AtomicU8>,
+}
+
+impl MethodBody<'_> {
+    /// Fuses sequences of instructions into superinstructions, if they haven't been fused yet.
+    pub fn fuse_superinstructions(&self) {
+        let superinstructions = self
+            .superinstructions
+            .get_or_init(|| superinstructions::fuse(&self.code));
+
+        for (handler, superinstruction) in self.handlers.iter().zip(superinstructions) {
+            if superinstruction.is_some() {
+                handler.store(
+                    call_frame::SUPERINSTRUCTION_HANDLER as u8,
+                    Ordering::Relaxed,
+                );
+            }
+        }
+    }
+
+    /// Returns the index of the instruction at a bytecode offset, or `None` if no instruction
+    /// starts there.
+    pub fn instruction_index(&self, offset: u32) -> Option<usize> {
+        self.offsets.binary_search(&offset).ok()
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -58,26 +160,39 @@ impl<'a> Class<'a> {
     pub fn new(
         arena: &'a Bump,
         class_file: &'a ClassFile,
-        class_loader: &mut dyn FnMut(&str) -> eyre::Result<&'a Class<'a>>,
-    ) -> eyre::Result<Class<'a>> {
+        super_class: Option<&'a Class<'a>>,
+    ) -> Result<Class<'a>> {
+        Class::with_code(arena, class_file, super_class, |_, attr| {
+            decode_instructions(arena, attr)
+        })
+    }
+
+    /// Creates a class whose methods' code has already been decoded, such as a class read from a
+    /// class archive. The code is given in the order of the class file's methods.
+    pub(crate) fn with_decoded_code(
+        arena: &'a Bump,
+        class_file: &'a ClassFile,
+        super_class: Option<&'a Class<'a>>,
+        code: std::vec::Vec<Option<DecodedCode<'a>>>,
+    ) -> Result<Class<'a>> {
+        let mut code = code;
+        Class::with_code(arena, class_file, super_class, |slot, _| {
+            code.get_mut(slot)
+                .and_then(Option::take)
+                .wrap_err("missing decoded code")
+        })
+    }
+
+    fn with_code(
+        arena: &'a Bump,
+        class_file: &'a ClassFile,
+        super_class: Option<&'a Class<'a>>,
+        mut decode: impl FnMut(usize, &CodeAttribute) -> Result<DecodedCode<'a>>,
+    ) -> Result<Class<'a>> {
         let this_class = class_file.constant_pool[class_file.this_class]
             .try_as_class_ref()
             .unwrap();
 
-        let super_class = if class_file.super_class == 0 {
-            None
-        } else {
-            class_file.constant_pool[class_file.super_class]
-                .try_as_class_ref()
-                .map(|class| {
-                    let name = class_file.constant_pool[class.name_index]
-                        .try_as_utf_8_ref()
-                        .unwrap();
-                    class_loader(name)
-                })
-                .transpose()?
-        };
-
         let name = class_file.constant_pool[this_class.name_index]
             .try_as_utf_8_ref()
             .unwrap();
@@ -112,10 +227,7 @@ impl<'a> Class<'a> {
                 access_flags: field.access_flags.clone(),
             });
 
-            field_ordinals.insert(
-                (name.as_str(), descriptor_str.as_str()),
-                field_ordinals.len(),
-            );
+            field_ordinals.insert((*name, *descriptor_str), field_ordinals.len());
         }
 
         Ok(Class {
@@ -124,7 +236,7 @@ impl<'a> Class<'a> {
             super_class,
             methods: {
                 let mut methods = HashMap::new();
-                for method in &class_file.methods {
+                for (slot, method) in class_file.methods.iter().enumerate() {
                     let name = class_file
                         .constant_pool
                         .get(method.name_index)
@@ -142,22 +254,37 @@ impl<'a> Class<'a> {
                     methods.insert(
                         MethodId { name, descriptor },
                         Method {
-                            descriptor: parse_method_descriptor(descriptor).wrap_err_with(
-                                || eyre!("invalid method descriptor: {descriptor}"),
+                            descriptor: parse_method_descriptor(arena, descriptor).wrap_err_with(
+                                || format_err!("invalid method descriptor: {descriptor}"),
                             )?,
                             access_flags: method.access_flags,
                             body: method
                                 .attributes
                                 .iter()
                                 .find_map(|attr| attr.try_as_code_ref())
-                                .map(|attr| -> eyre::Result<MethodBody> {
+                                .map(|attr| -> Result<MethodBody> {
+                                    let (code, offsets) =
+                                        decode(slot, attr).wrap_err_with(|| {
+                                            format_err!("invalid code in {name}{descriptor}")
+                                        })?;
                                     Ok(MethodBody {
                                         locals: attr.max_locals as usize,
                                         stack_size: attr.max_stack as usize,
-                                        code: decode_instructions(arena, attr.code.as_slice())?,
+                                        inline_caches: code
+                                            .iter()
+                                            .map(|_| InlineCache::default())
+                                            .collect(),
+                                        handlers: call_frame::handler_indices(&code),
+                                        superinstructions: OnceLock::new(),
+                                        code,
+                                        offsets,
                                     })
                                 })
                                 .transpose()?,
+                            slot,
+                            hotness: Hotness::default(),
+                            #[cfg(feature = "jit")]
+                            jit: Default::default(),
                         },
                     );
                 }
@@ -178,24 +305,26 @@ impl<'a> Class<'a> {
 
                     let descriptor = parse_field_descriptor(descriptor_str)?;
 
-                    let value = UnsafeCell::new(match descriptor.field_type {
+                    let value = Mutex::new(match descriptor.field_type {
                         FieldType::Base(t) => match t {
-                            BaseType::Byte => JvmValue::Byte(0),
-                            BaseType::Char => JvmValue::Char(0),
+                            // Stored as ints, as they are on the operand stack
+                            BaseType::Byte
+                            | BaseType::Char
+                            | BaseType::Int
+                            | BaseType::Short
+                            | BaseType::Boolean => JvmValue::Int(0),
                             BaseType::Double => JvmValue::Double(0.0),
                             BaseType::Float => JvmValue::Float(0.0),
-                            BaseType::Int => JvmValue::Int(0),
                             BaseType::Long => JvmValue::Long(0),
-                            BaseType::Short => JvmValue::Short(0),
-                            BaseType::Boolean => JvmValue::Boolean(false),
                             BaseType::Object(_) => JvmValue::Reference(0),
                         },
                         FieldType::Array(_, _) => JvmValue::Reference(0),
                     });
 
-                    Ok(((name.as_str(), descriptor_str.as_str()), value))
+                    Ok(((*name, *descriptor_str), value))
                 })
-                .collect::<eyre::Result<_>>()?,
+                .collect::<Result<_>>()?,
+            itables: OnceLock::new(),
             fields,
             field_ordinals,
         })
@@ -209,312 +338,327 @@ impl<'a> Class<'a> {
         self.name
     }
 
+    pub fn class_file(&self) -> &'a ClassFile<'a> {
+        self.class_file
+    }
+
+    pub fn access_flags(&self) -> &ClassAccessFlags {
+        &self.class_file.access_flags
+    }
+
     pub fn super_class(&self) -> Option<&'a Class<'a>> {
         self.super_class
     }
 
-    pub fn method<'b: 'a>(&'a self, name: &'b str, descriptor: &'b str) -> Option<&'a Method<'a>> {
-        self.methods.get(&MethodId { name, descriptor })
+    pub fn method(&self, name: &str, descriptor: &str) -> Option<&Method<'a>> {
+        self.methods.get(&MethodIdRef { name, descriptor })
+    }
+
+    /// Returns the name, descriptor and method for each method declared by the class, in the
+    /// order they appear in the class file.
+    pub fn declared_methods(&self) -> impl Iterator<Item = (&'a str, &'a str, &Method<'a>)> + '_ {
+        let constant_pool = &self.class_file.constant_pool;
+        self.class_file.methods.iter().filter_map(move |info| {
+            let name = constant_pool[info.name_index].try_as_utf_8_ref().copied()?;
+            let descriptor = constant_pool[info.descriptor_index]
+                .try_as_utf_8_ref()
+                .copied()?;
+            Some((name, descriptor, self.method(name, descriptor)?))
+        })
+    }
+
+    /// Returns the name, descriptor and access flags for each field declared by the class,
+    /// including static fields, in the order they appear in the class file.
+    pub fn declared_fields(
+        &self,
+    ) -> impl Iterator<Item = (&'a str, &'a str, &'a FieldAccessFlags)> + '_ {
+        let constant_pool = &self.class_file.constant_pool;
+        self.class_file.fields.iter().filter_map(move |info| {
+            let name = constant_pool[info.name_index].try_as_utf_8_ref().copied()?;
+            let descriptor = constant_pool[info.descriptor_index]
+                .try_as_utf_8_ref()
+                .copied()?;
+            Some((name, descriptor, &info.access_flags))
+        })
+    }
+
+    /// Returns the generic signature of the class, if it's generic or extends a generic type.
+    pub fn signature(&self) -> Option<&'a str> {
+        signature(self.class_file, &self.class_file.attributes)
+    }
+
+    /// Returns the generic signature of a method, given its index in [`Class::declared_methods`].
+    pub fn declared_method_signature(&self, slot: usize) -> Option<&'a str> {
+        signature(
+            self.class_file,
+            &self.class_file.methods.get(slot)?.attributes,
+        )
+    }
+
+    /// Returns the generic signature of a field, given its index in [`Class::declared_fields`].
+    pub fn declared_field_signature(&self, slot: usize) -> Option<&'a str> {
+        signature(
+            self.class_file,
+            &self.class_file.fields.get(slot)?.attributes,
+        )
+    }
+
+    /// Returns the name of the source file which the class was compiled from, if it was recorded.
+    pub fn source_file(&self) -> Option<&'a str> {
+        let index = self
+            .class_file
+            .attributes
+            .iter()
+            .find_map(|attribute| match attribute {
+                AttributeInfo::SourceFile(source_file) => Some(source_file.sourcefile_index),
+                _ => None,
+            })?;
+        self.class_file
+            .constant_pool
+            .get(index)?
+            .try_as_utf_8_ref()
+            .copied()
+    }
+
+    /// Returns the line number table of a method, given its index in
+    /// [`Class::declared_methods`], if the class was compiled with line numbers.
+    pub fn line_number_table(&self, slot: usize) -> Option<&'a LineNumberTableAttribute<'a>> {
+        let info = self.class_file.methods.get(slot)?;
+        let code = info.attributes.iter().find_map(|a| a.try_as_code_ref())?;
+        code.attributes.iter().find_map(|a| match a {
+            AttributeInfo::LineNumberTable(table) => Some(table),
+            _ => None,
+        })
+    }
+
+    /// Returns the `Code` attribute of a method, given its index in
+    /// [`Class::declared_methods`], or `None` if it's abstract or native.
+    pub fn code(&self, slot: usize) -> Option<&'a CodeAttribute<'a>> {
+        let info = self.class_file.methods.get(slot)?;
+        info.attributes.iter().find_map(|a| a.try_as_code_ref())
+    }
+
+    /// Returns the exception handlers of a method, in the order they're searched, given the
+    /// method's index in [`Class::declared_methods`].
+    pub fn exception_table(&self, slot: usize) -> &'a [ExceptionTableEntry] {
+        self.code(slot).map_or(&[], |code| &code.exception_table)
+    }
+
+    /// Returns the source line of the instruction at a bytecode offset in a method, given the
+    /// method's index in [`Class::declared_methods`].
+    pub fn line_number(&self, slot: usize, offset: u32) -> Option<u16> {
+        self.line_number_table(slot)?
+            .line_number_table
+            .iter()
+            .filter(|entry| u32::from(entry.start_pc) <= offset)
+            .max_by_key(|entry| entry.start_pc)
+            .map(|entry| entry.line_number)
+    }
+
+    /// Returns the names of the interfaces directly implemented by the class.
+    pub fn interfaces(&self) -> impl Iterator<Item = &'a str> {
+        let constant_pool = &self.class_file.constant_pool;
+        self.class_file.interfaces.iter().filter_map(|index| {
+            let class = constant_pool[*index].try_as_class_ref()?;
+            constant_pool[class.name_index].try_as_utf_8_ref().copied()
+        })
+    }
+
+    /// Returns the interface method tables of the class, which are set by the vm once it has
+    /// loaded the class's interfaces.
+    pub(crate) fn itables(&self) -> &OnceLock<HashMap<&'a str, Itable<'a>>> {
+        &self.itables
     }
 
     pub fn constant_pool(&self) -> &'a ConstantPool {
         &self.class_file.constant_pool
     }
 
-    pub fn static_field(
-        &self,
-        name: &'a str,
-        descriptor: &'a str,
-    ) -> Option<&UnsafeCell<JvmValue<'a>>> {
+    pub fn static_field(&self, name: &'a str, descriptor: &'a str) -> Option<&Mutex<JvmValue<'a>>> {
         self.static_fields.get(&(name, descriptor))
     }
 
+    /// Returns the name, descriptor and value of each static field declared by the class.
+    pub(crate) fn static_fields(
+        &self,
+    ) -> impl Iterator<Item = (&'a str, &'a str, &Mutex<JvmValue<'a>>)> + '_ {
+        self.static_fields
+            .iter()
+            .map(|((name, descriptor), value)| (*name, *descriptor, value))
+    }
+
     pub fn fields(&self) -> &[Field<'a>] {
         &self.fields
     }
 
-    pub fn field_ordinal(&self, name: &'a str, descriptor: &'a str) -> Option<usize> {
-        self.field_ordinals.get(&(name, descriptor)).copied()
+    pub fn field_ordinal(&self, name: &str, descriptor: &str) -> Option<usize> {
+        let field_ordinals: &HashMap<(&str, &str), usize> = &self.field_ordinals;
+        field_ordinals.get(&(name, descriptor)).copied()
     }
 }
 
+/// Finds the value of a `Signature` attribute.
+fn signature<'a>(class_file: &'a ClassFile, attributes: &'a [AttributeInfo]) -> Option<&'a str> {
+    let attribute = attributes
+        .iter()
+        .find_map(|attr| attr.try_as_signature_ref())?;
+    class_file.constant_pool[attribute.signature_index]
+        .try_as_utf_8_ref()
+        .copied()
+}
+
 #[derive(Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
 struct MethodId<'a> {
     name: &'a str,
     descriptor: &'a str,
 }
 
+/// Borrowed form of [`MethodId`], which hashes identically so it can be used for lookups with
+/// strings that don't live as long as the class.
+#[derive(Hash)]
+struct MethodIdRef<'b> {
+    name: &'b str,
+    descriptor: &'b str,
+}
+
+impl Equivalent<MethodId<'_>> for MethodIdRef<'_> {
+    fn equivalent(&self, key: &MethodId<'_>) -> bool {
+        symbols::same(self.name, key.name) && symbols::same(self.descriptor, key.descriptor)
+    }
+}
+
 impl<'a> Debug for MethodId<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "\"{}{}\"", self.name, self.descriptor)
     }
 }
 
+/// Decodes the instructions of a method, along with the bytecode offset of each instruction.
+/// Branches are converted to be relative to the index of the instruction rather than its offset.
+///
+/// The code is checked to be well-formed: branches and exception handlers must refer to the
+/// start of an instruction, and locals must be within `max_locals`.
 pub fn decode_instructions<'a>(
+    arena: &'a Bump,
+    code: &CodeAttribute,
+) -> Result<(Vec<'a, Instruction>, Vec<'a, u32>)> {
+    let (instructions, offsets) = decode(arena, code.code, false)?;
+    validate_code(code, &instructions, &offsets)?;
+    Ok((instructions, offsets))
+}
+
+/// Decodes instructions on a best-effort basis, for inspecting class files. Decoding stops at the
+/// first instruction that can't be decoded, which is replaced with [`Instruction::unknown`].
+pub fn decode_instructions_lenient<'a>(
     arena: &'a Bump,
     bytes: &[u8],
-) -> eyre::Result<Vec<'a, Instruction>> {
-    let mut instructions = vec![in arena];
-    let mut cursor = Cursor::new(&bytes);
+) -> Result<(Vec<'a, Instruction>, Vec<'a, u32>)> {
+    decode(arena, bytes, true)
+}
 
-    let mut address_map = std::vec![];
-    let mut index_map = std::vec![0; bytes.len()];
-    let mut i = 0;
+/// Returns the targets of the `tableswitch` or `lookupswitch` instruction at an offset in the
+/// code, as offsets. The default target comes first.
+pub fn switch_targets(code: &[u8], offset: u32) -> Result<std::vec::Vec<u32>> {
+    let mut cursor = Cursor::new(code);
+    cursor.set_position(offset as u64);
 
-    while let Ok(opcode) = cursor.read_u8() {
-        address_map.push(cursor.position() as usize - 1);
-        index_map[cursor.position() as usize - 1] = i;
-        i += 1;
-
-        let opcode =
-            OpCode::from_repr(opcode).wrap_err_with(|| eyre!("unknown opcode: {opcode}"))?;
-
-        let instruction = match opcode {
-            OpCode::nop => Instruction::nop,
-            OpCode::aconst_null => Instruction::aconst_null,
-            OpCode::iconst_m1 => Instruction::iconst(-1),
-            OpCode::iconst_0 => Instruction::iconst(0),
-            OpCode::iconst_1 => Instruction::iconst(1),
-            OpCode::iconst_2 => Instruction::iconst(2),
-            OpCode::iconst_3 => Instruction::iconst(3),
-            OpCode::iconst_4 => Instruction::iconst(4),
-            OpCode::iconst_5 => Instruction::iconst(5),
-            OpCode::lconst_0 => Instruction::lconst(0),
-            OpCode::lconst_1 => Instruction::lconst(1),
-            OpCode::fconst_0 => Instruction::fconst(0),
-            OpCode::fconst_1 => Instruction::fconst(1),
-            OpCode::fconst_2 => Instruction::fconst(2),
-            OpCode::dconst_0 => Instruction::dconst(0),
-            OpCode::dconst_1 => Instruction::dconst(1),
-            OpCode::bipush => Instruction::bipush(cursor.read_i8()?),
-            OpCode::sipush => Instruction::sipush(cursor.read_i16_be()?),
-            OpCode::ldc => Instruction::ldc(cursor.read_u8()? as u16),
-            OpCode::ldc_w => Instruction::ldc(cursor.read_u16_be()?),
-            OpCode::ldc2_w => Instruction::ldc2(cursor.read_u16_be()?),
-            OpCode::iload => Instruction::iload(cursor.read_u8()?),
-            OpCode::lload => Instruction::lload(cursor.read_u8()?),
-            OpCode::fload => Instruction::fload(cursor.read_u8()?),
-            OpCode::dload => Instruction::dload(cursor.read_u8()?),
-            OpCode::aload => Instruction::aload(cursor.read_u8()?),
-            OpCode::iload_0 => Instruction::iload(0),
-            OpCode::iload_1 => Instruction::iload(1),
-            OpCode::iload_2 => Instruction::iload(2),
-            OpCode::iload_3 => Instruction::iload(3),
-            OpCode::lload_0 => Instruction::lload(0),
-            OpCode::lload_1 => Instruction::lload(1),
-            OpCode::lload_2 => Instruction::lload(2),
-            OpCode::lload_3 => Instruction::lload(3),
-            OpCode::fload_0 => Instruction::fload(0),
-            OpCode::fload_1 => Instruction::fload(1),
-            OpCode::fload_2 => Instruction::fload(2),
-            OpCode::fload_3 => Instruction::fload(3),
-            OpCode::dload_0 => Instruction::dload(0),
-            OpCode::dload_1 => Instruction::dload(1),
-            OpCode::dload_2 => Instruction::dload(2),
-            OpCode::dload_3 => Instruction::dload(3),
-            OpCode::aload_0 => Instruction::aload(0),
-            OpCode::aload_1 => Instruction::aload(1),
-            OpCode::aload_2 => Instruction::aload(2),
-            OpCode::aload_3 => Instruction::aload(3),
-            OpCode::iaload => Instruction::arraystore(ArrayLoadStoreType::Int),
-            OpCode::laload => Instruction::arraystore(ArrayLoadStoreType::Long),
-            OpCode::faload => Instruction::arraystore(ArrayLoadStoreType::Float),
-            OpCode::daload => Instruction::arraystore(ArrayLoadStoreType::Double),
-            OpCode::aaload => Instruction::arraystore(ArrayLoadStoreType::Reference),
-            OpCode::baload => Instruction::arraystore(ArrayLoadStoreType::Byte),
-            OpCode::caload => Instruction::arraystore(ArrayLoadStoreType::Char),
-            OpCode::saload => Instruction::arraystore(ArrayLoadStoreType::Short),
-            OpCode::istore => Instruction::istore(cursor.read_u8()?),
-            OpCode::lstore => Instruction::lstore(cursor.read_u8()?),
-            OpCode::fstore => Instruction::fstore(cursor.read_u8()?),
-            OpCode::dstore => Instruction::dstore(cursor.read_u8()?),
-            OpCode::astore => Instruction::astore(cursor.read_u8()?),
-            OpCode::istore_0 => Instruction::istore(0),
-            OpCode::istore_1 => Instruction::istore(1),
-            OpCode::istore_2 => Instruction::istore(2),
-            OpCode::istore_3 => Instruction::istore(3),
-            OpCode::lstore_0 => Instruction::lstore(0),
-            OpCode::lstore_1 => Instruction::lstore(1),
-            OpCode::lstore_2 => Instruction::lstore(2),
-            OpCode::lstore_3 => Instruction::lstore(3),
-            OpCode::fstore_0 => Instruction::fstore(0),
-            OpCode::fstore_1 => Instruction::fstore(1),
-            OpCode::fstore_2 => Instruction::fstore(2),
-            OpCode::fstore_3 => Instruction::fstore(3),
-            OpCode::dstore_0 => Instruction::dstore(0),
-            OpCode::dstore_1 => Instruction::dstore(1),
-            OpCode::dstore_2 => Instruction::dstore(2),
-            OpCode::dstore_3 => Instruction::dstore(3),
-            OpCode::astore_0 => Instruction::astore(0),
-            OpCode::astore_1 => Instruction::astore(1),
-            OpCode::astore_2 => Instruction::astore(2),
-            OpCode::astore_3 => Instruction::astore(3),
-            OpCode::iastore => Instruction::arraystore(ArrayLoadStoreType::Int),
-            OpCode::lastore => Instruction::arraystore(ArrayLoadStoreType::Long),
-            OpCode::fastore => Instruction::arraystore(ArrayLoadStoreType::Float),
-            OpCode::dastore => Instruction::arraystore(ArrayLoadStoreType::Double),
-            OpCode::aastore => Instruction::arraystore(ArrayLoadStoreType::Reference),
-            OpCode::bastore => Instruction::arraystore(ArrayLoadStoreType::Byte),
-            OpCode::castore => Instruction::arraystore(ArrayLoadStoreType::Char),
-            OpCode::sastore => Instruction::arraystore(ArrayLoadStoreType::Short),
-            OpCode::pop => Instruction::pop,
-            OpCode::pop2 => Instruction::pop2,
-            OpCode::dup => Instruction::dup,
-            OpCode::dup_x1 => Instruction::dup_x1,
-            OpCode::dup_x2 => Instruction::dup_x2,
-            OpCode::dup2 => Instruction::dup2,
-            OpCode::dup2_x1 => Instruction::dup2_x1,
-            OpCode::dup2_x2 => Instruction::dup2_x2,
-            OpCode::swap => Instruction::swap,
-            OpCode::iadd => Instruction::add(NumberType::Int),
-            OpCode::ladd => Instruction::add(NumberType::Long),
-            OpCode::fadd => Instruction::add(NumberType::Float),
-            OpCode::dadd => Instruction::add(NumberType::Double),
-            OpCode::isub => Instruction::sub(NumberType::Int),
-            OpCode::lsub => Instruction::sub(NumberType::Long),
-            OpCode::fsub => Instruction::sub(NumberType::Float),
-            OpCode::dsub => Instruction::sub(NumberType::Double),
-            OpCode::imul => Instruction::mul(NumberType::Int),
-            OpCode::lmul => Instruction::mul(NumberType::Long),
-            OpCode::fmul => Instruction::mul(NumberType::Float),
-            OpCode::dmul => Instruction::mul(NumberType::Double),
-            OpCode::idiv => Instruction::div(NumberType::Int),
-            OpCode::ldiv => Instruction::div(NumberType::Long),
-            OpCode::fdiv => Instruction::div(NumberType::Float),
-            OpCode::ddiv => Instruction::div(NumberType::Double),
-            OpCode::irem => Instruction::rem(NumberType::Int),
-            OpCode::lrem => Instruction::rem(NumberType::Long),
-            OpCode::frem => Instruction::rem(NumberType::Float),
-            OpCode::drem => Instruction::rem(NumberType::Double),
-            OpCode::ineg => Instruction::neg(NumberType::Int),
-            OpCode::lneg => Instruction::neg(NumberType::Long),
-            OpCode::fneg => Instruction::neg(NumberType::Float),
-            OpCode::dneg => Instruction::neg(NumberType::Double),
-            OpCode::ishl => Instruction::shl(IntegerType::Int),
-            OpCode::lshl => Instruction::shl(IntegerType::Long),
-            OpCode::ishr => Instruction::shr(IntegerType::Int),
-            OpCode::lshr => Instruction::shr(IntegerType::Long),
-            OpCode::iushr => Instruction::ushr(IntegerType::Int),
-            OpCode::lushr => Instruction::ushr(IntegerType::Long),
-            OpCode::iand => Instruction::and(IntegerType::Int),
-            OpCode::land => Instruction::and(IntegerType::Long),
-            OpCode::ior => Instruction::or(IntegerType::Int),
-            OpCode::lor => Instruction::or(IntegerType::Long),
-            OpCode::ixor => Instruction::xor(IntegerType::Int),
-            OpCode::lxor => Instruction::xor(IntegerType::Long),
-            OpCode::iinc => Instruction::inc(cursor.read_u8()?, cursor.read_i8()?),
-            OpCode::i2l => Instruction::i2l,
-            OpCode::i2f => Instruction::i2f,
-            OpCode::i2d => Instruction::i2d,
-            OpCode::l2i => Instruction::l2i,
-            OpCode::l2f => Instruction::l2f,
-            OpCode::l2d => Instruction::l2d,
-            OpCode::f2i => Instruction::f2i,
-            OpCode::f2l => Instruction::f2l,
-            OpCode::f2d => Instruction::f2d,
-            OpCode::d2i => Instruction::d2i,
-            OpCode::d2l => Instruction::d2l,
-            OpCode::d2f => Instruction::d2f,
-            OpCode::i2b => Instruction::i2b,
-            OpCode::i2c => Instruction::i2c,
-            OpCode::i2s => Instruction::i2s,
-            OpCode::lcmp => Instruction::lcmp,
-            OpCode::fcmpl => Instruction::fcmp(OrdCondition::Lt),
-            OpCode::fcmpg => Instruction::fcmp(OrdCondition::Gt),
-            OpCode::dcmpl => Instruction::dcmp(OrdCondition::Lt),
-            OpCode::dcmpg => Instruction::dcmp(OrdCondition::Gt),
-            OpCode::ifeq => Instruction::r#if(Condition::Eq, cursor.read_i16_be()?),
-            OpCode::ifne => Instruction::r#if(Condition::Ne, cursor.read_i16_be()?),
-            OpCode::iflt => Instruction::r#if(Condition::Lt, cursor.read_i16_be()?),
-            OpCode::ifge => Instruction::r#if(Condition::Ge, cursor.read_i16_be()?),
-            OpCode::ifgt => Instruction::r#if(Condition::Gt, cursor.read_i16_be()?),
-            OpCode::ifle => Instruction::r#if(Condition::Le, cursor.read_i16_be()?),
-            OpCode::if_icmpeq => Instruction::if_icmp(Condition::Eq, cursor.read_i16_be()?),
-            OpCode::if_icmpne => Instruction::if_icmp(Condition::Ne, cursor.read_i16_be()?),
-            OpCode::if_icmplt => Instruction::if_icmp(Condition::Lt, cursor.read_i16_be()?),
-            OpCode::if_icmpge => Instruction::if_icmp(Condition::Ge, cursor.read_i16_be()?),
-            OpCode::if_icmpgt => Instruction::if_icmp(Condition::Gt, cursor.read_i16_be()?),
-            OpCode::if_icmple => Instruction::if_icmp(Condition::Le, cursor.read_i16_be()?),
-            OpCode::if_acmpeq => Instruction::if_acmp(EqCondition::Eq, cursor.read_i16_be()?),
-            OpCode::if_acmpne => Instruction::if_acmp(EqCondition::Ne, cursor.read_i16_be()?),
-            OpCode::goto => Instruction::goto(cursor.read_i16_be()? as i32),
-            OpCode::jsr => Instruction::jsr(cursor.read_i16_be()? as i32),
-            OpCode::ret => Instruction::ret(cursor.read_u8()?),
-            OpCode::tableswitch => {
-                cursor.align_to(4);
-                let _default = cursor.read_i32_be()?;
-                let low = cursor.read_i32_be()?;
-                let high = cursor.read_i32_be()?;
-                let count = high - low + 1;
-                cursor.set_position(cursor.position() + count as u64 * 4);
-                Instruction::tableswitch {}
-            }
-            OpCode::lookupswitch => {
-                cursor.align_to(4);
-                let _default = cursor.read_i32_be()?;
-                let npairs = cursor.read_i32_be()?;
-                cursor.set_position(cursor.position() + npairs as u64 * 8);
-                Instruction::lookupswitch {}
+    let opcode = OpCode::from_repr(cursor.read_u8()?);
+    cursor.align_to(4);
+
+    let mut branches = std::vec![cursor.read_i32_be()?];
+    match opcode {
+        Some(OpCode::tableswitch) => {
+            let low = cursor.read_i32_be()?;
+            let high = cursor.read_i32_be()?;
+            if low > high {
+                bail!("invalid tableswitch range: {low} to {high}");
             }
-            OpCode::ireturn => Instruction::r#return(ReturnType::Int),
-            OpCode::lreturn => Instruction::r#return(ReturnType::Long),
-            OpCode::freturn => Instruction::r#return(ReturnType::Float),
-            OpCode::dreturn => Instruction::r#return(ReturnType::Double),
-            OpCode::areturn => Instruction::r#return(ReturnType::Reference),
-            OpCode::r#return => Instruction::r#return(ReturnType::Void),
-            OpCode::getfield => Instruction::getfield(cursor.read_u16_be()?),
-            OpCode::putfield => Instruction::putfield(cursor.read_u16_be()?),
-            OpCode::getstatic => Instruction::getstatic(cursor.read_u16_be()?),
-            OpCode::putstatic => Instruction::putstatic(cursor.read_u16_be()?),
-            OpCode::invokevirtual => {
-                Instruction::invoke(InvokeKind::Virtual, cursor.read_u16_be()?)
+            for _ in low..=high {
+                branches.push(cursor.read_i32_be()?);
             }
-            OpCode::invokespecial => {
-                Instruction::invoke(InvokeKind::Special, cursor.read_u16_be()?)
+        }
+        Some(OpCode::lookupswitch) => {
+            let npairs = cursor.read_i32_be()?;
+            for _ in 0..npairs {
+                let _key = cursor.read_i32_be()?;
+                branches.push(cursor.read_i32_be()?);
             }
-            OpCode::invokestatic => Instruction::invoke(InvokeKind::Static, cursor.read_u16_be()?),
-            OpCode::invokeinterface => {
-                let index = cursor.read_u16_be()?;
-                let count = NonZeroU8::new(cursor.read_u8()?)
-                    .wrap_err("invokeinterface count must not be 0")?;
-                let zero = cursor.read_u8()?;
-                if zero != 0 {
-                    bail!("invalid bytes found in invokeinterface instruction: 0x{zero:0x}");
-                }
-                Instruction::invoke(InvokeKind::Interface { count }, index)
+        }
+        _ => bail!("expected a switch instruction at offset {offset}"),
+    }
+
+    branches
+        .into_iter()
+        .map(|branch| {
+            offset
+                .checked_add_signed(branch)
+                .wrap_err_with(|| format_err!("invalid branch target: {branch}"))
+        })
+        .collect()
+}
+
+/// Returns the target of the `tableswitch` or `lookupswitch` instruction at an offset in the
+/// code for a key, as an offset.
+pub fn switch_target(code: &[u8], offset: u32, key: i32) -> Result<u32> {
+    let mut cursor = Cursor::new(code);
+    cursor.set_position(offset as u64);
+
+    let opcode = OpCode::from_repr(cursor.read_u8()?);
+    cursor.align_to(4);
+
+    let default = cursor.read_i32_be()?;
+    let branch = match opcode {
+        Some(OpCode::tableswitch) => {
+            let low = cursor.read_i32_be()?;
+            let high = cursor.read_i32_be()?;
+            if (low..=high).contains(&key) {
+                cursor.set_position(cursor.position() + (key as i64 - low as i64) as u64 * 4);
+                cursor.read_i32_be()?
+            } else {
+                default
             }
-            OpCode::invokedynamic => {
-                let index = cursor.read_u16_be()?;
-                let zero = cursor.read_u16_be()?;
-                if zero != 0 {
-                    bail!("invalid bytes found in invokedynamic instruction: 0x{zero:0x}");
+        }
+        Some(OpCode::lookupswitch) => {
+            let npairs = cursor.read_i32_be()?;
+            let mut branch = default;
+            for _ in 0..npairs {
+                let (match_key, match_branch) = (cursor.read_i32_be()?, cursor.read_i32_be()?);
+                if match_key == key {
+                    branch = match_branch;
+                    break;
                 }
-                Instruction::invoke(InvokeKind::Dynamic, index)
             }
-            OpCode::new => Instruction::new(cursor.read_u16_be()?),
-            OpCode::newarray => Instruction::newarray(
-                ArrayType::from_repr(cursor.read_u8()?).wrap_err("invalid array type")?,
-            ),
-            OpCode::anewarray => Instruction::anewarray(cursor.read_u16_be()?),
-            OpCode::arraylength => Instruction::arraylength,
-            OpCode::athrow => Instruction::athrow,
-            OpCode::checkcast => Instruction::checkcast(cursor.read_u16_be()?),
-            OpCode::instanceof => Instruction::instanceof(cursor.read_u16_be()?),
-            OpCode::monitorenter => Instruction::monitorenter,
-            OpCode::monitorexit => Instruction::monitorexit,
-            OpCode::wide => todo!(),
-            OpCode::multianewarray => {
-                Instruction::multianewarray(cursor.read_u16_be()?, cursor.read_u8()?)
-            }
-            OpCode::ifnull => Instruction::ifnull(cursor.read_i16_be()?),
-            OpCode::ifnonnull => Instruction::ifnonnull(cursor.read_i16_be()?),
-            OpCode::goto_w => Instruction::goto(cursor.read_i32_be()?),
-            OpCode::jsr_w => Instruction::jsr(cursor.read_i32_be()?),
-            OpCode::breakpoint | OpCode::impdep1 | OpCode::impdep2 => {
-                bail!("unexpected opcode: {opcode:?}")
+            branch
+        }
+        _ => bail!("expected a switch instruction at offset {offset}"),
+    };
+
+    offset
+        .checked_add_signed(branch)
+        .wrap_err_with(|| format_err!("invalid branch target: {branch}"))
+}
+
+fn decode<'a>(
+    arena: &'a Bump,
+    bytes: &[u8],
+    lenient: bool,
+) -> Result<(Vec<'a, Instruction>, Vec<'a, u32>)> {
+    let mut instructions = vec![in arena];
+    let mut offsets = vec![in arena];
+    let mut cursor = Cursor::new(bytes);
+
+    while let Ok(opcode) = cursor.read_u8() {
+        offsets.push(cursor.position() as u32 - 1);
+
+        match decode_instruction(&mut cursor, opcode) {
+            Ok(instruction) => instructions.push(instruction),
+            Err(_) if lenient => {
+                // The length of the instruction isn't known, so nothing after it can be decoded
+                instructions.push(Instruction::unknown { opcode });
+                break;
             }
-        };
-        instructions.push(instruction);
+            Err(e) => return Err(e),
+        }
     }
 
     // Branch values represent byte address offsets of the instruction to jump to, relative to the current instruction.
@@ -523,8 +667,14 @@ pub fn decode_instructions<'a>(
     for (i, instruction) in instructions.iter_mut().enumerate() {
         macro_rules! address_to_index {
             ($branch:expr, $t:ty) => {{
-                (index_map[address_map[i].checked_add_signed($branch as isize).unwrap()] as isize
-                    - i as isize) as $t
+                let target = offsets[i]
+                    .checked_add_signed($branch as i32)
+                    .and_then(|offset| offsets.binary_search(&offset).ok());
+                match target {
+                    Some(target) => (target as isize - i as isize) as $t,
+                    None if lenient => $branch,
+                    None => return Err(invalid_branch_target(bytes, offsets[i], $branch as i32)),
+                }
             }};
         }
 
@@ -540,7 +690,347 @@ pub fn decode_instructions<'a>(
         }
     }
 
-    Ok(instructions)
+    Ok((instructions, offsets))
+}
+
+/// Describes a branch target which isn't the start of an instruction.
+fn invalid_branch_target(code: &[u8], pc: u32, branch: i32) -> Error {
+    match pc.checked_add_signed(branch) {
+        Some(target) if (target as usize) < code.len() => {
+            format_err!(
+                "branch at pc {pc} targets pc {target}, which is in the middle of an instruction"
+            )
+        }
+        _ => format_err!(
+            "branch at pc {pc} targets pc {}, which is outside the code (length {})",
+            pc as i64 + branch as i64,
+            code.len()
+        ),
+    }
+}
+
+/// Checks the parts of a method's code that can't be checked while decoding each instruction:
+/// switch targets, the exception table, and the locals used by each instruction.
+fn validate_code(
+    code: &CodeAttribute,
+    instructions: &[Instruction],
+    offsets: &[u32],
+) -> Result<()> {
+    let is_instruction = |pc: u32| offsets.binary_search(&pc).is_ok();
+
+    for (instruction, &pc) in instructions.iter().zip(offsets) {
+        let local = match instruction {
+            Instruction::load { data_type, index } | Instruction::store { data_type, index } => {
+                let size = match data_type {
+                    LoadStoreType::Long | LoadStoreType::Double => 2,
+                    _ => 1,
+                };
+                Some((*index, size))
+            }
+            Instruction::inc { index, .. } | Instruction::ret { index } => Some((*index, 1)),
+            Instruction::tableswitch {} | Instruction::lookupswitch {} => {
+                for target in switch_targets(code.code, pc)? {
+                    if !is_instruction(target) {
+                        return Err(invalid_branch_target(
+                            code.code,
+                            pc,
+                            target.wrapping_sub(pc) as i32,
+                        ));
+                    }
+                }
+                None
+            }
+            _ => None,
+        };
+
+        if let Some((index, _)) =
+            local.filter(|(index, size)| *index as usize + size > code.max_locals as usize)
+        {
+            bail!(
+                "local {index} used at pc {pc} is out of bounds (max_locals is {})",
+                code.max_locals
+            );
+        }
+    }
+
+    for (i, entry) in code.exception_table.iter().enumerate() {
+        let end_is_valid =
+            is_instruction(entry.end_pc as u32) || entry.end_pc as usize == code.code.len();
+
+        if !is_instruction(entry.start_pc as u32) {
+            bail!(
+                "exception table entry {i} has invalid start_pc {}",
+                entry.start_pc
+            );
+        } else if !end_is_valid {
+            bail!(
+                "exception table entry {i} has invalid end_pc {}",
+                entry.end_pc
+            );
+        } else if entry.start_pc >= entry.end_pc {
+            bail!(
+                "exception table entry {i} has an empty range: {} to {}",
+                entry.start_pc,
+                entry.end_pc
+            );
+        } else if !is_instruction(entry.handler_pc as u32) {
+            bail!(
+                "exception table entry {i} has invalid handler_pc {}",
+                entry.handler_pc
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn decode_instruction(cursor: &mut Cursor<&[u8]>, opcode: u8) -> Result<Instruction> {
+    let opcode =
+        OpCode::from_repr(opcode).wrap_err_with(|| format_err!("unknown opcode: {opcode}"))?;
+
+    let instruction = match opcode {
+        OpCode::nop => Instruction::nop,
+        OpCode::aconst_null => Instruction::aconst_null,
+        OpCode::iconst_m1 => Instruction::iconst(-1),
+        OpCode::iconst_0 => Instruction::iconst(0),
+        OpCode::iconst_1 => Instruction::iconst(1),
+        OpCode::iconst_2 => Instruction::iconst(2),
+        OpCode::iconst_3 => Instruction::iconst(3),
+        OpCode::iconst_4 => Instruction::iconst(4),
+        OpCode::iconst_5 => Instruction::iconst(5),
+        OpCode::lconst_0 => Instruction::lconst(0),
+        OpCode::lconst_1 => Instruction::lconst(1),
+        OpCode::fconst_0 => Instruction::fconst(0),
+        OpCode::fconst_1 => Instruction::fconst(1),
+        OpCode::fconst_2 => Instruction::fconst(2),
+        OpCode::dconst_0 => Instruction::dconst(0),
+        OpCode::dconst_1 => Instruction::dconst(1),
+        OpCode::bipush => Instruction::bipush(cursor.read_i8()?),
+        OpCode::sipush => Instruction::sipush(cursor.read_i16_be()?),
+        OpCode::ldc => Instruction::ldc(cursor.read_u8()? as u16),
+        OpCode::ldc_w => Instruction::ldc(cursor.read_u16_be()?),
+        OpCode::ldc2_w => Instruction::ldc2(cursor.read_u16_be()?),
+        OpCode::iload => Instruction::iload(cursor.read_u8()?),
+        OpCode::lload => Instruction::lload(cursor.read_u8()?),
+        OpCode::fload => Instruction::fload(cursor.read_u8()?),
+        OpCode::dload => Instruction::dload(cursor.read_u8()?),
+        OpCode::aload => Instruction::aload(cursor.read_u8()?),
+        OpCode::iload_0 => Instruction::iload(0),
+        OpCode::iload_1 => Instruction::iload(1),
+        OpCode::iload_2 => Instruction::iload(2),
+        OpCode::iload_3 => Instruction::iload(3),
+        OpCode::lload_0 => Instruction::lload(0),
+        OpCode::lload_1 => Instruction::lload(1),
+        OpCode::lload_2 => Instruction::lload(2),
+        OpCode::lload_3 => Instruction::lload(3),
+        OpCode::fload_0 => Instruction::fload(0),
+        OpCode::fload_1 => Instruction::fload(1),
+        OpCode::fload_2 => Instruction::fload(2),
+        OpCode::fload_3 => Instruction::fload(3),
+        OpCode::dload_0 => Instruction::dload(0),
+        OpCode::dload_1 => Instruction::dload(1),
+        OpCode::dload_2 => Instruction::dload(2),
+        OpCode::dload_3 => Instruction::dload(3),
+        OpCode::aload_0 => Instruction::aload(0),
+        OpCode::aload_1 => Instruction::aload(1),
+        OpCode::aload_2 => Instruction::aload(2),
+        OpCode::aload_3 => Instruction::aload(3),
+        OpCode::iaload => Instruction::arrayload(ArrayLoadStoreType::Int),
+        OpCode::laload => Instruction::arrayload(ArrayLoadStoreType::Long),
+        OpCode::faload => Instruction::arrayload(ArrayLoadStoreType::Float),
+        OpCode::daload => Instruction::arrayload(ArrayLoadStoreType::Double),
+        OpCode::aaload => Instruction::arrayload(ArrayLoadStoreType::Reference),
+        OpCode::baload => Instruction::arrayload(ArrayLoadStoreType::Byte),
+        OpCode::caload => Instruction::arrayload(ArrayLoadStoreType::Char),
+        OpCode::saload => Instruction::arrayload(ArrayLoadStoreType::Short),
+        OpCode::istore => Instruction::istore(cursor.read_u8()?),
+        OpCode::lstore => Instruction::lstore(cursor.read_u8()?),
+        OpCode::fstore => Instruction::fstore(cursor.read_u8()?),
+        OpCode::dstore => Instruction::dstore(cursor.read_u8()?),
+        OpCode::astore => Instruction::astore(cursor.read_u8()?),
+        OpCode::istore_0 => Instruction::istore(0),
+        OpCode::istore_1 => Instruction::istore(1),
+        OpCode::istore_2 => Instruction::istore(2),
+        OpCode::istore_3 => Instruction::istore(3),
+        OpCode::lstore_0 => Instruction::lstore(0),
+        OpCode::lstore_1 => Instruction::lstore(1),
+        OpCode::lstore_2 => Instruction::lstore(2),
+        OpCode::lstore_3 => Instruction::lstore(3),
+        OpCode::fstore_0 => Instruction::fstore(0),
+        OpCode::fstore_1 => Instruction::fstore(1),
+        OpCode::fstore_2 => Instruction::fstore(2),
+        OpCode::fstore_3 => Instruction::fstore(3),
+        OpCode::dstore_0 => Instruction::dstore(0),
+        OpCode::dstore_1 => Instruction::dstore(1),
+        OpCode::dstore_2 => Instruction::dstore(2),
+        OpCode::dstore_3 => Instruction::dstore(3),
+        OpCode::astore_0 => Instruction::astore(0),
+        OpCode::astore_1 => Instruction::astore(1),
+        OpCode::astore_2 => Instruction::astore(2),
+        OpCode::astore_3 => Instruction::astore(3),
+        OpCode::iastore => Instruction::arraystore(ArrayLoadStoreType::Int),
+        OpCode::lastore => Instruction::arraystore(ArrayLoadStoreType::Long),
+        OpCode::fastore => Instruction::arraystore(ArrayLoadStoreType::Float),
+        OpCode::dastore => Instruction::arraystore(ArrayLoadStoreType::Double),
+        OpCode::aastore => Instruction::arraystore(ArrayLoadStoreType::Reference),
+        OpCode::bastore => Instruction::arraystore(ArrayLoadStoreType::Byte),
+        OpCode::castore => Instruction::arraystore(ArrayLoadStoreType::Char),
+        OpCode::sastore => Instruction::arraystore(ArrayLoadStoreType::Short),
+        OpCode::pop => Instruction::pop,
+        OpCode::pop2 => Instruction::pop2,
+        OpCode::dup => Instruction::dup,
+        OpCode::dup_x1 => Instruction::dup_x1,
+        OpCode::dup_x2 => Instruction::dup_x2,
+        OpCode::dup2 => Instruction::dup2,
+        OpCode::dup2_x1 => Instruction::dup2_x1,
+        OpCode::dup2_x2 => Instruction::dup2_x2,
+        OpCode::swap => Instruction::swap,
+        OpCode::iadd => Instruction::add(NumberType::Int),
+        OpCode::ladd => Instruction::add(NumberType::Long),
+        OpCode::fadd => Instruction::add(NumberType::Float),
+        OpCode::dadd => Instruction::add(NumberType::Double),
+        OpCode::isub => Instruction::sub(NumberType::Int),
+        OpCode::lsub => Instruction::sub(NumberType::Long),
+        OpCode::fsub => Instruction::sub(NumberType::Float),
+        OpCode::dsub => Instruction::sub(NumberType::Double),
+        OpCode::imul => Instruction::mul(NumberType::Int),
+        OpCode::lmul => Instruction::mul(NumberType::Long),
+        OpCode::fmul => Instruction::mul(NumberType::Float),
+        OpCode::dmul => Instruction::mul(NumberType::Double),
+        OpCode::idiv => Instruction::div(NumberType::Int),
+        OpCode::ldiv => Instruction::div(NumberType::Long),
+        OpCode::fdiv => Instruction::div(NumberType::Float),
+        OpCode::ddiv => Instruction::div(NumberType::Double),
+        OpCode::irem => Instruction::rem(NumberType::Int),
+        OpCode::lrem => Instruction::rem(NumberType::Long),
+        OpCode::frem => Instruction::rem(NumberType::Float),
+        OpCode::drem => Instruction::rem(NumberType::Double),
+        OpCode::ineg => Instruction::neg(NumberType::Int),
+        OpCode::lneg => Instruction::neg(NumberType::Long),
+        OpCode::fneg => Instruction::neg(NumberType::Float),
+        OpCode::dneg => Instruction::neg(NumberType::Double),
+        OpCode::ishl => Instruction::shl(IntegerType::Int),
+        OpCode::lshl => Instruction::shl(IntegerType::Long),
+        OpCode::ishr => Instruction::shr(IntegerType::Int),
+        OpCode::lshr => Instruction::shr(IntegerType::Long),
+        OpCode::iushr => Instruction::ushr(IntegerType::Int),
+        OpCode::lushr => Instruction::ushr(IntegerType::Long),
+        OpCode::iand => Instruction::and(IntegerType::Int),
+        OpCode::land => Instruction::and(IntegerType::Long),
+        OpCode::ior => Instruction::or(IntegerType::Int),
+        OpCode::lor => Instruction::or(IntegerType::Long),
+        OpCode::ixor => Instruction::xor(IntegerType::Int),
+        OpCode::lxor => Instruction::xor(IntegerType::Long),
+        OpCode::iinc => Instruction::inc(cursor.read_u8()?, cursor.read_i8()?),
+        OpCode::i2l => Instruction::i2l,
+        OpCode::i2f => Instruction::i2f,
+        OpCode::i2d => Instruction::i2d,
+        OpCode::l2i => Instruction::l2i,
+        OpCode::l2f => Instruction::l2f,
+        OpCode::l2d => Instruction::l2d,
+        OpCode::f2i => Instruction::f2i,
+        OpCode::f2l => Instruction::f2l,
+        OpCode::f2d => Instruction::f2d,
+        OpCode::d2i => Instruction::d2i,
+        OpCode::d2l => Instruction::d2l,
+        OpCode::d2f => Instruction::d2f,
+        OpCode::i2b => Instruction::i2b,
+        OpCode::i2c => Instruction::i2c,
+        OpCode::i2s => Instruction::i2s,
+        OpCode::lcmp => Instruction::lcmp,
+        OpCode::fcmpl => Instruction::fcmp(OrdCondition::Lt),
+        OpCode::fcmpg => Instruction::fcmp(OrdCondition::Gt),
+        OpCode::dcmpl => Instruction::dcmp(OrdCondition::Lt),
+        OpCode::dcmpg => Instruction::dcmp(OrdCondition::Gt),
+        OpCode::ifeq => Instruction::r#if(Condition::Eq, cursor.read_i16_be()?),
+        OpCode::ifne => Instruction::r#if(Condition::Ne, cursor.read_i16_be()?),
+        OpCode::iflt => Instruction::r#if(Condition::Lt, cursor.read_i16_be()?),
+        OpCode::ifge => Instruction::r#if(Condition::Ge, cursor.read_i16_be()?),
+        OpCode::ifgt => Instruction::r#if(Condition::Gt, cursor.read_i16_be()?),
+        OpCode::ifle => Instruction::r#if(Condition::Le, cursor.read_i16_be()?),
+        OpCode::if_icmpeq => Instruction::if_icmp(Condition::Eq, cursor.read_i16_be()?),
+        OpCode::if_icmpne => Instruction::if_icmp(Condition::Ne, cursor.read_i16_be()?),
+        OpCode::if_icmplt => Instruction::if_icmp(Condition::Lt, cursor.read_i16_be()?),
+        OpCode::if_icmpge => Instruction::if_icmp(Condition::Ge, cursor.read_i16_be()?),
+        OpCode::if_icmpgt => Instruction::if_icmp(Condition::Gt, cursor.read_i16_be()?),
+        OpCode::if_icmple => Instruction::if_icmp(Condition::Le, cursor.read_i16_be()?),
+        OpCode::if_acmpeq => Instruction::if_acmp(EqCondition::Eq, cursor.read_i16_be()?),
+        OpCode::if_acmpne => Instruction::if_acmp(EqCondition::Ne, cursor.read_i16_be()?),
+        OpCode::goto => Instruction::goto(cursor.read_i16_be()? as i32),
+        OpCode::jsr => Instruction::jsr(cursor.read_i16_be()? as i32),
+        OpCode::ret => Instruction::ret(cursor.read_u8()?),
+        OpCode::tableswitch => {
+            cursor.align_to(4);
+            let _default = cursor.read_i32_be()?;
+            let low = cursor.read_i32_be()?;
+            let high = cursor.read_i32_be()?;
+            let count = high - low + 1;
+            cursor.set_position(cursor.position() + count as u64 * 4);
+            Instruction::tableswitch {}
+        }
+        OpCode::lookupswitch => {
+            cursor.align_to(4);
+            let _default = cursor.read_i32_be()?;
+            let npairs = cursor.read_i32_be()?;
+            cursor.set_position(cursor.position() + npairs as u64 * 8);
+            Instruction::lookupswitch {}
+        }
+        OpCode::ireturn => Instruction::r#return(ReturnType::Int),
+        OpCode::lreturn => Instruction::r#return(ReturnType::Long),
+        OpCode::freturn => Instruction::r#return(ReturnType::Float),
+        OpCode::dreturn => Instruction::r#return(ReturnType::Double),
+        OpCode::areturn => Instruction::r#return(ReturnType::Reference),
+        OpCode::r#return => Instruction::r#return(ReturnType::Void),
+        OpCode::getfield => Instruction::getfield(cursor.read_u16_be()?),
+        OpCode::putfield => Instruction::putfield(cursor.read_u16_be()?),
+        OpCode::getstatic => Instruction::getstatic(cursor.read_u16_be()?),
+        OpCode::putstatic => Instruction::putstatic(cursor.read_u16_be()?),
+        OpCode::invokevirtual => Instruction::invoke(InvokeKind::Virtual, cursor.read_u16_be()?),
+        OpCode::invokespecial => Instruction::invoke(InvokeKind::Special, cursor.read_u16_be()?),
+        OpCode::invokestatic => Instruction::invoke(InvokeKind::Static, cursor.read_u16_be()?),
+        OpCode::invokeinterface => {
+            let index = cursor.read_u16_be()?;
+            let count = NonZeroU8::new(cursor.read_u8()?)
+                .wrap_err("invokeinterface count must not be 0")?;
+            let zero = cursor.read_u8()?;
+            if zero != 0 {
+                bail!("invalid bytes found in invokeinterface instruction: 0x{zero:0x}");
+            }
+            Instruction::invoke(InvokeKind::Interface { count }, index)
+        }
+        OpCode::invokedynamic => {
+            let index = cursor.read_u16_be()?;
+            let zero = cursor.read_u16_be()?;
+            if zero != 0 {
+                bail!("invalid bytes found in invokedynamic instruction: 0x{zero:0x}");
+            }
+            Instruction::invoke(InvokeKind::Dynamic, index)
+        }
+        OpCode::new => Instruction::new(cursor.read_u16_be()?),
+        OpCode::newarray => Instruction::newarray(
+            ArrayType::from_repr(cursor.read_u8()?).wrap_err("invalid array type")?,
+        ),
+        OpCode::anewarray => Instruction::anewarray(cursor.read_u16_be()?),
+        OpCode::arraylength => Instruction::arraylength,
+        OpCode::athrow => Instruction::athrow,
+        OpCode::checkcast => Instruction::checkcast(cursor.read_u16_be()?),
+        OpCode::instanceof => Instruction::instanceof(cursor.read_u16_be()?),
+        OpCode::monitorenter => Instruction::monitorenter,
+        OpCode::monitorexit => Instruction::monitorexit,
+        OpCode::wide => bail!("wide instructions aren't supported yet"),
+        OpCode::multianewarray => {
+            Instruction::multianewarray(cursor.read_u16_be()?, cursor.read_u8()?)
+        }
+        OpCode::ifnull => Instruction::ifnull(cursor.read_i16_be()?),
+        OpCode::ifnonnull => Instruction::ifnonnull(cursor.read_i16_be()?),
+        OpCode::goto_w => Instruction::goto(cursor.read_i32_be()?),
+        OpCode::jsr_w => Instruction::jsr(cursor.read_i32_be()?),
+        OpCode::breakpoint | OpCode::impdep1 | OpCode::impdep2 => {
+            bail!("unexpected opcode: {opcode:?}")
+        }
+    };
+
+    Ok(instruction)
 }
 
 trait EndianReadExt {
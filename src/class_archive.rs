@@ -0,0 +1,694 @@
+//! Archives of the JDK classes that a program loads, which are read in one go on later runs
+//! instead of looking up each class in the JDK's jimage, like class data sharing in HotSpot.
+//!
+//! Each class is archived as its class file along with the parsed class file and the decoded code
+//! of its methods, so that none of it is parsed or decoded again on later runs. The archive is
+//! memory mapped, and each class is copied into the vm's arena in one piece when it's loaded, with
+//! its strings and code borrowed from the copy.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Cursor, Read, Write};
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use bincode::Options;
+use bumpalo::collections::Vec;
+use bumpalo::Bump;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use memmap2::Mmap;
+use serde::de::{
+    self, DeserializeSeed, EnumAccess, Error as _, SeqAccess, Unexpected, VariantAccess, Visitor,
+};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::class_file::constant_pool::{ConstantInfo, ConstantPool};
+use crate::class_file::{
+    Annotation, AnnotationsAttribute, AttributeInfo, BootstrapMethod, BootstrapMethodsAttribute,
+    ClassAccessFlags, ClassFile, CodeAttribute, CustomAttribute, ElementValue, ElementValuePair,
+    ExceptionTableEntry, ExportsFlags, FieldAccessFlags, FieldInfo, InnerClass,
+    InnerClassAccessFlags, InnerClassesAttribute, LineNumberTableAttribute, LineNumberTableEntry,
+    LocalVarTargetEntry, MalformedAttribute, MethodAccessFlags, MethodInfo, ModuleAttribute,
+    ModuleExports, ModuleFlags, ModuleMainClassAttribute, ModulePackagesAttribute, ModuleProvides,
+    ModuleRequires, ParameterAnnotationsAttribute, SignatureAttribute, SourceFileAttribute,
+    StackMapFrame, StackMapTableAttribute, TargetInfo, TypeAnnotation, TypeAnnotationsAttribute,
+    TypePathEntry, VerificationTypeInfo,
+};
+use crate::error::{bail, format_err, Context, Error, Result};
+use crate::instructions::Instruction;
+
+const MAGIC: &[u8; 4] = b"RJCA";
+const VERSION: u32 = 2;
+
+pub struct ClassArchive {
+    /// Identifies the jimage that the classes were read from.
+    jimage_stamp: JImageStamp,
+    data: Mmap,
+    classes: HashMap<String, ArchivedClassRanges>,
+}
+
+/// Where the parts of an archived class are in the archive.
+struct ArchivedClassRanges {
+    bytes: Range<usize>,
+    decoded: Range<usize>,
+}
+
+/// The decoded instructions of a method, along with the bytecode offset of each instruction.
+pub(crate) type DecodedCode<'a> = (Vec<'a, Instruction>, Vec<'a, u32>);
+
+/// A class read from an archive, with its strings and code borrowed from its copy in the arena.
+pub(crate) struct ArchivedClass<'a> {
+    pub class_file: ClassFile<'a>,
+    pub bytes: &'a [u8],
+    /// The decoded code of each method, in the order of the class file's methods.
+    pub code: std::vec::Vec<Option<DecodedCode<'a>>>,
+}
+
+/// The size and modification time of a jimage, which are used to check that an archive is for
+/// the same JDK.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct JImageStamp {
+    size: u64,
+    modified: u64,
+}
+
+impl JImageStamp {
+    pub(crate) fn new(jimage_path: &Path) -> Result<JImageStamp> {
+        let metadata = fs::metadata(jimage_path)
+            .wrap_err_with(|| format_err!("failed to read metadata of {jimage_path:?}"))?;
+
+        Ok(JImageStamp {
+            size: metadata.len(),
+            modified: metadata
+                .modified()?
+                .duration_since(UNIX_EPOCH)?
+                .as_nanos()
+                .try_into()?,
+        })
+    }
+}
+
+/// The parsed class file and decoded code of a class, as stored in an archive.
+struct DecodedClass<'a> {
+    class_file: ClassFile<'a>,
+    code: std::vec::Vec<Option<ArchivedCode<'a>>>,
+}
+
+struct ArchivedCode<'a> {
+    instructions: Vec<'a, Instruction>,
+    offsets: Vec<'a, u32>,
+}
+
+/// The same as [`DecodedClass`], but borrowing the code of a loaded class for writing.
+#[derive(Serialize)]
+struct DecodedClassRef<'b> {
+    class_file: &'b ClassFile<'b>,
+    code: std::vec::Vec<Option<(&'b [Instruction], &'b [u32])>>,
+}
+
+impl ClassArchive {
+    /// Maps an archive into memory. The file mustn't be changed while the archive is in use.
+    pub fn read(path: impl AsRef<Path>) -> Result<ClassArchive> {
+        let path = path.as_ref();
+        let file = File::open(path).wrap_err_with(|| format_err!("failed to open {path:?}"))?;
+        // SAFETY: archives are only written by `ClassArchive::write`, which replaces the file
+        // rather than changing it in place
+        let data =
+            unsafe { Mmap::map(&file) }.wrap_err_with(|| format_err!("failed to map {path:?}"))?;
+        ClassArchive::parse(data).wrap_err_with(|| format_err!("invalid class archive {path:?}"))
+    }
+
+    fn parse(data: Mmap) -> Result<ClassArchive> {
+        let mut cursor = Cursor::new(&data[..]);
+
+        let mut magic = [0; 4];
+        cursor.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            bail!("invalid magic bytes");
+        }
+
+        let version = cursor.read_u32::<LittleEndian>()?;
+        if version != VERSION {
+            bail!("unsupported version {version}");
+        }
+
+        let jimage_stamp = JImageStamp {
+            size: cursor.read_u64::<LittleEndian>()?,
+            modified: cursor.read_u64::<LittleEndian>()?,
+        };
+
+        let count = cursor.read_u32::<LittleEndian>()?;
+        let mut classes = HashMap::with_capacity(count as usize);
+
+        // Each class is stored as its name, followed by the class file and then the decoded
+        // class, all prefixed with their lengths
+        for _ in 0..count {
+            let name_len = cursor.read_u16::<LittleEndian>()? as usize;
+            let mut name = vec![0; name_len];
+            cursor.read_exact(&mut name)?;
+
+            let bytes = read_range(&mut cursor)?;
+            let decoded = read_range(&mut cursor)?;
+
+            classes.insert(
+                String::from_utf8(name)?,
+                ArchivedClassRanges { bytes, decoded },
+            );
+        }
+
+        Ok(ClassArchive {
+            jimage_stamp,
+            data,
+            classes,
+        })
+    }
+
+    /// Writes an archive of classes, given as their binary names, class files and parsed class
+    /// files, along with the decoded code of each of their methods. The archive is written to a
+    /// temporary file first, which then replaces any existing archive, since an existing archive
+    /// may be mapped by another process.
+    pub(crate) fn write<'b>(
+        path: &Path,
+        jimage_stamp: JImageStamp,
+        classes: impl ExactSizeIterator<
+            Item = (
+                &'b str,
+                &'b [u8],
+                &'b ClassFile<'b>,
+                std::vec::Vec<Option<(&'b [Instruction], &'b [u32])>>,
+            ),
+        >,
+    ) -> Result<()> {
+        let temp_path = path.with_extension("tmp");
+        let file = File::create(&temp_path)
+            .wrap_err_with(|| format_err!("failed to create {temp_path:?}"))?;
+        let mut writer = BufWriter::new(file);
+
+        writer.write_all(MAGIC)?;
+        writer.write_u32::<LittleEndian>(VERSION)?;
+        writer.write_u64::<LittleEndian>(jimage_stamp.size)?;
+        writer.write_u64::<LittleEndian>(jimage_stamp.modified)?;
+        writer.write_u32::<LittleEndian>(classes.len().try_into()?)?;
+
+        for (name, bytes, class_file, code) in classes {
+            let decoded =
+                bincode::serialize(&DecodedClassRef { class_file, code }).map_err(Error::other)?;
+
+            writer.write_u16::<LittleEndian>(name.len().try_into()?)?;
+            writer.write_all(name.as_bytes())?;
+            writer.write_u32::<LittleEndian>(bytes.len().try_into()?)?;
+            writer.write_all(bytes)?;
+            writer.write_u32::<LittleEndian>(decoded.len().try_into()?)?;
+            writer.write_all(&decoded)?;
+        }
+
+        writer.flush()?;
+        drop(writer);
+
+        fs::rename(&temp_path, path).wrap_err_with(|| format_err!("failed to create {path:?}"))
+    }
+
+    pub(crate) fn jimage_stamp(&self) -> JImageStamp {
+        self.jimage_stamp
+    }
+
+    /// Returns the class file of an archived class, given its binary name.
+    pub(crate) fn find_class(&self, class_name: &str) -> Option<&[u8]> {
+        let ranges = self.classes.get(class_name)?;
+        Some(&self.data[ranges.bytes.clone()])
+    }
+
+    /// Reads an archived class, given its binary name. The class is copied into the arena, so
+    /// that it can outlive the archive, and its strings and code are borrowed from the copy.
+    pub(crate) fn read_class<'a>(
+        &self,
+        arena: &'a Bump,
+        class_name: &str,
+    ) -> Option<Result<ArchivedClass<'a>>> {
+        let ranges = self.classes.get(class_name)?;
+        let bytes = &*arena.alloc_slice_copy(&self.data[ranges.bytes.clone()]);
+        let decoded = &*arena.alloc_slice_copy(&self.data[ranges.decoded.clone()]);
+
+        let decoded = bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .allow_trailing_bytes()
+            .deserialize_seed(InArena::<DecodedClass>::new(arena), decoded)
+            .map_err(Error::other)
+            .wrap_err_with(|| format_err!("invalid archived class {class_name}"));
+
+        Some(decoded.map(|decoded| {
+            ArchivedClass {
+                class_file: decoded.class_file,
+                bytes,
+                code: decoded
+                    .code
+                    .into_iter()
+                    .map(|code| code.map(|code| (code.instructions, code.offsets)))
+                    .collect(),
+            }
+        }))
+    }
+}
+
+/// Reads a length prefixed part of the archive, returning its range and moving past it.
+fn read_range(cursor: &mut Cursor<&[u8]>) -> Result<Range<usize>> {
+    let len = cursor.read_u32::<LittleEndian>()? as usize;
+    let start = cursor.position() as usize;
+    if start + len > cursor.get_ref().len() {
+        bail!("truncated class archive");
+    }
+
+    cursor.set_position((start + len) as u64);
+    Ok(start..start + len)
+}
+
+/// A value which is deserialized from an archive with the arena that its arena collections are
+/// allocated in, since serde's derives have no way to pass it to them.
+pub(crate) trait DeserializeIn<'de, 'a>: Sized {
+    fn deserialize_in<D: Deserializer<'de>>(
+        arena: &'a Bump,
+        deserializer: D,
+    ) -> Result<Self, D::Error>;
+}
+
+/// Deserializes a `T`, allocating its arena collections in the arena.
+pub(crate) struct InArena<'a, T>(&'a Bump, PhantomData<T>);
+
+impl<'a, T> InArena<'a, T> {
+    pub(crate) fn new(arena: &'a Bump) -> InArena<'a, T> {
+        InArena(arena, PhantomData)
+    }
+}
+
+impl<'de, 'a, T: DeserializeIn<'de, 'a>> DeserializeSeed<'de> for InArena<'a, T> {
+    type Value = T;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<T, D::Error> {
+        T::deserialize_in(self.0, deserializer)
+    }
+}
+
+/// Implements [`DeserializeIn`] for types without arena collections, using their `Deserialize`
+/// impls.
+macro_rules! deserialize_in_plain {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl<'de: 'a, 'a> DeserializeIn<'de, 'a> for $ty {
+                fn deserialize_in<D: Deserializer<'de>>(
+                    _arena: &'a Bump,
+                    deserializer: D,
+                ) -> Result<Self, D::Error> {
+                    <$ty>::deserialize(deserializer)
+                }
+            }
+        )*
+    };
+}
+
+/// Implements [`DeserializeIn`] for structs and enums with arena collections, reading the same
+/// format as their derived `Serialize` impls. The fields of each struct and struct variant must
+/// be listed in the order they're declared.
+macro_rules! deserialize_in {
+    ($(
+        $kind:ident $name:ident $body:tt
+    )*) => {
+        $(deserialize_in!(@item $kind $name $body);)*
+    };
+    (@item struct $name:ident { $($field:ident),* $(,)? }) => {
+        impl<'de: 'a, 'a> DeserializeIn<'de, 'a> for $name<'a> {
+            fn deserialize_in<D: Deserializer<'de>>(
+                arena: &'a Bump,
+                deserializer: D,
+            ) -> Result<Self, D::Error> {
+                deserializer.deserialize_struct(
+                    stringify!($name),
+                    &[$(stringify!($field)),*],
+                    deserialize_in!(@fields arena, $name { $($field),* }),
+                )
+            }
+        }
+    };
+    (@item enum $name:ident {
+        $($variant:ident $(($newtype:ident))? $({ $($field:ident),* $(,)? })?),* $(,)?
+    }) => {
+        impl<'de: 'a, 'a> DeserializeIn<'de, 'a> for $name<'a> {
+            fn deserialize_in<D: Deserializer<'de>>(
+                arena: &'a Bump,
+                deserializer: D,
+            ) -> Result<Self, D::Error> {
+                struct EnumVisitor<'a>(&'a Bump);
+
+                impl<'de: 'a, 'a> Visitor<'de> for EnumVisitor<'a> {
+                    type Value = $name<'a>;
+
+                    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                        f.write_str(concat!("enum ", stringify!($name)))
+                    }
+
+                    fn visit_enum<A: EnumAccess<'de>>(
+                        self,
+                        data: A,
+                    ) -> Result<Self::Value, A::Error> {
+                        // Variants are serialized as their indices
+                        #[allow(non_camel_case_types, clippy::enum_variant_names)]
+                        enum Index {
+                            $($variant),*
+                        }
+
+                        let arena = self.0;
+                        let (index, variant) = data.variant::<u32>()?;
+                        $(
+                            if index == Index::$variant as u32 {
+                                return deserialize_in!(
+                                    @variant arena, variant, $name::$variant
+                                    $(($newtype))? $({ $($field),* })?
+                                );
+                            }
+                        )*
+
+                        Err(A::Error::invalid_value(
+                            Unexpected::Unsigned(index.into()),
+                            &self,
+                        ))
+                    }
+                }
+
+                deserializer.deserialize_enum(
+                    stringify!($name),
+                    &[$(stringify!($variant)),*],
+                    EnumVisitor(arena),
+                )
+            }
+        }
+    };
+    (@variant $arena:ident, $access:ident, $name:ident::$variant:ident) => {{
+        $access.unit_variant()?;
+        Ok($name::$variant)
+    }};
+    (@variant $arena:ident, $access:ident, $name:ident::$variant:ident ($newtype:ident)) => {
+        $access
+            .newtype_variant_seed(InArena::new($arena))
+            .map($name::$variant)
+    };
+    (@variant
+        $arena:ident, $access:ident, $name:ident::$variant:ident { $($field:ident),* }
+    ) => {
+        $access.struct_variant(
+            &[$(stringify!($field)),*],
+            deserialize_in!(@fields $arena, $name::$variant { $($field),* }),
+        )
+    };
+    (@fields $arena:ident, $($path:ident)::+ { $($field:ident),* }) => {{
+        struct FieldsVisitor<'a>(&'a Bump);
+
+        impl<'de: 'a, 'a> Visitor<'de> for FieldsVisitor<'a> {
+            type Value = deserialize_in!(@type $($path)::+);
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str(stringify!($($path)::+))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut len = 0;
+                Ok($($path)::+ {
+                    $($field: {
+                        let field = seq.next_element_seed(InArena::new(self.0))?;
+                        len += 1;
+                        field.ok_or_else(|| A::Error::invalid_length(len - 1, &self))?
+                    }),*
+                })
+            }
+        }
+
+        FieldsVisitor($arena)
+    }};
+    (@type $name:ident $(::$variant:ident)?) => { $name<'a> };
+}
+
+deserialize_in_plain!(
+    u8,
+    u16,
+    u32,
+    i32,
+    i64,
+    f32,
+    f64,
+    Instruction,
+    ConstantInfo<'a>,
+    ClassAccessFlags,
+    FieldAccessFlags,
+    MethodAccessFlags,
+    InnerClassAccessFlags,
+    ModuleFlags,
+    ExceptionTableEntry,
+    LineNumberTableEntry,
+    VerificationTypeInfo,
+    InnerClass,
+    SourceFileAttribute,
+    SignatureAttribute,
+    ModuleRequires,
+    ModuleMainClassAttribute,
+    ExportsFlags,
+    LocalVarTargetEntry,
+    TypePathEntry,
+    CustomAttribute<'a>,
+);
+
+deserialize_in! {
+    struct DecodedClass { class_file, code }
+    struct ArchivedCode { instructions, offsets }
+    struct ClassFile {
+        minor_version,
+        major_version,
+        constant_pool,
+        access_flags,
+        this_class,
+        super_class,
+        interfaces,
+        fields,
+        methods,
+        attributes,
+    }
+    struct FieldInfo { access_flags, name_index, descriptor_index, attributes }
+    struct MethodInfo { access_flags, name_index, descriptor_index, attributes }
+    enum AttributeInfo {
+        Code(CodeAttribute),
+        LineNumberTable(LineNumberTableAttribute),
+        StackMapTable(StackMapTableAttribute),
+        BootstrapMethods(BootstrapMethodsAttribute),
+        InnerClasses(InnerClassesAttribute),
+        SourceFile(SourceFileAttribute),
+        Signature(SignatureAttribute),
+        RuntimeVisibleAnnotations(AnnotationsAttribute),
+        RuntimeInvisibleAnnotations(AnnotationsAttribute),
+        RuntimeVisibleParameterAnnotations(ParameterAnnotationsAttribute),
+        RuntimeInvisibleParameterAnnotations(ParameterAnnotationsAttribute),
+        RuntimeVisibleTypeAnnotations(TypeAnnotationsAttribute),
+        RuntimeInvisibleTypeAnnotations(TypeAnnotationsAttribute),
+        Module(ModuleAttribute),
+        ModulePackages(ModulePackagesAttribute),
+        ModuleMainClass(ModuleMainClassAttribute),
+        Synthetic,
+        Deprecated,
+        Custom(CustomAttribute),
+        Malformed(MalformedAttribute),
+    }
+    struct CodeAttribute { max_stack, max_locals, code, exception_table, attributes }
+    struct LineNumberTableAttribute { line_number_table }
+    struct StackMapTableAttribute { entries }
+    enum StackMapFrame {
+        Same { offset_delta },
+        SameLocals1StackItem { offset_delta, stack },
+        SameLocals1StackItemExtended { offset_delta, stack },
+        Chop { k, offset_delta },
+        SameExtended { offset_delta },
+        Append { offset_delta, locals },
+        Full { offset_delta, locals, stack },
+    }
+    struct BootstrapMethodsAttribute { bootstrap_methods }
+    struct BootstrapMethod { bootstrap_method_ref, bootstrap_arguments }
+    struct InnerClassesAttribute { classes }
+    struct ModuleAttribute {
+        module_name_index,
+        module_flags,
+        module_version_index,
+        requires,
+        exports,
+        opens,
+        uses,
+        provides,
+    }
+    struct ModuleExports { package_index, flags, to_index }
+    struct ModuleProvides { provides_index, provides_with_index }
+    struct ModulePackagesAttribute { package_index }
+    struct AnnotationsAttribute { annotations }
+    struct ParameterAnnotationsAttribute { parameter_annotations }
+    struct Annotation { type_index, element_value_pairs }
+    struct TypeAnnotationsAttribute { annotations }
+    struct TypeAnnotation { target_type, target_info, target_path, annotation }
+    enum TargetInfo {
+        TypeParameter { type_parameter_index },
+        Supertype { supertype_index },
+        TypeParameterBound { type_parameter_index, bound_index },
+        Empty,
+        FormalParameter { formal_parameter_index },
+        Throws { throws_type_index },
+        LocalVar { table },
+        Catch { exception_table_index },
+        Offset { offset },
+        TypeArgument { offset, type_argument_index },
+    }
+    struct ElementValuePair { element_name_index, value }
+    enum ElementValue {
+        Byte(u16),
+        Char(u16),
+        Double(u16),
+        Float(u16),
+        Int(u16),
+        Long(u16),
+        Short(u16),
+        Boolean(u16),
+        String(u16),
+        Enum { type_name_index, const_name_index },
+        Class(u16),
+        Annotation(Annotation),
+        Array(Vec),
+    }
+    struct MalformedAttribute { attribute_name_index, error, info }
+}
+
+impl<'de: 'a, 'a> DeserializeIn<'de, 'a> for ConstantPool<'a> {
+    fn deserialize_in<D: Deserializer<'de>>(
+        arena: &'a Bump,
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        struct ConstantPoolVisitor<'a>(&'a Bump);
+
+        impl<'de: 'a, 'a> Visitor<'de> for ConstantPoolVisitor<'a> {
+            type Value = ConstantPool<'a>;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a constant pool")
+            }
+
+            fn visit_newtype_struct<D: Deserializer<'de>>(
+                self,
+                deserializer: D,
+            ) -> Result<Self::Value, D::Error> {
+                Vec::deserialize_in(self.0, deserializer).map(ConstantPool)
+            }
+        }
+
+        deserializer.deserialize_newtype_struct("ConstantPool", ConstantPoolVisitor(arena))
+    }
+}
+
+impl<'de: 'a, 'a> DeserializeIn<'de, 'a> for &'a str {
+    fn deserialize_in<D: Deserializer<'de>>(
+        _arena: &'a Bump,
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        <&str>::deserialize(deserializer)
+    }
+}
+
+impl<'de: 'a, 'a> DeserializeIn<'de, 'a> for &'a [u8] {
+    fn deserialize_in<D: Deserializer<'de>>(
+        _arena: &'a Bump,
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        <&[u8]>::deserialize(deserializer)
+    }
+}
+
+impl<'de: 'a, 'a> DeserializeIn<'de, 'a> for bumpalo::collections::String<'a> {
+    fn deserialize_in<D: Deserializer<'de>>(
+        arena: &'a Bump,
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        let s = <&str>::deserialize(deserializer)?;
+        Ok(bumpalo::collections::String::from_str_in(s, arena))
+    }
+}
+
+impl<'de, 'a, T: DeserializeIn<'de, 'a>> DeserializeIn<'de, 'a> for Option<T> {
+    fn deserialize_in<D: Deserializer<'de>>(
+        arena: &'a Bump,
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        struct OptionVisitor<'a, T>(&'a Bump, PhantomData<T>);
+
+        impl<'de, 'a, T: DeserializeIn<'de, 'a>> Visitor<'de> for OptionVisitor<'a, T> {
+            type Value = Option<T>;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("an option")
+            }
+
+            fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+                Ok(None)
+            }
+
+            fn visit_some<D: Deserializer<'de>>(
+                self,
+                deserializer: D,
+            ) -> Result<Self::Value, D::Error> {
+                T::deserialize_in(self.0, deserializer).map(Some)
+            }
+        }
+
+        deserializer.deserialize_option(OptionVisitor(arena, PhantomData))
+    }
+}
+
+impl<'de, 'a, T: DeserializeIn<'de, 'a>> DeserializeIn<'de, 'a> for Vec<'a, T> {
+    fn deserialize_in<D: Deserializer<'de>>(
+        arena: &'a Bump,
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        deserializer.deserialize_seq(SeqVisitor::new(arena, |len| {
+            Vec::with_capacity_in(len, arena)
+        }))
+    }
+}
+
+impl<'de, 'a, T: DeserializeIn<'de, 'a>> DeserializeIn<'de, 'a> for std::vec::Vec<T> {
+    fn deserialize_in<D: Deserializer<'de>>(
+        arena: &'a Bump,
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        deserializer.deserialize_seq(SeqVisitor::new(arena, std::vec::Vec::with_capacity))
+    }
+}
+
+/// Deserializes a sequence of `T` into a collection created by the function, given the length.
+struct SeqVisitor<'a, F, T>(&'a Bump, F, PhantomData<T>);
+
+impl<'a, F, T> SeqVisitor<'a, F, T> {
+    fn new(arena: &'a Bump, f: F) -> SeqVisitor<'a, F, T> {
+        SeqVisitor(arena, f, PhantomData)
+    }
+}
+
+impl<'de, 'a, F, C, T> Visitor<'de> for SeqVisitor<'a, F, T>
+where
+    F: FnOnce(usize) -> C,
+    C: Extend<T>,
+    T: DeserializeIn<'de, 'a>,
+{
+    type Value = C;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("a sequence")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut collection = (self.1)(seq.size_hint().unwrap_or(0));
+        while let Some(element) = seq.next_element_seed(InArena::new(self.0))? {
+            collection.extend(Some(element));
+        }
+        Ok(collection)
+    }
+}
@@ -1,10 +1,11 @@
 use bitflags::bitflags;
 use bumpalo::collections::Vec;
+use serde::{Deserialize, Serialize};
 use strum::EnumTryAs;
 
 use self::constant_pool::ConstantPool;
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct ClassFile<'a> {
     pub minor_version: u16,
     pub major_version: u16,
@@ -18,12 +19,69 @@ pub struct ClassFile<'a> {
     pub attributes: Vec<'a, AttributeInfo<'a>>,
 }
 
+impl<'a> ClassFile<'a> {
+    /// Returns the binary name of the class.
+    pub fn this_class_name(&self) -> Option<&str> {
+        let class = self
+            .constant_pool
+            .get(self.this_class)?
+            .try_as_class_ref()?;
+        let name = self
+            .constant_pool
+            .get(class.name_index)?
+            .try_as_utf_8_ref()?;
+
+        Some(*name)
+    }
+
+    /// Returns the binary name of the direct super class, or `None` for `java/lang/Object`.
+    pub fn super_class_name(&self) -> Option<&str> {
+        if self.super_class == 0 {
+            return None;
+        }
+
+        let class = self
+            .constant_pool
+            .get(self.super_class)?
+            .try_as_class_ref()?;
+        let name = self
+            .constant_pool
+            .get(class.name_index)?
+            .try_as_utf_8_ref()?;
+
+        Some(*name)
+    }
+
+    /// Returns whether the class was generated by the compiler, from either the access flag or
+    /// the `Synthetic` attribute.
+    pub fn is_synthetic(&self) -> bool {
+        self.access_flags.contains(ClassAccessFlags::SYNTHETIC) || is_synthetic(&self.attributes)
+    }
+
+    pub fn is_deprecated(&self) -> bool {
+        is_deprecated(&self.attributes)
+    }
+}
+
+fn is_synthetic(attributes: &[AttributeInfo]) -> bool {
+    attributes
+        .iter()
+        .any(|attr| matches!(attr, AttributeInfo::Synthetic))
+}
+
+fn is_deprecated(attributes: &[AttributeInfo]) -> bool {
+    attributes
+        .iter()
+        .any(|attr| matches!(attr, AttributeInfo::Deprecated))
+}
+
 pub mod constant_pool {
     use std::ops::Index;
 
+    use serde::{Deserialize, Serialize};
     use strum::EnumTryAs;
 
-    #[derive(Debug)]
+    #[derive(Debug, Serialize)]
     pub struct ConstantPool<'a>(pub(crate) bumpalo::collections::Vec<'a, ConstantInfo<'a>>);
 
     impl<'a> ConstantPool<'a> {
@@ -40,10 +98,11 @@ pub mod constant_pool {
         }
     }
 
-    #[derive(Debug, EnumTryAs)]
+    #[derive(Debug, PartialEq, Serialize, Deserialize, EnumTryAs)]
+    #[serde(bound(deserialize = "'de: 'a"))]
     pub enum ConstantInfo<'a> {
         Unused,
-        Utf8(bumpalo::collections::String<'a>),
+        Utf8(&'a str),
         Integer(i32),
         Float(f32),
         Long(i64),
@@ -60,72 +119,75 @@ pub mod constant_pool {
         InvokeDynamic(InvokeDynamic),
         Module(Module),
         Package(Package),
+        /// A constant with an unknown tag, which is only produced by lenient parsing. Nothing
+        /// after it can be read, since its length isn't known.
+        Unknown(u8),
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
     pub struct Class {
         pub name_index: u16,
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
     pub struct String {
         pub string_index: u16,
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
     pub struct FieldRef {
         pub class_index: u16,
         pub name_and_type_index: u16,
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
     pub struct MethodRef {
         pub class_index: u16,
         pub name_and_type_index: u16,
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
     pub struct NameAndType {
         pub name_index: u16,
         pub descriptor_index: u16,
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
     pub struct MethodHandle {
         pub reference_kind: u8,
         pub reference_index: u16,
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
     pub struct MethodType {
         pub descriptor_index: u16,
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
     pub struct Dynamic {
         pub bootstrap_method_attr_index: u16,
         pub name_and_type_index: u16,
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
     pub struct InvokeDynamic {
         pub bootstrap_method_attr_index: u16,
         pub name_and_type_index: u16,
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
     pub struct Module {
         pub name_index: u16,
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
     pub struct Package {
         pub name_index: u16,
     }
 }
 
 bitflags! {
-    #[derive(Debug)]
+    #[derive(Debug, Serialize, Deserialize)]
     pub struct ClassAccessFlags: u16 {
         const PUBLIC = 0x0001;
         const FINAL = 0x0010;
@@ -139,7 +201,7 @@ bitflags! {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct FieldInfo<'a> {
     pub access_flags: FieldAccessFlags,
     pub name_index: u16,
@@ -147,8 +209,20 @@ pub struct FieldInfo<'a> {
     pub attributes: Vec<'a, AttributeInfo<'a>>,
 }
 
+impl FieldInfo<'_> {
+    /// Returns whether the field was generated by the compiler, from either the access flag or
+    /// the `Synthetic` attribute.
+    pub fn is_synthetic(&self) -> bool {
+        self.access_flags.contains(FieldAccessFlags::SYNTHETIC) || is_synthetic(&self.attributes)
+    }
+
+    pub fn is_deprecated(&self) -> bool {
+        is_deprecated(&self.attributes)
+    }
+}
+
 bitflags! {
-    #[derive(Debug, Clone)]
+    #[derive(Debug, Clone, Serialize, Deserialize)]
     pub struct FieldAccessFlags: u16 {
         const PUBLIC = 0x0001;
         const PRIVATE = 0x0002;
@@ -162,7 +236,7 @@ bitflags! {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct MethodInfo<'a> {
     pub access_flags: MethodAccessFlags,
     pub name_index: u16,
@@ -170,8 +244,20 @@ pub struct MethodInfo<'a> {
     pub attributes: Vec<'a, AttributeInfo<'a>>,
 }
 
+impl MethodInfo<'_> {
+    /// Returns whether the method was generated by the compiler, from either the access flag or
+    /// the `Synthetic` attribute.
+    pub fn is_synthetic(&self) -> bool {
+        self.access_flags.contains(MethodAccessFlags::SYNTHETIC) || is_synthetic(&self.attributes)
+    }
+
+    pub fn is_deprecated(&self) -> bool {
+        is_deprecated(&self.attributes)
+    }
+}
+
 bitflags! {
-    #[derive(Clone, Copy, Debug)]
+    #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
     pub struct MethodAccessFlags: u16 {
         const PUBLIC = 0x0001;
         const PRIVATE = 0x0002;
@@ -188,26 +274,43 @@ bitflags! {
     }
 }
 
-#[derive(Debug, EnumTryAs)]
+#[derive(Debug, Serialize, EnumTryAs)]
 pub enum AttributeInfo<'a> {
     Code(CodeAttribute<'a>),
     LineNumberTable(LineNumberTableAttribute<'a>),
+    StackMapTable(StackMapTableAttribute<'a>),
     BootstrapMethods(BootstrapMethodsAttribute<'a>),
     InnerClasses(InnerClassesAttribute<'a>),
     SourceFile(SourceFileAttribute),
+    Signature(SignatureAttribute),
+    RuntimeVisibleAnnotations(AnnotationsAttribute<'a>),
+    RuntimeInvisibleAnnotations(AnnotationsAttribute<'a>),
+    RuntimeVisibleParameterAnnotations(ParameterAnnotationsAttribute<'a>),
+    RuntimeInvisibleParameterAnnotations(ParameterAnnotationsAttribute<'a>),
+    RuntimeVisibleTypeAnnotations(TypeAnnotationsAttribute<'a>),
+    RuntimeInvisibleTypeAnnotations(TypeAnnotationsAttribute<'a>),
+    Module(ModuleAttribute<'a>),
+    ModulePackages(ModulePackagesAttribute<'a>),
+    ModuleMainClass(ModuleMainClassAttribute),
+    /// Marks a member generated by the compiler, like the `SYNTHETIC` access flag which replaced
+    /// it.
+    Synthetic,
+    Deprecated,
     Custom(CustomAttribute<'a>),
+    /// An attribute that couldn't be parsed, which is only produced by lenient parsing.
+    Malformed(MalformedAttribute<'a>),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct CodeAttribute<'a> {
     pub max_stack: u16,
     pub max_locals: u16,
-    pub code: Vec<'a, u8>,
+    pub code: &'a [u8],
     pub exception_table: Vec<'a, ExceptionTableEntry>,
     pub attributes: Vec<'a, AttributeInfo<'a>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ExceptionTableEntry {
     pub start_pc: u16,
     pub end_pc: u16,
@@ -215,34 +318,109 @@ pub struct ExceptionTableEntry {
     pub catch_type: u16,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct LineNumberTableAttribute<'a> {
     pub line_number_table: Vec<'a, LineNumberTableEntry>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct LineNumberTableEntry {
     pub start_pc: u16,
     pub line_number: u16,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+pub struct StackMapTableAttribute<'a> {
+    pub entries: Vec<'a, StackMapFrame<'a>>,
+}
+
+/// The types of the locals and operand stack at an offset in the code. Each frame is relative to
+/// the previous one, starting from a frame built from the method descriptor, and its offset is
+/// `offset_delta + 1` past the previous frame's (or just `offset_delta` for the first frame).
+#[derive(Debug, Serialize)]
+pub enum StackMapFrame<'a> {
+    /// The same locals as the previous frame, with an empty stack. `offset_delta` is at most 63.
+    Same {
+        offset_delta: u16,
+    },
+    /// The same locals as the previous frame, with one value on the stack. `offset_delta` is at
+    /// most 63.
+    SameLocals1StackItem {
+        offset_delta: u16,
+        stack: VerificationTypeInfo,
+    },
+    SameLocals1StackItemExtended {
+        offset_delta: u16,
+        stack: VerificationTypeInfo,
+    },
+    /// The locals of the previous frame without the last `k` of them, with an empty stack.
+    Chop {
+        k: u8,
+        offset_delta: u16,
+    },
+    SameExtended {
+        offset_delta: u16,
+    },
+    /// The locals of the previous frame plus up to 3 more, with an empty stack.
+    Append {
+        offset_delta: u16,
+        locals: Vec<'a, VerificationTypeInfo>,
+    },
+    Full {
+        offset_delta: u16,
+        locals: Vec<'a, VerificationTypeInfo>,
+        stack: Vec<'a, VerificationTypeInfo>,
+    },
+}
+
+impl StackMapFrame<'_> {
+    pub fn offset_delta(&self) -> u16 {
+        match self {
+            StackMapFrame::Same { offset_delta }
+            | StackMapFrame::SameLocals1StackItem { offset_delta, .. }
+            | StackMapFrame::SameLocals1StackItemExtended { offset_delta, .. }
+            | StackMapFrame::Chop { offset_delta, .. }
+            | StackMapFrame::SameExtended { offset_delta }
+            | StackMapFrame::Append { offset_delta, .. }
+            | StackMapFrame::Full { offset_delta, .. } => *offset_delta,
+        }
+    }
+}
+
+/// The type of a single local or stack entry in a [`StackMapFrame`]. `Long` and `Double` take up
+/// two locals, but are a single entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VerificationTypeInfo {
+    Top,
+    Integer,
+    Float,
+    Double,
+    Long,
+    Null,
+    UninitializedThis,
+    /// An instance of the `Class` constant at the index.
+    Object(u16),
+    /// An object created by the `new` instruction at the offset, before its constructor is called.
+    Uninitialized(u16),
+}
+
+#[derive(Debug, Serialize)]
 pub struct BootstrapMethodsAttribute<'a> {
     pub bootstrap_methods: Vec<'a, BootstrapMethod<'a>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct BootstrapMethod<'a> {
     pub bootstrap_method_ref: u16,
     pub bootstrap_arguments: Vec<'a, u16>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct InnerClassesAttribute<'a> {
     pub classes: Vec<'a, InnerClass>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct InnerClass {
     pub inner_class_info_index: u16,
     pub outer_class_info_index: u16,
@@ -251,7 +429,7 @@ pub struct InnerClass {
 }
 
 bitflags! {
-    #[derive(Debug)]
+    #[derive(Debug, Serialize, Deserialize)]
     pub struct InnerClassAccessFlags: u16 {
         const PUBLIC = 0x0001;
         const PRIVATE = 0x0002;
@@ -266,13 +444,230 @@ bitflags! {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct SourceFileAttribute {
     pub sourcefile_index: u16,
 }
 
-#[derive(Debug)]
+/// The generic signature of a class, method or field, which can be parsed with the functions in
+/// [`crate::descriptor`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignatureAttribute {
+    pub signature_index: u16,
+}
+
+/// The declaration of a module, from `module-info.class`.
+#[derive(Debug, Serialize)]
+pub struct ModuleAttribute<'a> {
+    pub module_name_index: u16,
+    pub module_flags: ModuleFlags,
+    /// The version of the module, or 0 if it has no version.
+    pub module_version_index: u16,
+    pub requires: Vec<'a, ModuleRequires>,
+    pub exports: Vec<'a, ModuleExports<'a>>,
+    pub opens: Vec<'a, ModuleExports<'a>>,
+    pub uses: Vec<'a, u16>,
+    pub provides: Vec<'a, ModuleProvides<'a>>,
+}
+
+bitflags! {
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct ModuleFlags: u16 {
+        const OPEN = 0x0020;
+        const SYNTHETIC = 0x1000;
+        const MANDATED = 0x8000;
+    }
+}
+
+bitflags! {
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct RequiresFlags: u16 {
+        const TRANSITIVE = 0x0020;
+        const STATIC_PHASE = 0x0040;
+        const SYNTHETIC = 0x1000;
+        const MANDATED = 0x8000;
+    }
+}
+
+bitflags! {
+    /// Flags of an `exports` or `opens` directive.
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct ExportsFlags: u16 {
+        const SYNTHETIC = 0x1000;
+        const MANDATED = 0x8000;
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ModuleRequires {
+    pub requires_index: u16,
+    pub requires_flags: RequiresFlags,
+    pub requires_version_index: u16,
+}
+
+/// An `exports` or `opens` directive. The package is exported or opened to all modules if there
+/// are no target modules.
+#[derive(Debug, Serialize)]
+pub struct ModuleExports<'a> {
+    pub package_index: u16,
+    pub flags: ExportsFlags,
+    pub to_index: Vec<'a, u16>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ModuleProvides<'a> {
+    pub provides_index: u16,
+    pub provides_with_index: Vec<'a, u16>,
+}
+
+/// All of the packages in a module, including those which aren't exported or opened.
+#[derive(Debug, Serialize)]
+pub struct ModulePackagesAttribute<'a> {
+    pub package_index: Vec<'a, u16>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ModuleMainClassAttribute {
+    pub main_class_index: u16,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnnotationsAttribute<'a> {
+    pub annotations: Vec<'a, Annotation<'a>>,
+}
+
+/// The annotations on each parameter of a method. There may be fewer entries than parameters in
+/// the descriptor, e.g. javac omits the synthetic parameters of inner class constructors.
+#[derive(Debug, Serialize)]
+pub struct ParameterAnnotationsAttribute<'a> {
+    pub parameter_annotations: Vec<'a, Vec<'a, Annotation<'a>>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Annotation<'a> {
+    /// The field descriptor of the annotation interface, e.g. `Ljava/lang/Deprecated;`.
+    pub type_index: u16,
+    pub element_value_pairs: Vec<'a, ElementValuePair<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TypeAnnotationsAttribute<'a> {
+    pub annotations: Vec<'a, TypeAnnotation<'a>>,
+}
+
+/// An annotation on a use of a type, e.g. `List<@NonNull String>`.
+#[derive(Debug, Serialize)]
+pub struct TypeAnnotation<'a> {
+    /// The kind of target, which determines the kind of `target_info`, e.g. `0x13` for the type
+    /// in a field declaration.
+    pub target_type: u8,
+    pub target_info: TargetInfo<'a>,
+    /// The location of the annotated type within the target type, e.g. a type argument.
+    pub target_path: Vec<'a, TypePathEntry>,
+    pub annotation: Annotation<'a>,
+}
+
+/// Identifies which type in a declaration or expression is annotated.
+#[derive(Debug, Serialize)]
+pub enum TargetInfo<'a> {
+    TypeParameter {
+        type_parameter_index: u8,
+    },
+    /// The index of the interface in `interfaces`, or 65535 for the super class.
+    Supertype {
+        supertype_index: u16,
+    },
+    TypeParameterBound {
+        type_parameter_index: u8,
+        bound_index: u8,
+    },
+    /// The type in a field declaration, the return type of a method, or the receiver type.
+    Empty,
+    FormalParameter {
+        formal_parameter_index: u8,
+    },
+    Throws {
+        throws_type_index: u16,
+    },
+    LocalVar {
+        table: Vec<'a, LocalVarTargetEntry>,
+    },
+    Catch {
+        exception_table_index: u16,
+    },
+    /// The type in an `instanceof`, `new` or method reference expression.
+    Offset {
+        offset: u16,
+    },
+    /// A type argument in a cast, a generic constructor or method invocation, or a method
+    /// reference.
+    TypeArgument {
+        offset: u16,
+        type_argument_index: u8,
+    },
+}
+
+/// The range of code where a local variable with an annotated type is live.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LocalVarTargetEntry {
+    pub start_pc: u16,
+    pub length: u16,
+    pub index: u16,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TypePathEntry {
+    /// Whether the step is into an array type (0), a nested type (1), a wildcard bound (2) or a
+    /// type argument (3).
+    pub type_path_kind: u8,
+    pub type_argument_index: u8,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ElementValuePair<'a> {
+    pub element_name_index: u16,
+    pub value: ElementValue<'a>,
+}
+
+/// The value of an annotation element. Constants are stored as indices into the constant pool,
+/// where strings are `Utf8` entries rather than `String` entries.
+#[derive(Debug, Serialize, EnumTryAs)]
+pub enum ElementValue<'a> {
+    Byte(u16),
+    Char(u16),
+    Double(u16),
+    Float(u16),
+    Int(u16),
+    Long(u16),
+    Short(u16),
+    Boolean(u16),
+    String(u16),
+    Enum {
+        type_name_index: u16,
+        const_name_index: u16,
+    },
+    /// The return descriptor of the class, e.g. `Ljava/lang/Object;` or `V` for `void.class`.
+    Class(u16),
+    Annotation(Annotation<'a>),
+    Array(Vec<'a, ElementValue<'a>>),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'a"))]
 pub struct CustomAttribute<'a> {
     pub attribute_name_index: u16,
-    pub info: Vec<'a, u8>,
+    pub info: &'a [u8],
+}
+
+#[derive(Debug, Serialize)]
+pub struct MalformedAttribute<'a> {
+    pub attribute_name_index: u16,
+    #[serde(serialize_with = "serialize_str")]
+    pub error: bumpalo::collections::String<'a>,
+    pub info: &'a [u8],
+}
+
+/// Serializes an arena string, which doesn't implement `Serialize` itself.
+fn serialize_str<S: serde::Serializer>(value: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(value)
 }
@@ -0,0 +1,67 @@
+use std::env;
+use std::fmt;
+use std::path::PathBuf;
+
+/// The directories searched for class files that aren't part of the JDK, like the `-cp` option
+/// of `java`. Jar files aren't supported yet.
+#[derive(Clone, Debug)]
+pub struct ClassPath {
+    entries: Vec<PathBuf>,
+}
+
+impl ClassPath {
+    pub fn new(entries: impl IntoIterator<Item = impl Into<PathBuf>>) -> ClassPath {
+        ClassPath {
+            entries: entries.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Parses a class path in the platform's format, e.g. `dir1:dir2` on Unix or `dir1;dir2` on
+    /// Windows.
+    pub fn parse(class_path: &str) -> ClassPath {
+        ClassPath::new(env::split_paths(class_path))
+    }
+
+    pub fn entries(&self) -> &[PathBuf] {
+        &self.entries
+    }
+
+    /// Returns the path to the class file for a class, given its binary name (e.g.
+    /// `com/example/Foo`). The entries are searched in order, and the first match is returned.
+    pub fn find(&self, class_name: &str) -> Option<PathBuf> {
+        self.candidates(class_name).find(|path| path.is_file())
+    }
+
+    /// Describes the locations that are searched for a class, for error messages.
+    pub(crate) fn searched_locations(&self, class_name: &str) -> String {
+        self.candidates(class_name)
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn candidates<'b>(&'b self, class_name: &str) -> impl Iterator<Item = PathBuf> + 'b {
+        let relative_path = format!("{class_name}.class");
+        self.entries
+            .iter()
+            .map(move |entry| entry.join(&relative_path))
+    }
+}
+
+/// The current directory, which is the default class path of `java`.
+impl Default for ClassPath {
+    fn default() -> ClassPath {
+        ClassPath::new(["."])
+    }
+}
+
+/// Formats the class path in the platform's format, as used for the `java.class.path` property.
+impl fmt::Display for ClassPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match env::join_paths(&self.entries) {
+            Ok(joined) => write!(f, "{}", joined.to_string_lossy()),
+            // Entries containing the separator can't be joined, so they are listed instead
+            Err(_) => write!(f, "{:?}", self.entries),
+        }
+    }
+}
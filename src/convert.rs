@@ -0,0 +1,600 @@
+//! Conversions between Rust values and the values of Java code, used to call Java methods from
+//! Rust and to implement native methods in Rust.
+
+use std::borrow::Cow;
+
+use crate::call_frame::{ArrayElementType, JavaException, JvmValue, RefTypeHeader};
+use crate::error::{bail, format_err, Result};
+use crate::instructions::ArrayType;
+use crate::vm::Vm;
+
+/// A Rust type which can be passed to Java code.
+pub trait ToJvm<'a> {
+    /// The descriptor of the corresponding Java type, e.g. `I` for `i32`.
+    fn descriptor() -> Cow<'static, str>;
+
+    /// Returns whether this type can be returned from a native method whose return type has the
+    /// given descriptor. By default only its own descriptor matches.
+    fn matches(descriptor: &str) -> bool {
+        descriptor == Self::descriptor()
+    }
+
+    fn to_jvm(self, vm: &Vm<'a>) -> Result<JvmValue<'a>>;
+}
+
+/// A Rust type which can be returned from Java code.
+pub trait FromJvm<'a>: Sized {
+    /// The descriptor of the corresponding Java type, or `V` for `()`.
+    fn descriptor() -> Cow<'static, str>;
+
+    /// Returns whether this type can be a parameter of a native method for a parameter with the
+    /// given descriptor. By default only its own descriptor matches.
+    fn matches(descriptor: &str) -> bool {
+        descriptor == Self::descriptor()
+    }
+
+    /// Converts a value returned by a method, which is `None` for void methods.
+    fn from_jvm(vm: &Vm<'a>, value: Option<JvmValue<'a>>) -> Result<Self>;
+}
+
+/// The arguments to a method, as a tuple of values which can be passed to Java code.
+pub trait Args<'a> {
+    /// Writes the parameter descriptors, without the parentheses around them.
+    fn write_descriptor(descriptor: &mut String);
+
+    fn into_values(self, vm: &Vm<'a>) -> Result<Vec<JvmValue<'a>>>;
+}
+
+/// The result of a Rust function which implements a Java method, which is `()` for void methods.
+pub trait ReturnValue<'a> {
+    fn descriptor() -> Cow<'static, str>;
+
+    /// Returns whether this type can be returned for the given return type, like
+    /// [`ToJvm::matches`].
+    fn matches(descriptor: &str) -> bool;
+
+    fn into_return_value(self, vm: &Vm<'a>) -> Result<Option<JvmValue<'a>>>;
+}
+
+/// A Rust function which implements a native method. It's called with the vm, followed by each
+/// argument converted with [`FromJvm`], starting with the receiver for instance methods.
+pub trait NativeFn<'a, A>: 'a {
+    /// Returns the descriptors of the parameters and of the return type.
+    fn descriptors() -> (Vec<Cow<'static, str>>, Cow<'static, str>);
+
+    /// Returns whether the function's types match the descriptors of a method's parameters,
+    /// including the receiver of instance methods, and of its return type. See
+    /// [`FromJvm::matches`] and [`ToJvm::matches`].
+    fn matches(params: &[&str], ret: &str) -> bool;
+
+    fn call(&self, vm: &Vm<'a>, args: &[JvmValue<'a>]) -> Result<Option<JvmValue<'a>>>;
+}
+
+/// Returns the descriptor of a method taking `A` and returning `R`.
+pub fn method_descriptor<'a, A: Args<'a>, R: FromJvm<'a>>() -> String {
+    let mut descriptor = String::from("(");
+    A::write_descriptor(&mut descriptor);
+    descriptor.push(')');
+    descriptor.push_str(&R::descriptor());
+    descriptor
+}
+
+macro_rules! primitive {
+    (
+        $ty:ty,
+        $descriptor:literal $(| $widened:literal)*,
+        |$this:ident| $to:expr,
+        |$value:ident| $from:expr
+    ) => {
+        impl<'a> ToJvm<'a> for $ty {
+            fn descriptor() -> Cow<'static, str> {
+                Cow::Borrowed($descriptor)
+            }
+
+            fn matches(descriptor: &str) -> bool {
+                matches!(descriptor, $descriptor $(| $widened)*)
+            }
+
+            fn to_jvm(self, _: &Vm<'a>) -> Result<JvmValue<'a>> {
+                let $this = self;
+                Ok($to)
+            }
+        }
+
+        impl<'a> FromJvm<'a> for $ty {
+            fn descriptor() -> Cow<'static, str> {
+                Cow::Borrowed($descriptor)
+            }
+
+            fn matches(descriptor: &str) -> bool {
+                matches!(descriptor, $descriptor $(| $widened)*)
+            }
+
+            fn from_jvm(_: &Vm<'a>, value: Option<JvmValue<'a>>) -> Result<Self> {
+                match value {
+                    Some($value) => $from,
+                    None => bail!(concat!("expected ", $descriptor, ", found void")),
+                }
+            }
+        }
+    };
+}
+
+// Values narrower than an int, including booleans, are represented as ints on the operand stack,
+// so an i32 can stand for any of them
+primitive!(i8, "B", |v| JvmValue::Int(v.into()), |value| Ok(
+    i8::try_from(int_value(value)?)?
+));
+
+primitive!(i16, "S", |v| JvmValue::Int(v.into()), |value| Ok(
+    i16::try_from(int_value(value)?)?
+));
+
+primitive!(
+    i32,
+    "I" | "B" | "C" | "S" | "Z",
+    |v| JvmValue::Int(v),
+    |value| int_value(value)
+);
+
+primitive!(i64, "J", |v| JvmValue::Long(v), |value| match value {
+    JvmValue::Long(v) => Ok(v),
+    value => bail!("expected long, found {value:?}"),
+});
+
+primitive!(f32, "F", |v| JvmValue::Float(v), |value| match value {
+    JvmValue::Float(v) => Ok(v),
+    value => bail!("expected float, found {value:?}"),
+});
+
+primitive!(f64, "D", |v| JvmValue::Double(v), |value| match value {
+    JvmValue::Double(v) => Ok(v),
+    value => bail!("expected double, found {value:?}"),
+});
+
+primitive!(bool, "Z", |v| JvmValue::Int(v.into()), |value| Ok(
+    int_value(value)? != 0
+));
+
+// Java chars are UTF-16 code units, so only chars in the Basic Multilingual Plane are convertible
+primitive!(
+    char,
+    "C",
+    |v| JvmValue::Int(
+        u16::try_from(u32::from(v))
+            .map_err(|_| format_err!("{v:?} isn't a single UTF-16 code unit"))?
+            .into()
+    ),
+    |value| {
+        let unit = u16::try_from(int_value(value)?)?;
+        char::from_u32(unit.into()).ok_or_else(|| format_err!("{unit:#x} is a surrogate"))
+    }
+);
+
+/// Returns the value of an int, or of a narrower value which is widened to an int.
+fn int_value(value: JvmValue) -> Result<i32> {
+    match value {
+        JvmValue::Int(v) => Ok(v),
+        JvmValue::Byte(v) => Ok(v.into()),
+        JvmValue::Short(v) => Ok(v.into()),
+        JvmValue::Char(v) => Ok(v.into()),
+        JvmValue::Boolean(v) => Ok(v.into()),
+        value => bail!("expected int, found {value:?}"),
+    }
+}
+
+impl<'a> ToJvm<'a> for &str {
+    fn descriptor() -> Cow<'static, str> {
+        Cow::Borrowed("Ljava/lang/String;")
+    }
+
+    fn to_jvm(self, vm: &Vm<'a>) -> Result<JvmValue<'a>> {
+        Ok(vm.new_string(self))
+    }
+}
+
+impl<'a> ToJvm<'a> for String {
+    fn descriptor() -> Cow<'static, str> {
+        Cow::Borrowed("Ljava/lang/String;")
+    }
+
+    fn to_jvm(self, vm: &Vm<'a>) -> Result<JvmValue<'a>> {
+        self.as_str().to_jvm(vm)
+    }
+}
+
+impl<'a> FromJvm<'a> for &'a str {
+    fn descriptor() -> Cow<'static, str> {
+        Cow::Borrowed("Ljava/lang/String;")
+    }
+
+    fn from_jvm(vm: &Vm<'a>, value: Option<JvmValue<'a>>) -> Result<Self> {
+        let value = value.ok_or_else(|| format_err!("expected string, found void"))?;
+        vm.string_value(&value)?
+            .ok_or_else(|| format_err!("expected string, found null"))
+    }
+}
+
+impl<'a> FromJvm<'a> for String {
+    fn descriptor() -> Cow<'static, str> {
+        Cow::Borrowed("Ljava/lang/String;")
+    }
+
+    fn from_jvm(vm: &Vm<'a>, value: Option<JvmValue<'a>>) -> Result<Self> {
+        <&str>::from_jvm(vm, value).map(str::to_owned)
+    }
+}
+
+impl<'a> FromJvm<'a> for () {
+    fn descriptor() -> Cow<'static, str> {
+        Cow::Borrowed("V")
+    }
+
+    fn from_jvm(_: &Vm<'a>, _: Option<JvmValue<'a>>) -> Result<Self> {
+        Ok(())
+    }
+}
+
+/// Values are passed through unchanged, as `java.lang.Object`s, e.g. for the receivers of native
+/// methods.
+impl<'a> ToJvm<'a> for JvmValue<'a> {
+    fn descriptor() -> Cow<'static, str> {
+        Cow::Borrowed("Ljava/lang/Object;")
+    }
+
+    /// Any type, since natives can return values of any type unconverted.
+    fn matches(descriptor: &str) -> bool {
+        descriptor != "V"
+    }
+
+    fn to_jvm(self, _: &Vm<'a>) -> Result<JvmValue<'a>> {
+        Ok(self)
+    }
+}
+
+impl<'a> FromJvm<'a> for JvmValue<'a> {
+    fn descriptor() -> Cow<'static, str> {
+        Cow::Borrowed("Ljava/lang/Object;")
+    }
+
+    /// Any type, e.g. so that one native can implement overloads for several types.
+    fn matches(descriptor: &str) -> bool {
+        descriptor != "V"
+    }
+
+    fn from_jvm(_: &Vm<'a>, value: Option<JvmValue<'a>>) -> Result<Self> {
+        value.ok_or_else(|| format_err!("expected a value, found void"))
+    }
+}
+
+/// A reference to an object or array other than a string, which is the address of its header.
+///
+/// Converting null throws `NullPointerException`, so parameters which may be null should be
+/// `Option<Reference>` instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Reference(pub usize);
+
+impl<'a> ToJvm<'a> for Reference {
+    fn descriptor() -> Cow<'static, str> {
+        Cow::Borrowed("Ljava/lang/Object;")
+    }
+
+    fn matches(descriptor: &str) -> bool {
+        is_reference(descriptor)
+    }
+
+    fn to_jvm(self, _: &Vm<'a>) -> Result<JvmValue<'a>> {
+        Ok(JvmValue::Reference(self.0))
+    }
+}
+
+impl<'a> FromJvm<'a> for Reference {
+    fn descriptor() -> Cow<'static, str> {
+        Cow::Borrowed("Ljava/lang/Object;")
+    }
+
+    fn matches(descriptor: &str) -> bool {
+        is_reference(descriptor)
+    }
+
+    fn from_jvm(_: &Vm<'a>, value: Option<JvmValue<'a>>) -> Result<Self> {
+        match value {
+            Some(JvmValue::Reference(0)) => bail!(JavaException::null_pointer()),
+            Some(JvmValue::Reference(reference)) => Ok(Reference(reference)),
+            value => bail!("expected reference, found {value:?}"),
+        }
+    }
+}
+
+/// A Rust type which converts to and from Java references, so it can be wrapped in an `Option`
+/// for values which may be null.
+pub trait Nullable {}
+
+impl Nullable for &str {}
+impl Nullable for String {}
+impl Nullable for JvmValue<'_> {}
+impl Nullable for Reference {}
+impl<T> Nullable for Vec<T> {}
+
+/// `None` is passed as null.
+impl<'a, T: ToJvm<'a> + Nullable> ToJvm<'a> for Option<T> {
+    fn descriptor() -> Cow<'static, str> {
+        T::descriptor()
+    }
+
+    fn matches(descriptor: &str) -> bool {
+        T::matches(descriptor)
+    }
+
+    fn to_jvm(self, vm: &Vm<'a>) -> Result<JvmValue<'a>> {
+        match self {
+            Some(value) => value.to_jvm(vm),
+            None => Ok(JvmValue::Reference(0)),
+        }
+    }
+}
+
+/// Null is returned as `None`.
+impl<'a, T: FromJvm<'a> + Nullable> FromJvm<'a> for Option<T> {
+    fn descriptor() -> Cow<'static, str> {
+        T::descriptor()
+    }
+
+    fn matches(descriptor: &str) -> bool {
+        T::matches(descriptor)
+    }
+
+    fn from_jvm(vm: &Vm<'a>, value: Option<JvmValue<'a>>) -> Result<Self> {
+        match value {
+            Some(JvmValue::Reference(0)) => Ok(None),
+            value => T::from_jvm(vm, value).map(Some),
+        }
+    }
+}
+
+/// Vecs are copied into new arrays, whose element type is the Java type of `T`.
+impl<'a, T: ToJvm<'a>> ToJvm<'a> for Vec<T> {
+    fn descriptor() -> Cow<'static, str> {
+        Cow::Owned(format!("[{}", T::descriptor()))
+    }
+
+    fn to_jvm(self, vm: &Vm<'a>) -> Result<JvmValue<'a>> {
+        let element_type = element_type(&T::descriptor());
+        let array = vm.alloc_array(element_type, self.len())?;
+        let header = unsafe { &mut *(array as *mut RefTypeHeader) };
+
+        for (index, element) in self.into_iter().enumerate() {
+            // SAFETY: The array has this element type, and the same length as the vec
+            unsafe { store_element(header, element_type, index, element.to_jvm(vm)?)? };
+        }
+
+        Ok(JvmValue::Reference(array))
+    }
+}
+
+impl<'a, T: FromJvm<'a>> FromJvm<'a> for Vec<T> {
+    fn descriptor() -> Cow<'static, str> {
+        Cow::Owned(format!("[{}", T::descriptor()))
+    }
+
+    fn matches(descriptor: &str) -> bool {
+        descriptor
+            .strip_prefix('[')
+            .is_some_and(|element| T::matches(element))
+    }
+
+    fn from_jvm(vm: &Vm<'a>, value: Option<JvmValue<'a>>) -> Result<Self> {
+        let array = match value {
+            Some(JvmValue::Reference(0)) => bail!("expected array, found null"),
+            Some(JvmValue::Reference(array)) => array,
+            value => bail!("expected array, found {value:?}"),
+        };
+
+        let header = unsafe { &mut *(array as *mut RefTypeHeader) };
+        let RefTypeHeader::Array(array) = header else {
+            bail!("expected array, found object");
+        };
+        let (element_type, length) = (array.element_type, array.length);
+
+        (0..length)
+            .map(|index| {
+                // SAFETY: The index is within the array's length
+                let element = unsafe { load_element(header, element_type, index)? };
+                T::from_jvm(vm, Some(element))
+            })
+            .collect()
+    }
+}
+
+/// Returns whether a descriptor is of an object or array type.
+pub(crate) fn is_reference(descriptor: &str) -> bool {
+    descriptor.starts_with(['L', '['])
+}
+
+/// Returns the element type of arrays of the type with the given descriptor.
+pub(crate) fn element_type(descriptor: &str) -> ArrayElementType {
+    let primitive = [
+        ArrayType::Boolean,
+        ArrayType::Char,
+        ArrayType::Float,
+        ArrayType::Double,
+        ArrayType::Byte,
+        ArrayType::Short,
+        ArrayType::Int,
+        ArrayType::Long,
+    ]
+    .into_iter()
+    .find(|t| descriptor.len() == 1 && descriptor.starts_with(t.descriptor()));
+
+    match primitive {
+        Some(t) => ArrayElementType::Primitive(t),
+        None => ArrayElementType::Reference,
+    }
+}
+
+/// Stores an element of an array, converting it as the array store instructions do.
+///
+/// # Safety
+///
+/// The array must have the given element type, and the index must be within its length.
+pub(crate) unsafe fn store_element<'a>(
+    array: &mut RefTypeHeader,
+    element_type: ArrayElementType,
+    index: usize,
+    value: JvmValue<'a>,
+) -> Result<()> {
+    unsafe {
+        match (element_type, value) {
+            (ArrayElementType::Primitive(ArrayType::Boolean | ArrayType::Byte), value) => {
+                array.array_data::<i8>()?[index] = int_value(value)? as i8
+            }
+            (ArrayElementType::Primitive(ArrayType::Char), value) => {
+                array.array_data::<u16>()?[index] = int_value(value)? as u16
+            }
+            (ArrayElementType::Primitive(ArrayType::Short), value) => {
+                array.array_data::<i16>()?[index] = int_value(value)? as i16
+            }
+            (ArrayElementType::Primitive(ArrayType::Int), value) => {
+                array.array_data::<i32>()?[index] = int_value(value)?
+            }
+            (ArrayElementType::Primitive(ArrayType::Long), JvmValue::Long(v)) => {
+                array.array_data::<i64>()?[index] = v
+            }
+            (ArrayElementType::Primitive(ArrayType::Float), JvmValue::Float(v)) => {
+                array.array_data::<f32>()?[index] = v
+            }
+            (ArrayElementType::Primitive(ArrayType::Double), JvmValue::Double(v)) => {
+                array.array_data::<f64>()?[index] = v
+            }
+            (ArrayElementType::Reference, value) => {
+                array.array_data::<JvmValue<'a>>()?[index] = value
+            }
+            (t, value) => bail!("can't store {value:?} in an array of {t:?}"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Loads an element of an array, as the array load instructions do.
+///
+/// # Safety
+///
+/// The array must have the given element type, and the index must be within its length.
+pub(crate) unsafe fn load_element<'a>(
+    array: &mut RefTypeHeader,
+    element_type: ArrayElementType,
+    index: usize,
+) -> Result<JvmValue<'a>> {
+    let value = unsafe {
+        match element_type {
+            ArrayElementType::Primitive(ArrayType::Boolean | ArrayType::Byte) => {
+                JvmValue::Int(array.array_data::<i8>()?[index].into())
+            }
+            ArrayElementType::Primitive(ArrayType::Char) => {
+                JvmValue::Int(array.array_data::<u16>()?[index].into())
+            }
+            ArrayElementType::Primitive(ArrayType::Short) => {
+                JvmValue::Int(array.array_data::<i16>()?[index].into())
+            }
+            ArrayElementType::Primitive(ArrayType::Int) => {
+                JvmValue::Int(array.array_data::<i32>()?[index])
+            }
+            ArrayElementType::Primitive(ArrayType::Long) => {
+                JvmValue::Long(array.array_data::<i64>()?[index])
+            }
+            ArrayElementType::Primitive(ArrayType::Float) => {
+                JvmValue::Float(array.array_data::<f32>()?[index])
+            }
+            ArrayElementType::Primitive(ArrayType::Double) => {
+                JvmValue::Double(array.array_data::<f64>()?[index])
+            }
+            ArrayElementType::Reference => array.array_data::<JvmValue<'a>>()?[index].clone(),
+        }
+    };
+
+    Ok(value)
+}
+
+impl<'a> ReturnValue<'a> for () {
+    fn descriptor() -> Cow<'static, str> {
+        Cow::Borrowed("V")
+    }
+
+    fn matches(descriptor: &str) -> bool {
+        descriptor == "V"
+    }
+
+    fn into_return_value(self, _: &Vm<'a>) -> Result<Option<JvmValue<'a>>> {
+        Ok(None)
+    }
+}
+
+impl<'a, T: ToJvm<'a>> ReturnValue<'a> for T {
+    fn descriptor() -> Cow<'static, str> {
+        T::descriptor()
+    }
+
+    fn matches(descriptor: &str) -> bool {
+        T::matches(descriptor)
+    }
+
+    fn into_return_value(self, vm: &Vm<'a>) -> Result<Option<JvmValue<'a>>> {
+        self.to_jvm(vm).map(Some)
+    }
+}
+
+macro_rules! args {
+    ($($arg:ident),*) => {
+        impl<'a, $($arg: ToJvm<'a>),*> Args<'a> for ($($arg,)*) {
+            fn write_descriptor(_descriptor: &mut String) {
+                $(_descriptor.push_str(&$arg::descriptor());)*
+            }
+
+            #[allow(non_snake_case)]
+            fn into_values(self, _vm: &Vm<'a>) -> Result<Vec<JvmValue<'a>>> {
+                let ($($arg,)*) = self;
+                Ok(vec![$($arg.to_jvm(_vm)?),*])
+            }
+        }
+
+        impl<'a, Func, Ret, $($arg),*> NativeFn<'a, ($($arg,)*)> for Func
+        where
+            Func: Fn(&Vm<'a>, $($arg),*) -> Result<Ret> + 'a,
+            Ret: ReturnValue<'a>,
+            $($arg: FromJvm<'a>),*
+        {
+            fn descriptors() -> (Vec<Cow<'static, str>>, Cow<'static, str>) {
+                (vec![$(<$arg as FromJvm>::descriptor()),*], Ret::descriptor())
+            }
+
+            #[allow(non_snake_case)]
+            fn matches(params: &[&str], ret: &str) -> bool {
+                let [$($arg),*] = params else {
+                    return false;
+                };
+                $(<$arg as FromJvm>::matches($arg) &&)* Ret::matches(ret)
+            }
+
+            #[allow(non_snake_case)]
+            fn call(
+                &self,
+                vm: &Vm<'a>,
+                args: &[JvmValue<'a>],
+            ) -> Result<Option<JvmValue<'a>>> {
+                let [$($arg),*] = args else {
+                    bail!("expected {} arguments, found {args:?}", <[&str]>::len(&[$(stringify!($arg)),*]));
+                };
+                self(vm, $($arg::from_jvm(vm, Some($arg.clone()))?),*)?.into_return_value(vm)
+            }
+        }
+    };
+}
+
+args!();
+args!(A);
+args!(A, B);
+args!(A, B, C);
+args!(A, B, C, D);
+args!(A, B, C, D, E);
+args!(A, B, C, D, E, F);
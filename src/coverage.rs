@@ -0,0 +1,289 @@
+//! Coverage of the bytecode run by the vm, for finding the parts of a program which its tests
+//! don't reach, or the parts of the interpreter which a program uses.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+use crate::call_frame::{JvmValue, LocalVariables, Operands};
+use crate::class::Class;
+use crate::hooks::{Hook, MethodRef};
+use crate::instructions::Instruction;
+use crate::trace::glob_matches;
+use crate::vm::Vm;
+
+/// A hook which counts how many times each instruction of the methods of some classes ran.
+/// Methods are tracked from when their class is loaded, so the ones which never run are seen
+/// too.
+///
+/// Classes are chosen by globs matched against their binary names, e.g. `com.example.*`, where
+/// `*` matches any characters and `?` matches one. Every class is tracked if there are no
+/// patterns.
+pub struct Coverage {
+    patterns: Vec<String>,
+    /// Keyed by the addresses of the methods. Methods are only added when a class is loaded, so
+    /// the counts are updated with atomics under the read lock.
+    methods: RwLock<HashMap<usize, TrackedMethod>>,
+}
+
+struct TrackedMethod {
+    class: String,
+    /// The method's name followed by its descriptor, e.g. `add(II)I`.
+    name: String,
+    /// The path of the source file relative to the class path, e.g. `com/example/Main.java`.
+    source_path: Option<String>,
+    /// The source line of each instruction, if the class was compiled with line numbers.
+    lines: Vec<Option<u16>>,
+    calls: AtomicU64,
+    /// The number of times each instruction ran.
+    hits: Vec<AtomicU64>,
+}
+
+/// How much of a method's code ran.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MethodCoverage {
+    /// The binary name of the class followed by the method's name and descriptor, e.g.
+    /// `com.example.Main.add(II)I`.
+    pub name: String,
+    pub calls: u64,
+    pub instructions: usize,
+    /// The number of instructions which ran at least once.
+    pub covered: usize,
+    /// The source lines with instructions which never ran, in order.
+    pub missed_lines: Vec<u16>,
+}
+
+impl Coverage {
+    pub fn new(patterns: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Coverage {
+            patterns: patterns.into_iter().map(Into::into).collect(),
+            methods: RwLock::default(),
+        }
+    }
+
+    fn matches(&self, class_name: &str) -> bool {
+        self.patterns.is_empty()
+            || self
+                .patterns
+                .iter()
+                .any(|pattern| glob_matches(pattern, class_name))
+    }
+
+    /// Returns the coverage of each tracked method which has code, ordered by name.
+    pub fn methods(&self) -> Vec<MethodCoverage> {
+        let methods = self.methods.read().unwrap();
+
+        let mut coverage = methods
+            .values()
+            .map(|method| {
+                let hits = method
+                    .hits
+                    .iter()
+                    .map(|hits| hits.load(Ordering::Relaxed))
+                    .collect::<Vec<_>>();
+
+                let mut missed_lines = hits
+                    .iter()
+                    .zip(&method.lines)
+                    .filter(|(&hits, _)| hits == 0)
+                    .filter_map(|(_, &line)| line)
+                    .collect::<Vec<_>>();
+                missed_lines.sort_unstable();
+                missed_lines.dedup();
+
+                MethodCoverage {
+                    name: format!("{}.{}", method.class, method.name),
+                    calls: method.calls.load(Ordering::Relaxed),
+                    instructions: hits.len(),
+                    covered: hits.iter().filter(|&&hits| hits > 0).count(),
+                    missed_lines,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        coverage.sort_by(|a, b| a.name.cmp(&b.name));
+        coverage
+    }
+
+    /// Writes the percentage of instructions which ran in each method, followed by the lines
+    /// which weren't reached, e.g.
+    ///
+    /// ```text
+    /// Coverage: 10/14 instructions (71.43%) in 1 of 2 methods
+    ///     0.00%       0/4  com.example.Main.add(II)I  missed lines 12
+    ///   100.00%     10/10  com.example.Main.main([Ljava/lang/String;)V
+    /// ```
+    pub fn report(&self, out: &mut impl io::Write) -> io::Result<()> {
+        let methods = self.methods();
+
+        let instructions = methods.iter().map(|m| m.instructions).sum::<usize>();
+        let covered = methods.iter().map(|m| m.covered).sum::<usize>();
+        let called = methods.iter().filter(|m| m.calls > 0).count();
+        writeln!(
+            out,
+            "Coverage: {covered}/{instructions} instructions ({:.2}%) in {called} of {} methods",
+            percent(covered, instructions),
+            methods.len()
+        )?;
+
+        for method in methods {
+            let counts = format!("{}/{}", method.covered, method.instructions);
+            write!(
+                out,
+                "  {:>6.2}% {counts:>9}  {}",
+                percent(method.covered, method.instructions),
+                method.name
+            )?;
+
+            if !method.missed_lines.is_empty() {
+                let lines = method
+                    .missed_lines
+                    .iter()
+                    .map(u16::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(out, "  missed lines {lines}")?;
+            }
+
+            writeln!(out)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the coverage as an lcov tracefile, which `genhtml` and most editors and CI
+    /// services can show against the source. A line's count is the most times any of its
+    /// instructions ran. Classes compiled without line numbers are left out, since lcov only
+    /// describes lines.
+    pub fn write_lcov(&self, out: &mut impl io::Write) -> io::Result<()> {
+        let methods = self.methods.read().unwrap();
+
+        let mut files = BTreeMap::<&str, Vec<&TrackedMethod>>::new();
+        for method in methods.values() {
+            if let Some(path) = &method.source_path {
+                if method.lines.iter().any(Option::is_some) {
+                    files.entry(path).or_default().push(method);
+                }
+            }
+        }
+
+        for (path, mut methods) in files {
+            methods.sort_by(|a, b| (&a.class, &a.name).cmp(&(&b.class, &b.name)));
+
+            writeln!(out, "TN:")?;
+            writeln!(out, "SF:{path}")?;
+
+            for method in &methods {
+                let first_line = method.lines.iter().flatten().min().unwrap_or(&0);
+                writeln!(out, "FN:{first_line},{}.{}", method.class, method.name)?;
+            }
+
+            let mut lines = BTreeMap::<u16, u64>::new();
+            for method in &methods {
+                let calls = method.calls.load(Ordering::Relaxed);
+                writeln!(out, "FNDA:{calls},{}.{}", method.class, method.name)?;
+
+                for (hits, line) in method.hits.iter().zip(&method.lines) {
+                    if let Some(line) = line {
+                        let count = lines.entry(*line).or_default();
+                        *count = (*count).max(hits.load(Ordering::Relaxed));
+                    }
+                }
+            }
+
+            let called = methods
+                .iter()
+                .filter(|method| method.calls.load(Ordering::Relaxed) > 0)
+                .count();
+            writeln!(out, "FNF:{}", methods.len())?;
+            writeln!(out, "FNH:{called}")?;
+
+            for (line, count) in &lines {
+                writeln!(out, "DA:{line},{count}")?;
+            }
+
+            writeln!(out, "LF:{}", lines.len())?;
+            writeln!(
+                out,
+                "LH:{}",
+                lines.values().filter(|&&count| count > 0).count()
+            )?;
+            writeln!(out, "end_of_record")?;
+        }
+
+        Ok(())
+    }
+}
+
+fn percent(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        100.0
+    } else {
+        count as f64 / total as f64 * 100.0
+    }
+}
+
+impl<'a> Hook<'a> for Coverage {
+    fn on_method_enter(&self, _: &Vm<'a>, method: MethodRef<'a>, _: &[JvmValue<'a>]) {
+        let key = method.method as *const _ as usize;
+        if let Some(method) = self.methods.read().unwrap().get(&key) {
+            method.calls.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn on_instruction(
+        &self,
+        _: &Vm<'a>,
+        method: MethodRef<'a>,
+        pc: usize,
+        _: &'a Instruction,
+        _: LocalVariables<'_, 'a>,
+        _: Operands<'_, 'a>,
+    ) {
+        let key = method.method as *const _ as usize;
+        if let Some(method) = self.methods.read().unwrap().get(&key) {
+            method.hits[pc].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn on_class_load(&self, _: &Vm<'a>, class: &'a Class<'a>) {
+        let class_name = class.name().replace('/', ".");
+        if !self.matches(&class_name) {
+            return;
+        }
+
+        // Source files are named without their package, which lcov needs to find them
+        let source_path = class
+            .source_file()
+            .map(|file| match class.name().rsplit_once('/') {
+                Some((package, _)) => format!("{package}/{file}"),
+                None => file.to_owned(),
+            });
+
+        let mut methods = self.methods.write().unwrap();
+        for (name, descriptor, method) in class.declared_methods() {
+            let Some(body) = &method.body else {
+                continue;
+            };
+
+            let lines = body
+                .offsets
+                .iter()
+                .map(|&offset| class.line_number(method.slot, offset))
+                .collect();
+
+            methods.insert(
+                method as *const _ as usize,
+                TrackedMethod {
+                    class: class_name.clone(),
+                    name: format!("{name}{descriptor}"),
+                    source_path: source_path.clone(),
+                    lines,
+                    calls: AtomicU64::new(0),
+                    hits: body.code.iter().map(|_| AtomicU64::new(0)).collect(),
+                },
+            );
+        }
+    }
+}
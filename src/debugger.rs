@@ -0,0 +1,442 @@
+//! An interactive debugger for stepping through the bytecode that the interpreter runs, which
+//! is simpler to use than attaching a debugger over JDWP when exploring how a class executes.
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread::{self, ThreadId};
+
+use crate::call_frame::{JvmValue, LocalVariables, Operands};
+use crate::disassembler::describe_constant;
+use crate::error::{bail, format_err, Error, Result};
+use crate::hooks::{may_call, Hook, MethodRef};
+use crate::instructions::Instruction;
+use crate::trace::describe_value;
+use crate::vm::Vm;
+
+const HELP: &str = "\
+Commands:
+  break <class>.<method>[<descriptor>][@<pc>]  stop before the instruction at an offset, or the first one
+  delete [<n>]                                 remove breakpoint n, or all of them
+  breakpoints                                  list the breakpoints
+  step                                         run the next instruction, entering calls
+  next                                         run the next instruction, skipping over calls
+  finish                                       run until the current method returns
+  continue                                     run until the next breakpoint
+  where                                        show the call stack
+  locals                                       show the locals of the current method
+  operands                                     show the operand stack, from the bottom
+  code                                         list the instructions of the current method
+  constant <index>                             show an entry of the current class's constant pool
+  quit                                         remove all breakpoints and run to the end
+Commands can be shortened to their first letter, or `cp` for `constant`.";
+
+/// A hook which stops the program at breakpoints and reads commands to inspect it from `input`,
+/// writing a prompt and the results to `out`. Breakpoints are set on an instruction of a method,
+/// where `pc` is the bytecode offset of the instruction like in `javap -c`, e.g.
+///
+/// ```text
+/// Breakpoint 1, com/example/Main.add(II)I 2: add { data_type: Int }
+/// (debug) operands
+/// [1, 2]
+/// ```
+///
+/// While one thread is stopped, the others wait before their next instruction. The debugger is
+/// detached when `input` ends, after which the program runs to the end.
+pub struct Debugger<R, W> {
+    /// Whether instructions need to be checked, which is only the case while there are
+    /// breakpoints or a step is in progress.
+    armed: AtomicBool,
+    state: Mutex<DebuggerState<R, W>>,
+}
+
+struct DebuggerState<R, W> {
+    input: R,
+    out: W,
+    /// The breakpoints, numbered from 1, which are left as `None` once deleted so that the
+    /// others keep their numbers.
+    breakpoints: Vec<Option<Breakpoint>>,
+    step: Option<Step>,
+    /// The methods running on each thread, with the innermost last.
+    stacks: HashMap<ThreadId, Vec<Frame>>,
+    names: HashMap<usize, String>,
+    detached: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Breakpoint {
+    /// The internal name of the class, e.g. `java/lang/String`.
+    class: String,
+    method: String,
+    descriptor: Option<String>,
+    pc: usize,
+}
+
+/// A step in progress, which stops on its thread once the stack is no deeper than `max_depth`.
+struct Step {
+    thread: ThreadId,
+    max_depth: usize,
+}
+
+struct Frame {
+    /// The address of the method, which its name is looked up by.
+    method: usize,
+    /// The offset of the last instruction run by the method which may have called another.
+    pc: usize,
+}
+
+/// What the program is stopped at.
+struct Stop<'f, 'a> {
+    method: MethodRef<'a>,
+    pc: usize,
+    locals: LocalVariables<'f, 'a>,
+    operands: Operands<'f, 'a>,
+}
+
+impl<R: io::BufRead + Send, W: io::Write + Send> Debugger<R, W> {
+    pub fn new(input: R, out: W) -> Self {
+        Debugger {
+            armed: AtomicBool::new(false),
+            state: Mutex::new(DebuggerState {
+                input,
+                out,
+                breakpoints: Vec::new(),
+                step: None,
+                stacks: HashMap::new(),
+                names: HashMap::new(),
+                detached: false,
+            }),
+        }
+    }
+
+    /// Adds a breakpoint in the same format as the `break` command, e.g. `com.example.Main.main`
+    /// or `com/example/Main.add(II)I@2`, and returns its number.
+    pub fn add_breakpoint(&self, spec: &str) -> Result<usize> {
+        let breakpoint = parse_breakpoint(spec)?;
+        let mut state = self.state.lock().unwrap();
+        state.breakpoints.push(Some(breakpoint));
+        self.armed.store(state.is_armed(), Ordering::Relaxed);
+        Ok(state.breakpoints.len())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.state.into_inner().unwrap().out
+    }
+}
+
+impl<R: io::BufRead, W: io::Write> DebuggerState<R, W> {
+    fn is_armed(&self) -> bool {
+        !self.detached && (self.step.is_some() || self.breakpoints.iter().any(Option::is_some))
+    }
+
+    /// Returns the message to stop with before an instruction, if any.
+    fn stop_reason(&self, method: MethodRef, pc: usize, depth: usize) -> Option<String> {
+        let stepped = self
+            .step
+            .as_ref()
+            .is_some_and(|step| step.thread == thread::current().id() && depth <= step.max_depth);
+
+        for (i, breakpoint) in self.breakpoints.iter().enumerate() {
+            let Some(breakpoint) = breakpoint else {
+                continue;
+            };
+
+            if breakpoint.pc == pc
+                && breakpoint.method == method.name()
+                && breakpoint.class == method.class.name()
+                && breakpoint
+                    .descriptor
+                    .as_ref()
+                    .map_or(true, |descriptor| descriptor == method.descriptor())
+            {
+                return Some(format!("Breakpoint {}, ", i + 1));
+            }
+        }
+
+        stepped.then(String::new)
+    }
+
+    /// Shows where the program stopped, and runs commands until one resumes it.
+    fn pause(&mut self, reason: &str, stop: &Stop, instruction: &Instruction) -> io::Result<()> {
+        self.step = None;
+        writeln!(
+            self.out,
+            "{reason}{} {}: {instruction:?}",
+            method_name(stop.method),
+            stop.pc
+        )?;
+
+        let mut line = String::new();
+        loop {
+            write!(self.out, "(debug) ")?;
+            self.out.flush()?;
+
+            line.clear();
+            if self.input.read_line(&mut line)? == 0 {
+                self.detach();
+                return Ok(());
+            }
+
+            let (command, arg) = match line.trim().split_once(char::is_whitespace) {
+                Some((command, arg)) => (command, arg.trim()),
+                None => (line.trim(), ""),
+            };
+
+            if self.command(command, arg, stop)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Runs a command, and returns whether the program should resume.
+    fn command(&mut self, command: &str, arg: &str, stop: &Stop) -> io::Result<bool> {
+        let thread = thread::current().id();
+        let depth = self.stacks.get(&thread).map_or(0, Vec::len);
+
+        match command {
+            "" => {}
+            "h" | "help" => writeln!(self.out, "{HELP}")?,
+            "b" | "break" => match parse_breakpoint(arg) {
+                Ok(breakpoint) => {
+                    self.breakpoints.push(Some(breakpoint));
+                    writeln!(self.out, "Breakpoint {} set", self.breakpoints.len())?;
+                }
+                Err(e) => writeln!(self.out, "{e}")?,
+            },
+            "d" | "delete" if arg.is_empty() => {
+                self.breakpoints.iter_mut().for_each(|b| *b = None);
+                writeln!(self.out, "Deleted all breakpoints")?;
+            }
+            "d" | "delete" => {
+                let breakpoint = arg
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|n| self.breakpoints.get_mut(n.checked_sub(1)?))
+                    .and_then(Option::take);
+                match breakpoint {
+                    Some(_) => writeln!(self.out, "Deleted breakpoint {arg}")?,
+                    None => writeln!(self.out, "No breakpoint {arg}")?,
+                }
+            }
+            "breakpoints" => {
+                for (i, breakpoint) in self.breakpoints.iter().enumerate() {
+                    if let Some(breakpoint) = breakpoint {
+                        writeln!(
+                            self.out,
+                            "{}: {}.{}{}@{}",
+                            i + 1,
+                            breakpoint.class,
+                            breakpoint.method,
+                            breakpoint.descriptor.as_deref().unwrap_or(""),
+                            breakpoint.pc
+                        )?;
+                    }
+                }
+            }
+            "s" | "step" => {
+                self.step = Some(Step {
+                    thread,
+                    max_depth: usize::MAX,
+                });
+                return Ok(true);
+            }
+            "n" | "next" => {
+                self.step = Some(Step {
+                    thread,
+                    max_depth: depth,
+                });
+                return Ok(true);
+            }
+            "f" | "finish" => {
+                self.step = Some(Step {
+                    thread,
+                    max_depth: depth.saturating_sub(1),
+                });
+                return Ok(true);
+            }
+            "c" | "continue" => return Ok(true),
+            "w" | "where" => {
+                writeln!(self.out, "#0 {} {}", method_name(stop.method), stop.pc)?;
+                let callers = self.stacks.get(&thread).into_iter().flatten().rev().skip(1);
+                for (i, frame) in callers.enumerate() {
+                    writeln!(
+                        self.out,
+                        "#{} {} {}",
+                        i + 1,
+                        self.names[&frame.method],
+                        frame.pc
+                    )?;
+                }
+            }
+            "l" | "locals" => {
+                for index in 0..stop.locals.len() {
+                    if let Some(value) = stop.locals.get(index) {
+                        writeln!(self.out, "{index}: {}", describe_value(&value))?;
+                    }
+                }
+            }
+            "o" | "operands" => {
+                let operands = stop
+                    .operands
+                    .iter()
+                    .map(|value| describe_value(&value))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                writeln!(self.out, "[{operands}]")?;
+            }
+            "code" => {
+                if let Some(body) = &stop.method.method.body {
+                    for (instruction, &pc) in body.code.iter().zip(&body.offsets) {
+                        let marker = if pc as usize == stop.pc { "=>" } else { "  " };
+                        writeln!(self.out, "{marker} {pc}: {instruction:?}")?;
+                    }
+                }
+            }
+            "cp" | "constant" => {
+                let constant_pool = stop.method.class.constant_pool();
+                let constant = arg
+                    .parse()
+                    .ok()
+                    .and_then(|index| describe_constant(constant_pool, index));
+                match constant {
+                    Some(constant) => writeln!(self.out, "#{arg} = {constant}")?,
+                    None => writeln!(self.out, "No constant #{arg}")?,
+                }
+            }
+            "q" | "quit" => {
+                self.detach();
+                writeln!(self.out, "Detached, the program will run to the end")?;
+                return Ok(true);
+            }
+            _ => writeln!(self.out, "Unknown command `{command}`, try `help`")?,
+        }
+
+        Ok(false)
+    }
+
+    fn detach(&mut self) {
+        self.detached = true;
+        self.breakpoints.clear();
+        self.step = None;
+    }
+}
+
+/// Parses a breakpoint in the format `<class>.<method>[<descriptor>][@<pc>]`, where the class
+/// can be given by its binary or internal name.
+fn parse_breakpoint(spec: &str) -> Result<Breakpoint> {
+    let (method, pc) = match spec.rsplit_once('@') {
+        Some((method, pc)) => {
+            let pc = pc
+                .parse()
+                .map_err(|_| format_err!("invalid pc `{pc}` in breakpoint `{spec}`"))?;
+            (method, pc)
+        }
+        None => (spec, 0),
+    };
+
+    let (method, descriptor) = match method.find('(') {
+        Some(start) => (&method[..start], Some(method[start..].to_owned())),
+        None => (method, None),
+    };
+
+    let Some((class, method)) = method.rsplit_once('.') else {
+        bail!("expected a breakpoint like `<class>.<method>[@<pc>]`, found `{spec}`");
+    };
+
+    if class.is_empty() || method.is_empty() {
+        bail!("expected a breakpoint like `<class>.<method>[@<pc>]`, found `{spec}`");
+    }
+
+    Ok(Breakpoint {
+        class: class.replace('.', "/"),
+        method: method.to_owned(),
+        descriptor,
+        pc,
+    })
+}
+
+fn method_name(method: MethodRef) -> String {
+    format!(
+        "{}.{}{}",
+        method.class.name(),
+        method.name(),
+        method.descriptor()
+    )
+}
+
+impl<'a, R: io::BufRead + Send, W: io::Write + Send> Hook<'a> for Debugger<R, W> {
+    fn on_method_enter(&self, _: &Vm<'a>, method: MethodRef<'a>, _: &[JvmValue<'a>]) {
+        let key = method.method as *const _ as usize;
+        let mut state = self.state.lock().unwrap();
+
+        state
+            .names
+            .entry(key)
+            .or_insert_with(|| method_name(method));
+
+        state
+            .stacks
+            .entry(thread::current().id())
+            .or_default()
+            .push(Frame { method: key, pc: 0 });
+    }
+
+    fn on_method_exit(
+        &self,
+        _: &Vm<'a>,
+        _: MethodRef<'a>,
+        _: &Result<Option<JvmValue<'a>>, Error>,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(stack) = state.stacks.get_mut(&thread::current().id()) {
+            stack.pop();
+        }
+    }
+
+    fn on_instruction(
+        &self,
+        _: &Vm<'a>,
+        method: MethodRef<'a>,
+        index: usize,
+        instruction: &'a Instruction,
+        locals: LocalVariables<'_, 'a>,
+        operands: Operands<'_, 'a>,
+    ) {
+        let armed = self.armed.load(Ordering::Relaxed);
+        if !armed && !may_call(instruction) {
+            return;
+        }
+
+        let pc = method.bytecode_offset(index);
+
+        let mut state = self.state.lock().unwrap();
+        let stack = state.stacks.entry(thread::current().id()).or_default();
+        if let Some(frame) = stack.last_mut() {
+            frame.pc = pc;
+        }
+
+        if !armed {
+            return;
+        }
+
+        let depth = stack.len();
+        let Some(reason) = state.stop_reason(method, pc, depth) else {
+            return;
+        };
+
+        let stop = Stop {
+            method,
+            pc,
+            locals,
+            operands,
+        };
+
+        // The program keeps running if the debugger can't be used
+        if state.pause(&reason, &stop, instruction).is_err() {
+            state.detach();
+        }
+
+        self.armed.store(state.is_armed(), Ordering::Relaxed);
+    }
+}
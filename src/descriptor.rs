@@ -1,5 +1,9 @@
-use color_eyre::eyre::{self, eyre};
-use winnow::combinator::{alt, delimited, dispatch, empty, fail, repeat, terminated};
+use crate::error::{format_err, Result};
+use bumpalo::Bump;
+use winnow::combinator::{
+    alt, delimited, dispatch, empty, fail, opt, peek, preceded, repeat, terminated,
+};
+use winnow::error::ContextError;
 use winnow::token::{any, take_till, take_while};
 use winnow::{PResult, Parser};
 
@@ -22,32 +26,62 @@ pub enum FieldType<'a> {
     Array(u8, BaseType<'a>),
 }
 
+impl FieldType<'_> {
+    /// The number of slots that a value of this type takes up in the locals, which is two for
+    /// longs and doubles.
+    pub fn slots(&self) -> usize {
+        match self {
+            FieldType::Base(BaseType::Long | BaseType::Double) => 2,
+            _ => 1,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct FieldDescriptor<'a> {
     pub field_type: FieldType<'a>,
 }
 
+/// A parsed method descriptor, whose parameters are allocated in an arena like the rest of a
+/// class's metadata.
 #[derive(Clone, Debug)]
 pub struct MethodDescriptor<'a> {
-    pub params: Vec<FieldType<'a>>,
+    pub params: bumpalo::collections::Vec<'a, FieldType<'a>>,
+    /// The index in the locals of each parameter, not counting the receiver of instance methods.
+    pub param_slots: bumpalo::collections::Vec<'a, u16>,
+    /// The number of slots that the parameters take up in the locals, not counting the receiver
+    /// of instance methods.
+    pub arg_slots: usize,
     pub return_type: Option<FieldType<'a>>,
 }
 
-pub fn parse_method_descriptor(descriptor: &str) -> eyre::Result<MethodDescriptor> {
-    let (params, return_type) = (parse_params_types, parse_return_type)
+pub fn parse_method_descriptor<'a>(
+    arena: &'a Bump,
+    descriptor: &'a str,
+) -> Result<MethodDescriptor<'a>> {
+    let (params, return_type) = (parse_params_types(arena), parse_return_type)
         .parse(descriptor)
-        .map_err(|e| eyre!("{e}"))?;
+        .map_err(|e| format_err!("{e}"))?;
+
+    let mut arg_slots = 0;
+    let mut param_slots = bumpalo::collections::Vec::with_capacity_in(params.len(), arena);
+    for param in &params {
+        param_slots.push(arg_slots as u16);
+        arg_slots += param.slots();
+    }
 
     Ok(MethodDescriptor {
         params,
+        param_slots,
+        arg_slots,
         return_type,
     })
 }
 
-pub fn parse_field_descriptor(descriptor: &str) -> eyre::Result<FieldDescriptor> {
+pub fn parse_field_descriptor(descriptor: &str) -> Result<FieldDescriptor> {
     let field_type = parse_field_type
         .parse(descriptor)
-        .map_err(|e| eyre!("{e}"))?;
+        .map_err(|e| format_err!("{e}"))?;
 
     Ok(FieldDescriptor { field_type })
 }
@@ -81,10 +115,229 @@ fn parse_field_type<'s>(input: &mut &'s str) -> PResult<FieldType<'s>> {
     .parse_next(input)
 }
 
-fn parse_params_types<'s>(input: &mut &'s str) -> PResult<Vec<FieldType<'s>>> {
-    delimited("(", repeat(.., parse_field_type), ")").parse_next(input)
+fn parse_params_types(
+    arena: &Bump,
+) -> impl Parser<&str, bumpalo::collections::Vec<'_, FieldType<'_>>, ContextError> {
+    delimited(
+        "(",
+        repeat(.., parse_field_type).fold(
+            || bumpalo::collections::Vec::new_in(arena),
+            |mut params, param| {
+                params.push(param);
+                params
+            },
+        ),
+        ")",
+    )
 }
 
 fn parse_return_type<'s>(input: &mut &'s str) -> PResult<Option<FieldType<'s>>> {
     alt(("V".map(|_| None), parse_field_type.map(Some))).parse_next(input)
 }
+
+/// The generic signature of a class, from its `Signature` attribute.
+#[derive(Clone, Debug)]
+pub struct ClassSignature<'a> {
+    pub type_params: Vec<TypeParam<'a>>,
+    pub super_class: ClassTypeSignature<'a>,
+    pub interfaces: Vec<ClassTypeSignature<'a>>,
+}
+
+/// The generic signature of a method, from its `Signature` attribute.
+#[derive(Clone, Debug)]
+pub struct MethodSignature<'a> {
+    pub type_params: Vec<TypeParam<'a>>,
+    pub params: Vec<TypeSignature<'a>>,
+    /// The return type, or `None` for void.
+    pub return_type: Option<TypeSignature<'a>>,
+    pub throws: Vec<ReferenceTypeSignature<'a>>,
+}
+
+/// A type parameter declared by a generic class or method, e.g. `T extends Comparable<T>`.
+#[derive(Clone, Debug)]
+pub struct TypeParam<'a> {
+    pub name: &'a str,
+    /// The bound which is a class or type variable. This is `None` if the only bounds are
+    /// interfaces.
+    pub class_bound: Option<ReferenceTypeSignature<'a>>,
+    pub interface_bounds: Vec<ReferenceTypeSignature<'a>>,
+}
+
+#[derive(Clone, Debug)]
+pub enum TypeSignature<'a> {
+    /// A primitive type. This is never [`BaseType::Object`].
+    Base(BaseType<'a>),
+    Reference(ReferenceTypeSignature<'a>),
+}
+
+#[derive(Clone, Debug)]
+pub enum ReferenceTypeSignature<'a> {
+    Class(ClassTypeSignature<'a>),
+    TypeVariable(&'a str),
+    Array(Box<TypeSignature<'a>>),
+}
+
+/// A possibly parameterized class type, e.g. `java/util/Map<TK;TV;>.Entry<TK;TV;>`.
+#[derive(Clone, Debug)]
+pub struct ClassTypeSignature<'a> {
+    /// The binary name of the outermost class, e.g. `java/util/Map`.
+    pub name: &'a str,
+    pub type_args: Vec<TypeArg<'a>>,
+    /// The simple names and type arguments of the nested classes, from outermost to innermost.
+    pub inner: Vec<(&'a str, Vec<TypeArg<'a>>)>,
+}
+
+impl ClassTypeSignature<'_> {
+    /// Returns the binary name of the class, with nested classes separated by `$`.
+    pub fn binary_name(&self) -> String {
+        let mut name = self.name.to_owned();
+        for (inner, _) in &self.inner {
+            name.push('$');
+            name.push_str(inner);
+        }
+        name
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum TypeArg<'a> {
+    /// An unbounded wildcard, `?`.
+    Any,
+    /// A type, or a wildcard bounded by a type.
+    Type(Option<WildcardBound>, ReferenceTypeSignature<'a>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WildcardBound {
+    /// `? extends T`
+    Extends,
+    /// `? super T`
+    Super,
+}
+
+pub fn parse_class_signature(signature: &str) -> Result<ClassSignature> {
+    let (type_params, super_class, interfaces) = (
+        parse_type_params,
+        parse_class_type_signature,
+        repeat(.., parse_class_type_signature),
+    )
+        .parse(signature)
+        .map_err(|e| format_err!("{e}"))?;
+
+    Ok(ClassSignature {
+        type_params,
+        super_class,
+        interfaces,
+    })
+}
+
+pub fn parse_method_signature(signature: &str) -> Result<MethodSignature> {
+    let (type_params, params, return_type, throws) = (
+        parse_type_params,
+        delimited("(", repeat(.., parse_type_signature), ")"),
+        alt(("V".map(|_| None), parse_type_signature.map(Some))),
+        repeat(.., preceded('^', parse_reference_type_signature)),
+    )
+        .parse(signature)
+        .map_err(|e| format_err!("{e}"))?;
+
+    Ok(MethodSignature {
+        type_params,
+        params,
+        return_type,
+        throws,
+    })
+}
+
+/// Parses the generic signature of a field, which is always a reference type.
+pub fn parse_field_signature(signature: &str) -> Result<ReferenceTypeSignature> {
+    parse_reference_type_signature
+        .parse(signature)
+        .map_err(|e| format_err!("{e}"))
+}
+
+/// Parses an identifier, which is any non-empty string without the characters that delimit the
+/// parts of a signature.
+fn parse_identifier<'s>(input: &mut &'s str) -> PResult<&'s str> {
+    take_till(1.., ['.', ';', '[', '/', '<', '>', ':']).parse_next(input)
+}
+
+fn parse_type_params<'s>(input: &mut &'s str) -> PResult<Vec<TypeParam<'s>>> {
+    opt(delimited('<', repeat(1.., parse_type_param), '>'))
+        .map(Option::unwrap_or_default)
+        .parse_next(input)
+}
+
+fn parse_type_param<'s>(input: &mut &'s str) -> PResult<TypeParam<'s>> {
+    let (name, class_bound, interface_bounds) = (
+        parse_identifier,
+        preceded(':', opt(parse_reference_type_signature)),
+        repeat(.., preceded(':', parse_reference_type_signature)),
+    )
+        .parse_next(input)?;
+
+    Ok(TypeParam {
+        name,
+        class_bound,
+        interface_bounds,
+    })
+}
+
+fn parse_type_signature<'s>(input: &mut &'s str) -> PResult<TypeSignature<'s>> {
+    alt((
+        parse_reference_type_signature.map(TypeSignature::Reference),
+        parse_base_type
+            .verify(|t| !matches!(t, BaseType::Object(_)))
+            .map(TypeSignature::Base),
+    ))
+    .parse_next(input)
+}
+
+fn parse_reference_type_signature<'s>(input: &mut &'s str) -> PResult<ReferenceTypeSignature<'s>> {
+    dispatch! { peek(any);
+        'L' => parse_class_type_signature.map(ReferenceTypeSignature::Class),
+        'T' => delimited('T', parse_identifier, ';').map(ReferenceTypeSignature::TypeVariable),
+        '[' => preceded('[', parse_type_signature)
+            .map(|t| ReferenceTypeSignature::Array(Box::new(t))),
+        _ => fail,
+    }
+    .parse_next(input)
+}
+
+fn parse_class_type_signature<'s>(input: &mut &'s str) -> PResult<ClassTypeSignature<'s>> {
+    // The package is part of the first identifier, since it's separated by slashes
+    let (name, type_args, inner) = delimited(
+        'L',
+        (
+            take_till(1.., ['.', ';', '[', '<', '>', ':']),
+            parse_type_args,
+            repeat(.., preceded('.', (parse_identifier, parse_type_args))),
+        ),
+        ';',
+    )
+    .parse_next(input)?;
+
+    Ok(ClassTypeSignature {
+        name,
+        type_args,
+        inner,
+    })
+}
+
+fn parse_type_args<'s>(input: &mut &'s str) -> PResult<Vec<TypeArg<'s>>> {
+    opt(delimited('<', repeat(1.., parse_type_arg), '>'))
+        .map(Option::unwrap_or_default)
+        .parse_next(input)
+}
+
+fn parse_type_arg<'s>(input: &mut &'s str) -> PResult<TypeArg<'s>> {
+    dispatch! { peek(any);
+        '*' => '*'.map(|_| TypeArg::Any),
+        '+' => preceded('+', parse_reference_type_signature)
+            .map(|t| TypeArg::Type(Some(WildcardBound::Extends), t)),
+        '-' => preceded('-', parse_reference_type_signature)
+            .map(|t| TypeArg::Type(Some(WildcardBound::Super), t)),
+        _ => parse_reference_type_signature.map(|t| TypeArg::Type(None, t)),
+    }
+    .parse_next(input)
+}
@@ -0,0 +1,826 @@
+use std::io;
+
+use bumpalo::Bump;
+
+use crate::class_file::constant_pool::{ConstantInfo, ConstantPool};
+use crate::class_file::{
+    AttributeInfo, ClassAccessFlags, ClassFile, CodeAttribute, FieldInfo, MethodAccessFlags,
+    MethodInfo,
+};
+use crate::descriptor::{parse_field_descriptor, parse_method_descriptor, BaseType, FieldType};
+use crate::error::Result;
+use crate::instructions::ArrayType;
+use crate::opcodes::OpCode;
+
+/// Prints a class file in a similar format to `javap -c -v`: the constant pool, then each field
+/// and method with its bytecode, where references to constants are resolved.
+///
+/// Class files which were read leniently can be printed too, with placeholders for anything that
+/// couldn't be parsed.
+pub fn disassemble(out: &mut impl io::Write, class_file: &ClassFile) -> Result<()> {
+    Disassembler {
+        out,
+        constant_pool: &class_file.constant_pool,
+    }
+    .class_file(class_file)
+}
+
+/// Describes the constant at an index in the same format as the constant pool listing of
+/// [`disassemble`], e.g. `Methodref #2.#3 // java/lang/Object."<init>":()V`.
+pub(crate) fn describe_constant(constant_pool: &ConstantPool, index: u16) -> Option<String> {
+    let disassembler = Disassembler {
+        out: &mut io::sink(),
+        constant_pool,
+    };
+
+    let (kind, args, comment) = disassembler.constant(index)?;
+    Some(match comment {
+        Some(comment) => format!("{kind} {args} // {comment}"),
+        None => format!("{kind} {args}"),
+    })
+}
+
+struct Disassembler<'d, W> {
+    out: &'d mut W,
+    constant_pool: &'d ConstantPool<'d>,
+}
+
+impl<'d, W: io::Write> Disassembler<'d, W> {
+    fn class_file(&mut self, class_file: &ClassFile) -> Result<()> {
+        let name = self.class_name(class_file.this_class);
+        let flags = &class_file.access_flags;
+
+        let mut declaration = modifiers(flags.iter_names().filter_map(|(name, _)| match name {
+            "PUBLIC" | "FINAL" => Some(name),
+            // Interfaces are always abstract, which isn't written in their declaration
+            "ABSTRACT" if !flags.contains(ClassAccessFlags::INTERFACE) => Some(name),
+            _ => None,
+        }));
+
+        declaration += if flags.contains(ClassAccessFlags::MODULE) {
+            "module "
+        } else if flags.contains(ClassAccessFlags::ANNOTATION) {
+            "@interface "
+        } else if flags.contains(ClassAccessFlags::INTERFACE) {
+            "interface "
+        } else if flags.contains(ClassAccessFlags::ENUM) {
+            "enum "
+        } else {
+            "class "
+        };
+        declaration += &name.replace('/', ".");
+
+        if class_file.super_class != 0 {
+            declaration += " extends ";
+            declaration += &self.class_name(class_file.super_class).replace('/', ".");
+        }
+
+        let interfaces = class_file
+            .interfaces
+            .iter()
+            .map(|index| self.class_name(*index).replace('/', "."))
+            .collect::<Vec<_>>();
+
+        if !interfaces.is_empty() {
+            let keyword = if flags.contains(ClassAccessFlags::INTERFACE) {
+                " extends "
+            } else {
+                " implements "
+            };
+            declaration += keyword;
+            declaration += &interfaces.join(", ");
+        }
+
+        writeln!(self.out, "{declaration}")?;
+        writeln!(self.out, "  minor version: {}", class_file.minor_version)?;
+        writeln!(self.out, "  major version: {}", class_file.major_version)?;
+        writeln!(
+            self.out,
+            "  flags: {}",
+            flag_names(flags.bits(), flags.iter_names())
+        )?;
+        self.line_with_comment(&format!("  this_class: #{}", class_file.this_class), &name)?;
+        if class_file.super_class != 0 {
+            self.line_with_comment(
+                &format!("  super_class: #{}", class_file.super_class),
+                &self.class_name(class_file.super_class),
+            )?;
+        }
+        writeln!(
+            self.out,
+            "  interfaces: {}, fields: {}, methods: {}, attributes: {}",
+            class_file.interfaces.len(),
+            class_file.fields.len(),
+            class_file.methods.len(),
+            class_file.attributes.len()
+        )?;
+
+        self.constants()?;
+
+        writeln!(self.out, "{{")?;
+        for (i, field) in class_file.fields.iter().enumerate() {
+            if i > 0 {
+                writeln!(self.out)?;
+            }
+            self.field(field)?;
+        }
+        for (i, method) in class_file.methods.iter().enumerate() {
+            if i > 0 || !class_file.fields.is_empty() {
+                writeln!(self.out)?;
+            }
+            self.method(&name, method)?;
+        }
+        writeln!(self.out, "}}")?;
+
+        for attribute in &class_file.attributes {
+            match attribute {
+                AttributeInfo::SourceFile(source_file) => writeln!(
+                    self.out,
+                    "SourceFile: \"{}\"",
+                    self.utf8(source_file.sourcefile_index)
+                )?,
+                AttributeInfo::Signature(signature) => self.line_with_comment(
+                    &format!("Signature: #{}", signature.signature_index),
+                    self.utf8(signature.signature_index),
+                )?,
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn constants(&mut self) -> Result<()> {
+        writeln!(self.out, "Constant pool:")?;
+
+        for index in 1..=self.constant_pool.0.len() as u16 {
+            let Some((kind, args, comment)) = self.constant(index) else {
+                continue;
+            };
+
+            let line = format!("{:>6} = {kind:<18} {args}", format!("#{index}"));
+            match comment {
+                Some(comment) => {
+                    let line = format!("{:>6} = {kind:<18} {args:<14}", format!("#{index}"));
+                    self.line_with_comment(&line, &comment)?
+                }
+                None => writeln!(self.out, "{line}")?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn field(&mut self, field: &FieldInfo) -> Result<()> {
+        let name = self.utf8(field.name_index);
+        let descriptor = self.utf8(field.descriptor_index);
+        let flags = &field.access_flags;
+
+        let mut declaration = modifiers(flags.iter_names().filter_map(|(name, _)| match name {
+            "SYNTHETIC" | "ENUM" => None,
+            _ => Some(name),
+        }));
+
+        match parse_field_descriptor(descriptor) {
+            Ok(descriptor) => declaration += &java_type(&descriptor.field_type),
+            Err(_) => declaration += descriptor,
+        }
+
+        writeln!(self.out, "  {declaration} {name};")?;
+        writeln!(self.out, "    descriptor: {descriptor}")?;
+        writeln!(
+            self.out,
+            "    flags: {}",
+            flag_names(flags.bits(), flags.iter_names())
+        )?;
+
+        Ok(())
+    }
+
+    fn method(&mut self, class_name: &str, method: &MethodInfo) -> Result<()> {
+        let name = self.utf8(method.name_index);
+        let descriptor = self.utf8(method.descriptor_index);
+        let flags = &method.access_flags;
+        let arena = Bump::new();
+
+        let mut declaration = modifiers(flags.iter_names().filter_map(|(name, _)| match name {
+            "BRIDGE" | "VARARGS" | "SYNTHETIC" => None,
+            "STRICT" => Some("STRICTFP"),
+            _ => Some(name),
+        }));
+
+        if name == "<clinit>" {
+            declaration += "{}";
+        } else {
+            match parse_method_descriptor(&arena, descriptor) {
+                Ok(parsed) => {
+                    let mut params = parsed.params.iter().map(java_type).collect::<Vec<_>>();
+                    if flags.contains(MethodAccessFlags::VARARGS) {
+                        if let Some(last) = params.last_mut() {
+                            if let Some(component) = last.strip_suffix("[]") {
+                                *last = format!("{component}...");
+                            }
+                        }
+                    }
+
+                    if name == "<init>" {
+                        declaration += &class_name.replace('/', ".");
+                    } else {
+                        match &parsed.return_type {
+                            Some(return_type) => declaration += &java_type(return_type),
+                            None => declaration += "void",
+                        }
+                        declaration += " ";
+                        declaration += name;
+                    }
+
+                    declaration += &format!("({})", params.join(", "));
+                }
+                Err(_) => declaration += &format!("{name}{descriptor}"),
+            }
+        }
+
+        writeln!(self.out, "  {declaration};")?;
+        writeln!(self.out, "    descriptor: {descriptor}")?;
+        writeln!(
+            self.out,
+            "    flags: {}",
+            flag_names(flags.bits(), flags.iter_names())
+        )?;
+
+        if let Some(code) = method.attributes.iter().find_map(|a| a.try_as_code_ref()) {
+            self.code(code)?;
+        }
+
+        Ok(())
+    }
+
+    fn code(&mut self, code: &CodeAttribute) -> Result<()> {
+        writeln!(self.out, "    Code:")?;
+        writeln!(
+            self.out,
+            "      stack={}, locals={}",
+            code.max_stack, code.max_locals
+        )?;
+
+        let mut pc = 0;
+        while pc < code.code.len() {
+            match self.instruction(code.code, pc)? {
+                Some(length) => pc += length,
+                None => {
+                    writeln!(self.out, "{pc:>10}: <invalid>")?;
+                    break;
+                }
+            }
+        }
+
+        if !code.exception_table.is_empty() {
+            writeln!(self.out, "      Exception table:")?;
+            writeln!(self.out, "         from    to  target type")?;
+            for entry in &code.exception_table {
+                let catch_type = match entry.catch_type {
+                    0 => "any".to_owned(),
+                    index => format!("Class {}", self.class_name(index)),
+                };
+                writeln!(
+                    self.out,
+                    "         {:>5} {:>5} {:>5}   {catch_type}",
+                    entry.start_pc, entry.end_pc, entry.handler_pc
+                )?;
+            }
+        }
+
+        for attribute in &code.attributes {
+            if let AttributeInfo::LineNumberTable(table) = attribute {
+                writeln!(self.out, "      LineNumberTable:")?;
+                for entry in &table.line_number_table {
+                    writeln!(
+                        self.out,
+                        "        line {}: {}",
+                        entry.line_number, entry.start_pc
+                    )?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Prints the instruction at an offset, returning its length, or `None` if it's invalid or
+    /// extends past the end of the code.
+    fn instruction(&mut self, code: &[u8], pc: usize) -> Result<Option<usize>> {
+        let Some(opcode) = OpCode::from_repr(code[pc]) else {
+            return Ok(None);
+        };
+
+        let operands = Operands { code, pc };
+        let mnemonic = format!("{opcode:?}");
+
+        // Operands and the comment after them, if any, and the length of the instruction
+        let (args, comment, length) = match opcode {
+            OpCode::bipush => match operands.i8(1) {
+                Some(value) => (value.to_string(), None, 2),
+                None => return Ok(None),
+            },
+            OpCode::sipush => match operands.i16(1) {
+                Some(value) => (value.to_string(), None, 3),
+                None => return Ok(None),
+            },
+            OpCode::ldc => match operands.u8(1) {
+                Some(index) => self.constant_operand(index as u16, 2),
+                None => return Ok(None),
+            },
+            OpCode::ldc_w
+            | OpCode::ldc2_w
+            | OpCode::getstatic
+            | OpCode::putstatic
+            | OpCode::getfield
+            | OpCode::putfield
+            | OpCode::invokevirtual
+            | OpCode::invokespecial
+            | OpCode::invokestatic
+            | OpCode::new
+            | OpCode::anewarray
+            | OpCode::checkcast
+            | OpCode::instanceof => match operands.u16(1) {
+                Some(index) => self.constant_operand(index, 3),
+                None => return Ok(None),
+            },
+            OpCode::invokeinterface => match (operands.u16(1), operands.u8(3)) {
+                (Some(index), Some(count)) => {
+                    let (args, comment, _) = self.constant_operand(index, 5);
+                    (format!("{args},  {count}"), comment, 5)
+                }
+                _ => return Ok(None),
+            },
+            OpCode::invokedynamic => match operands.u16(1) {
+                Some(index) => {
+                    let (args, comment, _) = self.constant_operand(index, 5);
+                    (format!("{args},  0"), comment, 5)
+                }
+                None => return Ok(None),
+            },
+            OpCode::multianewarray => match (operands.u16(1), operands.u8(3)) {
+                (Some(index), Some(dimensions)) => {
+                    let (args, comment, _) = self.constant_operand(index, 4);
+                    (format!("{args},  {dimensions}"), comment, 4)
+                }
+                _ => return Ok(None),
+            },
+            OpCode::iload
+            | OpCode::lload
+            | OpCode::fload
+            | OpCode::dload
+            | OpCode::aload
+            | OpCode::istore
+            | OpCode::lstore
+            | OpCode::fstore
+            | OpCode::dstore
+            | OpCode::astore
+            | OpCode::ret => match operands.u8(1) {
+                Some(index) => (index.to_string(), None, 2),
+                None => return Ok(None),
+            },
+            OpCode::iinc => match (operands.u8(1), operands.i8(2)) {
+                (Some(index), Some(value)) => (format!("{index}, {value}"), None, 3),
+                _ => return Ok(None),
+            },
+            OpCode::newarray => match operands.u8(1).and_then(ArrayType::from_repr) {
+                Some(atype) => (format!("{atype:?}").to_lowercase(), None, 2),
+                None => return Ok(None),
+            },
+            OpCode::ifeq
+            | OpCode::ifne
+            | OpCode::iflt
+            | OpCode::ifge
+            | OpCode::ifgt
+            | OpCode::ifle
+            | OpCode::if_icmpeq
+            | OpCode::if_icmpne
+            | OpCode::if_icmplt
+            | OpCode::if_icmpge
+            | OpCode::if_icmpgt
+            | OpCode::if_icmple
+            | OpCode::if_acmpeq
+            | OpCode::if_acmpne
+            | OpCode::goto
+            | OpCode::jsr
+            | OpCode::ifnull
+            | OpCode::ifnonnull => match operands.i16(1) {
+                Some(branch) => (target(pc, branch as i32), None, 3),
+                None => return Ok(None),
+            },
+            OpCode::goto_w | OpCode::jsr_w => match operands.i32(1) {
+                Some(branch) => (target(pc, branch), None, 5),
+                None => return Ok(None),
+            },
+            OpCode::wide => {
+                let Some(opcode) = operands.u8(1).and_then(OpCode::from_repr) else {
+                    return Ok(None);
+                };
+                let (Some(index), value) = (operands.u16(2), operands.i16(4)) else {
+                    return Ok(None);
+                };
+                match (opcode, value) {
+                    (OpCode::iinc, Some(value)) => {
+                        (format!("{opcode:?} {index}, {value}"), None, 6)
+                    }
+                    (OpCode::iinc, None) => return Ok(None),
+                    (opcode, _) => (format!("{opcode:?} {index}"), None, 4),
+                }
+            }
+            OpCode::tableswitch | OpCode::lookupswitch => {
+                return self.switch(opcode, &mnemonic, operands);
+            }
+            _ => (String::new(), None, 1),
+        };
+
+        if pc + length > code.len() {
+            return Ok(None);
+        }
+
+        let line = if args.is_empty() {
+            format!("{pc:>10}: {mnemonic}")
+        } else {
+            format!("{pc:>10}: {mnemonic:<13} {args}")
+        };
+
+        match comment {
+            Some(comment) => self.line_with_comment(&line, &comment)?,
+            None => writeln!(self.out, "{line}")?,
+        }
+
+        Ok(Some(length))
+    }
+
+    fn switch(
+        &mut self,
+        opcode: OpCode,
+        mnemonic: &str,
+        operands: Operands,
+    ) -> Result<Option<usize>> {
+        let pc = operands.pc;
+
+        // The operands are aligned to a multiple of 4 bytes from the start of the code
+        let start = (pc + 4) & !3;
+        let offset = start - pc;
+
+        let Some(default) = operands.i32(offset) else {
+            return Ok(None);
+        };
+
+        let (header, cases, length) = if let OpCode::tableswitch = opcode {
+            let (Some(low), Some(high)) = (operands.i32(offset + 4), operands.i32(offset + 8))
+            else {
+                return Ok(None);
+            };
+            if low > high {
+                return Ok(None);
+            }
+
+            let mut cases = vec![];
+            for (i, key) in (low..=high).enumerate() {
+                let Some(branch) = operands.i32(offset + 12 + i * 4) else {
+                    return Ok(None);
+                };
+                cases.push((key, branch));
+            }
+
+            let length = offset + 12 + cases.len() * 4;
+            (format!("{low} to {high}"), cases, length)
+        } else {
+            let Some(npairs) = operands.i32(offset + 4).filter(|n| *n >= 0) else {
+                return Ok(None);
+            };
+
+            let mut cases = vec![];
+            for i in 0..npairs as usize {
+                let (Some(key), Some(branch)) = (
+                    operands.i32(offset + 8 + i * 8),
+                    operands.i32(offset + 12 + i * 8),
+                ) else {
+                    return Ok(None);
+                };
+                cases.push((key, branch));
+            }
+
+            let length = offset + 8 + cases.len() * 8;
+            (npairs.to_string(), cases, length)
+        };
+
+        writeln!(self.out, "{pc:>10}: {mnemonic:<13} {{ // {header}")?;
+        for (key, branch) in cases {
+            writeln!(self.out, "{key:>24}: {}", target(pc, branch))?;
+        }
+        writeln!(self.out, "{:>24}: {}", "default", target(pc, default))?;
+        writeln!(self.out, "            }}")?;
+
+        Ok(Some(length))
+    }
+
+    /// Returns the kind of a constant for the constant pool listing, its arguments and the
+    /// values they refer to, or `None` for the second slot of a long or double.
+    fn constant(&self, index: u16) -> Option<(&'static str, String, Option<String>)> {
+        let constant = self.constant_pool.get(index)?;
+        Some(match constant {
+            // The second slot taken up by a long or double
+            ConstantInfo::Unused => return None,
+            ConstantInfo::Utf8(value) => ("Utf8", escape(value), None),
+            ConstantInfo::Integer(value) => ("Integer", value.to_string(), None),
+            ConstantInfo::Float(value) => ("Float", format!("{value:?}f"), None),
+            ConstantInfo::Long(value) => ("Long", format!("{value}l"), None),
+            ConstantInfo::Double(value) => ("Double", format!("{value:?}d"), None),
+            ConstantInfo::Class(class) => (
+                "Class",
+                format!("#{}", class.name_index),
+                Some(self.describe(index)),
+            ),
+            ConstantInfo::String(string) => (
+                "String",
+                format!("#{}", string.string_index),
+                Some(self.describe(index)),
+            ),
+            ConstantInfo::FieldRef(field_ref) => (
+                "Fieldref",
+                format!(
+                    "#{}.#{}",
+                    field_ref.class_index, field_ref.name_and_type_index
+                ),
+                Some(self.describe(index)),
+            ),
+            ConstantInfo::MethodRef(method_ref) => (
+                "Methodref",
+                format!(
+                    "#{}.#{}",
+                    method_ref.class_index, method_ref.name_and_type_index
+                ),
+                Some(self.describe(index)),
+            ),
+            ConstantInfo::InterfaceMethodRef(method_ref) => (
+                "InterfaceMethodref",
+                format!(
+                    "#{}.#{}",
+                    method_ref.class_index, method_ref.name_and_type_index
+                ),
+                Some(self.describe(index)),
+            ),
+            ConstantInfo::NameAndType(name_and_type) => (
+                "NameAndType",
+                format!(
+                    "#{}:#{}",
+                    name_and_type.name_index, name_and_type.descriptor_index
+                ),
+                Some(self.describe(index)),
+            ),
+            ConstantInfo::MethodHandle(handle) => (
+                "MethodHandle",
+                format!("{}:#{}", handle.reference_kind, handle.reference_index),
+                Some(self.describe(index)),
+            ),
+            ConstantInfo::MethodType(method_type) => (
+                "MethodType",
+                format!("#{}", method_type.descriptor_index),
+                Some(self.describe(index)),
+            ),
+            ConstantInfo::Dynamic(dynamic) => (
+                "Dynamic",
+                format!(
+                    "#{}:#{}",
+                    dynamic.bootstrap_method_attr_index, dynamic.name_and_type_index
+                ),
+                Some(self.describe(index)),
+            ),
+            ConstantInfo::InvokeDynamic(invoke_dynamic) => (
+                "InvokeDynamic",
+                format!(
+                    "#{}:#{}",
+                    invoke_dynamic.bootstrap_method_attr_index, invoke_dynamic.name_and_type_index
+                ),
+                Some(self.describe(index)),
+            ),
+            ConstantInfo::Module(module) => (
+                "Module",
+                format!("#{}", module.name_index),
+                Some(self.describe(index)),
+            ),
+            ConstantInfo::Package(package) => (
+                "Package",
+                format!("#{}", package.name_index),
+                Some(self.describe(index)),
+            ),
+            ConstantInfo::Unknown(tag) => ("Unknown", format!("tag {tag}"), None),
+        })
+    }
+
+    /// Formats an operand which refers to a constant, with the constant it refers to as a
+    /// comment.
+    fn constant_operand(&self, index: u16, length: usize) -> (String, Option<String>, usize) {
+        let kind = match self.constant_pool.get(index) {
+            Some(ConstantInfo::Integer(_)) => "int",
+            Some(ConstantInfo::Float(_)) => "float",
+            Some(ConstantInfo::Long(_)) => "long",
+            Some(ConstantInfo::Double(_)) => "double",
+            Some(ConstantInfo::Class(_)) => "class",
+            Some(ConstantInfo::String(_)) => "String",
+            Some(ConstantInfo::FieldRef(_)) => "Field",
+            Some(ConstantInfo::MethodRef(_)) => "Method",
+            Some(ConstantInfo::InterfaceMethodRef(_)) => "InterfaceMethod",
+            Some(ConstantInfo::MethodHandle(_)) => "MethodHandle",
+            Some(ConstantInfo::MethodType(_)) => "MethodType",
+            Some(ConstantInfo::Dynamic(_)) => "Dynamic",
+            Some(ConstantInfo::InvokeDynamic(_)) => "InvokeDynamic",
+            _ => "<invalid>",
+        };
+
+        (
+            format!("#{index}"),
+            Some(format!("{kind} {}", self.describe(index))),
+            length,
+        )
+    }
+
+    /// Describes the value of a constant, resolving the other constants it refers to.
+    fn describe(&self, index: u16) -> String {
+        let Some(constant) = self.constant_pool.get(index) else {
+            return "<invalid>".to_owned();
+        };
+
+        match constant {
+            ConstantInfo::Utf8(value) => escape(value),
+            ConstantInfo::Integer(value) => value.to_string(),
+            ConstantInfo::Float(value) => format!("{value:?}f"),
+            ConstantInfo::Long(value) => format!("{value}l"),
+            ConstantInfo::Double(value) => format!("{value:?}d"),
+            ConstantInfo::Class(class) => self.class_name_from(class.name_index),
+            ConstantInfo::String(string) => self.describe(string.string_index),
+            ConstantInfo::FieldRef(member) => format!(
+                "{}.{}",
+                self.class_name(member.class_index),
+                self.describe(member.name_and_type_index)
+            ),
+            ConstantInfo::MethodRef(member) | ConstantInfo::InterfaceMethodRef(member) => {
+                format!(
+                    "{}.{}",
+                    self.class_name(member.class_index),
+                    self.describe(member.name_and_type_index)
+                )
+            }
+            ConstantInfo::NameAndType(name_and_type) => {
+                let name = self.utf8(name_and_type.name_index);
+                let descriptor = self.utf8(name_and_type.descriptor_index);
+                if name.starts_with('<') {
+                    format!("\"{name}\":{descriptor}")
+                } else {
+                    format!("{name}:{descriptor}")
+                }
+            }
+            ConstantInfo::MethodHandle(handle) => format!(
+                "{} {}",
+                reference_kind(handle.reference_kind),
+                self.describe(handle.reference_index)
+            ),
+            ConstantInfo::MethodType(method_type) => self.describe(method_type.descriptor_index),
+            ConstantInfo::Dynamic(dynamic) => format!(
+                "#{}:{}",
+                dynamic.bootstrap_method_attr_index,
+                self.describe(dynamic.name_and_type_index)
+            ),
+            ConstantInfo::InvokeDynamic(invoke_dynamic) => format!(
+                "#{}:{}",
+                invoke_dynamic.bootstrap_method_attr_index,
+                self.describe(invoke_dynamic.name_and_type_index)
+            ),
+            ConstantInfo::Module(module) => self.describe(module.name_index),
+            ConstantInfo::Package(package) => self.describe(package.name_index),
+            ConstantInfo::Unused | ConstantInfo::Unknown(_) => "<invalid>".to_owned(),
+        }
+    }
+
+    fn utf8(&self, index: u16) -> &'d str {
+        match self.constant_pool.get(index) {
+            Some(ConstantInfo::Utf8(value)) => value,
+            _ => "<invalid>",
+        }
+    }
+
+    /// Returns the name of the `Class` constant at an index.
+    fn class_name(&self, index: u16) -> String {
+        match self.constant_pool.get(index) {
+            Some(ConstantInfo::Class(class)) => self.class_name_from(class.name_index),
+            _ => "<invalid>".to_owned(),
+        }
+    }
+
+    /// Returns a class name from a `Utf8` constant, which is quoted if it's an array descriptor
+    /// like javap does.
+    fn class_name_from(&self, name_index: u16) -> String {
+        let name = self.utf8(name_index);
+        if name.starts_with('[') {
+            format!("\"{name}\"")
+        } else {
+            name.to_owned()
+        }
+    }
+
+    fn line_with_comment(&mut self, line: &str, comment: &str) -> io::Result<()> {
+        writeln!(self.out, "{line:<40} // {comment}")
+    }
+}
+
+/// Reads the operands of an instruction, returning `None` for any past the end of the code.
+#[derive(Clone, Copy)]
+struct Operands<'c> {
+    code: &'c [u8],
+    pc: usize,
+}
+
+impl Operands<'_> {
+    fn bytes<const N: usize>(&self, offset: usize) -> Option<[u8; N]> {
+        let start = self.pc + offset;
+        self.code.get(start..start + N)?.try_into().ok()
+    }
+
+    fn u8(&self, offset: usize) -> Option<u8> {
+        self.bytes(offset).map(u8::from_be_bytes)
+    }
+
+    fn i8(&self, offset: usize) -> Option<i8> {
+        self.bytes(offset).map(i8::from_be_bytes)
+    }
+
+    fn u16(&self, offset: usize) -> Option<u16> {
+        self.bytes(offset).map(u16::from_be_bytes)
+    }
+
+    fn i16(&self, offset: usize) -> Option<i16> {
+        self.bytes(offset).map(i16::from_be_bytes)
+    }
+
+    fn i32(&self, offset: usize) -> Option<i32> {
+        self.bytes(offset).map(i32::from_be_bytes)
+    }
+}
+
+/// Formats the target of a branch as an offset in the code.
+fn target(pc: usize, branch: i32) -> String {
+    (pc as i64 + branch as i64).to_string()
+}
+
+/// Formats access flags like `(0x0021) ACC_PUBLIC, ACC_SUPER`.
+fn flag_names<'f, T>(bits: u16, names: impl Iterator<Item = (&'f str, T)>) -> String {
+    let names = names
+        .map(|(name, _)| format!("ACC_{name}"))
+        .collect::<Vec<_>>();
+    format!("(0x{bits:04x}) {}", names.join(", "))
+}
+
+/// Formats the names of access flags as Java modifiers, followed by a space.
+fn modifiers<'f>(names: impl Iterator<Item = &'f str>) -> String {
+    names.map(|name| name.to_lowercase() + " ").collect()
+}
+
+/// Returns a type as it would be written in Java source code, e.g. `java.lang.String[]`.
+fn java_type(field_type: &FieldType) -> String {
+    let base_type = |base: &BaseType| match base {
+        BaseType::Byte => "byte".to_owned(),
+        BaseType::Char => "char".to_owned(),
+        BaseType::Double => "double".to_owned(),
+        BaseType::Float => "float".to_owned(),
+        BaseType::Int => "int".to_owned(),
+        BaseType::Long => "long".to_owned(),
+        BaseType::Short => "short".to_owned(),
+        BaseType::Boolean => "boolean".to_owned(),
+        BaseType::Object(name) => name.replace('/', "."),
+    };
+
+    match field_type {
+        FieldType::Base(base) => base_type(base),
+        FieldType::Array(dimensions, base) => base_type(base) + &"[]".repeat(*dimensions as usize),
+    }
+}
+
+fn reference_kind(kind: u8) -> &'static str {
+    match kind {
+        1 => "REF_getField",
+        2 => "REF_getStatic",
+        3 => "REF_putField",
+        4 => "REF_putStatic",
+        5 => "REF_invokeVirtual",
+        6 => "REF_invokeStatic",
+        7 => "REF_invokeSpecial",
+        8 => "REF_newInvokeSpecial",
+        9 => "REF_invokeInterface",
+        _ => "REF_<invalid>",
+    }
+}
+
+/// Escapes control characters in a string constant, so that each constant is on one line.
+fn escape(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            c if c.is_control() => c.escape_default().to_string(),
+            c => c.to_string(),
+        })
+        .collect()
+}
@@ -0,0 +1,208 @@
+//! The error type returned by the vm, so that embedders can tell why a class failed to load or
+//! why Java code stopped without parsing error messages.
+
+use std::fmt::Display;
+use std::{alloc, error, ffi, io, mem, num, str, string, time};
+
+use crate::budget::BudgetExceeded;
+use crate::call_frame::JavaException;
+use crate::vm::SystemExit;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// A class wasn't found on the class path, in the JDK or in the vm's shims.
+    #[error("class not found: {name} (searched {searched} and the JDK)")]
+    ClassNotFound { name: Box<str>, searched: Box<str> },
+    /// A class file couldn't be parsed, or doesn't describe a valid class.
+    #[error("java.lang.ClassFormatError: {0}")]
+    ClassFormat(String),
+    /// A method of a class isn't type-safe, so the class can't be loaded.
+    #[error("java.lang.VerifyError: {0}")]
+    Verification(String),
+    /// A Java exception was thrown and not caught by any frame.
+    #[error(transparent)]
+    Exception(Box<JavaException>),
+    #[error(transparent)]
+    BudgetExceeded(#[from] BudgetExceeded),
+    /// The vm was stopped by [`VmHandle::stop`](crate::vm::VmHandle::stop) while running Java
+    /// code.
+    #[error("the vm was stopped")]
+    Stopped,
+    /// Java code called `System.exit`.
+    #[error(transparent)]
+    Exit(#[from] SystemExit),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// An error from another library, e.g. while reading the JDK's modules.
+    #[error(transparent)]
+    Other(Box<dyn error::Error + Send + Sync>),
+    /// Any other failure, such as an instruction which isn't supported yet or an invalid argument
+    /// passed to the vm.
+    #[error("{0}")]
+    Message(String),
+    /// An error, along with a description of what was being done when it happened.
+    #[error("{message}")]
+    Context {
+        message: String,
+        #[source]
+        source: Box<Error>,
+    },
+    /// An error raised while running Java code, along with the Java frames it unwound, from the
+    /// innermost. Each frame is described by its method and position, e.g.
+    /// `at com.example.Main.main([Ljava/lang/String;)V (Main.java:5, pc 3)`.
+    #[error("{}", frames.join("\n"))]
+    InJava {
+        frames: Vec<String>,
+        #[source]
+        source: Box<Error>,
+    },
+}
+
+const _: () = {
+    assert!(mem::size_of::<Error>() <= 40);
+};
+
+impl Error {
+    pub fn other(error: impl Into<Box<dyn error::Error + Send + Sync>>) -> Error {
+        Error::Other(error.into())
+    }
+
+    /// Returns the error which caused this one, skipping the context and Java frames added to it.
+    pub fn root(&self) -> &Error {
+        let mut error = self;
+        while let Error::Context { source, .. } | Error::InJava { source, .. } = error {
+            error = source;
+        }
+        error
+    }
+
+    /// Returns the Java exception which caused this error, if there is one.
+    pub fn exception(&self) -> Option<&JavaException> {
+        match self.root() {
+            Error::Exception(exception) => Some(exception),
+            _ => None,
+        }
+    }
+
+    /// Returns the message of this error followed by the messages of the errors which caused
+    /// it, separated by colons.
+    pub fn full_message(&self) -> String {
+        let mut message = self.to_string();
+        let mut source = error::Error::source(self);
+        while let Some(error) = source {
+            message.push_str(": ");
+            message.push_str(&error.to_string());
+            source = error.source();
+        }
+        message
+    }
+}
+
+impl From<JavaException> for Error {
+    fn from(exception: JavaException) -> Error {
+        Error::Exception(Box::new(exception))
+    }
+}
+
+/// Errors from other libraries which the vm doesn't need to tell apart.
+macro_rules! impl_from_other {
+    ($($ty:ty),*) => {
+        $(
+            impl From<$ty> for Error {
+                fn from(error: $ty) -> Error {
+                    Error::other(error)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_other!(
+    alloc::LayoutError,
+    ffi::NulError,
+    num::TryFromIntError,
+    str::Utf8Error,
+    string::FromUtf8Error,
+    time::SystemTimeError
+);
+
+#[cfg(feature = "jit")]
+impl_from_other!(
+    cranelift_codegen::CodegenError,
+    cranelift_codegen::settings::SetError,
+    cranelift_module::ModuleError
+);
+
+impl From<String> for Error {
+    fn from(message: String) -> Error {
+        Error::Message(message)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Error {
+        Error::Message(message.to_owned())
+    }
+}
+
+/// Adds context to the errors of results, describing what was being done when they happened.
+pub trait Context<T> {
+    fn wrap_err(self, message: impl Display) -> Result<T>;
+
+    fn wrap_err_with<D: Display>(self, f: impl FnOnce() -> D) -> Result<T>;
+}
+
+impl<T, E: Into<Error>> Context<T> for Result<T, E> {
+    fn wrap_err(self, message: impl Display) -> Result<T> {
+        self.wrap_err_with(|| message)
+    }
+
+    fn wrap_err_with<D: Display>(self, f: impl FnOnce() -> D) -> Result<T> {
+        self.map_err(|e| Error::Context {
+            message: f().to_string(),
+            source: Box::new(e.into()),
+        })
+    }
+}
+
+/// Turns missing values into errors.
+pub trait ContextCompat<T> {
+    fn wrap_err(self, error: impl Into<Error>) -> Result<T>;
+
+    fn wrap_err_with<E: Into<Error>>(self, f: impl FnOnce() -> E) -> Result<T>;
+}
+
+impl<T> ContextCompat<T> for Option<T> {
+    fn wrap_err(self, error: impl Into<Error>) -> Result<T> {
+        self.ok_or_else(|| error.into())
+    }
+
+    fn wrap_err_with<E: Into<Error>>(self, f: impl FnOnce() -> E) -> Result<T> {
+        self.ok_or_else(|| f().into())
+    }
+}
+
+/// Creates an [`Error`] from a format string, or from another error.
+macro_rules! format_err {
+    ($message:literal $(,)?) => {
+        $crate::error::Error::Message(format!($message))
+    };
+    ($error:expr $(,)?) => {
+        $crate::error::Error::from($error)
+    };
+    ($format:expr, $($arg:tt)*) => {
+        $crate::error::Error::Message(format!($format, $($arg)*))
+    };
+}
+
+/// Returns early with an [`Error`], created like [`format_err`].
+macro_rules! bail {
+    ($($arg:tt)*) => {
+        return Err($crate::error::format_err!($($arg)*))
+    };
+}
+
+pub(crate) use {bail, format_err};
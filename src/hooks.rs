@@ -0,0 +1,166 @@
+//! Hooks which the interpreter calls as Java code runs, for building tools like profilers,
+//! tracers and coverage tools on top of the vm.
+//!
+//! Hooks are installed with [`Vm::with_hook`]. While any are installed, instructions aren't fused
+//! into superinstructions and methods aren't compiled, so every instruction is seen by
+//! [`Hook::on_instruction`].
+
+use std::sync::Arc;
+
+use crate::call_frame::{JvmValue, LocalVariables, Operands};
+use crate::class::{Class, Method};
+use crate::error::Result;
+use crate::instructions::{ArrayType, Instruction};
+use crate::vm::Vm;
+
+/// Callbacks for events in the vm. Each one does nothing by default.
+///
+/// Hooks are called on the thread which runs the Java code, so they may be called from several
+/// threads at once.
+pub trait Hook<'a>: Send + Sync {
+    /// Called before a method runs, including native methods. The arguments of instance methods
+    /// start with the receiver.
+    fn on_method_enter(&self, vm: &Vm<'a>, method: MethodRef<'a>, args: &[JvmValue<'a>]) {
+        let _ = (vm, method, args);
+    }
+
+    /// Called after a method returns or throws.
+    fn on_method_exit(
+        &self,
+        vm: &Vm<'a>,
+        method: MethodRef<'a>,
+        result: &Result<Option<JvmValue<'a>>>,
+    ) {
+        let _ = (vm, method, result);
+    }
+
+    /// Called before each instruction of an interpreted method runs. `pc` is the index of the
+    /// instruction in the method's code, which [`MethodRef::bytecode_offset`] converts to its
+    /// offset, and `locals` and `operands` are the frame's locals and operand stack before the
+    /// instruction runs.
+    fn on_instruction(
+        &self,
+        vm: &Vm<'a>,
+        method: MethodRef<'a>,
+        pc: usize,
+        instruction: &'a Instruction,
+        locals: LocalVariables<'_, 'a>,
+        operands: Operands<'_, 'a>,
+    ) {
+        let _ = (vm, method, pc, instruction, locals, operands);
+    }
+
+    /// Called after an object or array is allocated on the heap.
+    fn on_allocation(&self, vm: &Vm<'a>, allocation: &Allocation<'a>) {
+        let _ = (vm, allocation);
+    }
+
+    /// Called after a class is loaded, before it's initialized.
+    fn on_class_load(&self, vm: &Vm<'a>, class: &'a Class<'a>) {
+        let _ = (vm, class);
+    }
+}
+
+/// Hooks can be shared, so that their results can be read once the vm is done with them.
+impl<'a, H: Hook<'a> + ?Sized> Hook<'a> for Arc<H> {
+    fn on_method_enter(&self, vm: &Vm<'a>, method: MethodRef<'a>, args: &[JvmValue<'a>]) {
+        (**self).on_method_enter(vm, method, args)
+    }
+
+    fn on_method_exit(
+        &self,
+        vm: &Vm<'a>,
+        method: MethodRef<'a>,
+        result: &Result<Option<JvmValue<'a>>>,
+    ) {
+        (**self).on_method_exit(vm, method, result)
+    }
+
+    fn on_instruction(
+        &self,
+        vm: &Vm<'a>,
+        method: MethodRef<'a>,
+        pc: usize,
+        instruction: &'a Instruction,
+        locals: LocalVariables<'_, 'a>,
+        operands: Operands<'_, 'a>,
+    ) {
+        (**self).on_instruction(vm, method, pc, instruction, locals, operands)
+    }
+
+    fn on_allocation(&self, vm: &Vm<'a>, allocation: &Allocation<'a>) {
+        (**self).on_allocation(vm, allocation)
+    }
+
+    fn on_class_load(&self, vm: &Vm<'a>, class: &'a Class<'a>) {
+        (**self).on_class_load(vm, class)
+    }
+}
+
+/// A method, along with the class which declares it.
+#[derive(Clone, Copy, Debug)]
+pub struct MethodRef<'a> {
+    pub class: &'a Class<'a>,
+    pub method: &'a Method<'a>,
+}
+
+impl<'a> MethodRef<'a> {
+    pub fn name(&self) -> &'a str {
+        let info = &self.class.class_file().methods[self.method.slot];
+        self.class.constant_pool()[info.name_index]
+            .try_as_utf_8_ref()
+            .copied()
+            .expect("method name should be utf8")
+    }
+
+    pub fn descriptor(&self) -> &'a str {
+        let info = &self.class.class_file().methods[self.method.slot];
+        self.class.constant_pool()[info.descriptor_index]
+            .try_as_utf_8_ref()
+            .copied()
+            .expect("method descriptor should be utf8")
+    }
+
+    /// Returns the bytecode offset of the instruction at an index in the method's code, which is
+    /// how tools like `javap` refer to instructions.
+    pub fn bytecode_offset(&self, index: usize) -> usize {
+        self.method
+            .body
+            .as_ref()
+            .and_then(|body| body.offsets.get(index))
+            .map_or(index, |&offset| offset as usize)
+    }
+}
+
+/// Returns whether an instruction may run other Java code, in which case its frame becomes a
+/// caller. Hooks which keep their own copy of each thread's stack only need to record the
+/// positions of callers at these instructions.
+pub(crate) fn may_call(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::invoke { .. }
+            | Instruction::new { .. }
+            | Instruction::getstatic { .. }
+            | Instruction::putstatic { .. }
+    )
+}
+
+/// An object or array which was allocated on the heap.
+#[derive(Clone, Copy, Debug)]
+pub struct Allocation<'a> {
+    /// The address of the allocation, which is the value of references to it.
+    pub reference: usize,
+    /// The size of the allocation in bytes, including its header.
+    pub size: usize,
+    pub kind: AllocationKind<'a>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum AllocationKind<'a> {
+    Object(&'a Class<'a>),
+    /// An array, whose element type is `None` for arrays of references.
+    Array {
+        element_type: Option<ArrayType>,
+        length: usize,
+    },
+}
@@ -0,0 +1,577 @@
+//! Heap dumps in HotSpot's HPROF format, which can be opened in tools like Eclipse MAT and
+//! VisualVM.
+//!
+//! The dump is made from a [`Snapshot`], so it has the objects reachable from the vm's classes and
+//! other roots. Strings aren't objects in the vm, so a `java.lang.String` and a backing array are
+//! made up for each of them.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use byteorder::{BigEndian, WriteBytesExt};
+
+use crate::class::{Class, Field};
+use crate::descriptor::{self, BaseType, FieldType};
+use crate::error::{bail, Result};
+use crate::snapshot::{Ref, Snapshot, SnapshotObject, Value};
+
+const HEADER: &[u8] = b"JAVA PROFILE 1.0.2\0";
+
+/// The size of object ids, which are made up rather than being addresses.
+const ID_SIZE: u32 = 8;
+
+// Top level record tags
+const UTF8: u8 = 0x01;
+const LOAD_CLASS: u8 = 0x02;
+const STACK_TRACE: u8 = 0x05;
+const HEAP_DUMP_SEGMENT: u8 = 0x1c;
+const HEAP_DUMP_END: u8 = 0x2c;
+
+// Heap dump sub-record tags
+const ROOT_UNKNOWN: u8 = 0xff;
+const ROOT_STICKY_CLASS: u8 = 0x05;
+const CLASS_DUMP: u8 = 0x20;
+const INSTANCE_DUMP: u8 = 0x21;
+const OBJECT_ARRAY_DUMP: u8 = 0x22;
+const PRIMITIVE_ARRAY_DUMP: u8 = 0x23;
+
+// Basic types
+const OBJECT: u8 = 2;
+const BOOLEAN: u8 = 4;
+const CHAR: u8 = 5;
+const FLOAT: u8 = 6;
+const DOUBLE: u8 = 7;
+const BYTE: u8 = 8;
+const SHORT: u8 = 9;
+const INT: u8 = 10;
+const LONG: u8 = 11;
+
+/// Heap dump segments are flushed once they reach this size, since their length is a `u4`.
+const SEGMENT_SIZE: usize = 1 << 20;
+
+/// Every object is recorded as allocated at this stack trace, which is empty.
+const STACK_TRACE_SERIAL: u32 = 1;
+
+// Ids of classes and strings are kept apart from the ids of objects, which are their references
+// in the snapshot.
+const CLASS_IDS: u64 = 1 << 48;
+const STRING_IDS: u64 = 2 << 48;
+
+/// A class which isn't loaded in the vm, but is needed to describe its objects.
+struct SyntheticClass {
+    name: &'static str,
+    /// The name and basic type of each field.
+    fields: &'static [(&'static str, u8)],
+}
+
+/// The class of reference arrays, whose element class isn't known.
+const OBJECT_ARRAY: SyntheticClass = SyntheticClass {
+    name: "[Ljava/lang/Object;",
+    fields: &[],
+};
+
+/// Used for strings if `java.lang.String` hasn't been loaded.
+const STRING: SyntheticClass = SyntheticClass {
+    name: "java/lang/String",
+    fields: &[("value", OBJECT)],
+};
+
+/// Writes a snapshot of a vm as a heap dump. `classes` are the vm's classes, in the same order as
+/// the snapshot's.
+pub(crate) fn write_heap_dump(
+    snapshot: &Snapshot,
+    classes: &[&Class],
+    time: SystemTime,
+    out: impl Write,
+) -> Result<()> {
+    let mut writer = HprofWriter {
+        out,
+        names: HashMap::new(),
+        heap: Vec::new(),
+    };
+
+    writer.out.write_all(HEADER)?;
+    writer.out.write_u32::<BigEndian>(ID_SIZE)?;
+    let millis = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    writer.out.write_u64::<BigEndian>(millis.try_into()?)?;
+
+    writer.record(STACK_TRACE, |body| {
+        body.write_u32::<BigEndian>(STACK_TRACE_SERIAL)?;
+        body.write_u32::<BigEndian>(0)?;
+        body.write_u32::<BigEndian>(0)?;
+        Ok(())
+    })?;
+
+    let indices = classes
+        .iter()
+        .enumerate()
+        .map(|(index, class)| (class.name(), index))
+        .collect::<HashMap<_, _>>();
+
+    let object_class_id = indices.get("java/lang/Object").map(|i| class_id(*i));
+    let object_array_id = class_id(classes.len());
+
+    // Strings use the vm's class if it has one, so that tools recognise their contents
+    let string_class = indices.get("java/lang/String").map(|i| classes[*i]);
+    let string_class_id = match string_class {
+        Some(class) => class_id(indices[class.name()]),
+        None => class_id(classes.len() + 1),
+    };
+
+    for (index, class) in classes.iter().enumerate() {
+        writer.load_class(index, class_id(index), class.name())?;
+    }
+    writer.load_class(classes.len(), object_array_id, OBJECT_ARRAY.name)?;
+    writer.synthetic_class(object_array_id, &OBJECT_ARRAY, object_class_id)?;
+    if string_class.is_none() {
+        writer.load_class(classes.len() + 1, string_class_id, STRING.name)?;
+        writer.synthetic_class(string_class_id, &STRING, object_class_id)?;
+    }
+
+    for (index, (class, snapshot_class)) in classes.iter().zip(&snapshot.classes).enumerate() {
+        let id = class_id(index);
+        let super_id = class
+            .super_class()
+            .and_then(|super_class| indices.get(super_class.name()))
+            .map_or(0, |i| class_id(*i));
+
+        let mut static_fields = Vec::new();
+        for (name, descriptor, value) in &snapshot_class.static_fields {
+            let field_type = descriptor::parse_field_descriptor(descriptor)?.field_type;
+            static_fields.push((writer.name(name)?, basic_type(&field_type), *value));
+        }
+
+        let mut instance_fields = Vec::new();
+        for field in declared_fields(class) {
+            let field_type = basic_type(&field.descriptor.field_type);
+            instance_fields.push((writer.name(field.name)?, field_type));
+        }
+
+        let heap = &mut writer.heap;
+        heap.write_u8(CLASS_DUMP)?;
+        heap.write_u64::<BigEndian>(id)?;
+        heap.write_u32::<BigEndian>(STACK_TRACE_SERIAL)?;
+        heap.write_u64::<BigEndian>(super_id)?;
+        heap.write_u64::<BigEndian>(object_id(snapshot_class.loader))?;
+        // Signers, protection domain and two reserved ids
+        for _ in 0..4 {
+            heap.write_u64::<BigEndian>(0)?;
+        }
+        let instance_size = class
+            .fields()
+            .iter()
+            .map(|field| type_size(basic_type(&field.descriptor.field_type)));
+        heap.write_u32::<BigEndian>(instance_size.sum::<usize>().try_into()?)?;
+        // The constant pool isn't included
+        heap.write_u16::<BigEndian>(0)?;
+
+        heap.write_u16::<BigEndian>(static_fields.len().try_into()?)?;
+        for (name, field_type, value) in static_fields {
+            heap.write_u64::<BigEndian>(name)?;
+            heap.write_u8(field_type)?;
+            write_value(heap, field_type, value)?;
+        }
+
+        heap.write_u16::<BigEndian>(instance_fields.len().try_into()?)?;
+        for (name, field_type) in instance_fields {
+            heap.write_u64::<BigEndian>(name)?;
+            heap.write_u8(field_type)?;
+        }
+
+        heap.write_u8(ROOT_STICKY_CLASS)?;
+        heap.write_u64::<BigEndian>(id)?;
+        writer.flush_heap_if_full()?;
+    }
+
+    for (index, object) in snapshot.objects.iter().enumerate() {
+        let id = object_id(index as Ref + 1);
+        let heap = &mut writer.heap;
+
+        match object {
+            SnapshotObject::Object { class, fields } => {
+                let class = classes[*class as usize];
+                let mut values = Vec::new();
+                for (class, range) in field_ranges(class) {
+                    for (field, value) in class.fields()[range.clone()].iter().zip(&fields[range]) {
+                        let field_type = basic_type(&field.descriptor.field_type);
+                        write_value(&mut values, field_type, *value)?;
+                    }
+                }
+
+                heap.write_u8(INSTANCE_DUMP)?;
+                heap.write_u64::<BigEndian>(id)?;
+                heap.write_u32::<BigEndian>(STACK_TRACE_SERIAL)?;
+                heap.write_u64::<BigEndian>(class_id(indices[class.name()]))?;
+                heap.write_u32::<BigEndian>(values.len().try_into()?)?;
+                heap.write_all(&values)?;
+            }
+            SnapshotObject::PrimitiveArray {
+                element_type,
+                length,
+                bytes,
+            } => {
+                let field_type = FieldType::Array(1, base_type(element_type.descriptor()));
+                let element_type = array_element_type(&field_type);
+
+                heap.write_u8(PRIMITIVE_ARRAY_DUMP)?;
+                heap.write_u64::<BigEndian>(id)?;
+                heap.write_u32::<BigEndian>(STACK_TRACE_SERIAL)?;
+                heap.write_u32::<BigEndian>((*length).try_into()?)?;
+                heap.write_u8(element_type)?;
+
+                // Elements are stored in the platform's byte order
+                for element in bytes.chunks(type_size(element_type)) {
+                    match cfg!(target_endian = "little") {
+                        true => heap.extend(element.iter().rev()),
+                        false => heap.extend(element),
+                    }
+                }
+            }
+            SnapshotObject::ReferenceArray(elements) => {
+                heap.write_u8(OBJECT_ARRAY_DUMP)?;
+                heap.write_u64::<BigEndian>(id)?;
+                heap.write_u32::<BigEndian>(STACK_TRACE_SERIAL)?;
+                heap.write_u32::<BigEndian>(elements.len().try_into()?)?;
+                heap.write_u64::<BigEndian>(object_array_id)?;
+                for element in elements {
+                    write_value(heap, OBJECT, *element)?;
+                }
+            }
+        }
+
+        writer.flush_heap_if_full()?;
+    }
+
+    for (index, string) in snapshot.strings.iter().enumerate() {
+        writer.string(index, &string.value, string_class, string_class_id)?;
+        writer.flush_heap_if_full()?;
+    }
+
+    // Objects which are only referenced by the vm, rather than by classes
+    let (platform, app) = snapshot.builtin_loaders.unwrap_or_default();
+    let roots = [platform, app, snapshot.current_thread]
+        .into_iter()
+        .chain(snapshot.class_mirrors.iter().map(|(_, r)| *r))
+        .chain(snapshot.boxes.iter().map(|(_, _, r)| *r))
+        .chain(snapshot.shutdown_hooks.iter().copied())
+        .chain(snapshot.string_builders.iter().map(|(r, _)| *r))
+        .filter(|r| *r != 0);
+
+    for root in roots {
+        writer.heap.write_u8(ROOT_UNKNOWN)?;
+        writer.heap.write_u64::<BigEndian>(object_id(root))?;
+    }
+
+    writer.flush_heap()?;
+    writer.record(HEAP_DUMP_END, |_| Ok(()))?;
+    writer.out.flush()?;
+
+    Ok(())
+}
+
+struct HprofWriter<W> {
+    out: W,
+    /// The ids of the names which have been written, by name.
+    names: HashMap<String, u64>,
+    /// The sub-records of the current heap dump segment.
+    heap: Vec<u8>,
+}
+
+impl<W: Write> HprofWriter<W> {
+    fn record(&mut self, tag: u8, f: impl FnOnce(&mut Vec<u8>) -> Result<()>) -> Result<()> {
+        let mut body = Vec::new();
+        f(&mut body)?;
+        self.out.write_u8(tag)?;
+        // The time since the header's timestamp
+        self.out.write_u32::<BigEndian>(0)?;
+        self.out.write_u32::<BigEndian>(body.len().try_into()?)?;
+        self.out.write_all(&body)?;
+        Ok(())
+    }
+
+    /// Returns the id of a name, writing it first if it hasn't been written already.
+    fn name(&mut self, name: &str) -> Result<u64> {
+        if let Some(id) = self.names.get(name) {
+            return Ok(*id);
+        }
+
+        let id = self.names.len() as u64 + 1;
+        self.record(UTF8, |body| {
+            body.write_u64::<BigEndian>(id)?;
+            body.write_all(name.as_bytes())?;
+            Ok(())
+        })?;
+
+        self.names.insert(name.to_owned(), id);
+        Ok(id)
+    }
+
+    fn load_class(&mut self, index: usize, id: u64, name: &str) -> Result<()> {
+        let name = self.name(name)?;
+        self.record(LOAD_CLASS, |body| {
+            body.write_u32::<BigEndian>((index + 1).try_into()?)?;
+            body.write_u64::<BigEndian>(id)?;
+            body.write_u32::<BigEndian>(STACK_TRACE_SERIAL)?;
+            body.write_u64::<BigEndian>(name)?;
+            Ok(())
+        })
+    }
+
+    fn synthetic_class(
+        &mut self,
+        id: u64,
+        class: &SyntheticClass,
+        super_id: Option<u64>,
+    ) -> Result<()> {
+        let mut fields = Vec::new();
+        for (name, field_type) in class.fields {
+            fields.push((self.name(name)?, *field_type));
+        }
+
+        let heap = &mut self.heap;
+        heap.write_u8(CLASS_DUMP)?;
+        heap.write_u64::<BigEndian>(id)?;
+        heap.write_u32::<BigEndian>(STACK_TRACE_SERIAL)?;
+        heap.write_u64::<BigEndian>(super_id.unwrap_or_default())?;
+        // The loader, signers, protection domain and two reserved ids
+        for _ in 0..5 {
+            heap.write_u64::<BigEndian>(0)?;
+        }
+        let instance_size = fields.iter().map(|(_, field_type)| type_size(*field_type));
+        heap.write_u32::<BigEndian>(instance_size.sum::<usize>().try_into()?)?;
+        heap.write_u16::<BigEndian>(0)?;
+        heap.write_u16::<BigEndian>(0)?;
+        heap.write_u16::<BigEndian>(fields.len().try_into()?)?;
+        for (name, field_type) in fields {
+            heap.write_u64::<BigEndian>(name)?;
+            heap.write_u8(field_type)?;
+        }
+
+        heap.write_u8(ROOT_STICKY_CLASS)?;
+        heap.write_u64::<BigEndian>(id)?;
+        Ok(())
+    }
+
+    /// Writes a string as an instance of `java.lang.String` and its backing array. The contents
+    /// are stored like the JDK stores them, depending on the type of the `value` field and
+    /// whether there is a `coder` field.
+    fn string(
+        &mut self,
+        index: usize,
+        value: &str,
+        class: Option<&Class>,
+        class_id: u64,
+    ) -> Result<()> {
+        let id = string_id(index);
+        let array_id = id + u64::from(ID_SIZE);
+
+        let value_type = class
+            .and_then(|class| {
+                let field = class.fields().iter().find(|field| field.name == "value")?;
+                Some(&field.descriptor.field_type)
+            })
+            .map_or(CHAR, array_element_type);
+        let has_coder = class.is_some_and(|class| class.field_ordinal("coder", "B").is_some());
+
+        let chars = value.encode_utf16().collect::<Vec<_>>();
+        let latin1 = value_type == BYTE && chars.iter().all(|c| *c <= 0xff);
+        let (element_type, elements, coder) = match value_type {
+            BYTE if latin1 && has_coder => (BYTE, chars.iter().map(|c| *c as u8).collect(), 0),
+            // UTF-16 strings are stored as pairs of bytes in the platform's byte order
+            BYTE => (
+                BYTE,
+                chars.iter().flat_map(|c| c.to_ne_bytes()).collect(),
+                1,
+            ),
+            _ => (
+                CHAR,
+                chars
+                    .iter()
+                    .flat_map(|c| c.to_be_bytes())
+                    .collect::<Vec<_>>(),
+                0,
+            ),
+        };
+
+        let mut values = Vec::new();
+        match class {
+            Some(class) => {
+                for (class, range) in field_ranges(class) {
+                    for field in &class.fields()[range] {
+                        // Other fields, like the cached hash code, are left as zero
+                        match (field.name, basic_type(&field.descriptor.field_type)) {
+                            ("value", OBJECT) => values.write_u64::<BigEndian>(array_id)?,
+                            ("coder", BYTE) => values.write_i8(coder)?,
+                            (_, field_type) => write_value(&mut values, field_type, Value::Int(0))?,
+                        }
+                    }
+                }
+            }
+            None => values.write_u64::<BigEndian>(array_id)?,
+        }
+
+        let heap = &mut self.heap;
+        heap.write_u8(INSTANCE_DUMP)?;
+        heap.write_u64::<BigEndian>(id)?;
+        heap.write_u32::<BigEndian>(STACK_TRACE_SERIAL)?;
+        heap.write_u64::<BigEndian>(class_id)?;
+        heap.write_u32::<BigEndian>(values.len().try_into()?)?;
+        heap.write_all(&values)?;
+
+        heap.write_u8(PRIMITIVE_ARRAY_DUMP)?;
+        heap.write_u64::<BigEndian>(array_id)?;
+        heap.write_u32::<BigEndian>(STACK_TRACE_SERIAL)?;
+        let length = elements.len() / type_size(element_type);
+        heap.write_u32::<BigEndian>(length.try_into()?)?;
+        heap.write_u8(element_type)?;
+        heap.write_all(&elements)?;
+
+        Ok(())
+    }
+
+    fn flush_heap_if_full(&mut self) -> Result<()> {
+        if self.heap.len() >= SEGMENT_SIZE {
+            self.flush_heap()?;
+        }
+        Ok(())
+    }
+
+    fn flush_heap(&mut self) -> Result<()> {
+        if !self.heap.is_empty() {
+            let heap = std::mem::take(&mut self.heap);
+            self.record(HEAP_DUMP_SEGMENT, |body| {
+                *body = heap;
+                Ok(())
+            })?;
+        }
+        Ok(())
+    }
+}
+
+fn object_id(r: Ref) -> u64 {
+    u64::from(r) * u64::from(ID_SIZE)
+}
+
+fn class_id(index: usize) -> u64 {
+    CLASS_IDS + index as u64 * u64::from(ID_SIZE)
+}
+
+/// Returns the id of a string, whose backing array has the next id.
+fn string_id(index: usize) -> u64 {
+    STRING_IDS + index as u64 * 2 * u64::from(ID_SIZE)
+}
+
+/// Returns the instance fields declared by a class, rather than inherited from its super class.
+fn declared_fields<'c, 'a>(class: &'c Class<'a>) -> &'c [Field<'a>] {
+    let inherited = class.super_class().map_or(0, |c| c.fields().len());
+    &class.fields()[inherited..]
+}
+
+/// Returns the range of each class's declared fields in the fields of an object of the given
+/// class, starting with the class itself and followed by its super classes, which is the order
+/// that fields are written in.
+fn field_ranges<'c, 'a>(
+    class: &'c Class<'a>,
+) -> impl Iterator<Item = (&'c Class<'a>, std::ops::Range<usize>)> {
+    std::iter::successors(Some(class), |class| class.super_class()).map(|class| {
+        let inherited = class.super_class().map_or(0, |c| c.fields().len());
+        (class, inherited..class.fields().len())
+    })
+}
+
+fn basic_type(field_type: &FieldType) -> u8 {
+    match field_type {
+        FieldType::Base(base_type) => match base_type {
+            BaseType::Byte => BYTE,
+            BaseType::Char => CHAR,
+            BaseType::Double => DOUBLE,
+            BaseType::Float => FLOAT,
+            BaseType::Int => INT,
+            BaseType::Long => LONG,
+            BaseType::Short => SHORT,
+            BaseType::Boolean => BOOLEAN,
+            BaseType::Object(_) => OBJECT,
+        },
+        FieldType::Array(..) => OBJECT,
+    }
+}
+
+/// Returns the basic type of the elements of an array type.
+fn array_element_type(field_type: &FieldType) -> u8 {
+    match field_type {
+        FieldType::Array(1, base_type) => basic_type(&FieldType::Base(base_type.clone())),
+        _ => OBJECT,
+    }
+}
+
+fn base_type(descriptor: char) -> BaseType<'static> {
+    match descriptor {
+        'B' => BaseType::Byte,
+        'C' => BaseType::Char,
+        'D' => BaseType::Double,
+        'F' => BaseType::Float,
+        'I' => BaseType::Int,
+        'J' => BaseType::Long,
+        'S' => BaseType::Short,
+        'Z' => BaseType::Boolean,
+        _ => BaseType::Object("java/lang/Object"),
+    }
+}
+
+fn type_size(basic_type: u8) -> usize {
+    match basic_type {
+        OBJECT => ID_SIZE as usize,
+        BOOLEAN | BYTE => 1,
+        CHAR | SHORT => 2,
+        FLOAT | INT => 4,
+        _ => 8,
+    }
+}
+
+/// Writes a value as the given basic type. Fields don't always hold values of their own type,
+/// e.g. booleans may be stored as ints, so values are converted.
+fn write_value(out: &mut Vec<u8>, basic_type: u8, value: Value) -> Result<()> {
+    let integer = || match value {
+        Value::Byte(v) => i64::from(v),
+        Value::Short(v) => i64::from(v),
+        Value::Int(v) => i64::from(v),
+        Value::Long(v) => v,
+        Value::Char(v) => i64::from(v),
+        Value::Boolean(v) => i64::from(v),
+        Value::Float(v) => v as i64,
+        Value::Double(v) => v as i64,
+        Value::Reference(_) | Value::String(_) => 0,
+    };
+
+    let float = || match value {
+        Value::Float(v) => f64::from(v),
+        Value::Double(v) => v,
+        _ => integer() as f64,
+    };
+
+    match basic_type {
+        OBJECT => {
+            let id = match value {
+                Value::Reference(r) => object_id(r),
+                Value::String(s) => string_id(s as usize),
+                _ => 0,
+            };
+            out.write_u64::<BigEndian>(id)?;
+        }
+        BOOLEAN => out.write_u8((integer() != 0).into())?,
+        BYTE => out.write_i8(integer() as i8)?,
+        CHAR => out.write_u16::<BigEndian>(integer() as u16)?,
+        SHORT => out.write_i16::<BigEndian>(integer() as i16)?,
+        INT => out.write_i32::<BigEndian>(integer() as i32)?,
+        LONG => out.write_i64::<BigEndian>(integer())?,
+        FLOAT => out.write_f32::<BigEndian>(float() as f32)?,
+        DOUBLE => out.write_f64::<BigEndian>(float())?,
+        basic_type => bail!("invalid basic type {basic_type}"),
+    }
+
+    Ok(())
+}
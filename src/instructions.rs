@@ -1,9 +1,16 @@
 use std::num::NonZeroU8;
 
-use strum::FromRepr;
+use serde::{Deserialize, Serialize};
+use strum::{EnumCount, EnumDiscriminants, FromRepr};
 
 #[allow(non_camel_case_types)]
-#[derive(Debug)]
+#[derive(Debug, EnumDiscriminants, Serialize, Deserialize)]
+#[strum_discriminants(
+    name(InstructionKind),
+    derive(EnumCount, FromRepr),
+    repr(u8),
+    allow(non_camel_case_types)
+)]
 pub enum Instruction {
     // Constants
     nop,
@@ -97,9 +104,11 @@ pub enum Instruction {
     breakpoint,
     impdep1,
     impdep2,
+    // Only produced by lenient decoding, for an instruction which couldn't be decoded
+    unknown { opcode: u8 },
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum NumberType {
     Int,
     Long,
@@ -107,13 +116,13 @@ pub enum NumberType {
     Double,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum IntegerType {
     Int,
     Long,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub enum LoadStoreType {
     Int,
     Long,
@@ -122,7 +131,7 @@ pub enum LoadStoreType {
     Reference,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ArrayLoadStoreType {
     Int,
     Long,
@@ -134,7 +143,7 @@ pub enum ArrayLoadStoreType {
     Short,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Condition {
     Eq,
     Ne,
@@ -144,25 +153,25 @@ pub enum Condition {
     Ge,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub enum EqCondition {
     Eq,
     Ne,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub enum OrdCondition {
     Lt,
     Gt,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub enum IfCmpType {
     Int,
     Reference,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum InvokeKind {
     Virtual,
     Special,
@@ -171,7 +180,7 @@ pub enum InvokeKind {
     Dynamic,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub enum ReturnType {
     Void,
     Int,
@@ -181,7 +190,7 @@ pub enum ReturnType {
     Reference,
 }
 
-#[derive(Clone, Copy, Debug, FromRepr)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromRepr, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum ArrayType {
     Boolean = 4,
@@ -194,6 +203,22 @@ pub enum ArrayType {
     Long = 11,
 }
 
+impl ArrayType {
+    /// Returns the field descriptor of the element type, e.g. `I` for int.
+    pub fn descriptor(self) -> char {
+        match self {
+            ArrayType::Boolean => 'Z',
+            ArrayType::Char => 'C',
+            ArrayType::Float => 'F',
+            ArrayType::Double => 'D',
+            ArrayType::Byte => 'B',
+            ArrayType::Short => 'S',
+            ArrayType::Int => 'I',
+            ArrayType::Long => 'J',
+        }
+    }
+}
+
 impl Instruction {
     pub fn iconst(value: i8) -> Instruction {
         Instruction::r#const {
@@ -454,6 +479,6 @@ impl Instruction {
     }
 
     pub fn ifnonnull(branch: i16) -> Instruction {
-        Instruction::ifnull { branch }
+        Instruction::ifnonnull { branch }
     }
 }
@@ -0,0 +1,475 @@
+//! A register-based form of a method's code, translated from its stack-based instructions.
+//!
+//! Each local and each slot of the operand stack gets a register: the locals come first, and the
+//! slot at each depth of the stack follows them. Loading a local doesn't copy it into a stack
+//! register. Instead, the operations which use the value read the local's register directly, so
+//! most of the shuffling between locals and the stack disappears. Values are only moved into
+//! their stack registers where the bytecode relies on them being there, which is at the end of a
+//! block that leaves values on the stack, or before the local they were loaded from is changed.
+
+use std::fmt::{self, Display};
+
+use bumpalo::Bump;
+
+use crate::cfg::ControlFlowGraph;
+use crate::class_file::constant_pool::{ConstantInfo, ConstantPool};
+use crate::class_file::CodeAttribute;
+use crate::descriptor::parse_method_descriptor;
+use crate::error::{bail, format_err, ContextCompat, Error, Result};
+use crate::instructions::{Instruction, InvokeKind, ReturnType};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Register(pub u16);
+
+impl Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "r{}", self.0)
+    }
+}
+
+#[derive(Debug)]
+pub enum Op<'a> {
+    /// Copies a value from one register to another.
+    Move {
+        dst: Register,
+        src: Register,
+    },
+    /// Executes an instruction which doesn't transfer control, with its operands read from
+    /// registers instead of the operand stack. Its result, if any, is written to `dst`.
+    Apply {
+        instruction: &'a Instruction,
+        args: Vec<Register>,
+        dst: Option<Register>,
+    },
+    /// Executes a conditional branch instruction, which continues at the `target` block if the
+    /// branch is taken or at the `fallthrough` block otherwise.
+    Branch {
+        instruction: &'a Instruction,
+        args: Vec<Register>,
+        target: usize,
+        fallthrough: usize,
+    },
+    Jump {
+        target: usize,
+    },
+    Return {
+        value: Option<Register>,
+    },
+    Throw {
+        exception: Register,
+    },
+}
+
+impl Op<'_> {
+    fn is_terminator(&self) -> bool {
+        matches!(
+            self,
+            Op::Branch { .. } | Op::Jump { .. } | Op::Return { .. } | Op::Throw { .. }
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Block<'a> {
+    /// The depth of the operand stack when the block is entered. The values on the stack are in
+    /// their stack registers.
+    pub stack_depth: usize,
+    /// The operations of the block, the last of which transfers control elsewhere. This is empty
+    /// if the block can't be reached.
+    pub ops: Vec<Op<'a>>,
+}
+
+#[derive(Debug)]
+pub struct Function<'a> {
+    /// The number of registers, which is `max_locals` plus `max_stack`.
+    pub registers: usize,
+    /// The blocks of the method, which have the same indices as in its [`ControlFlowGraph`].
+    pub blocks: Vec<Block<'a>>,
+}
+
+impl<'a> Function<'a> {
+    /// Translates a method's code, from the instructions and offsets returned by
+    /// [`decode_instructions`](crate::class::decode_instructions). Fails if the code uses
+    /// instructions which shuffle values within the operand stack, like `swap` and `dup_x1`, or
+    /// subroutines or switches.
+    pub fn translate(
+        constant_pool: &ConstantPool,
+        code: &CodeAttribute,
+        instructions: &'a [Instruction],
+        offsets: &[u32],
+    ) -> Result<Function<'a>> {
+        let cfg = ControlFlowGraph::new(code, instructions, offsets)?;
+        let locals = code.max_locals as usize;
+
+        let mut blocks = (0..cfg.blocks.len())
+            .map(|_| Block::default())
+            .collect::<Vec<_>>();
+
+        let mut stack_depths = vec![None; cfg.blocks.len()];
+        stack_depths[0] = Some(0);
+        for block in &cfg.blocks {
+            // Exception handlers start with just the exception on the stack
+            for handler in &block.handlers {
+                stack_depths[*handler] = Some(1);
+            }
+        }
+
+        // Each block is visited after at least one of its predecessors, so the depth of the
+        // stack is known when it's translated
+        for index in cfg.reverse_postorder() {
+            let stack_depth = stack_depths[index]
+                .wrap_err_with(|| format_err!("unknown stack depth at block {index}"))?;
+
+            let mut translator = Translator {
+                constant_pool,
+                locals,
+                stack: (0..stack_depth).map(|depth| stack(locals, depth)).collect(),
+                ops: vec![],
+            };
+
+            let block = &cfg.blocks[index];
+            for (i, instruction) in instructions[block.start..block.end].iter().enumerate() {
+                translator.translate(instruction, block.start + i, &cfg)?;
+            }
+
+            if !translator.ops.last().is_some_and(Op::is_terminator) {
+                translator.flush_stack();
+                translator.ops.push(Op::Jump {
+                    target: block.successors[0],
+                });
+            }
+
+            for successor in &block.successors {
+                match stack_depths[*successor] {
+                    None => stack_depths[*successor] = Some(translator.stack.len()),
+                    Some(depth) if depth == translator.stack.len() => {}
+                    Some(depth) => bail!(
+                        "inconsistent stack depth at block {successor}: {depth} and {}",
+                        translator.stack.len()
+                    ),
+                }
+            }
+
+            blocks[index] = Block {
+                stack_depth,
+                ops: translator.ops,
+            };
+        }
+
+        Ok(Function {
+            registers: locals + code.max_stack as usize,
+            blocks,
+        })
+    }
+}
+
+impl Display for Function<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, block) in self.blocks.iter().enumerate() {
+            if block.ops.is_empty() {
+                continue;
+            }
+
+            writeln!(f, "block {index} (stack depth {}):", block.stack_depth)?;
+
+            for op in &block.ops {
+                write!(f, "  ")?;
+                match op {
+                    Op::Move { dst, src } => write!(f, "{dst} = {src}")?,
+                    Op::Apply {
+                        instruction,
+                        args,
+                        dst,
+                    } => {
+                        if let Some(dst) = dst {
+                            write!(f, "{dst} = ")?;
+                        }
+                        write!(f, "{instruction:?}")?;
+                        write_args(f, args)?;
+                    }
+                    Op::Branch {
+                        instruction,
+                        args,
+                        target,
+                        fallthrough,
+                    } => {
+                        write!(f, "{instruction:?}")?;
+                        write_args(f, args)?;
+                        write!(f, " -> block {target}, else block {fallthrough}")?;
+                    }
+                    Op::Jump { target } => write!(f, "jump block {target}")?,
+                    Op::Return { value: None } => write!(f, "return")?,
+                    Op::Return { value: Some(value) } => write!(f, "return {value}")?,
+                    Op::Throw { exception } => write!(f, "throw {exception}")?,
+                }
+                writeln!(f)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn write_args(f: &mut fmt::Formatter<'_>, args: &[Register]) -> fmt::Result {
+    for (i, arg) in args.iter().enumerate() {
+        write!(f, "{}{arg}", if i == 0 { " " } else { ", " })?;
+    }
+    Ok(())
+}
+
+/// Returns the register of the operand stack slot at a depth.
+fn stack(locals: usize, depth: usize) -> Register {
+    Register((locals + depth) as u16)
+}
+
+/// Translates the instructions of a single block.
+struct Translator<'a, 'b> {
+    constant_pool: &'b ConstantPool<'b>,
+    locals: usize,
+    /// The register holding the value at each depth of the operand stack, which is either the
+    /// slot's own register or the register of the local it was loaded from.
+    stack: Vec<Register>,
+    ops: Vec<Op<'a>>,
+}
+
+impl<'a> Translator<'a, '_> {
+    fn translate(
+        &mut self,
+        instruction: &'a Instruction,
+        pc: usize,
+        cfg: &ControlFlowGraph,
+    ) -> Result<()> {
+        let target = |branch: isize| {
+            let target = pc
+                .checked_add_signed(branch)
+                .wrap_err("invalid branch target")?;
+            Ok::<_, Error>(cfg.block_index(target))
+        };
+
+        match instruction {
+            Instruction::nop => {}
+            Instruction::load { index, .. } => self.stack.push(Register(*index as u16)),
+            Instruction::store { index, .. } => {
+                let value = self.pop()?;
+                self.store(Register(*index as u16), value);
+            }
+            Instruction::inc { index, .. } => {
+                let local = Register(*index as u16);
+                self.flush_local(local);
+                self.ops.push(Op::Apply {
+                    instruction,
+                    args: vec![local],
+                    dst: Some(local),
+                });
+            }
+            Instruction::pop => {
+                self.pop()?;
+            }
+            Instruction::dup => {
+                let value = *self.stack.last().wrap_err("operand stack is empty")?;
+                self.stack.push(value);
+            }
+            Instruction::r#if { branch, .. }
+            | Instruction::if_icmp { branch, .. }
+            | Instruction::if_acmp { branch, .. }
+            | Instruction::ifnull { branch }
+            | Instruction::ifnonnull { branch } => {
+                let nargs = match instruction {
+                    Instruction::if_icmp { .. } | Instruction::if_acmp { .. } => 2,
+                    _ => 1,
+                };
+                let args = self.pop_args(nargs)?;
+                self.flush_stack();
+                self.ops.push(Op::Branch {
+                    instruction,
+                    args,
+                    target: target(*branch as isize)?,
+                    fallthrough: cfg.block_index(pc + 1),
+                });
+            }
+            Instruction::goto { branch } => {
+                self.flush_stack();
+                self.ops.push(Op::Jump {
+                    target: target(*branch as isize)?,
+                });
+            }
+            Instruction::r#return { data_type } => {
+                let value = match data_type {
+                    ReturnType::Void => None,
+                    _ => Some(self.pop()?),
+                };
+                self.ops.push(Op::Return { value });
+            }
+            Instruction::athrow => {
+                let exception = self.pop()?;
+                self.ops.push(Op::Throw { exception });
+            }
+            _ => {
+                let (nargs, has_result) = self.stack_effect(instruction)?;
+                let args = self.pop_args(nargs)?;
+                let dst = has_result.then(|| stack(self.locals, self.stack.len()));
+                self.ops.push(Op::Apply {
+                    instruction,
+                    args,
+                    dst,
+                });
+                self.stack.extend(dst);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the number of values that an instruction pops from the operand stack, and
+    /// whether it pushes a result.
+    fn stack_effect(&self, instruction: &Instruction) -> Result<(usize, bool)> {
+        Ok(match instruction {
+            Instruction::aconst_null
+            | Instruction::r#const { .. }
+            | Instruction::bipush { .. }
+            | Instruction::sipush { .. }
+            | Instruction::ldc { .. }
+            | Instruction::ldc2 { .. }
+            | Instruction::getstatic { .. }
+            | Instruction::new { .. } => (0, true),
+            Instruction::neg { .. }
+            | Instruction::i2l
+            | Instruction::i2f
+            | Instruction::i2d
+            | Instruction::l2i
+            | Instruction::l2f
+            | Instruction::l2d
+            | Instruction::f2i
+            | Instruction::f2l
+            | Instruction::f2d
+            | Instruction::d2i
+            | Instruction::d2l
+            | Instruction::d2f
+            | Instruction::i2b
+            | Instruction::i2c
+            | Instruction::i2s
+            | Instruction::getfield { .. }
+            | Instruction::newarray { .. }
+            | Instruction::anewarray { .. }
+            | Instruction::arraylength
+            | Instruction::checkcast { .. }
+            | Instruction::instanceof { .. } => (1, true),
+            Instruction::add { .. }
+            | Instruction::sub { .. }
+            | Instruction::mul { .. }
+            | Instruction::div { .. }
+            | Instruction::rem { .. }
+            | Instruction::shl { .. }
+            | Instruction::shr { .. }
+            | Instruction::ushr { .. }
+            | Instruction::and { .. }
+            | Instruction::or { .. }
+            | Instruction::xor { .. }
+            | Instruction::lcmp
+            | Instruction::fcmp { .. }
+            | Instruction::dcmp { .. }
+            | Instruction::arrayload { .. } => (2, true),
+            Instruction::putstatic { .. }
+            | Instruction::monitorenter
+            | Instruction::monitorexit => (1, false),
+            Instruction::putfield { .. } => (2, false),
+            Instruction::arraystore { .. } => (3, false),
+            Instruction::multianewarray { dimensions, .. } => (*dimensions as usize, true),
+            Instruction::invoke { kind, index } => {
+                let name_and_type_index = match self.constant_pool.get(*index) {
+                    Some(
+                        ConstantInfo::MethodRef(method) | ConstantInfo::InterfaceMethodRef(method),
+                    ) => method.name_and_type_index,
+                    Some(ConstantInfo::InvokeDynamic(call_site)) => call_site.name_and_type_index,
+                    _ => bail!("invalid method reference: {index}"),
+                };
+
+                let descriptor = self
+                    .constant_pool
+                    .get(name_and_type_index)
+                    .and_then(|c| c.try_as_name_and_type_ref())
+                    .and_then(|name_and_type| {
+                        self.constant_pool.get(name_and_type.descriptor_index)
+                    })
+                    .and_then(|c| c.try_as_utf_8_ref())
+                    .wrap_err("invalid method reference")?;
+                let arena = Bump::new();
+                let descriptor = parse_method_descriptor(&arena, descriptor)?;
+
+                let mut nargs = descriptor.params.len();
+                if !matches!(kind, InvokeKind::Static | InvokeKind::Dynamic) {
+                    nargs += 1;
+                }
+
+                (nargs, descriptor.return_type.is_some())
+            }
+            _ => bail!("{instruction:?} isn't supported"),
+        })
+    }
+
+    fn pop(&mut self) -> Result<Register> {
+        self.stack.pop().wrap_err("operand stack is empty")
+    }
+
+    /// Pops the operands of an instruction, in the order that they were pushed.
+    fn pop_args(&mut self, nargs: usize) -> Result<Vec<Register>> {
+        let start = self
+            .stack
+            .len()
+            .checked_sub(nargs)
+            .wrap_err("operand stack is empty")?;
+        Ok(self.stack.split_off(start))
+    }
+
+    /// Stores a value to a local. If the value was just computed into a stack register, the
+    /// computation writes to the local instead.
+    fn store(&mut self, local: Register, value: Register) {
+        if value == local {
+            return;
+        }
+
+        self.flush_local(local);
+
+        if value.0 as usize >= self.locals && !self.stack.contains(&value) {
+            if let Some(Op::Apply { dst, .. }) = self.ops.last_mut() {
+                if *dst == Some(value) {
+                    *dst = Some(local);
+                    return;
+                }
+            }
+        }
+
+        self.ops.push(Op::Move {
+            dst: local,
+            src: value,
+        });
+    }
+
+    /// Moves the values on the stack which were loaded from a local into their stack registers,
+    /// before the local is changed.
+    fn flush_local(&mut self, local: Register) {
+        for depth in 0..self.stack.len() {
+            if self.stack[depth] == local {
+                self.flush(depth);
+            }
+        }
+    }
+
+    /// Moves every value on the stack into its stack register, as it is at the end of a block.
+    fn flush_stack(&mut self) {
+        for depth in 0..self.stack.len() {
+            self.flush(depth);
+        }
+    }
+
+    fn flush(&mut self, depth: usize) {
+        let register = stack(self.locals, depth);
+        if self.stack[depth] != register {
+            self.ops.push(Op::Move {
+                dst: register,
+                src: self.stack[depth],
+            });
+            self.stack[depth] = register;
+        }
+    }
+}
@@ -0,0 +1,922 @@
+//! An agent for the Java Debug Wire Protocol, which lets debuggers like jdb and IntelliJ IDEA
+//! attach to the vm.
+//!
+//! Only part of the protocol is implemented: listing classes and threads, breakpoints, stepping,
+//! and reading the values of locals, fields and arrays. Values can't be changed and methods can't
+//! be invoked from the debugger. Commands are handled by the threads running Java code, between
+//! instructions, so the debugger gets no replies while every thread is blocked in native code.
+
+mod commands;
+mod packet;
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, ThreadId};
+
+use crate::call_frame::{
+    ArrayElementType, JvmValue, LocalVariables, ObjectHeader, Operands, RefTypeHeader,
+};
+use crate::class::{Class, Method};
+use crate::class_file::ClassAccessFlags;
+use crate::error::{bail, Error, Result};
+use crate::hooks::{may_call, Hook, MethodRef};
+use crate::instructions::Instruction;
+use crate::vm::Vm;
+
+use self::packet::{Command, ErrorCode, Location, Writer, HANDSHAKE};
+
+const EVENT_SINGLE_STEP: u8 = 1;
+const EVENT_BREAKPOINT: u8 = 2;
+const EVENT_THREAD_START: u8 = 6;
+const EVENT_CLASS_PREPARE: u8 = 8;
+const EVENT_VM_START: u8 = 90;
+const EVENT_VM_DEATH: u8 = 99;
+
+const SUSPEND_NONE: u8 = 0;
+const SUSPEND_EVENT_THREAD: u8 = 1;
+const SUSPEND_ALL: u8 = 2;
+
+const TYPE_TAG_CLASS: u8 = 1;
+const TYPE_TAG_INTERFACE: u8 = 2;
+const TYPE_TAG_ARRAY: u8 = 3;
+
+const STEP_SIZE_MIN: i32 = 0;
+const STEP_DEPTH_OVER: i32 = 1;
+const STEP_DEPTH_OUT: i32 = 2;
+
+/// The ids of objects and classes are their addresses, so the other kinds of ids are given high
+/// bits which addresses don't use.
+const STRING_IDS: u64 = 1 << 60;
+const THREAD_IDS: u64 = 2 << 60;
+const ARRAY_TYPE_IDS: u64 = 3 << 60;
+const THREAD_GROUP_ID: u64 = 4 << 60;
+const ID_KIND_MASK: u64 = 0xf << 60;
+
+/// A hook which lets a debugger attach to the vm over JDWP.
+pub struct JdwpAgent {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    /// Notified when a command arrives or a thread is resumed.
+    changed: Condvar,
+    /// Cleared once the debugger disconnects, after which the hooks do nothing.
+    attached: AtomicBool,
+    /// Whether threads need to check in with the agent before their next instruction, which is
+    /// the case while commands are waiting, threads are suspended or events could be reported.
+    attention: AtomicBool,
+}
+
+struct State {
+    stream: TcpStream,
+    commands: VecDeque<Command>,
+    detached: bool,
+    /// Set by the `Exit` command, which exits the process once it's been replied to.
+    exit_status: Option<i32>,
+    next_packet_id: u32,
+    threads: HashMap<ThreadId, DebugThread>,
+    requests: Vec<EventRequest>,
+    next_request_id: i32,
+    /// The strings which have been given ids, indexed by their id without [`STRING_IDS`].
+    strings: Vec<String>,
+    string_ids: HashMap<String, u64>,
+    /// The objects which have been given to the debugger. Objects are never moved or freed, so
+    /// their addresses are used as ids, but only these ones can be dereferenced safely.
+    objects: HashSet<u64>,
+    /// Arrays don't have classes in the vm, so their types are given ids by signature.
+    array_types: Vec<String>,
+}
+
+/// A thread which has run Java code.
+struct DebugThread {
+    /// The thread's id, without [`THREAD_IDS`].
+    number: u64,
+    name: String,
+    suspend_count: u32,
+    /// The thread's copy of its stack, with the innermost frame last, since the interpreter's
+    /// frames can't be walked.
+    frames: Vec<Frame>,
+}
+
+struct Frame {
+    class: usize,
+    method: usize,
+    /// The index of the instruction the frame is at, which is only kept up to date for frames
+    /// which are calling a method and for the frame of a suspended thread.
+    pc: usize,
+    /// The frame's locals, as of when it last called a method or was suspended.
+    locals: Vec<Option<Value>>,
+}
+
+/// A copy of a Java value, which can outlive the frame it was read from.
+#[derive(Clone, Debug)]
+enum Value {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Char(u16),
+    Float(f32),
+    Double(f64),
+    Boolean(bool),
+    Reference(usize),
+    String(String),
+}
+
+impl From<&JvmValue<'_>> for Value {
+    fn from(value: &JvmValue<'_>) -> Value {
+        match *value {
+            JvmValue::Byte(v) => Value::Byte(v),
+            JvmValue::Short(v) => Value::Short(v),
+            JvmValue::Int(v) => Value::Int(v),
+            JvmValue::Long(v) => Value::Long(v),
+            JvmValue::Char(v) => Value::Char(v),
+            JvmValue::Float(v) => Value::Float(v),
+            JvmValue::Double(v) => Value::Double(v),
+            JvmValue::Boolean(v) => Value::Boolean(v),
+            JvmValue::ReturnAddress(pc) => Value::Int(pc as i32),
+            JvmValue::Reference(reference) => Value::Reference(reference),
+            JvmValue::StringConst(s) => Value::String(s.to_owned()),
+        }
+    }
+}
+
+struct EventRequest {
+    id: i32,
+    kind: u8,
+    suspend_policy: u8,
+    modifiers: Vec<Modifier>,
+}
+
+/// A filter on the events reported for a request. Filters which only apply to events the agent
+/// doesn't report are accepted but ignored.
+enum Modifier {
+    /// Reports the event only once, when the other filters have matched this many times.
+    Count(i32),
+    ThreadOnly(u64),
+    ClassOnly(u64),
+    ClassMatch(String),
+    ClassExclude(String),
+    LocationOnly(Location),
+    Step(Step),
+    Ignored,
+}
+
+/// A step requested for a thread, along with where the thread was when it was requested.
+struct Step {
+    thread: u64,
+    size: i32,
+    depth: i32,
+    start_depth: usize,
+    start_line: Option<u16>,
+    start_offset: u32,
+}
+
+/// Where an event happened, which the filters of requests are matched against.
+struct EventContext<'c> {
+    thread: u64,
+    class: Option<&'c Class<'c>>,
+    location: Option<Location>,
+    position: Option<Position>,
+}
+
+/// The position of a thread within its code, for deciding when steps are done.
+struct Position {
+    depth: usize,
+    line: Option<u16>,
+    line_start: bool,
+    offset: u32,
+}
+
+/// An event, without its kind and request id.
+struct Event {
+    kind: u8,
+    request: i32,
+    data: Writer,
+}
+
+impl JdwpAgent {
+    /// Waits for a debugger to attach to `listener`. The calling thread is reported to the
+    /// debugger as the main thread, and if `suspend` is set it's suspended before its first
+    /// instruction, so that breakpoints can be set before the program starts.
+    pub fn accept(listener: &TcpListener, suspend: bool) -> Result<JdwpAgent> {
+        let (mut stream, _) = listener.accept()?;
+
+        let mut handshake = [0; HANDSHAKE.len()];
+        stream.read_exact(&mut handshake)?;
+        if handshake != HANDSHAKE {
+            bail!("debugger sent an invalid handshake");
+        }
+        stream.write_all(HANDSHAKE)?;
+
+        let mut reader = stream.try_clone()?;
+        let mut state = State {
+            stream,
+            commands: VecDeque::new(),
+            detached: false,
+            exit_status: None,
+            next_packet_id: 1,
+            threads: HashMap::new(),
+            requests: Vec::new(),
+            next_request_id: 1,
+            strings: Vec::new(),
+            string_ids: HashMap::new(),
+            objects: HashSet::new(),
+            array_types: Vec::new(),
+        };
+
+        let main = state.thread(thread::current().id());
+        main.name = "main".to_owned();
+        main.suspend_count = suspend as u32;
+        let main = main.number;
+
+        let mut data = Writer::default();
+        data.id(THREAD_IDS | main);
+        let policy = if suspend { SUSPEND_ALL } else { SUSPEND_NONE };
+        state.send_events(
+            policy,
+            vec![Event {
+                kind: EVENT_VM_START,
+                request: 0,
+                data,
+            }],
+        );
+
+        let shared = Arc::new(Shared {
+            attention: AtomicBool::new(state.needs_attention()),
+            attached: AtomicBool::new(true),
+            state: Mutex::new(state),
+            changed: Condvar::new(),
+        });
+
+        let reader_shared = shared.clone();
+        thread::Builder::new()
+            .name("jdwp".to_owned())
+            .spawn(move || loop {
+                let command = packet::read_command(&mut reader);
+                let mut state = reader_shared.state.lock().unwrap();
+                match command {
+                    Ok(command) => state.commands.push_back(command),
+                    Err(_) => state.detach(),
+                }
+                let detached = state.detached;
+                reader_shared.update(&state);
+                drop(state);
+
+                if detached {
+                    break;
+                }
+            })?;
+
+        Ok(JdwpAgent { shared })
+    }
+
+    /// Tells the debugger that the program has ended, and disconnects from it.
+    pub fn vm_death(&self) {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.detached {
+            return;
+        }
+
+        let mut events = vec![Event {
+            kind: EVENT_VM_DEATH,
+            request: 0,
+            data: Writer::default(),
+        }];
+        for request in &state.requests {
+            if request.kind == EVENT_VM_DEATH {
+                events.push(Event {
+                    kind: EVENT_VM_DEATH,
+                    request: request.id,
+                    data: Writer::default(),
+                });
+            }
+        }
+
+        state.send_events(SUSPEND_NONE, events);
+        let _ = state.stream.shutdown(Shutdown::Both);
+        state.detach();
+        self.shared.update(&state);
+    }
+
+    /// Handles any commands from the debugger, reports the events which match the current thread
+    /// at `position`, and then waits while the thread is suspended.
+    fn check_in<'s, 'a>(
+        &'s self,
+        vm: &Vm<'a>,
+        mut state: MutexGuard<'s, State>,
+        method: MethodRef<'a>,
+        pc: usize,
+        locals: LocalVariables<'_, 'a>,
+    ) -> MutexGuard<'s, State> {
+        state = self.handle_commands(vm, state);
+
+        let thread = state.thread(thread::current().id());
+        let number = thread.number;
+        let depth = thread.frames.len();
+
+        let position = method.method.body.as_ref().map(|body| {
+            let offset = body.offsets[pc];
+            let (line, line_start) = line_at(method, offset);
+            Position {
+                depth,
+                line,
+                line_start,
+                offset,
+            }
+        });
+
+        let location = position
+            .as_ref()
+            .map(|position| location(method.class, method.method, Some(position.offset)));
+
+        let context = EventContext {
+            thread: number,
+            class: Some(method.class),
+            location,
+            position,
+        };
+
+        let mut events = Vec::new();
+        let mut policy = SUSPEND_NONE;
+        for kind in [EVENT_BREAKPOINT, EVENT_SINGLE_STEP] {
+            for (request, request_policy) in state.matching_requests(kind, &context) {
+                let mut data = Writer::default();
+                data.id(THREAD_IDS | number);
+                data.location(&location.unwrap());
+                events.push(Event {
+                    kind,
+                    request,
+                    data,
+                });
+                policy = policy.max(request_policy);
+            }
+        }
+
+        if !events.is_empty() || state.thread(thread::current().id()).suspend_count > 0 {
+            if let Some(frame) = state.thread(thread::current().id()).frames.last_mut() {
+                frame.locals = capture_locals(locals);
+            }
+        }
+
+        self.report(vm, state, policy, events)
+    }
+
+    /// Sends events which happened on the current thread and suspends threads according to
+    /// `policy`, then waits while the current thread is suspended.
+    fn report<'s>(
+        &'s self,
+        vm: &Vm,
+        mut state: MutexGuard<'s, State>,
+        policy: u8,
+        events: Vec<Event>,
+    ) -> MutexGuard<'s, State> {
+        if !events.is_empty() {
+            match policy {
+                SUSPEND_EVENT_THREAD => state.thread(thread::current().id()).suspend_count += 1,
+                SUSPEND_ALL => {
+                    for thread in state.threads.values_mut() {
+                        thread.suspend_count += 1;
+                    }
+                }
+                _ => {}
+            }
+            state.send_events(policy, events);
+        }
+
+        loop {
+            state = self.handle_commands(vm, state);
+            let suspended = state.thread(thread::current().id()).suspend_count > 0;
+            if state.detached || !suspended {
+                break;
+            }
+            state = self.shared.changed.wait(state).unwrap();
+        }
+
+        self.shared.update(&state);
+        state
+    }
+
+    fn handle_commands<'s>(
+        &'s self,
+        vm: &Vm,
+        mut state: MutexGuard<'s, State>,
+    ) -> MutexGuard<'s, State> {
+        let mut handled = false;
+        while let Some(command) = state.commands.pop_front() {
+            let reply = commands::handle(vm, &mut state, &command);
+            let result = packet::write_reply(
+                &mut state.stream,
+                command.id,
+                reply.as_ref().map(|data| data.as_slice()).map_err(|e| *e),
+            );
+            if result.is_err() {
+                state.detach();
+            }
+
+            if let Some(status) = state.exit_status {
+                let _ = state.stream.shutdown(Shutdown::Both);
+                std::process::exit(status);
+            }
+
+            handled = true;
+        }
+
+        if handled {
+            // Commands can resume threads, which may be waiting for them
+            self.shared.update(&state);
+            self.shared.changed.notify_all();
+        }
+
+        state
+    }
+}
+
+impl Shared {
+    fn update(&self, state: &State) {
+        self.attached.store(!state.detached, Ordering::Relaxed);
+        self.attention
+            .store(state.needs_attention(), Ordering::Relaxed);
+        self.changed.notify_all();
+    }
+}
+
+impl State {
+    /// Returns the debugger's view of a thread, which is registered when it's first seen.
+    fn thread(&mut self, id: ThreadId) -> &mut DebugThread {
+        let number = self.threads.len() as u64 + 1;
+        self.threads.entry(id).or_insert_with(|| DebugThread {
+            number,
+            name: format!("Thread-{number}"),
+            suspend_count: 0,
+            frames: Vec::new(),
+        })
+    }
+
+    fn thread_by_id(&mut self, id: u64) -> Result<&mut DebugThread, ErrorCode> {
+        if id & ID_KIND_MASK != THREAD_IDS {
+            return Err(ErrorCode::InvalidThread);
+        }
+        self.threads
+            .values_mut()
+            .find(|thread| thread.number == id & !ID_KIND_MASK)
+            .ok_or(ErrorCode::InvalidThread)
+    }
+
+    fn needs_attention(&self) -> bool {
+        !self.detached
+            && (!self.commands.is_empty()
+                || self.threads.values().any(|thread| thread.suspend_count > 0)
+                || self
+                    .requests
+                    .iter()
+                    .any(|request| matches!(request.kind, EVENT_BREAKPOINT | EVENT_SINGLE_STEP)))
+    }
+
+    /// Forgets the debugger's requests and resumes every thread, so that the program carries on
+    /// as if the debugger had never attached.
+    fn detach(&mut self) {
+        self.detached = true;
+        self.commands.clear();
+        self.requests.clear();
+        for thread in self.threads.values_mut() {
+            thread.suspend_count = 0;
+        }
+    }
+
+    fn send_events(&mut self, policy: u8, events: Vec<Event>) {
+        let mut data = Writer::default();
+        data.u8(policy);
+        data.count(events.len());
+        for event in events {
+            data.u8(event.kind);
+            data.i32(event.request);
+            data.extend(event.data);
+        }
+
+        let id = self.next_packet_id;
+        self.next_packet_id += 1;
+
+        // The composite command of the event command set
+        if packet::write_command(&mut self.stream, id, 64, 100, &data.into_inner()).is_err() {
+            self.detach();
+        }
+    }
+
+    /// Returns the id and suspend policy of each request of a kind whose filters match an event.
+    fn matching_requests(&mut self, kind: u8, context: &EventContext) -> Vec<(i32, u8)> {
+        let mut matching = Vec::new();
+        for request in &mut self.requests {
+            if request.kind != kind {
+                continue;
+            }
+
+            let matches = request
+                .modifiers
+                .iter()
+                .all(|modifier| modifier.matches(context));
+            if !matches {
+                continue;
+            }
+
+            let mut reported = true;
+            for modifier in &mut request.modifiers {
+                if let Modifier::Count(count) = modifier {
+                    *count -= 1;
+                    reported &= *count == 0;
+                }
+            }
+
+            if reported {
+                matching.push((request.id, request.suspend_policy));
+            }
+        }
+        matching
+    }
+
+    /// Returns the id of a string, which is given one if it doesn't have one yet.
+    fn string_id(&mut self, s: &str) -> u64 {
+        if let Some(&id) = self.string_ids.get(s) {
+            return id;
+        }
+        let id = STRING_IDS | self.strings.len() as u64;
+        self.strings.push(s.to_owned());
+        self.string_ids.insert(s.to_owned(), id);
+        id
+    }
+
+    fn string(&self, id: u64) -> Result<&str, ErrorCode> {
+        if id & ID_KIND_MASK != STRING_IDS {
+            return Err(ErrorCode::InvalidObject);
+        }
+        self.strings
+            .get((id & !ID_KIND_MASK) as usize)
+            .map(|s| s.as_str())
+            .ok_or(ErrorCode::InvalidObject)
+    }
+
+    fn array_type_id(&mut self, signature: String) -> u64 {
+        let index = match self.array_types.iter().position(|s| *s == signature) {
+            Some(index) => index,
+            None => {
+                self.array_types.push(signature);
+                self.array_types.len() - 1
+            }
+        };
+        ARRAY_TYPE_IDS | index as u64
+    }
+
+    /// Returns the header of an object which has been given to the debugger.
+    fn object(&self, id: u64) -> Result<&'static mut RefTypeHeader, ErrorCode> {
+        if !self.objects.contains(&id) {
+            return Err(ErrorCode::InvalidObject);
+        }
+        // SAFETY: The object was given to the debugger, so it's a valid reference, and objects
+        // are never freed
+        Ok(unsafe { &mut *(id as *mut RefTypeHeader) })
+    }
+
+    /// Writes a value, tagged with its type. Ints are narrowed to the type given by `signature`,
+    /// since locals of the smaller types hold ints.
+    fn write_value(&mut self, w: &mut Writer, value: Option<&Value>, signature: u8) {
+        match value {
+            Some(Value::Int(v)) => match signature {
+                b'Z' => {
+                    w.u8(b'Z');
+                    w.bool(*v != 0);
+                }
+                b'B' => {
+                    w.u8(b'B');
+                    w.u8(*v as u8);
+                }
+                b'C' => {
+                    w.u8(b'C');
+                    w.i16(*v as i16);
+                }
+                b'S' => {
+                    w.u8(b'S');
+                    w.i16(*v as i16);
+                }
+                _ => {
+                    w.u8(b'I');
+                    w.i32(*v);
+                }
+            },
+            Some(Value::Byte(v)) => {
+                w.u8(b'B');
+                w.u8(*v as u8);
+            }
+            Some(Value::Short(v)) => {
+                w.u8(b'S');
+                w.i16(*v);
+            }
+            Some(Value::Char(v)) => {
+                w.u8(b'C');
+                w.i16(*v as i16);
+            }
+            Some(Value::Boolean(v)) => {
+                w.u8(b'Z');
+                w.bool(*v);
+            }
+            Some(Value::Long(v)) => {
+                w.u8(b'J');
+                w.i64(*v);
+            }
+            Some(Value::Float(v)) => {
+                w.u8(b'F');
+                w.i32(v.to_bits() as i32);
+            }
+            Some(Value::Double(v)) => {
+                w.u8(b'D');
+                w.i64(v.to_bits() as i64);
+            }
+            Some(Value::String(s)) => {
+                let id = self.string_id(s);
+                w.u8(b's');
+                w.id(id);
+            }
+            Some(Value::Reference(reference)) if *reference != 0 => {
+                let reference = *reference as u64;
+                self.objects.insert(reference);
+                w.u8(object_tag(self.object(reference).unwrap()));
+                w.id(reference);
+            }
+            Some(Value::Reference(_)) | None => {
+                w.u8(if signature == b'[' { b'[' } else { b'L' });
+                w.id(0);
+            }
+        }
+    }
+}
+
+impl Modifier {
+    fn matches(&self, context: &EventContext) -> bool {
+        let class_name = || context.class.map(|class| class.name().replace('/', "."));
+        match self {
+            Modifier::Count(count) => *count > 0,
+            Modifier::ThreadOnly(thread) => *thread == THREAD_IDS | context.thread,
+            Modifier::ClassOnly(id) => context
+                .class
+                .is_some_and(|class| class as *const _ as u64 == *id),
+            Modifier::ClassMatch(pattern) => {
+                class_name().is_some_and(|name| matches(pattern, &name))
+            }
+            Modifier::ClassExclude(pattern) => {
+                !class_name().is_some_and(|name| matches(pattern, &name))
+            }
+            Modifier::LocationOnly(location) => context.location.is_some_and(|l| {
+                l.class == location.class
+                    && l.method == location.method
+                    && l.index == location.index
+            }),
+            Modifier::Step(step) => {
+                step.thread == THREAD_IDS | context.thread
+                    && context
+                        .position
+                        .as_ref()
+                        .is_some_and(|position| step.is_done(position))
+            }
+            Modifier::Ignored => true,
+        }
+    }
+}
+
+impl Step {
+    fn is_done(&self, position: &Position) -> bool {
+        let returned = position.depth < self.start_depth;
+        let entered = position.depth > self.start_depth;
+
+        if returned {
+            return true;
+        }
+
+        if self.depth == STEP_DEPTH_OUT || (entered && self.depth == STEP_DEPTH_OVER) {
+            return false;
+        }
+
+        if self.size == STEP_SIZE_MIN {
+            return true;
+        }
+
+        // Steps by line end at the start of a line, which can be the line the step started on
+        // if a loop jumped back to it
+        position.line_start
+            && (entered || position.line != self.start_line || position.offset <= self.start_offset)
+    }
+}
+
+/// Matches a class name against a pattern of a `ClassMatch` or `ClassExclude` filter, which can
+/// start or end with `*`.
+fn matches(pattern: &str, name: &str) -> bool {
+    if let Some(suffix) = pattern.strip_prefix('*') {
+        name.ends_with(suffix)
+    } else if let Some(prefix) = pattern.strip_suffix('*') {
+        name.starts_with(prefix)
+    } else {
+        pattern == name
+    }
+}
+
+fn capture_locals(locals: LocalVariables) -> Vec<Option<Value>> {
+    (0..locals.len())
+        .map(|index| locals.get(index).as_ref().map(Value::from))
+        .collect()
+}
+
+/// Returns the tag of an object's type, as used in tagged values.
+fn object_tag(header: &RefTypeHeader) -> u8 {
+    match header {
+        RefTypeHeader::Array(_) => b'[',
+        RefTypeHeader::Object(ObjectHeader { class }) => {
+            // SAFETY: Classes live as long as the vm's arena
+            match unsafe { class.as_ref() }.name() {
+                "java/lang/Class" => b'c',
+                "java/lang/Thread" => b't',
+                _ => b'L',
+            }
+        }
+    }
+}
+
+/// Returns the signature of an array's type. The element types of arrays of references aren't
+/// known, so they're all treated as arrays of objects.
+fn array_signature(element_type: ArrayElementType) -> String {
+    match element_type {
+        ArrayElementType::Primitive(t) => format!("[{}", t.descriptor()),
+        ArrayElementType::Reference => "[Ljava/lang/Object;".to_owned(),
+    }
+}
+
+fn type_tag(class: &Class) -> u8 {
+    if class.access_flags().contains(ClassAccessFlags::INTERFACE) {
+        TYPE_TAG_INTERFACE
+    } else {
+        TYPE_TAG_CLASS
+    }
+}
+
+/// Returns the location of an instruction, given its bytecode offset, which is `None` for native
+/// methods.
+fn location(class: &Class, method: &Method, offset: Option<u32>) -> Location {
+    Location {
+        type_tag: type_tag(class),
+        class: class as *const _ as u64,
+        method: method as *const _ as u64,
+        index: offset.map_or(u64::MAX, u64::from),
+    }
+}
+
+/// Returns the line of the instruction at a bytecode offset, and whether it's the first
+/// instruction of the line.
+fn line_at(method: MethodRef, offset: u32) -> (Option<u16>, bool) {
+    let line = method.class.line_number(method.method.slot, offset);
+    let line_start = method
+        .class
+        .line_number_table(method.method.slot)
+        .is_some_and(|table| {
+            table
+                .line_number_table
+                .iter()
+                .any(|entry| u32::from(entry.start_pc) == offset)
+        });
+
+    (line, line_start)
+}
+
+impl<'a> Hook<'a> for JdwpAgent {
+    fn on_method_enter(&self, vm: &Vm<'a>, method: MethodRef<'a>, args: &[JvmValue<'a>]) {
+        if !self.shared.attached.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let mut state = self.shared.state.lock().unwrap();
+        let id = thread::current().id();
+        let new_thread = !state.threads.contains_key(&id);
+
+        let mut locals = Vec::new();
+        for arg in args {
+            locals.push(Some(Value::from(arg)));
+            if matches!(arg, JvmValue::Long(_) | JvmValue::Double(_)) {
+                locals.push(None);
+            }
+        }
+
+        let thread = state.thread(id);
+        let number = thread.number;
+        thread.frames.push(Frame {
+            class: method.class as *const _ as usize,
+            method: method.method as *const _ as usize,
+            pc: 0,
+            locals,
+        });
+
+        if new_thread {
+            let context = EventContext {
+                thread: number,
+                class: None,
+                location: None,
+                position: None,
+            };
+
+            let mut events = Vec::new();
+            let mut policy = SUSPEND_NONE;
+            for (request, request_policy) in state.matching_requests(EVENT_THREAD_START, &context) {
+                let mut data = Writer::default();
+                data.id(THREAD_IDS | number);
+                events.push(Event {
+                    kind: EVENT_THREAD_START,
+                    request,
+                    data,
+                });
+                policy = policy.max(request_policy);
+            }
+
+            drop(self.report(vm, state, policy, events));
+        }
+    }
+
+    fn on_method_exit(
+        &self,
+        _: &Vm<'a>,
+        _: MethodRef<'a>,
+        _: &Result<Option<JvmValue<'a>>, Error>,
+    ) {
+        if !self.shared.attached.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let mut state = self.shared.state.lock().unwrap();
+        state.thread(thread::current().id()).frames.pop();
+    }
+
+    fn on_instruction(
+        &self,
+        vm: &Vm<'a>,
+        method: MethodRef<'a>,
+        pc: usize,
+        instruction: &'a Instruction,
+        locals: LocalVariables<'_, 'a>,
+        _: Operands<'_, 'a>,
+    ) {
+        if !self.shared.attached.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let calls = may_call(instruction);
+        let attention = self.shared.attention.load(Ordering::Relaxed);
+        if !calls && !attention {
+            return;
+        }
+
+        let mut state = self.shared.state.lock().unwrap();
+        if let Some(frame) = state.thread(thread::current().id()).frames.last_mut() {
+            frame.pc = pc;
+            if calls {
+                frame.locals = capture_locals(locals);
+            }
+        }
+
+        if attention {
+            drop(self.check_in(vm, state, method, pc, locals));
+        }
+    }
+
+    fn on_class_load(&self, vm: &Vm<'a>, class: &'a Class<'a>) {
+        if !self.shared.attached.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let mut state = self.shared.state.lock().unwrap();
+        let number = state.thread(thread::current().id()).number;
+        let context = EventContext {
+            thread: number,
+            class: Some(class),
+            location: None,
+            position: None,
+        };
+
+        let mut events = Vec::new();
+        let mut policy = SUSPEND_NONE;
+        for (request, request_policy) in state.matching_requests(EVENT_CLASS_PREPARE, &context) {
+            let mut data = Writer::default();
+            data.id(THREAD_IDS | number);
+            data.u8(type_tag(class));
+            data.id(class as *const _ as u64);
+            data.string(&format!("L{};", class.name()));
+            // Verified and prepared
+            data.i32(3);
+            events.push(Event {
+                kind: EVENT_CLASS_PREPARE,
+                request,
+                data,
+            });
+            policy = policy.max(request_policy);
+        }
+
+        drop(self.report(vm, state, policy, events));
+    }
+}
@@ -0,0 +1,1086 @@
+//! The commands which the debugger can send, grouped by command set.
+
+use crate::call_frame::{ArrayElementType, JvmValue, ObjectHeader, RefTypeHeader};
+use crate::class::{Class, Method};
+use crate::class_file::{AttributeInfo, MethodAccessFlags};
+use crate::hooks::MethodRef;
+use crate::instructions::ArrayType;
+use crate::vm::Vm;
+
+use super::packet::{Command, ErrorCode, Reader, Writer};
+use super::{
+    array_signature, line_at, location, type_tag, EventRequest, Frame, Modifier, State, Step,
+    Value, ARRAY_TYPE_IDS, ID_KIND_MASK, STRING_IDS, THREAD_GROUP_ID, THREAD_IDS, TYPE_TAG_ARRAY,
+};
+
+const VIRTUAL_MACHINE: u8 = 1;
+const REFERENCE_TYPE: u8 = 2;
+const CLASS_TYPE: u8 = 3;
+const METHOD: u8 = 6;
+const OBJECT_REFERENCE: u8 = 9;
+const STRING_REFERENCE: u8 = 10;
+const THREAD_REFERENCE: u8 = 11;
+const THREAD_GROUP_REFERENCE: u8 = 12;
+const ARRAY_REFERENCE: u8 = 13;
+const CLASS_LOADER_REFERENCE: u8 = 14;
+const EVENT_REQUEST: u8 = 15;
+const STACK_FRAME: u8 = 16;
+const CLASS_OBJECT_REFERENCE: u8 = 17;
+
+/// The version of JDWP which the vm claims to support, which is that of the JDK it runs.
+const JDWP_VERSION: (i32, i32) = (17, 0);
+
+/// The status of classes which have been loaded.
+const CLASS_STATUS_PREPARED: i32 = 3;
+const CLASS_STATUS_INITIALIZED: i32 = 7;
+
+const THREAD_STATUS_RUNNING: i32 = 1;
+
+/// Handles a command, returning the data of its reply.
+pub(super) fn handle(vm: &Vm, state: &mut State, command: &Command) -> Result<Vec<u8>, ErrorCode> {
+    let mut r = Reader::new(&command.data);
+    let mut w = Writer::default();
+
+    match command.command_set {
+        VIRTUAL_MACHINE => virtual_machine(vm, state, command.command, &mut r, &mut w)?,
+        REFERENCE_TYPE => reference_type(vm, state, command.command, &mut r, &mut w)?,
+        CLASS_TYPE => class_type(vm, state, command.command, &mut r, &mut w)?,
+        METHOD => method(vm, state, command.command, &mut r, &mut w)?,
+        OBJECT_REFERENCE => object_reference(vm, state, command.command, &mut r, &mut w)?,
+        STRING_REFERENCE if command.command == 1 => w.string(state.string(r.id()?)?),
+        THREAD_REFERENCE => thread_reference(vm, state, command.command, &mut r, &mut w)?,
+        THREAD_GROUP_REFERENCE => thread_group_reference(state, command.command, &mut r, &mut w)?,
+        ARRAY_REFERENCE => array_reference(state, command.command, &mut r, &mut w)?,
+        CLASS_LOADER_REFERENCE if command.command == 1 => {
+            // Every class is reported as visible, since the vm doesn't track which loaders have
+            // seen which classes
+            let classes = vm.loaded_classes();
+            w.count(classes.len());
+            for class in classes {
+                write_class(&mut w, class);
+            }
+        }
+        EVENT_REQUEST => event_request(vm, state, command.command, &mut r, &mut w)?,
+        STACK_FRAME => stack_frame(vm, state, command.command, &mut r, &mut w)?,
+        CLASS_OBJECT_REFERENCE if command.command == 1 => {
+            let name = vm
+                .class_mirror_name(r.id()? as usize)
+                .ok_or(ErrorCode::InvalidObject)?;
+            match ref_type_by_name(vm, state, name)? {
+                RefType::Class(class) => write_class(&mut w, class),
+                RefType::Array(signature) => {
+                    w.u8(TYPE_TAG_ARRAY);
+                    w.id(state.array_type_id(signature));
+                }
+            }
+        }
+        _ => return Err(ErrorCode::NotImplemented),
+    }
+
+    Ok(w.into_inner())
+}
+
+fn virtual_machine(
+    vm: &Vm,
+    state: &mut State,
+    command: u8,
+    r: &mut Reader,
+    w: &mut Writer,
+) -> Result<(), ErrorCode> {
+    match command {
+        // Version
+        1 => {
+            let java_version = vm.property("java.version").unwrap_or_default();
+            w.string(&format!(
+                "rusty-java {} (Java {java_version})",
+                env!("CARGO_PKG_VERSION")
+            ));
+            w.i32(JDWP_VERSION.0);
+            w.i32(JDWP_VERSION.1);
+            w.string(&java_version);
+            w.string("rusty-java");
+        }
+        // ClassesBySignature
+        2 => {
+            let signature = r.string()?;
+            let class = signature
+                .strip_prefix('L')
+                .and_then(|name| name.strip_suffix(';'))
+                .and_then(|name| vm.find_class(name));
+            match class {
+                Some(class) => {
+                    w.count(1);
+                    write_class(w, class);
+                    w.i32(class_status(vm, class));
+                }
+                None => w.count(0),
+            }
+        }
+        // AllClasses and AllClassesWithGeneric
+        3 | 20 => {
+            let classes = vm.loaded_classes();
+            w.count(classes.len());
+            for class in classes {
+                write_class(w, class);
+                w.string(&format!("L{};", class.name()));
+                if command == 20 {
+                    w.string(class.signature().unwrap_or_default());
+                }
+                w.i32(class_status(vm, class));
+            }
+        }
+        // AllThreads
+        4 => {
+            let mut threads = state
+                .threads
+                .values()
+                .map(|thread| thread.number)
+                .collect::<Vec<_>>();
+            threads.sort();
+            w.count(threads.len());
+            for number in threads {
+                w.id(THREAD_IDS | number);
+            }
+        }
+        // TopLevelThreadGroups
+        5 => {
+            w.count(1);
+            w.id(THREAD_GROUP_ID);
+        }
+        // Dispose
+        6 => state.detach(),
+        // IDSizes, which are the same for fields, methods, objects, types and frames
+        7 => {
+            for _ in 0..5 {
+                w.i32(8);
+            }
+        }
+        // Suspend
+        8 => {
+            for thread in state.threads.values_mut() {
+                thread.suspend_count += 1;
+            }
+        }
+        // Resume
+        9 => {
+            for thread in state.threads.values_mut() {
+                thread.suspend_count = thread.suspend_count.saturating_sub(1);
+            }
+        }
+        // Exit
+        10 => state.exit_status = Some(r.i32()?),
+        // CreateString
+        11 => {
+            let id = state.string_id(&r.string()?);
+            w.id(id);
+        }
+        // Capabilities, of which only getting bytecodes is supported
+        12 => {
+            for capability in 0..7 {
+                w.bool(capability == 2);
+            }
+        }
+        // ClassPaths
+        13 => {
+            w.string(&vm.property("user.dir").unwrap_or_default());
+            let class_path = vm.property("java.class.path").unwrap_or_default();
+            let paths = std::env::split_paths(&class_path).collect::<Vec<_>>();
+            w.count(paths.len());
+            for path in paths {
+                w.string(&path.to_string_lossy());
+            }
+            w.count(0);
+        }
+        // DisposeObjects, HoldEvents and ReleaseEvents, which have nothing to do since objects
+        // are never freed and events are sent as they happen
+        14..=16 => {}
+        // CapabilitiesNew, of which getting bytecodes and VM death events are supported
+        17 => {
+            for capability in 0..32 {
+                w.bool(matches!(capability, 2 | 13));
+            }
+        }
+        _ => return Err(ErrorCode::NotImplemented),
+    }
+
+    Ok(())
+}
+
+fn reference_type(
+    vm: &Vm,
+    state: &mut State,
+    command: u8,
+    r: &mut Reader,
+    w: &mut Writer,
+) -> Result<(), ErrorCode> {
+    let class = match ref_type(vm, state, r.id()?)? {
+        RefType::Class(class) => class,
+        RefType::Array(signature) => return array_type(vm, state, command, &signature, w),
+    };
+
+    match command {
+        // Signature and SignatureWithGeneric
+        1 | 13 => {
+            w.string(&format!("L{};", class.name()));
+            if command == 13 {
+                w.string(class.signature().unwrap_or_default());
+            }
+        }
+        // ClassLoader
+        2 => {
+            let loader = vm.defining_loader(class) as u64;
+            if loader != 0 {
+                state.objects.insert(loader);
+            }
+            w.id(loader);
+        }
+        // Modifiers
+        3 => w.i32(i32::from(class.access_flags().bits())),
+        // Fields and FieldsWithGeneric
+        4 | 14 => {
+            let fields = &class.class_file().fields;
+            w.count(fields.len());
+            for (slot, info) in fields.iter().enumerate() {
+                w.id(info as *const _ as u64);
+                w.string(utf8(class, info.name_index));
+                w.string(utf8(class, info.descriptor_index));
+                if command == 14 {
+                    w.string(class.declared_field_signature(slot).unwrap_or_default());
+                }
+                w.i32(i32::from(info.access_flags.bits()));
+            }
+        }
+        // Methods and MethodsWithGeneric
+        5 | 15 => {
+            let methods = class.declared_methods().collect::<Vec<_>>();
+            w.count(methods.len());
+            for (name, descriptor, method) in methods {
+                w.id(method as *const _ as u64);
+                w.string(name);
+                w.string(descriptor);
+                if command == 15 {
+                    w.string(
+                        class
+                            .declared_method_signature(method.slot)
+                            .unwrap_or_default(),
+                    );
+                }
+                w.i32(i32::from(method.access_flags.bits()));
+            }
+        }
+        // GetValues
+        6 => {
+            let count = r.i32()?;
+            w.i32(count);
+            for _ in 0..count {
+                let (class, name, descriptor) = field(class, r.id()?)?;
+                let value = class
+                    .static_field(name, descriptor)
+                    .ok_or(ErrorCode::InvalidFieldId)?
+                    .lock()
+                    .unwrap()
+                    .clone();
+                state.write_value(w, Some(&Value::from(&value)), descriptor.as_bytes()[0]);
+            }
+        }
+        // SourceFile
+        7 => {
+            let source_file = class.source_file().ok_or(ErrorCode::AbsentInformation)?;
+            w.string(source_file);
+        }
+        // NestedTypes, which aren't tracked
+        8 => w.count(0),
+        // Status
+        9 => w.i32(class_status(vm, class)),
+        // Interfaces
+        10 => {
+            let interfaces = class
+                .interfaces()
+                .filter_map(|name| vm.find_class(name))
+                .collect::<Vec<_>>();
+            w.count(interfaces.len());
+            for interface in interfaces {
+                w.id(interface as *const _ as u64);
+            }
+        }
+        // ClassObject
+        11 => write_class_object(vm, state, class.name(), w)?,
+        // SourceDebugExtension
+        12 => return Err(ErrorCode::AbsentInformation),
+        _ => return Err(ErrorCode::NotImplemented),
+    }
+
+    Ok(())
+}
+
+/// Handles the reference type commands for array types, which have no members of their own.
+fn array_type(
+    vm: &Vm,
+    state: &mut State,
+    command: u8,
+    signature: &str,
+    w: &mut Writer,
+) -> Result<(), ErrorCode> {
+    match command {
+        1 | 13 => {
+            w.string(signature);
+            if command == 13 {
+                w.string("");
+            }
+        }
+        2 => w.id(0),
+        // Public, final and abstract, like HotSpot
+        3 => w.i32(0x0411),
+        4 | 5 | 8 | 10 | 14 | 15 => w.count(0),
+        9 => w.i32(CLASS_STATUS_INITIALIZED),
+        11 => write_class_object(vm, state, signature, w)?,
+        7 | 12 => return Err(ErrorCode::AbsentInformation),
+        _ => return Err(ErrorCode::NotImplemented),
+    }
+
+    Ok(())
+}
+
+fn class_type(
+    vm: &Vm,
+    state: &mut State,
+    command: u8,
+    r: &mut Reader,
+    w: &mut Writer,
+) -> Result<(), ErrorCode> {
+    let id = r.id()?;
+    match command {
+        // Superclass
+        1 => match ref_type(vm, state, id)? {
+            RefType::Class(class) => {
+                w.id(class
+                    .super_class()
+                    .map_or(0, |class| class as *const _ as u64));
+            }
+            RefType::Array(_) => {
+                let object = vm.find_class("java/lang/Object");
+                w.id(object.map_or(0, |class| class as *const _ as u64));
+            }
+        },
+        _ => return Err(ErrorCode::NotImplemented),
+    }
+
+    Ok(())
+}
+
+fn method(
+    vm: &Vm,
+    state: &mut State,
+    command: u8,
+    r: &mut Reader,
+    w: &mut Writer,
+) -> Result<(), ErrorCode> {
+    let RefType::Class(class) = ref_type(vm, state, r.id()?)? else {
+        return Err(ErrorCode::InvalidMethodId);
+    };
+    let method = method_by_id(class, r.id()?)?;
+
+    match command {
+        // LineTable
+        1 => {
+            let Some(body) = &method.method.body else {
+                // Native methods have no code, so their locations are -1
+                w.i64(-1);
+                w.i64(-1);
+                w.count(0);
+                return Ok(());
+            };
+
+            w.i64(0);
+            w.i64(i64::from(body.offsets.last().copied().unwrap_or_default()));
+
+            let lines = class
+                .line_number_table(method.method.slot)
+                .map_or(&[][..], |table| &table.line_number_table[..]);
+            w.count(lines.len());
+            for entry in lines {
+                w.i64(i64::from(entry.start_pc));
+                w.i32(i32::from(entry.line_number));
+            }
+        }
+        // VariableTable and VariableTableWithGeneric
+        2 | 5 => {
+            let variables = local_variable_table(class, method.method)?;
+
+            let receiver = !method
+                .method
+                .access_flags
+                .contains(MethodAccessFlags::STATIC);
+            w.count(method.method.descriptor.arg_slots + receiver as usize);
+            w.count(variables.len());
+            for variable in variables {
+                w.i64(i64::from(variable.start_pc));
+                w.string(utf8(class, variable.name_index));
+                w.string(utf8(class, variable.descriptor_index));
+                if command == 5 {
+                    w.string("");
+                }
+                w.i32(i32::from(variable.length));
+                w.i32(i32::from(variable.index));
+            }
+        }
+        // Bytecodes
+        3 => {
+            let info = &class.class_file().methods[method.method.slot];
+            let code = info
+                .attributes
+                .iter()
+                .find_map(|attribute| attribute.try_as_code_ref())
+                .map_or(&[][..], |code| code.code);
+            w.count(code.len());
+            for byte in code {
+                w.u8(*byte);
+            }
+        }
+        // IsObsolete, which is never true since classes can't be redefined
+        4 => w.bool(false),
+        _ => return Err(ErrorCode::NotImplemented),
+    }
+
+    Ok(())
+}
+
+fn object_reference(
+    vm: &Vm,
+    state: &mut State,
+    command: u8,
+    r: &mut Reader,
+    w: &mut Writer,
+) -> Result<(), ErrorCode> {
+    let id = r.id()?;
+
+    // Strings, threads and thread groups aren't objects in the vm, so they only have a type
+    let class_name = match id & ID_KIND_MASK {
+        STRING_IDS => state.string(id).map(|_| "java/lang/String"),
+        THREAD_IDS => state.thread_by_id(id).map(|_| "java/lang/Thread"),
+        THREAD_GROUP_ID => Ok("java/lang/ThreadGroup"),
+        _ => Err(ErrorCode::InvalidObject),
+    };
+    if let Ok(class_name) = class_name {
+        let class = vm
+            .find_class(class_name)
+            .or_else(|| vm.find_class("java/lang/Object"))
+            .ok_or(ErrorCode::InvalidObject)?;
+        return match command {
+            1 => {
+                write_class(w, class);
+                Ok(())
+            }
+            7 | 8 => Ok(()),
+            9 => {
+                w.bool(false);
+                Ok(())
+            }
+            _ => Err(ErrorCode::NotImplemented),
+        };
+    }
+
+    let header = state.object(id)?;
+    match command {
+        // ReferenceType
+        1 => match header {
+            RefTypeHeader::Object(ObjectHeader { class }) => {
+                // SAFETY: Classes live as long as the vm's arena
+                let class = unsafe { class.as_ref() };
+                w.u8(type_tag(class));
+                w.id(class as *const _ as u64);
+            }
+            RefTypeHeader::Array(array) => {
+                let signature = array_signature(array.element_type);
+                w.u8(TYPE_TAG_ARRAY);
+                w.id(state.array_type_id(signature));
+            }
+        },
+        // GetValues
+        2 => {
+            let RefTypeHeader::Object(ObjectHeader { class }) = header else {
+                return Err(ErrorCode::InvalidObject);
+            };
+            let class = ref_type(vm, state, class.as_ptr() as u64)?;
+            let RefType::Class(class) = class else {
+                return Err(ErrorCode::InvalidObject);
+            };
+
+            let count = r.i32()?;
+            w.i32(count);
+            for _ in 0..count {
+                let (declaring_class, name, descriptor) = field(class, r.id()?)?;
+                let value = match declaring_class.static_field(name, descriptor) {
+                    Some(value) => value.lock().unwrap().clone(),
+                    None => vm
+                        .get_field(id as usize, name, descriptor)
+                        .map_err(|_| ErrorCode::InvalidFieldId)?,
+                };
+                state.write_value(w, Some(&Value::from(&value)), descriptor.as_bytes()[0]);
+            }
+        }
+        // DisableCollection and EnableCollection, which have nothing to do since objects are
+        // never freed
+        7 | 8 => {}
+        // IsCollected
+        9 => w.bool(false),
+        _ => return Err(ErrorCode::NotImplemented),
+    }
+
+    Ok(())
+}
+
+fn thread_reference(
+    vm: &Vm,
+    state: &mut State,
+    command: u8,
+    r: &mut Reader,
+    w: &mut Writer,
+) -> Result<(), ErrorCode> {
+    let thread = state.thread_by_id(r.id()?)?;
+    match command {
+        // Name
+        1 => w.string(&thread.name),
+        // Suspend
+        2 => thread.suspend_count += 1,
+        // Resume
+        3 => thread.suspend_count = thread.suspend_count.saturating_sub(1),
+        // Status
+        4 => {
+            w.i32(THREAD_STATUS_RUNNING);
+            w.i32((thread.suspend_count > 0) as i32);
+        }
+        // ThreadGroup
+        5 => w.id(THREAD_GROUP_ID),
+        // Frames
+        6 => {
+            if thread.suspend_count == 0 {
+                return Err(ErrorCode::ThreadNotSuspended);
+            }
+
+            let start = usize::try_from(r.i32()?).map_err(|_| ErrorCode::IllegalArgument)?;
+            let length = r.i32()?;
+            let available = thread.frames.len().saturating_sub(start);
+            let length = match length {
+                -1 => available,
+                length => usize::try_from(length)
+                    .ok()
+                    .filter(|length| *length <= available)
+                    .ok_or(ErrorCode::IllegalArgument)?,
+            };
+
+            let number = thread.number;
+            let frames = &thread.frames;
+            w.count(length);
+            // Frames are numbered from the outermost, but listed from the innermost
+            for depth in (0..frames.len()).rev().skip(start).take(length) {
+                w.id(number << 32 | depth as u64);
+                w.location(&frame_location(vm, &frames[depth])?);
+            }
+        }
+        // FrameCount
+        7 => {
+            if thread.suspend_count == 0 {
+                return Err(ErrorCode::ThreadNotSuspended);
+            }
+            w.count(thread.frames.len());
+        }
+        // SuspendCount
+        12 => w.i32(thread.suspend_count as i32),
+        _ => return Err(ErrorCode::NotImplemented),
+    }
+
+    Ok(())
+}
+
+fn thread_group_reference(
+    state: &mut State,
+    command: u8,
+    r: &mut Reader,
+    w: &mut Writer,
+) -> Result<(), ErrorCode> {
+    if r.id()? != THREAD_GROUP_ID {
+        return Err(ErrorCode::InvalidThreadGroup);
+    }
+
+    match command {
+        // Name
+        1 => w.string("main"),
+        // Parent
+        2 => w.id(0),
+        // Children
+        3 => {
+            let mut threads = state
+                .threads
+                .values()
+                .map(|thread| thread.number)
+                .collect::<Vec<_>>();
+            threads.sort();
+            w.count(threads.len());
+            for number in threads {
+                w.id(THREAD_IDS | number);
+            }
+            w.count(0);
+        }
+        _ => return Err(ErrorCode::NotImplemented),
+    }
+
+    Ok(())
+}
+
+fn array_reference(
+    state: &mut State,
+    command: u8,
+    r: &mut Reader,
+    w: &mut Writer,
+) -> Result<(), ErrorCode> {
+    let header = state.object(r.id()?)?;
+    let (element_type, length) = match header {
+        RefTypeHeader::Array(array) => (array.element_type, array.length),
+        RefTypeHeader::Object(_) => return Err(ErrorCode::InvalidArray),
+    };
+
+    match command {
+        // Length
+        1 => w.count(length),
+        // GetValues
+        2 => {
+            let first = usize::try_from(r.i32()?).map_err(|_| ErrorCode::InvalidIndex)?;
+            let count = usize::try_from(r.i32()?).map_err(|_| ErrorCode::InvalidLength)?;
+            let end = first
+                .checked_add(count)
+                .filter(|end| *end <= length)
+                .ok_or(ErrorCode::InvalidLength)?;
+
+            match element_type {
+                ArrayElementType::Primitive(t) => {
+                    w.u8(t.descriptor() as u8);
+                    w.count(count);
+                    // SAFETY: The array's elements have the layout of its element type
+                    unsafe { write_primitives(header, t, first..end, w) }
+                        .ok_or(ErrorCode::InvalidArray)?;
+                }
+                ArrayElementType::Reference => {
+                    // SAFETY: Arrays of references store `JvmValue`s
+                    let elements = unsafe { header.array_data::<JvmValue>() }
+                        .map_err(|_| ErrorCode::InvalidArray)?;
+                    w.u8(b'L');
+                    w.count(count);
+                    for element in &elements[first..end] {
+                        state.write_value(w, Some(&Value::from(element)), b'L');
+                    }
+                }
+            }
+        }
+        _ => return Err(ErrorCode::NotImplemented),
+    }
+
+    Ok(())
+}
+
+/// Writes a range of the elements of a primitive array, without tags.
+unsafe fn write_primitives(
+    header: &mut RefTypeHeader,
+    element_type: ArrayType,
+    range: std::ops::Range<usize>,
+    w: &mut Writer,
+) -> Option<()> {
+    match element_type {
+        ArrayType::Boolean => {
+            for v in &header.array_data::<i8>().ok()?[range] {
+                w.bool(*v != 0);
+            }
+        }
+        ArrayType::Byte => {
+            for v in &header.array_data::<i8>().ok()?[range] {
+                w.u8(*v as u8);
+            }
+        }
+        ArrayType::Char => {
+            for v in &header.array_data::<u16>().ok()?[range] {
+                w.i16(*v as i16);
+            }
+        }
+        ArrayType::Short => {
+            for v in &header.array_data::<i16>().ok()?[range] {
+                w.i16(*v);
+            }
+        }
+        ArrayType::Int => {
+            for v in &header.array_data::<i32>().ok()?[range] {
+                w.i32(*v);
+            }
+        }
+        ArrayType::Long => {
+            for v in &header.array_data::<i64>().ok()?[range] {
+                w.i64(*v);
+            }
+        }
+        ArrayType::Float => {
+            for v in &header.array_data::<f32>().ok()?[range] {
+                w.i32(v.to_bits() as i32);
+            }
+        }
+        ArrayType::Double => {
+            for v in &header.array_data::<f64>().ok()?[range] {
+                w.i64(v.to_bits() as i64);
+            }
+        }
+    }
+    Some(())
+}
+
+fn event_request(
+    vm: &Vm,
+    state: &mut State,
+    command: u8,
+    r: &mut Reader,
+    w: &mut Writer,
+) -> Result<(), ErrorCode> {
+    match command {
+        // Set
+        1 => {
+            let kind = r.u8()?;
+            let suspend_policy = r.u8()?;
+            let count = r.i32()?;
+
+            let mut modifiers = Vec::new();
+            for _ in 0..count {
+                let modifier = match r.u8()? {
+                    1 => Modifier::Count(r.i32()?),
+                    // Conditional
+                    2 => {
+                        r.i32()?;
+                        Modifier::Ignored
+                    }
+                    3 => Modifier::ThreadOnly(r.id()?),
+                    4 => Modifier::ClassOnly(r.id()?),
+                    5 => Modifier::ClassMatch(r.string()?),
+                    6 => Modifier::ClassExclude(r.string()?),
+                    7 => Modifier::LocationOnly(r.location()?),
+                    // ExceptionOnly
+                    8 => {
+                        r.id()?;
+                        r.bool()?;
+                        r.bool()?;
+                        Modifier::Ignored
+                    }
+                    // FieldOnly
+                    9 => {
+                        r.id()?;
+                        r.id()?;
+                        Modifier::Ignored
+                    }
+                    10 => {
+                        let thread = r.id()?;
+                        let size = r.i32()?;
+                        let depth = r.i32()?;
+                        Modifier::Step(step(vm, state, thread, size, depth)?)
+                    }
+                    // InstanceOnly
+                    11 => {
+                        r.id()?;
+                        Modifier::Ignored
+                    }
+                    // SourceNameMatch
+                    12 => {
+                        r.string()?;
+                        Modifier::Ignored
+                    }
+                    _ => return Err(ErrorCode::IllegalArgument),
+                };
+                modifiers.push(modifier);
+            }
+
+            let id = state.next_request_id;
+            state.next_request_id += 1;
+            state.requests.push(EventRequest {
+                id,
+                kind,
+                suspend_policy,
+                modifiers,
+            });
+            w.i32(id);
+        }
+        // Clear
+        2 => {
+            let kind = r.u8()?;
+            let id = r.i32()?;
+            state
+                .requests
+                .retain(|request| request.kind != kind || request.id != id);
+        }
+        // ClearAllBreakpoints
+        3 => state
+            .requests
+            .retain(|request| request.kind != super::EVENT_BREAKPOINT),
+        _ => return Err(ErrorCode::NotImplemented),
+    }
+
+    Ok(())
+}
+
+/// Creates a step for a suspended thread, starting from where it's suspended.
+fn step(vm: &Vm, state: &mut State, thread: u64, size: i32, depth: i32) -> Result<Step, ErrorCode> {
+    let debug_thread = state.thread_by_id(thread)?;
+    if debug_thread.suspend_count == 0 {
+        return Err(ErrorCode::ThreadNotSuspended);
+    }
+
+    let start_depth = debug_thread.frames.len();
+    let (start_line, start_offset) = match debug_thread.frames.last() {
+        Some(frame) => {
+            let method = frame_method(vm, frame)?;
+            match &method.method.body {
+                Some(body) => {
+                    let offset = body.offsets[frame.pc];
+                    (line_at(method, offset).0, offset)
+                }
+                None => (None, 0),
+            }
+        }
+        None => (None, 0),
+    };
+
+    Ok(Step {
+        thread,
+        size,
+        depth,
+        start_depth,
+        start_line,
+        start_offset,
+    })
+}
+
+fn stack_frame(
+    vm: &Vm,
+    state: &mut State,
+    command: u8,
+    r: &mut Reader,
+    w: &mut Writer,
+) -> Result<(), ErrorCode> {
+    let thread = r.id()?;
+    let frame_id = r.id()?;
+
+    let debug_thread = state.thread_by_id(thread)?;
+    if debug_thread.suspend_count == 0 {
+        return Err(ErrorCode::ThreadNotSuspended);
+    }
+    if frame_id >> 32 != debug_thread.number {
+        return Err(ErrorCode::InvalidFrameId);
+    }
+    let frame = debug_thread
+        .frames
+        .get((frame_id & 0xffff_ffff) as usize)
+        .ok_or(ErrorCode::InvalidFrameId)?;
+
+    match command {
+        // GetValues
+        1 => {
+            let locals = frame.locals.clone();
+            let count = r.i32()?;
+            w.i32(count);
+            for _ in 0..count {
+                let slot = usize::try_from(r.i32()?).map_err(|_| ErrorCode::InvalidSlot)?;
+                let signature = r.u8()?;
+                let value = locals.get(slot).ok_or(ErrorCode::InvalidSlot)?;
+                state.write_value(w, value.as_ref(), signature);
+            }
+        }
+        // ThisObject
+        3 => {
+            let method = frame_method(vm, frame)?;
+            let this = if method
+                .method
+                .access_flags
+                .contains(MethodAccessFlags::STATIC)
+            {
+                None
+            } else {
+                frame.locals.first().cloned().flatten()
+            };
+            state.write_value(w, this.as_ref(), b'L');
+        }
+        _ => return Err(ErrorCode::NotImplemented),
+    }
+
+    Ok(())
+}
+
+/// A class or an array type.
+enum RefType<'a> {
+    Class(&'a Class<'a>),
+    Array(String),
+}
+
+fn ref_type<'a>(vm: &Vm<'a>, state: &State, id: u64) -> Result<RefType<'a>, ErrorCode> {
+    if id & ID_KIND_MASK == ARRAY_TYPE_IDS {
+        return state
+            .array_types
+            .get((id & !ID_KIND_MASK) as usize)
+            .map(|signature| RefType::Array(signature.clone()))
+            .ok_or(ErrorCode::InvalidClass);
+    }
+
+    // Only the addresses of loaded classes are accepted, so that others aren't dereferenced
+    vm.loaded_classes()
+        .into_iter()
+        .find(|class| *class as *const _ as u64 == id)
+        .map(RefType::Class)
+        .ok_or(ErrorCode::InvalidClass)
+}
+
+fn ref_type_by_name<'a>(
+    vm: &Vm<'a>,
+    state: &mut State,
+    name: &str,
+) -> Result<RefType<'a>, ErrorCode> {
+    if name.starts_with('[') {
+        let id = state.array_type_id(name.to_owned());
+        return ref_type(vm, state, id);
+    }
+    vm.find_class(name)
+        .map(RefType::Class)
+        .ok_or(ErrorCode::InvalidClass)
+}
+
+fn method_by_id<'a>(class: &'a Class<'a>, id: u64) -> Result<MethodRef<'a>, ErrorCode> {
+    class
+        .declared_methods()
+        .find(|(_, _, method)| *method as *const _ as u64 == id)
+        .map(|(_, _, method)| MethodRef { class, method })
+        .ok_or(ErrorCode::InvalidMethodId)
+}
+
+fn frame_method<'a>(vm: &Vm<'a>, frame: &Frame) -> Result<MethodRef<'a>, ErrorCode> {
+    let class = vm
+        .loaded_classes()
+        .into_iter()
+        .find(|class| *class as *const _ as usize == frame.class)
+        .ok_or(ErrorCode::InvalidClass)?;
+    method_by_id(class, frame.method as u64)
+}
+
+fn frame_location(vm: &Vm, frame: &Frame) -> Result<super::packet::Location, ErrorCode> {
+    let method = frame_method(vm, frame)?;
+    let offset = method
+        .method
+        .body
+        .as_ref()
+        .map(|body| body.offsets[frame.pc]);
+    Ok(location(method.class, method.method, offset))
+}
+
+/// Finds a field of a class or its superclasses, returning the class which declares it along
+/// with its name and descriptor.
+fn field<'a>(
+    class: &'a Class<'a>,
+    id: u64,
+) -> Result<(&'a Class<'a>, &'a str, &'a str), ErrorCode> {
+    let mut class = Some(class);
+    while let Some(current) = class {
+        for info in &current.class_file().fields {
+            if info as *const _ as u64 == id {
+                let name = utf8(current, info.name_index);
+                let descriptor = utf8(current, info.descriptor_index);
+                if descriptor.is_empty() {
+                    return Err(ErrorCode::InvalidFieldId);
+                }
+                return Ok((current, name, descriptor));
+            }
+        }
+        class = current.super_class();
+    }
+    Err(ErrorCode::InvalidFieldId)
+}
+
+/// An entry of a method's `LocalVariableTable` attribute, which the class file parser leaves
+/// unparsed.
+struct LocalVariable {
+    start_pc: u16,
+    length: u16,
+    name_index: u16,
+    descriptor_index: u16,
+    index: u16,
+}
+
+fn local_variable_table<'a>(
+    class: &'a Class<'a>,
+    method: &Method,
+) -> Result<Vec<LocalVariable>, ErrorCode> {
+    let info = &class.class_file().methods[method.slot];
+    let code = info
+        .attributes
+        .iter()
+        .find_map(|attribute| attribute.try_as_code_ref())
+        .ok_or(ErrorCode::AbsentInformation)?;
+
+    let table = code
+        .attributes
+        .iter()
+        .find_map(|attribute| match attribute {
+            AttributeInfo::Custom(custom)
+                if utf8(class, custom.attribute_name_index) == "LocalVariableTable" =>
+            {
+                Some(custom.info)
+            }
+            _ => None,
+        })
+        .ok_or(ErrorCode::AbsentInformation)?;
+
+    let u16_at = |offset: usize| {
+        table
+            .get(offset..offset + 2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+            .ok_or(ErrorCode::AbsentInformation)
+    };
+
+    let count = u16_at(0)?;
+    (0..usize::from(count))
+        .map(|i| {
+            let offset = 2 + i * 10;
+            Ok(LocalVariable {
+                start_pc: u16_at(offset)?,
+                length: u16_at(offset + 2)?,
+                name_index: u16_at(offset + 4)?,
+                descriptor_index: u16_at(offset + 6)?,
+                index: u16_at(offset + 8)?,
+            })
+        })
+        .collect()
+}
+
+fn write_class(w: &mut Writer, class: &Class) {
+    w.u8(type_tag(class));
+    w.id(class as *const _ as u64);
+}
+
+fn write_class_object(
+    vm: &Vm,
+    state: &mut State,
+    name: &str,
+    w: &mut Writer,
+) -> Result<(), ErrorCode> {
+    let mirror = vm.class_mirror(name).map_err(|_| ErrorCode::InvalidClass)? as u64;
+    state.objects.insert(mirror);
+    w.id(mirror);
+    Ok(())
+}
+
+fn class_status(vm: &Vm, class: &Class) -> i32 {
+    if vm.is_initialized(class) {
+        CLASS_STATUS_INITIALIZED
+    } else {
+        CLASS_STATUS_PREPARED
+    }
+}
+
+/// Returns a string from a class's constant pool, or an empty string if the index is invalid.
+fn utf8<'a>(class: &'a Class<'a>, index: u16) -> &'a str {
+    class
+        .constant_pool()
+        .get(index)
+        .and_then(|constant| constant.try_as_utf_8_ref())
+        .copied()
+        .unwrap_or_default()
+}
@@ -0,0 +1,229 @@
+//! The wire format of JDWP packets, which are big-endian.
+
+use std::io::{self, Read, Write};
+
+use byteorder::{BigEndian, ReadBytesExt};
+
+/// Sent by the debugger once it connects, and echoed back by the vm.
+pub(crate) const HANDSHAKE: &[u8] = b"JDWP-Handshake";
+
+const HEADER_LENGTH: u32 = 11;
+const REPLY_FLAG: u8 = 0x80;
+
+/// A command sent by the debugger.
+#[derive(Debug)]
+pub(crate) struct Command {
+    pub id: u32,
+    pub command_set: u8,
+    pub command: u8,
+    pub data: Vec<u8>,
+}
+
+/// Reads the next command from the debugger. Replies are skipped, since the only commands the vm
+/// sends are events, which the debugger doesn't reply to.
+pub(crate) fn read_command(r: &mut impl Read) -> io::Result<Command> {
+    loop {
+        let length = r.read_u32::<BigEndian>()?;
+        if length < HEADER_LENGTH {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("packet length {length} is shorter than its header"),
+            ));
+        }
+
+        let id = r.read_u32::<BigEndian>()?;
+        let flags = r.read_u8()?;
+        let command_set = r.read_u8()?;
+        let command = r.read_u8()?;
+
+        let mut data = vec![0; (length - HEADER_LENGTH) as usize];
+        r.read_exact(&mut data)?;
+
+        if flags & REPLY_FLAG == 0 {
+            return Ok(Command {
+                id,
+                command_set,
+                command,
+                data,
+            });
+        }
+    }
+}
+
+pub(crate) fn write_reply(
+    w: &mut impl Write,
+    id: u32,
+    result: Result<&[u8], ErrorCode>,
+) -> io::Result<()> {
+    let (error, data) = match result {
+        Ok(data) => (0, data),
+        Err(error) => (error as u16, &[][..]),
+    };
+
+    let mut packet = Vec::with_capacity(HEADER_LENGTH as usize + data.len());
+    packet.extend_from_slice(&(HEADER_LENGTH + data.len() as u32).to_be_bytes());
+    packet.extend_from_slice(&id.to_be_bytes());
+    packet.push(REPLY_FLAG);
+    packet.extend_from_slice(&error.to_be_bytes());
+    packet.extend_from_slice(data);
+    w.write_all(&packet)
+}
+
+pub(crate) fn write_command(
+    w: &mut impl Write,
+    id: u32,
+    command_set: u8,
+    command: u8,
+    data: &[u8],
+) -> io::Result<()> {
+    let mut packet = Vec::with_capacity(HEADER_LENGTH as usize + data.len());
+    packet.extend_from_slice(&(HEADER_LENGTH + data.len() as u32).to_be_bytes());
+    packet.extend_from_slice(&id.to_be_bytes());
+    packet.push(0);
+    packet.push(command_set);
+    packet.push(command);
+    packet.extend_from_slice(data);
+    w.write_all(&packet)
+}
+
+/// The errors which the vm replies to commands with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u16)]
+pub(crate) enum ErrorCode {
+    InvalidThread = 10,
+    InvalidThreadGroup = 11,
+    ThreadNotSuspended = 13,
+    InvalidObject = 20,
+    InvalidClass = 21,
+    InvalidMethodId = 23,
+    InvalidFieldId = 25,
+    InvalidFrameId = 30,
+    InvalidSlot = 35,
+    NotImplemented = 99,
+    AbsentInformation = 101,
+    IllegalArgument = 103,
+    InvalidIndex = 503,
+    InvalidLength = 504,
+    InvalidArray = 508,
+}
+
+/// Reads the fields of a command's data.
+pub(crate) struct Reader<'d> {
+    data: &'d [u8],
+}
+
+impl<'d> Reader<'d> {
+    pub fn new(data: &'d [u8]) -> Self {
+        Reader { data }
+    }
+
+    fn bytes<const N: usize>(&mut self) -> Result<[u8; N], ErrorCode> {
+        if self.data.len() < N {
+            return Err(ErrorCode::IllegalArgument);
+        }
+        let (bytes, rest) = self.data.split_at(N);
+        self.data = rest;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    pub fn u8(&mut self) -> Result<u8, ErrorCode> {
+        Ok(u8::from_be_bytes(self.bytes()?))
+    }
+
+    pub fn bool(&mut self) -> Result<bool, ErrorCode> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn i32(&mut self) -> Result<i32, ErrorCode> {
+        Ok(i32::from_be_bytes(self.bytes()?))
+    }
+
+    /// Reads an id, which are all 8 bytes.
+    pub fn id(&mut self) -> Result<u64, ErrorCode> {
+        Ok(u64::from_be_bytes(self.bytes()?))
+    }
+
+    pub fn string(&mut self) -> Result<String, ErrorCode> {
+        let length = usize::try_from(self.i32()?).map_err(|_| ErrorCode::IllegalArgument)?;
+        if self.data.len() < length {
+            return Err(ErrorCode::IllegalArgument);
+        }
+        let (bytes, rest) = self.data.split_at(length);
+        self.data = rest;
+        // Java's modified UTF-8 only differs for nulls and supplementary characters
+        Ok(String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    pub fn location(&mut self) -> Result<Location, ErrorCode> {
+        Ok(Location {
+            type_tag: self.u8()?,
+            class: self.id()?,
+            method: self.id()?,
+            index: self.id()?,
+        })
+    }
+}
+
+/// Builds the data of a reply or event.
+#[derive(Default)]
+pub(crate) struct Writer(Vec<u8>);
+
+impl Writer {
+    pub fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.0.push(value as u8);
+    }
+
+    pub fn i16(&mut self, value: i16) {
+        self.0.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn i32(&mut self, value: i32) {
+        self.0.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn i64(&mut self, value: i64) {
+        self.0.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn id(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_be_bytes());
+    }
+
+    /// Writes a count, which JDWP always sends as an int.
+    pub fn count(&mut self, count: usize) {
+        self.i32(count as i32);
+    }
+
+    pub fn string(&mut self, value: &str) {
+        self.count(value.len());
+        self.0.extend_from_slice(value.as_bytes());
+    }
+
+    pub fn location(&mut self, location: &Location) {
+        self.u8(location.type_tag);
+        self.id(location.class);
+        self.id(location.method);
+        self.id(location.index);
+    }
+
+    pub fn extend(&mut self, other: Writer) {
+        self.0.extend(other.0);
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+/// An instruction of a method, where `index` is its bytecode offset.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Location {
+    pub type_tag: u8,
+    pub class: u64,
+    pub method: u64,
+    pub index: u64,
+}
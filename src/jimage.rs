@@ -0,0 +1,364 @@
+//! A reader for jimage files, the format of `lib/modules` in a JDK, which contains the classes of
+//! all of the JDK's modules.
+//!
+//! The file starts with an index, which is a hash table mapping resource names like
+//! `/java.base/java/lang/Object.class` to their locations in the rest of the file. The file is
+//! memory mapped, and resources are borrowed from the mapping rather than read.
+
+use std::env;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use memmap2::Mmap;
+
+use crate::error::{bail, format_err, Context, Result};
+
+const MAGIC: u32 = 0xCAFEDADA;
+const MAJOR_VERSION: u32 = 1;
+const HEADER_SIZE: usize = 7 * 4;
+const HASH_MULTIPLIER: u32 = 0x01000193;
+
+// Kinds of location attributes
+const ATTRIBUTE_END: u8 = 0;
+const ATTRIBUTE_MODULE: usize = 1;
+const ATTRIBUTE_PARENT: usize = 2;
+const ATTRIBUTE_BASE: usize = 3;
+const ATTRIBUTE_EXTENSION: usize = 4;
+const ATTRIBUTE_OFFSET: usize = 5;
+const ATTRIBUTE_COMPRESSED: usize = 6;
+const ATTRIBUTE_UNCOMPRESSED: usize = 7;
+const ATTRIBUTE_COUNT: usize = 8;
+
+pub struct JImage {
+    data: Mmap,
+    /// The size of the header and the index, which are followed by the resources.
+    index_size: usize,
+    /// The index is written in the byte order of the platform that created it.
+    big_endian: bool,
+    table_length: usize,
+    locations_size: usize,
+}
+
+impl JImage {
+    /// Opens the jimage of the JDK at `java_home`.
+    pub fn open_java_home(java_home: impl AsRef<Path>) -> Result<JImage> {
+        JImage::open(jimage_path(java_home))
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<JImage> {
+        let path = path.as_ref();
+        let file = File::open(path).wrap_err_with(|| format_err!("failed to open {path:?}"))?;
+        // SAFETY: a JDK's jimage isn't changed in place while it's installed
+        let data =
+            unsafe { Mmap::map(&file) }.wrap_err_with(|| format_err!("failed to map {path:?}"))?;
+
+        let Some(header) = data.get(..HEADER_SIZE) else {
+            bail!("{path:?} is too short to be a jimage file");
+        };
+
+        let big_endian = match header[..4].try_into().unwrap() {
+            magic if u32::from_le_bytes(magic) == MAGIC => false,
+            magic if u32::from_be_bytes(magic) == MAGIC => true,
+            _ => bail!("{path:?} is not a jimage file"),
+        };
+
+        let field = |i: usize| {
+            let bytes = header[i * 4..i * 4 + 4].try_into().unwrap();
+            match big_endian {
+                true => u32::from_be_bytes(bytes),
+                false => u32::from_le_bytes(bytes),
+            }
+        };
+
+        let version = field(1);
+        if version >> 16 != MAJOR_VERSION {
+            bail!(
+                "unsupported jimage version {}.{} in {path:?}",
+                version >> 16,
+                version & 0xffff
+            );
+        }
+
+        let table_length = field(4) as usize;
+        let locations_size = field(5) as usize;
+        let strings_size = field(6) as usize;
+
+        let index_size = HEADER_SIZE + table_length * 8 + locations_size + strings_size;
+        if data.len() < index_size {
+            bail!("the index of {path:?} is truncated");
+        }
+
+        Ok(JImage {
+            data,
+            index_size,
+            big_endian,
+            table_length,
+            locations_size,
+        })
+    }
+
+    /// Returns the contents of a class file, given its binary name (e.g. `java/lang/Object`), or
+    /// `None` if it isn't in any module.
+    pub fn find_class(&self, class_name: &str) -> Result<Option<&[u8]>> {
+        // The JDK doesn't have any classes in the unnamed package
+        let Some((package, _)) = class_name.rsplit_once('/') else {
+            return Ok(None);
+        };
+
+        let Some(module) = self.package_module(package)? else {
+            return Ok(None);
+        };
+
+        self.find_resource(&format!("/{module}/{class_name}.class"))
+    }
+
+    /// Returns the name of the module containing a package (e.g. `java/lang`), or `None` if it
+    /// isn't in any module.
+    pub fn package_module(&self, package: &str) -> Result<Option<String>> {
+        let name = format!("/packages/{}", package.replace('/', "."));
+        let Some(content) = self.find_resource(&name)? else {
+            return Ok(None);
+        };
+
+        // A package may appear in several modules, but it's only non-empty in one of them. Each
+        // entry is a flag for whether the package is empty, and the module name.
+        for entry in content.chunks_exact(8) {
+            let is_empty = self.u32_from_bytes(entry[..4].try_into().unwrap());
+            let module = self.u32_from_bytes(entry[4..].try_into().unwrap());
+            if is_empty == 0 {
+                return Ok(Some(self.string(module as usize)?.to_owned()));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns the contents of a resource, given its full name (e.g.
+    /// `/java.base/java/lang/Object.class`), or `None` if there is no such resource.
+    pub fn find_resource(&self, name: &str) -> Result<Option<&[u8]>> {
+        let Some(location) = self.find_location(name)? else {
+            return Ok(None);
+        };
+
+        if location[ATTRIBUTE_COMPRESSED] != 0 {
+            bail!("resource {name} is compressed, which isn't supported");
+        }
+
+        let offset = self.index_size as u64 + location[ATTRIBUTE_OFFSET];
+        let content = usize::try_from(offset)
+            .ok()
+            .and_then(|offset| self.data.get(offset..))
+            .and_then(|rest| rest.get(..location[ATTRIBUTE_UNCOMPRESSED] as usize))
+            .ok_or_else(|| format_err!("resource {name} is outside of the jimage"))?;
+
+        Ok(Some(content))
+    }
+
+    /// Returns the names of every resource, e.g. `/java.base/java/lang/Object.class`, in no
+    /// particular order.
+    pub fn resource_names(&self) -> Result<Vec<String>> {
+        (0..self.table_length)
+            .map(|index| {
+                let offset = self.u32_at(HEADER_SIZE + self.table_length * 4 + index * 4)?;
+                self.full_name(&self.location(offset as usize)?)
+            })
+            .collect()
+    }
+
+    /// Returns the classes in every module, as the name of the module and the binary name of
+    /// the class, e.g. `("java.base", "java/lang/Object")`, sorted by module and then by class.
+    /// The `module-info` of each module isn't included.
+    pub fn classes(&self) -> Result<Vec<(String, String)>> {
+        let mut classes = self
+            .resource_names()?
+            .into_iter()
+            .filter_map(|name| {
+                let (module, path) = name.strip_prefix('/')?.split_once('/')?;
+                let class_name = path.strip_suffix(".class")?;
+                // Packages and modules are listed as directories too
+                if module == "packages" || module == "modules" || !class_name.contains('/') {
+                    return None;
+                }
+                Some((module.to_owned(), class_name.to_owned()))
+            })
+            .collect::<Vec<_>>();
+
+        classes.sort();
+        Ok(classes)
+    }
+
+    /// Looks up the attributes of a resource in the hash table. Collisions are resolved through a
+    /// redirect table, which either gives the index directly or a seed to rehash the name with.
+    fn find_location(&self, name: &str) -> Result<Option<[u64; ATTRIBUTE_COUNT]>> {
+        if self.table_length == 0 {
+            return Ok(None);
+        }
+
+        let index = hash(name, HASH_MULTIPLIER) as usize % self.table_length;
+        let index = match self.u32_at(HEADER_SIZE + index * 4)? as i32 {
+            0 => return Ok(None),
+            redirect if redirect < 0 => (-1 - redirect) as usize,
+            seed => hash(name, seed as u32) as usize % self.table_length,
+        };
+
+        if index >= self.table_length {
+            bail!("invalid redirect for {name} in jimage");
+        }
+
+        let offset = self.u32_at(HEADER_SIZE + self.table_length * 4 + index * 4)?;
+        let location = self.location(offset as usize)?;
+
+        // The table only stores hashes, so a different resource may be found
+        if self.full_name(&location)? != name {
+            return Ok(None);
+        }
+
+        Ok(Some(location))
+    }
+
+    /// Decodes the attributes of a location, which are stored as a sequence of a byte with the
+    /// kind and length, followed by a big endian value of that length.
+    fn location(&self, offset: usize) -> Result<[u64; ATTRIBUTE_COUNT]> {
+        let locations = HEADER_SIZE + self.table_length * 8;
+        if offset >= self.locations_size {
+            bail!("invalid location offset {offset} in jimage");
+        }
+
+        let mut attributes = [0; ATTRIBUTE_COUNT];
+        let mut bytes = self.index()[locations + offset..].iter();
+
+        loop {
+            let byte = *bytes
+                .next()
+                .ok_or_else(|| format_err!("truncated location"))?;
+            let kind = byte >> 3;
+            if kind == ATTRIBUTE_END {
+                break;
+            }
+
+            let attribute = attributes
+                .get_mut(kind as usize)
+                .ok_or_else(|| format_err!("invalid location attribute {kind}"))?;
+
+            for _ in 0..=(byte & 0x7) {
+                let byte = *bytes
+                    .next()
+                    .ok_or_else(|| format_err!("truncated location"))?;
+                *attribute = (*attribute << 8) | byte as u64;
+            }
+        }
+
+        Ok(attributes)
+    }
+
+    /// Reconstructs the full name of a resource from its location, i.e.
+    /// `/module/parent/base.extension`.
+    fn full_name(&self, location: &[u64; ATTRIBUTE_COUNT]) -> Result<String> {
+        let module = self.string(location[ATTRIBUTE_MODULE] as usize)?;
+        let parent = self.string(location[ATTRIBUTE_PARENT] as usize)?;
+        let base = self.string(location[ATTRIBUTE_BASE] as usize)?;
+        let extension = self.string(location[ATTRIBUTE_EXTENSION] as usize)?;
+
+        let mut name = String::new();
+
+        if !module.is_empty() {
+            name += "/";
+            name += module;
+            name += "/";
+        }
+
+        if !parent.is_empty() {
+            name += parent;
+            name += "/";
+        }
+
+        name += base;
+
+        if !extension.is_empty() {
+            name += ".";
+            name += extension;
+        }
+
+        Ok(name)
+    }
+
+    /// Returns a nul-terminated string from the strings table.
+    fn string(&self, offset: usize) -> Result<&str> {
+        let strings = HEADER_SIZE + self.table_length * 8 + self.locations_size;
+        let bytes = self
+            .index()
+            .get(strings + offset..)
+            .ok_or_else(|| format_err!("invalid string offset {offset} in jimage"))?;
+
+        let len = bytes
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| format_err!("unterminated string in jimage"))?;
+
+        std::str::from_utf8(&bytes[..len]).wrap_err("invalid string in jimage")
+    }
+
+    /// The header and the index, which are the start of the file.
+    fn index(&self) -> &[u8] {
+        &self.data[..self.index_size]
+    }
+
+    fn u32_at(&self, offset: usize) -> Result<u32> {
+        let bytes = self
+            .index()
+            .get(offset..offset + 4)
+            .ok_or_else(|| format_err!("invalid offset {offset} in jimage"))?;
+        Ok(self.u32_from_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn u32_from_bytes(&self, bytes: [u8; 4]) -> u32 {
+        match self.big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        }
+    }
+}
+
+/// The hash function used for the index, which is FNV-1 over the UTF-8 bytes of the name.
+fn hash(name: &str, seed: u32) -> u32 {
+    name.bytes().fold(seed, |hash, byte| {
+        hash.wrapping_mul(HASH_MULTIPLIER) ^ byte as u32
+    }) & 0x7fffffff
+}
+
+/// Returns the path to the jimage of the JDK at `java_home`.
+pub fn jimage_path(java_home: impl AsRef<Path>) -> PathBuf {
+    java_home.as_ref().join("lib").join("modules")
+}
+
+/// Returns the path to the `release` file of the JDK at `java_home`, which describes its version.
+pub fn release_path(java_home: impl AsRef<Path>) -> PathBuf {
+    java_home.as_ref().join("release")
+}
+
+/// Returns the version of the JDK at `java_home`, e.g. `17.0.15`, from its `release` file.
+pub fn java_version(java_home: impl AsRef<Path>) -> Option<String> {
+    let release = fs::read_to_string(release_path(java_home)).ok()?;
+    release.lines().find_map(|line| {
+        let version = line.strip_prefix("JAVA_VERSION=")?;
+        Some(version.trim_matches('"').to_owned())
+    })
+}
+
+/// Finds the JDK to load classes from, using `JAVA_HOME` or else the location of the `java`
+/// executable on the `PATH`.
+pub fn find_java_home() -> Option<PathBuf> {
+    if let Some(java_home) = env::var_os("JAVA_HOME").filter(|home| !home.is_empty()) {
+        return Some(PathBuf::from(java_home));
+    }
+
+    let java = if cfg!(windows) { "java.exe" } else { "java" };
+    let java = env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(java))
+        .find(|path| path.is_file())?;
+
+    // The executable is usually a symlink, e.g. /usr/bin/java, so resolve it to find the JDK
+    let java = java.canonicalize().ok()?;
+
+    Some(java.parent()?.parent()?.to_owned())
+}
@@ -0,0 +1,862 @@
+//! Compiles frequently called methods to native code with Cranelift.
+//!
+//! Methods are compiled from their [register IR](crate::ir) once they've been called enough
+//! times, which is set by [`Vm::with_jit_threshold`]. Only static methods which work entirely with ints and longs can
+//! be compiled: their parameters and return value must be ints or longs, and their code can only
+//! use integer arithmetic, branches and calls to other static methods of the same kind. Calls go
+//! back through the interpreter, so the callee may be compiled or interpreted. Methods which
+//! can't be compiled are interpreted as before.
+//!
+//! Every register of the IR is a 64-bit variable. Ints are kept sign-extended to 64 bits, which
+//! is also how they're stored in frame slots, so compiled code can read its arguments straight
+//! from the caller's slots.
+
+use std::any::Any;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Mutex, OnceLock};
+
+use bumpalo::Bump;
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::types::{I16, I32, I64, I8};
+use cranelift_codegen::ir::{
+    AbiParam, Block, FuncRef, InstBuilder, MemFlags, StackSlotData, StackSlotKind, Type, Value,
+};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, FuncId, Linkage, Module};
+
+use crate::call_frame::{JavaException, JvmValue};
+use crate::class::{Class, Method};
+use crate::class_file::constant_pool::ConstantInfo;
+use crate::class_file::MethodAccessFlags;
+use crate::descriptor::{parse_method_descriptor, BaseType, FieldType};
+use crate::error::{bail, format_err, ContextCompat, Error, Result};
+use crate::instructions::{Condition, Instruction, IntegerType, InvokeKind, NumberType};
+use crate::ir::{self, Op, Register};
+use crate::vm::Vm;
+
+/// The number of calls to a method after which it's compiled, if no other threshold is set.
+pub const DEFAULT_COMPILE_THRESHOLD: u64 = 1000;
+
+/// The compiler state kept for each method.
+#[derive(Default)]
+pub struct MethodState<'a> {
+    /// The compiled code, or `None` if the method couldn't be compiled. This is only set once the
+    /// method is hot.
+    code: OnceLock<Option<CompiledMethod<'a>>>,
+}
+
+impl fmt::Debug for MethodState<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MethodState")
+            .field("compiled", &self.code.get().map(Option::is_some))
+            .finish()
+    }
+}
+
+/// The signature of compiled methods, which take a pointer to their locals.
+type CompiledFn = unsafe extern "C" fn(*const u64, *mut Context) -> i64;
+
+pub struct CompiledMethod<'a> {
+    function: CompiledFn,
+    /// The methods called by the code, which it refers to by index.
+    calls: Vec<StaticCall<'a>>,
+    return_type: Option<ValueType>,
+}
+
+impl<'a> CompiledMethod<'a> {
+    /// Calls the compiled code with the locals of a frame.
+    ///
+    /// # Safety
+    ///
+    /// `locals` must point to the locals of a frame for the method, with its arguments stored in
+    /// the first slots.
+    pub(crate) unsafe fn call(
+        &self,
+        vm: &Vm<'a>,
+        locals: *const u64,
+    ) -> Result<Option<JvmValue<'a>>> {
+        let mut context = Context {
+            vm,
+            calls: &self.calls,
+            failure: None,
+        };
+
+        let value = unsafe { (self.function)(locals, &mut context) };
+
+        match context.failure {
+            None => Ok(self.return_type.map(|t| t.to_value(value))),
+            Some(Failure::Error(e)) => Err(e),
+            Some(Failure::Panic(payload)) => panic::resume_unwind(payload),
+        }
+    }
+}
+
+/// The state shared by compiled code and the runtime functions that it calls.
+struct Context<'a, 'b> {
+    vm: &'b Vm<'a>,
+    calls: &'b [StaticCall<'a>],
+    /// Set when a runtime function fails, after which the compiled code returns immediately.
+    failure: Option<Failure>,
+}
+
+enum Failure {
+    Error(Error),
+    /// A panic in the interpreter, which can't unwind through compiled code so it's resumed once
+    /// the compiled code returns.
+    Panic(Box<dyn Any + Send>),
+}
+
+/// A call to a static method, which is resolved the first time it's made.
+struct StaticCall<'a> {
+    /// The class which declares the method, or `None` for the class of the caller.
+    class_name: Option<&'a str>,
+    name: &'a str,
+    descriptor: &'a str,
+    params: Vec<ValueType>,
+    /// Only set once the class is initialized, so that calls made while it's being initialized
+    /// still check whether it has finished.
+    target: OnceLock<(&'a Class<'a>, &'a Method<'a>)>,
+}
+
+impl<'a> StaticCall<'a> {
+    fn invoke(&self, vm: &Vm<'a>, args: &[i64]) -> Result<i64> {
+        let (class, method) = match self.target.get() {
+            Some(target) => *target,
+            None => {
+                let class_name = self.class_name.wrap_err("missing class")?;
+                let class = vm.load_class_file(class_name)?;
+                let target = vm.find_method(class, self.name, self.descriptor)?;
+                if vm.is_initialized(class) {
+                    let _ = self.target.set(target);
+                }
+                target
+            }
+        };
+
+        let args = self.params.iter().zip(args).map(|(t, v)| t.to_value(*v));
+        let value = vm.invoke_method(class, method, self.name, self.descriptor, args)?;
+
+        Ok(match value {
+            Some(JvmValue::Int(v)) => v as i64,
+            Some(JvmValue::Long(v)) => v,
+            Some(value) => bail!("unexpected return value: {value:?}"),
+            None => 0,
+        })
+    }
+}
+
+/// The types of values that compiled code can work with.
+#[derive(Clone, Copy)]
+enum ValueType {
+    Int,
+    Long,
+}
+
+impl ValueType {
+    fn from_field_type(field_type: &FieldType) -> Result<ValueType> {
+        match field_type {
+            FieldType::Base(BaseType::Int) => Ok(ValueType::Int),
+            FieldType::Base(BaseType::Long) => Ok(ValueType::Long),
+            _ => bail!("{field_type:?} values aren't supported"),
+        }
+    }
+
+    fn to_value<'a>(self, bits: i64) -> JvmValue<'a> {
+        match self {
+            ValueType::Int => JvmValue::Int(bits as i32),
+            ValueType::Long => JvmValue::Long(bits),
+        }
+    }
+
+    /// The number of local variable slots taken by a value.
+    fn slots(self) -> usize {
+        match self {
+            ValueType::Int => 1,
+            ValueType::Long => 2,
+        }
+    }
+}
+
+/// Called by compiled code to invoke a static method. The result is written to `result`, and
+/// `false` is returned if the call failed.
+unsafe extern "C" fn invoke_static(
+    context: *mut Context,
+    call: u32,
+    args: *const i64,
+    result: *mut i64,
+) -> bool {
+    let context = unsafe { &mut *context };
+    let call = &context.calls[call as usize];
+    let args = unsafe { std::slice::from_raw_parts(args, call.params.len()) };
+
+    match panic::catch_unwind(AssertUnwindSafe(|| call.invoke(context.vm, args))) {
+        Ok(Ok(value)) => {
+            unsafe { result.write(value) };
+            true
+        }
+        Ok(Err(e)) => {
+            context.failure = Some(Failure::Error(e));
+            false
+        }
+        Err(payload) => {
+            context.failure = Some(Failure::Panic(payload));
+            false
+        }
+    }
+}
+
+/// Called by compiled code when an integer is divided by zero.
+unsafe extern "C" fn throw_division_by_zero(context: *mut Context) {
+    let context = unsafe { &mut *context };
+    context.failure = Some(Failure::Error(
+        JavaException::new("java/lang/ArithmeticException", "/ by zero").into(),
+    ));
+}
+
+pub struct Jit {
+    /// The number of calls to a method after which it's compiled.
+    threshold: u64,
+    module: Mutex<JITModule>,
+    invoke_static: FuncId,
+    throw_division_by_zero: FuncId,
+}
+
+impl Jit {
+    /// Creates a compiler for the host machine, which fails if the host isn't supported by
+    /// Cranelift.
+    pub fn new(threshold: u64) -> Result<Jit> {
+        let mut flags = settings::builder();
+        flags.set("opt_level", "speed")?;
+
+        let isa = cranelift_native::builder()
+            .map_err(|e| format_err!("{e}"))?
+            .finish(settings::Flags::new(flags))?;
+
+        let mut builder = JITBuilder::with_isa(isa, default_libcall_names());
+        builder.symbol("invoke_static", invoke_static as *const u8);
+        builder.symbol(
+            "throw_division_by_zero",
+            throw_division_by_zero as *const u8,
+        );
+
+        let mut module = JITModule::new(builder);
+        let pointer_type = module.target_config().pointer_type();
+
+        let mut signature = module.make_signature();
+        signature.params.extend([
+            AbiParam::new(pointer_type),
+            AbiParam::new(I32),
+            AbiParam::new(pointer_type),
+            AbiParam::new(pointer_type),
+        ]);
+        signature.returns.push(AbiParam::new(I8));
+        let invoke_static =
+            module.declare_function("invoke_static", Linkage::Import, &signature)?;
+
+        let mut signature = module.make_signature();
+        signature.params.push(AbiParam::new(pointer_type));
+        let throw_division_by_zero =
+            module.declare_function("throw_division_by_zero", Linkage::Import, &signature)?;
+
+        Ok(Jit {
+            threshold,
+            module: Mutex::new(module),
+            invoke_static,
+            throw_division_by_zero,
+        })
+    }
+
+    /// Returns the compiled code of a method if it has been called enough times to be compiled.
+    /// The method is compiled on the first call which reaches the threshold.
+    pub(crate) fn compiled<'a>(
+        &self,
+        class: &'a Class<'a>,
+        method: &'a Method<'a>,
+    ) -> Option<&'a CompiledMethod<'a>> {
+        let state = &method.jit;
+        if let Some(code) = state.code.get() {
+            return code.as_ref();
+        }
+
+        if method.hotness.invocations() < self.threshold {
+            return None;
+        }
+
+        state
+            .code
+            .get_or_init(|| self.compile(class, method).ok())
+            .as_ref()
+    }
+
+    fn compile<'a>(
+        &self,
+        class: &'a Class<'a>,
+        method: &'a Method<'a>,
+    ) -> Result<CompiledMethod<'a>> {
+        if !method.access_flags.contains(MethodAccessFlags::STATIC)
+            || method
+                .access_flags
+                .contains(MethodAccessFlags::SYNCHRONIZED)
+        {
+            bail!("only static, unsynchronized methods can be compiled");
+        }
+
+        let params = method
+            .descriptor
+            .params
+            .iter()
+            .map(ValueType::from_field_type)
+            .collect::<Result<Vec<_>>>()?;
+        let return_type = method
+            .descriptor
+            .return_type
+            .as_ref()
+            .map(ValueType::from_field_type)
+            .transpose()?;
+
+        let body = method.body.as_ref().wrap_err("missing method body")?;
+        let code = class.class_file().methods[method.slot]
+            .attributes
+            .iter()
+            .find_map(|a| a.try_as_code_ref())
+            .wrap_err("missing code attribute")?;
+
+        if !code.exception_table.is_empty() {
+            bail!("exception handlers aren't supported");
+        }
+
+        let function =
+            ir::Function::translate(class.constant_pool(), code, &body.code, &body.offsets)?;
+
+        let mut module = self.module.lock().unwrap();
+        let mut context = module.make_context();
+        let pointer_type = module.target_config().pointer_type();
+        context
+            .func
+            .signature
+            .params
+            .extend([AbiParam::new(pointer_type), AbiParam::new(pointer_type)]);
+        context.func.signature.returns.push(AbiParam::new(I64));
+
+        let mut builder_context = FunctionBuilderContext::new();
+        let mut builder = FunctionBuilder::new(&mut context.func, &mut builder_context);
+
+        let invoke_static = module.declare_func_in_func(self.invoke_static, builder.func);
+        let throw_division_by_zero =
+            module.declare_func_in_func(self.throw_division_by_zero, builder.func);
+
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        let context_param = builder.block_params(entry)[1];
+
+        let mut translator = Translator {
+            class,
+            builder,
+            pointer_type,
+            blocks: vec![],
+            context: context_param,
+            invoke_static,
+            throw_division_by_zero,
+            failed: None,
+            calls: vec![],
+        };
+        translator.translate(&function, &params, entry)?;
+
+        let Translator { builder, calls, .. } = translator;
+        builder.finalize();
+
+        let id = module.declare_anonymous_function(&context.func.signature)?;
+        module.define_function(id, &mut context)?;
+        module.clear_context(&mut context);
+        module.finalize_definitions()?;
+
+        // SAFETY: The function was compiled with the signature of `CompiledFn`
+        let function = unsafe {
+            std::mem::transmute::<*const u8, CompiledFn>(module.get_finalized_function(id))
+        };
+
+        Ok(CompiledMethod {
+            function,
+            calls,
+            return_type,
+        })
+    }
+}
+
+/// Translates the IR of a method to Cranelift IR.
+struct Translator<'a, 'f> {
+    class: &'a Class<'a>,
+    builder: FunctionBuilder<'f>,
+    pointer_type: Type,
+    /// The Cranelift block of each IR block.
+    blocks: Vec<Block>,
+    /// The pointer to the [`Context`], which is the function's second parameter.
+    context: Value,
+    invoke_static: FuncRef,
+    throw_division_by_zero: FuncRef,
+    /// The block which returns after a runtime function fails, which is created when needed.
+    failed: Option<Block>,
+    calls: Vec<StaticCall<'a>>,
+}
+
+impl<'a, 'f> Translator<'a, 'f> {
+    /// Translates a function, starting from the entry block whose parameters are the locals and
+    /// context pointers.
+    fn translate(
+        &mut self,
+        function: &ir::Function,
+        params: &[ValueType],
+        entry: Block,
+    ) -> Result<()> {
+        let locals = self.builder.block_params(entry)[0];
+
+        for register in 0..function.registers {
+            self.builder
+                .declare_var(var(Register(register as u16)), I64);
+        }
+
+        // Registers which don't hold arguments start as zero, although the bytecode can't read
+        // them before they're written
+        let mut slot = 0;
+        let mut initialized = vec![false; function.registers];
+        for param in params {
+            let mut value =
+                self.builder
+                    .ins()
+                    .load(I64, MemFlags::trusted(), locals, (slot * 8) as i32);
+            if let ValueType::Int = param {
+                value = self.narrow(value, I32);
+            }
+            self.builder.def_var(var(Register(slot as u16)), value);
+            initialized[slot] = true;
+            slot += param.slots();
+        }
+
+        let zero = self.builder.ins().iconst(I64, 0);
+        for (register, initialized) in initialized.iter().enumerate() {
+            if !initialized {
+                self.builder.def_var(var(Register(register as u16)), zero);
+            }
+        }
+
+        self.blocks = function
+            .blocks
+            .iter()
+            .map(|_| self.builder.create_block())
+            .collect();
+        self.builder.ins().jump(self.blocks[0], &[]);
+
+        for (index, block) in function.blocks.iter().enumerate() {
+            if block.ops.is_empty() {
+                continue;
+            }
+
+            self.builder.switch_to_block(self.blocks[index]);
+            for op in &block.ops {
+                self.translate_op(op)?;
+            }
+        }
+
+        if let Some(failed) = self.failed {
+            self.builder.switch_to_block(failed);
+            let zero = self.builder.ins().iconst(I64, 0);
+            self.builder.ins().return_(&[zero]);
+        }
+
+        self.builder.seal_all_blocks();
+
+        Ok(())
+    }
+
+    fn translate_op(&mut self, op: &Op) -> Result<()> {
+        match op {
+            Op::Move { dst, src } => {
+                let value = self.builder.use_var(var(*src));
+                self.builder.def_var(var(*dst), value);
+            }
+            Op::Apply {
+                instruction,
+                args,
+                dst,
+            } => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.builder.use_var(var(*arg)))
+                    .collect::<Vec<_>>();
+                let value = self.translate_instruction(instruction, &args)?;
+                match (dst, value) {
+                    (Some(dst), Some(value)) => self.builder.def_var(var(*dst), value),
+                    (None, None) => {}
+                    _ => bail!("mismatched result for {instruction:?}"),
+                }
+            }
+            Op::Branch {
+                instruction,
+                args,
+                target,
+                fallthrough,
+            } => {
+                let args = args
+                    .iter()
+                    .map(|arg| {
+                        let value = self.builder.use_var(var(*arg));
+                        self.builder.ins().ireduce(I32, value)
+                    })
+                    .collect::<Vec<_>>();
+
+                let condition = match (instruction, args.as_slice()) {
+                    (Instruction::r#if { condition, .. }, [value]) => {
+                        self.builder.ins().icmp_imm(int_cc(*condition), *value, 0)
+                    }
+                    (Instruction::if_icmp { condition, .. }, [a, b]) => {
+                        self.builder.ins().icmp(int_cc(*condition), *a, *b)
+                    }
+                    _ => bail!("{instruction:?} isn't supported"),
+                };
+
+                self.builder.ins().brif(
+                    condition,
+                    self.blocks[*target],
+                    &[],
+                    self.blocks[*fallthrough],
+                    &[],
+                );
+            }
+            Op::Jump { target } => {
+                self.builder.ins().jump(self.blocks[*target], &[]);
+            }
+            Op::Return { value } => {
+                let value = match value {
+                    Some(value) => self.builder.use_var(var(*value)),
+                    None => self.builder.ins().iconst(I64, 0),
+                };
+                self.builder.ins().return_(&[value]);
+            }
+            Op::Throw { .. } => bail!("athrow isn't supported"),
+        }
+
+        Ok(())
+    }
+
+    /// Translates an instruction which doesn't transfer control, returning its result.
+    fn translate_instruction(
+        &mut self,
+        instruction: &Instruction,
+        args: &[Value],
+    ) -> Result<Option<Value>> {
+        let value = match (instruction, args) {
+            (
+                Instruction::r#const {
+                    data_type: NumberType::Int | NumberType::Long,
+                    value,
+                },
+                [],
+            ) => self.builder.ins().iconst(I64, *value as i64),
+            (Instruction::bipush { value }, []) => self.builder.ins().iconst(I64, *value as i64),
+            (Instruction::sipush { value }, []) => self.builder.ins().iconst(I64, *value as i64),
+            (Instruction::ldc { index } | Instruction::ldc2 { index }, []) => {
+                match self.class.constant_pool().get(*index) {
+                    Some(ConstantInfo::Integer(v)) => self.builder.ins().iconst(I64, *v as i64),
+                    Some(ConstantInfo::Long(v)) => self.builder.ins().iconst(I64, *v),
+                    _ => bail!("only int and long constants are supported"),
+                }
+            }
+            (Instruction::add { data_type }, [a, b]) => {
+                self.binary(number_type(*data_type)?, *a, *b, |builder, _, a, b| {
+                    builder.ins().iadd(a, b)
+                })
+            }
+            (Instruction::sub { data_type }, [a, b]) => {
+                self.binary(number_type(*data_type)?, *a, *b, |builder, _, a, b| {
+                    builder.ins().isub(a, b)
+                })
+            }
+            (Instruction::mul { data_type }, [a, b]) => {
+                self.binary(number_type(*data_type)?, *a, *b, |builder, _, a, b| {
+                    builder.ins().imul(a, b)
+                })
+            }
+            (Instruction::div { data_type }, [a, b]) => {
+                self.divide(number_type(*data_type)?, *a, *b, false)
+            }
+            (Instruction::rem { data_type }, [a, b]) => {
+                self.divide(number_type(*data_type)?, *a, *b, true)
+            }
+            (Instruction::neg { data_type }, [a]) => {
+                self.binary(number_type(*data_type)?, *a, *a, |builder, _, a, _| {
+                    builder.ins().ineg(a)
+                })
+            }
+            (Instruction::shl { data_type }, [a, b]) => {
+                self.binary(*data_type, *a, *b, |builder, _, a, b| {
+                    builder.ins().ishl(a, b)
+                })
+            }
+            (Instruction::shr { data_type }, [a, b]) => {
+                self.binary(*data_type, *a, *b, |builder, _, a, b| {
+                    builder.ins().sshr(a, b)
+                })
+            }
+            (Instruction::ushr { data_type }, [a, b]) => {
+                self.binary(*data_type, *a, *b, |builder, _, a, b| {
+                    builder.ins().ushr(a, b)
+                })
+            }
+            (Instruction::and { data_type }, [a, b]) => {
+                self.binary(*data_type, *a, *b, |builder, _, a, b| {
+                    builder.ins().band(a, b)
+                })
+            }
+            (Instruction::or { data_type }, [a, b]) => {
+                self.binary(*data_type, *a, *b, |builder, _, a, b| {
+                    builder.ins().bor(a, b)
+                })
+            }
+            (Instruction::xor { data_type }, [a, b]) => {
+                self.binary(*data_type, *a, *b, |builder, _, a, b| {
+                    builder.ins().bxor(a, b)
+                })
+            }
+            (Instruction::inc { value, .. }, [a]) => {
+                let value = *value as i64;
+                self.binary(IntegerType::Int, *a, *a, |builder, _, a, _| {
+                    builder.ins().iadd_imm(a, value)
+                })
+            }
+            // Ints are already sign-extended
+            (Instruction::i2l, [a]) => *a,
+            (Instruction::l2i, [a]) => self.narrow(*a, I32),
+            (Instruction::i2b, [a]) => self.narrow(*a, I8),
+            (Instruction::i2s, [a]) => self.narrow(*a, I16),
+            (Instruction::i2c, [a]) => {
+                let value = self.builder.ins().ireduce(I16, *a);
+                self.builder.ins().uextend(I64, value)
+            }
+            (Instruction::lcmp, [a, b]) => {
+                let greater = self.builder.ins().icmp(IntCC::SignedGreaterThan, *a, *b);
+                let less = self.builder.ins().icmp(IntCC::SignedLessThan, *a, *b);
+                let greater = self.builder.ins().uextend(I64, greater);
+                let less = self.builder.ins().uextend(I64, less);
+                self.builder.ins().isub(greater, less)
+            }
+            (
+                Instruction::invoke {
+                    kind: InvokeKind::Static,
+                    index,
+                },
+                args,
+            ) => return self.invoke_static(*index, args),
+            _ => bail!("{instruction:?} isn't supported"),
+        };
+
+        Ok(Some(value))
+    }
+
+    /// Applies an operation to two values of a type. Int operations are done on the low 32 bits,
+    /// and the result is sign-extended again.
+    fn binary(
+        &mut self,
+        data_type: IntegerType,
+        a: Value,
+        b: Value,
+        op: impl FnOnce(&mut FunctionBuilder<'f>, Type, Value, Value) -> Value,
+    ) -> Value {
+        match data_type {
+            IntegerType::Int => {
+                let a = self.builder.ins().ireduce(I32, a);
+                let b = self.builder.ins().ireduce(I32, b);
+                let value = op(&mut self.builder, I32, a, b);
+                self.builder.ins().sextend(I64, value)
+            }
+            IntegerType::Long => op(&mut self.builder, I64, a, b),
+        }
+    }
+
+    /// Divides two values, or takes the remainder, with Java's semantics: dividing by zero throws
+    /// `ArithmeticException`, and dividing the minimum value by -1 overflows instead of trapping.
+    fn divide(&mut self, data_type: IntegerType, a: Value, b: Value, remainder: bool) -> Value {
+        let is_zero = self.builder.ins().icmp_imm(IntCC::Equal, b, 0);
+        let division_by_zero = self.builder.create_block();
+        let next = self.builder.create_block();
+        self.builder
+            .ins()
+            .brif(is_zero, division_by_zero, &[], next, &[]);
+
+        self.builder.switch_to_block(division_by_zero);
+        self.builder
+            .ins()
+            .call(self.throw_division_by_zero, &[self.context]);
+        let failed = self.failed_block();
+        self.builder.ins().jump(failed, &[]);
+
+        self.builder.switch_to_block(next);
+        self.binary(data_type, a, b, |builder, ty, a, b| {
+            let is_minus_one = builder.ins().icmp_imm(IntCC::Equal, b, -1);
+            let one = builder.ins().iconst(ty, 1);
+            let divisor = builder.ins().select(is_minus_one, one, b);
+            if remainder {
+                let value = builder.ins().srem(a, divisor);
+                let zero = builder.ins().iconst(ty, 0);
+                builder.ins().select(is_minus_one, zero, value)
+            } else {
+                let value = builder.ins().sdiv(a, divisor);
+                let negated = builder.ins().ineg(a);
+                builder.ins().select(is_minus_one, negated, value)
+            }
+        })
+    }
+
+    /// Calls a static method through the interpreter.
+    fn invoke_static(&mut self, index: u16, args: &[Value]) -> Result<Option<Value>> {
+        let call = self.resolve_static(index)?;
+        let returns = call.1;
+        let call_index = self.calls.len() as i64;
+        self.calls.push(call.0);
+
+        let args_slot = self.builder.create_sized_stack_slot(StackSlotData::new(
+            StackSlotKind::ExplicitSlot,
+            (args.len().max(1) * 8) as u32,
+            3,
+        ));
+        let result_slot = self.builder.create_sized_stack_slot(StackSlotData::new(
+            StackSlotKind::ExplicitSlot,
+            8,
+            3,
+        ));
+
+        for (i, arg) in args.iter().enumerate() {
+            self.builder
+                .ins()
+                .stack_store(*arg, args_slot, (i * 8) as i32);
+        }
+
+        let call_index = self.builder.ins().iconst(I32, call_index);
+        let args = self
+            .builder
+            .ins()
+            .stack_addr(self.pointer_type, args_slot, 0);
+        let result = self
+            .builder
+            .ins()
+            .stack_addr(self.pointer_type, result_slot, 0);
+        let call = self.builder.ins().call(
+            self.invoke_static,
+            &[self.context, call_index, args, result],
+        );
+        let succeeded = self.builder.inst_results(call)[0];
+
+        let next = self.builder.create_block();
+        let failed = self.failed_block();
+        self.builder.ins().brif(succeeded, next, &[], failed, &[]);
+        self.builder.switch_to_block(next);
+
+        Ok(returns.then(|| self.builder.ins().stack_load(I64, result_slot, 0)))
+    }
+
+    /// Reads the method referenced by an invokestatic instruction, and whether it returns a
+    /// value.
+    fn resolve_static(&self, index: u16) -> Result<(StaticCall<'a>, bool)> {
+        let constant_pool = self.class.constant_pool();
+        let Some(ConstantInfo::MethodRef(method_ref)) = constant_pool.get(index) else {
+            bail!("only methods of classes can be called");
+        };
+
+        let name_and_type = constant_pool
+            .get(method_ref.name_and_type_index)
+            .and_then(|c| c.try_as_name_and_type_ref())
+            .wrap_err("expected name_and_type")?;
+        let name = constant_pool
+            .get(name_and_type.name_index)
+            .and_then(|c| c.try_as_utf_8_ref())
+            .wrap_err("expected utf8")?;
+        let descriptor = constant_pool
+            .get(name_and_type.descriptor_index)
+            .and_then(|c| c.try_as_utf_8_ref())
+            .wrap_err("expected utf8")?;
+
+        let class_name = if method_ref.class_index == self.class.index() {
+            None
+        } else {
+            let class = constant_pool
+                .get(method_ref.class_index)
+                .and_then(|c| c.try_as_class_ref())
+                .wrap_err("expected class")?;
+            let class_name = constant_pool
+                .get(class.name_index)
+                .and_then(|c| c.try_as_utf_8_ref())
+                .wrap_err("expected utf8")?;
+            Some(*class_name)
+        };
+
+        let arena = Bump::new();
+        let parsed = parse_method_descriptor(&arena, descriptor)?;
+        let params = parsed
+            .params
+            .iter()
+            .map(ValueType::from_field_type)
+            .collect::<Result<Vec<_>>>()?;
+        if let Some(return_type) = &parsed.return_type {
+            ValueType::from_field_type(return_type)?;
+        }
+
+        // Calls within the class can be resolved now, since the class is already being
+        // initialized by the time its methods run
+        let target = OnceLock::new();
+        if class_name.is_none() {
+            let method = self
+                .class
+                .method(name, descriptor)
+                .wrap_err_with(|| format_err!("method {name}{descriptor} not found"))?;
+            let _ = target.set((self.class, method));
+        }
+
+        let call = StaticCall {
+            class_name,
+            name,
+            descriptor,
+            params,
+            target,
+        };
+
+        Ok((call, parsed.return_type.is_some()))
+    }
+
+    /// Truncates a value to a smaller type and sign-extends it back to 64 bits.
+    fn narrow(&mut self, value: Value, ty: Type) -> Value {
+        let value = self.builder.ins().ireduce(ty, value);
+        self.builder.ins().sextend(I64, value)
+    }
+
+    fn failed_block(&mut self) -> Block {
+        *self
+            .failed
+            .get_or_insert_with(|| self.builder.create_block())
+    }
+}
+
+fn var(register: Register) -> Variable {
+    Variable::from_u32(register.0 as u32)
+}
+
+fn number_type(data_type: NumberType) -> Result<IntegerType> {
+    match data_type {
+        NumberType::Int => Ok(IntegerType::Int),
+        NumberType::Long => Ok(IntegerType::Long),
+        NumberType::Float | NumberType::Double => bail!("floating point isn't supported"),
+    }
+}
+
+fn int_cc(condition: Condition) -> IntCC {
+    match condition {
+        Condition::Eq => IntCC::Equal,
+        Condition::Ne => IntCC::NotEqual,
+        Condition::Lt => IntCC::SignedLessThan,
+        Condition::Le => IntCC::SignedLessThanOrEqual,
+        Condition::Gt => IntCC::SignedGreaterThan,
+        Condition::Ge => IntCC::SignedGreaterThanOrEqual,
+    }
+}
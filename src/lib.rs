@@ -1,10 +1,37 @@
-#![feature(cursor_remaining, let_chains, macro_metavar_expr)]
-
+pub mod assembler;
+pub mod budget;
 pub mod call_frame;
+pub mod cfg;
 pub mod class;
+pub mod class_archive;
 pub mod class_file;
+pub mod class_path;
+pub mod convert;
+pub mod coverage;
+pub mod debugger;
 pub mod descriptor;
+pub mod disassembler;
+pub mod error;
+pub mod hooks;
+mod hprof;
 pub mod instructions;
+pub mod ir;
+pub mod jdwp;
+pub mod jimage;
+#[cfg(feature = "jit")]
+pub mod jit;
+pub mod natives;
+pub mod object;
 pub mod opcodes;
+pub mod owned_vm;
+pub mod profiler;
 pub mod reader;
+pub mod shims;
+pub mod snapshot;
+pub mod stats;
+pub mod superinstructions;
+pub mod symbols;
+pub mod trace;
+pub mod verifier;
 pub mod vm;
+pub mod writer;
@@ -1,37 +1,554 @@
-use std::io;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::net::TcpListener;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::thread;
 
 use bumpalo::Bump;
 use clap::Parser;
-use color_eyre::eyre::{self, Context, ContextCompat};
+use color_eyre::eyre::{self, Context};
+use rusty_java::cfg::ControlFlowGraph;
+use rusty_java::class::decode_instructions_lenient;
+use rusty_java::class_archive::ClassArchive;
+use rusty_java::class_path::ClassPath;
+use rusty_java::coverage::Coverage;
+use rusty_java::debugger::Debugger;
+use rusty_java::disassembler::disassemble;
+use rusty_java::ir::Function;
+use rusty_java::jdwp::JdwpAgent;
+use rusty_java::profiler::Profiler;
+use rusty_java::reader::ClassReader;
+use rusty_java::stats::ExecutionStats;
+use rusty_java::trace::{CallTracer, InstructionTracer};
+use rusty_java::verifier::Verify;
 use rusty_java::vm::Vm;
 
 #[derive(clap::Parser)]
+#[clap(version, subcommand_negates_reqs = true)]
 struct Args {
-    class_file: String,
+    #[clap(subcommand)]
+    command: Option<Command>,
+    /// The class to run, e.g. `com.example.Main`
+    #[clap(required = true)]
+    class_name: Option<String>,
+    /// Arguments passed to the main method
+    #[clap(trailing_var_arg = true, allow_hyphen_values = true)]
+    args: Vec<String>,
+    /// Directories to search for classes, separated like `PATH` [default: .]
+    #[clap(long, value_name = "PATH")]
+    class_path: Option<String>,
+    /// The JDK to load core classes from [default: $JAVA_HOME]
+    #[clap(long, value_name = "PATH")]
+    java_home: Option<PathBuf>,
+    /// Loads JDK classes from an archive created with --dump-class-archive
+    #[clap(long, value_name = "FILE")]
+    class_archive: Option<PathBuf>,
+    /// Writes an archive of the JDK classes used by the program once it exits, for faster startup
+    #[clap(long, value_name = "FILE")]
+    dump_class_archive: Option<PathBuf>,
+    /// Prints the class file and its decoded instructions instead of running it. Parts which can't
+    /// be parsed are shown as placeholders, so broken class files can be inspected too
     #[clap(long)]
     dump: bool,
+    /// The format used by --dump. JSON contains only the parsed class file, for other tools to
+    /// read, and IR shows the code of each method translated to registers
+    #[clap(long, value_enum, default_value_t = DumpFormat::Text, requires = "dump")]
+    dump_format: DumpFormat,
+    /// Runs the JDK implementation of StringBuilder instead of the vm's faster replacement
+    #[clap(long)]
+    no_string_builder_intrinsic: bool,
+    /// Sets a system property
+    #[clap(short = 'D', value_name = "KEY=VALUE", value_parser = parse_property)]
+    properties: Vec<(String, String)>,
+    /// Enables assertions in classes outside of the JDK
+    #[clap(long, overrides_with = "disable_assertions")]
+    enable_assertions: bool,
+    /// Disables assertions, which is the default
+    #[clap(long, overrides_with = "enable_assertions")]
+    disable_assertions: bool,
+    /// Logs each class as it's loaded
+    #[clap(long)]
+    verbose_class: bool,
+    /// Logs each instruction as it runs, along with its method and the operand stack, to stderr or
+    /// to a file
+    #[clap(long, value_name = "FILE", num_args = 0..=1, require_equals = true)]
+    trace: Option<Option<PathBuf>>,
+    /// Logs calls to the methods matching any of a comma separated list of patterns to stderr,
+    /// with their arguments and results. Patterns match the class and method name, with `*` for
+    /// any characters, e.g. `com.example.*` or `*.toString`
+    #[clap(long, value_name = "PATTERNS", value_delimiter = ',')]
+    trace_calls: Vec<String>,
+    /// Prints the number of instructions executed of each kind, the most called methods, the
+    /// allocations of each class and the classes loaded to stderr when the program ends
+    #[clap(long)]
+    stats: bool,
+    /// Samples the running methods and prints the hottest ones to stderr when the program ends
+    #[clap(long)]
+    profile: bool,
+    /// Samples the running methods like --profile, and writes the samples to a file as collapsed
+    /// stacks for making a flame graph with `inferno-flamegraph` or `flamegraph.pl`
+    #[clap(long, value_name = "FILE")]
+    profile_folded: Option<PathBuf>,
+    /// The number of instructions between each sample taken by --profile and --profile-folded
+    #[clap(long, value_name = "INSTRUCTIONS", default_value_t = 1000)]
+    profile_interval: u64,
+    /// Writes a heap dump in the HPROF format to a file when the program ends, which can be opened
+    /// in Eclipse MAT or VisualVM
+    #[clap(long, value_name = "FILE")]
+    heap_dump_on_exit: Option<PathBuf>,
+    /// Prints how much of the code of the classes matching any of a comma separated list of
+    /// patterns ran to stderr when the program ends, e.g. `com.example.*`, or `*` for every class
+    #[clap(long, value_name = "PATTERNS", value_delimiter = ',')]
+    coverage: Vec<String>,
+    /// Writes the coverage of the classes chosen by --coverage to a file in the lcov format, for
+    /// `genhtml` or an editor to show against the source
+    #[clap(long, value_name = "FILE", requires = "coverage")]
+    coverage_lcov: Option<PathBuf>,
+    /// Waits for a debugger like jdb or IntelliJ IDEA to attach over JDWP at `[HOST:]PORT` before
+    /// running the program, which starts suspended so that breakpoints can be set
+    #[clap(long, value_name = "[HOST:]PORT")]
+    jdwp: Option<String>,
+    /// Runs the program as soon as the debugger attaches, instead of waiting for it to resume
+    #[clap(long, requires = "jdwp")]
+    jdwp_no_suspend: bool,
+    /// Stops before the main method runs, and reads debugger commands like `break`, `step` and
+    /// `locals` from stdin. `help` lists them
+    #[clap(long, conflicts_with = "jdwp")]
+    debug: bool,
+    /// Which classes to verify when they're loaded: `none`, `remote` (classes outside of the JDK)
+    /// or `all`
+    #[clap(long, value_name = "CLASSES", value_parser = parse_verify, default_value = "none")]
+    verify: Verify,
+    /// The stack size of the main thread, which also limits the depth of Java calls, e.g. `16m`
+    #[clap(long, value_name = "SIZE", value_parser = parse_size)]
+    stack_size: Option<usize>,
+    /// Interprets every method, instead of compiling frequently called methods to native code
+    #[cfg(feature = "jit")]
+    #[clap(long)]
+    interpret: bool,
+    /// The number of calls to a method after which it's compiled to native code
+    #[cfg(feature = "jit")]
+    #[clap(long, value_name = "CALLS")]
+    jit_threshold: Option<u64>,
+    /// The number of calls to a method plus iterations of its loops after which its instructions
+    /// are fused into superinstructions
+    #[clap(long, value_name = "COUNT")]
+    superinstruction_threshold: Option<u64>,
+    /// The maximum size of the heap, e.g. `512m`
+    #[clap(long, value_name = "SIZE", value_parser = parse_size)]
+    max_heap_size: Option<usize>,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum DumpFormat {
+    Text,
+    Json,
+    Ir,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Prints a class file's constant pool, members and disassembled code, like `javap -c -v`
+    Disasm {
+        /// A class file, or the name of a class to find on the class path or in the JDK
+        class: String,
+    },
+}
+
+fn parse_property(property: &str) -> Result<(String, String), String> {
+    // A property without a value is set to the empty string, like with `java`
+    let (key, value) = property.split_once('=').unwrap_or((property, ""));
+
+    if key.is_empty() {
+        return Err("property key can't be empty".to_owned());
+    }
+
+    Ok((key.to_owned(), value.to_owned()))
 }
 
-fn main() -> eyre::Result<()> {
+fn parse_verify(verify: &str) -> Result<Verify, String> {
+    match verify {
+        "none" => Ok(Verify::None),
+        "remote" => Ok(Verify::Remote),
+        "all" => Ok(Verify::All),
+        _ => Err(format!("invalid verification mode: {verify}")),
+    }
+}
+
+/// Parses a size in bytes, with an optional `k`, `m` or `g` suffix, like `java -Xmx`.
+fn parse_size(size: &str) -> Result<usize, String> {
+    let (digits, multiplier) = match size.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&size[..i], 1 << 10),
+        Some((i, 'm' | 'M')) => (&size[..i], 1 << 20),
+        Some((i, 'g' | 'G')) => (&size[..i], 1 << 30),
+        _ => (size, 1),
+    };
+
+    digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| format!("invalid size: {size}"))
+}
+
+/// Rewrites the options of `java` which clap can't express, since they aren't single letters.
+/// Only the options before the main class are rewritten, since the rest are passed to the program.
+fn normalize_args(args: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut args = args.into_iter();
+    let mut normalized = args.next().into_iter().collect::<Vec<_>>();
+
+    while let Some(arg) = args.next() {
+        let arg = match arg.as_str() {
+            "-cp" | "-classpath" => "--class-path".to_owned(),
+            "-ea" | "-enableassertions" => "--enable-assertions".to_owned(),
+            "-da" | "-disableassertions" => "--disable-assertions".to_owned(),
+            "-verbose:class" => "--verbose-class".to_owned(),
+            "-version" => "--version".to_owned(),
+            // Without the jit every method is interpreted already, so its options are ignored
+            "-Xint" if cfg!(not(feature = "jit")) => continue,
+            "-Xint" => "--interpret".to_owned(),
+            "-Xtrace" => "--trace".to_owned(),
+            _ => {
+                if let Some(size) = arg.strip_prefix("-Xss") {
+                    format!("--stack-size={size}")
+                } else if let Some(size) = arg.strip_prefix("-Xmx") {
+                    format!("--max-heap-size={size}")
+                } else if let Some(path) = arg.strip_prefix("-Xtrace:") {
+                    format!("--trace={path}")
+                } else if let Some(verify) = arg.strip_prefix("-Xverify:") {
+                    format!("--verify={verify}")
+                } else if let Some(threshold) = arg.strip_prefix("-XX:CompileThreshold=") {
+                    if cfg!(not(feature = "jit")) {
+                        continue;
+                    }
+                    format!("--jit-threshold={threshold}")
+                } else if let Some(options) = arg.strip_prefix("-agentlib:jdwp=") {
+                    let options = options
+                        .split(',')
+                        .filter_map(|option| option.split_once('='))
+                        .collect::<Vec<_>>();
+                    if options.contains(&("suspend", "n")) {
+                        normalized.push("--jdwp-no-suspend".to_owned());
+                    }
+                    let address = options
+                        .iter()
+                        .find(|(key, _)| *key == "address")
+                        .map_or("", |(_, address)| address);
+                    format!("--jdwp={address}")
+                } else {
+                    arg
+                }
+            }
+        };
+
+        // Options which take a separate value, which could be mistaken for the main class
+        let takes_value = matches!(
+            arg.as_str(),
+            "--class-path"
+                | "--java-home"
+                | "--class-archive"
+                | "--dump-class-archive"
+                | "--dump-format"
+                | "--jit-threshold"
+                | "--superinstruction-threshold"
+                | "--trace-calls"
+                | "--profile-folded"
+                | "--heap-dump-on-exit"
+                | "--coverage"
+                | "--coverage-lcov"
+                | "--profile-interval"
+                | "--jdwp"
+                | "-D"
+        );
+        let is_main_class = !arg.starts_with('-');
+
+        normalized.push(arg);
+
+        if takes_value {
+            normalized.extend(args.next());
+        } else if is_main_class {
+            break;
+        }
+    }
+
+    normalized.extend(args);
+    normalized
+}
+
+fn main() -> eyre::Result<ExitCode> {
     color_eyre::install()?;
 
-    let args = Args::parse();
+    let args = Args::parse_from(normalize_args(std::env::args()));
+
+    // The main thread's stack size can't be changed, so the vm runs on a new thread instead
+    match args.stack_size {
+        Some(stack_size) => thread::Builder::new()
+            .stack_size(stack_size)
+            .spawn(move || run(args))?
+            .join()
+            .unwrap_or_else(|e| panic::resume_unwind(e)),
+        None => run(args),
+    }
+}
 
+fn run(args: Args) -> eyre::Result<ExitCode> {
     let arena = Bump::new();
-    let mut stdout = io::stdout();
-    let mut vm = Vm::new(&arena, &mut stdout);
+    let mut vm = Vm::new(&arena, io::stdout())
+        .with_string_builder_intrinsic(!args.no_string_builder_intrinsic)
+        .with_assertions(args.enable_assertions)
+        .with_verbose_class(args.verbose_class)
+        .with_verify(args.verify);
+
+    #[cfg(feature = "jit")]
+    {
+        vm = vm.with_jit(!args.interpret);
+
+        if let Some(threshold) = args.jit_threshold {
+            vm = vm.with_jit_threshold(threshold);
+        }
+    }
+
+    if let Some(path) = &args.trace {
+        let out: Box<dyn Write + Send> = match path {
+            Some(path) => {
+                Box::new(File::create(path).wrap_err_with(|| format!("failed to create {path:?}"))?)
+            }
+            None => Box::new(io::stderr()),
+        };
+        vm = vm.with_hook(InstructionTracer::new(BufWriter::new(out)));
+    }
+
+    if !args.trace_calls.is_empty() {
+        vm = vm.with_hook(CallTracer::new(io::stderr(), args.trace_calls));
+    }
+
+    let stats = args.stats.then(|| Arc::new(ExecutionStats::new()));
+    if let Some(stats) = &stats {
+        vm = vm.with_hook(stats.clone());
+    }
+
+    let profiler = (args.profile || args.profile_folded.is_some())
+        .then(|| Arc::new(Profiler::new(args.profile_interval)));
+    if let Some(profiler) = &profiler {
+        vm = vm.with_hook(profiler.clone());
+    }
+
+    let coverage = (!args.coverage.is_empty()).then(|| Arc::new(Coverage::new(&args.coverage)));
+    if let Some(coverage) = &coverage {
+        vm = vm.with_hook(coverage.clone());
+    }
+
+    let jdwp = match &args.jdwp {
+        Some(address) => {
+            let agent = Arc::new(attach_debugger(address, !args.jdwp_no_suspend)?);
+            vm = vm.with_hook(agent.clone());
+            Some(agent)
+        }
+        None => None,
+    };
+
+    let debugger = args
+        .debug
+        .then(|| Arc::new(Debugger::new(BufReader::new(io::stdin()), io::stderr())));
+    if let Some(debugger) = &debugger {
+        vm = vm.with_hook(debugger.clone());
+    }
+
+    if let Some(threshold) = args.superinstruction_threshold {
+        vm = vm.with_superinstruction_threshold(threshold);
+    }
+
+    if let Some(stack_size) = args.stack_size {
+        vm = vm.with_stack_size(stack_size);
+    }
+
+    if let Some(max_heap_size) = args.max_heap_size {
+        vm = vm.with_max_heap_size(max_heap_size);
+    }
 
-    let class = vm.load_class_file(&args.class_file)?;
+    if let Some(java_home) = args.java_home {
+        vm = vm.with_java_home(java_home);
+    }
+
+    if let Some(path) = &args.class_archive {
+        vm = vm.with_class_archive(ClassArchive::read(path)?);
+    }
+
+    if let Some(class_path) = &args.class_path {
+        vm = vm.with_class_path(ClassPath::parse(class_path));
+    }
+
+    for (key, value) in args.properties {
+        vm.set_property(key, value);
+    }
+
+    if let Some(Command::Disasm { class }) = &args.command {
+        disasm(&arena, &vm, class)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    // Binary names use dots, but a file name is accepted too for convenience
+    let class_name = args.class_name.as_deref().unwrap_or_default();
+    let class_name = class_name.strip_suffix(".class").unwrap_or(class_name);
+    let class_name = class_name.replace('.', "/");
 
     if args.dump {
-        println!("{class:#?}");
+        dump(&arena, &vm, &class_name, args.dump_format)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let class = vm.load_class_file(&class_name)?;
+
+    if let Some(debugger) = &debugger {
+        debugger.add_breakpoint(&format!("{class_name}.main([Ljava/lang/String;)V"))?;
+    }
+
+    let result = vm.run_main(class, &args.args);
+
+    if let Some(jdwp) = &jdwp {
+        jdwp.vm_death();
+    }
+
+    // The stats are useful even if the program failed, so they're printed before the error
+    if let Some(stats) = &stats {
+        stats.report(&mut io::stderr().lock())?;
+    }
+
+    if let Some(profiler) = profiler.as_ref().filter(|_| args.profile) {
+        profiler.report(&mut io::stderr().lock())?;
+    }
+
+    if let (Some(profiler), Some(path)) = (&profiler, &args.profile_folded) {
+        let file = File::create(path).wrap_err_with(|| format!("failed to create {path:?}"))?;
+        let mut out = BufWriter::new(file);
+        profiler.write_folded(&mut out)?;
+        out.flush()?;
+    }
+
+    if let Some(coverage) = &coverage {
+        coverage.report(&mut io::stderr().lock())?;
+    }
+
+    if let (Some(coverage), Some(path)) = (&coverage, &args.coverage_lcov) {
+        let file = File::create(path).wrap_err_with(|| format!("failed to create {path:?}"))?;
+        let mut out = BufWriter::new(file);
+        coverage.write_lcov(&mut out)?;
+        out.flush()?;
+    }
+
+    if let Some(path) = &args.heap_dump_on_exit {
+        let file = File::create(path).wrap_err_with(|| format!("failed to create {path:?}"))?;
+        vm.write_heap_dump(BufWriter::new(file))
+            .wrap_err("failed to write heap dump")?;
+    }
+
+    let status = result.wrap_err("failed to execute main method")?;
+
+    if let Some(path) = &args.dump_class_archive {
+        vm.write_class_archive(path)
+            .wrap_err("failed to write class archive")?;
+    }
+
+    // Only the low 8 bits of the status are visible to the parent process
+    Ok(ExitCode::from(status as u8))
+}
+
+/// Waits for a debugger to attach at an address like those of `-agentlib:jdwp`, which is a port
+/// on localhost, or a host and port where the host `*` listens on every interface.
+fn attach_debugger(address: &str, suspend: bool) -> eyre::Result<JdwpAgent> {
+    let address = match address.rsplit_once(':') {
+        Some(("*", port)) => format!("0.0.0.0:{port}"),
+        Some(_) => address.to_owned(),
+        None => format!("localhost:{address}"),
+    };
+
+    let listener =
+        TcpListener::bind(&address).wrap_err_with(|| format!("failed to listen at {address}"))?;
+
+    // The same message as HotSpot, which IDEs wait for before attaching
+    println!(
+        "Listening for transport dt_socket at address: {}",
+        listener.local_addr()?.port()
+    );
+
+    Ok(JdwpAgent::accept(&listener, suspend)?)
+}
+
+fn disasm<'a>(arena: &'a Bump, vm: &Vm<'a>, class: &str) -> eyre::Result<()> {
+    // Paths to class files are read directly, rather than being found on the class path
+    let class_file = if Path::new(class).is_file() {
+        let bytes = fs::read(class).wrap_err_with(|| format!("failed to read {class}"))?;
+        let class_file = ClassReader::from_slice(arena, arena.alloc_slice_copy(&bytes))
+            .lenient(true)
+            .read_class_file()
+            .wrap_err_with(|| format!("failed to read class file '{class}'"))?;
+        &*arena.alloc(class_file)
     } else {
-        let main = class
-            .method("main", "([Ljava/lang/String;)V")
-            .wrap_err("main method not found")?;
+        let class_name = class.strip_suffix(".class").unwrap_or(class);
+        vm.read_class_file_lenient(&class_name.replace('.', "/"))?
+    };
+
+    Ok(disassemble(&mut io::stdout().lock(), class_file)?)
+}
+
+fn dump(arena: &Bump, vm: &Vm, class_name: &str, format: DumpFormat) -> eyre::Result<()> {
+    let class_file = vm.read_class_file_lenient(class_name)?;
+
+    if let DumpFormat::Json = format {
+        let mut stdout = io::stdout().lock();
+        serde_json::to_writer_pretty(&mut stdout, class_file)?;
+        writeln!(stdout)?;
+        return Ok(());
+    }
+
+    if let DumpFormat::Text = format {
+        println!("{class_file:#?}");
+    }
+
+    for method in &class_file.methods {
+        let Some(code) = method.attributes.iter().find_map(|a| a.try_as_code_ref()) else {
+            continue;
+        };
+
+        let name = class_file
+            .constant_pool
+            .get(method.name_index)
+            .and_then(|c| c.try_as_utf_8_ref())
+            .map_or("<invalid>", |name| *name);
+
+        println!();
+        println!("{name}:");
+
+        let (instructions, offsets) = decode_instructions_lenient(arena, code.code)?;
+
+        if let DumpFormat::Ir = format {
+            match Function::translate(&class_file.constant_pool, code, &instructions, &offsets) {
+                Ok(function) => print!("{function}"),
+                Err(e) => println!("  can't be translated: {e}"),
+            }
+            continue;
+        }
+
+        // Code which can't be split into basic blocks is still listed, just without the blocks
+        let cfg = ControlFlowGraph::new(code, &instructions, &offsets).ok();
 
-        vm.call_method(class, main)
-            .wrap_err("failed to execute main method")?;
+        for (index, (instruction, offset)) in instructions.iter().zip(&offsets).enumerate() {
+            if let Some(cfg) = &cfg {
+                let block = &cfg.blocks[cfg.block_index(index)];
+                if block.start == index {
+                    println!(
+                        "  block {} (successors {:?}, handlers {:?}):",
+                        cfg.block_index(index),
+                        block.successors,
+                        block.handlers
+                    );
+                }
+            }
+            println!("    {offset:>5}: {instruction:?}");
+        }
     }
 
     Ok(())
@@ -0,0 +1,1082 @@
+use std::io::Write;
+use std::time::{Duration, SystemTime};
+
+use crate::call_frame::{ArrayElementType, JavaException, JvmValue, RefTypeHeader};
+use crate::class_file::ClassAccessFlags;
+use crate::convert::Reference;
+use crate::error::{bail, format_err, ContextCompat, Result};
+use crate::instructions::ArrayType;
+use crate::vm::{is_missing_class, SystemExit, Vm};
+
+pub(crate) mod class_loader;
+mod fdlibm;
+pub(crate) mod io;
+mod misc;
+mod reflect;
+mod scanner;
+pub(crate) mod string;
+
+/// Implementation of a native method.
+///
+/// The arguments are passed in declaration order, preceded by the receiver for instance methods.
+/// The return value must be `None` for `void` methods.
+pub type NativeMethod<'a> = dyn Fn(&Vm<'a>, &[JvmValue<'a>]) -> Result<Option<JvmValue<'a>>> + 'a;
+
+/// Registers the natives implemented by the vm itself.
+pub(crate) fn register_builtins<'a>(vm: &Vm<'a>) -> Result<()> {
+    vm.register_native("*", "registerNatives", "()V", |_: &Vm| Ok(()))?;
+
+    // Debugging aid which lets test programs print values without going through System.out.
+    vm.register_native("*", "print", "(Z)V", |vm: &Vm, value: bool| {
+        write!(vm.stdout(), "{value}")?;
+        Ok(())
+    })?;
+
+    for descriptor in [
+        "(B)V",
+        "(C)V",
+        "(S)V",
+        "(I)V",
+        "(J)V",
+        "(F)V",
+        "(D)V",
+        "([I)V",
+        "(Ljava/lang/Object;)V",
+        "(Ljava/lang/String;)V",
+    ] {
+        vm.register_raw_native("*", "print", descriptor, |vm, args| {
+            let arg = args.first().wrap_err("missing argument to print")?;
+            print_jvm_value(&mut **vm.stdout(), arg)?;
+            Ok(None)
+        });
+    }
+
+    vm.register_native("java/lang/System", "currentTimeMillis", "()J", |vm: &Vm| {
+        let millis = vm
+            .time
+            .system_time()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_millis();
+        Ok(i64::try_from(millis)?)
+    })?;
+
+    vm.register_native("java/lang/System", "nanoTime", "()J", |vm: &Vm| {
+        Ok(i64::try_from(vm.time.monotonic_time().as_nanos())?)
+    })?;
+
+    vm.register_native(
+        "java/lang/System",
+        "arraycopy",
+        "(Ljava/lang/Object;ILjava/lang/Object;II)V",
+        |_: &Vm,
+         src: Option<Reference>,
+         src_pos: i32,
+         dest: Option<Reference>,
+         dest_pos: i32,
+         length: i32| {
+            let (Some(src), Some(dest)) = (src, dest) else {
+                bail!(JavaException::new(
+                    "java/lang/NullPointerException",
+                    "arraycopy"
+                ));
+            };
+            array_copy(src.0, src_pos, dest.0, dest_pos, length)
+        },
+    )?;
+
+    vm.register_native(
+        "java/lang/System",
+        "identityHashCode",
+        "(Ljava/lang/Object;)I",
+        |_: &Vm, object: JvmValue<'a>| identity_hash_code(&object),
+    )?;
+
+    vm.register_native(
+        "java/lang/Object",
+        "hashCode",
+        "()I",
+        |_: &Vm, this: JvmValue<'a>| identity_hash_code(&this),
+    )?;
+
+    vm.register_native(
+        "java/lang/Object",
+        "getClass",
+        "()Ljava/lang/Class;",
+        |vm: &Vm<'a>, this: JvmValue<'a>| {
+            // The component types of reference arrays aren't tracked, so they are all treated as
+            // arrays of Object
+            let name = match this {
+                JvmValue::Reference(object) if object != 0 => {
+                    match unsafe { &*(object as *const RefTypeHeader) } {
+                        RefTypeHeader::Array(array) => match array.element_type {
+                            ArrayElementType::Primitive(t) => format!("[{}", t.descriptor()),
+                            ArrayElementType::Reference => "[Ljava/lang/Object;".to_owned(),
+                        },
+                        RefTypeHeader::Object(_) => vm.runtime_class(&this)?.name().to_owned(),
+                    }
+                }
+                _ => vm.runtime_class(&this)?.name().to_owned(),
+            };
+
+            Ok(Reference(vm.class_mirror(&name)?))
+        },
+    )?;
+
+    // Stack traces aren't recorded in exceptions, so they're always empty
+    vm.register_native(
+        "java/lang/Throwable",
+        "fillInStackTrace",
+        "(I)Ljava/lang/Throwable;",
+        |_: &Vm, this: Reference, _: i32| Ok(this),
+    )?;
+
+    // The vm doesn't describe which part of an expression was null, so the message is the one
+    // given when the exception was created, if any
+    vm.register_native(
+        "java/lang/NullPointerException",
+        "getExtendedNPEMessage",
+        "()Ljava/lang/String;",
+        |_: &Vm, _: Reference| Ok(None::<String>),
+    )?;
+
+    register_class_natives(vm)?;
+    register_property_natives(vm)?;
+    register_runtime_natives(vm)?;
+    register_math_natives(vm);
+    register_box_natives(vm)?;
+    io::register(vm)?;
+    misc::register(vm)?;
+    reflect::register(vm)?;
+    scanner::register(vm)?;
+    class_loader::register(vm)?;
+    string::register_string(vm)?;
+    string::register_string_builder(vm)?;
+
+    // Used by many classes to cache field and method ids for their natives
+    vm.register_native("*", "initIDs", "()V", |_: &Vm| Ok(()))?;
+
+    // Replaces the JDK implementation, which builds the string with StringBuilder.
+    vm.register_native(
+        "java/lang/Object",
+        "toString",
+        "()Ljava/lang/String;",
+        |vm: &Vm, this: Reference| {
+            let RefTypeHeader::Object(object) = (unsafe { &*(this.0 as *const RefTypeHeader) })
+            else {
+                bail!("expected an object");
+            };
+
+            let class = unsafe { object.class.as_ref() };
+            let hash_code = vm
+                .invoke_virtual(this.0, "hashCode", "()I", &[])?
+                .and_then(|v| v.try_as_int())
+                .wrap_err("expected int")?;
+
+            Ok(format!("{}@{hash_code:x}", class.name().replace('/', ".")))
+        },
+    )?;
+
+    vm.register_native(
+        "java/lang/Float",
+        "floatToRawIntBits",
+        "(F)I",
+        |_: &Vm, value: f32| Ok(value.to_bits() as i32),
+    )?;
+
+    vm.register_native(
+        "java/lang/Float",
+        "intBitsToFloat",
+        "(I)F",
+        |_: &Vm, bits: i32| Ok(f32::from_bits(bits as u32)),
+    )?;
+
+    vm.register_native(
+        "java/lang/Double",
+        "doubleToRawLongBits",
+        "(D)J",
+        |_: &Vm, value: f64| Ok(value.to_bits() as i64),
+    )?;
+
+    vm.register_native(
+        "java/lang/Double",
+        "longBitsToDouble",
+        "(J)D",
+        |_: &Vm, bits: i64| Ok(f64::from_bits(bits as u64)),
+    )?;
+
+    vm.register_native(
+        "java/lang/Thread",
+        "sleep",
+        "(J)V",
+        |vm: &Vm, millis: i64| {
+            let Ok(millis) = u64::try_from(millis) else {
+                bail!(JavaException::new(
+                    "java/lang/IllegalArgumentException",
+                    "timeout value is negative"
+                ));
+            };
+
+            let interrupted = vm.sleep(Duration::from_millis(millis));
+            vm.check_stopped()?;
+
+            if interrupted {
+                // The interrupt status is cleared when InterruptedException is thrown
+                vm.set_field(vm.current_thread()?, "interrupted", "Z", JvmValue::Int(0))?;
+
+                bail!(JavaException::new(
+                    "java/lang/InterruptedException",
+                    "sleep interrupted"
+                ));
+            }
+
+            Ok(())
+        },
+    )?;
+
+    vm.register_native("java/lang/Thread", "yield", "()V", |_: &Vm| {
+        std::thread::yield_now();
+        Ok(())
+    })?;
+
+    vm.register_native(
+        "java/lang/Thread",
+        "interrupt0",
+        "()V",
+        |vm: &Vm, this: Reference| {
+            // Threads other than the host thread never run, so there is nothing to interrupt
+            if vm.is_current_thread(this.0) {
+                vm.interrupt();
+            }
+
+            Ok(())
+        },
+    )?;
+
+    // Called by `Thread.interrupted` after it clears the interrupt status of the current thread
+    vm.register_native(
+        "java/lang/Thread",
+        "clearInterruptEvent",
+        "()V",
+        |vm: &Vm| {
+            vm.take_interrupted();
+            Ok(())
+        },
+    )?;
+
+    vm.register_native(
+        "java/lang/Thread",
+        "currentThread",
+        "()Ljava/lang/Thread;",
+        |vm: &Vm| Ok(Reference(vm.current_thread()?)),
+    )?;
+
+    // Replaces the JDK implementation, which depends on security managers and thread groups.
+    vm.register_native(
+        "java/lang/Thread",
+        "setPriority",
+        "(I)V",
+        |vm: &Vm, this: Reference, priority: i32| {
+            // Thread.MIN_PRIORITY and Thread.MAX_PRIORITY
+            if !(1..=10).contains(&priority) {
+                bail!(JavaException::new(
+                    "java/lang/IllegalArgumentException",
+                    "priority out of range"
+                ));
+            }
+
+            vm.set_field(this.0, "priority", "I", JvmValue::Int(priority))?;
+
+            Ok(())
+        },
+    )?;
+
+    // The JDK constructors depend on security managers and thread groups, so the common public
+    // ones are replaced with a direct initialization of the thread's fields.
+    vm.register_native(
+        "java/lang/Thread",
+        "<init>",
+        "()V",
+        |vm: &Vm, this: Reference| vm.init_thread(this.0, None, 0),
+    )?;
+
+    vm.register_native(
+        "java/lang/Thread",
+        "<init>",
+        "(Ljava/lang/Runnable;)V",
+        |vm: &Vm, this: Reference, target: Option<Reference>| {
+            vm.init_thread(this.0, None, target.map_or(0, |target| target.0))
+        },
+    )?;
+
+    vm.register_native(
+        "java/lang/Thread",
+        "<init>",
+        "(Ljava/lang/String;)V",
+        |vm: &Vm<'a>, this: Reference, name: Option<&'a str>| {
+            vm.init_thread(this.0, Some(thread_name(name)?), 0)
+        },
+    )?;
+
+    vm.register_native(
+        "java/lang/Thread",
+        "<init>",
+        "(Ljava/lang/Runnable;Ljava/lang/String;)V",
+        |vm: &Vm<'a>, this: Reference, target: Option<Reference>, name: Option<&'a str>| {
+            let target = target.map_or(0, |target| target.0);
+            vm.init_thread(this.0, Some(thread_name(name)?), target)
+        },
+    )?;
+
+    // Host threads are scheduled by the OS, so priorities are only advisory.
+    vm.register_native(
+        "java/lang/Thread",
+        "setPriority0",
+        "(I)V",
+        |_: &Vm, _: Reference, _: i32| Ok(()),
+    )?;
+
+    Ok(())
+}
+
+/// Natives for `java.lang.Class`. Some of these replace methods which are implemented in Java
+/// by the JDK, but depend on reflection machinery that the vm doesn't support yet.
+fn register_class_natives<'a>(vm: &Vm<'a>) -> Result<()> {
+    const CLASS: &str = "java/lang/Class";
+
+    vm.register_native(
+        CLASS,
+        "getName",
+        "()Ljava/lang/String;",
+        |vm: &Vm, this: Reference| Ok(mirrored_class_name(vm, this)?.replace('/', ".")),
+    )?;
+
+    vm.register_native(
+        CLASS,
+        "getSimpleName",
+        "()Ljava/lang/String;",
+        |vm: &Vm, this: Reference| Ok(simple_name(mirrored_class_name(vm, this)?)),
+    )?;
+
+    vm.register_native(CLASS, "isInterface", "()Z", |vm: &Vm, this: Reference| {
+        let name = mirrored_class_name(vm, this)?;
+        Ok(!is_primitive(name)
+            && !name.starts_with('[')
+            && vm
+                .load_class_file(name)?
+                .access_flags()
+                .contains(ClassAccessFlags::INTERFACE))
+    })?;
+
+    vm.register_native(CLASS, "isArray", "()Z", |vm: &Vm, this: Reference| {
+        Ok(mirrored_class_name(vm, this)?.starts_with('['))
+    })?;
+
+    vm.register_native(CLASS, "isPrimitive", "()Z", |vm: &Vm, this: Reference| {
+        Ok(is_primitive(mirrored_class_name(vm, this)?))
+    })?;
+
+    vm.register_native(
+        CLASS,
+        "getComponentType",
+        "()Ljava/lang/Class;",
+        |vm: &Vm, this: Reference| {
+            let name = mirrored_class_name(vm, this)?;
+            match component_name(name) {
+                Some(component) => Ok(Some(Reference(vm.class_mirror(component)?))),
+                None => Ok(None),
+            }
+        },
+    )?;
+
+    vm.register_native(
+        CLASS,
+        "getPrimitiveClass",
+        "(Ljava/lang/String;)Ljava/lang/Class;",
+        |vm: &Vm<'a>, name: &'a str| {
+            if !is_primitive(name) {
+                bail!(JavaException::new(
+                    "java/lang/IllegalArgumentException",
+                    format!("not a primitive type: {name}")
+                ));
+            }
+
+            Ok(Reference(vm.class_mirror(name)?))
+        },
+    )?;
+
+    // Class loaders aren't supported yet, so the loader is ignored and classes are always loaded
+    // by the vm. The public methods are replaced since they look up the caller by stack walking.
+    vm.register_native(
+        CLASS,
+        "forName",
+        "(Ljava/lang/String;)Ljava/lang/Class;",
+        |vm: &Vm<'a>, name: Option<&'a str>| for_name(vm, name, true),
+    )?;
+
+    vm.register_native(
+        CLASS,
+        "forName",
+        "(Ljava/lang/String;ZLjava/lang/ClassLoader;)Ljava/lang/Class;",
+        |vm: &Vm<'a>, name: Option<&'a str>, initialize: bool, _: JvmValue<'a>| {
+            for_name(vm, name, initialize)
+        },
+    )?;
+
+    vm.register_native(
+        CLASS,
+        "forName0",
+        "(Ljava/lang/String;ZLjava/lang/ClassLoader;Ljava/lang/Class;)Ljava/lang/Class;",
+        |vm: &Vm<'a>, name: Option<&'a str>, initialize: bool, _: JvmValue<'a>, _: JvmValue<'a>| {
+            for_name(vm, name, initialize)
+        },
+    )?;
+
+    // Used by getTypeParameters and getGenericSuperclass, which parse the signature in Java
+    vm.register_native(
+        CLASS,
+        "getGenericSignature0",
+        "()Ljava/lang/String;",
+        |vm: &Vm<'a>, this: Reference| {
+            let name = mirrored_class_name(vm, this)?;
+            let signature = match is_primitive(name) || name.starts_with('[') {
+                true => None,
+                false => vm.load_class(name)?.signature(),
+            };
+            Ok(signature_string(vm, signature))
+        },
+    )?;
+
+    // Classes with assert statements call this from their static initializer
+    vm.register_native(
+        CLASS,
+        "desiredAssertionStatus",
+        "()Z",
+        |vm: &Vm, this: Reference| {
+            let name = mirrored_class_name(vm, this)?;
+            Ok(!is_primitive(name)
+                && !name.starts_with('[')
+                && vm.assertions_enabled(vm.load_class(name)?))
+        },
+    )?;
+
+    Ok(())
+}
+
+/// Replacements for the `java.lang.System` property methods, which are backed by the vm's
+/// property map rather than a `java.util.Properties` instance.
+fn register_property_natives<'a>(vm: &Vm<'a>) -> Result<()> {
+    const SYSTEM: &str = "java/lang/System";
+
+    vm.register_native(
+        SYSTEM,
+        "getProperty",
+        "(Ljava/lang/String;)Ljava/lang/String;",
+        |vm: &Vm<'a>, key: Option<&'a str>| Ok(vm.property(property_key(key)?)),
+    )?;
+
+    vm.register_native(
+        SYSTEM,
+        "getProperty",
+        "(Ljava/lang/String;Ljava/lang/String;)Ljava/lang/String;",
+        |vm: &Vm<'a>, key: Option<&'a str>, default: JvmValue<'a>| {
+            Ok(match vm.property(property_key(key)?) {
+                Some(value) => vm.new_string(&value),
+                None => default,
+            })
+        },
+    )?;
+
+    vm.register_native(
+        SYSTEM,
+        "setProperty",
+        "(Ljava/lang/String;Ljava/lang/String;)Ljava/lang/String;",
+        |vm: &Vm<'a>, key: Option<&'a str>, value: Option<&'a str>| {
+            let key = property_key(key)?;
+            let value = value
+                .ok_or_else(|| JavaException::new("java/lang/NullPointerException", "value"))?;
+            Ok(vm.set_property(key, value))
+        },
+    )?;
+
+    vm.register_native(
+        SYSTEM,
+        "clearProperty",
+        "(Ljava/lang/String;)Ljava/lang/String;",
+        |vm: &Vm<'a>, key: Option<&'a str>| Ok(vm.clear_property(property_key(key)?)),
+    )?;
+
+    vm.register_native(
+        SYSTEM,
+        "lineSeparator",
+        "()Ljava/lang/String;",
+        |vm: &Vm| Ok(vm.property("line.separator").unwrap_or_default()),
+    )?;
+
+    Ok(())
+}
+
+/// Replacements for the `java.lang.Runtime` methods used to exit and to register shutdown hooks,
+/// since the JDK implementations depend on security managers and `java.util` collections.
+fn register_runtime_natives(vm: &Vm) -> Result<()> {
+    vm.register_native(
+        "java/lang/Runtime",
+        "availableProcessors",
+        "()I",
+        |_: &Vm, _: Reference| {
+            Ok(std::thread::available_parallelism().map_or(1, |n| n.get() as i32))
+        },
+    )?;
+
+    vm.register_native(
+        "java/lang/System",
+        "exit",
+        "(I)V",
+        |_: &Vm, status: i32| -> Result<()> { bail!(SystemExit { status }) },
+    )?;
+
+    vm.register_native(
+        "java/lang/Runtime",
+        "exit",
+        "(I)V",
+        |_: &Vm, _: Reference, status: i32| -> Result<()> { bail!(SystemExit { status }) },
+    )?;
+
+    vm.register_native(
+        "java/lang/Runtime",
+        "addShutdownHook",
+        "(Ljava/lang/Thread;)V",
+        |vm: &Vm, _: Reference, hook: Option<Reference>| {
+            if !vm.add_shutdown_hook(shutdown_hook(hook)?) {
+                bail!(JavaException::new(
+                    "java/lang/IllegalArgumentException",
+                    "Hook previously registered"
+                ));
+            }
+            Ok(())
+        },
+    )?;
+
+    vm.register_native(
+        "java/lang/Runtime",
+        "removeShutdownHook",
+        "(Ljava/lang/Thread;)Z",
+        |vm: &Vm, _: Reference, hook: Option<Reference>| {
+            Ok(vm.remove_shutdown_hook(shutdown_hook(hook)?))
+        },
+    )?;
+
+    Ok(())
+}
+
+/// Natives for `java.lang.StrictMath`, which also replace the equivalent methods of
+/// `java.lang.Math`. StrictMath's results are specified to match fdlibm bit for bit, so most of
+/// these are ports of it, and the rest are exact in Rust's standard library. Some of these are
+/// implemented in Java by the JDK, but bit manipulation of doubles is slow in the interpreter.
+/// `pow` is left to run as bytecode.
+fn register_math_natives(vm: &Vm) {
+    let unary = [
+        ("sin", fdlibm::sin as fn(f64) -> f64),
+        ("cos", fdlibm::cos),
+        ("tan", fdlibm::tan),
+        ("asin", fdlibm::asin),
+        ("acos", fdlibm::acos),
+        ("atan", fdlibm::atan),
+        ("sinh", fdlibm::sinh),
+        ("cosh", fdlibm::cosh),
+        ("tanh", fdlibm::tanh),
+        ("exp", fdlibm::exp),
+        ("expm1", fdlibm::expm1),
+        ("log", fdlibm::log),
+        ("log10", fdlibm::log10),
+        ("log1p", fdlibm::log1p),
+        ("sqrt", f64::sqrt),
+        ("cbrt", fdlibm::cbrt),
+        ("floor", f64::floor),
+        ("ceil", f64::ceil),
+        ("rint", f64::round_ties_even),
+    ];
+
+    let binary = [
+        ("atan2", fdlibm::atan2 as fn(f64, f64) -> f64),
+        ("hypot", fdlibm::hypot),
+        ("IEEEremainder", fdlibm::remainder),
+    ];
+
+    for class in ["java/lang/Math", "java/lang/StrictMath"] {
+        for (name, f) in unary {
+            vm.register_fn(class, name, move |_: &Vm, a: f64| Ok(f(a)));
+        }
+
+        for (name, f) in binary {
+            vm.register_fn(class, name, move |_: &Vm, a: f64, b: f64| Ok(f(a, b)));
+        }
+    }
+}
+
+/// The wrapper class of each primitive type, by descriptor, along with the method which unboxes
+/// its values.
+const WRAPPERS: [(&str, &str, &str); 8] = [
+    ("Z", "java/lang/Boolean", "booleanValue"),
+    ("B", "java/lang/Byte", "byteValue"),
+    ("C", "java/lang/Character", "charValue"),
+    ("S", "java/lang/Short", "shortValue"),
+    ("I", "java/lang/Integer", "intValue"),
+    ("J", "java/lang/Long", "longValue"),
+    ("F", "java/lang/Float", "floatValue"),
+    ("D", "java/lang/Double", "doubleValue"),
+];
+
+/// Returns the name of a primitive wrapper class as a static string, which is how the vm's cache
+/// of boxes is keyed.
+pub(crate) fn wrapper_class_name(name: &str) -> Option<&'static str> {
+    WRAPPERS
+        .into_iter()
+        .map(|(_, class, _)| class)
+        .find(|class| *class == name)
+}
+
+/// Replaces `valueOf` for the integral wrapper classes, since the JDK's caches of boxed values
+/// are initialized using parts of the JDK that the vm doesn't support yet. The methods which
+/// unbox values are also replaced, to avoid creating a frame for them.
+fn register_box_natives<'a>(vm: &Vm<'a>) -> Result<()> {
+    for (descriptor, class, value_method) in WRAPPERS {
+        if !matches!(descriptor, "B" | "C" | "S" | "I" | "J") {
+            continue;
+        }
+
+        let value_of = format!("({descriptor})L{class};");
+        vm.register_native(
+            class,
+            "valueOf",
+            &value_of,
+            move |vm: &Vm<'a>, value: JvmValue<'a>| {
+                Ok(Reference(box_primitive(vm, descriptor, value)?))
+            },
+        )?;
+
+        let unbox = format!("(){descriptor}");
+        vm.register_native(
+            class,
+            value_method,
+            &unbox,
+            move |vm: &Vm<'a>, this: Reference| vm.get_field(this.0, "value", descriptor),
+        )?;
+    }
+
+    // The JDK implementations convert with FloatingDecimal, which the vm can't run yet
+    vm.register_native(
+        "java/lang/Double",
+        "toString",
+        "(D)Ljava/lang/String;",
+        |_: &Vm, value: f64| Ok(format_float(value, value.to_string())),
+    )?;
+
+    vm.register_native(
+        "java/lang/Float",
+        "toString",
+        "(F)Ljava/lang/String;",
+        |_: &Vm, value: f32| Ok(format_float(value as f64, value.to_string())),
+    )?;
+
+    Ok(())
+}
+
+/// Boxes a primitive value, given the descriptor of its type. Like the `valueOf` methods of the
+/// wrapper classes, values in the same ranges as the JDK's caches are only boxed once, so that
+/// they are identical when compared with `==`.
+fn box_primitive<'a>(vm: &Vm<'a>, descriptor: &str, value: JvmValue<'a>) -> Result<usize> {
+    let (descriptor, class, _) = WRAPPERS
+        .into_iter()
+        .find(|(d, ..)| *d == descriptor)
+        .wrap_err_with(|| format_err!("not a primitive type: {descriptor}"))?;
+
+    let wrapper = vm.load_class_file(class)?;
+
+    if descriptor == "Z" {
+        let name = if value.try_as_int() == Some(0) {
+            "FALSE"
+        } else {
+            "TRUE"
+        };
+        return wrapper
+            .static_field(name, "Ljava/lang/Boolean;")
+            .wrap_err("missing field")?
+            .lock()
+            .unwrap()
+            .try_as_reference_ref()
+            .copied()
+            .wrap_err("expected reference");
+    }
+
+    let key = match (descriptor, &value) {
+        ("C", JvmValue::Int(v @ 0..=127)) => Some(*v as i64),
+        ("B" | "S" | "I", JvmValue::Int(v @ -128..=127)) => Some(*v as i64),
+        ("J", JvmValue::Long(v @ -128..=127)) => Some(*v),
+        _ => None,
+    };
+
+    let alloc = || -> Result<usize> {
+        let object = vm.alloc_object(wrapper)?;
+        vm.set_field(object, "value", descriptor, value.clone())?;
+        Ok(object)
+    };
+
+    let Some(key) = key else {
+        return alloc();
+    };
+
+    let mut boxes = vm.boxes();
+    match boxes.get(&(class, key)) {
+        Some(object) => Ok(*object),
+        None => {
+            let object = alloc()?;
+            boxes.insert((class, key), object);
+            Ok(object)
+        }
+    }
+}
+
+/// Unboxes an instance of a primitive wrapper class, returning the descriptor of the primitive
+/// type along with the value. Returns `None` for other objects.
+fn unbox_primitive<'a>(
+    vm: &Vm<'a>,
+    object: &JvmValue<'a>,
+) -> Result<Option<(&'static str, JvmValue<'a>)>> {
+    let JvmValue::Reference(object) = object else {
+        return Ok(None);
+    };
+
+    if *object == 0 {
+        return Ok(None);
+    }
+
+    let class = vm.runtime_class(&JvmValue::Reference(*object))?;
+    let Some((descriptor, ..)) = WRAPPERS.into_iter().find(|(_, c, _)| *c == class.name()) else {
+        return Ok(None);
+    };
+
+    Ok(Some((
+        descriptor,
+        vm.get_field(*object, "value", descriptor)?,
+    )))
+}
+
+fn shutdown_hook(hook: Option<Reference>) -> Result<usize> {
+    match hook {
+        Some(hook) => Ok(hook.0),
+        None => bail!(JavaException::new("java/lang/NullPointerException", "hook")),
+    }
+}
+
+/// Validates the name passed to a `Thread` constructor.
+fn thread_name(name: Option<&str>) -> Result<&str> {
+    name.ok_or_else(|| {
+        JavaException::new("java/lang/NullPointerException", "name cannot be null").into()
+    })
+}
+
+/// Validates the key passed to a property method, like `System.checkKey`.
+fn property_key(key: Option<&str>) -> Result<&str> {
+    match key {
+        Some("") => bail!(JavaException::new(
+            "java/lang/IllegalArgumentException",
+            "key can't be empty"
+        )),
+        Some(key) => Ok(key),
+        None => bail!(JavaException::new(
+            "java/lang/NullPointerException",
+            "key can't be null"
+        )),
+    }
+}
+
+/// Returns the name of the class represented by the receiver of a `java.lang.Class` method.
+fn mirrored_class_name<'a>(vm: &Vm<'a>, mirror: Reference) -> Result<&'a str> {
+    vm.class_mirror_name(mirror.0)
+        .wrap_err("receiver is not a java.lang.Class")
+}
+
+/// Converts a generic signature to a Java string, or null if there is no signature.
+fn signature_string<'a>(vm: &Vm<'a>, signature: Option<&str>) -> JvmValue<'a> {
+    match signature {
+        Some(signature) => JvmValue::StringConst(vm.intern(signature)),
+        None => JvmValue::Reference(0),
+    }
+}
+
+/// Loads a class by its binary name (e.g. `java.lang.String` or `[Ljava.lang.String;`) and
+/// returns its mirror, initializing it if requested, like `Class.forName`.
+fn for_name(vm: &Vm, name: Option<&str>, initialize: bool) -> Result<Reference> {
+    let Some(name) = name else {
+        bail!(JavaException::null_pointer());
+    };
+
+    let not_found = || JavaException::new("java/lang/ClassNotFoundException", name);
+
+    // Primitive types can't be loaded by name, and binary names never contain slashes
+    if is_primitive(name) || name.contains('/') || name.is_empty() {
+        bail!(not_found());
+    }
+
+    let internal_name = name.replace('.', "/");
+    let element_type = internal_name.trim_start_matches('[');
+
+    let class_name = if element_type.len() == internal_name.len() {
+        Some(element_type)
+    } else if let Some(class_name) = element_type
+        .strip_prefix('L')
+        .and_then(|c| c.strip_suffix(';'))
+    {
+        Some(class_name)
+    } else if matches!(element_type, "Z" | "B" | "C" | "S" | "I" | "J" | "F" | "D") {
+        None
+    } else {
+        bail!(not_found());
+    };
+
+    if let Some(class_name) = class_name {
+        let class = match vm.load_class(class_name) {
+            Ok(class) => class,
+            Err(e) if is_missing_class(&e, class_name) => bail!(not_found()),
+            Err(e) => return Err(e),
+        };
+
+        // Array classes are never initialized, even if their element type is a class
+        if initialize && class_name.len() == internal_name.len() {
+            vm.initialize_class(class)?;
+        }
+    }
+
+    Ok(Reference(vm.class_mirror(&internal_name)?))
+}
+
+fn is_primitive(name: &str) -> bool {
+    matches!(
+        name,
+        "boolean" | "byte" | "char" | "short" | "int" | "long" | "float" | "double" | "void"
+    )
+}
+
+/// Returns the class name of the component type of an array class, e.g. `int` for `[I` or
+/// `java/lang/String` for `[Ljava/lang/String;`.
+fn component_name(name: &str) -> Option<&str> {
+    let component = name.strip_prefix('[')?;
+
+    Some(match component {
+        "Z" => "boolean",
+        "B" => "byte",
+        "C" => "char",
+        "S" => "short",
+        "I" => "int",
+        "J" => "long",
+        "F" => "float",
+        "D" => "double",
+        _ => component
+            .strip_prefix('L')
+            .and_then(|c| c.strip_suffix(';'))
+            .unwrap_or(component),
+    })
+}
+
+fn simple_name(name: &str) -> String {
+    if let Some(component) = component_name(name) {
+        return simple_name(component) + "[]";
+    }
+
+    let name = name.rsplit('/').next().unwrap_or(name);
+
+    // TODO: Use the InnerClasses attribute rather than guessing from the binary name. This
+    // gives the right answer for member, local and anonymous classes compiled by javac.
+    match name.rsplit_once('$') {
+        Some((_, inner)) => inner.trim_start_matches(|c: char| c.is_ascii_digit()),
+        None => name,
+    }
+    .to_owned()
+}
+
+fn array_copy(src: usize, src_pos: i32, dest: usize, dest_pos: i32, length: i32) -> Result<()> {
+    if src == 0 || dest == 0 {
+        bail!(JavaException::new(
+            "java/lang/NullPointerException",
+            "arraycopy"
+        ));
+    }
+
+    // SAFETY: Non-null references always point to a valid header.
+    let src = unsafe { &mut *(src as *mut RefTypeHeader) };
+    let dest = unsafe { &mut *(dest as *mut RefTypeHeader) };
+
+    let (RefTypeHeader::Array(src_array), RefTypeHeader::Array(dest_array)) = (&*src, &*dest)
+    else {
+        bail!(JavaException::new(
+            "java/lang/ArrayStoreException",
+            "arraycopy: argument type is not an array"
+        ));
+    };
+
+    let element_type = src_array.element_type;
+
+    // TODO: Check that reference elements are assignable to the destination component type
+    // once array component classes are tracked.
+    match (src_array.element_type, dest_array.element_type) {
+        (ArrayElementType::Primitive(a), ArrayElementType::Primitive(b)) if a == b => {}
+        (ArrayElementType::Reference, ArrayElementType::Reference) => {}
+        _ => bail!(JavaException::new(
+            "java/lang/ArrayStoreException",
+            "arraycopy: type mismatch"
+        )),
+    }
+
+    let in_bounds = |pos: i32, array_length: usize| {
+        pos >= 0 && length >= 0 && pos as usize + length as usize <= array_length
+    };
+
+    if !in_bounds(src_pos, src_array.length) || !in_bounds(dest_pos, dest_array.length) {
+        bail!(JavaException::new(
+            "java/lang/ArrayIndexOutOfBoundsException",
+            format!(
+                "arraycopy: range [{src_pos}, {src_pos} + {length}) out of bounds for length {} \
+                 or [{dest_pos}, {dest_pos} + {length}) out of bounds for length {}",
+                src_array.length, dest_array.length
+            )
+        ));
+    }
+
+    let element_size = element_type.element_layout().size();
+
+    unsafe {
+        let src_ptr = src.array_data_ptr()?.add(src_pos as usize * element_size);
+        let dest_ptr = dest.array_data_ptr()?.add(dest_pos as usize * element_size);
+        // The arrays may be the same, so the ranges can overlap.
+        std::ptr::copy(src_ptr, dest_ptr, length as usize * element_size);
+    }
+
+    Ok(())
+}
+
+/// Computes an identity hash code from the address of an object. Objects are never moved, so
+/// this is stable for the lifetime of the object.
+fn identity_hash_code(value: &JvmValue) -> Result<i32> {
+    let address = match value {
+        JvmValue::Reference(address) => *address,
+        JvmValue::StringConst(s) => s.as_ptr() as usize,
+        value => bail!("expected reference, found {value:?}"),
+    };
+
+    Ok(((address >> 3) ^ (address >> 32)) as i32)
+}
+
+/// Converts a value to a string, like `String.valueOf`. `param` is the descriptor of the
+/// parameter which the value was passed as, used to tell booleans and chars apart from ints.
+fn to_java_string(vm: &Vm, param: &str, value: &JvmValue) -> Result<String> {
+    Ok(match (param, value) {
+        ("Z", JvmValue::Int(v)) => (*v != 0).to_string(),
+        ("C", JvmValue::Int(v)) => char::from_u32(*v as u16 as u32)
+            .unwrap_or(char::REPLACEMENT_CHARACTER)
+            .to_string(),
+        (_, JvmValue::Int(v)) => v.to_string(),
+        (_, JvmValue::Long(v)) => v.to_string(),
+        (_, JvmValue::Float(v)) => format_float(*v as f64, v.to_string()),
+        (_, JvmValue::Double(v)) => format_float(*v, v.to_string()),
+        (_, JvmValue::StringConst(v)) => v.to_string(),
+        (_, JvmValue::Reference(0)) => "null".to_owned(),
+        (_, JvmValue::Reference(object)) => {
+            let value = vm
+                .invoke_virtual(*object, "toString", "()Ljava/lang/String;", &[])?
+                .wrap_err("missing return value from toString")?;
+            vm.string_value(&value)?.unwrap_or("null").to_owned()
+        }
+        (_, value) => bail!("unsupported value for conversion to string: {value:?}"),
+    })
+}
+
+fn print_jvm_value(out: &mut dyn Write, value: &JvmValue) -> Result<()> {
+    match value {
+        JvmValue::StringConst(v) => write!(out, "{v}")?,
+        JvmValue::Byte(v) => write!(out, "{v}")?,
+        JvmValue::Int(v) => write!(out, "{v}")?,
+        JvmValue::Long(v) => write!(out, "{v}")?,
+        JvmValue::Float(v) => write!(out, "{}", format_float(*v as f64, v.to_string()))?,
+        JvmValue::Double(v) => write!(out, "{}", format_float(*v, v.to_string()))?,
+        JvmValue::Reference(ptr) => {
+            let header = unsafe { (*ptr as *mut RefTypeHeader).as_mut() };
+
+            match header {
+                None => {
+                    write!(out, "null")?;
+                }
+                Some(header) => match header {
+                    RefTypeHeader::Array(array) => match array.element_type {
+                        ArrayElementType::Primitive(ArrayType::Int) => {
+                            let elements = unsafe { header.array_data::<i32>()? };
+                            write!(out, "{elements:?}")?
+                        }
+                        t => todo!("{t:?}"),
+                    },
+                    RefTypeHeader::Object(object) => {
+                        let class = unsafe { object.class.as_ref() };
+                        let fields = unsafe { header.object_data() }?;
+
+                        write!(out, "{} {{", class.name())?;
+
+                        for (i, field) in class.fields().iter().enumerate() {
+                            let name = field.name;
+                            let value = &fields[i];
+
+                            write!(out, "{name}: ")?;
+
+                            print_jvm_value(out, value)?;
+
+                            if i < fields.len() - 1 {
+                                write!(out, ", ")?;
+                            }
+                        }
+
+                        write!(out, "}}")?;
+                    }
+                },
+            };
+        }
+        arg => todo!("{arg:?}"),
+    }
+
+    Ok(())
+}
+
+/// Formats a floating point number like `Double.toString`, given the shortest decimal string
+/// which represents it (which differs between `float` and `double`).
+fn format_float(value: f64, shortest: String) -> String {
+    if value.is_nan() {
+        return "NaN".to_owned();
+    }
+
+    if value.is_infinite() {
+        return if value > 0.0 { "Infinity" } else { "-Infinity" }.to_owned();
+    }
+
+    let magnitude = value.abs();
+    if magnitude == 0.0 || (1e-3..1e7).contains(&magnitude) {
+        return if shortest.contains('.') {
+            shortest
+        } else {
+            shortest + ".0"
+        };
+    }
+
+    // Computerized scientific notation, e.g. 1.0E10
+    let (sign, digits) = match shortest.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", shortest.as_str()),
+    };
+
+    let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    let digits = format!("{integer}{fraction}");
+    let leading_zeros = digits.len() - digits.trim_start_matches('0').len();
+    let significant = digits.trim_matches('0');
+    let exponent = integer.len() as i32 - leading_zeros as i32 - 1;
+
+    let (first, rest) = significant.split_at(1);
+    let rest = if rest.is_empty() { "0" } else { rest };
+
+    format!("{sign}{first}.{rest}E{exponent}")
+}
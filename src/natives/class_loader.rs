@@ -0,0 +1,356 @@
+//! Natives for `java.lang.ClassLoader`. The platform and application class loaders are instances
+//! of the JDK classes, but their methods which load classes are replaced by natives backed by the
+//! vm's class loading.
+
+use crate::call_frame::{JavaException, JvmValue};
+use crate::convert::Reference;
+use crate::error::{bail, ContextCompat, Error, Result};
+use crate::vm::{BuiltinLoaders, Vm};
+
+use super::io::byte_range;
+use super::{component_name, is_primitive, mirrored_class_name};
+
+const CLASS_LOADER: &str = "java/lang/ClassLoader";
+const BUILTIN_CLASS_LOADER: &str = "jdk/internal/loader/BuiltinClassLoader";
+const APP_CLASS_LOADER: &str = "jdk/internal/loader/ClassLoaders$AppClassLoader";
+const PLATFORM_CLASS_LOADER: &str = "jdk/internal/loader/ClassLoaders$PlatformClassLoader";
+
+/// Creates the platform and application class loaders. The loader classes aren't initialized,
+/// since their static initializers set up the module system and class path, which the vm
+/// doesn't support yet.
+pub(crate) fn create_builtin_loaders(vm: &Vm) -> Result<BuiltinLoaders> {
+    let platform = vm.alloc_object(vm.load_class(PLATFORM_CLASS_LOADER)?)?;
+    init_loader(vm, platform, Some("platform"), 0)?;
+
+    let app = vm.alloc_object(vm.load_class(APP_CLASS_LOADER)?)?;
+    init_loader(vm, app, Some("app"), platform)?;
+
+    Ok(BuiltinLoaders { platform, app })
+}
+
+pub(super) fn register<'a>(vm: &Vm<'a>) -> Result<()> {
+    for name in ["getClassLoader", "getClassLoader0"] {
+        vm.register_native(
+            "java/lang/Class",
+            name,
+            "()Ljava/lang/ClassLoader;",
+            |vm: &Vm<'a>, this: Reference| {
+                let mut name = mirrored_class_name(vm, this)?;
+
+                // Arrays have the loader of their element type
+                while let Some(component) = component_name(name) {
+                    name = component;
+                }
+
+                let loader = match is_primitive(name) {
+                    true => 0,
+                    false => vm.defining_loader(vm.load_class(name)?),
+                };
+
+                Ok(Reference(loader))
+            },
+        )?;
+    }
+
+    vm.register_native(
+        CLASS_LOADER,
+        "getSystemClassLoader",
+        "()Ljava/lang/ClassLoader;",
+        |vm: &Vm| Ok(Reference(vm.builtin_loaders()?.app)),
+    )?;
+
+    vm.register_native(
+        CLASS_LOADER,
+        "getPlatformClassLoader",
+        "()Ljava/lang/ClassLoader;",
+        |vm: &Vm| Ok(Reference(vm.builtin_loaders()?.platform)),
+    )?;
+
+    // The JDK implementation checks permissions using the security manager
+    vm.register_native(
+        CLASS_LOADER,
+        "getParent",
+        "()Ljava/lang/ClassLoader;",
+        |vm: &Vm, this: Reference| Ok(Reference(parent(vm, this.0)?)),
+    )?;
+
+    // The JDK constructors set up locks and the loader's unnamed module, which aren't needed
+    // by the vm, so only the name and parent are set. Loaders created without a parent delegate
+    // to the application class loader.
+    vm.register_native(CLASS_LOADER, "<init>", "()V", |vm: &Vm, this: Reference| {
+        let app = vm.builtin_loaders()?.app;
+        init_loader(vm, this.0, None, app)
+    })?;
+
+    vm.register_native(
+        CLASS_LOADER,
+        "<init>",
+        "(Ljava/lang/ClassLoader;)V",
+        |vm: &Vm, this: Reference, parent: Option<Reference>| {
+            init_loader(vm, this.0, None, parent.map_or(0, |parent| parent.0))
+        },
+    )?;
+
+    vm.register_native(
+        CLASS_LOADER,
+        "<init>",
+        "(Ljava/lang/String;Ljava/lang/ClassLoader;)V",
+        |vm: &Vm, this: Reference, name: Option<&'a str>, parent: Option<Reference>| {
+            if name == Some("") {
+                bail!(JavaException::new(
+                    "java/lang/IllegalArgumentException",
+                    "name must be non-empty or null"
+                ));
+            }
+
+            init_loader(vm, this.0, name, parent.map_or(0, |parent| parent.0))
+        },
+    )?;
+
+    // Replaces the JDK implementations, which synchronize on a per-class lock, and look up
+    // classes in the module graph for the built-in loaders
+    for class in [CLASS_LOADER, BUILTIN_CLASS_LOADER, APP_CLASS_LOADER] {
+        vm.register_native(
+            class,
+            "loadClass",
+            "(Ljava/lang/String;Z)Ljava/lang/Class;",
+            |vm: &Vm<'a>, this: Reference, name: JvmValue<'a>, _: bool| {
+                Ok(Reference(load_class(vm, this.0, &name)?))
+            },
+        )?;
+    }
+
+    // The JDK implementations check the package of the class and record its certificates
+    // before calling into the vm, which relies on state set up by the JDK constructors
+    vm.register_native(
+        CLASS_LOADER,
+        "defineClass",
+        "([BII)Ljava/lang/Class;",
+        |vm: &Vm<'a>, this: Reference, bytes: JvmValue<'a>, off: i32, len: i32| {
+            let name = JvmValue::Reference(0);
+            Ok(Reference(define_class(
+                vm, this.0, &name, &bytes, off, len,
+            )?))
+        },
+    )?;
+
+    vm.register_native(
+        CLASS_LOADER,
+        "defineClass",
+        "(Ljava/lang/String;[BII)Ljava/lang/Class;",
+        |vm: &Vm<'a>,
+         this: Reference,
+         name: JvmValue<'a>,
+         bytes: JvmValue<'a>,
+         off: i32,
+         len: i32| {
+            Ok(Reference(define_class(
+                vm, this.0, &name, &bytes, off, len,
+            )?))
+        },
+    )?;
+
+    vm.register_native(
+        CLASS_LOADER,
+        "defineClass",
+        "(Ljava/lang/String;[BIILjava/security/ProtectionDomain;)Ljava/lang/Class;",
+        |vm: &Vm<'a>,
+         this: Reference,
+         name: JvmValue<'a>,
+         bytes: JvmValue<'a>,
+         off: i32,
+         len: i32,
+         _: Option<Reference>| {
+            Ok(Reference(define_class(
+                vm, this.0, &name, &bytes, off, len,
+            )?))
+        },
+    )?;
+
+    vm.register_native(
+        CLASS_LOADER,
+        "findLoadedClass",
+        "(Ljava/lang/String;)Ljava/lang/Class;",
+        |vm: &Vm<'a>, this: Reference, name: JvmValue<'a>| {
+            let mirror = match internal_name(&name)? {
+                Some(name) => match vm.find_loaded_class(&name, this.0) {
+                    Some(class) => vm.class_mirror(class.name())?,
+                    None => 0,
+                },
+                None => 0,
+            };
+
+            Ok(Reference(mirror))
+        },
+    )?;
+
+    vm.register_native(
+        CLASS_LOADER,
+        "findClass",
+        "(Ljava/lang/String;)Ljava/lang/Class;",
+        |_: &Vm<'a>, _: Reference, name: JvmValue<'a>| -> Result<Reference> {
+            bail!(class_not_found(&name))
+        },
+    )?;
+
+    // Only the application class loader finds classes itself, on the file system
+    vm.register_native(
+        BUILTIN_CLASS_LOADER,
+        "findClass",
+        "(Ljava/lang/String;)Ljava/lang/Class;",
+        |vm: &Vm<'a>, this: Reference, name: JvmValue<'a>| {
+            if this.0 == vm.builtin_loaders()?.app {
+                if let Some(internal_name) = internal_name(&name)? {
+                    if let Some(class) = vm.load_app_class(&internal_name)? {
+                        return Ok(Reference(vm.class_mirror(class.name())?));
+                    }
+                }
+            }
+
+            bail!(class_not_found(&name))
+        },
+    )?;
+
+    Ok(())
+}
+
+/// Defines a class from a range of a byte array, like `ClassLoader.defineClass`. If a name is
+/// given, it must match the name in the class file.
+fn define_class<'a>(
+    vm: &Vm<'a>,
+    loader: usize,
+    name: &JvmValue<'a>,
+    bytes: &JvmValue<'a>,
+    off: i32,
+    len: i32,
+) -> Result<usize> {
+    let name = match name {
+        JvmValue::StringConst(name) => Some(*name),
+        JvmValue::Reference(0) => None,
+        _ => bail!("expected string"),
+    };
+
+    // Only the bootstrap loader can define classes in the java.* packages
+    if let Some((package, _)) = name
+        .and_then(|name| name.rsplit_once('.'))
+        .filter(|(package, _)| *package == "java" || package.starts_with("java."))
+    {
+        bail!(JavaException::new(
+            "java/lang/SecurityException",
+            format!("Prohibited package name: {package}")
+        ));
+    }
+
+    let bytes = byte_range(bytes, off, len)?;
+    let internal_name = name.map(|name| name.replace('.', "/"));
+    let class = vm.define_class_with_loader(bytes, loader, internal_name.as_deref())?;
+
+    vm.class_mirror(class.name())
+}
+
+/// Loads a class using the parent delegation model, like `ClassLoader.loadClass`. The class is
+/// looked up in the loader's own classes first, then loaded by its parent, or the bootstrap
+/// loader for loaders without a parent. If neither finds it, the loader's `findClass` is called.
+fn load_class<'a>(vm: &Vm<'a>, loader: usize, name: &JvmValue<'a>) -> Result<usize> {
+    let Some(internal_name) = internal_name(name)? else {
+        bail!(class_not_found(name));
+    };
+
+    if let Some(class) = vm.find_loaded_class(&internal_name, loader) {
+        return vm.class_mirror(class.name());
+    }
+
+    let parent = parent(vm, loader)?;
+    let mirror = if parent == 0 {
+        match vm.load_bootstrap_class(&internal_name)? {
+            Some(class) => Some(vm.class_mirror(class.name())?),
+            None => None,
+        }
+    } else {
+        let result = vm.invoke_virtual(
+            parent,
+            "loadClass",
+            "(Ljava/lang/String;Z)Ljava/lang/Class;",
+            &[name.clone(), JvmValue::Int(0)],
+        );
+
+        match result {
+            Ok(mirror) => Some(
+                mirror
+                    .and_then(|mirror| mirror.try_as_reference())
+                    .wrap_err("expected reference")?,
+            ),
+            Err(e) if is_class_not_found(&e) => None,
+            Err(e) => return Err(e),
+        }
+    };
+
+    if let Some(mirror) = mirror {
+        return Ok(mirror);
+    }
+
+    vm.invoke_virtual(
+        loader,
+        "findClass",
+        "(Ljava/lang/String;)Ljava/lang/Class;",
+        &[name.clone()],
+    )?
+    .and_then(|mirror| mirror.try_as_reference())
+    .wrap_err("expected reference")
+}
+
+fn init_loader(vm: &Vm, loader: usize, name: Option<&str>, parent: usize) -> Result<()> {
+    let name = match name {
+        Some(name) => JvmValue::StringConst(vm.intern(name)),
+        None => JvmValue::Reference(0),
+    };
+
+    vm.set_field(loader, "name", "Ljava/lang/String;", name)?;
+    vm.set_field(
+        loader,
+        "parent",
+        "Ljava/lang/ClassLoader;",
+        JvmValue::Reference(parent),
+    )
+}
+
+fn parent(vm: &Vm, loader: usize) -> Result<usize> {
+    vm.get_field(loader, "parent", "Ljava/lang/ClassLoader;")?
+        .try_as_reference()
+        .wrap_err("expected reference")
+}
+
+/// Converts a binary class name to internal form, or returns `None` if it isn't a valid name
+/// for a class that a loader could load (e.g. an array type).
+fn internal_name(name: &JvmValue) -> Result<Option<String>> {
+    let name = match name {
+        JvmValue::StringConst(name) => *name,
+        JvmValue::Reference(0) => bail!(JavaException::null_pointer()),
+        _ => bail!("expected string"),
+    };
+
+    if name.is_empty() || name.contains('/') || name.starts_with('[') {
+        return Ok(None);
+    }
+
+    Ok(Some(name.replace('.', "/")))
+}
+
+fn class_not_found(name: &JvmValue) -> JavaException {
+    match name {
+        JvmValue::StringConst(name) => {
+            JavaException::new("java/lang/ClassNotFoundException", *name)
+        }
+        _ => JavaException {
+            class_name: "java/lang/ClassNotFoundException".to_owned(),
+            message: None,
+            object: None,
+        },
+    }
+}
+
+fn is_class_not_found(error: &Error) -> bool {
+    error
+        .exception()
+        .is_some_and(|e| e.class_name == "java/lang/ClassNotFoundException")
+}
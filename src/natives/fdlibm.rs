@@ -0,0 +1,1494 @@
+//! Ports of the fdlibm functions which the natives of `java.lang.StrictMath` are specified to
+//! match bit for bit. The platform's maths library is usually more accurate, but gives different
+//! results, e.g. for `sin(1e300)`.
+//!
+//! The code follows fdlibm 5.3 closely, including its constants, which are written with more
+//! digits than a double holds.
+
+#![allow(clippy::excessive_precision, clippy::approx_constant)]
+
+fn high(x: f64) -> i32 {
+    (x.to_bits() >> 32) as i32
+}
+
+fn low(x: f64) -> u32 {
+    x.to_bits() as u32
+}
+
+fn with_high(x: f64, high: i32) -> f64 {
+    f64::from_bits((x.to_bits() & 0xffff_ffff) | (high as u32 as u64) << 32)
+}
+
+fn with_low(x: f64, low: u32) -> f64 {
+    f64::from_bits((x.to_bits() & !0xffff_ffff) | low as u64)
+}
+
+/// Returns `x * 2^n`, without overflowing in the intermediate power of two.
+fn scalbn(x: f64, mut n: i32) -> f64 {
+    let mut y = x;
+    if n > 1023 {
+        y *= f64::from_bits(0x7fe0_0000_0000_0000);
+        n -= 1023;
+        if n > 1023 {
+            y *= f64::from_bits(0x7fe0_0000_0000_0000);
+            n = (n - 1023).min(1023);
+        }
+    } else if n < -1022 {
+        // 2^-1022 * 2^53, so that the result isn't rounded twice if it's subnormal
+        y *= f64::from_bits(0x0360_0000_0000_0000);
+        n += 1022 - 53;
+        if n < -1022 {
+            y *= f64::from_bits(0x0360_0000_0000_0000);
+            n = (n + 1022 - 53).max(-1022);
+        }
+    }
+    y * f64::from_bits(((0x3ff + n) as u64) << 52)
+}
+
+pub(super) fn sin(x: f64) -> f64 {
+    let ix = high(x) & 0x7fff_ffff;
+    if ix <= 0x3fe9_21fb {
+        return kernel_sin(x, 0.0, false);
+    }
+    if ix >= 0x7ff0_0000 {
+        return f64::NAN;
+    }
+
+    let (n, y0, y1) = rem_pio2(x);
+    match n & 3 {
+        0 => kernel_sin(y0, y1, true),
+        1 => kernel_cos(y0, y1),
+        2 => -kernel_sin(y0, y1, true),
+        _ => -kernel_cos(y0, y1),
+    }
+}
+
+pub(super) fn cos(x: f64) -> f64 {
+    let ix = high(x) & 0x7fff_ffff;
+    if ix <= 0x3fe9_21fb {
+        return kernel_cos(x, 0.0);
+    }
+    if ix >= 0x7ff0_0000 {
+        return f64::NAN;
+    }
+
+    let (n, y0, y1) = rem_pio2(x);
+    match n & 3 {
+        0 => kernel_cos(y0, y1),
+        1 => -kernel_sin(y0, y1, true),
+        2 => -kernel_cos(y0, y1),
+        _ => kernel_sin(y0, y1, true),
+    }
+}
+
+pub(super) fn tan(x: f64) -> f64 {
+    let ix = high(x) & 0x7fff_ffff;
+    if ix <= 0x3fe9_21fb {
+        return kernel_tan(x, 0.0, 1);
+    }
+    if ix >= 0x7ff0_0000 {
+        return f64::NAN;
+    }
+
+    let (n, y0, y1) = rem_pio2(x);
+    kernel_tan(y0, y1, 1 - ((n & 1) << 1))
+}
+
+/// Computes sin(x + y) for |x| <= pi/4, where y is the tail of x, which is only used if
+/// `has_tail` is set.
+fn kernel_sin(x: f64, y: f64, has_tail: bool) -> f64 {
+    const S1: f64 = -1.66666666666666324348e-01;
+    const S2: f64 = 8.33333333332248946124e-03;
+    const S3: f64 = -1.98412698298579493134e-04;
+    const S4: f64 = 2.75573137070700676789e-06;
+    const S5: f64 = -2.50507602534068634195e-08;
+    const S6: f64 = 1.58969099521155010221e-10;
+
+    let ix = high(x) & 0x7fff_ffff;
+    if ix < 0x3e40_0000 && x as i32 == 0 {
+        return x;
+    }
+
+    let z = x * x;
+    let v = z * x;
+    let r = S2 + z * (S3 + z * (S4 + z * (S5 + z * S6)));
+    if !has_tail {
+        x + v * (S1 + z * r)
+    } else {
+        x - ((z * (0.5 * y - v * r) - y) - v * S1)
+    }
+}
+
+/// Computes cos(x + y) for |x| <= pi/4, where y is the tail of x.
+fn kernel_cos(x: f64, y: f64) -> f64 {
+    const C1: f64 = 4.16666666666666019037e-02;
+    const C2: f64 = -1.38888888888741095749e-03;
+    const C3: f64 = 2.48015872894767294178e-05;
+    const C4: f64 = -2.75573143513906633035e-07;
+    const C5: f64 = 2.08757232129817482790e-09;
+    const C6: f64 = -1.13596475577881948265e-11;
+
+    let ix = high(x) & 0x7fff_ffff;
+    if ix < 0x3e40_0000 && x as i32 == 0 {
+        return 1.0;
+    }
+
+    let z = x * x;
+    let r = z * (C1 + z * (C2 + z * (C3 + z * (C4 + z * (C5 + z * C6)))));
+    if ix < 0x3fd3_3333 {
+        return 1.0 - (0.5 * z - (z * r - x * y));
+    }
+
+    let qx = if ix > 0x3fe9_0000 {
+        0.28125
+    } else {
+        // x/4
+        with_low(with_high(0.0, ix - 0x0020_0000), 0)
+    };
+    let hz = 0.5 * z - qx;
+    let a = 1.0 - qx;
+    a - (hz - (z * r - x * y))
+}
+
+/// Computes tan(x + y) for |x| <= pi/4, where y is the tail of x, or -1/tan(x + y) if `iy` is
+/// -1 rather than 1.
+fn kernel_tan(mut x: f64, mut y: f64, iy: i32) -> f64 {
+    const T: [f64; 13] = [
+        3.33333333333334091986e-01,
+        1.33333333333201242699e-01,
+        5.39682539762260521377e-02,
+        2.18694882948595424599e-02,
+        8.86323982359930005737e-03,
+        3.59207910759131235356e-03,
+        1.45620945432529025516e-03,
+        5.88041240820264096874e-04,
+        2.46463134818469906812e-04,
+        7.81794442939557092300e-05,
+        7.14072491382608190305e-05,
+        -1.85586374855275456654e-05,
+        2.59073051863633712884e-05,
+    ];
+    const PIO4: f64 = 7.85398163397448278999e-01;
+    const PIO4_LO: f64 = 3.06161699786838301793e-17;
+
+    let hx = high(x);
+    let ix = hx & 0x7fff_ffff;
+    if ix < 0x3e30_0000 && x as i32 == 0 {
+        if (ix | low(x) as i32 | (iy + 1)) == 0 {
+            return 1.0 / x.abs();
+        } else if iy == 1 {
+            return x;
+        } else {
+            // Compute -1 / (x + y) carefully
+            let w = x + y;
+            let z = with_low(w, 0);
+            let v = y - (z - x);
+            let a = -1.0 / w;
+            let t = with_low(a, 0);
+            let s = 1.0 + t * z;
+            return t + a * (s + t * v);
+        }
+    }
+
+    if ix >= 0x3fe5_9428 {
+        if hx < 0 {
+            x = -x;
+            y = -y;
+        }
+        let z = PIO4 - x;
+        let w = PIO4_LO - y;
+        x = z + w;
+        y = 0.0;
+    }
+
+    let z = x * x;
+    let w = z * z;
+    // Break x^5 * (T[1] + x^2 * T[2] + ...) into odd and even polynomials
+    let r = T[1] + w * (T[3] + w * (T[5] + w * (T[7] + w * (T[9] + w * T[11]))));
+    let v = z * (T[2] + w * (T[4] + w * (T[6] + w * (T[8] + w * (T[10] + w * T[12])))));
+    let s = z * x;
+    let mut r = y + z * (s * (r + v) + y);
+    r += T[0] * s;
+    let w = x + r;
+
+    if ix >= 0x3fe5_9428 {
+        let v = iy as f64;
+        return (1 - ((hx >> 30) & 2)) as f64 * (v - 2.0 * (x - (w * w / (w + v) - r)));
+    }
+
+    if iy == 1 {
+        w
+    } else {
+        // Compute -1 / (x + r) accurately
+        let z = with_low(w, 0);
+        let v = r - (z - x);
+        let a = -1.0 / w;
+        let t = with_low(a, 0);
+        let s = 1.0 + t * z;
+        t + a * (s + t * v)
+    }
+}
+
+/// The bits of 2/pi, 24 at a time.
+const TWO_OVER_PI: [i32; 66] = [
+    0xA2F983, 0x6E4E44, 0x1529FC, 0x2757D1, 0xF534DD, 0xC0DB62, 0x95993C, 0x439041, 0xFE5163,
+    0xABDEBB, 0xC561B7, 0x246E3A, 0x424DD2, 0xE00649, 0x2EEA09, 0xD1921C, 0xFE1DEB, 0x1CB129,
+    0xA73EE8, 0x8235F5, 0x2EBB44, 0x84E99C, 0x7026B4, 0x5F7E41, 0x3991D6, 0x398353, 0x39F49C,
+    0x845F8B, 0xBDF928, 0x3B1FF8, 0x97FFDE, 0x05980F, 0xEF2F11, 0x8B5A0A, 0x6D1F6D, 0x367ECF,
+    0x27CB09, 0xB74F46, 0x3F669E, 0x5FEA2D, 0x7527BA, 0xC7EBE5, 0xF17B3D, 0x0739F7, 0x8A5292,
+    0xEA6BFB, 0x5FB11F, 0x8D5D08, 0x560330, 0x46FC7B, 0x6BABF0, 0xCFBC20, 0x9AF436, 0x1DA9E3,
+    0x91615E, 0xE61B08, 0x659985, 0x5F14A0, 0x68408D, 0xFFD880, 0x4D7327, 0x310606, 0x1556CA,
+    0x73A8C9, 0x60E27B, 0xC08C6B,
+];
+
+/// The high words of n * pi/2, for n from 1 to 32.
+const NPIO2_HW: [i32; 32] = [
+    0x3FF921FB, 0x400921FB, 0x4012D97C, 0x401921FB, 0x401F6A7A, 0x4022D97C, 0x4025FDBB, 0x402921FB,
+    0x402C463A, 0x402F6A7A, 0x4031475C, 0x4032D97C, 0x40346B9C, 0x4035FDBB, 0x40378FDB, 0x403921FB,
+    0x403AB41B, 0x403C463A, 0x403DD85A, 0x403F6A7A, 0x40407E4C, 0x4041475C, 0x4042106C, 0x4042D97C,
+    0x4043A28C, 0x40446B9C, 0x404534AC, 0x4045FDBB, 0x4046C6CB, 0x40478FDB, 0x404858EB, 0x404921FB,
+];
+
+const TWO24: f64 = 1.67772160000000000000e+07;
+const TWON24: f64 = 5.96046447753906250000e-08;
+
+/// Reduces x to y0 + y1 in [-pi/4, pi/4], returning n such that x = n * pi/2 + y0 + y1.
+fn rem_pio2(x: f64) -> (i32, f64, f64) {
+    const INVPIO2: f64 = 6.36619772367581382433e-01;
+    const PIO2_1: f64 = 1.57079632673412561417e+00;
+    const PIO2_1T: f64 = 6.07710050650619224932e-11;
+    const PIO2_2: f64 = 6.07710050630396597660e-11;
+    const PIO2_2T: f64 = 2.02226624879595063154e-21;
+    const PIO2_3: f64 = 2.02226624871116645580e-21;
+    const PIO2_3T: f64 = 8.47842766036889956997e-32;
+
+    let hx = high(x);
+    let ix = hx & 0x7fff_ffff;
+
+    // |x| <= pi/4, so no reduction is needed
+    if ix <= 0x3fe9_21fb {
+        return (0, x, 0.0);
+    }
+
+    // |x| < 3pi/4, where n is 1 or -1
+    if ix < 0x4002_d97c {
+        return if hx > 0 {
+            let mut z = x - PIO2_1;
+            if ix != 0x3ff9_21fb {
+                let y0 = z - PIO2_1T;
+                (1, y0, (z - y0) - PIO2_1T)
+            } else {
+                // Near pi/2, so more bits of pi are needed
+                z -= PIO2_2;
+                let y0 = z - PIO2_2T;
+                (1, y0, (z - y0) - PIO2_2T)
+            }
+        } else {
+            let mut z = x + PIO2_1;
+            if ix != 0x3ff9_21fb {
+                let y0 = z + PIO2_1T;
+                (-1, y0, (z - y0) + PIO2_1T)
+            } else {
+                z += PIO2_2;
+                let y0 = z + PIO2_2T;
+                (-1, y0, (z - y0) + PIO2_2T)
+            }
+        };
+    }
+
+    // |x| <= 2^19 * pi/2
+    if ix <= 0x4139_21fb {
+        let t = x.abs();
+        let n = (t * INVPIO2 + 0.5) as i32;
+        let f = n as f64;
+        let mut r = t - f * PIO2_1;
+        // The first round is good to 85 bits
+        let mut w = f * PIO2_1T;
+        let mut y0 = r - w;
+        if n >= 32 || ix == NPIO2_HW[n as usize - 1] {
+            let j = ix >> 20;
+            let i = j - ((high(y0) >> 20) & 0x7ff);
+            if i > 16 {
+                // A second iteration is needed, which is good to 118 bits
+                let t = r;
+                w = f * PIO2_2;
+                r = t - w;
+                w = f * PIO2_2T - ((t - r) - w);
+                y0 = r - w;
+                let i = j - ((high(y0) >> 20) & 0x7ff);
+                if i > 49 {
+                    // A third iteration is needed, which is good to 151 bits
+                    let t = r;
+                    w = f * PIO2_3;
+                    r = t - w;
+                    w = f * PIO2_3T - ((t - r) - w);
+                    y0 = r - w;
+                }
+            }
+        }
+        let y1 = (r - y0) - w;
+        return if hx < 0 { (-n, -y0, -y1) } else { (n, y0, y1) };
+    }
+
+    // Infinity or NaN
+    if ix >= 0x7ff0_0000 {
+        return (0, f64::NAN, f64::NAN);
+    }
+
+    // Split |x| * 2^-e0 into three 24 bit chunks
+    let e0 = (ix >> 20) - 1046;
+    let mut z = with_low(with_high(0.0, ix - (e0 << 20)), low(x));
+    let mut tx = [0.0; 3];
+    for t in &mut tx[..2] {
+        *t = z as i32 as f64;
+        z = (z - *t) * TWO24;
+    }
+    tx[2] = z;
+
+    let mut nx = 3;
+    while tx[nx - 1] == 0.0 {
+        nx -= 1;
+    }
+
+    let (n, y0, y1) = kernel_rem_pio2(&tx[..nx], e0);
+    if hx < 0 {
+        (-n, -y0, -y1)
+    } else {
+        (n, y0, y1)
+    }
+}
+
+/// Reduces a large x, given as 24 bit chunks scaled by 2^-e0, to y0 + y1 in [-pi/4, pi/4],
+/// returning the last three bits of n such that x = n * pi/2 + y0 + y1.
+fn kernel_rem_pio2(x: &[f64], e0: i32) -> (i32, f64, f64) {
+    // The number of terms of 2/pi to start with, for 53 bits of precision
+    const JK: usize = 4;
+    const PIO2: [f64; 8] = [
+        1.57079625129699707031e+00,
+        7.54978941586159635335e-08,
+        5.39030252995776476554e-15,
+        3.28200341580791294123e-22,
+        1.27065575308067607349e-29,
+        1.22933308981111328932e-36,
+        2.73370053816464559624e-44,
+        2.16741683877804819444e-51,
+    ];
+
+    let jx = x.len() - 1;
+    let jv = ((e0 - 3) / 24).max(0);
+    let mut q0 = e0 - 24 * (jv + 1);
+    let jv = jv as usize;
+
+    let mut f = [0.0; 20];
+    let mut q = [0.0; 20];
+    let mut fq = [0.0; 20];
+    let mut iq = [0i32; 20];
+
+    // f[jx + JK] = TWO_OVER_PI[jv + JK]
+    for (i, f) in f[..=jx + JK].iter_mut().enumerate() {
+        *f = match (jv + i).checked_sub(jx) {
+            Some(j) => TWO_OVER_PI[j] as f64,
+            None => 0.0,
+        };
+    }
+
+    for i in 0..=JK {
+        q[i] = (0..=jx)
+            .map(|j| x[j] * f[jx + i - j])
+            .fold(0.0, |fw, t| fw + t);
+    }
+
+    let mut jz = JK;
+    loop {
+        // Distill q into iq in reverse
+        let mut z = q[jz];
+        for (i, j) in (1..=jz).rev().enumerate() {
+            let fw = (TWON24 * z) as i32 as f64;
+            iq[i] = (z - TWO24 * fw) as i32;
+            z = q[j - 1] + fw;
+        }
+
+        z = scalbn(z, q0);
+        // Trim off integers >= 8
+        z -= 8.0 * (z * 0.125).floor();
+        let mut n = z as i32;
+        z -= n as f64;
+
+        let mut ih = 0;
+        if q0 > 0 {
+            // iq[jz - 1] is needed to determine n
+            let i = iq[jz - 1] >> (24 - q0);
+            n += i;
+            iq[jz - 1] -= i << (24 - q0);
+            ih = iq[jz - 1] >> (23 - q0);
+        } else if q0 == 0 {
+            ih = iq[jz - 1] >> 23;
+        } else if z >= 0.5 {
+            ih = 2;
+        }
+
+        // q > 0.5, so compute 1 - q
+        if ih > 0 {
+            n += 1;
+            let mut carry = false;
+            for iq in &mut iq[..jz] {
+                if carry {
+                    *iq = 0xffffff - *iq;
+                } else if *iq != 0 {
+                    carry = true;
+                    *iq = 0x1000000 - *iq;
+                }
+            }
+            match q0 {
+                1 => iq[jz - 1] &= 0x7fffff,
+                2 => iq[jz - 1] &= 0x3fffff,
+                _ => {}
+            }
+            if ih == 2 {
+                z = 1.0 - z;
+                if carry {
+                    z -= scalbn(1.0, q0);
+                }
+            }
+        }
+
+        // Add more terms of 2/pi if too many bits cancelled out
+        if z == 0.0 && iq[JK..jz].iter().all(|&iq| iq == 0) {
+            let mut k = 1;
+            while iq[JK - k] == 0 {
+                k += 1;
+            }
+
+            for i in jz + 1..=jz + k {
+                f[jx + i] = TWO_OVER_PI[jv + i] as f64;
+                q[i] = (0..=jx)
+                    .map(|j| x[j] * f[jx + i - j])
+                    .fold(0.0, |fw, t| fw + t);
+            }
+            jz += k;
+            continue;
+        }
+
+        // Chop off zero terms
+        if z == 0.0 {
+            jz -= 1;
+            q0 -= 24;
+            while iq[jz] == 0 {
+                jz -= 1;
+                q0 -= 24;
+            }
+        } else {
+            // Break z into 24 bit chunks if needed
+            z = scalbn(z, -q0);
+            if z >= TWO24 {
+                let fw = (TWON24 * z) as i32 as f64;
+                iq[jz] = (z - TWO24 * fw) as i32;
+                jz += 1;
+                q0 += 24;
+                iq[jz] = fw as i32;
+            } else {
+                iq[jz] = z as i32;
+            }
+        }
+
+        // Convert the chunks to floating point values
+        let mut fw = scalbn(1.0, q0);
+        for i in (0..=jz).rev() {
+            q[i] = fw * iq[i] as f64;
+            fw *= TWON24;
+        }
+
+        // Compute PIO2[0..=JK] * q[jz..=0]
+        for i in (0..=jz).rev() {
+            let mut fw = 0.0;
+            for k in 0..=JK.min(jz - i) {
+                fw += PIO2[k] * q[i + k];
+            }
+            fq[jz - i] = fw;
+        }
+
+        // Compress fq into y0 and y1
+        let mut fw = 0.0;
+        for fq in fq[..=jz].iter().rev() {
+            fw += fq;
+        }
+        let y0 = if ih == 0 { fw } else { -fw };
+        let mut fw = fq[0] - fw;
+        for fq in &fq[1..=jz] {
+            fw += fq;
+        }
+        let y1 = if ih == 0 { fw } else { -fw };
+
+        return (n & 7, y0, y1);
+    }
+}
+
+// Coefficients of the rational approximation of asin and acos
+const PS0: f64 = 1.66666666666666657415e-01;
+const PS1: f64 = -3.25565818622400915405e-01;
+const PS2: f64 = 2.01212532134862925881e-01;
+const PS3: f64 = -4.00555345006794114027e-02;
+const PS4: f64 = 7.91534994289814532176e-04;
+const PS5: f64 = 3.47933107596021167570e-05;
+const QS1: f64 = -2.40339491173441421878e+00;
+const QS2: f64 = 2.02094576023350569471e+00;
+const QS3: f64 = -6.88283971605453293030e-01;
+const QS4: f64 = 7.70381505559019352791e-02;
+
+const PIO2_HI: f64 = 1.57079632679489655800e+00;
+const PIO2_LO: f64 = 6.12323399573676603587e-17;
+const PI: f64 = 3.14159265358979311600e+00;
+
+/// Evaluates the rational approximation of asin and acos at t.
+fn asin_ratio(t: f64) -> (f64, f64) {
+    let p = t * (PS0 + t * (PS1 + t * (PS2 + t * (PS3 + t * (PS4 + t * PS5)))));
+    let q = 1.0 + t * (QS1 + t * (QS2 + t * (QS3 + t * QS4)));
+    (p, q)
+}
+
+pub(super) fn asin(x: f64) -> f64 {
+    const PIO4_HI: f64 = 7.85398163397448278999e-01;
+
+    let hx = high(x);
+    let ix = hx & 0x7fff_ffff;
+    if ix >= 0x3ff0_0000 {
+        if ((ix - 0x3ff0_0000) | low(x) as i32) == 0 {
+            // asin(1) = +-pi/2
+            return x * PIO2_HI + x * PIO2_LO;
+        }
+        // asin(|x| > 1) is NaN
+        return f64::NAN;
+    }
+
+    if ix < 0x3fe0_0000 {
+        // |x| < 0.5
+        if ix < 0x3e40_0000 {
+            return x;
+        }
+        let t = x * x;
+        let (p, q) = asin_ratio(t);
+        let w = p / q;
+        return x + x * w;
+    }
+
+    // 1 > |x| >= 0.5
+    let w = 1.0 - x.abs();
+    let t = w * 0.5;
+    let (p, q) = asin_ratio(t);
+    let s = t.sqrt();
+    let t = if ix >= 0x3fef_3333 {
+        // |x| > 0.975
+        let w = p / q;
+        PIO2_HI - (2.0 * (s + s * w) - PIO2_LO)
+    } else {
+        let w = with_low(s, 0);
+        let c = (t - w * w) / (s + w);
+        let r = p / q;
+        let p = 2.0 * s * r - (PIO2_LO - 2.0 * c);
+        let q = PIO4_HI - 2.0 * w;
+        PIO4_HI - (p - q)
+    };
+
+    if hx > 0 {
+        t
+    } else {
+        -t
+    }
+}
+
+pub(super) fn acos(x: f64) -> f64 {
+    let hx = high(x);
+    let ix = hx & 0x7fff_ffff;
+    if ix >= 0x3ff0_0000 {
+        if ((ix - 0x3ff0_0000) | low(x) as i32) == 0 {
+            // acos(1) = 0 and acos(-1) = pi
+            return if hx > 0 { 0.0 } else { PI + 2.0 * PIO2_LO };
+        }
+        // acos(|x| > 1) is NaN
+        return f64::NAN;
+    }
+
+    if ix < 0x3fe0_0000 {
+        // |x| < 0.5
+        if ix <= 0x3c60_0000 {
+            return PIO2_HI + PIO2_LO;
+        }
+        let z = x * x;
+        let (p, q) = asin_ratio(z);
+        let r = p / q;
+        PIO2_HI - (x - (PIO2_LO - x * r))
+    } else if hx < 0 {
+        // x < -0.5
+        let z = (1.0 + x) * 0.5;
+        let (p, q) = asin_ratio(z);
+        let s = z.sqrt();
+        let r = p / q;
+        let w = r * s - PIO2_LO;
+        PI - 2.0 * (s + w)
+    } else {
+        // x > 0.5
+        let z = (1.0 - x) * 0.5;
+        let s = z.sqrt();
+        let df = with_low(s, 0);
+        let c = (z - df * df) / (s + df);
+        let (p, q) = asin_ratio(z);
+        let r = p / q;
+        let w = r * s + c;
+        2.0 * (df + w)
+    }
+}
+
+pub(super) fn atan(x: f64) -> f64 {
+    const ATAN_HI: [f64; 4] = [
+        4.63647609000806093515e-01,
+        7.85398163397448278999e-01,
+        9.82793723247329054082e-01,
+        1.57079632679489655800e+00,
+    ];
+    const ATAN_LO: [f64; 4] = [
+        2.26987774529616870924e-17,
+        3.06161699786838301793e-17,
+        1.39033110312309984516e-17,
+        6.12323399573676603587e-17,
+    ];
+    const AT: [f64; 11] = [
+        3.33333333333329318027e-01,
+        -1.99999999998764832476e-01,
+        1.42857142725034663711e-01,
+        -1.11111104054623557880e-01,
+        9.09088713343650656196e-02,
+        -7.69187620504482999495e-02,
+        6.66107313738753120669e-02,
+        -5.83357013379057348645e-02,
+        4.97687799461593236017e-02,
+        -3.65315727442169155270e-02,
+        1.62858201153657823623e-02,
+    ];
+
+    let hx = high(x);
+    let ix = hx & 0x7fff_ffff;
+    if ix >= 0x4410_0000 {
+        // |x| >= 2^66
+        if ix > 0x7ff0_0000 || (ix == 0x7ff0_0000 && low(x) != 0) {
+            return x + x;
+        }
+        return if hx > 0 {
+            ATAN_HI[3] + ATAN_LO[3]
+        } else {
+            -ATAN_HI[3] - ATAN_LO[3]
+        };
+    }
+
+    let (x, id) = if ix < 0x3fdc_0000 {
+        // |x| < 0.4375
+        if ix < 0x3e20_0000 {
+            return x;
+        }
+        (x, None)
+    } else {
+        let x = x.abs();
+        if ix < 0x3ff3_0000 {
+            if ix < 0x3fe6_0000 {
+                // 7/16 <= |x| < 11/16
+                ((2.0 * x - 1.0) / (2.0 + x), Some(0))
+            } else {
+                // 11/16 <= |x| < 19/16
+                ((x - 1.0) / (x + 1.0), Some(1))
+            }
+        } else if ix < 0x4003_8000 {
+            // |x| < 2.4375
+            ((x - 1.5) / (1.0 + 1.5 * x), Some(2))
+        } else {
+            // 2.4375 <= |x| < 2^66
+            (-1.0 / x, Some(3))
+        }
+    };
+
+    let z = x * x;
+    let w = z * z;
+    // Break the sum of AT[i] * z^(i + 1) into odd and even polynomials
+    let s1 = z * (AT[0] + w * (AT[2] + w * (AT[4] + w * (AT[6] + w * (AT[8] + w * AT[10])))));
+    let s2 = w * (AT[1] + w * (AT[3] + w * (AT[5] + w * (AT[7] + w * AT[9]))));
+    match id {
+        None => x - x * (s1 + s2),
+        Some(id) => {
+            let z = ATAN_HI[id] - ((x * (s1 + s2) - ATAN_LO[id]) - x);
+            if hx < 0 {
+                -z
+            } else {
+                z
+            }
+        }
+    }
+}
+
+pub(super) fn atan2(y: f64, x: f64) -> f64 {
+    const TINY: f64 = 1.0e-300;
+    const PI_O_4: f64 = 7.8539816339744827900E-01;
+    const PI_O_2: f64 = 1.5707963267948965580E+00;
+    const PI_LO: f64 = 1.2246467991473531772E-16;
+
+    if x.is_nan() || y.is_nan() {
+        return x + y;
+    }
+
+    let hx = high(x);
+    let ix = hx & 0x7fff_ffff;
+    let lx = low(x);
+    let hy = high(y);
+    let iy = hy & 0x7fff_ffff;
+    let ly = low(y);
+
+    // x = 1.0
+    if hx == 0x3ff0_0000 && lx == 0 {
+        return atan(y);
+    }
+
+    // 2 * sign(x) + sign(y)
+    let m = ((hy >> 31) & 1) | ((hx >> 30) & 2);
+
+    // y = 0
+    if (iy | ly as i32) == 0 {
+        return match m {
+            0 | 1 => y,
+            2 => PI + TINY,
+            _ => -PI - TINY,
+        };
+    }
+
+    // x = 0
+    if (ix | lx as i32) == 0 {
+        return if hy < 0 {
+            -PI_O_2 - TINY
+        } else {
+            PI_O_2 + TINY
+        };
+    }
+
+    // x is infinite
+    if ix == 0x7ff0_0000 {
+        return if iy == 0x7ff0_0000 {
+            match m {
+                0 => PI_O_4 + TINY,
+                1 => -PI_O_4 - TINY,
+                2 => 3.0 * PI_O_4 + TINY,
+                _ => -3.0 * PI_O_4 - TINY,
+            }
+        } else {
+            match m {
+                0 => 0.0,
+                1 => -0.0,
+                2 => PI + TINY,
+                _ => -PI - TINY,
+            }
+        };
+    }
+
+    // y is infinite
+    if iy == 0x7ff0_0000 {
+        return if hy < 0 {
+            -PI_O_2 - TINY
+        } else {
+            PI_O_2 + TINY
+        };
+    }
+
+    // Compute y/x
+    let k = (iy - ix) >> 20;
+    let z = if k > 60 {
+        // |y/x| > 2^60
+        PI_O_2 + 0.5 * PI_LO
+    } else if hx < 0 && k < -60 {
+        // |y|/x < -2^60
+        0.0
+    } else {
+        atan((y / x).abs())
+    };
+
+    match m {
+        0 => z,
+        1 => -z,
+        2 => PI - (z - PI_LO),
+        _ => (z - PI_LO) - PI,
+    }
+}
+
+const LN2_HI: f64 = 6.93147180369123816490e-01;
+const LN2_LO: f64 = 1.90821492927058770002e-10;
+const TWO54: f64 = 1.80143985094819840000e+16;
+
+// Coefficients of the polynomial approximations of log and log1p
+const LG1: f64 = 6.666666666666735130e-01;
+const LG2: f64 = 3.999999999940941908e-01;
+const LG3: f64 = 2.857142874366239149e-01;
+const LG4: f64 = 2.222219843214978396e-01;
+const LG5: f64 = 1.818357216161805012e-01;
+const LG6: f64 = 1.531383769920937332e-01;
+const LG7: f64 = 1.479819860511658591e-01;
+
+pub(super) fn log(mut x: f64) -> f64 {
+    let mut hx = high(x);
+    let lx = low(x);
+
+    let mut k = 0;
+    if hx < 0x0010_0000 {
+        // x < 2^-1022
+        if ((hx & 0x7fff_ffff) | lx as i32) == 0 {
+            // log(+-0) = -inf
+            return f64::NEG_INFINITY;
+        }
+        if hx < 0 {
+            // log(-x) = NaN
+            return f64::NAN;
+        }
+        // Scale up subnormal numbers
+        k -= 54;
+        x *= TWO54;
+        hx = high(x);
+    }
+
+    if hx >= 0x7ff0_0000 {
+        return x + x;
+    }
+
+    k += (hx >> 20) - 1023;
+    hx &= 0x000f_ffff;
+    let i = (hx + 0x95f64) & 0x10_0000;
+    // Normalize x or x/2
+    let x = with_high(x, hx | (i ^ 0x3ff0_0000));
+    k += i >> 20;
+    let f = x - 1.0;
+    let dk = k as f64;
+
+    if (0x000f_ffff & (2 + hx)) < 3 {
+        // |f| < 2^-20
+        if f == 0.0 {
+            return if k == 0 {
+                0.0
+            } else {
+                dk * LN2_HI + dk * LN2_LO
+            };
+        }
+        let r = f * f * (0.5 - 0.33333333333333333 * f);
+        return if k == 0 {
+            f - r
+        } else {
+            dk * LN2_HI - ((r - dk * LN2_LO) - f)
+        };
+    }
+
+    let s = f / (2.0 + f);
+    let z = s * s;
+    let i = hx - 0x6147a;
+    let w = z * z;
+    let j = 0x6b851 - hx;
+    let t1 = w * (LG2 + w * (LG4 + w * LG6));
+    let t2 = z * (LG1 + w * (LG3 + w * (LG5 + w * LG7)));
+    let r = t2 + t1;
+
+    if (i | j) > 0 {
+        let hfsq = 0.5 * f * f;
+        if k == 0 {
+            f - (hfsq - s * (hfsq + r))
+        } else {
+            dk * LN2_HI - ((hfsq - (s * (hfsq + r) + dk * LN2_LO)) - f)
+        }
+    } else if k == 0 {
+        f - s * (f - r)
+    } else {
+        dk * LN2_HI - ((s * (f - r) - dk * LN2_LO) - f)
+    }
+}
+
+pub(super) fn log10(mut x: f64) -> f64 {
+    const IVLN10: f64 = 4.34294481903251816668e-01;
+    const LOG10_2HI: f64 = 3.01029995663611771306e-01;
+    const LOG10_2LO: f64 = 3.69423907715893078616e-13;
+
+    let mut hx = high(x);
+    let lx = low(x);
+
+    let mut k = 0;
+    if hx < 0x0010_0000 {
+        // x < 2^-1022
+        if ((hx & 0x7fff_ffff) | lx as i32) == 0 {
+            return f64::NEG_INFINITY;
+        }
+        if hx < 0 {
+            return f64::NAN;
+        }
+        k -= 54;
+        x *= TWO54;
+        hx = high(x);
+    }
+
+    if hx >= 0x7ff0_0000 {
+        return x + x;
+    }
+
+    k += (hx >> 20) - 1023;
+    let i = ((k as u32 & 0x8000_0000) >> 31) as i32;
+    let hx = (hx & 0x000f_ffff) | ((0x3ff - i) << 20);
+    let y = (k + i) as f64;
+    let x = with_high(x, hx);
+    let z = y * LOG10_2LO + IVLN10 * log(x);
+    z + y * LOG10_2HI
+}
+
+pub(super) fn log1p(x: f64) -> f64 {
+    let hx = high(x);
+    let ax = hx & 0x7fff_ffff;
+
+    let mut k = 1;
+    let mut f = 0.0;
+    let mut hu = 0;
+    if hx < 0x3fda_827a {
+        // x < 0.41422
+        if ax >= 0x3ff0_0000 {
+            // x <= -1.0
+            return if x == -1.0 {
+                f64::NEG_INFINITY
+            } else {
+                f64::NAN
+            };
+        }
+        if ax < 0x3e20_0000 {
+            // |x| < 2^-29
+            return if ax < 0x3c90_0000 { x } else { x - x * x * 0.5 };
+        }
+        if hx > 0 || hx <= 0xbfd2_bec3_u32 as i32 {
+            // -0.2929 < x < 0.41422
+            k = 0;
+            f = x;
+            hu = 1;
+        }
+    }
+
+    if hx >= 0x7ff0_0000 {
+        return x + x;
+    }
+
+    let mut c = 0.0;
+    if k != 0 {
+        let mut u;
+        if hx < 0x4340_0000 {
+            u = 1.0 + x;
+            hu = high(u);
+            k = (hu >> 20) - 1023;
+            // The correction term
+            c = if k > 0 { 1.0 - (u - x) } else { x - (u - 1.0) };
+            c /= u;
+        } else {
+            u = x;
+            hu = high(u);
+            k = (hu >> 20) - 1023;
+        }
+        hu &= 0x000f_ffff;
+        if hu < 0x6a09e {
+            // Normalize u
+            u = with_high(u, hu | 0x3ff0_0000);
+        } else {
+            // Normalize u/2
+            k += 1;
+            u = with_high(u, hu | 0x3fe0_0000);
+            hu = (0x0010_0000 - hu) >> 2;
+        }
+        f = u - 1.0;
+    }
+
+    let hfsq = 0.5 * f * f;
+    let dk = k as f64;
+    if hu == 0 {
+        // |f| < 2^-20
+        if f == 0.0 {
+            return if k == 0 {
+                0.0
+            } else {
+                c += dk * LN2_LO;
+                dk * LN2_HI + c
+            };
+        }
+        let r = hfsq * (1.0 - 0.66666666666666666 * f);
+        return if k == 0 {
+            f - r
+        } else {
+            dk * LN2_HI - ((r - (dk * LN2_LO + c)) - f)
+        };
+    }
+
+    let s = f / (2.0 + f);
+    let z = s * s;
+    let r = z * (LG1 + z * (LG2 + z * (LG3 + z * (LG4 + z * (LG5 + z * (LG6 + z * LG7))))));
+    if k == 0 {
+        f - (hfsq - s * (hfsq + r))
+    } else {
+        dk * LN2_HI - ((hfsq - (s * (hfsq + r) + (dk * LN2_LO + c))) - f)
+    }
+}
+
+const HUGE: f64 = 1.0e+300;
+const INVLN2: f64 = 1.44269504088896338700e+00;
+const O_THRESHOLD: f64 = 7.09782712893383973096e+02;
+
+pub(super) fn exp(x: f64) -> f64 {
+    const TWOM1000: f64 = 9.33263618503218878990e-302;
+    const U_THRESHOLD: f64 = -7.45133219101941108420e+02;
+    const P1: f64 = 1.66666666666666019037e-01;
+    const P2: f64 = -2.77777777770155933842e-03;
+    const P3: f64 = 6.61375632143793436117e-05;
+    const P4: f64 = -1.65339022054652515390e-06;
+    const P5: f64 = 4.13813679705723846039e-08;
+
+    let hx = high(x) as u32;
+    let negative = hx >> 31 != 0;
+    let hx = hx & 0x7fff_ffff;
+
+    if hx >= 0x4086_2e42 {
+        // |x| >= 709.78...
+        if hx >= 0x7ff0_0000 {
+            if ((hx & 0xfffff) | low(x)) != 0 {
+                return x + x;
+            }
+            // exp(+-inf) = {inf, 0}
+            return if negative { 0.0 } else { x };
+        }
+        if x > O_THRESHOLD {
+            return f64::INFINITY;
+        }
+        if x < U_THRESHOLD {
+            return 0.0;
+        }
+    }
+
+    // Reduce x to hi - lo, where x = k * ln2 + hi - lo
+    let (x, hi, lo, k) = if hx > 0x3fd6_2e42 {
+        // |x| > 0.5 ln2
+        let (hi, lo, k) = if hx < 0x3ff0_a2b2 {
+            // |x| < 1.5 ln2
+            if negative {
+                (x + LN2_HI, -LN2_LO, -1)
+            } else {
+                (x - LN2_HI, LN2_LO, 1)
+            }
+        } else {
+            let k = (INVLN2 * x + if negative { -0.5 } else { 0.5 }) as i32;
+            let t = k as f64;
+            (x - t * LN2_HI, t * LN2_LO, k)
+        };
+        (hi - lo, hi, lo, k)
+    } else if hx < 0x3e30_0000 {
+        // |x| < 2^-28
+        return 1.0 + x;
+    } else {
+        (x, 0.0, 0.0, 0)
+    };
+
+    let t = x * x;
+    let c = x - t * (P1 + t * (P2 + t * (P3 + t * (P4 + t * P5))));
+    if k == 0 {
+        return 1.0 - ((x * c) / (c - 2.0) - x);
+    }
+
+    let y = 1.0 - ((lo - (x * c) / (2.0 - c)) - hi);
+    if k >= -1021 {
+        with_high(y, high(y) + (k << 20))
+    } else {
+        with_high(y, high(y) + ((k + 1000) << 20)) * TWOM1000
+    }
+}
+
+pub(super) fn expm1(x: f64) -> f64 {
+    const TINY: f64 = 1.0e-300;
+    const Q1: f64 = -3.33333333333331316428e-02;
+    const Q2: f64 = 1.58730158725481460165e-03;
+    const Q3: f64 = -7.93650757867487942473e-05;
+    const Q4: f64 = 4.00821782732936239552e-06;
+    const Q5: f64 = -2.01099218183624371326e-07;
+
+    let hx = high(x) as u32;
+    let negative = hx & 0x8000_0000 != 0;
+    let hx = hx & 0x7fff_ffff;
+
+    if hx >= 0x4043_687a {
+        // |x| >= 56 * ln2
+        if hx >= 0x4086_2e42 {
+            // |x| >= 709.78...
+            if hx >= 0x7ff0_0000 {
+                if ((hx & 0xfffff) | low(x)) != 0 {
+                    return x + x;
+                }
+                // expm1(+-inf) = {inf, -1}
+                return if negative { -1.0 } else { x };
+            }
+            if x > O_THRESHOLD {
+                return f64::INFINITY;
+            }
+        }
+        if negative {
+            // x < -56 * ln2
+            return TINY - 1.0;
+        }
+    }
+
+    // Reduce x to hi - lo, where x = k * ln2 + hi - lo
+    let (x, c, k) = if hx > 0x3fd6_2e42 {
+        // |x| > 0.5 ln2
+        let (hi, lo, k) = if hx < 0x3ff0_a2b2 {
+            // |x| < 1.5 ln2
+            if negative {
+                (x + LN2_HI, -LN2_LO, -1)
+            } else {
+                (x - LN2_HI, LN2_LO, 1)
+            }
+        } else {
+            let k = (INVLN2 * x + if negative { -0.5 } else { 0.5 }) as i32;
+            let t = k as f64;
+            (x - t * LN2_HI, t * LN2_LO, k)
+        };
+        let x = hi - lo;
+        (x, (hi - x) - lo, k)
+    } else if hx < 0x3c90_0000 {
+        // |x| < 2^-54
+        return x;
+    } else {
+        (x, 0.0, 0)
+    };
+
+    let hfx = 0.5 * x;
+    let hxs = x * hfx;
+    let r1 = 1.0 + hxs * (Q1 + hxs * (Q2 + hxs * (Q3 + hxs * (Q4 + hxs * Q5))));
+    let t = 3.0 - r1 * hfx;
+    let e = hxs * ((r1 - t) / (6.0 - x * t));
+    if k == 0 {
+        return x - (x * e - hxs);
+    }
+
+    let e = (x * (e - c) - c) - hxs;
+    if k == -1 {
+        return 0.5 * (x - e) - 0.5;
+    }
+    if k == 1 {
+        return if x < -0.25 {
+            -2.0 * (e - (x + 0.5))
+        } else {
+            1.0 + 2.0 * (x - e)
+        };
+    }
+    if k <= -2 || k > 56 {
+        let y = 1.0 - (e - x);
+        return with_high(y, high(y) + (k << 20)) - 1.0;
+    }
+
+    let y = if k < 20 {
+        // 1 - 2^-k
+        let t = with_high(0.0, 0x3ff0_0000 - (0x20_0000 >> k));
+        t - (e - x)
+    } else {
+        // 2^-k
+        let t = with_high(0.0, (0x3ff - k) << 20);
+        (x - (e + t)) + 1.0
+    };
+    with_high(y, high(y) + (k << 20))
+}
+
+pub(super) fn sinh(x: f64) -> f64 {
+    const SHUGE: f64 = 1.0e307;
+
+    let jx = high(x);
+    let ix = jx & 0x7fff_ffff;
+
+    // Infinity or NaN
+    if ix >= 0x7ff0_0000 {
+        return x + x;
+    }
+
+    let h = if jx < 0 { -0.5 } else { 0.5 };
+
+    // |x| < 22, so return sign(x) * 0.5 * (E + E / (E + 1))
+    if ix < 0x4036_0000 {
+        if ix < 0x3e30_0000 {
+            return x;
+        }
+        let t = expm1(x.abs());
+        if ix < 0x3ff0_0000 {
+            return h * (2.0 * t - t * t / (t + 1.0));
+        }
+        return h * (t + t / (t + 1.0));
+    }
+
+    // |x| < log(f64::MAX), so return 0.5 * exp(|x|)
+    if ix < 0x4086_2e42 {
+        return h * exp(x.abs());
+    }
+
+    // |x| is below the overflow threshold
+    if ix < 0x4086_33ce || (ix == 0x4086_33ce && low(x) <= 0x8fb9_f87d) {
+        let w = exp(0.5 * x.abs());
+        let t = h * w;
+        return t * w;
+    }
+
+    x * SHUGE
+}
+
+pub(super) fn cosh(x: f64) -> f64 {
+    let ix = high(x) & 0x7fff_ffff;
+
+    // Infinity or NaN
+    if ix >= 0x7ff0_0000 {
+        return x * x;
+    }
+
+    // |x| < 0.5 * ln2, so return 1 + expm1(|x|)^2 / (2 * exp(|x|))
+    if ix < 0x3fd6_2e43 {
+        let t = expm1(x.abs());
+        let w = 1.0 + t;
+        if ix < 0x3c80_0000 {
+            return w;
+        }
+        return 1.0 + (t * t) / (w + w);
+    }
+
+    // |x| < 22, so return (exp(|x|) + 1 / exp(|x|)) / 2
+    if ix < 0x4036_0000 {
+        let t = exp(x.abs());
+        return 0.5 * t + 0.5 / t;
+    }
+
+    // |x| < log(f64::MAX), so return 0.5 * exp(|x|)
+    if ix < 0x4086_2e42 {
+        return 0.5 * exp(x.abs());
+    }
+
+    // |x| is below the overflow threshold
+    if ix < 0x4086_33ce || (ix == 0x4086_33ce && low(x) <= 0x8fb9_f87d) {
+        let w = exp(0.5 * x.abs());
+        let t = 0.5 * w;
+        return t * w;
+    }
+
+    HUGE * HUGE
+}
+
+pub(super) fn tanh(x: f64) -> f64 {
+    const TINY: f64 = 1.0e-300;
+
+    let jx = high(x);
+    let ix = jx & 0x7fff_ffff;
+
+    // Infinity or NaN
+    if ix >= 0x7ff0_0000 {
+        return if jx >= 0 {
+            1.0 / x + 1.0
+        } else {
+            1.0 / x - 1.0
+        };
+    }
+
+    let z = if ix < 0x4036_0000 {
+        // |x| < 22
+        if ix < 0x3c80_0000 {
+            // |x| < 2^-55
+            return x * (1.0 + x);
+        }
+        if ix >= 0x3ff0_0000 {
+            // |x| >= 1
+            let t = expm1(2.0 * x.abs());
+            1.0 - 2.0 / (t + 2.0)
+        } else {
+            let t = expm1(-2.0 * x.abs());
+            -t / (t + 2.0)
+        }
+    } else {
+        // |x| >= 22, so the result is +-1
+        1.0 - TINY
+    };
+
+    if jx >= 0 {
+        z
+    } else {
+        -z
+    }
+}
+
+pub(super) fn hypot(x: f64, y: f64) -> f64 {
+    let mut ha = high(x) & 0x7fff_ffff;
+    let mut hb = high(y) & 0x7fff_ffff;
+    let (a, b) = if hb > ha {
+        std::mem::swap(&mut ha, &mut hb);
+        (y, x)
+    } else {
+        (x, y)
+    };
+    let mut a = with_high(a, ha);
+    let mut b = with_high(b, hb);
+
+    // a/b > 2^60
+    if ha - hb > 0x3c0_0000 {
+        return a + b;
+    }
+
+    let mut k = 0;
+    if ha > 0x5f30_0000 {
+        // a > 2^500
+        if ha >= 0x7ff0_0000 {
+            // Infinity takes precedence over NaN
+            let mut w = a + b;
+            if ((ha & 0xfffff) | low(a) as i32) == 0 {
+                w = a;
+            }
+            if ((hb ^ 0x7ff0_0000) | low(b) as i32) == 0 {
+                w = b;
+            }
+            return w;
+        }
+        // Scale a and b by 2^-600
+        ha -= 0x2580_0000;
+        hb -= 0x2580_0000;
+        k += 600;
+        a = with_high(a, ha);
+        b = with_high(b, hb);
+    }
+
+    if hb < 0x20b0_0000 {
+        // b < 2^-500
+        if hb <= 0x000f_ffff {
+            // b is subnormal or zero
+            if (hb | low(b) as i32) == 0 {
+                return a;
+            }
+            // 2^1022
+            let t1 = with_high(0.0, 0x7fd0_0000);
+            b *= t1;
+            a *= t1;
+            k -= 1022;
+        } else {
+            // Scale a and b by 2^600
+            ha += 0x2580_0000;
+            hb += 0x2580_0000;
+            k -= 600;
+            a = with_high(a, ha);
+            b = with_high(b, hb);
+        }
+    }
+
+    let w = a - b;
+    let w = if w > b {
+        let t1 = with_high(0.0, ha);
+        let t2 = a - t1;
+        (t1 * t1 - (b * (-b) - t2 * (a + t1))).sqrt()
+    } else {
+        let a = a + a;
+        let y1 = with_high(0.0, hb);
+        let y2 = b - y1;
+        let t1 = with_high(0.0, ha + 0x0010_0000);
+        let t2 = a - t1;
+        (t1 * y1 - (w * (-w) - (t1 * y2 + t2 * b))).sqrt()
+    };
+
+    if k != 0 {
+        with_high(1.0, high(1.0) + (k << 20)) * w
+    } else {
+        w
+    }
+}
+
+/// The IEEE 754 remainder of x / p, which is exact, like `StrictMath.IEEEremainder`.
+pub(super) fn remainder(x: f64, p: f64) -> f64 {
+    let hx = high(x);
+    let lx = low(x);
+    let hp = high(p) & 0x7fff_ffff;
+    let lp = low(p);
+    let sx = hx as u32 & 0x8000_0000;
+    let hx = hx & 0x7fff_ffff;
+
+    // p = 0, x isn't finite, or p is NaN
+    if (hp | lp as i32) == 0
+        || hx >= 0x7ff0_0000
+        || (hp >= 0x7ff0_0000 && ((hp - 0x7ff0_0000) | lp as i32) != 0)
+    {
+        return f64::NAN;
+    }
+
+    let mut x = x;
+    if hp <= 0x7fdf_ffff {
+        // Now x < 2p
+        x %= p + p;
+    }
+    if ((hx - hp) | (lx as i32).wrapping_sub(lp as i32)) == 0 {
+        return 0.0 * x;
+    }
+
+    let mut x = x.abs();
+    let p = p.abs();
+    if hp < 0x0020_0000 {
+        if x + x > p {
+            x -= p;
+            if x + x >= p {
+                x -= p;
+            }
+        }
+    } else {
+        let p_half = 0.5 * p;
+        if x > p_half {
+            x -= p;
+            if x >= p_half {
+                x -= p;
+            }
+        }
+    }
+
+    with_high(x, high(x) ^ sx as i32)
+}
+
+/// A port of fdlibm's `cbrt`, which `StrictMath.cbrt` is specified to match. The platform's
+/// `cbrt` isn't always correctly rounded, e.g. it gives 3.0000000000000004 for 27.
+pub(super) fn cbrt(x: f64) -> f64 {
+    const B1: i32 = 715094163;
+    const B2: i32 = 696219795;
+
+    const C: f64 = 0.5428571428571428;
+    const D: f64 = -0.7053061224489796;
+    const E: f64 = 1.4142857142857144;
+    const F: f64 = 1.6071428571428572;
+    const G: f64 = 0.35714285714285715;
+
+    let sign = high(x) & i32::MIN;
+    let hx = high(x) ^ sign;
+
+    // cbrt(NaN) and cbrt(inf) are themselves, as are cbrt(0) and cbrt(-0)
+    if hx >= 0x7ff0_0000 {
+        return x + x;
+    }
+    if x == 0.0 {
+        return x;
+    }
+
+    let x = x.abs();
+
+    // Rough cbrt to 5 bits
+    let mut t = if hx < 0x0010_0000 {
+        // Subnormal
+        let t = with_high(0.0, 0x4350_0000) * x;
+        with_high(0.0, high(t) / 3 + B2)
+    } else {
+        with_high(0.0, hx / 3 + B1)
+    };
+
+    // New cbrt to 23 bits
+    let r = t * t / x;
+    let s = C + r * t;
+    t *= G + F / (s + E + D / s);
+
+    // Chop to 20 bits and make it larger than cbrt(x)
+    t = with_high(with_low(t, 0), high(t) + 1);
+
+    // One step of Newton's method to 53 bits, with error less than 0.667 ulps
+    let s = t * t;
+    let r = x / s;
+    let w = t + t;
+    let r = (r - t) / (w + r);
+    t += t * r;
+
+    with_high(t, high(t) | sign)
+}
@@ -0,0 +1,446 @@
+//! Natives for `java.io`, and the standard streams of `java.lang.System`.
+
+use crate::call_frame::{ArrayElementType, JavaException, JvmValue, RefTypeHeader};
+use crate::class::Class;
+use crate::convert::Reference;
+use crate::error::{bail, format_err, ContextCompat, Result};
+use crate::instructions::ArrayType;
+use crate::vm::Vm;
+
+/// Sets `System.in` to a stream which reads from the vm's stdin, and `System.out` and
+/// `System.err` to print streams which write to the vm's streams.
+pub(crate) fn init_system_streams<'a>(vm: &Vm<'a>, system: &'a Class<'a>) -> Result<()> {
+    // The file stream classes and FileDescriptor aren't initialized, since their static
+    // initializers depend on parts of the JDK that the vm doesn't support yet. Their methods
+    // which would need this are replaced by natives below.
+    let file_descriptor = vm.load_class("java/io/FileDescriptor")?;
+    let file_input_stream = vm.load_class("java/io/FileInputStream")?;
+    let file_output_stream = vm.load_class("java/io/FileOutputStream")?;
+
+    // The JDK wraps this in a BufferedInputStream, but reads from the vm's stdin are already
+    // buffered where it matters, so the file stream is used directly.
+    let descriptor = vm.alloc_object(file_descriptor)?;
+    vm.set_field(descriptor, "fd", "I", JvmValue::Int(0))?;
+    vm.set_field(descriptor, "handle", "J", JvmValue::Long(-1))?;
+
+    let input = vm.alloc_object(file_input_stream)?;
+    vm.set_field(
+        input,
+        "fd",
+        "Ljava/io/FileDescriptor;",
+        JvmValue::Reference(descriptor),
+    )?;
+
+    *system
+        .static_field("in", "Ljava/io/InputStream;")
+        .wrap_err("missing field java.lang.System.in")?
+        .lock()
+        .unwrap() = JvmValue::Reference(input);
+
+    // The JDK's charset encoders use JavaLangAccess, which System.initPhase1 would set up
+    if system.method("setJavaLangAccess", "()V").is_some() {
+        vm.invoke_static_method("java/lang/System", "setJavaLangAccess", "()V", &[])?;
+    }
+
+    for (name, fd) in [("out", 1), ("err", 2)] {
+        let descriptor = vm.alloc_object(file_descriptor)?;
+        vm.set_field(descriptor, "fd", "I", JvmValue::Int(fd))?;
+        vm.set_field(descriptor, "handle", "J", JvmValue::Long(-1))?;
+
+        let output = vm.alloc_object(file_output_stream)?;
+        vm.set_field(
+            output,
+            "fd",
+            "Ljava/io/FileDescriptor;",
+            JvmValue::Reference(descriptor),
+        )?;
+
+        let stream = vm
+            .new_object(
+                "java/io/PrintStream",
+                "(Ljava/io/OutputStream;Z)V",
+                (JvmValue::Reference(output), true),
+            )?
+            .reference();
+
+        *system
+            .static_field(name, "Ljava/io/PrintStream;")
+            .wrap_err_with(|| format_err!("missing field java.lang.System.{name}"))?
+            .lock()
+            .unwrap() = JvmValue::Reference(stream);
+    }
+
+    Ok(())
+}
+
+pub(super) fn register(vm: &Vm) -> Result<()> {
+    register_file_input_stream_natives(vm)?;
+    register_file_output_stream_natives(vm)?;
+    register_input_stream_reader_natives(vm)?;
+
+    Ok(())
+}
+
+fn register_file_input_stream_natives<'a>(vm: &Vm<'a>) -> Result<()> {
+    const FILE_INPUT_STREAM: &str = "java/io/FileInputStream";
+
+    vm.register_native(
+        FILE_INPUT_STREAM,
+        "read0",
+        "()I",
+        |vm: &Vm, this: Reference| {
+            let mut byte = [0];
+            let n = vm.read_fd(file_descriptor(vm, this.0)?, &mut byte)?;
+            Ok(if n == 0 { -1 } else { byte[0] as i32 })
+        },
+    )?;
+
+    vm.register_native(
+        FILE_INPUT_STREAM,
+        "readBytes",
+        "([BII)I",
+        |vm: &Vm<'a>, this: Reference, bytes: JvmValue<'a>, off: i32, len: i32| {
+            let buf = byte_range(&bytes, off, len)?;
+            if buf.is_empty() {
+                return Ok(0);
+            }
+
+            let n = vm.read_fd(file_descriptor(vm, this.0)?, buf)?;
+            Ok(if n == 0 { -1 } else { n as i32 })
+        },
+    )?;
+
+    vm.register_native(
+        FILE_INPUT_STREAM,
+        "skip0",
+        "(J)J",
+        |vm: &Vm, this: Reference, n: i64| {
+            let fd = file_descriptor(vm, this.0)?;
+            let n = n.max(0);
+            let mut remaining = n as u64;
+            let mut buf = [0; 512];
+
+            while remaining > 0 {
+                let len = remaining.min(buf.len() as u64) as usize;
+                let n = vm.read_fd(fd, &mut buf[..len])?;
+                if n == 0 {
+                    break;
+                }
+                remaining -= n as u64;
+            }
+
+            Ok(n - remaining as i64)
+        },
+    )?;
+
+    // There's no way to tell how much input is available without blocking
+    vm.register_native(
+        FILE_INPUT_STREAM,
+        "available0",
+        "()I",
+        |_: &Vm, _: Reference| Ok(0),
+    )?;
+
+    Ok(())
+}
+
+fn register_file_output_stream_natives<'a>(vm: &Vm<'a>) -> Result<()> {
+    const FILE_OUTPUT_STREAM: &str = "java/io/FileOutputStream";
+
+    vm.register_native(
+        FILE_OUTPUT_STREAM,
+        "writeBytes",
+        "([BIIZ)V",
+        |vm: &Vm<'a>, this: Reference, bytes: JvmValue<'a>, off: i32, len: i32, _: bool| {
+            vm.write_fd(file_descriptor(vm, this.0)?, byte_range(&bytes, off, len)?)
+        },
+    )?;
+
+    vm.register_native(
+        FILE_OUTPUT_STREAM,
+        "write",
+        "(IZ)V",
+        |vm: &Vm, this: Reference, byte: i32, _: bool| {
+            vm.write_fd(file_descriptor(vm, this.0)?, &[byte as u8])
+        },
+    )?;
+
+    // These replace the JDK implementations, which look up the append mode through
+    // SharedSecrets and would require FileOutputStream to be initialized.
+    vm.register_native(
+        FILE_OUTPUT_STREAM,
+        "write",
+        "(I)V",
+        |vm: &Vm, this: Reference, byte: i32| {
+            vm.write_fd(file_descriptor(vm, this.0)?, &[byte as u8])
+        },
+    )?;
+
+    vm.register_native(
+        FILE_OUTPUT_STREAM,
+        "write",
+        "([B)V",
+        |vm: &Vm<'a>, this: Reference, bytes: JvmValue<'a>| {
+            vm.write_fd(file_descriptor(vm, this.0)?, byte_array(&bytes)?)
+        },
+    )?;
+
+    vm.register_native(
+        FILE_OUTPUT_STREAM,
+        "write",
+        "([BII)V",
+        |vm: &Vm<'a>, this: Reference, bytes: JvmValue<'a>, off: i32, len: i32| {
+            vm.write_fd(file_descriptor(vm, this.0)?, byte_range(&bytes, off, len)?)
+        },
+    )?;
+
+    vm.register_native(
+        FILE_OUTPUT_STREAM,
+        "flush",
+        "()V",
+        |vm: &Vm, this: Reference| vm.flush_fd(file_descriptor(vm, this.0)?),
+    )?;
+
+    Ok(())
+}
+
+/// Replacements for the methods of `java.io.InputStreamReader`, which decodes bytes with a
+/// charset decoder that the vm can't run yet. Instead, the bytes of the underlying stream are
+/// decoded as UTF-8 here, and only the constructor which uses the default charset is supported.
+fn register_input_stream_reader_natives<'a>(vm: &Vm<'a>) -> Result<()> {
+    const INPUT_STREAM_READER: &str = "java/io/InputStreamReader";
+
+    // Like the JDK implementation, the stream is used as the reader's lock, which is also where
+    // the other natives find it
+    vm.register_native(
+        INPUT_STREAM_READER,
+        "<init>",
+        "(Ljava/io/InputStream;)V",
+        |vm: &Vm, this: Reference, stream: Reference| {
+            vm.set_field(
+                this.0,
+                "lock",
+                "Ljava/lang/Object;",
+                JvmValue::Reference(stream.0),
+            )
+        },
+    )?;
+
+    vm.register_native(
+        INPUT_STREAM_READER,
+        "read",
+        "()I",
+        |vm: &Vm, this: Reference| {
+            let mut c = [0];
+            let n = read_utf8(vm, reader_stream(vm, this.0)?, &mut c)?;
+
+            Ok(match n {
+                Some(_) => c[0] as i32,
+                None => -1,
+            })
+        },
+    )?;
+
+    vm.register_native(
+        INPUT_STREAM_READER,
+        "read",
+        "([CII)I",
+        |vm: &Vm<'a>, this: Reference, chars: JvmValue<'a>, off: i32, len: i32| {
+            let buf = char_range(&chars, off, len)?;
+            if buf.is_empty() {
+                return Ok(0);
+            }
+
+            let n = read_utf8(vm, reader_stream(vm, this.0)?, buf)?;
+
+            Ok(match n {
+                Some(n) => n.try_into()?,
+                None => -1,
+            })
+        },
+    )?;
+
+    vm.register_native(
+        INPUT_STREAM_READER,
+        "ready",
+        "()Z",
+        |vm: &Vm, this: Reference| {
+            let available = vm
+                .invoke_virtual(reader_stream(vm, this.0)?, "available", "()I", &[])?
+                .and_then(|available| available.try_as_int())
+                .wrap_err("expected int")?;
+
+            Ok(available > 0)
+        },
+    )?;
+
+    vm.register_native(
+        INPUT_STREAM_READER,
+        "close",
+        "()V",
+        |vm: &Vm, this: Reference| {
+            vm.invoke_virtual(reader_stream(vm, this.0)?, "close", "()V", &[])?;
+            Ok(())
+        },
+    )?;
+
+    Ok(())
+}
+
+/// Returns the stream which an `InputStreamReader` reads from.
+fn reader_stream(vm: &Vm, reader: usize) -> Result<usize> {
+    vm.get_field(reader, "lock", "Ljava/lang/Object;")?
+        .try_as_reference()
+        .wrap_err("expected reference")
+}
+
+/// Reads UTF-8 from an input stream, and decodes it into UTF-16 code units. Returns the number
+/// of code units, or `None` at the end of the stream. Malformed input is replaced by U+FFFD, as
+/// the JDK's readers do.
+///
+/// The stream is only read from once, so that this doesn't block when some input is available,
+/// unless that read ends part way through a character, in which case the rest of the character
+/// is read too.
+fn read_utf8(vm: &Vm, stream: usize, buf: &mut [u16]) -> Result<Option<usize>> {
+    // Every character takes at least as many bytes as code units, apart from one which is split
+    // by the read, whose remaining bytes make up for the second code unit it may take
+    let mut bytes = read_bytes(vm, stream, buf.len().saturating_sub(1).max(1))?;
+    if bytes.is_empty() {
+        return Ok(None);
+    }
+
+    while let Err(e) = std::str::from_utf8(&bytes) {
+        if e.error_len().is_some() {
+            break;
+        }
+
+        match vm
+            .invoke_virtual(stream, "read", "()I", &[])?
+            .and_then(|byte| byte.try_as_int())
+            .wrap_err("expected int")?
+        {
+            -1 => break,
+            byte => bytes.push(byte as u8),
+        }
+    }
+
+    let text = String::from_utf8_lossy(&bytes);
+    let mut units = text.encode_utf16().collect::<Vec<_>>();
+
+    // A supplementary character can't be split across reads, since there's nowhere to keep its
+    // second code unit, so it's replaced when only one code unit can be returned
+    if units.len() > buf.len() {
+        units = vec![char::REPLACEMENT_CHARACTER as u16];
+    }
+
+    buf[..units.len()].copy_from_slice(&units);
+    Ok(Some(units.len()))
+}
+
+/// Reads up to `len` bytes from an input stream with a single call to its `read` method.
+fn read_bytes(vm: &Vm, stream: usize, len: usize) -> Result<Vec<u8>> {
+    let array = vm.alloc_array(ArrayElementType::Primitive(ArrayType::Byte), len)?;
+
+    let n = vm
+        .invoke_virtual(
+            stream,
+            "read",
+            "([BII)I",
+            &[
+                JvmValue::Reference(array),
+                JvmValue::Int(0),
+                JvmValue::Int(len.try_into()?),
+            ],
+        )?
+        .and_then(|n| n.try_as_int())
+        .wrap_err("expected int")?;
+
+    let bytes = unsafe { (*(array as *mut RefTypeHeader)).array_data::<u8>()? };
+    Ok(bytes[..n.max(0) as usize].to_vec())
+}
+
+/// Returns the file descriptor number of a `FileOutputStream`.
+fn file_descriptor(vm: &Vm, stream: usize) -> Result<i32> {
+    let JvmValue::Reference(descriptor) = vm.get_field(stream, "fd", "Ljava/io/FileDescriptor;")?
+    else {
+        bail!("expected reference");
+    };
+
+    vm.get_field(descriptor, "fd", "I")?
+        .try_as_int()
+        .wrap_err("expected int")
+}
+
+fn byte_array<'a>(array: &JvmValue) -> Result<&'a mut [u8]> {
+    let array = match array {
+        JvmValue::Reference(0) => {
+            bail!(JavaException::new("java/lang/NullPointerException", "b"))
+        }
+        JvmValue::Reference(array) => *array,
+        _ => bail!("expected reference"),
+    };
+
+    let header = unsafe { &mut *(array as *mut RefTypeHeader) };
+    match header {
+        RefTypeHeader::Array(array)
+            if matches!(
+                array.element_type,
+                ArrayElementType::Primitive(ArrayType::Byte)
+            ) => {}
+        _ => bail!("expected byte array"),
+    }
+
+    Ok(unsafe { header.array_data::<u8>()? })
+}
+
+/// Returns a range of a byte array, throwing `IndexOutOfBoundsException` for invalid ranges.
+pub(super) fn byte_range<'a>(array: &JvmValue, off: i32, len: i32) -> Result<&'a mut [u8]> {
+    let bytes = byte_array(array)?;
+
+    if off < 0 || len < 0 || off as usize + len as usize > bytes.len() {
+        bail!(JavaException::new(
+            "java/lang/IndexOutOfBoundsException",
+            format!(
+                "Range [{off}, {off} + {len}) out of bounds for length {}",
+                bytes.len()
+            )
+        ));
+    }
+
+    Ok(&mut bytes[off as usize..(off + len) as usize])
+}
+
+/// Returns a range of a char array, throwing `IndexOutOfBoundsException` for invalid ranges.
+fn char_range<'a>(array: &JvmValue, off: i32, len: i32) -> Result<&'a mut [u16]> {
+    let array = match array {
+        JvmValue::Reference(0) => {
+            bail!(JavaException::new("java/lang/NullPointerException", "cbuf"))
+        }
+        JvmValue::Reference(array) => *array,
+        _ => bail!("expected reference"),
+    };
+
+    let header = unsafe { &mut *(array as *mut RefTypeHeader) };
+    match header {
+        RefTypeHeader::Array(array)
+            if matches!(
+                array.element_type,
+                ArrayElementType::Primitive(ArrayType::Char)
+            ) => {}
+        _ => bail!("expected char array"),
+    }
+
+    let chars = unsafe { header.array_data::<u16>()? };
+
+    if off < 0 || len < 0 || off as usize + len as usize > chars.len() {
+        bail!(JavaException::new(
+            "java/lang/IndexOutOfBoundsException",
+            format!(
+                "Range [{off}, {off} + {len}) out of bounds for length {}",
+                chars.len()
+            )
+        ));
+    }
+
+    Ok(&mut chars[off as usize..(off + len) as usize])
+}
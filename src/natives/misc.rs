@@ -0,0 +1,332 @@
+//! Natives for `jdk.internal.misc`, mostly `Unsafe`, which the JDK's atomics, concurrent collections and
+//! buffers are built on.
+//!
+//! Fields and array elements are addressed by offsets like in HotSpot, but the offsets aren't
+//! addresses. The offset of an instance field is its ordinal in the object, and the offset of an
+//! array element is its index scaled by the size of a primitive element, or its index in a
+//! reference array. Objects on the heap are read and written without synchronization, so the
+//! atomic operations don't need any either.
+
+use std::ptr;
+
+use crate::call_frame::{ArrayElementType, JavaException, JvmValue, RefTypeHeader};
+use crate::convert::Reference;
+use crate::error::{bail, format_err, ContextCompat, Result};
+use crate::instructions::ArrayType;
+use crate::vm::Vm;
+
+const UNSAFE: &str = "jdk/internal/misc/Unsafe";
+
+/// The types that `Unsafe` reads and writes, named as in its methods, e.g. `getInt`.
+const TYPES: &[(&str, &str)] = &[
+    ("Boolean", "Z"),
+    ("Byte", "B"),
+    ("Short", "S"),
+    ("Char", "C"),
+    ("Int", "I"),
+    ("Long", "J"),
+    ("Float", "F"),
+    ("Double", "D"),
+    ("Reference", "Ljava/lang/Object;"),
+];
+
+pub(super) fn register(vm: &Vm) -> Result<()> {
+    vm.register_native(
+        UNSAFE,
+        "arrayBaseOffset0",
+        "(Ljava/lang/Class;)I",
+        |_: &Vm, _: Reference, _: Reference| Ok(0),
+    )?;
+
+    vm.register_native(
+        UNSAFE,
+        "arrayIndexScale0",
+        "(Ljava/lang/Class;)I",
+        |vm: &Vm, _: Reference, class: Reference| {
+            let name = mirror_name(vm, class)?;
+            let element = name
+                .strip_prefix('[')
+                .wrap_err_with(|| format_err!("{name} isn't an array class"))?;
+
+            Ok(match element_type(element) {
+                ArrayElementType::Primitive(_) => element_size(element_type(element)) as i32,
+                ArrayElementType::Reference => 1,
+            })
+        },
+    )?;
+
+    vm.register_native(
+        UNSAFE,
+        "objectFieldOffset1",
+        "(Ljava/lang/Class;Ljava/lang/String;)J",
+        |vm: &Vm, _: Reference, class: Reference, name: &str| {
+            let class = vm.load_class(mirror_name(vm, class)?)?;
+            let ordinal = class
+                .fields()
+                .iter()
+                .rposition(|field| field.name == name)
+                .ok_or_else(|| JavaException::new("java/lang/InternalError", name))?;
+
+            Ok(ordinal as i64)
+        },
+    )?;
+
+    vm.register_native(UNSAFE, "addressSize0", "()I", |_: &Vm, _: Reference| Ok(8))?;
+
+    vm.register_native(UNSAFE, "pageSize", "()I", |_: &Vm, _: Reference| Ok(4096))?;
+
+    for fence in ["fullFence", "loadFence", "storeFence"] {
+        vm.register_native(UNSAFE, fence, "()V", |_: &Vm, _: Reference| Ok(()))?;
+    }
+
+    vm.register_native(
+        UNSAFE,
+        "shouldBeInitialized0",
+        "(Ljava/lang/Class;)Z",
+        |vm: &Vm, _: Reference, class: Reference| {
+            Ok(!vm.is_initialized(vm.load_class(mirror_name(vm, class)?)?))
+        },
+    )?;
+
+    vm.register_native(
+        UNSAFE,
+        "ensureClassInitialized0",
+        "(Ljava/lang/Class;)V",
+        |vm: &Vm, _: Reference, class: Reference| {
+            vm.initialize_class(vm.load_class(mirror_name(vm, class)?)?)
+        },
+    )?;
+
+    for &(name, descriptor) in TYPES {
+        for suffix in ["", "Volatile"] {
+            vm.register_raw_native(
+                UNSAFE,
+                &format!("get{name}{suffix}"),
+                &format!("(Ljava/lang/Object;J){descriptor}"),
+                move |_, args| {
+                    let [_, object, JvmValue::Long(offset)] = args else {
+                        bail!("invalid arguments to Unsafe.get{name}: {args:?}");
+                    };
+
+                    Ok(Some(read(object, *offset, descriptor)?))
+                },
+            );
+
+            vm.register_raw_native(
+                UNSAFE,
+                &format!("put{name}{suffix}"),
+                &format!("(Ljava/lang/Object;J{descriptor})V"),
+                move |_, args| {
+                    let [_, object, JvmValue::Long(offset), value] = args else {
+                        bail!("invalid arguments to Unsafe.put{name}: {args:?}");
+                    };
+
+                    write(object, *offset, descriptor, value.clone())?;
+                    Ok(None)
+                },
+            );
+        }
+    }
+
+    for &(name, descriptor) in TYPES {
+        if !matches!(name, "Int" | "Long" | "Reference") {
+            continue;
+        }
+
+        for (operation, exchange) in [("compareAndSet", false), ("compareAndExchange", true)] {
+            vm.register_raw_native(
+                UNSAFE,
+                &format!("{operation}{name}"),
+                &format!(
+                    "(Ljava/lang/Object;J{descriptor}{descriptor}){}",
+                    if exchange { descriptor } else { "Z" }
+                ),
+                move |_, args| {
+                    let [_, object, JvmValue::Long(offset), expected, value] = args else {
+                        bail!("invalid arguments to Unsafe.{operation}{name}: {args:?}");
+                    };
+
+                    let current = read(object, *offset, descriptor)?;
+                    let matches = same_value(&current, expected);
+                    if matches {
+                        write(object, *offset, descriptor, value.clone())?;
+                    }
+
+                    Ok(Some(match exchange {
+                        true => current,
+                        false => JvmValue::Int(matches as i32),
+                    }))
+                },
+            );
+        }
+    }
+
+    // The JDK calls this to find the vm's configuration, which isn't needed
+    vm.register_native("jdk/internal/misc/VM", "initialize", "()V", |_: &Vm| Ok(()))?;
+
+    // The vm has no shared archive like HotSpot's, so there is nothing for the JDK to read from
+    // one
+    for name in [
+        "isDumpingClassList0",
+        "isDumpingArchive0",
+        "isSharingEnabled0",
+    ] {
+        vm.register_native("jdk/internal/misc/CDS", name, "()Z", |_: &Vm| Ok(false))?;
+    }
+
+    vm.register_native(
+        "jdk/internal/misc/CDS",
+        "initializeFromArchive",
+        "(Ljava/lang/Class;)V",
+        |_: &Vm, _: Reference| Ok(()),
+    )?;
+
+    vm.register_native(
+        "jdk/internal/misc/CDS",
+        "getRandomSeedForDumping",
+        "()J",
+        |_: &Vm| Ok(0i64),
+    )?;
+
+    Ok(())
+}
+
+fn mirror_name<'a>(vm: &Vm<'a>, class: Reference) -> Result<&'a str> {
+    if class.0 == 0 {
+        bail!(JavaException::null_pointer());
+    }
+
+    vm.class_mirror_name(class.0)
+        .wrap_err("expected java.lang.Class")
+}
+
+/// Returns the element type of arrays with the given component descriptor.
+fn element_type(descriptor: &str) -> ArrayElementType {
+    let primitive = match descriptor {
+        "Z" => ArrayType::Boolean,
+        "B" => ArrayType::Byte,
+        "S" => ArrayType::Short,
+        "C" => ArrayType::Char,
+        "I" => ArrayType::Int,
+        "J" => ArrayType::Long,
+        "F" => ArrayType::Float,
+        "D" => ArrayType::Double,
+        _ => return ArrayElementType::Reference,
+    };
+
+    ArrayElementType::Primitive(primitive)
+}
+
+fn element_size(element_type: ArrayElementType) -> usize {
+    element_type.element_layout().size()
+}
+
+/// Where a value is read or written, given an object and an offset.
+enum Location<'v, 'a> {
+    Value(&'v mut JvmValue<'a>),
+    Bytes(*mut u8),
+}
+
+fn locate<'v, 'a>(object: &JvmValue<'a>, offset: i64, size: usize) -> Result<Location<'v, 'a>> {
+    let object = match object {
+        JvmValue::Reference(0) => bail!(JavaException::null_pointer()),
+        JvmValue::Reference(object) => *object,
+        value => bail!("expected reference, found {value:?}"),
+    };
+
+    let offset = usize::try_from(offset)?;
+    let header = unsafe { &mut *(object as *mut RefTypeHeader) };
+    let element_type = match header {
+        RefTypeHeader::Object(_) => {
+            let fields = unsafe { header.object_data()? };
+            let field = fields
+                .get_mut(offset)
+                .wrap_err_with(|| format_err!("invalid field offset {offset}"))?;
+            return Ok(Location::Value(field));
+        }
+        RefTypeHeader::Array(array) => array.element_type,
+    };
+
+    match element_type {
+        ArrayElementType::Reference => {
+            let elements = unsafe { header.array_data::<JvmValue>()? };
+            let element = elements
+                .get_mut(offset)
+                .wrap_err_with(|| format_err!("invalid array offset {offset}"))?;
+            Ok(Location::Value(element))
+        }
+        ArrayElementType::Primitive(_) => {
+            let len = unsafe { header.array_data::<u8>()? }.len() * element_size(element_type);
+            if offset + size > len {
+                bail!("invalid array offset {offset}");
+            }
+
+            Ok(Location::Bytes(unsafe {
+                header.array_data_ptr()?.add(offset)
+            }))
+        }
+    }
+}
+
+fn read<'a>(object: &JvmValue<'a>, offset: i64, descriptor: &str) -> Result<JvmValue<'a>> {
+    let size = element_size(element_type(descriptor));
+    let bytes = match locate(object, offset, size)? {
+        Location::Value(value) => return Ok(value.clone()),
+        Location::Bytes(bytes) => bytes,
+    };
+
+    // Values are widened to how they're stored on the operand stack
+    Ok(unsafe {
+        match descriptor {
+            "Z" | "B" => JvmValue::Int(ptr::read(bytes as *const i8).into()),
+            "S" => JvmValue::Int(ptr::read_unaligned(bytes as *const i16).into()),
+            "C" => JvmValue::Int(ptr::read_unaligned(bytes as *const u16).into()),
+            "I" => JvmValue::Int(ptr::read_unaligned(bytes as *const i32)),
+            "J" => JvmValue::Long(ptr::read_unaligned(bytes as *const i64)),
+            "F" => JvmValue::Float(ptr::read_unaligned(bytes as *const f32)),
+            "D" => JvmValue::Double(ptr::read_unaligned(bytes as *const f64)),
+            _ => bail!("can't read a reference from a primitive array"),
+        }
+    })
+}
+
+fn write<'a>(
+    object: &JvmValue<'a>,
+    offset: i64,
+    descriptor: &str,
+    value: JvmValue<'a>,
+) -> Result<()> {
+    let size = element_size(element_type(descriptor));
+    let bytes = match locate(object, offset, size)? {
+        Location::Value(slot) => {
+            *slot = value;
+            return Ok(());
+        }
+        Location::Bytes(bytes) => bytes,
+    };
+
+    unsafe {
+        match (descriptor, value) {
+            ("Z" | "B", JvmValue::Int(v)) => ptr::write(bytes as *mut i8, v as i8),
+            ("S" | "C", JvmValue::Int(v)) => ptr::write_unaligned(bytes as *mut i16, v as i16),
+            ("I", JvmValue::Int(v)) => ptr::write_unaligned(bytes as *mut i32, v),
+            ("J", JvmValue::Long(v)) => ptr::write_unaligned(bytes as *mut i64, v),
+            ("F", JvmValue::Float(v)) => ptr::write_unaligned(bytes as *mut f32, v),
+            ("D", JvmValue::Double(v)) => ptr::write_unaligned(bytes as *mut f64, v),
+            (descriptor, value) => bail!("can't write {value:?} as {descriptor}"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Compares values like `==` in Java, where references are compared by identity.
+fn same_value(a: &JvmValue, b: &JvmValue) -> bool {
+    match (a, b) {
+        (JvmValue::Int(a), JvmValue::Int(b)) => a == b,
+        (JvmValue::Long(a), JvmValue::Long(b)) => a == b,
+        (JvmValue::Reference(a), JvmValue::Reference(b)) => a == b,
+        (JvmValue::StringConst(a), JvmValue::StringConst(b)) => ptr::eq(*a, *b),
+        _ => false,
+    }
+}
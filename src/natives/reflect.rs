@@ -0,0 +1,805 @@
+//! Natives for `java.lang.reflect`. Reflection objects are created from the vm's class
+//! metadata, and refer back to it by their declaring class and slot, like in HotSpot.
+
+use std::sync::Mutex;
+
+use crate::call_frame::{
+    caller_class, callers_caller_class, ArrayElementType, JavaException, JvmValue, RefTypeHeader,
+};
+use crate::class::{Class, Method};
+use crate::class_file::{ClassAccessFlags, FieldAccessFlags, MethodAccessFlags};
+use crate::convert::Reference;
+use crate::descriptor::{parse_field_descriptor, BaseType, FieldType};
+use crate::error::{bail, format_err, ContextCompat, Result};
+use crate::vm::Vm;
+
+use super::{box_primitive, mirrored_class_name, signature_string, unbox_primitive};
+
+const METHOD: &str = "java/lang/reflect/Method";
+const FIELD: &str = "java/lang/reflect/Field";
+
+pub(super) fn register(vm: &Vm) -> Result<()> {
+    register_methods(vm)?;
+    register_fields(vm)?;
+
+    // Replaces the JDK implementation, which checks the caller using stack walking
+    for class in ["java/lang/reflect/AccessibleObject", METHOD, FIELD] {
+        vm.register_native(
+            class,
+            "setAccessible",
+            "(Z)V",
+            |vm: &Vm, this: Reference, flag: bool| {
+                vm.set_field(this.0, "override", "Z", JvmValue::Int(flag.into()))
+            },
+        )?;
+    }
+
+    vm.register_native(
+        "jdk/internal/reflect/Reflection",
+        "getCallerClass",
+        "()Ljava/lang/Class;",
+        |vm: &Vm| match callers_caller_class() {
+            Some(class) => Ok(Some(Reference(vm.class_mirror(class.name())?))),
+            None => Ok(None),
+        },
+    )?;
+
+    Ok(())
+}
+
+fn register_methods<'a>(vm: &Vm<'a>) -> Result<()> {
+    vm.register_native(
+        "java/lang/Class",
+        "getDeclaredMethods",
+        "()[Ljava/lang/reflect/Method;",
+        |vm: &Vm, this: Reference| {
+            let name = mirrored_class_name(vm, this)?;
+
+            // Primitive and array types don't declare any methods
+            let mut methods = vec![];
+            if !super::is_primitive(name) && !name.starts_with('[') {
+                let class = vm.load_class(name)?;
+                for (slot, (name, ..)) in class.declared_methods().enumerate() {
+                    if !is_initializer(name) {
+                        methods.push(JvmValue::Reference(new_method(vm, class, slot)?));
+                    }
+                }
+            }
+
+            Ok(Reference(reference_array(vm, &methods)?))
+        },
+    )?;
+
+    vm.register_native(
+        "java/lang/Class",
+        "getDeclaredMethod",
+        "(Ljava/lang/String;[Ljava/lang/Class;)Ljava/lang/reflect/Method;",
+        |vm: &Vm<'a>, this: Reference, name: Option<&'a str>, parameter_types: JvmValue<'a>| {
+            let class_name = mirrored_class_name(vm, this)?;
+            let Some(name) = name else {
+                bail!(JavaException::null_pointer());
+            };
+
+            // A null array of parameter types is the same as an empty one
+            let parameter_types = match parameter_types {
+                JvmValue::Reference(0) => vec![],
+                ref array => reference_array_elements(array)?
+                    .iter()
+                    .map(|mirror| mirror_name(vm, mirror))
+                    .collect::<Result<Vec<_>>>()?,
+            };
+
+            let not_found = || {
+                JavaException::new(
+                    "java/lang/NoSuchMethodException",
+                    format!(
+                        "{}.{name}({})",
+                        class_name.replace('/', "."),
+                        parameter_types.join(", ").replace('/', ".")
+                    ),
+                )
+            };
+
+            if super::is_primitive(class_name)
+                || class_name.starts_with('[')
+                || is_initializer(name)
+            {
+                bail!(not_found());
+            }
+
+            let class = vm.load_class(class_name)?;
+            let slot = class
+                .declared_methods()
+                .position(|(method_name, _, method)| {
+                    method_name == name
+                        && method.descriptor.params.len() == parameter_types.len()
+                        && method
+                            .descriptor
+                            .params
+                            .iter()
+                            .zip(&parameter_types)
+                            .all(|(param, name)| type_name(param) == *name)
+                });
+
+            let Some(slot) = slot else {
+                bail!(not_found());
+            };
+
+            Ok(Reference(new_method(vm, class, slot)?))
+        },
+    )?;
+
+    // The JDK implementation clones the array, which the vm doesn't support yet
+    vm.register_native(
+        METHOD,
+        "getParameterTypes",
+        "()[Ljava/lang/Class;",
+        |vm: &Vm, this: Reference| {
+            let (_, _, _, method) = reflected_method(vm, this.0)?;
+
+            let types = method
+                .descriptor
+                .params
+                .iter()
+                .map(|param| Ok(JvmValue::Reference(vm.class_mirror(&type_name(param))?)))
+                .collect::<Result<Vec<_>>>()?;
+
+            Ok(Reference(reference_array(vm, &types)?))
+        },
+    )?;
+
+    vm.register_native(
+        METHOD,
+        "invoke",
+        "(Ljava/lang/Object;[Ljava/lang/Object;)Ljava/lang/Object;",
+        |vm: &Vm<'a>, this: Reference, receiver: JvmValue<'a>, arguments: JvmValue<'a>| {
+            invoke(vm, this.0, &receiver, &arguments)
+        },
+    )?;
+
+    Ok(())
+}
+
+fn register_fields<'a>(vm: &Vm<'a>) -> Result<()> {
+    vm.register_native(
+        "java/lang/Class",
+        "getDeclaredFields",
+        "()[Ljava/lang/reflect/Field;",
+        |vm: &Vm, this: Reference| {
+            let name = mirrored_class_name(vm, this)?;
+
+            // Primitive and array types don't declare any fields
+            let mut fields = vec![];
+            if !super::is_primitive(name) && !name.starts_with('[') {
+                let class = vm.load_class(name)?;
+                for slot in 0..class.declared_fields().count() {
+                    fields.push(JvmValue::Reference(new_field(vm, class, slot)?));
+                }
+            }
+
+            Ok(Reference(reference_array(vm, &fields)?))
+        },
+    )?;
+
+    vm.register_native(
+        "java/lang/Class",
+        "getDeclaredField",
+        "(Ljava/lang/String;)Ljava/lang/reflect/Field;",
+        |vm: &Vm<'a>, this: Reference, name: Option<&'a str>| {
+            let class_name = mirrored_class_name(vm, this)?;
+            let Some(name) = name else {
+                bail!(JavaException::null_pointer());
+            };
+
+            let slot = if super::is_primitive(class_name) || class_name.starts_with('[') {
+                None
+            } else {
+                vm.load_class(class_name)?
+                    .declared_fields()
+                    .position(|(field_name, ..)| field_name == name)
+            };
+
+            let Some(slot) = slot else {
+                bail!(JavaException::new(
+                    "java/lang/NoSuchFieldException",
+                    name.to_string()
+                ));
+            };
+
+            let class = vm.load_class(class_name)?;
+            Ok(Reference(new_field(vm, class, slot)?))
+        },
+    )?;
+
+    vm.register_native(
+        FIELD,
+        "get",
+        "(Ljava/lang/Object;)Ljava/lang/Object;",
+        |vm: &Vm<'a>, this: Reference, object: JvmValue<'a>| {
+            let field = ReflectedField::new(vm, this.0)?;
+            let value = match field.target(vm, &object)? {
+                Some(object) => vm.get_field(object, field.name, field.descriptor)?,
+                None => field.static_value()?.lock().unwrap().clone(),
+            };
+
+            Ok(match field.primitive_descriptor() {
+                Some(descriptor) => JvmValue::Reference(box_primitive(vm, descriptor, value)?),
+                None => value,
+            })
+        },
+    )?;
+
+    vm.register_native(
+        FIELD,
+        "set",
+        "(Ljava/lang/Object;Ljava/lang/Object;)V",
+        |vm: &Vm<'a>, this: Reference, object: JvmValue<'a>, value: JvmValue<'a>| {
+            let field = ReflectedField::new(vm, this.0)?;
+            let target = field.target(vm, &object)?;
+
+            // Final instance fields can only be set if access checks are suppressed, and final
+            // static fields can't be set at all
+            if field.flags.contains(FieldAccessFlags::FINAL)
+                && (field.flags.contains(FieldAccessFlags::STATIC) || !field.is_override)
+            {
+                bail!(JavaException::new(
+                    "java/lang/IllegalAccessException",
+                    field.set_error(vm, &value)?
+                ));
+            }
+
+            let converted = match field.primitive_descriptor() {
+                Some(to) => {
+                    unbox_primitive(vm, &value)?.and_then(|(from, value)| widen(from, to, value))
+                }
+                None if matches!(value, JvmValue::Reference(0))
+                    || vm.is_instance_of(&value, &type_name(&field.field_type))? =>
+                {
+                    Some(value.clone())
+                }
+                None => None,
+            };
+
+            let Some(converted) = converted else {
+                bail!(illegal_argument(field.set_error(vm, &value)?));
+            };
+
+            match target {
+                Some(object) => vm.set_field(object, field.name, field.descriptor, converted)?,
+                None => *field.static_value()?.lock().unwrap() = converted,
+            }
+
+            Ok(())
+        },
+    )?;
+
+    Ok(())
+}
+
+/// Creates a `java.lang.reflect.Field` for the field declared by a class at the given slot.
+fn new_field(vm: &Vm, class: &Class, slot: usize) -> Result<usize> {
+    let (name, descriptor, flags) = class
+        .declared_fields()
+        .nth(slot)
+        .wrap_err("invalid field slot")?;
+
+    let field_type = parse_field_descriptor(descriptor)?.field_type;
+
+    // Like Method, Field isn't initialized
+    let object = vm.alloc_object(vm.load_class(FIELD)?)?;
+
+    vm.set_field(
+        object,
+        "clazz",
+        "Ljava/lang/Class;",
+        JvmValue::Reference(vm.class_mirror(class.name())?),
+    )?;
+    vm.set_field(object, "slot", "I", JvmValue::Int(slot.try_into()?))?;
+    vm.set_field(
+        object,
+        "name",
+        "Ljava/lang/String;",
+        JvmValue::StringConst(vm.intern(name)),
+    )?;
+    vm.set_field(
+        object,
+        "type",
+        "Ljava/lang/Class;",
+        JvmValue::Reference(vm.class_mirror(&type_name(&field_type))?),
+    )?;
+    vm.set_field(object, "modifiers", "I", JvmValue::Int(flags.bits() as i32))?;
+    vm.set_field(
+        object,
+        "signature",
+        "Ljava/lang/String;",
+        signature_string(vm, class.declared_field_signature(slot)),
+    )?;
+
+    Ok(object)
+}
+
+/// The field referred to by a `java.lang.reflect.Field`.
+struct ReflectedField<'a> {
+    class: &'a Class<'a>,
+    name: &'a str,
+    descriptor: &'a str,
+    flags: &'a FieldAccessFlags,
+    field_type: FieldType<'a>,
+    is_override: bool,
+}
+
+impl<'a> ReflectedField<'a> {
+    fn new(vm: &Vm<'a>, object: usize) -> Result<ReflectedField<'a>> {
+        let JvmValue::Reference(mirror) = vm.get_field(object, "clazz", "Ljava/lang/Class;")?
+        else {
+            bail!("expected reference");
+        };
+
+        let class_name = vm
+            .class_mirror_name(mirror)
+            .wrap_err("invalid declaring class")?;
+
+        let slot = vm
+            .get_field(object, "slot", "I")?
+            .try_as_int()
+            .wrap_err("expected int")?;
+
+        let class = vm.load_class(class_name)?;
+        let (name, descriptor, flags) = class
+            .declared_fields()
+            .nth(slot.try_into()?)
+            .wrap_err("invalid field slot")?;
+
+        Ok(ReflectedField {
+            class,
+            name,
+            descriptor,
+            flags,
+            field_type: parse_field_descriptor(descriptor)?.field_type,
+            is_override: vm.get_field(object, "override", "Z")?.try_as_int() == Some(1),
+        })
+    }
+
+    /// Checks that the field can be accessed through the given object, returning the object for
+    /// instance fields, or `None` for static fields after initializing their class.
+    fn target(&self, vm: &Vm<'a>, object: &JvmValue<'a>) -> Result<Option<usize>> {
+        if !self.is_override {
+            check_member_access(self.class, self.flags.bits())?;
+        }
+
+        if self.flags.contains(FieldAccessFlags::STATIC) {
+            vm.initialize_class(self.class)?;
+            return Ok(None);
+        }
+
+        match object {
+            JvmValue::Reference(0) => bail!(JavaException::null_pointer()),
+            JvmValue::Reference(object)
+                if vm.is_instance_of(&JvmValue::Reference(*object), self.class.name())? =>
+            {
+                Ok(Some(*object))
+            }
+            object => bail!(illegal_argument(self.set_error(vm, object)?)),
+        }
+    }
+
+    fn static_value(&self) -> Result<&'a Mutex<JvmValue<'a>>> {
+        self.class
+            .static_field(self.name, self.descriptor)
+            .wrap_err_with(|| format_err!("field not found: {}.{}", self.class.name(), self.name))
+    }
+
+    fn primitive_descriptor(&self) -> Option<&'static str> {
+        match &self.field_type {
+            FieldType::Base(base) => primitive_descriptor(base),
+            FieldType::Array(..) => None,
+        }
+    }
+
+    /// Returns the message used when a value can't be assigned to the field, which is also used
+    /// for invalid objects when getting fields, like in the JDK.
+    fn set_error(&self, vm: &Vm<'a>, value: &JvmValue<'a>) -> Result<String> {
+        let mut message = "Can not set".to_owned();
+        if self.flags.contains(FieldAccessFlags::STATIC) {
+            message += " static";
+        }
+        if self.flags.contains(FieldAccessFlags::FINAL) {
+            message += " final";
+        }
+
+        let value = match value {
+            JvmValue::Reference(0) => "null value".to_owned(),
+            value => vm.runtime_class(value)?.name().replace('/', "."),
+        };
+
+        Ok(format!(
+            "{message} {} field {}.{} to {value}",
+            type_name(&self.field_type).replace('/', "."),
+            self.class.name().replace('/', "."),
+            self.name,
+        ))
+    }
+}
+
+/// Creates a `java.lang.reflect.Method` for the method declared by a class at the given slot.
+fn new_method(vm: &Vm, class: &Class, slot: usize) -> Result<usize> {
+    let (name, _, method) = class
+        .declared_methods()
+        .nth(slot)
+        .wrap_err("invalid method slot")?;
+
+    // Method isn't initialized, since AccessibleObject's static initializer depends on parts of
+    // the JDK that the vm doesn't support yet
+    let object = vm.alloc_object(vm.load_class(METHOD)?)?;
+
+    let parameter_types = method
+        .descriptor
+        .params
+        .iter()
+        .map(|param| Ok(JvmValue::Reference(vm.class_mirror(&type_name(param))?)))
+        .collect::<Result<Vec<_>>>()?;
+
+    let return_type = match &method.descriptor.return_type {
+        Some(return_type) => type_name(return_type),
+        None => "void".to_owned(),
+    };
+
+    vm.set_field(
+        object,
+        "clazz",
+        "Ljava/lang/Class;",
+        JvmValue::Reference(vm.class_mirror(class.name())?),
+    )?;
+    vm.set_field(object, "slot", "I", JvmValue::Int(slot.try_into()?))?;
+    vm.set_field(
+        object,
+        "name",
+        "Ljava/lang/String;",
+        JvmValue::StringConst(vm.intern(name)),
+    )?;
+    vm.set_field(
+        object,
+        "returnType",
+        "Ljava/lang/Class;",
+        JvmValue::Reference(vm.class_mirror(&return_type)?),
+    )?;
+    vm.set_field(
+        object,
+        "parameterTypes",
+        "[Ljava/lang/Class;",
+        JvmValue::Reference(reference_array(vm, &parameter_types)?),
+    )?;
+    vm.set_field(
+        object,
+        "exceptionTypes",
+        "[Ljava/lang/Class;",
+        JvmValue::Reference(reference_array(vm, &[])?),
+    )?;
+    vm.set_field(
+        object,
+        "modifiers",
+        "I",
+        JvmValue::Int(method.access_flags.bits() as i32),
+    )?;
+    vm.set_field(
+        object,
+        "signature",
+        "Ljava/lang/String;",
+        signature_string(vm, class.declared_method_signature(slot)),
+    )?;
+
+    Ok(object)
+}
+
+/// Returns the declaring class, name, descriptor and metadata of a `java.lang.reflect.Method`.
+fn reflected_method<'a>(
+    vm: &Vm<'a>,
+    object: usize,
+) -> Result<(&'a Class<'a>, &'a str, &'a str, &'a Method<'a>)> {
+    let JvmValue::Reference(mirror) = vm.get_field(object, "clazz", "Ljava/lang/Class;")? else {
+        bail!("expected reference");
+    };
+
+    let class_name = vm
+        .class_mirror_name(mirror)
+        .wrap_err("invalid declaring class")?;
+
+    let slot = vm
+        .get_field(object, "slot", "I")?
+        .try_as_int()
+        .wrap_err("expected int")?;
+
+    let class = vm.load_class(class_name)?;
+    let (name, descriptor, method) = class
+        .declared_methods()
+        .nth(slot.try_into()?)
+        .wrap_err("invalid method slot")?;
+
+    Ok((class, name, descriptor, method))
+}
+
+/// Implements `Method.invoke`, converting the arguments to the parameter types of the method,
+/// and boxing the return value.
+fn invoke<'a>(
+    vm: &Vm<'a>,
+    method_object: usize,
+    receiver: &JvmValue<'a>,
+    arguments: &JvmValue<'a>,
+) -> Result<JvmValue<'a>> {
+    let (class, name, descriptor, method) = reflected_method(vm, method_object)?;
+
+    let is_override = vm.get_field(method_object, "override", "Z")?.try_as_int() == Some(1);
+    if !is_override {
+        check_member_access(class, method.access_flags.bits())?;
+    }
+
+    let is_static = method.access_flags.contains(MethodAccessFlags::STATIC);
+    if !is_static {
+        if let JvmValue::Reference(0) = receiver {
+            bail!(JavaException::null_pointer());
+        }
+
+        if !vm.is_instance_of(receiver, class.name())? {
+            bail!(illegal_argument(
+                "object is not an instance of declaring class"
+            ));
+        }
+    }
+
+    // A null array of arguments is the same as an empty one
+    let arguments = match arguments {
+        JvmValue::Reference(0) => &[],
+        arguments => reference_array_elements(arguments)?,
+    };
+
+    if arguments.len() != method.descriptor.params.len() {
+        bail!(illegal_argument(format!(
+            "wrong number of arguments: {} expected: {}",
+            arguments.len(),
+            method.descriptor.params.len()
+        )));
+    }
+
+    let mut args = vec![];
+    if !is_static {
+        args.push(receiver.clone());
+    }
+
+    for (param, argument) in method.descriptor.params.iter().zip(arguments) {
+        args.push(convert_argument(vm, param, argument)?);
+    }
+
+    // Instance methods are selected from the class of the receiver, unless they are private
+    let (class, method) = if is_static || method.access_flags.contains(MethodAccessFlags::PRIVATE) {
+        vm.initialize_class(class)?;
+        (class, method)
+    } else {
+        vm.find_method(vm.runtime_class(receiver)?, name, descriptor)?
+    };
+
+    let result = vm
+        .invoke_method(class, method, name, descriptor, args.into_iter())
+        .map_err(|e| match e.exception() {
+            // Exceptions thrown by the method are wrapped, but exceptions don't have causes yet
+            Some(cause) => format_err!(JavaException::new(
+                "java/lang/reflect/InvocationTargetException",
+                cause.to_string()
+            )),
+            None => e,
+        })?;
+
+    Ok(match (&method.descriptor.return_type, result) {
+        (None, _) => JvmValue::Reference(0),
+        (Some(FieldType::Base(base)), Some(value)) if primitive_descriptor(base).is_some() => {
+            let descriptor = primitive_descriptor(base).unwrap();
+            JvmValue::Reference(box_primitive(vm, descriptor, value)?)
+        }
+        (Some(_), Some(value)) => value,
+        (Some(_), None) => bail!("missing return value from {name}{descriptor}"),
+    })
+}
+
+/// Converts an argument passed to `Method.invoke` to the type of a parameter, unboxing and
+/// widening primitive values.
+fn convert_argument<'a>(
+    vm: &Vm<'a>,
+    param: &FieldType,
+    argument: &JvmValue<'a>,
+) -> Result<JvmValue<'a>> {
+    let type_mismatch = || illegal_argument("argument type mismatch");
+
+    let primitive = match param {
+        FieldType::Base(base) => primitive_descriptor(base),
+        _ => None,
+    };
+
+    if let Some(to) = primitive {
+        let Some((from, value)) = unbox_primitive(vm, argument)? else {
+            bail!(type_mismatch());
+        };
+
+        return widen(from, to, value).wrap_err_with(type_mismatch);
+    }
+
+    if !matches!(argument, JvmValue::Reference(0))
+        && !vm.is_instance_of(argument, &type_name(param))?
+    {
+        bail!(type_mismatch());
+    }
+
+    Ok(argument.clone())
+}
+
+/// Applies a widening primitive conversion, returning `None` if the conversion isn't allowed.
+fn widen<'a>(from: &str, to: &str, value: JvmValue<'a>) -> Option<JvmValue<'a>> {
+    if from == to {
+        return Some(value);
+    }
+
+    Some(match (value, to) {
+        // Booleans can't be converted to any other type
+        (JvmValue::Int(_), _) if from == "Z" => return None,
+        (JvmValue::Int(v), "S") if from == "B" => JvmValue::Int(v),
+        (JvmValue::Int(v), "I") if from != "Z" => JvmValue::Int(v),
+        (JvmValue::Int(v), "J") => JvmValue::Long(v as i64),
+        (JvmValue::Int(v), "F") => JvmValue::Float(v as f32),
+        (JvmValue::Int(v), "D") => JvmValue::Double(v as f64),
+        (JvmValue::Long(v), "F") => JvmValue::Float(v as f32),
+        (JvmValue::Long(v), "D") => JvmValue::Double(v as f64),
+        (JvmValue::Float(v), "D") => JvmValue::Double(v as f64),
+        _ => return None,
+    })
+}
+
+/// Checks that the caller of a native can access a member of a class with the given modifiers,
+/// like `Reflection.verifyMemberAccess`.
+fn check_member_access(class: &Class, modifiers: u16) -> Result<()> {
+    // Natives called from outside of Java code have full access
+    let Some(caller) = caller_class() else {
+        return Ok(());
+    };
+
+    let modifiers = MethodAccessFlags::from_bits_truncate(modifiers);
+    let same_package = package(caller.name()) == package(class.name());
+
+    let allowed = if caller.name() == class.name() {
+        true
+    } else if !class.access_flags().contains(ClassAccessFlags::PUBLIC) && !same_package {
+        false
+    } else if modifiers.contains(MethodAccessFlags::PUBLIC) {
+        true
+    } else if modifiers.contains(MethodAccessFlags::PRIVATE) {
+        // Nested classes can access the private members of their enclosing classes
+        top_level_name(caller.name()) == top_level_name(class.name())
+    } else if same_package {
+        true
+    } else if modifiers.contains(MethodAccessFlags::PROTECTED) {
+        let mut super_class = caller.super_class();
+        loop {
+            match super_class {
+                Some(c) if c.name() == class.name() => break true,
+                Some(c) => super_class = c.super_class(),
+                None => break false,
+            }
+        }
+    } else {
+        false
+    };
+
+    if !allowed {
+        let visibility = if modifiers.contains(MethodAccessFlags::PRIVATE) {
+            "\"private\""
+        } else if modifiers.contains(MethodAccessFlags::PROTECTED) {
+            "\"protected\""
+        } else if modifiers.contains(MethodAccessFlags::PUBLIC) {
+            "\"public\""
+        } else {
+            "\"\""
+        };
+
+        bail!(JavaException::new(
+            "java/lang/IllegalAccessException",
+            format!(
+                "class {} cannot access a member of class {} with modifiers {visibility}",
+                caller.name().replace('/', "."),
+                class.name().replace('/', "."),
+            )
+        ));
+    }
+
+    Ok(())
+}
+
+fn package(class_name: &str) -> &str {
+    class_name
+        .rsplit_once('/')
+        .map_or("", |(package, _)| package)
+}
+
+fn top_level_name(class_name: &str) -> &str {
+    class_name
+        .split_once('$')
+        .map_or(class_name, |(name, _)| name)
+}
+
+fn is_initializer(name: &str) -> bool {
+    name == "<init>" || name == "<clinit>"
+}
+
+fn illegal_argument(message: impl Into<String>) -> JavaException {
+    JavaException::new("java/lang/IllegalArgumentException", message)
+}
+
+/// Returns the descriptor of a primitive type, or `None` for class types.
+fn primitive_descriptor(base: &BaseType) -> Option<&'static str> {
+    Some(match base {
+        BaseType::Byte => "B",
+        BaseType::Char => "C",
+        BaseType::Double => "D",
+        BaseType::Float => "F",
+        BaseType::Int => "I",
+        BaseType::Long => "J",
+        BaseType::Short => "S",
+        BaseType::Boolean => "Z",
+        BaseType::Object(_) => return None,
+    })
+}
+
+/// Returns the name of a type as used for class mirrors, e.g. `int`, `java/lang/String` or
+/// `[Ljava/lang/String;`.
+fn type_name(field_type: &FieldType) -> String {
+    match field_type {
+        FieldType::Base(BaseType::Object(name)) => name.to_string(),
+        FieldType::Base(base) => match base {
+            BaseType::Byte => "byte",
+            BaseType::Char => "char",
+            BaseType::Double => "double",
+            BaseType::Float => "float",
+            BaseType::Int => "int",
+            BaseType::Long => "long",
+            BaseType::Short => "short",
+            BaseType::Boolean => "boolean",
+            BaseType::Object(_) => unreachable!(),
+        }
+        .to_owned(),
+        FieldType::Array(dimensions, base) => {
+            let component = match base {
+                BaseType::Object(name) => format!("L{name};"),
+                base => primitive_descriptor(base).unwrap().to_owned(),
+            };
+            "[".repeat(*dimensions as usize) + &component
+        }
+    }
+}
+
+fn mirror_name<'a>(vm: &Vm<'a>, mirror: &JvmValue<'a>) -> Result<&'a str> {
+    match mirror {
+        JvmValue::Reference(0) => bail!(JavaException::null_pointer()),
+        JvmValue::Reference(mirror) => vm
+            .class_mirror_name(*mirror)
+            .wrap_err("expected java.lang.Class"),
+        _ => bail!("expected reference"),
+    }
+}
+
+fn reference_array<'a>(vm: &Vm<'a>, values: &[JvmValue<'a>]) -> Result<usize> {
+    let array = vm.alloc_array(ArrayElementType::Reference, values.len())?;
+    let header = unsafe { &mut *(array as *mut RefTypeHeader) };
+    unsafe { header.array_data::<JvmValue>()? }.clone_from_slice(values);
+    Ok(array)
+}
+
+fn reference_array_elements<'a, 'b>(array: &JvmValue<'a>) -> Result<&'b [JvmValue<'a>]> {
+    let JvmValue::Reference(array) = array else {
+        bail!("expected reference");
+    };
+
+    let header = unsafe { &mut *(*array as *mut RefTypeHeader) };
+    match header {
+        RefTypeHeader::Array(array)
+            if matches!(array.element_type, ArrayElementType::Reference) => {}
+        _ => bail!("expected reference array"),
+    }
+
+    Ok(unsafe { header.array_data::<JvmValue>()? })
+}
@@ -0,0 +1,297 @@
+//! Natives for `java.util.Scanner`.
+
+use std::str::FromStr;
+
+use crate::call_frame::{ArrayElementType, JavaException, JvmValue, RefTypeHeader};
+use crate::convert::{Reference, ToJvm};
+use crate::error::{bail, ContextCompat, Result};
+use crate::instructions::ArrayType;
+use crate::vm::Vm;
+
+const SCANNER: &str = "java/util/Scanner";
+
+/// The number of characters read from a scanner's source at a time.
+const READ_SIZE: usize = 1024;
+
+/// Replacements for the methods of `java.util.Scanner` which read input. The JDK implementation
+/// matches its input with regular expressions, and parses numbers in the format of the default
+/// locale, neither of which the vm can run yet. Instead, tokens are separated by whitespace and
+/// numbers are parsed like `Integer.parseInt`, and the input which has been read from a scanner's
+/// source but not consumed yet is kept by the vm.
+pub(super) fn register<'a>(vm: &Vm<'a>) -> Result<()> {
+    // This compiles the patterns which the JDK implementation matches input with
+    vm.register_native(SCANNER, "<clinit>", "()V", |_: &Vm| Ok(()))?;
+
+    vm.register_native(
+        SCANNER,
+        "<init>",
+        "(Ljava/io/InputStream;)V",
+        |vm: &Vm, this: Reference, stream: Option<Reference>| {
+            let Some(stream) = stream else {
+                bail!(JavaException::new(
+                    "java/lang/NullPointerException",
+                    "source"
+                ));
+            };
+
+            let reader = vm.new_object(
+                "java/io/InputStreamReader",
+                "(Ljava/io/InputStream;)V",
+                (JvmValue::Reference(stream.0),),
+            )?;
+            vm.set_field(
+                this.0,
+                "source",
+                "Ljava/lang/Readable;",
+                JvmValue::Reference(reader.reference()),
+            )
+        },
+    )?;
+
+    // The whole string is the scanner's input, so it has no source to read from
+    vm.register_native(
+        SCANNER,
+        "<init>",
+        "(Ljava/lang/String;)V",
+        |vm: &Vm<'a>, this: Reference, source: Option<&'a str>| {
+            let source = source
+                .ok_or_else(|| JavaException::new("java/lang/NullPointerException", "source"))?;
+
+            vm.scanner_input().insert(this.0, source.to_owned());
+            vm.set_field(this.0, "sourceClosed", "Z", JvmValue::Int(1))
+        },
+    )?;
+
+    vm.register_native(SCANNER, "hasNextLine", "()Z", |vm: &Vm, this: Reference| {
+        let this = open_scanner(vm, this)?;
+        Ok(next_line(vm, this)?.is_some())
+    })?;
+
+    vm.register_native(
+        SCANNER,
+        "nextLine",
+        "()Ljava/lang/String;",
+        |vm: &Vm, this: Reference| {
+            let this = open_scanner(vm, this)?;
+            let Some((line, len)) = next_line(vm, this)? else {
+                bail!(JavaException::new(
+                    "java/util/NoSuchElementException",
+                    "No line found"
+                ));
+            };
+
+            consume(vm, this, len);
+            Ok(line)
+        },
+    )?;
+
+    vm.register_native(SCANNER, "hasNext", "()Z", |vm: &Vm, this: Reference| {
+        let this = open_scanner(vm, this)?;
+        Ok(next_token(vm, this)?.is_some())
+    })?;
+
+    vm.register_native(
+        SCANNER,
+        "next",
+        "()Ljava/lang/String;",
+        |vm: &Vm, this: Reference| {
+            let this = open_scanner(vm, this)?;
+            let Some((token, len)) = next_token(vm, this)? else {
+                bail!(JavaException::new("java/util/NoSuchElementException", ""));
+            };
+
+            consume(vm, this, len);
+            Ok(token)
+        },
+    )?;
+
+    register_number::<i32>(vm, "Int")?;
+    register_number::<i64>(vm, "Long")?;
+    register_number::<f64>(vm, "Double")?;
+
+    vm.register_native(SCANNER, "close", "()V", |vm: &Vm, this: Reference| {
+        if is_closed(vm, this.0)? {
+            return Ok(());
+        }
+
+        if let JvmValue::Reference(source) =
+            vm.get_field(this.0, "source", "Ljava/lang/Readable;")?
+        {
+            if source != 0 {
+                vm.invoke_virtual(source, "close", "()V", &[])?;
+            }
+        }
+
+        vm.set_field(this.0, "closed", "Z", JvmValue::Int(1))?;
+        vm.scanner_input().remove(&this.0);
+
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+/// Registers `hasNextX` and `nextX` for a type of number, where `X` is the name of the type.
+fn register_number<'a, T: FromStr + ToJvm<'a> + 'a>(vm: &Vm<'a>, name: &str) -> Result<()> {
+    vm.register_native(
+        SCANNER,
+        &format!("hasNext{name}"),
+        "()Z",
+        |vm: &Vm, this: Reference| {
+            let this = open_scanner(vm, this)?;
+            Ok(next_token(vm, this)?.is_some_and(|(token, _)| token.parse::<T>().is_ok()))
+        },
+    )?;
+
+    vm.register_native(
+        SCANNER,
+        &format!("next{name}"),
+        &format!("(){}", T::descriptor()),
+        |vm: &Vm, this: Reference| {
+            let this = open_scanner(vm, this)?;
+            let Some((token, len)) = next_token(vm, this)? else {
+                bail!(JavaException::new("java/util/NoSuchElementException", ""));
+            };
+
+            // The token isn't consumed if it isn't a number, so that it can be read another way
+            let Ok(number) = token.parse::<T>() else {
+                bail!(JavaException::new(
+                    "java/util/InputMismatchException",
+                    format!("For input string: \"{token}\"")
+                ));
+            };
+
+            consume(vm, this, len);
+            Ok(number)
+        },
+    )?;
+
+    Ok(())
+}
+
+/// Returns the address of a scanner which a method was called on, throwing
+/// `IllegalStateException` if it's been closed.
+fn open_scanner(vm: &Vm, this: Reference) -> Result<usize> {
+    if is_closed(vm, this.0)? {
+        bail!(JavaException::new(
+            "java/lang/IllegalStateException",
+            "Scanner closed"
+        ));
+    }
+
+    Ok(this.0)
+}
+
+fn is_closed(vm: &Vm, scanner: usize) -> Result<bool> {
+    Ok(vm.get_field(scanner, "closed", "Z")?.try_as_int() == Some(1))
+}
+
+/// Returns the next line of a scanner's input without consuming it, along with the number of
+/// bytes of input which it takes up, including its line separator. Input is read until a line
+/// separator is found or the input ends.
+fn next_line(vm: &Vm, scanner: usize) -> Result<Option<(String, usize)>> {
+    loop {
+        {
+            let input = vm.scanner_input();
+            let input = input.get(&scanner).map_or("", String::as_str);
+
+            // A carriage return at the end of the input may be followed by a line feed which
+            // hasn't been read yet
+            if let Some(end) = input
+                .find(['\n', '\r'])
+                .filter(|&end| &input[end..] != "\r")
+            {
+                let separator = if input[end..].starts_with("\r\n") {
+                    2
+                } else {
+                    1
+                };
+                return Ok(Some((input[..end].to_owned(), end + separator)));
+            }
+        }
+
+        if !read(vm, scanner)? {
+            let input = vm.scanner_input();
+            let input = input.get(&scanner).map_or("", String::as_str);
+            if input.is_empty() {
+                return Ok(None);
+            }
+
+            let line = input.strip_suffix('\r').unwrap_or(input);
+            return Ok(Some((line.to_owned(), input.len())));
+        }
+    }
+}
+
+/// Returns the next token of a scanner's input without consuming it, along with the number of
+/// bytes of input up to its end. Input is read until the token is followed by whitespace or the
+/// input ends.
+fn next_token(vm: &Vm, scanner: usize) -> Result<Option<(String, usize)>> {
+    loop {
+        {
+            let input = vm.scanner_input();
+            let input = input.get(&scanner).map_or("", String::as_str);
+
+            if let Some(start) = input.find(|c: char| !c.is_whitespace()) {
+                if let Some(len) = input[start..].find(char::is_whitespace) {
+                    return Ok(Some((input[start..start + len].to_owned(), start + len)));
+                }
+            }
+        }
+
+        if !read(vm, scanner)? {
+            let input = vm.scanner_input();
+            let input = input.get(&scanner).map_or("", String::as_str);
+            return Ok(input
+                .find(|c: char| !c.is_whitespace())
+                .map(|start| (input[start..].to_owned(), input.len())));
+        }
+    }
+}
+
+/// Removes input which has been scanned from the start of a scanner's input.
+fn consume(vm: &Vm, scanner: usize, len: usize) {
+    if let Some(input) = vm.scanner_input().get_mut(&scanner) {
+        input.drain(..len);
+    }
+}
+
+/// Reads more input from a scanner's source, returning false if its input has ended.
+fn read(vm: &Vm, scanner: usize) -> Result<bool> {
+    if vm.get_field(scanner, "sourceClosed", "Z")?.try_as_int() == Some(1) {
+        return Ok(false);
+    }
+
+    let source = vm
+        .get_field(scanner, "source", "Ljava/lang/Readable;")?
+        .try_as_reference()
+        .wrap_err("expected reference")?;
+
+    let chars = vm.alloc_array(ArrayElementType::Primitive(ArrayType::Char), READ_SIZE)?;
+    let n = vm
+        .invoke_virtual(
+            source,
+            "read",
+            "([CII)I",
+            &[
+                JvmValue::Reference(chars),
+                JvmValue::Int(0),
+                JvmValue::Int(READ_SIZE as i32),
+            ],
+        )?
+        .and_then(|n| n.try_as_int())
+        .wrap_err("expected int")?;
+
+    if n < 0 {
+        vm.set_field(scanner, "sourceClosed", "Z", JvmValue::Int(1))?;
+        return Ok(false);
+    }
+
+    let chars = unsafe { (*(chars as *mut RefTypeHeader)).array_data::<u16>()? };
+    vm.scanner_input()
+        .entry(scanner)
+        .or_default()
+        .push_str(&String::from_utf16_lossy(&chars[..n as usize]));
+
+    Ok(true)
+}
@@ -0,0 +1,313 @@
+//! Natives for `java.lang.String` and `java.lang.StringBuilder`.
+
+use crate::call_frame::{ArrayElementType, JavaException, JvmValue, RefTypeHeader};
+use crate::convert::{NativeFn, Reference};
+use crate::error::{bail, ContextCompat, Result};
+use crate::instructions::ArrayType;
+use crate::vm::{self, Vm};
+
+use super::to_java_string;
+
+pub(crate) const STRING_BUILDER: &str = "java/lang/StringBuilder";
+
+/// Natives for `java.lang.String`. Strings are represented by the vm as Rust strings rather than
+/// `String` objects, so the methods which would read the fields of a `String` are replaced too.
+/// Natives are passed the contents of `String` objects created by Java code in the same way.
+pub(crate) fn register_string<'a>(vm: &Vm<'a>) -> Result<()> {
+    const STRING: &str = "java/lang/String";
+
+    vm.register_native(
+        STRING,
+        "intern",
+        "()Ljava/lang/String;",
+        |vm: &Vm<'a>, this: &'a str| Ok(JvmValue::StringConst(vm.intern(this))),
+    )?;
+
+    vm.register_native(
+        STRING,
+        "toString",
+        "()Ljava/lang/String;",
+        |_: &Vm<'a>, this: &'a str| Ok(JvmValue::StringConst(this)),
+    )?;
+
+    vm.register_native(
+        STRING,
+        "equals",
+        "(Ljava/lang/Object;)Z",
+        |_: &Vm<'a>, this: &'a str, other: JvmValue<'a>| {
+            Ok(matches!(other, JvmValue::StringConst(other) if other == this))
+        },
+    )?;
+
+    // Like `String.hashCode`, this is computed from the UTF-16 code units of the string
+    vm.register_native(STRING, "hashCode", "()I", |_: &Vm, this: &str| {
+        Ok(this
+            .encode_utf16()
+            .fold(0i32, |hash, c| hash.wrapping_mul(31).wrapping_add(c as i32)))
+    })?;
+
+    vm.register_native(STRING, "length", "()I", |vm: &Vm<'a>, this: &'a str| {
+        Ok(i32::try_from(vm.string_utf16(this).len())?)
+    })?;
+
+    vm.register_native(STRING, "isEmpty", "()Z", |_: &Vm, this: &str| {
+        Ok(this.is_empty())
+    })?;
+
+    vm.register_native(
+        STRING,
+        "charAt",
+        "(I)C",
+        |vm: &Vm<'a>, this: &'a str, index: i32| {
+            let units = vm.string_utf16(this);
+            let Some(c) = usize::try_from(index).ok().and_then(|i| units.get(i)) else {
+                bail!(JavaException::new(
+                    "java/lang/StringIndexOutOfBoundsException",
+                    format!("index {index}, length {}", units.len())
+                ));
+            };
+
+            // Returned as an int, since a code unit may be a surrogate, which `char` can't hold
+            Ok(i32::from(*c))
+        },
+    )?;
+
+    // These use the default locale, which is always treated as the root locale, since the JDK's
+    // locale data can't be loaded yet
+    vm.register_native(
+        STRING,
+        "toUpperCase",
+        "()Ljava/lang/String;",
+        |_: &Vm, this: &str| Ok(this.to_uppercase()),
+    )?;
+
+    vm.register_native(
+        STRING,
+        "toLowerCase",
+        "()Ljava/lang/String;",
+        |_: &Vm, this: &str| Ok(this.to_lowercase()),
+    )?;
+
+    // `String` objects created by Java code store UTF-16 in the native byte order, which is how
+    // the vm reads them
+    vm.register_native("java/lang/StringUTF16", "isBigEndian", "()Z", |_: &Vm| {
+        Ok(cfg!(target_endian = "big"))
+    })?;
+
+    Ok(())
+}
+
+/// Replaces the methods of `java.lang.StringBuilder` which are commonly used to build strings.
+/// The JDK implementation appends to a byte array one character at a time, which is very slow
+/// to interpret, so the contents of each builder are kept in a Rust string by the vm instead.
+///
+/// Methods which aren't replaced run the JDK implementation, which moves a builder's contents
+/// into its fields when it first reads them (see [`Vm::materialize_string_builder`]). The
+/// replacements then run the JDK implementation for that builder too.
+pub(crate) fn register_string_builder<'a>(vm: &Vm<'a>) -> Result<()> {
+    vm.register_native(
+        STRING_BUILDER,
+        "<init>",
+        "()V",
+        |vm: &Vm, this: Reference| {
+            vm.string_builders().insert(this.0, String::new());
+            Ok(())
+        },
+    )?;
+
+    vm.register_native(
+        STRING_BUILDER,
+        "<init>",
+        "(I)V",
+        |vm: &Vm, this: Reference, capacity: i32| {
+            if capacity < 0 {
+                bail!(JavaException::new(
+                    "java/lang/NegativeArraySizeException",
+                    capacity.to_string()
+                ));
+            }
+
+            vm.string_builders()
+                .insert(this.0, String::with_capacity(capacity as usize));
+
+            Ok(())
+        },
+    )?;
+
+    for param in ["Ljava/lang/String;", "Ljava/lang/CharSequence;"] {
+        let descriptor = format!("({param})V");
+        vm.register_native(
+            STRING_BUILDER,
+            "<init>",
+            &descriptor,
+            move |vm: &Vm<'a>, this: Reference, value: JvmValue<'a>| {
+                if let JvmValue::Reference(0) = value {
+                    bail!(JavaException::null_pointer());
+                }
+
+                let contents = to_java_string(vm, param, &value)?;
+                vm.string_builders().insert(this.0, contents);
+
+                Ok(())
+            },
+        )?;
+    }
+
+    for param in [
+        "Z",
+        "C",
+        "I",
+        "J",
+        "F",
+        "D",
+        "Ljava/lang/String;",
+        "Ljava/lang/CharSequence;",
+        "Ljava/lang/Object;",
+    ] {
+        let descriptor = format!("({param})Ljava/lang/StringBuilder;");
+        register_intrinsic(
+            vm,
+            "append",
+            &descriptor,
+            move |vm: &Vm<'a>, this: Reference, value: JvmValue<'a>| {
+                // Converted first, since toString may append to this builder
+                let value = to_java_string(vm, param, &value)?;
+                vm.string_builders()
+                    .entry(this.0)
+                    .or_default()
+                    .push_str(&value);
+
+                Ok(this)
+            },
+        )?;
+    }
+
+    register_intrinsic(
+        vm,
+        "append",
+        "([C)Ljava/lang/StringBuilder;",
+        |vm: &Vm<'a>, this: Reference, chars: JvmValue<'a>| {
+            let value = String::from_utf16_lossy(char_array(&chars)?);
+            vm.string_builders()
+                .entry(this.0)
+                .or_default()
+                .push_str(&value);
+
+            Ok(this)
+        },
+    )?;
+
+    register_intrinsic(
+        vm,
+        "toString",
+        "()Ljava/lang/String;",
+        |vm: &Vm, this: Reference| {
+            Ok(vm
+                .string_builders()
+                .get(&this.0)
+                .cloned()
+                .unwrap_or_default())
+        },
+    )?;
+
+    // Lengths and indices are in UTF-16 code units, like in Java
+    register_intrinsic(vm, "length", "()I", |vm: &Vm, this: Reference| {
+        let length = vm
+            .string_builders()
+            .get(&this.0)
+            .map_or(0, |contents| contents.encode_utf16().count());
+        Ok(i32::try_from(length)?)
+    })?;
+
+    register_intrinsic(
+        vm,
+        "charAt",
+        "(I)C",
+        |vm: &Vm, this: Reference, index: i32| {
+            let units = utf16(vm, this.0);
+            let Some(c) = usize::try_from(index).ok().and_then(|i| units.get(i)) else {
+                bail!(JavaException::new(
+                    "java/lang/StringIndexOutOfBoundsException",
+                    format!("index {index},length {}", units.len())
+                ));
+            };
+
+            Ok(i32::from(*c))
+        },
+    )?;
+
+    register_intrinsic(
+        vm,
+        "setLength",
+        "(I)V",
+        |vm: &Vm, this: Reference, length: i32| {
+            let Ok(length) = usize::try_from(length) else {
+                bail!(JavaException::new(
+                    "java/lang/StringIndexOutOfBoundsException",
+                    format!("String index out of range: {length}")
+                ));
+            };
+
+            // Truncates the contents, or pads them with null characters
+            let mut units = utf16(vm, this.0);
+            units.resize(length, 0);
+            vm.string_builders()
+                .insert(this.0, String::from_utf16_lossy(&units));
+
+            Ok(())
+        },
+    )?;
+
+    Ok(())
+}
+
+/// Registers a replacement for a method of `StringBuilder`, which runs the JDK implementation
+/// instead for builders whose contents have been moved into their fields.
+fn register_intrinsic<'a, A: 'a>(
+    vm: &Vm<'a>,
+    name: &'static str,
+    descriptor: &str,
+    f: impl NativeFn<'a, A>,
+) -> Result<()> {
+    vm::check_native_fn(STRING_BUILDER, name, descriptor, &f)?;
+    let bytecode_descriptor = descriptor.to_owned();
+    vm.register_raw_native(STRING_BUILDER, name, descriptor, move |vm, args| {
+        let this = args
+            .first()
+            .and_then(JvmValue::try_as_reference_ref)
+            .wrap_err("expected reference")?;
+        if !vm.string_builders().contains_key(this) {
+            return vm.invoke_bytecode(STRING_BUILDER, name, &bytecode_descriptor, args);
+        }
+        f.call(vm, args)
+    });
+    Ok(())
+}
+
+/// Returns the contents of a builder as UTF-16 code units.
+fn utf16(vm: &Vm, string_builder: usize) -> Vec<u16> {
+    vm.string_builders()
+        .get(&string_builder)
+        .map(|contents| contents.encode_utf16().collect())
+        .unwrap_or_default()
+}
+
+fn char_array<'a>(array: &JvmValue) -> Result<&'a [u16]> {
+    let array = match array {
+        JvmValue::Reference(0) => bail!(JavaException::null_pointer()),
+        JvmValue::Reference(array) => *array,
+        _ => bail!("expected reference"),
+    };
+
+    let header = unsafe { &mut *(array as *mut RefTypeHeader) };
+    match header {
+        RefTypeHeader::Array(array)
+            if matches!(
+                array.element_type,
+                ArrayElementType::Primitive(ArrayType::Char)
+            ) => {}
+        _ => bail!("expected char array"),
+    }
+
+    Ok(unsafe { header.array_data::<u16>()? })
+}
@@ -0,0 +1,296 @@
+//! Handles to Java objects and arrays, which let embedders call methods, access fields and read
+//! and write array elements from Rust without writing bytecode.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::marker::PhantomData;
+
+use crate::call_frame::{ArrayElementType, JavaException, JvmValue, RefTypeHeader};
+use crate::class::{Class, Field};
+use crate::convert::{self, Args, FromJvm, Nullable, ToJvm};
+use crate::descriptor::{BaseType, FieldType};
+use crate::error::{bail, format_err, Result};
+use crate::vm::Vm;
+
+/// A non-null reference to an object, which is only valid as long as the vm that created it.
+#[derive(Clone, Copy)]
+pub struct Object<'v, 'a> {
+    vm: &'v Vm<'a>,
+    reference: usize,
+    class: &'a Class<'a>,
+}
+
+impl<'v, 'a> Object<'v, 'a> {
+    /// Wraps a reference to an object, failing if it's null or an array.
+    pub fn new(vm: &'v Vm<'a>, value: JvmValue<'a>) -> Result<Object<'v, 'a>> {
+        let reference = match value {
+            JvmValue::Reference(0) => bail!("expected object, found null"),
+            JvmValue::Reference(reference) => reference,
+            value => bail!("expected object, found {value:?}"),
+        };
+
+        if let RefTypeHeader::Array(_) = unsafe { &*(reference as *const RefTypeHeader) } {
+            bail!("expected object, found array");
+        }
+
+        let class = vm.runtime_class(&value)?;
+        Ok(Object {
+            vm,
+            reference,
+            class,
+        })
+    }
+
+    /// The address of the object, which is the value of references to it.
+    pub fn reference(&self) -> usize {
+        self.reference
+    }
+
+    pub fn class(&self) -> &'a Class<'a> {
+        self.class
+    }
+
+    /// Calls an instance method of the object, as with [`Vm::invoke_instance`].
+    pub fn call<A: Args<'a>, R: FromJvm<'a>>(&self, name: &str, args: A) -> Result<R> {
+        self.vm
+            .invoke_instance(JvmValue::Reference(self.reference), name, args)
+    }
+
+    /// Returns the value of an instance field, which may be declared by a superclass.
+    pub fn get<T: FromJvm<'a>>(&self, name: &str) -> Result<T> {
+        let (ordinal, _) = self.field(name)?;
+        let value =
+            unsafe { (*(self.reference as *mut RefTypeHeader)).object_data()?[ordinal].clone() };
+        T::from_jvm(self.vm, Some(value))
+    }
+
+    /// Sets the value of an instance field, which must have the same type as the value, or be a
+    /// reference if the value is a reference.
+    pub fn set<T: ToJvm<'a>>(&self, name: &str, value: T) -> Result<()> {
+        let (ordinal, field) = self.field(name)?;
+        let value = value.to_jvm(self.vm)?;
+
+        let is_assignable = match (&field.descriptor.field_type, &value) {
+            (FieldType::Base(BaseType::Long), JvmValue::Long(_))
+            | (FieldType::Base(BaseType::Float), JvmValue::Float(_))
+            | (FieldType::Base(BaseType::Double), JvmValue::Double(_))
+            | (FieldType::Base(BaseType::Object(_)), JvmValue::Reference(_))
+            | (FieldType::Base(BaseType::Object(_)), JvmValue::StringConst(_))
+            | (FieldType::Array(_, _), JvmValue::Reference(_)) => true,
+            // Values narrower than an int are stored as ints, as they are on the operand stack
+            (
+                FieldType::Base(
+                    BaseType::Boolean
+                    | BaseType::Byte
+                    | BaseType::Char
+                    | BaseType::Short
+                    | BaseType::Int,
+                ),
+                JvmValue::Int(_),
+            ) => true,
+            _ => false,
+        };
+
+        if !is_assignable {
+            bail!(
+                "can't assign {value:?} to {}.{name} of type {:?}",
+                self.class.name(),
+                field.descriptor.field_type
+            );
+        }
+
+        unsafe { (*(self.reference as *mut RefTypeHeader)).object_data()?[ordinal] = value };
+        Ok(())
+    }
+
+    /// Finds an instance field by name, returning its ordinal. Fields declared by the class hide
+    /// fields with the same name in its superclasses.
+    fn field(&self, name: &str) -> Result<(usize, &'a Field<'a>)> {
+        self.class
+            .fields()
+            .iter()
+            .enumerate()
+            .rfind(|(_, field)| field.name == name)
+            .ok_or_else(|| format_err!("field not found: {}.{name}", self.class.name()))
+    }
+}
+
+impl Nullable for Object<'_, '_> {}
+
+impl fmt::Debug for Object<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@{:#x}", self.class.name(), self.reference)
+    }
+}
+
+/// Objects are passed by reference, as `java.lang.Object`s.
+impl<'a> ToJvm<'a> for Object<'_, 'a> {
+    fn descriptor() -> Cow<'static, str> {
+        <JvmValue as ToJvm>::descriptor()
+    }
+
+    fn matches(descriptor: &str) -> bool {
+        convert::is_reference(descriptor)
+    }
+
+    fn to_jvm(self, _: &Vm<'a>) -> Result<JvmValue<'a>> {
+        Ok(JvmValue::Reference(self.reference))
+    }
+}
+
+/// A non-null reference to an array whose elements convert to and from `T`, e.g. `Array<i32>` for
+/// an `int[]`, or `Array<&str>` for a `String[]`.
+pub struct Array<'v, 'a, T> {
+    vm: &'v Vm<'a>,
+    reference: usize,
+    length: usize,
+    element: PhantomData<fn() -> T>,
+}
+
+/// A primitive type which is stored as itself in arrays, so arrays of it can be viewed as slices.
+///
+/// # Safety
+///
+/// Arrays with the element type of [`ToJvm::descriptor`] must store their elements as `Self`.
+pub unsafe trait Primitive<'a>: ToJvm<'a> + FromJvm<'a> + Copy {}
+
+unsafe impl Primitive<'_> for i8 {}
+unsafe impl Primitive<'_> for i16 {}
+unsafe impl Primitive<'_> for i32 {}
+unsafe impl Primitive<'_> for i64 {}
+unsafe impl Primitive<'_> for f32 {}
+unsafe impl Primitive<'_> for f64 {}
+
+impl<'v, 'a, T: ToJvm<'a> + FromJvm<'a>> Array<'v, 'a, T> {
+    /// Wraps a reference to an array, failing if it's null, or if its elements aren't of the
+    /// Java type of `T`. The classes of reference elements aren't checked.
+    pub fn new(vm: &'v Vm<'a>, value: JvmValue<'a>) -> Result<Array<'v, 'a, T>> {
+        let reference = match value {
+            JvmValue::Reference(0) => bail!("expected array, found null"),
+            JvmValue::Reference(reference) => reference,
+            value => bail!("expected array, found {value:?}"),
+        };
+
+        let RefTypeHeader::Array(header) = (unsafe { &*(reference as *const RefTypeHeader) })
+        else {
+            bail!("expected array, found object");
+        };
+
+        let descriptor = <T as ToJvm>::descriptor();
+        if header.element_type != convert::element_type(&descriptor) {
+            bail!(
+                "expected array of {descriptor}, found array of {:?}",
+                header.element_type
+            );
+        }
+
+        Ok(Array {
+            vm,
+            reference,
+            length: header.length,
+            element: PhantomData,
+        })
+    }
+
+    /// The address of the array, which is the value of references to it.
+    pub fn reference(&self) -> usize {
+        self.reference
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn get(&self, index: usize) -> Result<T> {
+        let (header, element_type) = self.header(index)?;
+        // SAFETY: The element type was checked when the handle was created
+        let value = unsafe { convert::load_element(&mut *header, element_type, index)? };
+        T::from_jvm(self.vm, Some(value))
+    }
+
+    pub fn set(&self, index: usize, value: T) -> Result<()> {
+        let value = value.to_jvm(self.vm)?;
+        let (header, element_type) = self.header(index)?;
+        // SAFETY: The element type was checked when the handle was created
+        unsafe { convert::store_element(&mut *header, element_type, index, value) }
+    }
+
+    /// Copies the elements into a vec.
+    pub fn to_vec(&self) -> Result<Vec<T>> {
+        (0..self.length).map(|index| self.get(index)).collect()
+    }
+
+    /// Returns the header of the array and its element type, after checking that an index is
+    /// within its bounds.
+    fn header(&self, index: usize) -> Result<(*mut RefTypeHeader, ArrayElementType)> {
+        if index >= self.length {
+            bail!(JavaException::new(
+                "java/lang/ArrayIndexOutOfBoundsException",
+                format!("Index {index} out of bounds for length {}", self.length)
+            ));
+        }
+
+        let header = self.reference as *mut RefTypeHeader;
+        let RefTypeHeader::Array(array) = (unsafe { &*header }) else {
+            unreachable!()
+        };
+
+        Ok((header, array.element_type))
+    }
+}
+
+impl<'a, T: Primitive<'a>> Array<'_, 'a, T> {
+    /// Returns the elements without copying them. Java code which writes to the array must not
+    /// run while the slice is borrowed.
+    pub fn as_slice(&self) -> &[T] {
+        let header = unsafe { &mut *(self.reference as *mut RefTypeHeader) };
+        // SAFETY: Arrays of primitives store their elements as the primitive type
+        unsafe { header.array_data::<T>() }.expect("expected array")
+    }
+
+    /// Overwrites the elements with those of a slice of the same length.
+    pub fn copy_from_slice(&self, elements: &[T]) -> Result<()> {
+        if elements.len() != self.length {
+            bail!(
+                "expected {} elements, found {}",
+                self.length,
+                elements.len()
+            );
+        }
+
+        let header = unsafe { &mut *(self.reference as *mut RefTypeHeader) };
+        unsafe { header.array_data::<T>()? }.copy_from_slice(elements);
+        Ok(())
+    }
+}
+
+impl<T> Nullable for Array<'_, '_, T> {}
+
+impl<T> Clone for Array<'_, '_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Array<'_, '_, T> {}
+
+impl<'a, T: ToJvm<'a>> fmt::Debug for Array<'_, 'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}@{:#x}", T::descriptor(), self.reference)
+    }
+}
+
+/// Arrays are passed by reference, as arrays of the Java type of `T`.
+impl<'a, T: ToJvm<'a>> ToJvm<'a> for Array<'_, 'a, T> {
+    fn descriptor() -> Cow<'static, str> {
+        Cow::Owned(format!("[{}", T::descriptor()))
+    }
+
+    fn to_jvm(self, _: &Vm<'a>) -> Result<JvmValue<'a>> {
+        Ok(JvmValue::Reference(self.reference))
+    }
+}
@@ -1,7 +1,7 @@
 use strum::FromRepr;
 
 #[allow(non_camel_case_types)]
-#[derive(Debug, FromRepr)]
+#[derive(Clone, Copy, Debug, FromRepr)]
 #[repr(u8)]
 pub enum OpCode {
     nop,
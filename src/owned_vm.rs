@@ -0,0 +1,74 @@
+use std::io;
+
+use bumpalo::Bump;
+use ouroboros::self_referencing;
+
+use crate::error::Result;
+use crate::vm::Vm;
+
+/// A vm which owns its arena and stdout, for embedding in programs which don't want to keep them
+/// alive alongside the vm.
+///
+/// Classes and objects from the vm borrow its arena, so the vm is only accessible inside
+/// [`OwnedVm::with`], and nothing it returns can outlive the call.
+pub struct OwnedVm<W: io::Write + Send + 'static = io::Stdout>(Inner<W>);
+
+#[self_referencing]
+struct Inner<W: io::Write + Send + 'static> {
+    arena: Bump,
+    stdout: W,
+    #[borrows(arena, mut stdout)]
+    #[not_covariant]
+    vm: Vm<'this>,
+}
+
+impl OwnedVm {
+    /// Creates a vm which writes to the process's stdout.
+    pub fn new() -> OwnedVm {
+        OwnedVm::with_stdout(io::stdout())
+    }
+}
+
+impl Default for OwnedVm {
+    fn default() -> Self {
+        OwnedVm::new()
+    }
+}
+
+impl<W: io::Write + Send + 'static> OwnedVm<W> {
+    pub fn with_stdout(stdout: W) -> OwnedVm<W> {
+        OwnedVm::with_config(stdout, |vm| vm)
+    }
+
+    /// Creates a vm which writes to `stdout`, and is configured by a function which is given the
+    /// new vm, e.g. `|vm| vm.with_java_home(java_home)`.
+    pub fn with_config(stdout: W, configure: impl for<'a> FnOnce(Vm<'a>) -> Vm<'a>) -> OwnedVm<W> {
+        OwnedVm(
+            InnerBuilder {
+                arena: Bump::new(),
+                stdout,
+                vm_builder: |arena, stdout| configure(Vm::new(arena, stdout)),
+            }
+            .build(),
+        )
+    }
+
+    /// Calls a function with the vm, returning its result.
+    pub fn with<R>(&self, f: impl for<'a> FnOnce(&Vm<'a>) -> R) -> R {
+        self.0.with_vm(f)
+    }
+
+    /// Loads a class by its binary name or the path to its class file, and runs its main method.
+    /// Returns the exit status, as [`Vm::run_main`] does.
+    pub fn run_main(&self, class_name: &str, args: &[String]) -> Result<i32> {
+        self.with(|vm| {
+            let class = vm.load_class_file(class_name)?;
+            vm.run_main(class, args)
+        })
+    }
+
+    /// Drops the vm, returning its stdout.
+    pub fn into_stdout(self) -> W {
+        self.0.into_heads().stdout
+    }
+}
@@ -0,0 +1,224 @@
+//! A sampling profiler, which finds the methods that Java code spends most of its time in.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread::{self, ThreadId};
+
+use crate::call_frame::{JvmValue, LocalVariables, Operands};
+use crate::error::{Error, Result};
+use crate::hooks::{Hook, MethodRef};
+use crate::instructions::Instruction;
+use crate::vm::Vm;
+
+/// The number of methods listed in the report.
+const MAX_REPORT_ENTRIES: usize = 20;
+
+/// A hook which samples the stack of the thread running Java code once every `interval`
+/// instructions, counting the instructions of all threads. Since instructions are counted rather
+/// than time, native methods are only seen while they call back into Java code.
+///
+/// The hook keeps its own copy of each thread's stack, since the interpreter's frames can't be
+/// walked.
+pub struct Profiler {
+    interval: u64,
+    instructions: AtomicU64,
+    state: Mutex<ProfilerState>,
+}
+
+#[derive(Default)]
+struct ProfilerState {
+    /// The methods running on each thread, keyed by their addresses, with the innermost last.
+    stacks: HashMap<ThreadId, Vec<usize>>,
+    names: HashMap<usize, String>,
+    /// The number of times each stack was sampled.
+    samples: HashMap<Vec<usize>, u64>,
+}
+
+/// The number of samples which a method was seen in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MethodSamples {
+    /// The binary name of the class followed by the method's name and descriptor, e.g.
+    /// `java.lang.String.length()I`.
+    pub name: String,
+    /// The number of samples taken while the method was running its own code.
+    pub exclusive: u64,
+    /// The number of samples taken while the method was running, including the methods it called.
+    pub inclusive: u64,
+}
+
+impl Profiler {
+    /// Creates a profiler which samples once every `interval` instructions.
+    pub fn new(interval: u64) -> Self {
+        Profiler {
+            interval: interval.max(1),
+            instructions: AtomicU64::new(0),
+            state: Mutex::default(),
+        }
+    }
+
+    /// Returns the number of samples taken so far.
+    pub fn sample_count(&self) -> u64 {
+        self.state.lock().unwrap().samples.values().sum()
+    }
+
+    /// Returns the methods seen in the samples, with the ones which ran the most of their own
+    /// code first.
+    pub fn hot_methods(&self) -> Vec<MethodSamples> {
+        let state = self.state.lock().unwrap();
+
+        let mut methods = HashMap::<usize, (u64, u64)>::new();
+        for (stack, &count) in &state.samples {
+            if let Some(&top) = stack.last() {
+                methods.entry(top).or_default().0 += count;
+            }
+
+            // Recursive methods are only counted once per sample
+            let mut seen = HashSet::new();
+            for &method in stack {
+                if seen.insert(method) {
+                    methods.entry(method).or_default().1 += count;
+                }
+            }
+        }
+
+        let mut methods = methods
+            .into_iter()
+            .map(|(method, (exclusive, inclusive))| MethodSamples {
+                name: state.names[&method].clone(),
+                exclusive,
+                inclusive,
+            })
+            .collect::<Vec<_>>();
+
+        methods.sort_by(|a, b| {
+            b.exclusive
+                .cmp(&a.exclusive)
+                .then_with(|| b.inclusive.cmp(&a.inclusive))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+
+        methods
+    }
+
+    /// Writes the hottest methods, with the percentage of samples spent in their own code and in
+    /// the methods they called.
+    pub fn report(&self, out: &mut impl io::Write) -> io::Result<()> {
+        let total = self.sample_count();
+        writeln!(
+            out,
+            "Samples: {total} (one every {} instructions)",
+            self.interval
+        )?;
+
+        if total == 0 {
+            return Ok(());
+        }
+
+        writeln!(out, "{:>8} {:>8}  Method", "Self", "Total")?;
+        for method in self.hot_methods().into_iter().take(MAX_REPORT_ENTRIES) {
+            let exclusive = method.exclusive as f64 / total as f64 * 100.0;
+            let inclusive = method.inclusive as f64 / total as f64 * 100.0;
+            writeln!(out, "{exclusive:>7.2}% {inclusive:>7.2}%  {}", method.name)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the samples as collapsed stacks, which can be turned into a flame graph by
+    /// `inferno-flamegraph` or `flamegraph.pl`. Each line has the methods of a stack from the
+    /// outermost to the innermost, separated by semicolons, followed by the number of times the
+    /// stack was sampled, e.g.
+    ///
+    /// ```text
+    /// com.example.Main.main;com.example.Main.run 42
+    /// ```
+    ///
+    /// Descriptors are left out, since they contain semicolons.
+    pub fn write_folded(&self, out: &mut impl io::Write) -> io::Result<()> {
+        let state = self.state.lock().unwrap();
+
+        // Overloaded methods have the same name, so their stacks are merged
+        let mut lines = BTreeMap::<String, u64>::new();
+        for (stack, count) in &state.samples {
+            let frames = stack
+                .iter()
+                .map(|method| {
+                    let name = &state.names[method];
+                    name.split_once('(').map_or(name.as_str(), |(name, _)| name)
+                })
+                .collect::<Vec<_>>();
+            *lines.entry(frames.join(";")).or_default() += count;
+        }
+
+        for (stack, count) in lines {
+            writeln!(out, "{stack} {count}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> Hook<'a> for Profiler {
+    fn on_method_enter(&self, _: &Vm<'a>, method: MethodRef<'a>, _: &[JvmValue<'a>]) {
+        let key = method.method as *const _ as usize;
+        let mut state = self.state.lock().unwrap();
+
+        state.names.entry(key).or_insert_with(|| {
+            format!(
+                "{}.{}{}",
+                method.class.name().replace('/', "."),
+                method.name(),
+                method.descriptor()
+            )
+        });
+
+        state
+            .stacks
+            .entry(thread::current().id())
+            .or_default()
+            .push(key);
+    }
+
+    fn on_method_exit(
+        &self,
+        _: &Vm<'a>,
+        _: MethodRef<'a>,
+        _: &Result<Option<JvmValue<'a>>, Error>,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(stack) = state.stacks.get_mut(&thread::current().id()) {
+            stack.pop();
+        }
+    }
+
+    fn on_instruction(
+        &self,
+        _: &Vm<'a>,
+        _: MethodRef<'a>,
+        _: usize,
+        _: &'a Instruction,
+        _: LocalVariables<'_, 'a>,
+        _: Operands<'_, 'a>,
+    ) {
+        let instructions = self.instructions.fetch_add(1, Ordering::Relaxed) + 1;
+        if instructions % self.interval != 0 {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        let Some(stack) = state.stacks.get(&thread::current().id()) else {
+            return;
+        };
+
+        // Looking up the stack before inserting it avoids copying it for every sample
+        match state.samples.get_mut(stack) {
+            Some(count) => *count += 1,
+            None => {
+                state.samples.insert(stack.clone(), 1);
+            }
+        }
+    }
+}
@@ -1,29 +1,113 @@
 use std::io;
 
 use bumpalo::collections::{CollectIn, String, Vec};
-use bumpalo::{vec, Bump};
-use byteorder::{BigEndian, ReadBytesExt};
-use color_eyre::eyre::{self, bail, eyre, Context};
+use bumpalo::Bump;
 
+use crate::call_frame::JavaException;
 use crate::class_file::constant_pool::{self, ConstantInfo, ConstantPool};
 use crate::class_file::{
-    AttributeInfo, BootstrapMethod, BootstrapMethodsAttribute, ClassAccessFlags, ClassFile,
-    CodeAttribute, CustomAttribute, ExceptionTableEntry, FieldAccessFlags, FieldInfo, InnerClass,
+    Annotation, AnnotationsAttribute, AttributeInfo, BootstrapMethod, BootstrapMethodsAttribute,
+    ClassAccessFlags, ClassFile, CodeAttribute, CustomAttribute, ElementValue, ElementValuePair,
+    ExceptionTableEntry, ExportsFlags, FieldAccessFlags, FieldInfo, InnerClass,
     InnerClassAccessFlags, InnerClassesAttribute, LineNumberTableAttribute, LineNumberTableEntry,
-    MethodAccessFlags, MethodInfo, SourceFileAttribute,
+    LocalVarTargetEntry, MalformedAttribute, MethodAccessFlags, MethodInfo, ModuleAttribute,
+    ModuleExports, ModuleFlags, ModuleMainClassAttribute, ModulePackagesAttribute, ModuleProvides,
+    ModuleRequires, ParameterAnnotationsAttribute, RequiresFlags, SignatureAttribute,
+    SourceFileAttribute, StackMapFrame, StackMapTableAttribute, TargetInfo, TypeAnnotation,
+    TypeAnnotationsAttribute, TypePathEntry, VerificationTypeInfo,
 };
+use crate::error::{bail, format_err, Context, Result};
 
-pub struct ClassReader<'a, R> {
-    reader: R,
+/// The oldest class file version supported by the vm, from JDK 1.1.
+pub const MIN_MAJOR_VERSION: u16 = 45;
+
+/// The newest class file version supported by the vm, from Java 17.
+pub const MAX_MAJOR_VERSION: u16 = 61;
+
+/// The input that a class file is read from.
+///
+/// Strings and code are borrowed from the input if it lives as long as the arena, which saves
+/// allocating and copying each of them. Otherwise they're copied into the arena.
+pub trait ClassInput<'a> {
+    fn read_exact(&mut self, bytes: &mut [u8]) -> io::Result<()>;
+
+    /// Reads the next `length` bytes, borrowing them from the input if possible.
+    fn read_slice(&mut self, arena: &'a Bump, length: usize) -> io::Result<&'a [u8]>;
+}
+
+/// Input from an [`io::Read`], which is copied into the arena.
+pub struct ReadInput<R>(R);
+
+impl<'a, R: io::Read> ClassInput<'a> for ReadInput<R> {
+    fn read_exact(&mut self, bytes: &mut [u8]) -> io::Result<()> {
+        self.0.read_exact(bytes)
+    }
+
+    fn read_slice(&mut self, arena: &'a Bump, length: usize) -> io::Result<&'a [u8]> {
+        let bytes = arena.alloc_slice_fill_copy(length, 0);
+        self.0.read_exact(bytes)?;
+        Ok(bytes)
+    }
+}
+
+impl<'a> ClassInput<'a> for &'a [u8] {
+    fn read_exact(&mut self, bytes: &mut [u8]) -> io::Result<()> {
+        io::Read::read_exact(self, bytes)
+    }
+
+    fn read_slice(&mut self, _arena: &'a Bump, length: usize) -> io::Result<&'a [u8]> {
+        if length > self.len() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        let (bytes, rest) = self.split_at(length);
+        *self = rest;
+        Ok(bytes)
+    }
+}
+
+pub struct ClassReader<'a, I> {
+    input: I,
     arena: &'a Bump,
+    /// The number of bytes read so far, for checking attribute lengths and reporting errors.
+    position: u64,
+    lenient: bool,
+}
+
+impl<'a, R: io::Read> ClassReader<'a, ReadInput<R>> {
+    pub fn new(arena: &'a Bump, reader: R) -> ClassReader<'a, ReadInput<R>> {
+        ClassReader::with_input(arena, ReadInput(reader))
+    }
+}
+
+impl<'a> ClassReader<'a, &'a [u8]> {
+    /// Creates a reader for a class file that lives as long as the arena, which strings and code
+    /// are borrowed from instead of being copied.
+    pub fn from_slice(arena: &'a Bump, bytes: &'a [u8]) -> ClassReader<'a, &'a [u8]> {
+        ClassReader::with_input(arena, bytes)
+    }
 }
 
-impl<'a, R: io::Read> ClassReader<'a, R> {
-    pub fn new(arena: &'a Bump, reader: R) -> ClassReader<'a, R> {
-        ClassReader { reader, arena }
+impl<'a, I: ClassInput<'a>> ClassReader<'a, I> {
+    fn with_input(arena: &'a Bump, input: I) -> ClassReader<'a, I> {
+        ClassReader {
+            input,
+            arena,
+            position: 0,
+            lenient: false,
+        }
+    }
+
+    /// Enables best-effort parsing, for inspecting class files which are corrupt or from a newer
+    /// version of Java. The version isn't checked, and anything that can't be parsed is kept as
+    /// a placeholder instead of failing, e.g. [`ConstantInfo::Unknown`] and
+    /// [`AttributeInfo::Malformed`].
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
     }
 
-    pub fn read_class_file<'b>(&'b mut self) -> eyre::Result<ClassFile<'a>> {
+    pub fn read_class_file<'b>(&'b mut self) -> Result<ClassFile<'a>> {
         let magic = self.read_u32()?;
         if magic != 0xcafebabe {
             bail!("invalid magic bytes: 0x{magic:0x}");
@@ -31,7 +115,28 @@ impl<'a, R: io::Read> ClassReader<'a, R> {
 
         let minor_version = self.read_u16()?;
         let major_version = self.read_u16()?;
+        if !self.lenient {
+            check_version(major_version, minor_version)?;
+        }
+
         let constant_pool = self.read_constant_pool()?;
+
+        // The length of an unknown constant isn't known, so nothing after it can be read
+        if let Some(ConstantInfo::Unknown(_)) = constant_pool.0.last() {
+            return Ok(ClassFile {
+                minor_version,
+                major_version,
+                constant_pool,
+                access_flags: ClassAccessFlags::empty(),
+                this_class: 0,
+                super_class: 0,
+                interfaces: Vec::new_in(self.arena),
+                fields: Vec::new_in(self.arena),
+                methods: Vec::new_in(self.arena),
+                attributes: Vec::new_in(self.arena),
+            });
+        }
+
         let access_flags = ClassAccessFlags::from_bits_truncate(self.read_u16()?);
         let this_class = self.read_u16()?;
         let super_class = self.read_u16()?;
@@ -54,7 +159,7 @@ impl<'a, R: io::Read> ClassReader<'a, R> {
         })
     }
 
-    fn read_constant_pool<'s>(&'s mut self) -> eyre::Result<ConstantPool<'a>> {
+    fn read_constant_pool<'s>(&'s mut self) -> Result<ConstantPool<'a>> {
         let constant_pool_count = self.read_u16()?;
         let mut constant_pool = Vec::new_in(self.arena);
         let mut i = 1;
@@ -78,6 +183,10 @@ impl<'a, R: io::Read> ClassReader<'a, R> {
                 18 => ConstantInfo::InvokeDynamic(self.read_invoke_dynamic_info()?),
                 19 => ConstantInfo::Module(self.read_module_info()?),
                 20 => ConstantInfo::Package(self.read_package_info()?),
+                _ if self.lenient => {
+                    constant_pool.push(ConstantInfo::Unknown(tag));
+                    break;
+                }
                 _ => bail!("unknown constant pool tag: {tag}"),
             };
 
@@ -96,86 +205,91 @@ impl<'a, R: io::Read> ClassReader<'a, R> {
         Ok(ConstantPool(constant_pool))
     }
 
-    fn read_utf8<'s>(&'s mut self) -> eyre::Result<String<'a>> {
+    fn read_utf8<'s>(&'s mut self) -> Result<&'a str> {
         let length = self.read_u16()? as usize;
-        let mut bytes = bumpalo::vec![in self.arena; 0; length];
-        self.reader.read_exact(&mut bytes)?;
-        String::from_utf8(bytes).map_err(|e| eyre!("{e}"))
+        let bytes = self.read_slice(length)?;
+
+        // Most strings are also valid UTF-8, since they don't contain nul or supplementary
+        // characters, so they can be used as is
+        match std::str::from_utf8(bytes) {
+            Ok(string) => Ok(string),
+            Err(_) => Ok(self.arena.alloc_str(&decode_modified_utf8(bytes)?)),
+        }
     }
 
-    fn read_class_info(&mut self) -> eyre::Result<constant_pool::Class> {
+    fn read_class_info(&mut self) -> Result<constant_pool::Class> {
         Ok(constant_pool::Class {
             name_index: self.read_u16()?,
         })
     }
 
-    fn read_string_info(&mut self) -> eyre::Result<constant_pool::String> {
+    fn read_string_info(&mut self) -> Result<constant_pool::String> {
         Ok(constant_pool::String {
             string_index: self.read_u16()?,
         })
     }
 
-    fn read_fieldref_info(&mut self) -> eyre::Result<constant_pool::FieldRef> {
+    fn read_fieldref_info(&mut self) -> Result<constant_pool::FieldRef> {
         Ok(constant_pool::FieldRef {
             class_index: self.read_u16()?,
             name_and_type_index: self.read_u16()?,
         })
     }
 
-    fn read_methodref_info(&mut self) -> eyre::Result<constant_pool::MethodRef> {
+    fn read_methodref_info(&mut self) -> Result<constant_pool::MethodRef> {
         Ok(constant_pool::MethodRef {
             class_index: self.read_u16()?,
             name_and_type_index: self.read_u16()?,
         })
     }
 
-    fn read_name_and_type_info(&mut self) -> eyre::Result<constant_pool::NameAndType> {
+    fn read_name_and_type_info(&mut self) -> Result<constant_pool::NameAndType> {
         Ok(constant_pool::NameAndType {
             name_index: self.read_u16()?,
             descriptor_index: self.read_u16()?,
         })
     }
 
-    fn read_method_handle_info(&mut self) -> eyre::Result<constant_pool::MethodHandle> {
+    fn read_method_handle_info(&mut self) -> Result<constant_pool::MethodHandle> {
         Ok(constant_pool::MethodHandle {
             reference_kind: self.read_u8()?,
             reference_index: self.read_u16()?,
         })
     }
 
-    fn read_method_type_info(&mut self) -> eyre::Result<constant_pool::MethodType> {
+    fn read_method_type_info(&mut self) -> Result<constant_pool::MethodType> {
         Ok(constant_pool::MethodType {
             descriptor_index: self.read_u16()?,
         })
     }
 
-    fn read_dynamic_info(&mut self) -> eyre::Result<constant_pool::Dynamic> {
+    fn read_dynamic_info(&mut self) -> Result<constant_pool::Dynamic> {
         Ok(constant_pool::Dynamic {
             bootstrap_method_attr_index: self.read_u16()?,
             name_and_type_index: self.read_u16()?,
         })
     }
 
-    fn read_invoke_dynamic_info(&mut self) -> eyre::Result<constant_pool::InvokeDynamic> {
+    fn read_invoke_dynamic_info(&mut self) -> Result<constant_pool::InvokeDynamic> {
         Ok(constant_pool::InvokeDynamic {
             bootstrap_method_attr_index: self.read_u16()?,
             name_and_type_index: self.read_u16()?,
         })
     }
 
-    fn read_module_info(&mut self) -> eyre::Result<constant_pool::Module> {
+    fn read_module_info(&mut self) -> Result<constant_pool::Module> {
         Ok(constant_pool::Module {
             name_index: self.read_u16()?,
         })
     }
 
-    fn read_package_info(&mut self) -> eyre::Result<constant_pool::Package> {
+    fn read_package_info(&mut self) -> Result<constant_pool::Package> {
         Ok(constant_pool::Package {
             name_index: self.read_u16()?,
         })
     }
 
-    fn read_interfaces<'s>(&'s mut self) -> eyre::Result<Vec<'a, u16>> {
+    fn read_interfaces<'s>(&'s mut self) -> Result<Vec<'a, u16>> {
         let interfaces_count = self.read_u16()?;
         let arena = self.arena;
         (0..interfaces_count)
@@ -184,10 +298,7 @@ impl<'a, R: io::Read> ClassReader<'a, R> {
             .wrap_err("failed to read interfaces")
     }
 
-    fn read_fields(
-        &mut self,
-        constant_pool: &ConstantPool,
-    ) -> eyre::Result<Vec<'a, FieldInfo<'a>>> {
+    fn read_fields(&mut self, constant_pool: &ConstantPool) -> Result<Vec<'a, FieldInfo<'a>>> {
         let fields_count = self.read_u16()?;
         let arena = self.arena;
         (0..fields_count)
@@ -195,7 +306,7 @@ impl<'a, R: io::Read> ClassReader<'a, R> {
             .collect_in(arena)
     }
 
-    fn read_field_info(&mut self, constant_pool: &ConstantPool) -> eyre::Result<FieldInfo<'a>> {
+    fn read_field_info(&mut self, constant_pool: &ConstantPool) -> Result<FieldInfo<'a>> {
         Ok(FieldInfo {
             access_flags: FieldAccessFlags::from_bits_truncate(self.read_u16()?),
             name_index: self.read_u16()?,
@@ -207,7 +318,7 @@ impl<'a, R: io::Read> ClassReader<'a, R> {
     fn read_methods<'s, 'b>(
         &'s mut self,
         constant_pool: &'b ConstantPool,
-    ) -> eyre::Result<Vec<'a, MethodInfo<'a>>> {
+    ) -> Result<Vec<'a, MethodInfo<'a>>> {
         let methods_count = self.read_u16()?;
         let arena = self.arena;
         (0..methods_count)
@@ -218,23 +329,23 @@ impl<'a, R: io::Read> ClassReader<'a, R> {
     fn read_method_info<'s, 'b>(
         &'s mut self,
         constant_pool: &'b ConstantPool,
-    ) -> eyre::Result<MethodInfo<'a>> {
+    ) -> Result<MethodInfo<'a>> {
         let access_flags = self.read_u16()?;
         let name_index = self.read_u16()?;
         Ok(MethodInfo {
             access_flags: MethodAccessFlags::from_bits_truncate(access_flags),
             name_index,
             descriptor_index: self.read_u16()?,
-            attributes: self
-                .read_attributes(constant_pool)
-                .wrap_err_with(|| eyre!("failed to read attributes for method: {name_index}"))?,
+            attributes: self.read_attributes(constant_pool).wrap_err_with(|| {
+                format_err!("failed to read attributes for method: {name_index}")
+            })?,
         })
     }
 
     fn read_attributes<'s, 'b>(
         &'s mut self,
         constant_pool: &'b ConstantPool,
-    ) -> eyre::Result<Vec<'a, AttributeInfo<'a>>> {
+    ) -> Result<Vec<'a, AttributeInfo<'a>>> {
         let attributes_count = self.read_u16()?;
         let arena = self.arena;
         (0..attributes_count)
@@ -245,31 +356,135 @@ impl<'a, R: io::Read> ClassReader<'a, R> {
     fn read_attribute_info<'s, 'b>(
         &'s mut self,
         constant_pool: &'b ConstantPool,
-    ) -> eyre::Result<AttributeInfo<'a>> {
+    ) -> Result<AttributeInfo<'a>> {
+        let offset = self.position;
         let attribute_name_index = self.read_u16()?;
         let length = self.read_u32()? as usize;
 
         let Some(ConstantInfo::Utf8(name)) = &constant_pool.get(attribute_name_index) else {
-            bail!("invalid attribute name index: {attribute_name_index}")
+            bail!("invalid attribute name index {attribute_name_index} at offset {offset:#x}")
         };
 
-        let attribute_info = match name.as_str() {
+        if self.lenient {
+            return self.read_attribute_lenient(
+                constant_pool,
+                attribute_name_index,
+                name,
+                length,
+                offset,
+            );
+        }
+
+        // The attribute parsers don't know the length, so a malformed attribute could be read
+        // past its end, which would make the rest of the class file unreadable
+        let start = self.position;
+        let attribute_info = self
+            .read_attribute(constant_pool, attribute_name_index, name, length)
+            .wrap_err_with(|| format_err!("invalid {name} attribute at offset {offset:#x}"))?;
+
+        let read = self.position - start;
+        if read != length as u64 {
+            bail!("{name} attribute at offset {offset:#x} has length {length}, but read {read}");
+        }
+
+        Ok(attribute_info)
+    }
+
+    /// Reads an attribute from a copy of its bytes, so that it can be kept as raw bytes if it's
+    /// malformed. The error which strict parsing would have failed with is kept along with it.
+    fn read_attribute_lenient<'s, 'b>(
+        &'s mut self,
+        constant_pool: &'b ConstantPool,
+        attribute_name_index: u16,
+        name: &str,
+        length: usize,
+        offset: u64,
+    ) -> Result<AttributeInfo<'a>> {
+        let info = self.read_slice(length)?;
+
+        // Offsets in errors are still relative to the start of the file
+        let start = self.position - length as u64;
+        let mut reader = ClassReader::from_slice(self.arena, info).lenient(true);
+        reader.position = start;
+        let result = reader.read_attribute(constant_pool, attribute_name_index, name, length);
+
+        let error = match result {
+            Ok(attribute_info) if reader.position == self.position => return Ok(attribute_info),
+            Ok(_) => {
+                let read = reader.position - start;
+                format!(
+                    "{name} attribute at offset {offset:#x} has length {length}, but read {read}"
+                )
+            }
+            Err(e) => format!(
+                "invalid {name} attribute at offset {offset:#x}: {}",
+                e.full_message()
+            ),
+        };
+
+        Ok(AttributeInfo::Malformed(MalformedAttribute {
+            attribute_name_index,
+            error: String::from_str_in(&error, self.arena),
+            info,
+        }))
+    }
+
+    fn read_attribute<'s, 'b>(
+        &'s mut self,
+        constant_pool: &'b ConstantPool,
+        attribute_name_index: u16,
+        name: &str,
+        length: usize,
+    ) -> Result<AttributeInfo<'a>> {
+        let attribute_info = match name {
             "Code" => AttributeInfo::Code(self.read_code_attribute(constant_pool)?),
             "LineNumberTable" => {
                 AttributeInfo::LineNumberTable(self.read_line_number_table_attribute()?)
             }
+            "StackMapTable" => AttributeInfo::StackMapTable(self.read_stack_map_table_attribute()?),
             "BootstrapMethods" => {
                 AttributeInfo::BootstrapMethods(self.read_bootstrap_methods_attribute()?)
             }
             "InnerClasses" => AttributeInfo::InnerClasses(self.read_inner_classes_attribute()?),
             "SourceFile" => AttributeInfo::SourceFile(self.read_source_file_attribute()?),
+            "Signature" => AttributeInfo::Signature(self.read_signature_attribute()?),
+            "RuntimeVisibleAnnotations" => {
+                AttributeInfo::RuntimeVisibleAnnotations(self.read_annotations_attribute()?)
+            }
+            "RuntimeInvisibleAnnotations" => {
+                AttributeInfo::RuntimeInvisibleAnnotations(self.read_annotations_attribute()?)
+            }
+            "RuntimeVisibleParameterAnnotations" => {
+                AttributeInfo::RuntimeVisibleParameterAnnotations(
+                    self.read_parameter_annotations_attribute()?,
+                )
+            }
+            "RuntimeInvisibleParameterAnnotations" => {
+                AttributeInfo::RuntimeInvisibleParameterAnnotations(
+                    self.read_parameter_annotations_attribute()?,
+                )
+            }
+            "RuntimeVisibleTypeAnnotations" => AttributeInfo::RuntimeVisibleTypeAnnotations(
+                self.read_type_annotations_attribute()?,
+            ),
+            "RuntimeInvisibleTypeAnnotations" => AttributeInfo::RuntimeInvisibleTypeAnnotations(
+                self.read_type_annotations_attribute()?,
+            ),
+            "Synthetic" | "Deprecated" if length != 0 => {
+                bail!("invalid length: {length}")
+            }
+            "Synthetic" => AttributeInfo::Synthetic,
+            "Deprecated" => AttributeInfo::Deprecated,
+            "Module" => AttributeInfo::Module(self.read_module_attribute()?),
+            "ModulePackages" => AttributeInfo::ModulePackages(ModulePackagesAttribute {
+                package_index: self.read_u16_array()?,
+            }),
+            "ModuleMainClass" => AttributeInfo::ModuleMainClass(ModuleMainClassAttribute {
+                main_class_index: self.read_u16()?,
+            }),
             _ => AttributeInfo::Custom(CustomAttribute {
                 attribute_name_index,
-                info: {
-                    let mut bytes = vec![in self.arena; 0; length];
-                    self.reader.read_exact(&mut bytes)?;
-                    bytes
-                },
+                info: self.read_slice(length)?,
             }),
         };
 
@@ -279,21 +494,19 @@ impl<'a, R: io::Read> ClassReader<'a, R> {
     fn read_code_attribute<'s, 'b>(
         &'s mut self,
         constant_pool: &'b ConstantPool,
-    ) -> eyre::Result<CodeAttribute<'a>> {
+    ) -> Result<CodeAttribute<'a>> {
         let arena = self.arena;
         Ok(CodeAttribute {
             max_stack: self.read_u16()?,
             max_locals: self.read_u16()?,
             code: {
                 let length = self.read_u32()? as usize;
-                let mut bytes = vec![in arena; 0; length];
-                self.reader.read_exact(&mut bytes)?;
-                bytes
+                self.read_slice(length)?
             },
             exception_table: {
                 let length = self.read_u16()? as usize;
                 (0..length)
-                    .map(|_| -> eyre::Result<ExceptionTableEntry> {
+                    .map(|_| -> Result<ExceptionTableEntry> {
                         Ok(ExceptionTableEntry {
                             start_pc: self.read_u16()?,
                             end_pc: self.read_u16()?,
@@ -307,15 +520,13 @@ impl<'a, R: io::Read> ClassReader<'a, R> {
         })
     }
 
-    fn read_line_number_table_attribute<'s>(
-        &'s mut self,
-    ) -> eyre::Result<LineNumberTableAttribute<'a>> {
+    fn read_line_number_table_attribute<'s>(&'s mut self) -> Result<LineNumberTableAttribute<'a>> {
         let arena = self.arena;
         Ok(LineNumberTableAttribute {
             line_number_table: {
                 let length = self.read_u16()? as usize;
                 (0..length)
-                    .map(|_| -> eyre::Result<LineNumberTableEntry> {
+                    .map(|_| -> Result<LineNumberTableEntry> {
                         Ok(LineNumberTableEntry {
                             start_pc: self.read_u16()?,
                             line_number: self.read_u16()?,
@@ -326,15 +537,88 @@ impl<'a, R: io::Read> ClassReader<'a, R> {
         })
     }
 
-    fn read_bootstrap_methods_attribute<'s>(
-        &'s mut self,
-    ) -> eyre::Result<BootstrapMethodsAttribute<'a>> {
+    fn read_stack_map_table_attribute<'s>(&'s mut self) -> Result<StackMapTableAttribute<'a>> {
+        let arena = self.arena;
+        Ok(StackMapTableAttribute {
+            entries: {
+                let length = self.read_u16()? as usize;
+                (0..length)
+                    .map(|_| self.read_stack_map_frame())
+                    .collect_in::<Result<_, _>>(arena)?
+            },
+        })
+    }
+
+    fn read_stack_map_frame<'s>(&'s mut self) -> Result<StackMapFrame<'a>> {
+        let arena = self.arena;
+        let frame_type = self.read_u8()?;
+        Ok(match frame_type {
+            0..=63 => StackMapFrame::Same {
+                offset_delta: frame_type as u16,
+            },
+            64..=127 => StackMapFrame::SameLocals1StackItem {
+                offset_delta: frame_type as u16 - 64,
+                stack: self.read_verification_type_info()?,
+            },
+            247 => StackMapFrame::SameLocals1StackItemExtended {
+                offset_delta: self.read_u16()?,
+                stack: self.read_verification_type_info()?,
+            },
+            248..=250 => StackMapFrame::Chop {
+                k: 251 - frame_type,
+                offset_delta: self.read_u16()?,
+            },
+            251 => StackMapFrame::SameExtended {
+                offset_delta: self.read_u16()?,
+            },
+            252..=254 => StackMapFrame::Append {
+                offset_delta: self.read_u16()?,
+                locals: (0..frame_type - 251)
+                    .map(|_| self.read_verification_type_info())
+                    .collect_in::<Result<_, _>>(arena)?,
+            },
+            255 => StackMapFrame::Full {
+                offset_delta: self.read_u16()?,
+                locals: {
+                    let length = self.read_u16()?;
+                    (0..length)
+                        .map(|_| self.read_verification_type_info())
+                        .collect_in::<Result<_, _>>(arena)?
+                },
+                stack: {
+                    let length = self.read_u16()?;
+                    (0..length)
+                        .map(|_| self.read_verification_type_info())
+                        .collect_in::<Result<_, _>>(arena)?
+                },
+            },
+            _ => bail!("invalid stack map frame type: {frame_type}"),
+        })
+    }
+
+    fn read_verification_type_info(&mut self) -> Result<VerificationTypeInfo> {
+        let tag = self.read_u8()?;
+        Ok(match tag {
+            0 => VerificationTypeInfo::Top,
+            1 => VerificationTypeInfo::Integer,
+            2 => VerificationTypeInfo::Float,
+            3 => VerificationTypeInfo::Double,
+            4 => VerificationTypeInfo::Long,
+            5 => VerificationTypeInfo::Null,
+            6 => VerificationTypeInfo::UninitializedThis,
+            7 => VerificationTypeInfo::Object(self.read_u16()?),
+            8 => VerificationTypeInfo::Uninitialized(self.read_u16()?),
+            _ => bail!("invalid verification type tag: {tag}"),
+        })
+    }
+
+    fn read_bootstrap_methods_attribute<'s>(&'s mut self) -> Result<BootstrapMethodsAttribute<'a>> {
         let arena = self.arena;
         Ok(BootstrapMethodsAttribute {
             bootstrap_methods: {
                 let length = self.read_u16()? as usize;
                 (0..length)
-                    .map(|_| -> eyre::Result<BootstrapMethod> {
+                    .map(|_| -> Result<BootstrapMethod> {
                         Ok(BootstrapMethod {
                             bootstrap_method_ref: self.read_u16()?,
                             bootstrap_arguments: {
@@ -350,13 +634,13 @@ impl<'a, R: io::Read> ClassReader<'a, R> {
         })
     }
 
-    fn read_inner_classes_attribute<'s>(&'s mut self) -> eyre::Result<InnerClassesAttribute<'a>> {
+    fn read_inner_classes_attribute<'s>(&'s mut self) -> Result<InnerClassesAttribute<'a>> {
         let arena = self.arena;
         Ok(InnerClassesAttribute {
             classes: {
                 let length = self.read_u16()? as usize;
                 (0..length)
-                    .map(|_| -> eyre::Result<InnerClass> {
+                    .map(|_| -> Result<InnerClass> {
                         Ok(InnerClass {
                             inner_class_info_index: self.read_u16()?,
                             outer_class_info_index: self.read_u16()?,
@@ -371,25 +655,319 @@ impl<'a, R: io::Read> ClassReader<'a, R> {
         })
     }
 
-    fn read_source_file_attribute(&mut self) -> eyre::Result<SourceFileAttribute> {
+    fn read_source_file_attribute(&mut self) -> Result<SourceFileAttribute> {
         Ok(SourceFileAttribute {
             sourcefile_index: self.read_u16()?,
         })
     }
 
+    fn read_signature_attribute(&mut self) -> Result<SignatureAttribute> {
+        Ok(SignatureAttribute {
+            signature_index: self.read_u16()?,
+        })
+    }
+
+    fn read_module_attribute<'s>(&'s mut self) -> Result<ModuleAttribute<'a>> {
+        let arena = self.arena;
+        Ok(ModuleAttribute {
+            module_name_index: self.read_u16()?,
+            module_flags: ModuleFlags::from_bits_truncate(self.read_u16()?),
+            module_version_index: self.read_u16()?,
+            requires: {
+                let length = self.read_u16()? as usize;
+                (0..length)
+                    .map(|_| -> Result<ModuleRequires> {
+                        Ok(ModuleRequires {
+                            requires_index: self.read_u16()?,
+                            requires_flags: RequiresFlags::from_bits_truncate(self.read_u16()?),
+                            requires_version_index: self.read_u16()?,
+                        })
+                    })
+                    .collect_in::<Result<_, _>>(arena)?
+            },
+            exports: self.read_module_exports()?,
+            opens: self.read_module_exports()?,
+            uses: self.read_u16_array()?,
+            provides: {
+                let length = self.read_u16()? as usize;
+                (0..length)
+                    .map(|_| -> Result<ModuleProvides> {
+                        Ok(ModuleProvides {
+                            provides_index: self.read_u16()?,
+                            provides_with_index: self.read_u16_array()?,
+                        })
+                    })
+                    .collect_in::<Result<_, _>>(arena)?
+            },
+        })
+    }
+
+    /// Reads the `exports` or `opens` directives of a module, which have the same layout.
+    fn read_module_exports<'s>(&'s mut self) -> Result<Vec<'a, ModuleExports<'a>>> {
+        let arena = self.arena;
+        let length = self.read_u16()? as usize;
+        (0..length)
+            .map(|_| -> Result<ModuleExports> {
+                Ok(ModuleExports {
+                    package_index: self.read_u16()?,
+                    flags: ExportsFlags::from_bits_truncate(self.read_u16()?),
+                    to_index: self.read_u16_array()?,
+                })
+            })
+            .collect_in::<Result<_, _>>(arena)
+    }
+
+    /// Reads a table of constant pool indices, prefixed with its length.
+    fn read_u16_array<'s>(&'s mut self) -> Result<Vec<'a, u16>> {
+        let arena = self.arena;
+        let length = self.read_u16()? as usize;
+        Ok((0..length)
+            .map(|_| self.read_u16())
+            .collect_in::<Result<_, _>>(arena)?)
+    }
+
+    fn read_annotations_attribute<'s>(&'s mut self) -> Result<AnnotationsAttribute<'a>> {
+        Ok(AnnotationsAttribute {
+            annotations: self.read_annotations()?,
+        })
+    }
+
+    fn read_parameter_annotations_attribute<'s>(
+        &'s mut self,
+    ) -> Result<ParameterAnnotationsAttribute<'a>> {
+        let arena = self.arena;
+        Ok(ParameterAnnotationsAttribute {
+            parameter_annotations: {
+                let length = self.read_u8()? as usize;
+                (0..length)
+                    .map(|_| self.read_annotations())
+                    .collect_in::<Result<_, _>>(arena)?
+            },
+        })
+    }
+
+    fn read_type_annotations_attribute<'s>(&'s mut self) -> Result<TypeAnnotationsAttribute<'a>> {
+        let arena = self.arena;
+        Ok(TypeAnnotationsAttribute {
+            annotations: {
+                let length = self.read_u16()? as usize;
+                (0..length)
+                    .map(|_| self.read_type_annotation())
+                    .collect_in::<Result<_, _>>(arena)?
+            },
+        })
+    }
+
+    fn read_type_annotation<'s>(&'s mut self) -> Result<TypeAnnotation<'a>> {
+        let arena = self.arena;
+        let target_type = self.read_u8()?;
+        Ok(TypeAnnotation {
+            target_type,
+            target_info: self.read_target_info(target_type)?,
+            target_path: {
+                let length = self.read_u8()? as usize;
+                (0..length)
+                    .map(|_| -> Result<TypePathEntry> {
+                        Ok(TypePathEntry {
+                            type_path_kind: self.read_u8()?,
+                            type_argument_index: self.read_u8()?,
+                        })
+                    })
+                    .collect_in::<Result<_, _>>(arena)?
+            },
+            annotation: self.read_annotation()?,
+        })
+    }
+
+    fn read_target_info<'s>(&'s mut self, target_type: u8) -> Result<TargetInfo<'a>> {
+        let arena = self.arena;
+        Ok(match target_type {
+            0x00 | 0x01 => TargetInfo::TypeParameter {
+                type_parameter_index: self.read_u8()?,
+            },
+            0x10 => TargetInfo::Supertype {
+                supertype_index: self.read_u16()?,
+            },
+            0x11 | 0x12 => TargetInfo::TypeParameterBound {
+                type_parameter_index: self.read_u8()?,
+                bound_index: self.read_u8()?,
+            },
+            0x13..=0x15 => TargetInfo::Empty,
+            0x16 => TargetInfo::FormalParameter {
+                formal_parameter_index: self.read_u8()?,
+            },
+            0x17 => TargetInfo::Throws {
+                throws_type_index: self.read_u16()?,
+            },
+            0x40 | 0x41 => TargetInfo::LocalVar {
+                table: {
+                    let length = self.read_u16()? as usize;
+                    (0..length)
+                        .map(|_| -> Result<LocalVarTargetEntry> {
+                            Ok(LocalVarTargetEntry {
+                                start_pc: self.read_u16()?,
+                                length: self.read_u16()?,
+                                index: self.read_u16()?,
+                            })
+                        })
+                        .collect_in::<Result<_, _>>(arena)?
+                },
+            },
+            0x42 => TargetInfo::Catch {
+                exception_table_index: self.read_u16()?,
+            },
+            0x43..=0x46 => TargetInfo::Offset {
+                offset: self.read_u16()?,
+            },
+            0x47..=0x4B => TargetInfo::TypeArgument {
+                offset: self.read_u16()?,
+                type_argument_index: self.read_u8()?,
+            },
+            _ => bail!("invalid type annotation target type: {target_type:#x}"),
+        })
+    }
+
+    fn read_annotations<'s>(&'s mut self) -> Result<Vec<'a, Annotation<'a>>> {
+        let arena = self.arena;
+        let length = self.read_u16()? as usize;
+        (0..length)
+            .map(|_| self.read_annotation())
+            .collect_in::<Result<_, _>>(arena)
+    }
+
+    fn read_annotation<'s>(&'s mut self) -> Result<Annotation<'a>> {
+        let arena = self.arena;
+        Ok(Annotation {
+            type_index: self.read_u16()?,
+            element_value_pairs: {
+                let length = self.read_u16()? as usize;
+                (0..length)
+                    .map(|_| -> Result<ElementValuePair> {
+                        Ok(ElementValuePair {
+                            element_name_index: self.read_u16()?,
+                            value: self.read_element_value()?,
+                        })
+                    })
+                    .collect_in::<Result<_, _>>(arena)?
+            },
+        })
+    }
+
+    fn read_element_value<'s>(&'s mut self) -> Result<ElementValue<'a>> {
+        let arena = self.arena;
+        let tag = self.read_u8()?;
+        Ok(match tag {
+            b'B' => ElementValue::Byte(self.read_u16()?),
+            b'C' => ElementValue::Char(self.read_u16()?),
+            b'D' => ElementValue::Double(self.read_u16()?),
+            b'F' => ElementValue::Float(self.read_u16()?),
+            b'I' => ElementValue::Int(self.read_u16()?),
+            b'J' => ElementValue::Long(self.read_u16()?),
+            b'S' => ElementValue::Short(self.read_u16()?),
+            b'Z' => ElementValue::Boolean(self.read_u16()?),
+            b's' => ElementValue::String(self.read_u16()?),
+            b'e' => ElementValue::Enum {
+                type_name_index: self.read_u16()?,
+                const_name_index: self.read_u16()?,
+            },
+            b'c' => ElementValue::Class(self.read_u16()?),
+            b'@' => ElementValue::Annotation(self.read_annotation()?),
+            b'[' => ElementValue::Array({
+                let length = self.read_u16()? as usize;
+                (0..length)
+                    .map(|_| self.read_element_value())
+                    .collect_in::<Result<_, _>>(arena)?
+            }),
+            _ => bail!("invalid element value tag: {tag}"),
+        })
+    }
+
+    fn read_bytes(&mut self, bytes: &mut [u8]) -> io::Result<()> {
+        self.input.read_exact(bytes)?;
+        self.position += bytes.len() as u64;
+        Ok(())
+    }
+
+    fn read_slice(&mut self, length: usize) -> io::Result<&'a [u8]> {
+        let bytes = self.input.read_slice(self.arena, length)?;
+        self.position += length as u64;
+        Ok(bytes)
+    }
+
     fn read_u8(&mut self) -> io::Result<u8> {
-        self.reader.read_u8()
+        Ok(u8::from_be_bytes(self.read_array()?))
     }
 
     fn read_u16(&mut self) -> io::Result<u16> {
-        self.reader.read_u16::<BigEndian>()
+        Ok(u16::from_be_bytes(self.read_array()?))
     }
 
     fn read_u32(&mut self) -> io::Result<u32> {
-        self.reader.read_u32::<BigEndian>()
+        Ok(u32::from_be_bytes(self.read_array()?))
     }
 
     fn read_u64(&mut self) -> io::Result<u64> {
-        self.reader.read_u64::<BigEndian>()
+        Ok(u64::from_be_bytes(self.read_array()?))
+    }
+
+    fn read_array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut bytes = [0; N];
+        self.read_bytes(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+/// Decodes modified UTF-8, which differs from UTF-8 in that nul is encoded as two bytes, and
+/// supplementary characters are encoded as surrogate pairs with three bytes for each surrogate.
+fn decode_modified_utf8(bytes: &[u8]) -> Result<std::string::String> {
+    let mut units = std::vec::Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    let continuation = |i: usize| match bytes.get(i) {
+        Some(&byte) if byte & 0xc0 == 0x80 => Ok((byte & 0x3f) as u16),
+        _ => Err(format_err!("invalid modified UTF-8 at offset {i}")),
+    };
+
+    while i < bytes.len() {
+        let byte = bytes[i];
+        let (unit, len) = match byte {
+            0x01..=0x7f => (byte as u16, 1),
+            0xc0..=0xdf => (((byte & 0x1f) as u16) << 6 | continuation(i + 1)?, 2),
+            0xe0..=0xef => (
+                ((byte & 0x0f) as u16) << 12 | continuation(i + 1)? << 6 | continuation(i + 2)?,
+                3,
+            ),
+            _ => bail!("invalid modified UTF-8 byte {byte:#x} at offset {i}"),
+        };
+
+        units.push(unit);
+        i += len;
     }
+
+    // Java strings may contain unpaired surrogates, which Rust strings can't represent
+    Ok(std::string::String::from_utf16_lossy(&units))
+}
+
+/// Checks that the vm supports a class file version, before the rest of the class file is read.
+/// Newer class files may contain constants and attributes that can't be read.
+fn check_version(major: u16, minor: u16) -> Result<()> {
+    let message = if major > MAX_MAJOR_VERSION {
+        format!("class file version {major}.{minor}, this VM supports up to {MAX_MAJOR_VERSION}.0")
+    } else if major < MIN_MAJOR_VERSION {
+        format!("class file version {major}.{minor}, this VM supports {MIN_MAJOR_VERSION}.0 and up")
+    } else if major >= 56 && minor != 0 {
+        // Since Java 12, the minor version is only used to mark class files which use preview
+        // features
+        match minor {
+            0xffff => format!("class file version {major}.{minor} uses preview features"),
+            _ => format!("class file version {major}.{minor} has an invalid minor version"),
+        }
+    } else {
+        return Ok(());
+    };
+
+    bail!(JavaException::new(
+        "java/lang/UnsupportedClassVersionError",
+        message
+    ))
 }
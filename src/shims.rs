@@ -0,0 +1,62 @@
+//! Minimal replacements for core JDK classes, which are built into the vm so that simple programs
+//! can run without a JDK installed. They are only used when no JDK is found.
+//!
+//! The sources are in `shims/src`, and `shims/build.sh` compiles them into `shims/classes`. Most
+//! of their methods are native, and implemented by the same natives which replace the JDK's
+//! implementations.
+
+macro_rules! shims {
+    ($($name:literal),* $(,)?) => {
+        &[$((
+            $name,
+            include_bytes!(concat!("../shims/classes/", $name, ".class")).as_slice(),
+        )),*]
+    };
+}
+
+static SHIMS: &[(&str, &[u8])] = shims![
+    "java/io/FileDescriptor",
+    "java/io/FileInputStream",
+    "java/io/FileOutputStream",
+    "java/io/FilterOutputStream",
+    "java/io/InputStream",
+    "java/io/OutputStream",
+    "java/io/PrintStream",
+    "java/lang/ArithmeticException",
+    "java/lang/ArrayIndexOutOfBoundsException",
+    "java/lang/Boolean",
+    "java/lang/Byte",
+    "java/lang/CharSequence",
+    "java/lang/Character",
+    "java/lang/Class",
+    "java/lang/ClassCastException",
+    "java/lang/Double",
+    "java/lang/Error",
+    "java/lang/Exception",
+    "java/lang/Float",
+    "java/lang/IllegalArgumentException",
+    "java/lang/IllegalStateException",
+    "java/lang/IndexOutOfBoundsException",
+    "java/lang/Integer",
+    "java/lang/Long",
+    "java/lang/NegativeArraySizeException",
+    "java/lang/NullPointerException",
+    "java/lang/Number",
+    "java/lang/Object",
+    "java/lang/RuntimeException",
+    "java/lang/Short",
+    "java/lang/String",
+    "java/lang/StringBuilder",
+    "java/lang/StringIndexOutOfBoundsException",
+    "java/lang/System",
+    "java/lang/Throwable",
+    "java/lang/UnsupportedOperationException",
+];
+
+/// Returns the class file of a shim, given its binary name.
+pub(crate) fn find_class(class_name: &str) -> Option<&'static [u8]> {
+    SHIMS
+        .iter()
+        .find(|(name, _)| *name == class_name)
+        .map(|(_, bytes)| *bytes)
+}
@@ -0,0 +1,642 @@
+//! Snapshots of a vm's loaded classes, static fields and heap, which can be restored into a new
+//! vm so that expensive static initializers only have to run once, e.g. for test fixtures.
+//!
+//! Objects are allocated at different addresses when a snapshot is restored, so references are
+//! stored as indices into the snapshot's objects. Elements of primitive arrays are stored in the
+//! platform's byte order, so a snapshot can only be restored on the platform that created it.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufWriter, Cursor, Read, Write};
+use std::path::Path;
+use std::ptr;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::call_frame::{ArrayElementType, JvmValue, RefTypeHeader};
+use crate::class::Class;
+use crate::error::{bail, format_err, Context, ContextCompat, Result};
+use crate::instructions::ArrayType;
+
+const MAGIC: &[u8; 4] = b"RJSN";
+const VERSION: u32 = 1;
+
+/// The state of a vm at some point, taken with [`Vm::snapshot`] and restored with
+/// [`Vm::restore_snapshot`].
+///
+/// [`Vm::snapshot`]: crate::vm::Vm::snapshot
+/// [`Vm::restore_snapshot`]: crate::vm::Vm::restore_snapshot
+#[derive(Debug, Default)]
+pub struct Snapshot {
+    /// Loaded classes, in the order they were loaded, so that super classes come first.
+    pub(crate) classes: Vec<SnapshotClass>,
+    /// Strings referred to by values, which are identical in the restored vm if they were
+    /// identical in the snapshot.
+    pub(crate) strings: Vec<SnapshotString>,
+    pub(crate) objects: Vec<SnapshotObject>,
+    /// Mirrors of classes, by name.
+    pub(crate) class_mirrors: Vec<(String, Ref)>,
+    /// Cached boxes of primitives, by wrapper class name and value.
+    pub(crate) boxes: Vec<(String, i64, Ref)>,
+    /// The platform and application class loaders, if they were created.
+    pub(crate) builtin_loaders: Option<(Ref, Ref)>,
+    pub(crate) shutdown_hooks: Vec<Ref>,
+    pub(crate) string_builders: Vec<(Ref, String)>,
+    /// The `java.lang.Thread` of the thread that took the snapshot, which becomes the thread that
+    /// restores it.
+    pub(crate) current_thread: Ref,
+    pub(crate) next_tid: i64,
+    pub(crate) next_thread_number: usize,
+}
+
+/// A reference to an object, as its index in [`Snapshot::objects`] plus one, so that 0 is null.
+pub(crate) type Ref = u32;
+
+#[derive(Debug)]
+pub(crate) struct SnapshotClass {
+    pub name: String,
+    pub bytes: Vec<u8>,
+    pub loader: Ref,
+    pub initialized: bool,
+    /// The name, descriptor and value of each static field.
+    pub static_fields: Vec<(String, String, Value)>,
+}
+
+#[derive(Debug)]
+pub(crate) struct SnapshotString {
+    pub value: String,
+    /// Whether this is the canonical instance of the string, returned by `String.intern`.
+    pub interned: bool,
+}
+
+#[derive(Debug)]
+pub(crate) enum SnapshotObject {
+    Object {
+        /// The index of the object's class in [`Snapshot::classes`].
+        class: u32,
+        fields: Vec<Value>,
+    },
+    PrimitiveArray {
+        element_type: ArrayType,
+        length: usize,
+        bytes: Vec<u8>,
+    },
+    ReferenceArray(Vec<Value>),
+}
+
+/// A field value or array element. Strings are indices into [`Snapshot::strings`].
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Value {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Char(u16),
+    Float(f32),
+    Double(f64),
+    Boolean(bool),
+    Reference(Ref),
+    String(u32),
+}
+
+impl Value {
+    /// Converts a value to the value it represents in a vm where the snapshot's objects and
+    /// strings have been restored.
+    pub fn to_jvm<'a>(self, addresses: &[usize], strings: &[&'a str]) -> JvmValue<'a> {
+        match self {
+            Value::Byte(v) => JvmValue::Byte(v),
+            Value::Short(v) => JvmValue::Short(v),
+            Value::Int(v) => JvmValue::Int(v),
+            Value::Long(v) => JvmValue::Long(v),
+            Value::Char(v) => JvmValue::Char(v),
+            Value::Float(v) => JvmValue::Float(v),
+            Value::Double(v) => JvmValue::Double(v),
+            Value::Boolean(v) => JvmValue::Boolean(v),
+            Value::Reference(r) => JvmValue::Reference(address(addresses, r)),
+            Value::String(s) => JvmValue::StringConst(strings[s as usize]),
+        }
+    }
+}
+
+/// Returns the address of a restored object, given the addresses of all of the snapshot's
+/// objects.
+pub(crate) fn address(addresses: &[usize], r: Ref) -> usize {
+    match r {
+        0 => 0,
+        r => addresses[r as usize - 1],
+    }
+}
+
+impl Snapshot {
+    pub fn read(path: impl AsRef<Path>) -> Result<Snapshot> {
+        let path = path.as_ref();
+        let data = fs::read(path).wrap_err_with(|| format_err!("failed to read {path:?}"))?;
+        Snapshot::parse(&data).wrap_err_with(|| format_err!("invalid snapshot {path:?}"))
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let file = File::create(path).wrap_err_with(|| format_err!("failed to create {path:?}"))?;
+        let mut writer = BufWriter::new(file);
+
+        writer.write_all(MAGIC)?;
+        writer.write_u32::<LittleEndian>(VERSION)?;
+
+        write_len(&mut writer, self.classes.len())?;
+        for class in &self.classes {
+            write_str(&mut writer, &class.name)?;
+            write_bytes(&mut writer, &class.bytes)?;
+            writer.write_u32::<LittleEndian>(class.loader)?;
+            writer.write_u8(class.initialized.into())?;
+            write_len(&mut writer, class.static_fields.len())?;
+            for (name, descriptor, value) in &class.static_fields {
+                write_str(&mut writer, name)?;
+                write_str(&mut writer, descriptor)?;
+                write_value(&mut writer, *value)?;
+            }
+        }
+
+        write_len(&mut writer, self.strings.len())?;
+        for string in &self.strings {
+            write_str(&mut writer, &string.value)?;
+            writer.write_u8(string.interned.into())?;
+        }
+
+        write_len(&mut writer, self.objects.len())?;
+        for object in &self.objects {
+            match object {
+                SnapshotObject::Object { class, fields } => {
+                    writer.write_u8(0)?;
+                    writer.write_u32::<LittleEndian>(*class)?;
+                    write_len(&mut writer, fields.len())?;
+                    for value in fields {
+                        write_value(&mut writer, *value)?;
+                    }
+                }
+                SnapshotObject::PrimitiveArray {
+                    element_type,
+                    length,
+                    bytes,
+                } => {
+                    writer.write_u8(1)?;
+                    writer.write_u8(*element_type as u8)?;
+                    write_len(&mut writer, *length)?;
+                    write_bytes(&mut writer, bytes)?;
+                }
+                SnapshotObject::ReferenceArray(elements) => {
+                    writer.write_u8(2)?;
+                    write_len(&mut writer, elements.len())?;
+                    for value in elements {
+                        write_value(&mut writer, *value)?;
+                    }
+                }
+            }
+        }
+
+        write_len(&mut writer, self.class_mirrors.len())?;
+        for (name, mirror) in &self.class_mirrors {
+            write_str(&mut writer, name)?;
+            writer.write_u32::<LittleEndian>(*mirror)?;
+        }
+
+        write_len(&mut writer, self.boxes.len())?;
+        for (class, value, object) in &self.boxes {
+            write_str(&mut writer, class)?;
+            writer.write_i64::<LittleEndian>(*value)?;
+            writer.write_u32::<LittleEndian>(*object)?;
+        }
+
+        let (platform, app) = self.builtin_loaders.unwrap_or_default();
+        writer.write_u8(self.builtin_loaders.is_some().into())?;
+        writer.write_u32::<LittleEndian>(platform)?;
+        writer.write_u32::<LittleEndian>(app)?;
+
+        write_len(&mut writer, self.shutdown_hooks.len())?;
+        for hook in &self.shutdown_hooks {
+            writer.write_u32::<LittleEndian>(*hook)?;
+        }
+
+        write_len(&mut writer, self.string_builders.len())?;
+        for (builder, contents) in &self.string_builders {
+            writer.write_u32::<LittleEndian>(*builder)?;
+            write_str(&mut writer, contents)?;
+        }
+
+        writer.write_u32::<LittleEndian>(self.current_thread)?;
+        writer.write_i64::<LittleEndian>(self.next_tid)?;
+        writer.write_u64::<LittleEndian>(self.next_thread_number.try_into()?)?;
+
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    fn parse(data: &[u8]) -> Result<Snapshot> {
+        let mut cursor = Cursor::new(data);
+
+        let mut magic = [0; 4];
+        cursor.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            bail!("invalid magic bytes");
+        }
+
+        let version = cursor.read_u32::<LittleEndian>()?;
+        if version != VERSION {
+            bail!("unsupported version {version}");
+        }
+
+        let mut snapshot = Snapshot::default();
+
+        for _ in 0..read_len(&mut cursor)? {
+            let name = read_str(&mut cursor)?;
+            let bytes = read_bytes(&mut cursor)?;
+            let loader = cursor.read_u32::<LittleEndian>()?;
+            let initialized = cursor.read_u8()? != 0;
+
+            let mut static_fields = Vec::new();
+            for _ in 0..read_len(&mut cursor)? {
+                static_fields.push((
+                    read_str(&mut cursor)?,
+                    read_str(&mut cursor)?,
+                    read_value(&mut cursor)?,
+                ));
+            }
+
+            snapshot.classes.push(SnapshotClass {
+                name,
+                bytes,
+                loader,
+                initialized,
+                static_fields,
+            });
+        }
+
+        for _ in 0..read_len(&mut cursor)? {
+            snapshot.strings.push(SnapshotString {
+                value: read_str(&mut cursor)?,
+                interned: cursor.read_u8()? != 0,
+            });
+        }
+
+        for _ in 0..read_len(&mut cursor)? {
+            let object = match cursor.read_u8()? {
+                0 => {
+                    let class = cursor.read_u32::<LittleEndian>()?;
+                    let fields = (0..read_len(&mut cursor)?)
+                        .map(|_| read_value(&mut cursor))
+                        .collect::<Result<_>>()?;
+                    SnapshotObject::Object { class, fields }
+                }
+                1 => SnapshotObject::PrimitiveArray {
+                    element_type: {
+                        let atype = cursor.read_u8()?;
+                        ArrayType::from_repr(atype)
+                            .wrap_err_with(|| format_err!("invalid array type {atype}"))?
+                    },
+                    length: read_len(&mut cursor)?,
+                    bytes: read_bytes(&mut cursor)?,
+                },
+                2 => SnapshotObject::ReferenceArray(
+                    (0..read_len(&mut cursor)?)
+                        .map(|_| read_value(&mut cursor))
+                        .collect::<Result<_>>()?,
+                ),
+                tag => bail!("invalid object tag {tag}"),
+            };
+            snapshot.objects.push(object);
+        }
+
+        for _ in 0..read_len(&mut cursor)? {
+            snapshot
+                .class_mirrors
+                .push((read_str(&mut cursor)?, cursor.read_u32::<LittleEndian>()?));
+        }
+
+        for _ in 0..read_len(&mut cursor)? {
+            snapshot.boxes.push((
+                read_str(&mut cursor)?,
+                cursor.read_i64::<LittleEndian>()?,
+                cursor.read_u32::<LittleEndian>()?,
+            ));
+        }
+
+        let has_builtin_loaders = cursor.read_u8()? != 0;
+        let builtin_loaders = (
+            cursor.read_u32::<LittleEndian>()?,
+            cursor.read_u32::<LittleEndian>()?,
+        );
+        snapshot.builtin_loaders = has_builtin_loaders.then_some(builtin_loaders);
+
+        for _ in 0..read_len(&mut cursor)? {
+            snapshot
+                .shutdown_hooks
+                .push(cursor.read_u32::<LittleEndian>()?);
+        }
+
+        for _ in 0..read_len(&mut cursor)? {
+            snapshot
+                .string_builders
+                .push((cursor.read_u32::<LittleEndian>()?, read_str(&mut cursor)?));
+        }
+
+        snapshot.current_thread = cursor.read_u32::<LittleEndian>()?;
+        snapshot.next_tid = cursor.read_i64::<LittleEndian>()?;
+        snapshot.next_thread_number = cursor.read_u64::<LittleEndian>()?.try_into()?;
+
+        snapshot.check_refs()?;
+
+        Ok(snapshot)
+    }
+
+    /// Checks that every reference and string index is in bounds, so that restoring a corrupt
+    /// snapshot fails instead of panicking.
+    fn check_refs(&self) -> Result<()> {
+        let check_ref = |r: Ref| match r as usize <= self.objects.len() {
+            true => Ok(()),
+            false => Err(format_err!("invalid reference {r}")),
+        };
+
+        let check_value = |value: &Value| match *value {
+            Value::Reference(r) => check_ref(r),
+            Value::String(s) if s as usize >= self.strings.len() => bail!("invalid string {s}"),
+            _ => Ok(()),
+        };
+
+        for class in &self.classes {
+            check_ref(class.loader)?;
+            for (_, _, value) in &class.static_fields {
+                check_value(value)?;
+            }
+        }
+
+        for object in &self.objects {
+            match object {
+                SnapshotObject::Object { class, fields } => {
+                    if *class as usize >= self.classes.len() {
+                        bail!("invalid class {class}");
+                    }
+                    fields.iter().try_for_each(check_value)?;
+                }
+                SnapshotObject::PrimitiveArray {
+                    element_type,
+                    length,
+                    bytes,
+                } => {
+                    let size = ArrayElementType::Primitive(*element_type)
+                        .element_layout()
+                        .size();
+                    if length.checked_mul(size) != Some(bytes.len()) {
+                        bail!("array of length {length} has {} bytes", bytes.len());
+                    }
+                }
+                SnapshotObject::ReferenceArray(elements) => {
+                    elements.iter().try_for_each(check_value)?;
+                }
+            }
+        }
+
+        let (platform, app) = self.builtin_loaders.unwrap_or_default();
+        [platform, app, self.current_thread]
+            .into_iter()
+            .chain(self.class_mirrors.iter().map(|(_, r)| *r))
+            .chain(self.boxes.iter().map(|(_, _, r)| *r))
+            .chain(self.shutdown_hooks.iter().copied())
+            .chain(self.string_builders.iter().map(|(r, _)| *r))
+            .try_for_each(check_ref)
+    }
+}
+
+/// Collects the objects reachable from a vm's roots into a snapshot.
+pub(crate) struct SnapshotBuilder<'s, 'a> {
+    snapshot: Snapshot,
+    /// Indices of the loaded classes in the snapshot, by name.
+    classes: HashMap<&'a str, u32>,
+    /// The address of each object in the snapshot, in order.
+    addresses: Vec<usize>,
+    refs: HashMap<usize, Ref>,
+    /// Strings by address and length, since strings are compared by identity.
+    strings: HashMap<(usize, usize), u32>,
+    interned: &'s HashSet<&'a str>,
+}
+
+impl<'s, 'a> SnapshotBuilder<'s, 'a> {
+    pub fn new(
+        classes: &[(&'a Class<'a>, &'a [u8])],
+        interned: &'s HashSet<&'a str>,
+    ) -> SnapshotBuilder<'s, 'a> {
+        let mut snapshot = Snapshot::default();
+        let mut indices = HashMap::new();
+
+        for (index, (class, bytes)) in classes.iter().enumerate() {
+            indices.insert(class.name(), index as u32);
+            snapshot.classes.push(SnapshotClass {
+                name: class.name().to_owned(),
+                bytes: bytes.to_vec(),
+                loader: 0,
+                initialized: false,
+                static_fields: Vec::new(),
+            });
+        }
+
+        SnapshotBuilder {
+            snapshot,
+            classes: indices,
+            addresses: Vec::new(),
+            refs: HashMap::new(),
+            strings: HashMap::new(),
+            interned,
+        }
+    }
+
+    pub fn snapshot(&mut self) -> &mut Snapshot {
+        &mut self.snapshot
+    }
+
+    /// Returns the reference to an object in the snapshot, adding it if it hasn't been already.
+    /// Its contents are added by [`SnapshotBuilder::finish`].
+    pub fn reference(&mut self, address: usize) -> Ref {
+        if address == 0 {
+            return 0;
+        }
+
+        *self.refs.entry(address).or_insert_with(|| {
+            self.addresses.push(address);
+            self.addresses.len() as Ref
+        })
+    }
+
+    pub fn value(&mut self, value: &JvmValue<'a>) -> Result<Value> {
+        Ok(match value {
+            JvmValue::Byte(v) => Value::Byte(*v),
+            JvmValue::Short(v) => Value::Short(*v),
+            JvmValue::Int(v) => Value::Int(*v),
+            JvmValue::Long(v) => Value::Long(*v),
+            JvmValue::Char(v) => Value::Char(*v),
+            JvmValue::Float(v) => Value::Float(*v),
+            JvmValue::Double(v) => Value::Double(*v),
+            JvmValue::Boolean(v) => Value::Boolean(*v),
+            JvmValue::Reference(address) => Value::Reference(self.reference(*address)),
+            JvmValue::StringConst(s) => {
+                let strings = &mut self.snapshot.strings;
+                let interned = self.interned.get(s).is_some_and(|i| ptr::eq(*i, *s));
+                let index = *self
+                    .strings
+                    .entry((s.as_ptr() as usize, s.len()))
+                    .or_insert_with(|| {
+                        strings.push(SnapshotString {
+                            value: (*s).to_owned(),
+                            interned,
+                        });
+                        strings.len() as u32 - 1
+                    });
+                Value::String(index)
+            }
+            JvmValue::ReturnAddress(_) => bail!("unexpected return address on the heap"),
+        })
+    }
+
+    /// Adds the contents of every object that has been referenced, and the objects they refer
+    /// to.
+    pub fn finish(mut self) -> Result<Snapshot> {
+        while self.snapshot.objects.len() < self.addresses.len() {
+            let address = self.addresses[self.snapshot.objects.len()];
+            let header = unsafe { &mut *(address as *mut RefTypeHeader) };
+
+            let object = match header {
+                RefTypeHeader::Object(object) => {
+                    let class = unsafe { object.class.cast::<Class<'a>>().as_ref() };
+                    let class = *self.classes.get(class.name()).wrap_err_with(|| {
+                        format_err!("class of object isn't loaded: {}", class.name())
+                    })?;
+                    let fields = unsafe { header.object_data()? }
+                        .iter()
+                        .map(|value| self.value(value))
+                        .collect::<Result<_>>()?;
+                    SnapshotObject::Object { class, fields }
+                }
+                RefTypeHeader::Array(array) => match array.element_type {
+                    ArrayElementType::Primitive(element_type) => {
+                        let length = array.length;
+                        let size = array.element_type.element_layout().size() * length;
+                        let data = unsafe { header.array_data_ptr()? };
+                        let bytes = unsafe { std::slice::from_raw_parts(data, size) }.to_vec();
+                        SnapshotObject::PrimitiveArray {
+                            element_type,
+                            length,
+                            bytes,
+                        }
+                    }
+                    ArrayElementType::Reference => {
+                        let elements = unsafe { header.array_data::<JvmValue>()? }
+                            .iter()
+                            .map(|value| self.value(value))
+                            .collect::<Result<_>>()?;
+                        SnapshotObject::ReferenceArray(elements)
+                    }
+                },
+            };
+
+            self.snapshot.objects.push(object);
+        }
+
+        Ok(self.snapshot)
+    }
+}
+
+fn write_len(writer: &mut impl Write, len: usize) -> Result<()> {
+    writer.write_u64::<LittleEndian>(len.try_into()?)?;
+    Ok(())
+}
+
+fn write_bytes(writer: &mut impl Write, bytes: &[u8]) -> Result<()> {
+    write_len(writer, bytes.len())?;
+    writer.write_all(bytes)?;
+    Ok(())
+}
+
+fn write_str(writer: &mut impl Write, s: &str) -> Result<()> {
+    write_bytes(writer, s.as_bytes())
+}
+
+fn write_value(writer: &mut impl Write, value: Value) -> Result<()> {
+    match value {
+        Value::Byte(v) => {
+            writer.write_u8(0)?;
+            writer.write_i8(v)?;
+        }
+        Value::Short(v) => {
+            writer.write_u8(1)?;
+            writer.write_i16::<LittleEndian>(v)?;
+        }
+        Value::Int(v) => {
+            writer.write_u8(2)?;
+            writer.write_i32::<LittleEndian>(v)?;
+        }
+        Value::Long(v) => {
+            writer.write_u8(3)?;
+            writer.write_i64::<LittleEndian>(v)?;
+        }
+        Value::Char(v) => {
+            writer.write_u8(4)?;
+            writer.write_u16::<LittleEndian>(v)?;
+        }
+        Value::Float(v) => {
+            writer.write_u8(5)?;
+            writer.write_f32::<LittleEndian>(v)?;
+        }
+        Value::Double(v) => {
+            writer.write_u8(6)?;
+            writer.write_f64::<LittleEndian>(v)?;
+        }
+        Value::Boolean(v) => {
+            writer.write_u8(7)?;
+            writer.write_u8(v.into())?;
+        }
+        Value::Reference(v) => {
+            writer.write_u8(8)?;
+            writer.write_u32::<LittleEndian>(v)?;
+        }
+        Value::String(v) => {
+            writer.write_u8(9)?;
+            writer.write_u32::<LittleEndian>(v)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_len(reader: &mut Cursor<&[u8]>) -> Result<usize> {
+    let len = reader.read_u64::<LittleEndian>()?.try_into()?;
+    // Lengths which are longer than the rest of the data are rejected before anything is
+    // allocated for them
+    let remaining = reader.get_ref().len() as u64 - reader.position();
+    if len as u64 > remaining {
+        bail!("truncated snapshot");
+    }
+    Ok(len)
+}
+
+fn read_bytes(reader: &mut Cursor<&[u8]>) -> Result<Vec<u8>> {
+    let mut bytes = vec![0; read_len(reader)?];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_str(reader: &mut Cursor<&[u8]>) -> Result<String> {
+    Ok(String::from_utf8(read_bytes(reader)?)?)
+}
+
+fn read_value(reader: &mut Cursor<&[u8]>) -> Result<Value> {
+    Ok(match reader.read_u8()? {
+        0 => Value::Byte(reader.read_i8()?),
+        1 => Value::Short(reader.read_i16::<LittleEndian>()?),
+        2 => Value::Int(reader.read_i32::<LittleEndian>()?),
+        3 => Value::Long(reader.read_i64::<LittleEndian>()?),
+        4 => Value::Char(reader.read_u16::<LittleEndian>()?),
+        5 => Value::Float(reader.read_f32::<LittleEndian>()?),
+        6 => Value::Double(reader.read_f64::<LittleEndian>()?),
+        7 => Value::Boolean(reader.read_u8()? != 0),
+        8 => Value::Reference(reader.read_u32::<LittleEndian>()?),
+        9 => Value::String(reader.read_u32::<LittleEndian>()?),
+        tag => bail!("invalid value tag {tag}"),
+    })
+}
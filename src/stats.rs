@@ -0,0 +1,193 @@
+//! Counts of the work done by Java code, for finding out what a program spends its time on and
+//! which parts of the interpreter are worth optimizing.
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use strum::EnumCount;
+
+use crate::call_frame::{JvmValue, LocalVariables, Operands};
+use crate::class::Class;
+use crate::hooks::{Allocation, AllocationKind, Hook, MethodRef};
+use crate::instructions::{Instruction, InstructionKind};
+use crate::trace::primitive_name;
+use crate::vm::Vm;
+
+/// The number of methods, classes and packages listed in each part of the report.
+const MAX_REPORT_ENTRIES: usize = 20;
+
+/// A hook which counts the instructions executed by kind, the calls to each method, the
+/// allocations of each class and the classes loaded from each package. The counts are written
+/// with [`ExecutionStats::report`].
+pub struct ExecutionStats {
+    /// Indexed by [`InstructionKind`], since these are counted far more often than anything else.
+    instructions: [AtomicU64; InstructionKind::COUNT],
+    counts: Mutex<Counts>,
+}
+
+#[derive(Default)]
+struct Counts {
+    /// Keyed by the addresses of the methods, so that names are only formatted for new methods.
+    calls: HashMap<usize, (String, u64)>,
+    /// The name of each class or array type, along with the number of allocations and the bytes
+    /// allocated.
+    allocations: HashMap<AllocationKey, (String, u64, usize)>,
+    classes: HashMap<String, u64>,
+}
+
+#[derive(PartialEq, Eq, Hash)]
+enum AllocationKey {
+    /// The address of the class.
+    Object(usize),
+    /// The element type of the array, if it's primitive.
+    Array(Option<u8>),
+}
+
+impl ExecutionStats {
+    pub fn new() -> Self {
+        ExecutionStats {
+            instructions: [const { AtomicU64::new(0) }; InstructionKind::COUNT],
+            counts: Mutex::default(),
+        }
+    }
+
+    /// Writes the counts, with the most frequent entries of each kind first.
+    pub fn report(&self, out: &mut impl io::Write) -> io::Result<()> {
+        let counts = self.counts.lock().unwrap();
+
+        let mut instructions = self
+            .instructions
+            .iter()
+            .enumerate()
+            .filter_map(|(kind, count)| {
+                let kind = InstructionKind::from_repr(kind as u8)?;
+                Some((kind, count.load(Ordering::Relaxed)))
+            })
+            .filter(|(_, count)| *count > 0)
+            .collect::<Vec<_>>();
+        instructions.sort_by(|a, b| b.1.cmp(&a.1));
+
+        let total = instructions.iter().map(|(_, count)| count).sum::<u64>();
+        writeln!(out, "Instructions executed: {total}")?;
+        for (kind, count) in instructions {
+            let percent = count as f64 / total as f64 * 100.0;
+            writeln!(
+                out,
+                "  {:<20} {count:>12} {percent:>6.2}%",
+                format!("{kind:?}")
+            )?;
+        }
+
+        let mut calls = counts.calls.values().collect::<Vec<_>>();
+        calls.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        let total = calls.iter().map(|(_, count)| count).sum::<u64>();
+        writeln!(out, "\nMethod calls: {total} ({} methods)", calls.len())?;
+        for (name, count) in calls.into_iter().take(MAX_REPORT_ENTRIES) {
+            writeln!(out, "  {count:>12} {name}")?;
+        }
+
+        let mut allocations = counts.allocations.values().collect::<Vec<_>>();
+        allocations.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+
+        let (total, total_bytes) = allocations
+            .iter()
+            .fold((0, 0), |(total, total_bytes), (_, count, bytes)| {
+                (total + count, total_bytes + bytes)
+            });
+        writeln!(out, "\nAllocations: {total} ({total_bytes} bytes)")?;
+        for (name, count, bytes) in allocations.into_iter().take(MAX_REPORT_ENTRIES) {
+            writeln!(out, "  {count:>12} {bytes:>12} bytes  {name}")?;
+        }
+
+        let mut classes = counts.classes.iter().collect::<Vec<_>>();
+        classes.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+
+        let total = classes.iter().map(|(_, count)| *count).sum::<u64>();
+        writeln!(out, "\nClasses loaded: {total}")?;
+        for (package, count) in classes.into_iter().take(MAX_REPORT_ENTRIES) {
+            writeln!(out, "  {count:>12} {package}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for ExecutionStats {
+    fn default() -> Self {
+        ExecutionStats::new()
+    }
+}
+
+impl<'a> Hook<'a> for ExecutionStats {
+    fn on_method_enter(&self, _: &Vm<'a>, method: MethodRef<'a>, _: &[JvmValue<'a>]) {
+        let key = method.method as *const _ as usize;
+        let mut counts = self.counts.lock().unwrap();
+        let (_, count) = counts.calls.entry(key).or_insert_with(|| {
+            let name = format!(
+                "{}.{}{}",
+                method.class.name().replace('/', "."),
+                method.name(),
+                method.descriptor()
+            );
+            (name, 0)
+        });
+        *count += 1;
+    }
+
+    fn on_instruction(
+        &self,
+        _: &Vm<'a>,
+        _: MethodRef<'a>,
+        _: usize,
+        instruction: &'a Instruction,
+        _: LocalVariables<'_, 'a>,
+        _: Operands<'_, 'a>,
+    ) {
+        self.instructions[InstructionKind::from(instruction) as usize]
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_allocation(&self, _: &Vm<'a>, allocation: &Allocation<'a>) {
+        let key = match allocation.kind {
+            AllocationKind::Object(class) => AllocationKey::Object(class as *const _ as usize),
+            AllocationKind::Array { element_type, .. } => {
+                AllocationKey::Array(element_type.map(|t| t as u8))
+            }
+        };
+
+        let mut counts = self.counts.lock().unwrap();
+        let (_, count, bytes) = counts.allocations.entry(key).or_insert_with(|| {
+            let name = match allocation.kind {
+                AllocationKind::Object(class) => class.name().replace('/', "."),
+                AllocationKind::Array {
+                    element_type: Some(element_type),
+                    ..
+                } => format!("{}[]", primitive_name(element_type)),
+                // The element class of reference arrays isn't known
+                AllocationKind::Array {
+                    element_type: None, ..
+                } => "java.lang.Object[]".to_owned(),
+            };
+            (name, 0, 0)
+        });
+        *count += 1;
+        *bytes += allocation.size;
+    }
+
+    fn on_class_load(&self, _: &Vm<'a>, class: &'a Class<'a>) {
+        let package = match class.name().rsplit_once('/') {
+            Some((package, _)) => package.replace('/', "."),
+            None => "(default package)".to_owned(),
+        };
+        *self
+            .counts
+            .lock()
+            .unwrap()
+            .classes
+            .entry(package)
+            .or_default() += 1;
+    }
+}
@@ -0,0 +1,110 @@
+//! Fuses common sequences of instructions into superinstructions, which the interpreter executes
+//! with a single dispatch.
+//!
+//! A superinstruction replaces the first instruction of its sequence, and the rest of the
+//! instructions are left in place. Branches into the middle of a sequence still execute the
+//! remaining instructions one at a time, so instruction indices and branch targets are
+//! unaffected.
+
+use crate::instructions::{Condition, Instruction, LoadStoreType, NumberType};
+
+/// The [hotness](crate::class::Hotness) after which a method's instructions are fused, if no
+/// other threshold is set.
+pub const DEFAULT_FUSE_THRESHOLD: u64 = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Superinstruction {
+    /// `iload a; iload b; iadd`
+    AddLocals { a: u8, b: u8 },
+    /// `iload index; iconst/bipush/sipush value; if_icmp<condition> branch`, where the branch is
+    /// relative to the `if_icmp`.
+    CompareLocal {
+        index: u8,
+        value: i16,
+        condition: Condition,
+        branch: i16,
+    },
+    /// `aload_0; getfield index`
+    GetThisField { index: u16 },
+}
+
+impl Superinstruction {
+    /// The number of instructions which are fused.
+    pub fn instruction_count(&self) -> usize {
+        match self {
+            Superinstruction::AddLocals { .. } => 3,
+            Superinstruction::CompareLocal { .. } => 3,
+            Superinstruction::GetThisField { .. } => 2,
+        }
+    }
+}
+
+/// Returns the superinstruction starting at each instruction, if any. Sequences don't overlap.
+pub fn fuse(instructions: &[Instruction]) -> Vec<Option<Superinstruction>> {
+    let mut superinstructions = vec![None; instructions.len()];
+
+    let mut index = 0;
+    while index < instructions.len() {
+        let superinstruction = match_sequence(&instructions[index..]);
+        superinstructions[index] = superinstruction;
+        index += superinstruction.map_or(1, |s| s.instruction_count());
+    }
+
+    superinstructions
+}
+
+fn match_sequence(instructions: &[Instruction]) -> Option<Superinstruction> {
+    if let [first, second, Instruction::add {
+        data_type: NumberType::Int,
+    }, ..] = instructions
+    {
+        if let (Some(a), Some(b)) = (int_load(first), int_load(second)) {
+            return Some(Superinstruction::AddLocals { a, b });
+        }
+    }
+
+    if let [first, second, Instruction::if_icmp { condition, branch }, ..] = instructions {
+        if let (Some(index), Some(value)) = (int_load(first), int_constant(second)) {
+            return Some(Superinstruction::CompareLocal {
+                index,
+                value,
+                condition: *condition,
+                branch: *branch,
+            });
+        }
+    }
+
+    if let [Instruction::load {
+        data_type: LoadStoreType::Reference,
+        index: 0,
+    }, Instruction::getfield { index }, ..] = instructions
+    {
+        return Some(Superinstruction::GetThisField { index: *index });
+    }
+
+    None
+}
+
+/// Returns the local loaded by an `iload` instruction.
+fn int_load(instruction: &Instruction) -> Option<u8> {
+    match instruction {
+        Instruction::load {
+            data_type: LoadStoreType::Int,
+            index,
+        } => Some(*index),
+        _ => None,
+    }
+}
+
+/// Returns the value pushed by an instruction which pushes an int constant.
+fn int_constant(instruction: &Instruction) -> Option<i16> {
+    match instruction {
+        Instruction::r#const {
+            data_type: NumberType::Int,
+            value,
+        } => Some(*value as i16),
+        Instruction::bipush { value } => Some(*value as i16),
+        Instruction::sipush { value } => Some(*value),
+        _ => None,
+    }
+}
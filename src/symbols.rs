@@ -0,0 +1,60 @@
+//! The vm's table of symbols, which are the names and descriptors in class files.
+//!
+//! Most classes refer to the same handful of classes, methods and descriptors, like
+//! `java/lang/Object` and `()V`. The strings in each class's constant pool are replaced by a
+//! single canonical instance when the class file is read, so equal symbols from different classes
+//! are also identical, and can be compared by address with [`same`].
+
+use std::ptr;
+use std::sync::RwLock;
+
+use hashbrown::HashSet;
+
+use crate::class_file::constant_pool::{ConstantInfo, ConstantPool};
+
+#[derive(Default)]
+pub struct SymbolTable<'a> {
+    symbols: RwLock<HashSet<&'a str>>,
+}
+
+impl<'a> SymbolTable<'a> {
+    /// Returns the canonical instance of a string, which is the string itself if no equal string
+    /// has been interned yet.
+    pub fn intern(&self, s: &'a str) -> &'a str {
+        if let Some(symbol) = self.get(s) {
+            return symbol;
+        }
+
+        self.symbols.write().unwrap().get_or_insert(s)
+    }
+
+    /// Returns the canonical instance of a string, if it has been interned.
+    pub fn get(&self, s: &str) -> Option<&'a str> {
+        self.symbols.read().unwrap().get(s).copied()
+    }
+
+    /// Replaces each string in a constant pool with its canonical instance.
+    pub fn intern_constant_pool(&self, constant_pool: &mut ConstantPool<'a>) {
+        let mut symbols = self.symbols.write().unwrap();
+        for constant in constant_pool.0.iter_mut() {
+            if let ConstantInfo::Utf8(s) = constant {
+                *s = symbols.get_or_insert(s);
+            }
+        }
+    }
+
+    /// The number of distinct symbols.
+    pub fn len(&self) -> usize {
+        self.symbols.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Compares two strings, which are equal without comparing their contents if they're the same
+/// symbol.
+pub fn same(a: &str, b: &str) -> bool {
+    ptr::eq(a, b) || a == b
+}
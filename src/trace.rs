@@ -0,0 +1,262 @@
+//! Traces of the code run by the vm, for debugging the interpreter and the programs it runs.
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::Mutex;
+use std::thread::{self, ThreadId};
+
+use crate::call_frame::{
+    ArrayElementType, JvmValue, LocalVariables, ObjectHeader, Operands, RefTypeHeader,
+};
+use crate::error::{Error, Result};
+use crate::hooks::{Hook, MethodRef};
+use crate::instructions::{ArrayType, Instruction};
+use crate::vm::Vm;
+
+/// Strings longer than this are cut short in traces.
+const MAX_STRING_CHARS: usize = 32;
+
+/// A hook which writes a line for each instruction that runs, with its method, its bytecode offset
+/// and the values on the operand stack before it runs, e.g.
+///
+/// ```text
+/// Example.add(II)I 2: add { data_type: Int } [1, 2]
+/// ```
+pub struct InstructionTracer<W> {
+    out: Mutex<W>,
+}
+
+impl<W: io::Write + Send> InstructionTracer<W> {
+    pub fn new(out: W) -> Self {
+        InstructionTracer {
+            out: Mutex::new(out),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out.into_inner().unwrap()
+    }
+}
+
+impl<'a, W: io::Write + Send> Hook<'a> for InstructionTracer<W> {
+    fn on_instruction(
+        &self,
+        _: &Vm<'a>,
+        method: MethodRef<'a>,
+        index: usize,
+        instruction: &'a Instruction,
+        _: LocalVariables<'_, 'a>,
+        operands: Operands<'_, 'a>,
+    ) {
+        let pc = method.bytecode_offset(index);
+        let operands = operands
+            .iter()
+            .map(|value| describe_value(&value))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        // Tracing shouldn't stop the program, so failed writes are ignored
+        let _ = writeln!(
+            self.out.lock().unwrap(),
+            "{}.{}{} {pc}: {instruction:?} [{operands}]",
+            method.class.name(),
+            method.name(),
+            method.descriptor()
+        );
+    }
+}
+
+/// A hook which writes a line when a method is entered with its arguments, and when it returns
+/// with its result, for the methods which match any of a set of patterns. Calls are indented by
+/// how many matching methods they're nested in on the same thread, e.g.
+///
+/// ```text
+/// -> Example.fib(2)
+///   -> Example.fib(1)
+///   <- Example.fib = 1
+///   -> Example.fib(0)
+///   <- Example.fib = 0
+/// <- Example.fib = 1
+/// ```
+///
+/// Patterns are globs matched against the binary name of the class followed by the method name,
+/// e.g. `com.example.*` or `*.toString`. `*` matches any characters, and `?` matches one.
+pub struct CallTracer<W> {
+    patterns: Vec<String>,
+    state: Mutex<CallTracerState<W>>,
+}
+
+struct CallTracerState<W> {
+    out: W,
+    /// The number of matching methods running on each thread.
+    depths: HashMap<ThreadId, usize>,
+}
+
+impl<W: io::Write + Send> CallTracer<W> {
+    pub fn new(out: W, patterns: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        CallTracer {
+            patterns: patterns.into_iter().map(Into::into).collect(),
+            state: Mutex::new(CallTracerState {
+                out,
+                depths: HashMap::new(),
+            }),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.state.into_inner().unwrap().out
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        self.patterns
+            .iter()
+            .any(|pattern| glob_matches(pattern, name))
+    }
+}
+
+impl<'a, W: io::Write + Send> Hook<'a> for CallTracer<W> {
+    fn on_method_enter(&self, _: &Vm<'a>, method: MethodRef<'a>, args: &[JvmValue<'a>]) {
+        let name = qualified_name(method);
+        if !self.matches(&name) {
+            return;
+        }
+
+        let args = args
+            .iter()
+            .map(describe_value)
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let mut state = self.state.lock().unwrap();
+        let depth = state.depths.entry(thread::current().id()).or_default();
+        let indent = "  ".repeat(*depth);
+        *depth += 1;
+
+        // Tracing shouldn't stop the program, so failed writes are ignored
+        let _ = writeln!(state.out, "{indent}-> {name}({args})");
+    }
+
+    fn on_method_exit(
+        &self,
+        _: &Vm<'a>,
+        method: MethodRef<'a>,
+        result: &Result<Option<JvmValue<'a>>, Error>,
+    ) {
+        let name = qualified_name(method);
+        if !self.matches(&name) {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let depth = state.depths.entry(thread::current().id()).or_default();
+        *depth = depth.saturating_sub(1);
+        let indent = "  ".repeat(*depth);
+
+        let _ = match result {
+            Ok(Some(value)) => writeln!(state.out, "{indent}<- {name} = {}", describe_value(value)),
+            Ok(None) => writeln!(state.out, "{indent}<- {name}"),
+            Err(e) => writeln!(state.out, "{indent}<- {name} threw {}", e.root()),
+        };
+    }
+}
+
+/// Returns the binary name of a method's class followed by the method's name, e.g.
+/// `java.lang.String.length`.
+fn qualified_name(method: MethodRef) -> String {
+    format!(
+        "{}.{}",
+        method.class.name().replace('/', "."),
+        method.name()
+    )
+}
+
+/// Returns whether a string matches a glob, where `*` matches any characters and `?` matches
+/// one character.
+pub(crate) fn glob_matches(pattern: &str, s: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let s = s.chars().collect::<Vec<_>>();
+
+    // The positions to go back to when a mismatch is found after a `*`, which is tried with one
+    // more character each time
+    let (mut p, mut i) = (0, 0);
+    let mut backtrack = None;
+
+    while i < s.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, i));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == s[i] => {
+                p += 1;
+                i += 1;
+            }
+            _ => match backtrack {
+                Some((star, start)) => {
+                    backtrack = Some((star, start + 1));
+                    p = star + 1;
+                    i = start + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Formats a value compactly. Objects are shown by the simple name of their class and their
+/// address, and arrays by their element type and length.
+pub(crate) fn describe_value(value: &JvmValue) -> String {
+    match value {
+        JvmValue::Byte(v) => v.to_string(),
+        JvmValue::Short(v) => v.to_string(),
+        JvmValue::Int(v) => v.to_string(),
+        JvmValue::Long(v) => format!("{v}L"),
+        JvmValue::Char(v) => match char::from_u32(u32::from(*v)) {
+            Some(c) => format!("{c:?}"),
+            None => format!("'\\u{v:04x}'"),
+        },
+        JvmValue::Float(v) => format!("{v:?}f"),
+        JvmValue::Double(v) => format!("{v:?}d"),
+        JvmValue::Boolean(v) => v.to_string(),
+        JvmValue::ReturnAddress(pc) => format!("ret {pc}"),
+        JvmValue::Reference(0) => "null".to_owned(),
+        JvmValue::Reference(reference) => {
+            // SAFETY: Non-null references point to the header of an object or array
+            match unsafe { &*(*reference as *const RefTypeHeader) } {
+                RefTypeHeader::Object(ObjectHeader { class }) => {
+                    // SAFETY: Classes live as long as the vm's arena
+                    let name = unsafe { class.as_ref() }.name();
+                    let simple_name = name.rsplit_once('/').map_or(name, |(_, name)| name);
+                    format!("{simple_name}@{reference:x}")
+                }
+                RefTypeHeader::Array(array) => {
+                    let element_type = match array.element_type {
+                        ArrayElementType::Primitive(t) => primitive_name(t),
+                        ArrayElementType::Reference => "Object",
+                    };
+                    format!("{element_type}[{}]@{reference:x}", array.length)
+                }
+            }
+        }
+        JvmValue::StringConst(s) if s.chars().count() > MAX_STRING_CHARS => {
+            let prefix = s.chars().take(MAX_STRING_CHARS).collect::<String>();
+            format!("{prefix:?}...")
+        }
+        JvmValue::StringConst(s) => format!("{s:?}"),
+    }
+}
+
+pub(crate) fn primitive_name(t: ArrayType) -> &'static str {
+    match t {
+        ArrayType::Boolean => "boolean",
+        ArrayType::Char => "char",
+        ArrayType::Float => "float",
+        ArrayType::Double => "double",
+        ArrayType::Byte => "byte",
+        ArrayType::Short => "short",
+        ArrayType::Int => "int",
+        ArrayType::Long => "long",
+    }
+}
@@ -0,0 +1,915 @@
+//! Checks that the code of each method is type-safe before a class is linked, so that the
+//! interpreter can rely on the operand stack and locals holding the values it expects.
+//!
+//! Types are tracked at the level the interpreter cares about: values narrower than int are all
+//! ints, and references aren't distinguished by class. Where a method has a `StackMapTable`, its
+//! frames are checked against the inferred types at each offset they describe. Elsewhere the
+//! types at branch targets are inferred by merging the types from each branch.
+
+use bumpalo::Bump;
+
+use crate::class::{switch_targets, Class, MethodBody};
+use crate::class_file::constant_pool::{ConstantInfo, ConstantPool};
+use crate::class_file::{CodeAttribute, MethodAccessFlags, StackMapFrame, VerificationTypeInfo};
+use crate::descriptor::{
+    parse_field_descriptor, parse_method_descriptor, BaseType, FieldType, MethodDescriptor,
+};
+use crate::error::{bail, format_err, ContextCompat, Error, Result};
+use crate::instructions::{
+    ArrayLoadStoreType, Instruction, IntegerType, InvokeKind, LoadStoreType, NumberType, ReturnType,
+};
+
+/// Which classes are verified when they're loaded, like `java -Xverify`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Verify {
+    #[default]
+    None,
+    /// Only classes outside of the JDK are verified.
+    Remote,
+    All,
+}
+
+/// Verifies the code of each method in a class, failing with [`Error::Verification`] for the first
+/// method which isn't type-safe.
+pub fn verify_class(class: &Class) -> Result<()> {
+    let class_file = class.class_file();
+    let constant_pool = &class_file.constant_pool;
+    let arena = Bump::new();
+
+    for info in &class_file.methods {
+        let (Some(name), Some(descriptor)) = (
+            constant_pool[info.name_index].try_as_utf_8_ref(),
+            constant_pool[info.descriptor_index].try_as_utf_8_ref(),
+        ) else {
+            continue;
+        };
+
+        let (Some(method), Some(code)) = (
+            class.method(name, descriptor),
+            info.attributes
+                .iter()
+                .find_map(|attr| attr.try_as_code_ref()),
+        ) else {
+            continue;
+        };
+
+        let Some(body) = &method.body else {
+            continue;
+        };
+
+        let verifier = MethodVerifier {
+            constant_pool,
+            body,
+            code,
+            descriptor: &method.descriptor,
+            arena: &arena,
+            is_static: method.access_flags.contains(MethodAccessFlags::STATIC),
+        };
+
+        if let Err(e) = verifier.verify() {
+            return Err(Error::Verification(format!(
+                "{}.{name}{descriptor} at pc {}: {}",
+                class.name().replace('/', "."),
+                e.pc,
+                e.reason.full_message()
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Type {
+    /// An unusable value, like a local which hasn't been set or which has different types on
+    /// different paths. Also used for the second local taken up by a long or double.
+    Top,
+    Int,
+    Float,
+    Long,
+    Double,
+    Reference,
+}
+
+impl Type {
+    fn from_field_type(field_type: &FieldType) -> Type {
+        match field_type {
+            FieldType::Base(
+                BaseType::Boolean
+                | BaseType::Byte
+                | BaseType::Char
+                | BaseType::Short
+                | BaseType::Int,
+            ) => Type::Int,
+            FieldType::Base(BaseType::Long) => Type::Long,
+            FieldType::Base(BaseType::Float) => Type::Float,
+            FieldType::Base(BaseType::Double) => Type::Double,
+            FieldType::Base(BaseType::Object(_)) | FieldType::Array(_, _) => Type::Reference,
+        }
+    }
+
+    /// The number of stack or local slots taken up by the type.
+    fn size(self) -> usize {
+        match self {
+            Type::Long | Type::Double => 2,
+            _ => 1,
+        }
+    }
+}
+
+impl From<&NumberType> for Type {
+    fn from(number_type: &NumberType) -> Type {
+        match number_type {
+            NumberType::Int => Type::Int,
+            NumberType::Long => Type::Long,
+            NumberType::Float => Type::Float,
+            NumberType::Double => Type::Double,
+        }
+    }
+}
+
+impl From<&IntegerType> for Type {
+    fn from(integer_type: &IntegerType) -> Type {
+        match integer_type {
+            IntegerType::Int => Type::Int,
+            IntegerType::Long => Type::Long,
+        }
+    }
+}
+
+impl From<&LoadStoreType> for Type {
+    fn from(load_store_type: &LoadStoreType) -> Type {
+        match load_store_type {
+            LoadStoreType::Int => Type::Int,
+            LoadStoreType::Long => Type::Long,
+            LoadStoreType::Float => Type::Float,
+            LoadStoreType::Double => Type::Double,
+            LoadStoreType::Reference => Type::Reference,
+        }
+    }
+}
+
+impl From<&ArrayLoadStoreType> for Type {
+    fn from(array_type: &ArrayLoadStoreType) -> Type {
+        match array_type {
+            ArrayLoadStoreType::Int
+            | ArrayLoadStoreType::Byte
+            | ArrayLoadStoreType::Char
+            | ArrayLoadStoreType::Short => Type::Int,
+            ArrayLoadStoreType::Long => Type::Long,
+            ArrayLoadStoreType::Float => Type::Float,
+            ArrayLoadStoreType::Double => Type::Double,
+            ArrayLoadStoreType::Reference => Type::Reference,
+        }
+    }
+}
+
+/// The types of the locals and operand stack before an instruction. Longs and doubles are
+/// followed by a `Top` local, but take up a single entry on the stack.
+#[derive(Clone, Debug)]
+struct Frame {
+    locals: Vec<Type>,
+    stack: Vec<Type>,
+}
+
+impl Frame {
+    fn stack_size(&self) -> usize {
+        self.stack.iter().map(|t| t.size()).sum()
+    }
+
+    fn push(&mut self, value: Type) {
+        self.stack.push(value);
+    }
+
+    fn pop(&mut self, expected: Type) -> Result<()> {
+        match self.stack.pop() {
+            Some(value) if value == expected => Ok(()),
+            Some(value) => bail!("expected {expected:?} on the stack, found {value:?}"),
+            None => bail!("expected {expected:?} on the stack, but the stack is empty"),
+        }
+    }
+
+    /// Pops values taking up exactly `size` slots, in the order they were pushed.
+    fn pop_slots(&mut self, size: usize) -> Result<Vec<Type>> {
+        let mut values = vec![];
+        let mut popped = 0;
+        while popped < size {
+            let value = self.stack.pop().wrap_err("stack underflow")?;
+            popped += value.size();
+            values.push(value);
+        }
+
+        if popped != size {
+            bail!("can't split a long or double on the stack");
+        }
+
+        values.reverse();
+        Ok(values)
+    }
+
+    fn load(&self, index: usize, expected: Type) -> Result<()> {
+        let value = self.local(index, expected)?;
+        if value != expected {
+            bail!("expected {expected:?} in local {index}, found {value:?}");
+        }
+        Ok(())
+    }
+
+    fn store(&mut self, index: usize, value: Type) -> Result<()> {
+        self.local(index, value)?;
+
+        // Overwriting the second half of a long or double makes it unusable
+        if index > 0 && self.locals[index - 1].size() == 2 {
+            self.locals[index - 1] = Type::Top;
+        }
+
+        self.locals[index] = value;
+        if value.size() == 2 {
+            self.locals[index + 1] = Type::Top;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the type of a local, checking that a value of type `value` fits in the locals at
+    /// that index.
+    fn local(&self, index: usize, value: Type) -> Result<Type> {
+        if index + value.size() > self.locals.len() {
+            bail!("local {index} is out of bounds");
+        }
+        Ok(self.locals[index])
+    }
+
+    /// Checks that this frame can be used where `declared` is expected.
+    fn is_assignable_to(&self, declared: &Frame) -> bool {
+        let locals_match = self
+            .locals
+            .iter()
+            .zip(&declared.locals)
+            .all(|(actual, declared)| *declared == Type::Top || actual == declared);
+        locals_match && self.stack == declared.stack
+    }
+}
+
+/// A type error found in a method, at the instruction with the given offset.
+struct VerifyError {
+    pc: u32,
+    reason: Error,
+}
+
+struct MethodVerifier<'v, 'a> {
+    constant_pool: &'v ConstantPool<'a>,
+    body: &'v MethodBody<'a>,
+    code: &'v CodeAttribute<'a>,
+    descriptor: &'v MethodDescriptor<'a>,
+    /// Holds the descriptors of the methods that are invoked.
+    arena: &'v Bump,
+    is_static: bool,
+}
+
+/// An exception handler, with the indices of the instructions it covers.
+struct Handler {
+    start: usize,
+    end: usize,
+    handler: usize,
+}
+
+impl MethodVerifier<'_, '_> {
+    fn verify(&self) -> Result<(), VerifyError> {
+        let offsets = &self.body.offsets;
+        let at = |index: usize| {
+            let pc = offsets.get(index).copied().unwrap_or(0);
+            move |reason| VerifyError { pc, reason }
+        };
+
+        if self.body.code.is_empty() {
+            return Err(at(0)(format_err!("method has no instructions")));
+        }
+
+        let initial = self.initial_locals();
+        let declared = self.stack_map_frames(&initial).map_err(at(0))?;
+        let handlers = self.handlers().map_err(at(0))?;
+
+        let mut frames: Vec<Option<Frame>> = vec![None; self.body.code.len()];
+        let mut pending = vec![];
+
+        let mut state = Inference {
+            frames: &mut frames,
+            declared: &declared,
+            pending: &mut pending,
+        };
+
+        let initial = Frame {
+            locals: self.expand_locals(&initial).map_err(at(0))?,
+            stack: vec![],
+        };
+        state.merge(0, initial).map_err(at(0))?;
+
+        while let Some(index) = pending.pop() {
+            let mut frame = frames[index].clone().unwrap();
+
+            let mut state = Inference {
+                frames: &mut frames,
+                declared: &declared,
+                pending: &mut pending,
+            };
+
+            for handler in &handlers {
+                if (handler.start..handler.end).contains(&index) {
+                    let frame = Frame {
+                        locals: frame.locals.clone(),
+                        stack: vec![Type::Reference],
+                    };
+                    state.merge(handler.handler, frame).map_err(at(index))?;
+                }
+            }
+
+            let successors = self.step(index, &mut frame).map_err(at(index))?;
+
+            if frame.stack_size() > self.body.stack_size {
+                return Err(at(index)(format_err!(
+                    "stack size exceeds max_stack of {}",
+                    self.body.stack_size
+                )));
+            }
+
+            for successor in successors {
+                state.merge(successor, frame.clone()).map_err(at(index))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the types of the method's parameters, one entry per parameter as in a
+    /// `StackMapTable`.
+    fn initial_locals(&self) -> Vec<Type> {
+        let mut locals = vec![];
+
+        if !self.is_static {
+            locals.push(Type::Reference);
+        }
+
+        for param in &self.descriptor.params {
+            locals.push(Type::from_field_type(param));
+        }
+
+        locals
+    }
+
+    /// Expands the types of locals to one per slot, filling the rest of the slots with `Top`.
+    fn expand_locals(&self, entries: &[Type]) -> Result<Vec<Type>> {
+        let mut locals = Vec::with_capacity(self.body.locals);
+        for entry in entries {
+            locals.push(*entry);
+            if entry.size() == 2 {
+                locals.push(Type::Top);
+            }
+        }
+
+        if locals.len() > self.body.locals {
+            bail!("locals exceed max_locals of {}", self.body.locals);
+        }
+
+        locals.resize(self.body.locals, Type::Top);
+        Ok(locals)
+    }
+
+    /// Returns the frames declared by the method's `StackMapTable`, by instruction index.
+    fn stack_map_frames(&self, initial: &[Type]) -> Result<Vec<Option<Frame>>> {
+        let mut frames = vec![None; self.body.code.len()];
+
+        let Some(table) = self
+            .code
+            .attributes
+            .iter()
+            .find_map(|attr| attr.try_as_stack_map_table_ref())
+        else {
+            return Ok(frames);
+        };
+
+        let mut locals = initial.to_vec();
+        let mut offset = None;
+
+        for entry in &table.entries {
+            let delta = entry.offset_delta() as u32;
+            let pc = match offset {
+                Some(offset) => offset + delta + 1,
+                None => delta,
+            };
+            offset = Some(pc);
+
+            let stack = match entry {
+                StackMapFrame::Same { .. } | StackMapFrame::SameExtended { .. } => vec![],
+                StackMapFrame::SameLocals1StackItem { stack, .. }
+                | StackMapFrame::SameLocals1StackItemExtended { stack, .. } => {
+                    vec![self.verification_type(stack)?]
+                }
+                StackMapFrame::Chop { k, .. } => {
+                    let len = locals
+                        .len()
+                        .checked_sub(*k as usize)
+                        .wrap_err("stack map frame chops too many locals")?;
+                    locals.truncate(len);
+                    vec![]
+                }
+                StackMapFrame::Append {
+                    locals: appended, ..
+                } => {
+                    for local in appended {
+                        locals.push(self.verification_type(local)?);
+                    }
+                    vec![]
+                }
+                StackMapFrame::Full {
+                    locals: full_locals,
+                    stack,
+                    ..
+                } => {
+                    locals = full_locals
+                        .iter()
+                        .map(|local| self.verification_type(local))
+                        .collect::<Result<_>>()?;
+                    stack
+                        .iter()
+                        .map(|value| self.verification_type(value))
+                        .collect::<Result<_>>()?
+                }
+            };
+
+            let index = self.body.instruction_index(pc).wrap_err_with(|| {
+                format_err!("stack map frame at pc {pc} isn't at an instruction")
+            })?;
+
+            frames[index] = Some(Frame {
+                locals: self.expand_locals(&locals)?,
+                stack,
+            });
+        }
+
+        Ok(frames)
+    }
+
+    fn verification_type(&self, info: &VerificationTypeInfo) -> Result<Type> {
+        Ok(match info {
+            VerificationTypeInfo::Top => Type::Top,
+            VerificationTypeInfo::Integer => Type::Int,
+            VerificationTypeInfo::Float => Type::Float,
+            VerificationTypeInfo::Double => Type::Double,
+            VerificationTypeInfo::Long => Type::Long,
+            VerificationTypeInfo::Object(index) => {
+                self.class(*index)?;
+                Type::Reference
+            }
+            VerificationTypeInfo::Null
+            | VerificationTypeInfo::UninitializedThis
+            | VerificationTypeInfo::Uninitialized(_) => Type::Reference,
+        })
+    }
+
+    fn handlers(&self) -> Result<Vec<Handler>> {
+        let index = |pc: u16| {
+            if pc as usize == self.code.code.len() {
+                return Ok(self.body.code.len());
+            }
+            self.body
+                .instruction_index(pc as u32)
+                .wrap_err_with(|| format_err!("exception handler pc {pc} isn't at an instruction"))
+        };
+
+        self.code
+            .exception_table
+            .iter()
+            .map(|entry| {
+                if entry.catch_type != 0 {
+                    self.class(entry.catch_type)?;
+                }
+
+                let handler = index(entry.handler_pc)?;
+                if handler == self.body.code.len() {
+                    bail!("exception handler pc {} is out of bounds", entry.handler_pc);
+                }
+
+                Ok(Handler {
+                    start: index(entry.start_pc)?,
+                    end: index(entry.end_pc)?,
+                    handler,
+                })
+            })
+            .collect()
+    }
+
+    /// Applies the effect of an instruction to a frame, returning the indices of the
+    /// instructions which can be executed next.
+    fn step(&self, index: usize, frame: &mut Frame) -> Result<Vec<usize>> {
+        let next = index + 1;
+        let branch = |branch: isize| -> Result<usize> {
+            index
+                .checked_add_signed(branch)
+                .filter(|target| *target < self.body.code.len())
+                .wrap_err_with(|| format_err!("invalid branch target: {branch}"))
+        };
+
+        match &self.body.code[index] {
+            Instruction::nop => {}
+            Instruction::aconst_null => frame.push(Type::Reference),
+            Instruction::r#const { data_type, .. } => frame.push(data_type.into()),
+            Instruction::bipush { .. } | Instruction::sipush { .. } => frame.push(Type::Int),
+            Instruction::ldc { index } => {
+                let value = match self.constant(*index)? {
+                    ConstantInfo::Integer(_) => Type::Int,
+                    ConstantInfo::Float(_) => Type::Float,
+                    ConstantInfo::String(_)
+                    | ConstantInfo::Class(_)
+                    | ConstantInfo::MethodType(_)
+                    | ConstantInfo::MethodHandle(_) => Type::Reference,
+                    ConstantInfo::Dynamic(dynamic) => {
+                        self.loadable_dynamic(dynamic.name_and_type_index, 1)?
+                    }
+                    constant => bail!("invalid constant for ldc: {constant:?}"),
+                };
+                frame.push(value);
+            }
+            Instruction::ldc2 { index } => {
+                let value = match self.constant(*index)? {
+                    ConstantInfo::Long(_) => Type::Long,
+                    ConstantInfo::Double(_) => Type::Double,
+                    ConstantInfo::Dynamic(dynamic) => {
+                        self.loadable_dynamic(dynamic.name_and_type_index, 2)?
+                    }
+                    constant => bail!("invalid constant for ldc2_w: {constant:?}"),
+                };
+                frame.push(value);
+            }
+            Instruction::load { data_type, index } => {
+                let value = data_type.into();
+                frame.load(*index as usize, value)?;
+                frame.push(value);
+            }
+            Instruction::arrayload { data_type } => {
+                frame.pop(Type::Int)?;
+                frame.pop(Type::Reference)?;
+                frame.push(data_type.into());
+            }
+            Instruction::store { data_type, index } => {
+                let value = data_type.into();
+                frame.pop(value)?;
+                frame.store(*index as usize, value)?;
+            }
+            Instruction::arraystore { data_type } => {
+                frame.pop(data_type.into())?;
+                frame.pop(Type::Int)?;
+                frame.pop(Type::Reference)?;
+            }
+            Instruction::pop => {
+                frame.pop_slots(1)?;
+            }
+            Instruction::pop2 => {
+                frame.pop_slots(2)?;
+            }
+            Instruction::dup => {
+                let a = frame.pop_slots(1)?;
+                frame.stack.extend(&a);
+                frame.stack.extend(&a);
+            }
+            Instruction::dup_x1 => self.dup_x(frame, 1, 1)?,
+            Instruction::dup_x2 => self.dup_x(frame, 1, 2)?,
+            Instruction::dup2 => {
+                let a = frame.pop_slots(2)?;
+                frame.stack.extend(&a);
+                frame.stack.extend(&a);
+            }
+            Instruction::dup2_x1 => self.dup_x(frame, 2, 1)?,
+            Instruction::dup2_x2 => self.dup_x(frame, 2, 2)?,
+            Instruction::swap => {
+                let a = frame.pop_slots(1)?;
+                let b = frame.pop_slots(1)?;
+                frame.stack.extend(a);
+                frame.stack.extend(b);
+            }
+            Instruction::add { data_type }
+            | Instruction::sub { data_type }
+            | Instruction::mul { data_type }
+            | Instruction::div { data_type }
+            | Instruction::rem { data_type } => {
+                let value = data_type.into();
+                frame.pop(value)?;
+                frame.pop(value)?;
+                frame.push(value);
+            }
+            Instruction::neg { data_type } => {
+                let value = data_type.into();
+                frame.pop(value)?;
+                frame.push(value);
+            }
+            Instruction::shl { data_type }
+            | Instruction::shr { data_type }
+            | Instruction::ushr { data_type } => {
+                let value = data_type.into();
+                frame.pop(Type::Int)?;
+                frame.pop(value)?;
+                frame.push(value);
+            }
+            Instruction::and { data_type }
+            | Instruction::or { data_type }
+            | Instruction::xor { data_type } => {
+                let value = data_type.into();
+                frame.pop(value)?;
+                frame.pop(value)?;
+                frame.push(value);
+            }
+            Instruction::inc { index, .. } => frame.load(*index as usize, Type::Int)?,
+            Instruction::i2l => convert(frame, Type::Int, Type::Long)?,
+            Instruction::i2f => convert(frame, Type::Int, Type::Float)?,
+            Instruction::i2d => convert(frame, Type::Int, Type::Double)?,
+            Instruction::l2i => convert(frame, Type::Long, Type::Int)?,
+            Instruction::l2f => convert(frame, Type::Long, Type::Float)?,
+            Instruction::l2d => convert(frame, Type::Long, Type::Double)?,
+            Instruction::f2i => convert(frame, Type::Float, Type::Int)?,
+            Instruction::f2l => convert(frame, Type::Float, Type::Long)?,
+            Instruction::f2d => convert(frame, Type::Float, Type::Double)?,
+            Instruction::d2i => convert(frame, Type::Double, Type::Int)?,
+            Instruction::d2l => convert(frame, Type::Double, Type::Long)?,
+            Instruction::d2f => convert(frame, Type::Double, Type::Float)?,
+            Instruction::i2b | Instruction::i2c | Instruction::i2s => {
+                convert(frame, Type::Int, Type::Int)?
+            }
+            Instruction::lcmp => compare(frame, Type::Long)?,
+            Instruction::fcmp { .. } => compare(frame, Type::Float)?,
+            Instruction::dcmp { .. } => compare(frame, Type::Double)?,
+            Instruction::r#if { branch: offset, .. } => {
+                frame.pop(Type::Int)?;
+                return Ok(vec![next, branch(*offset as isize)?]);
+            }
+            Instruction::if_icmp { branch: offset, .. } => {
+                frame.pop(Type::Int)?;
+                frame.pop(Type::Int)?;
+                return Ok(vec![next, branch(*offset as isize)?]);
+            }
+            Instruction::if_acmp { branch: offset, .. } => {
+                frame.pop(Type::Reference)?;
+                frame.pop(Type::Reference)?;
+                return Ok(vec![next, branch(*offset as isize)?]);
+            }
+            Instruction::ifnull { branch: offset } | Instruction::ifnonnull { branch: offset } => {
+                frame.pop(Type::Reference)?;
+                return Ok(vec![next, branch(*offset as isize)?]);
+            }
+            Instruction::getstatic { index } => frame.push(self.field(*index)?),
+            Instruction::putstatic { index } => frame.pop(self.field(*index)?)?,
+            Instruction::getfield { index } => {
+                let value = self.field(*index)?;
+                frame.pop(Type::Reference)?;
+                frame.push(value);
+            }
+            Instruction::putfield { index } => {
+                frame.pop(self.field(*index)?)?;
+                frame.pop(Type::Reference)?;
+            }
+            Instruction::invoke { kind, index } => {
+                let descriptor = self.method(*kind, *index)?;
+                for param in descriptor.params.iter().rev() {
+                    frame.pop(Type::from_field_type(param))?;
+                }
+                if !matches!(kind, InvokeKind::Static | InvokeKind::Dynamic) {
+                    frame.pop(Type::Reference)?;
+                }
+                if let Some(return_type) = &descriptor.return_type {
+                    frame.push(Type::from_field_type(return_type));
+                }
+            }
+            Instruction::new { index } => {
+                self.class(*index)?;
+                frame.push(Type::Reference);
+            }
+            Instruction::newarray { .. } => {
+                frame.pop(Type::Int)?;
+                frame.push(Type::Reference);
+            }
+            Instruction::anewarray { index } => {
+                self.class(*index)?;
+                frame.pop(Type::Int)?;
+                frame.push(Type::Reference);
+            }
+            Instruction::multianewarray { index, dimensions } => {
+                self.class(*index)?;
+                if *dimensions == 0 {
+                    bail!("multianewarray must have at least 1 dimension");
+                }
+                for _ in 0..*dimensions {
+                    frame.pop(Type::Int)?;
+                }
+                frame.push(Type::Reference);
+            }
+            Instruction::arraylength => {
+                frame.pop(Type::Reference)?;
+                frame.push(Type::Int);
+            }
+            Instruction::athrow => {
+                frame.pop(Type::Reference)?;
+                return Ok(vec![]);
+            }
+            Instruction::checkcast { index } => {
+                self.class(*index)?;
+                frame.pop(Type::Reference)?;
+                frame.push(Type::Reference);
+            }
+            Instruction::instanceof { index } => {
+                self.class(*index)?;
+                frame.pop(Type::Reference)?;
+                frame.push(Type::Int);
+            }
+            Instruction::monitorenter | Instruction::monitorexit => frame.pop(Type::Reference)?,
+            Instruction::goto { branch: offset } => return Ok(vec![branch(*offset as isize)?]),
+            Instruction::jsr { .. } | Instruction::ret { .. } => {
+                bail!("jsr and ret aren't supported")
+            }
+            Instruction::tableswitch {} | Instruction::lookupswitch {} => {
+                frame.pop(Type::Int)?;
+                return switch_targets(self.code.code, self.body.offsets[index])?
+                    .into_iter()
+                    .map(|pc| {
+                        self.body
+                            .instruction_index(pc)
+                            .wrap_err_with(|| format_err!("invalid switch target: {pc}"))
+                    })
+                    .collect();
+            }
+            Instruction::r#return { data_type } => {
+                let expected = self
+                    .descriptor
+                    .return_type
+                    .as_ref()
+                    .map(Type::from_field_type);
+                let actual = match data_type {
+                    ReturnType::Void => None,
+                    ReturnType::Int => Some(Type::Int),
+                    ReturnType::Long => Some(Type::Long),
+                    ReturnType::Float => Some(Type::Float),
+                    ReturnType::Double => Some(Type::Double),
+                    ReturnType::Reference => Some(Type::Reference),
+                };
+                if actual != expected {
+                    bail!("wrong return instruction for the method's return type");
+                }
+                if let Some(value) = actual {
+                    frame.pop(value)?;
+                }
+                return Ok(vec![]);
+            }
+            Instruction::breakpoint
+            | Instruction::impdep1
+            | Instruction::impdep2
+            | Instruction::unknown { .. } => bail!("invalid instruction"),
+        }
+
+        if next == self.body.code.len() {
+            bail!("execution falls off the end of the code");
+        }
+
+        Ok(vec![next])
+    }
+
+    /// Duplicates the values taking up the top `size` slots, inserting them below the values
+    /// taking up the next `depth` slots.
+    fn dup_x(&self, frame: &mut Frame, size: usize, depth: usize) -> Result<()> {
+        let a = frame.pop_slots(size)?;
+        let b = frame.pop_slots(depth)?;
+        frame.stack.extend(&a);
+        frame.stack.extend(b);
+        frame.stack.extend(a);
+        Ok(())
+    }
+
+    fn constant(&self, index: u16) -> Result<&ConstantInfo> {
+        self.constant_pool
+            .get(index)
+            .wrap_err_with(|| format_err!("invalid constant pool index: {index}"))
+    }
+
+    fn class(&self, index: u16) -> Result<()> {
+        match self.constant(index)? {
+            ConstantInfo::Class(_) => Ok(()),
+            constant => bail!("expected a class constant, found {constant:?}"),
+        }
+    }
+
+    fn descriptor_of(&self, name_and_type_index: u16) -> Result<&str> {
+        let name_and_type = self
+            .constant(name_and_type_index)?
+            .try_as_name_and_type_ref()
+            .wrap_err("expected a name and type constant")?;
+        self.constant(name_and_type.descriptor_index)?
+            .try_as_utf_8_ref()
+            .copied()
+            .wrap_err("expected a utf8 constant")
+    }
+
+    fn field(&self, index: u16) -> Result<Type> {
+        let field_ref = match self.constant(index)? {
+            ConstantInfo::FieldRef(field_ref) => field_ref,
+            constant => bail!("expected a field constant, found {constant:?}"),
+        };
+        let descriptor =
+            parse_field_descriptor(self.descriptor_of(field_ref.name_and_type_index)?)?;
+        Ok(Type::from_field_type(&descriptor.field_type))
+    }
+
+    fn method(&self, kind: InvokeKind, index: u16) -> Result<MethodDescriptor> {
+        let name_and_type_index = match (kind, self.constant(index)?) {
+            (
+                InvokeKind::Virtual | InvokeKind::Special | InvokeKind::Static,
+                ConstantInfo::MethodRef(method_ref),
+            )
+            | (
+                InvokeKind::Special | InvokeKind::Static | InvokeKind::Interface { .. },
+                ConstantInfo::InterfaceMethodRef(method_ref),
+            ) => method_ref.name_and_type_index,
+            (InvokeKind::Dynamic, ConstantInfo::InvokeDynamic(invoke_dynamic)) => {
+                invoke_dynamic.name_and_type_index
+            }
+            (_, constant) => bail!("invalid constant for {kind:?} invoke: {constant:?}"),
+        };
+        parse_method_descriptor(self.arena, self.descriptor_of(name_and_type_index)?)
+    }
+
+    /// Returns the type of a dynamically-computed constant, checking that it takes up `size`
+    /// slots.
+    fn loadable_dynamic(&self, name_and_type_index: u16, size: usize) -> Result<Type> {
+        let descriptor = parse_field_descriptor(self.descriptor_of(name_and_type_index)?)?;
+        let value = Type::from_field_type(&descriptor.field_type);
+        if value.size() != size {
+            bail!("wrong ldc instruction for a constant of type {value:?}");
+        }
+        Ok(value)
+    }
+}
+
+fn convert(frame: &mut Frame, from: Type, to: Type) -> Result<()> {
+    frame.pop(from)?;
+    frame.push(to);
+    Ok(())
+}
+
+fn compare(frame: &mut Frame, operand: Type) -> Result<()> {
+    frame.pop(operand)?;
+    frame.pop(operand)?;
+    frame.push(Type::Int);
+    Ok(())
+}
+
+/// The inferred frames of a method, and the instructions whose frames have changed since they
+/// were last checked.
+struct Inference<'s> {
+    frames: &'s mut [Option<Frame>],
+    declared: &'s [Option<Frame>],
+    pending: &'s mut Vec<usize>,
+}
+
+impl Inference<'_> {
+    /// Merges the frame at an instruction with a frame which can flow into it.
+    fn merge(&mut self, index: usize, incoming: Frame) -> Result<()> {
+        // Frames from the stack map are used as they are, as long as the incoming frame matches
+        if let Some(declared) = &self.declared[index] {
+            if !incoming.is_assignable_to(declared) {
+                bail!(
+                    "frame doesn't match the stack map frame at instruction {index}: \
+                     {incoming:?} vs {declared:?}"
+                );
+            }
+            if self.frames[index].is_none() {
+                self.frames[index] = Some(declared.clone());
+                self.pending.push(index);
+            }
+            return Ok(());
+        }
+
+        let Some(frame) = &mut self.frames[index] else {
+            self.frames[index] = Some(incoming);
+            self.pending.push(index);
+            return Ok(());
+        };
+
+        if frame.stack != incoming.stack {
+            bail!(
+                "inconsistent stack at instruction {index}: {:?} vs {:?}",
+                frame.stack,
+                incoming.stack
+            );
+        }
+
+        let mut changed = false;
+        for (local, incoming) in frame.locals.iter_mut().zip(incoming.locals) {
+            if *local != incoming && *local != Type::Top {
+                *local = Type::Top;
+                changed = true;
+            }
+        }
+
+        if changed {
+            self.pending.push(index);
+        }
+
+        Ok(())
+    }
+}
@@ -1,20 +1,58 @@
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::{self, BufReader, Cursor};
+use std::alloc::Layout;
+use std::borrow::Cow;
+use std::cell::{OnceCell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
 use std::iter;
-use std::path::Path;
-use std::time::SystemTime;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::ptr::{self, NonNull};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock, PoisonError, RwLock};
+use std::time::{Duration, Instant, SystemTime};
 
 use bumpalo::Bump;
-use color_eyre::eyre::{self, eyre, Context};
+use hashbrown::Equivalent;
 
-use crate::call_frame::CallFrame;
-use crate::class::{Class, Method};
-use crate::class_file::MethodAccessFlags;
+use crate::budget::{Budget, BudgetExceeded, BudgetUsage};
+use crate::call_frame::{
+    ArrayElementType, ArrayHeader, CallFrame, JavaException, JvmValue, ObjectHeader, RefTypeHeader,
+    DEFAULT_STACK_SIZE,
+};
+use crate::class::{Class, Itable, ItableEntry, Method};
+use crate::class_archive::{ArchivedClass, ClassArchive, DecodedCode, JImageStamp};
+use crate::class_file::{ClassAccessFlags, ClassFile, MethodAccessFlags};
+use crate::class_path::ClassPath;
+use crate::convert::{self, Args, FromJvm, NativeFn, ToJvm};
+use crate::descriptor::{BaseType, FieldType};
+use crate::error::{bail, format_err, Context, ContextCompat, Error, Result};
+use crate::hooks::{Allocation, AllocationKind, Hook, MethodRef};
+use crate::hprof;
+use crate::instructions::ArrayType;
+use crate::jimage::{self, JImage};
+#[cfg(feature = "jit")]
+use crate::jit::{self, Jit};
+use crate::natives::{self, NativeMethod};
+use crate::object::{Array, Object};
 use crate::reader::ClassReader;
+use crate::shims;
+use crate::snapshot::{self, Snapshot, SnapshotBuilder, SnapshotObject};
+use crate::superinstructions;
+use crate::symbols::{self, SymbolTable};
+use crate::verifier::{self, Verify};
 
 pub trait TimeProvider {
     fn system_time(&self) -> SystemTime;
+
+    /// Returns the time elapsed since some fixed but arbitrary origin. Used to implement
+    /// `System.nanoTime`.
+    fn monotonic_time(&self) -> Duration {
+        static ORIGIN: OnceLock<Instant> = OnceLock::new();
+        ORIGIN.get_or_init(Instant::now).elapsed()
+    }
 }
 
 struct DefaultTimeProvider;
@@ -25,90 +63,2707 @@ impl TimeProvider for DefaultTimeProvider {
     }
 }
 
+/// Raised by `System.exit` to unwind the interpreter. Java code can't catch this.
+#[derive(Debug)]
+pub struct SystemExit {
+    pub status: i32,
+}
+
+impl fmt::Display for SystemExit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "exited with status {}", self.status)
+    }
+}
+
+impl std::error::Error for SystemExit {}
+
+/// What Java code wrote to `System.out` and `System.err`, as returned by
+/// [`Vm::capture_output`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Output {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+/// A buffer which is written to by the vm while output is being captured, and read afterwards.
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    fn take(&self) -> Vec<u8> {
+        mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+impl io::Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Flushes a writer of the vm and then replaces it, returning the previous writer. The writer is
+/// left in place if flushing it fails.
+fn replace_writer<'a>(
+    writer: &Mutex<Box<dyn io::Write + Send + 'a>>,
+    new: Box<dyn io::Write + Send + 'a>,
+) -> Result<Box<dyn io::Write + Send + 'a>> {
+    let mut writer = writer.lock().unwrap();
+    writer.flush()?;
+    Ok(mem::replace(&mut *writer, new))
+}
+
+/// Restores the vm's writers when it's dropped at the end of [`Vm::capture_output`], including
+/// when the function capturing the output panics.
+struct RestoreWriters<'v, 'a> {
+    vm: &'v Vm<'a>,
+    stdout: Option<Box<dyn io::Write + Send + 'a>>,
+    stderr: Option<Box<dyn io::Write + Send + 'a>>,
+}
+
+impl Drop for RestoreWriters<'_, '_> {
+    fn drop(&mut self) {
+        // The locks are poisoned if the panic happened while writing
+        if let Some(stdout) = self.stdout.take() {
+            *self
+                .vm
+                .stdout
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner) = stdout;
+        }
+        if let Some(stderr) = self.stderr.take() {
+            *self
+                .vm
+                .stderr
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner) = stderr;
+        }
+    }
+}
+
+/// A handle to a vm which can be sent to other host threads, to interrupt the Java code running
+/// in it. Created by [`Vm::handle`].
+#[derive(Clone)]
+pub struct VmHandle {
+    signals: Arc<Signals>,
+}
+
+impl VmHandle {
+    /// Interrupts the vm's thread, as if by `Thread.interrupt`. If the thread is sleeping, it
+    /// wakes and `Thread.sleep` throws `InterruptedException`, and otherwise its next call to
+    /// `Thread.sleep` throws.
+    pub fn interrupt(&self) {
+        self.signals.interrupt();
+    }
+
+    /// Stops the Java code running in the vm, e.g. once it has run for too long. The code fails
+    /// with [`Error::Stopped`] the next time it calls a method or jumps back in a loop, or
+    /// straight away if it's sleeping. Compiled code only stops once it calls a method. The vm
+    /// can't run Java code again after it's stopped.
+    pub fn stop(&self) {
+        self.signals.stop();
+    }
+}
+
+/// Signals sent to a vm by other host threads.
+#[derive(Default)]
+struct Signals {
+    interrupted: Mutex<bool>,
+    /// Notified when the vm's thread is interrupted or stopped, to wake it from `Thread.sleep`.
+    wake: Condvar,
+    stopped: AtomicBool,
+}
+
+impl Signals {
+    fn interrupt(&self) {
+        *self.interrupted.lock().unwrap() = true;
+        self.wake.notify_all();
+    }
+
+    fn stop(&self) {
+        // Set while holding the lock, so that a thread which is about to sleep sees it
+        let _interrupted = self.interrupted.lock().unwrap();
+        self.stopped.store(true, Ordering::Relaxed);
+        self.wake.notify_all();
+    }
+}
+
+/// The `coder` of a `String` whose characters are stored as Latin-1 bytes.
+const LATIN1: i32 = 0;
+/// The `coder` of a `String` whose characters are stored as UTF-16 code units.
+const UTF16: i32 = 1;
+
+/// `String` objects which have been created for strings, and the contents of `String` objects.
+#[derive(Default)]
+struct StringObjects<'a> {
+    /// Keyed by the address and length of each string.
+    objects: HashMap<(usize, usize), usize>,
+    contents: HashMap<usize, &'a str>,
+    /// The UTF-16 code units of strings which Java code has indexed, keyed like `objects`.
+    utf16: HashMap<(usize, usize), &'a [u16]>,
+}
+
+/// Encodes a string like the JDK's compact strings, returning its coder and bytes. Strings are
+/// stored as Latin-1 if they can be, and otherwise as UTF-16 in the native byte order.
+fn compact_string(s: &str) -> (i32, Vec<u8>) {
+    if s.chars().all(|c| u32::from(c) <= 0xFF) {
+        (LATIN1, s.chars().map(|c| c as u8).collect())
+    } else {
+        (UTF16, s.encode_utf16().flat_map(u16::to_ne_bytes).collect())
+    }
+}
+
+/// Returns whether a reference is to a `String` object, rather than a string represented by the
+/// vm.
+fn is_string_object(object: usize) -> bool {
+    object != 0
+        && matches!(
+            unsafe { &*(object as *const RefTypeHeader) },
+            RefTypeHeader::Object(ObjectHeader { class })
+                if unsafe { class.as_ref() }.name() == "java/lang/String"
+        )
+}
+
+/// A virtual machine instance.
+///
+/// A vm can only be used by the host thread which created it, since its arena is borrowed from
+/// the caller and objects on its heap are read and written without synchronization. Programs
+/// which run Java code on several host threads create a vm for each of them.
 pub struct Vm<'a> {
+    /// Class metadata, which is shared by all class loaders and lives as long as the vm. Classes
+    /// can't be unloaded yet, since objects are never collected, so there is no way to tell when
+    /// a loader and its classes become unreachable.
     arena: &'a Bump,
-    classes: HashMap<&'a str, &'a Class<'a>>,
-    pub(crate) stdout: &'a mut dyn io::Write,
-    pub(crate) heap: Bump,
-    pub(crate) time: Box<dyn TimeProvider>,
-    system_jvm: Option<jdk_tools::Jvm>,
+    classes: RefCell<HashMap<&'a str, &'a Class<'a>>>,
+    /// Every loaded class and its class file, in the order the classes were loaded, for
+    /// snapshots.
+    class_files: Mutex<Vec<(&'a Class<'a>, &'a [u8])>>,
+    /// Classes that are currently being loaded, in the order that loading started.
+    loading: RefCell<Vec<String>>,
+    /// Initialization state of classes which have started initialization.
+    initialization: RefCell<HashMap<&'a str, Initialization>>,
+    stdin: Mutex<Box<dyn io::Read + Send + 'a>>,
+    stdout: Mutex<Box<dyn io::Write + Send + 'a>>,
+    stderr: Mutex<Box<dyn io::Write + Send + 'a>>,
+    heap: Mutex<Bump>,
+    pub(crate) time: Box<dyn TimeProvider + Send + Sync>,
+    /// The JDK that core classes are loaded from, if one was found.
+    java_home: Option<PathBuf>,
+    /// Opened when the first class is read from it, and unmapped when the vm is dropped.
+    jimage: OnceCell<JImage>,
+    class_archive: Option<ClassArchive>,
+    /// Whether the class archive was created from the same JDK, which is checked when it's first
+    /// used.
+    class_archive_valid: OnceCell<bool>,
+    /// State shared with [`VmHandle`]s, which other host threads use to signal the vm.
+    signals: Arc<Signals>,
+    natives: RefCell<hashbrown::HashMap<NativeId, Rc<NativeMethod<'a>>>>,
+    class_mirrors: Mutex<ClassMirrors<'a>>,
+    threads: RefCell<Threads>,
+    properties: RwLock<HashMap<String, String>>,
+    /// `java.lang.Thread` instances registered with `Runtime.addShutdownHook`.
+    shutdown_hooks: Mutex<Vec<usize>>,
+    /// Contents of `java.lang.StringBuilder` instances, used by the StringBuilder intrinsic.
+    string_builders: Mutex<HashMap<usize, String>>,
+    /// `String` objects and the strings they contain, for strings which have been converted
+    /// between the vm's representation and objects.
+    string_objects: Mutex<StringObjects<'a>>,
+    /// Input which `java.util.Scanner` instances have read but not consumed yet.
+    scanner_input: Mutex<HashMap<usize, String>>,
+    /// The names and descriptors of all classes that have been read.
+    symbols: SymbolTable<'a>,
+    /// Canonical instances of strings, shared by string literals and `String.intern`.
+    interned: Mutex<HashSet<&'a str>>,
+    /// Boxed primitives returned by the `valueOf` methods of the wrapper classes, keyed by the
+    /// wrapper class name and the primitive value.
+    boxes: Mutex<HashMap<(&'static str, i64), usize>>,
+    /// The platform and application class loaders, which are created when first needed.
+    builtin_loaders: Mutex<Option<BuiltinLoaders>>,
+    /// Loaders which defined classes, other than the bootstrap loader. Classes loaded from the
+    /// class path are defined by the application class loader.
+    defining_loaders: RwLock<HashMap<&'a str, usize>>,
+    class_path: ClassPath,
+    /// Whether assertions are enabled in classes outside of the JDK, like `java -ea`.
+    assertions: bool,
+    /// Whether to log each class that is loaded, like `java -verbose:class`.
+    verbose_class: bool,
+    /// Which classes are verified when they're loaded, like `java -Xverify`.
+    verify: Verify,
+    /// The size in bytes of each thread's frame stack, like `java -Xss`.
+    stack_size: usize,
+    /// The hotness after which a method's instructions are fused into superinstructions.
+    superinstruction_threshold: u64,
+    /// The number of calls to a method after which it's compiled.
+    #[cfg(feature = "jit")]
+    jit_threshold: u64,
+    /// Whether hot methods are compiled to native code, unlike `java -Xint`.
+    #[cfg(feature = "jit")]
+    jit_enabled: bool,
+    /// Hooks which are called as Java code runs. While there are any, every method is
+    /// interpreted.
+    hooks: Vec<Box<dyn Hook<'a> + 'a>>,
+    /// Limits on the work done by Java code, if any. Like hooks, a budget causes every method to
+    /// be interpreted, so that each instruction can be counted.
+    budget: Option<BudgetUsage>,
+    /// The compiler, which is created when a method first becomes hot. This is `None` if the host
+    /// isn't supported.
+    #[cfg(feature = "jit")]
+    jit: OnceLock<Option<Jit>>,
+}
+
+/// The `java.lang.ClassLoader` instances created by the vm. The bootstrap loader is represented
+/// by null, as in Java.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct BuiltinLoaders {
+    pub platform: usize,
+    pub app: usize,
+}
+
+#[derive(PartialEq, Eq, Hash)]
+struct NativeId {
+    class: String,
+    name: String,
+    descriptor: String,
+}
+
+/// Borrowed form of [`NativeId`], which hashes identically so it can be used for lookups.
+#[derive(Hash)]
+struct NativeIdRef<'b> {
+    class: &'b str,
+    name: &'b str,
+    descriptor: &'b str,
+}
+
+impl Equivalent<NativeId> for NativeIdRef<'_> {
+    fn equivalent(&self, key: &NativeId) -> bool {
+        self.class == key.class && self.name == key.name && self.descriptor == key.descriptor
+    }
+}
+
+enum Initialization {
+    InProgress,
+    Done,
+}
+
+/// `java.lang.Thread` instances created by the vm.
+#[derive(Default)]
+struct Threads {
+    /// The instance for the host thread, once it has called into the vm.
+    current: Option<usize>,
+    next_tid: i64,
+    /// Used to name threads which are created without a name.
+    next_number: usize,
+}
+
+/// `java.lang.Class` instances, which are keyed by the name that `Class.getName` would return
+/// but in internal form, e.g. `java/lang/String`, `[I` or `int`.
+#[derive(Default)]
+struct ClassMirrors<'a> {
+    by_name: HashMap<&'a str, usize>,
+    names: HashMap<usize, &'a str>,
 }
 
 impl<'a> Vm<'a> {
-    pub fn new(arena: &'a Bump, stdout: &'a mut dyn io::Write) -> Vm<'a> {
-        Vm {
+    /// Creates a vm which allocates class metadata in `arena`, and writes `System.out` to
+    /// `stdout`. The vm takes ownership of the writer, which can be a borrowed one as long as it
+    /// outlives the vm.
+    pub fn new(arena: &'a Bump, stdout: impl io::Write + Send + 'a) -> Vm<'a> {
+        let vm = Vm {
             arena,
-            classes: HashMap::new(),
-            stdout,
-            heap: Bump::new(),
+            classes: RefCell::new(HashMap::new()),
+            class_files: Mutex::new(Vec::new()),
+            loading: RefCell::new(Vec::new()),
+            initialization: RefCell::new(HashMap::new()),
+            stdin: Mutex::new(Box::new(io::stdin())),
+            stdout: Mutex::new(Box::new(stdout)),
+            stderr: Mutex::new(Box::new(io::stderr())),
+            heap: Mutex::new(Bump::new()),
             time: Box::new(DefaultTimeProvider),
-            system_jvm: None,
-        }
+            java_home: jimage::find_java_home(),
+            jimage: OnceCell::new(),
+            class_archive: None,
+            class_archive_valid: OnceCell::new(),
+            signals: Arc::default(),
+            natives: RefCell::new(hashbrown::HashMap::new()),
+            class_mirrors: Mutex::new(ClassMirrors::default()),
+            threads: RefCell::new(Threads::default()),
+            properties: RwLock::new(default_properties()),
+            shutdown_hooks: Mutex::new(Vec::new()),
+            string_builders: Mutex::new(HashMap::new()),
+            string_objects: Mutex::default(),
+            scanner_input: Mutex::new(HashMap::new()),
+            boxes: Mutex::new(HashMap::new()),
+            symbols: SymbolTable::default(),
+            interned: Mutex::new(HashSet::new()),
+            builtin_loaders: Mutex::new(None),
+            defining_loaders: RwLock::new(HashMap::new()),
+            class_path: ClassPath::default(),
+            assertions: false,
+            verbose_class: false,
+            verify: Verify::None,
+            stack_size: DEFAULT_STACK_SIZE,
+            superinstruction_threshold: superinstructions::DEFAULT_FUSE_THRESHOLD,
+            #[cfg(feature = "jit")]
+            jit_threshold: jit::DEFAULT_COMPILE_THRESHOLD,
+            #[cfg(feature = "jit")]
+            jit_enabled: true,
+            hooks: Vec::new(),
+            budget: None,
+            #[cfg(feature = "jit")]
+            jit: OnceLock::new(),
+        };
+
+        // The built-in natives' descriptors are fixed, so this only fails if one of them is wrong
+        natives::register_builtins(&vm).expect("invalid built-in native");
+
+        vm
     }
 
-    pub fn with_time_provider(mut self, time_provider: Box<dyn TimeProvider>) -> Self {
+    pub fn with_time_provider(
+        mut self,
+        time_provider: Box<dyn TimeProvider + Send + Sync>,
+    ) -> Self {
         self.time = time_provider;
         self
     }
 
-    pub fn load_class_file(&mut self, name: &str) -> eyre::Result<&'a Class<'a>> {
+    /// Sets the JDK that core classes are loaded from, which is found from `JAVA_HOME` or the
+    /// `java` executable by default. If there is no JDK, the vm's built-in shims are used instead.
+    pub fn with_java_home(mut self, java_home: impl Into<PathBuf>) -> Self {
+        self.java_home = Some(java_home.into());
+        self
+    }
+
+    /// Sets an archive to load JDK classes from before looking in the JDK itself, which is
+    /// ignored if it was created from a different JDK. See [`Vm::write_class_archive`].
+    pub fn with_class_archive(mut self, archive: ClassArchive) -> Self {
+        self.class_archive = Some(archive);
+        self
+    }
+
+    /// Sets the directories searched for classes outside of the JDK, which is the current
+    /// directory by default.
+    pub fn with_class_path(mut self, class_path: ClassPath) -> Self {
+        self.set_property("java.class.path", class_path.to_string());
+        self.class_path = class_path;
+        self
+    }
+
+    /// Enables or disables assertions in classes outside of the JDK, which are disabled by
+    /// default.
+    pub fn with_assertions(mut self, enabled: bool) -> Self {
+        self.assertions = enabled;
+        self
+    }
+
+    /// Enables logging of each class as it's loaded, with the location that it was loaded from.
+    pub fn with_verbose_class(mut self, enabled: bool) -> Self {
+        self.verbose_class = enabled;
+        self
+    }
+
+    /// Sets which classes have their code verified when they're loaded, like `java -Xverify`.
+    /// Classes which fail verification can't be loaded, and fail with [`Error::Verification`]
+    /// instead.
+    pub fn with_verify(mut self, verify: Verify) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Limits the size of the heap, after which allocations throw `OutOfMemoryError`. The heap
+    /// is unlimited by default.
+    pub fn with_max_heap_size(self, bytes: usize) -> Self {
+        self.heap.lock().unwrap().set_allocation_limit(Some(bytes));
+        self
+    }
+
+    /// Sets the size of the memory used for the locals and operand stacks of each thread's
+    /// frames, after which calls throw `StackOverflowError`. The default is 1 MiB.
+    pub fn with_stack_size(mut self, bytes: usize) -> Self {
+        self.stack_size = bytes;
+        self
+    }
+
+    /// Enables or disables compiling frequently called methods to native code, which is enabled
+    /// by default. When disabled, every method is interpreted.
+    #[cfg(feature = "jit")]
+    pub fn with_jit(mut self, enabled: bool) -> Self {
+        self.jit_enabled = enabled;
+        self
+    }
+
+    /// Sets how many times a method is called before it's compiled to native code. The default is
+    /// [`DEFAULT_COMPILE_THRESHOLD`](jit::DEFAULT_COMPILE_THRESHOLD).
+    #[cfg(feature = "jit")]
+    pub fn with_jit_threshold(mut self, invocations: u64) -> Self {
+        self.jit_threshold = invocations;
+        self
+    }
+
+    /// Sets how hot a method gets, counting its invocations and the iterations of its loops,
+    /// before sequences of its instructions are fused into superinstructions. The default is
+    /// [`DEFAULT_FUSE_THRESHOLD`](superinstructions::DEFAULT_FUSE_THRESHOLD), and 0 fuses them
+    /// before a method first runs.
+    pub fn with_superinstruction_threshold(mut self, hotness: u64) -> Self {
+        self.superinstruction_threshold = hotness;
+        self
+    }
+
+    /// Installs a hook, which is called as Java code runs. Instrumented code runs slower, since
+    /// every method is interpreted without superinstructions.
+    pub fn with_hook(mut self, hook: impl Hook<'a> + 'a) -> Self {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+
+    /// Limits the work that Java code can do, after which execution is aborted with a
+    /// [`BudgetExceeded`] error. Code runs slower with a budget, since every method is
+    /// interpreted so that its instructions can be counted.
+    pub fn with_budget(mut self, budget: Budget) -> Self {
+        self.budget = Some(BudgetUsage::new(budget));
+        self
+    }
+
+    /// Forgets the work counted against the budget so far, e.g. before each call into Java code
+    /// from a host application which reuses the vm.
+    pub fn reset_budget(&self) {
+        if let Some(budget) = &self.budget {
+            budget.reset();
+        }
+    }
+
+    /// Sets the reader used for `System.in`, which is the process stdin by default.
+    pub fn with_stdin(self, stdin: impl io::Read + Send + 'a) -> Self {
+        *self.stdin.lock().unwrap() = Box::new(stdin);
+        self
+    }
+
+    /// Sets the writer used for `System.err`, which is the process stderr by default.
+    pub fn with_stderr(self, stderr: impl io::Write + Send + 'a) -> Self {
+        *self.stderr.lock().unwrap() = Box::new(stderr);
+        self
+    }
+
+    /// Replaces the writer used for `System.out` while the vm is running, returning the previous
+    /// writer.
+    pub fn set_stdout(
+        &self,
+        stdout: impl io::Write + Send + 'a,
+    ) -> Result<Box<dyn io::Write + Send + 'a>> {
+        replace_writer(&self.stdout, Box::new(stdout))
+    }
+
+    /// Replaces the writer used for `System.err` while the vm is running, returning the previous
+    /// writer.
+    pub fn set_stderr(
+        &self,
+        stderr: impl io::Write + Send + 'a,
+    ) -> Result<Box<dyn io::Write + Send + 'a>> {
+        replace_writer(&self.stderr, Box::new(stderr))
+    }
+
+    /// Calls a function, e.g. one which calls a Java method, and returns what it wrote to
+    /// `System.out` and `System.err` along with its result. The vm's writers are restored
+    /// afterwards, even if `f` panics.
+    ///
+    /// The vm's writers are flushed first, so that output written before the call isn't held
+    /// back until after it, and an error is returned if that fails.
+    pub fn capture_output<R>(&self, f: impl FnOnce(&Self) -> R) -> Result<(R, Output)> {
+        let stdout = SharedBuffer::default();
+        let stderr = SharedBuffer::default();
+
+        let mut restore = RestoreWriters {
+            vm: self,
+            stdout: None,
+            stderr: None,
+        };
+        restore.stdout = Some(replace_writer(&self.stdout, Box::new(stdout.clone()))?);
+        restore.stderr = Some(replace_writer(&self.stderr, Box::new(stderr.clone()))?);
+
+        let result = f(self);
+        drop(restore);
+
+        let output = Output {
+            stdout: stdout.take(),
+            stderr: stderr.take(),
+        };
+
+        Ok((result, output))
+    }
+
+    /// Enables or disables the intrinsic implementation of `java.lang.StringBuilder`, which is
+    /// enabled by default. Disabling it runs the JDK implementation instead, which is much slower
+    /// but useful for conformance testing.
+    ///
+    /// The intrinsic only replaces the methods which are commonly used to build strings. When the
+    /// JDK's code first reads the fields of a builder, such as for `insert` or `capacity`, the
+    /// builder's contents are moved into its fields and the JDK implementation is used for it
+    /// from then on.
+    pub fn with_string_builder_intrinsic(self, enabled: bool) -> Self {
+        if enabled {
+            natives::string::register_string_builder(&self).expect("invalid built-in native");
+        } else {
+            self.natives
+                .borrow_mut()
+                .retain(|id, _| id.class != natives::string::STRING_BUILDER);
+        }
+        self
+    }
+
+    /// Loads and initializes a class.
+    pub fn load_class_file(&self, name: &str) -> Result<&'a Class<'a>> {
+        let class = self.load_class(name)?;
+        self.initialize_class(class)?;
+        Ok(class)
+    }
+
+    /// Loads a class (and its super classes) without initializing it.
+    pub(crate) fn load_class(&self, name: &str) -> Result<&'a Class<'a>> {
         let class_name = name.strip_suffix(".class").unwrap_or(name);
 
-        if let Some(class) = self.classes.get(class_name) {
+        if let Some(class) = self.find_class(class_name) {
             return Ok(class);
         }
 
-        let path = Path::new(name).with_extension("class");
+        // If the class is already being loaded, it's its own super class
+        {
+            let mut loading = self.loading.borrow_mut();
+            if loading.iter().any(|name| name == class_name) {
+                let chain = loading
+                    .iter()
+                    .map(String::as_str)
+                    .skip_while(|name| *name != class_name)
+                    .chain([class_name])
+                    .collect::<Vec<_>>();
+
+                bail!(JavaException::new(
+                    "java/lang/ClassCircularityError",
+                    chain.join(" -> ")
+                ));
+            }
+            loading.push(class_name.to_owned());
+        }
+
+        let class = self.load_class_uncached(name, class_name);
+
+        self.loading.borrow_mut().retain(|name| name != class_name);
+
+        class
+    }
+
+    fn load_class_uncached(&self, name: &str, class_name: &str) -> Result<&'a Class<'a>> {
+        if let Some(archived) = self.read_archived_class(class_name)? {
+            let ArchivedClass {
+                class_file,
+                bytes,
+                code,
+            } = archived;
+            let class_file = &*self.arena.alloc(class_file);
+
+            let super_class = class_file
+                .super_class_name()
+                .map(|name| self.load_class(name))
+                .transpose()?;
+
+            return self.add_class(
+                class_file,
+                bytes,
+                Some(code),
+                super_class,
+                0,
+                "class archive",
+            );
+        }
+
+        let (bytes, source, is_file) = self.find_class_file(class_name)?;
+
+        let class_file = self
+            .read_class_file(bytes)
+            .wrap_err_with(|| format_err!("failed to read class file '{}'", name))?;
+
+        let super_class = class_file
+            .super_class_name()
+            .map(|name| self.load_class(name))
+            .transpose()?;
+
+        // The class loader classes are only available from a JDK, so without one every class is
+        // defined by the bootstrap loader
+        let loader = match is_file && self.java_home.is_some() {
+            true => self.builtin_loaders()?.app,
+            false => 0,
+        };
+
+        self.add_class(class_file, bytes, None, super_class, loader, &source)
+    }
+
+    /// Reads a class file without loading it, in lenient mode so that a class file which can't be
+    /// loaded can still be inspected. See [`ClassReader::lenient`].
+    pub fn read_class_file_lenient(&self, name: &str) -> Result<&'a ClassFile<'a>> {
+        let class_name = name.strip_suffix(".class").unwrap_or(name);
+        let (bytes, _, _) = self.find_class_file(class_name)?;
+        let mut class_file = ClassReader::from_slice(self.arena, bytes)
+            .lenient(true)
+            .read_class_file()
+            .wrap_err_with(|| format_err!("failed to read class file '{}'", name))?;
+        self.symbols
+            .intern_constant_pool(&mut class_file.constant_pool);
+        Ok(&*self.arena.alloc(class_file))
+    }
+
+    /// Reads a class from the class archive, if it's in the archive and not on the class path.
+    /// The class is already parsed and decoded.
+    fn read_archived_class(&self, class_name: &str) -> Result<Option<ArchivedClass<'a>>> {
+        let Some(archive) = self.class_archive() else {
+            return Ok(None);
+        };
+
+        if self.class_path.find(class_name).is_some() {
+            return Ok(None);
+        }
+
+        let Some(mut archived) = archive.read_class(self.arena, class_name).transpose()? else {
+            return Ok(None);
+        };
+
+        self.symbols
+            .intern_constant_pool(&mut archived.class_file.constant_pool);
+
+        Ok(Some(archived))
+    }
+
+    /// Finds the bytes of a class, along with a description of where they came from and whether
+    /// they came from the class path. Classes in the shims are borrowed from them, and other
+    /// classes are copied into the arena. Classes from the JDK are copied out of the jimage or the
+    /// class archive because they're unmapped when the vm is dropped, while classes live as long
+    /// as the arena.
+    fn find_class_file(&self, class_name: &str) -> Result<(&'a [u8], String, bool)> {
+        let path = self.class_path.find(class_name);
 
-        let reader: Box<dyn io::Read> = if path.exists() {
-            Box::new(BufReader::new(
-                File::open(&path).wrap_err_with(|| eyre!("failed to open {path:?}"))?,
-            ))
+        let is_file = path.is_some();
+        let (bytes, source) = if let Some(path) = path {
+            let bytes = fs::read(&path).wrap_err_with(|| format_err!("failed to read {path:?}"))?;
+            (
+                &*self.arena.alloc_slice_copy(&bytes),
+                path.display().to_string(),
+            )
         } else {
-            Box::new(Cursor::new(
-                self.system_jvm()?
-                    .extract_jrt_class(class_name)
-                    .wrap_err_with(|| eyre!("class not found: {class_name}"))?,
-            ))
+            // Without a JDK, core classes come from the shims built into the vm
+            let (bytes, source) = match self.class_archive().and_then(|a| a.find_class(class_name))
+            {
+                Some(bytes) => (
+                    Some(&*self.arena.alloc_slice_copy(bytes)),
+                    "class archive".to_owned(),
+                ),
+                None => match self.jimage()? {
+                    Some(jimage) => (
+                        jimage
+                            .find_class(class_name)?
+                            .map(|bytes| &*self.arena.alloc_slice_copy(bytes)),
+                        self.jrt_source(jimage, class_name)?,
+                    ),
+                    None => (shims::find_class(class_name), "shims".to_owned()),
+                },
+            };
+
+            let bytes = bytes.ok_or_else(|| Error::ClassNotFound {
+                name: class_name.into(),
+                searched: self.class_path.searched_locations(class_name).into(),
+            })?;
+
+            (bytes, source)
         };
 
-        let class_file = self.arena.alloc(
-            ClassReader::new(self.arena, reader)
-                .read_class_file()
-                .wrap_err_with(|| eyre!("failed to read class file '{}'", name))?,
-        );
+        Ok((bytes, source, is_file))
+    }
+
+    /// Defines a class from the contents of a class file, without reading it from the file
+    /// system. The class is defined by the application class loader, so it can be loaded by name
+    /// like classes on the class path. It isn't initialized until it's first used.
+    pub fn define_class(&self, bytes: &[u8]) -> Result<&'a Class<'a>> {
+        let app = self.builtin_loaders()?.app;
+        self.define_class_with_loader(bytes, app, None)
+    }
 
-        let class = self
-            .arena
-            .alloc(Class::new(self.arena, class_file, &mut |name| {
-                self.load_class_file(name)
-            })?);
+    /// Defines a class from the contents of a class file, with the given defining loader. If an
+    /// expected name is given, the class file must be for a class with that name.
+    ///
+    /// The super class is loaded by the same loader, but classes referenced by the class's code
+    /// are still resolved by name, since classes aren't namespaced by loader yet.
+    pub(crate) fn define_class_with_loader(
+        &self,
+        bytes: &[u8],
+        loader: usize,
+        expected_name: Option<&str>,
+    ) -> Result<&'a Class<'a>> {
+        let bytes = self.arena.alloc_slice_copy(bytes);
+        let class_file = self.read_class_file(bytes).map_err(|e| match e {
+            Error::Exception(_) => e,
+            e => Error::ClassFormat(e.full_message()),
+        })?;
 
-        if let Some(clinit) = class.method("<clinit>", "()V")
-            && clinit.access_flags.contains(MethodAccessFlags::STATIC)
-        {
-            self.call_method(class, clinit)?;
+        let name = class_file
+            .this_class_name()
+            .wrap_err_with(|| Error::ClassFormat("invalid this_class".to_owned()))?;
+
+        if let Some(expected_name) = expected_name.filter(|expected| *expected != name) {
+            bail!(JavaException::new(
+                "java/lang/NoClassDefFoundError",
+                format!("{expected_name} (wrong name: {name})")
+            ));
+        }
+
+        if self.find_class(name).is_some() {
+            bail!(JavaException::new(
+                "java/lang/LinkageError",
+                format!(
+                    "attempted duplicate class definition for {}.",
+                    name.replace('/', ".")
+                )
+            ));
+        }
+
+        let super_class = class_file
+            .super_class_name()
+            .map(|name| self.load_class_with_loader(name, loader))
+            .transpose()?;
+
+        self.add_class(class_file, bytes, None, super_class, loader, "defineClass")
+    }
+
+    /// Loads a class as if it were loaded by the given loader, calling its `loadClass` method if
+    /// it isn't one of the loaders built into the vm.
+    fn load_class_with_loader(&self, name: &str, loader: usize) -> Result<&'a Class<'a>> {
+        let builtin_loaders = self.builtin_loaders()?;
+        if loader == 0 || loader == builtin_loaders.app || loader == builtin_loaders.platform {
+            return self.load_class(name);
+        }
+
+        let binary_name = JvmValue::StringConst(self.intern(&name.replace('/', ".")));
+        let mirror = self
+            .invoke_virtual(
+                loader,
+                "loadClass",
+                "(Ljava/lang/String;)Ljava/lang/Class;",
+                &[binary_name],
+            )?
+            .and_then(|mirror| mirror.try_as_reference())
+            .wrap_err("expected reference")?;
+
+        let name = self
+            .class_mirror_name(mirror)
+            .wrap_err("expected java.lang.Class")?;
+
+        self.load_class(name)
+    }
+
+    /// Reads a class file, borrowing its strings and code from the bytes rather than allocating
+    /// them separately, which is why the bytes must live as long as the arena. Its strings are
+    /// then replaced by the vm's symbols.
+    fn read_class_file(&self, bytes: &'a [u8]) -> Result<&'a ClassFile<'a>> {
+        let mut class_file = ClassReader::from_slice(self.arena, bytes).read_class_file()?;
+        self.symbols
+            .intern_constant_pool(&mut class_file.constant_pool);
+        Ok(&*self.arena.alloc(class_file))
+    }
+
+    /// Creates a class from a class file that has been read, and adds it to the loaded classes.
+    /// The code of its methods is decoded unless it's given. The source describes where the class
+    /// file came from, for `-verbose:class`.
+    fn add_class(
+        &self,
+        class_file: &'a ClassFile<'a>,
+        bytes: &'a [u8],
+        code: Option<Vec<Option<DecodedCode<'a>>>>,
+        super_class: Option<&'a Class<'a>>,
+        loader: usize,
+        source: &str,
+    ) -> Result<&'a Class<'a>> {
+        let class = {
+            let class = match code {
+                Some(code) => Class::with_decoded_code(self.arena, class_file, super_class, code),
+                None => Class::new(self.arena, class_file, super_class),
+            }
+            .map_err(|e| Error::ClassFormat(e.full_message()))?;
+            &*self.arena.alloc(class)
+        };
+
+        let verify = match self.verify {
+            Verify::None => false,
+            Verify::Remote => loader != 0 || self.class_path.find(class.name()).is_some(),
+            Verify::All => true,
+        };
+
+        if verify {
+            verifier::verify_class(class)?;
+        }
+
+        self.classes.borrow_mut().insert(class.name(), class);
+        self.class_files.lock().unwrap().push((class, bytes));
+
+        if loader != 0 {
+            self.defining_loaders
+                .write()
+                .unwrap()
+                .insert(class.name(), loader);
+        }
+
+        if self.verbose_class {
+            // The same format as HotSpot's unified logging
+            writeln!(
+                self.stdout(),
+                "[info][class,load] {} source: {source}",
+                class.name().replace('/', ".")
+            )?;
         }
 
-        self.classes.insert(class.name(), class);
+        for hook in &self.hooks {
+            hook.on_class_load(self, class);
+        }
 
         Ok(class)
     }
 
-    pub fn call_method(
-        &mut self,
-        class: &'a Class<'a>,
-        method: &'a Method<'a>,
-    ) -> eyre::Result<()> {
-        CallFrame::new(class, method, iter::empty(), self)?.execute()?;
-        Ok(())
+    /// Describes a class in the jimage as a `jrt:` URL with its module, for `-verbose:class`. The
+    /// module is only looked up when logging, since it's another lookup in the jimage.
+    fn jrt_source(&self, jimage: &JImage, class_name: &str) -> Result<String> {
+        let module = match class_name.rsplit_once('/') {
+            Some((package, _)) if self.verbose_class => jimage.package_module(package)?,
+            _ => None,
+        };
+        Ok(format!("jrt:/{}", module.unwrap_or_default()))
+    }
+
+    /// Returns the platform and application class loaders, creating them if needed.
+    pub(crate) fn builtin_loaders(&self) -> Result<BuiltinLoaders> {
+        let mut loaders = self.builtin_loaders.lock().unwrap();
+        if let Some(loaders) = *loaders {
+            return Ok(loaders);
+        }
+
+        // Only classes from the JDK are loaded while creating the loaders, so this doesn't
+        // recurse.
+        let created = natives::class_loader::create_builtin_loaders(self)?;
+        *loaders = Some(created);
+
+        Ok(created)
+    }
+
+    /// Returns the loader which defined a class, or null for the bootstrap loader.
+    pub(crate) fn defining_loader(&self, class: &Class) -> usize {
+        self.defining_loaders
+            .read()
+            .unwrap()
+            .get(class.name())
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Returns a class if it has already been loaded, and was defined by the given loader.
+    pub(crate) fn find_loaded_class(&self, name: &str, loader: usize) -> Option<&'a Class<'a>> {
+        self.find_class(name)
+            .filter(|class| self.defining_loader(class) == loader)
+    }
+
+    /// Loads a class with the bootstrap loader, which only sees classes from the JDK. Returns
+    /// `None` if there is no such class.
+    pub(crate) fn load_bootstrap_class(&self, name: &str) -> Result<Option<&'a Class<'a>>> {
+        if let Some(class) = self.find_class(name) {
+            return Ok((self.defining_loader(class) == 0).then_some(class));
+        }
+
+        if self.class_path.find(name).is_some() {
+            return Ok(None);
+        }
+
+        match self.load_class(name) {
+            Ok(class) => Ok(Some(class)),
+            Err(e) if is_missing_class(&e, name) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Loads a class from the class path, as the application class loader. Returns `None` if
+    /// there is no such class.
+    pub(crate) fn load_app_class(&self, name: &str) -> Result<Option<&'a Class<'a>>> {
+        if self.class_path.find(name).is_none() {
+            return Ok(None);
+        }
+
+        self.load_class(name).map(Some)
+    }
+
+    /// Initializes a class (and its super classes) if it hasn't been already, by running its
+    /// static initializer.
+    pub(crate) fn initialize_class(&self, class: &'a Class<'a>) -> Result<()> {
+        {
+            let mut initialization = self.initialization.borrow_mut();
+            match initialization.get(class.name()) {
+                // A recursive request to initialize a class sees it while it is still being
+                // initialized (JVMS §5.5), e.g. when `<clinit>` creates an instance.
+                Some(Initialization::Done | Initialization::InProgress) => return Ok(()),
+                None => initialization.insert(class.name(), Initialization::InProgress),
+            };
+        }
+
+        let result = self.initialize_class_uncached(class);
+
+        {
+            let mut initialization = self.initialization.borrow_mut();
+            match result {
+                Ok(()) => initialization.insert(class.name(), Initialization::Done),
+                // TODO: Mark the class as erroneous, so that later attempts throw
+                // NoClassDefFoundError. For now initialization is retried.
+                Err(_) => initialization.remove(class.name()),
+            };
+        }
+
+        result
+    }
+
+    /// Returns the size in bytes of each thread's frame stack.
+    pub(crate) fn stack_size(&self) -> usize {
+        self.stack_size
+    }
+
+    /// Returns the compiler for hot methods, unless it's disabled or the host isn't supported.
+    #[cfg(feature = "jit")]
+    pub(crate) fn jit(&self) -> Option<&Jit> {
+        if !self.jit_enabled {
+            return None;
+        }
+
+        self.jit
+            .get_or_init(|| Jit::new(self.jit_threshold).ok())
+            .as_ref()
+    }
+
+    pub(crate) fn hooks(&self) -> &[Box<dyn Hook<'a> + 'a>] {
+        &self.hooks
     }
 
-    fn system_jvm(&mut self) -> eyre::Result<&jdk_tools::Jvm> {
-        if self.system_jvm.is_none() {
-            self.system_jvm = Some(jdk_tools::Jvm::new()?);
+    /// Whether methods should run in the instrumented interpreter loop, which calls hooks and
+    /// counts instructions against the budget.
+    pub(crate) fn is_instrumented(&self) -> bool {
+        !self.hooks.is_empty() || self.budget.is_some()
+    }
+
+    /// Counts an instruction against the budget, if there is one.
+    pub(crate) fn record_instruction(&self) -> Result<(), BudgetExceeded> {
+        match &self.budget {
+            Some(budget) => budget.record_instruction(),
+            None => Ok(()),
         }
+    }
+
+    pub(crate) fn superinstruction_threshold(&self) -> u64 {
+        self.superinstruction_threshold
+    }
+
+    /// The names and descriptors of the classes that have been read.
+    pub fn symbols(&self) -> &SymbolTable<'a> {
+        &self.symbols
+    }
 
-        Ok(unsafe { self.system_jvm.as_ref().unwrap_unchecked() })
+    /// Returns whether a class has finished initializing.
+    pub(crate) fn is_initialized(&self, class: &Class) -> bool {
+        matches!(
+            self.initialization.borrow().get(class.name()),
+            Some(Initialization::Done)
+        )
     }
+
+    fn initialize_class_uncached(&self, class: &'a Class<'a>) -> Result<()> {
+        if let Some(super_class) = class.super_class() {
+            self.initialize_class(super_class)?;
+        }
+
+        if let Some(clinit) = class
+            .method("<clinit>", "()V")
+            .filter(|clinit| clinit.access_flags.contains(MethodAccessFlags::STATIC))
+        {
+            // Static initializers which can't be run yet can be replaced like other methods
+            match self.intrinsic(class.name(), "<clinit>", "()V") {
+                Some(native) => {
+                    native(self, &[])?;
+                }
+                None => self.call_method(class, clinit)?,
+            }
+        }
+
+        // The JDK sets up the standard streams in System.initPhase1, which is called by the vm
+        // once System is initialized. This can't be run yet, so they are created directly.
+        if class.name() == "java/lang/System" {
+            natives::io::init_system_streams(self, class)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns a class if it's been loaded, without loading it.
+    pub fn find_class(&self, name: &str) -> Option<&'a Class<'a>> {
+        self.classes.borrow().get(name).copied()
+    }
+
+    /// Returns the classes which have been loaded, in the order they were loaded.
+    pub(crate) fn loaded_classes(&self) -> Vec<&'a Class<'a>> {
+        let class_files = self.class_files.lock().unwrap();
+        class_files.iter().map(|(class, _)| *class).collect()
+    }
+
+    pub fn call_method(&self, class: &'a Class<'a>, method: &'a Method<'a>) -> Result<()> {
+        CallFrame::new(class, method, iter::empty(), self)?.execute()?;
+        Ok(())
+    }
+
+    /// Calls a static method of a class, which is loaded and initialized first if it hasn't been.
+    /// The method's descriptor is made from the types of the arguments and the result, e.g.
+    /// `vm.invoke_static::<i32>("java/lang/Math", "max", (1, 2))` calls `max(II)I`.
+    pub fn invoke_static<A: Args<'a>, R: FromJvm<'a>>(
+        &self,
+        class_name: &str,
+        name: &str,
+        args: A,
+    ) -> Result<R> {
+        let descriptor = convert::method_descriptor::<A, R>();
+        let args = args.into_values(self)?;
+        let result = self.invoke_static_method(class_name, name, &descriptor, &args)?;
+        R::from_jvm(self, result)
+    }
+
+    /// Calls a static method with a descriptor and arguments which are only known at runtime,
+    /// e.g. for calls from other languages. The arguments aren't checked against the descriptor.
+    pub fn invoke_static_method(
+        &self,
+        class_name: &str,
+        name: &str,
+        descriptor: &str,
+        args: &[JvmValue<'a>],
+    ) -> Result<Option<JvmValue<'a>>> {
+        let class = self.load_class_file(class_name)?;
+        let (class, method) = self.find_method(class, name, descriptor)?;
+        if !method.access_flags.contains(MethodAccessFlags::STATIC) {
+            bail!("{}.{name}{descriptor} isn't static", class.name());
+        }
+
+        self.invoke_method(class, method, name, descriptor, args.iter().cloned())
+    }
+
+    /// Calls an instance method, selecting the implementation from the class of the receiver.
+    /// The method's descriptor is made from the types of the arguments and the result, as with
+    /// [`Vm::invoke_static`].
+    pub fn invoke_instance<A: Args<'a>, R: FromJvm<'a>>(
+        &self,
+        receiver: JvmValue<'a>,
+        name: &str,
+        args: A,
+    ) -> Result<R> {
+        let descriptor = convert::method_descriptor::<A, R>();
+        let (class, method) =
+            self.find_method(self.runtime_class(&receiver)?, name, &descriptor)?;
+        if method.access_flags.contains(MethodAccessFlags::STATIC) {
+            bail!("{}.{name}{descriptor} is static", class.name());
+        }
+
+        let args = iter::once(receiver).chain(args.into_values(self)?);
+        let result = self.invoke_method(class, method, name, &descriptor, args)?;
+        R::from_jvm(self, result)
+    }
+
+    /// Creates an instance of a class, calling the constructor with the given descriptor, e.g.
+    /// `vm.new_object("java/lang/StringBuilder", "(I)V", (16,))`. The class is loaded and
+    /// initialized first if it hasn't been.
+    pub fn new_object<A: Args<'a>>(
+        &self,
+        class_name: &str,
+        descriptor: &str,
+        args: A,
+    ) -> Result<Object<'_, 'a>> {
+        let class = self.load_class_file(class_name)?;
+        if class
+            .access_flags()
+            .intersects(ClassAccessFlags::ABSTRACT | ClassAccessFlags::INTERFACE)
+        {
+            bail!(JavaException::new(
+                "java/lang/InstantiationError",
+                class.name().replace('/', ".")
+            ));
+        }
+
+        let constructor = class.method("<init>", descriptor).wrap_err_with(|| {
+            format_err!("constructor not found: {}.<init>{descriptor}", class.name())
+        })?;
+
+        let args = args.into_values(self)?;
+        if args.len() != constructor.descriptor.params.len() {
+            bail!(
+                "expected {} arguments to {}.<init>{descriptor}, found {}",
+                constructor.descriptor.params.len(),
+                class.name(),
+                args.len()
+            );
+        }
+
+        let object = JvmValue::Reference(self.alloc_object(class)?);
+        let args = iter::once(object.clone()).chain(args);
+        self.invoke_method(class, constructor, "<init>", descriptor, args)?;
+
+        Object::new(self, object)
+    }
+
+    /// Creates a `java.lang.String` with the contents of a Rust string.
+    ///
+    /// Strings are stored by the vm as UTF-8 in its arena rather than as `String` objects, so
+    /// they live as long as the vm, like string literals.
+    pub fn new_string(&self, s: &str) -> JvmValue<'a> {
+        JvmValue::StringConst(self.alloc_str(s))
+    }
+
+    /// Returns the contents of a `java.lang.String`, or `None` if the value is null.
+    pub fn string_value(&self, value: &JvmValue<'a>) -> Result<Option<&'a str>> {
+        match value {
+            JvmValue::StringConst(s) => Ok(Some(s)),
+            JvmValue::Reference(0) => Ok(None),
+            JvmValue::Reference(object) if is_string_object(*object) => {
+                self.string_object_contents(*object).map(Some)
+            }
+            value => bail!("expected string, found {value:?}"),
+        }
+    }
+
+    /// Returns a `String` object with the contents of a string, so that the JDK's implementation
+    /// of `String` can read its fields. The same object is returned each time for a string.
+    pub(crate) fn string_object(&self, s: &'a str) -> Result<usize> {
+        let key = (s.as_ptr() as usize, s.len());
+        if let Some(&object) = self.string_objects.lock().unwrap().objects.get(&key) {
+            return Ok(object);
+        }
+
+        let (coder, bytes) = compact_string(s);
+        let value = self.alloc_array(ArrayElementType::Primitive(ArrayType::Byte), bytes.len())?;
+        unsafe { (*(value as *mut RefTypeHeader)).array_data::<u8>()? }.copy_from_slice(&bytes);
+
+        let object = self.alloc_object(self.load_class_file("java/lang/String")?)?;
+        self.set_field(object, "value", "[B", JvmValue::Reference(value))?;
+        self.set_field(object, "coder", "B", JvmValue::Int(coder))?;
+
+        let mut string_objects = self.string_objects.lock().unwrap();
+        string_objects.objects.insert(key, object);
+        string_objects.contents.insert(object, s);
+
+        Ok(object)
+    }
+
+    /// Returns the contents of a `String` object, such as one created by Java code with
+    /// `new String(chars)`. The same string is returned each time for an object.
+    fn string_object_contents(&self, object: usize) -> Result<&'a str> {
+        if let Some(&s) = self.string_objects.lock().unwrap().contents.get(&object) {
+            return Ok(s);
+        }
+
+        let value = match self.get_field(object, "value", "[B")? {
+            JvmValue::Reference(0) => bail!("string has no value"),
+            JvmValue::Reference(value) => value,
+            value => bail!("expected byte array, found {value:?}"),
+        };
+        let bytes = unsafe { (*(value as *mut RefTypeHeader)).array_data::<u8>()? };
+
+        let s = match self.get_field(object, "coder", "B")? {
+            JvmValue::Int(LATIN1) => {
+                self.alloc_str(&bytes.iter().map(|&b| char::from(b)).collect::<String>())
+            }
+            JvmValue::Int(UTF16) => {
+                let units = bytes
+                    .chunks_exact(2)
+                    .map(|unit| u16::from_ne_bytes([unit[0], unit[1]]))
+                    .collect::<Vec<_>>();
+                self.alloc_str(&String::from_utf16_lossy(&units))
+            }
+            coder => bail!("invalid string coder: {coder:?}"),
+        };
+
+        let mut string_objects = self.string_objects.lock().unwrap();
+        string_objects
+            .objects
+            .insert((s.as_ptr() as usize, s.len()), object);
+        string_objects.contents.insert(object, s);
+
+        Ok(s)
+    }
+
+    /// Replaces `String` objects in the arguments of a native with the strings they contain, so
+    /// that natives only need to handle the vm's representation of strings.
+    fn native_args<'v>(&self, args: &'v [JvmValue<'a>]) -> Result<Cow<'v, [JvmValue<'a>]>> {
+        let is_string = |arg: &JvmValue| matches!(arg, JvmValue::Reference(object) if is_string_object(*object));
+        if !args.iter().any(is_string) {
+            return Ok(Cow::Borrowed(args));
+        }
+
+        args.iter()
+            .map(|arg| match arg {
+                JvmValue::Reference(object) if is_string(arg) => {
+                    Ok(JvmValue::StringConst(self.string_object_contents(*object)?))
+                }
+                arg => Ok(arg.clone()),
+            })
+            .collect()
+    }
+
+    /// Creates an array with a copy of the given elements, whose element type is the Java type of
+    /// `T`, e.g. `vm.new_array(&["a", "b"])` creates a `String[]`.
+    pub fn new_array<T: ToJvm<'a> + FromJvm<'a> + Clone>(
+        &self,
+        elements: &[T],
+    ) -> Result<Array<'_, 'a, T>> {
+        Array::new(self, elements.to_vec().to_jvm(self)?)
+    }
+
+    /// Runs the `main` method of a class as the entry point of a program, with the given program
+    /// arguments, followed by any shutdown hooks. Returns the exit status, which is non-zero if
+    /// the program called `System.exit` with a non-zero status.
+    ///
+    /// Like in the JDK, a hook which throws doesn't change how the program finished, and the
+    /// exception is written to `System.err` instead. A hook which calls `System.exit` decides the
+    /// exit status, and the remaining hooks aren't run.
+    pub fn run_main(&self, class: &'a Class<'a>, args: &[String]) -> Result<i32> {
+        let main = class
+            .method("main", "([Ljava/lang/String;)V")
+            .wrap_err("main method not found")?;
+
+        let array = self.alloc_array(ArrayElementType::Reference, args.len())?;
+        let elements = unsafe { (*(array as *mut RefTypeHeader)).array_data::<JvmValue>()? };
+        for (element, arg) in elements.iter_mut().zip(args) {
+            *element = JvmValue::StringConst(self.alloc_str(arg));
+        }
+
+        let args = iter::once(JvmValue::Reference(array));
+        let result = CallFrame::new(class, main, args, self).and_then(|frame| frame.execute());
+
+        let status = match result {
+            Ok(_) => Ok(0),
+            Err(e) => match e.root() {
+                // Shutdown hooks would exceed the budget or be stopped too
+                Error::BudgetExceeded(_) | Error::Stopped => return Err(e),
+                Error::Exit(exit) => Ok(exit.status),
+                _ => Err(e),
+            },
+        };
+
+        if let Some(status) = self.run_shutdown_hooks()? {
+            return Ok(status);
+        }
+
+        status
+    }
+
+    /// Registers a shutdown hook. Returns false if the hook was already registered.
+    pub(crate) fn add_shutdown_hook(&self, hook: usize) -> bool {
+        let mut hooks = self.shutdown_hooks.lock().unwrap();
+        if hooks.contains(&hook) {
+            return false;
+        }
+        hooks.push(hook);
+        true
+    }
+
+    /// Unregisters a shutdown hook. Returns false if the hook wasn't registered.
+    pub(crate) fn remove_shutdown_hook(&self, hook: usize) -> bool {
+        let mut hooks = self.shutdown_hooks.lock().unwrap();
+        let len = hooks.len();
+        hooks.retain(|&h| h != hook);
+        hooks.len() != len
+    }
+
+    /// Runs the registered shutdown hooks, in registration order, returning the exit status if
+    /// one of them calls `System.exit`.
+    ///
+    /// Each hook is a `java.lang.Thread`, but its `run` method is called on the current thread
+    /// rather than starting it.
+    fn run_shutdown_hooks(&self) -> Result<Option<i32>> {
+        let hooks = mem::take(&mut *self.shutdown_hooks.lock().unwrap());
+
+        for hook in hooks {
+            let Err(e) = self.invoke_virtual(hook, "run", "()V", &[]) else {
+                continue;
+            };
+
+            match e.root() {
+                Error::Exit(exit) => return Ok(Some(exit.status)),
+                Error::BudgetExceeded(_) | Error::Stopped => return Err(e),
+                _ => self.report_uncaught(hook, &e)?,
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Writes an error which ended a thread to `System.err`, as the JDK's default handler for
+    /// uncaught exceptions does.
+    fn report_uncaught(&self, thread: usize, error: &Error) -> Result<()> {
+        let name = self.get_field(thread, "name", "Ljava/lang/String;")?;
+        let name = self.string_value(&name)?.unwrap_or("");
+
+        let mut frames: &[String] = &[];
+        let mut cause = error;
+        loop {
+            match cause {
+                Error::Context { source, .. } => cause = source,
+                Error::InJava {
+                    frames: in_java, ..
+                } => {
+                    frames = in_java;
+                    break;
+                }
+                _ => break,
+            }
+        }
+
+        let mut stderr = self.stderr.lock().unwrap();
+        writeln!(stderr, "Exception in thread \"{name}\" {}", error.root())?;
+        for frame in frames {
+            writeln!(stderr, "\t{frame}")?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the value of a system property, as seen by `System.getProperty`.
+    pub fn property(&self, key: &str) -> Option<String> {
+        self.properties.read().unwrap().get(key).cloned()
+    }
+
+    /// Sets a system property, returning its previous value.
+    pub fn set_property(&self, key: impl Into<String>, value: impl Into<String>) -> Option<String> {
+        self.properties
+            .write()
+            .unwrap()
+            .insert(key.into(), value.into())
+    }
+
+    /// Removes a system property, returning its previous value.
+    pub fn clear_property(&self, key: &str) -> Option<String> {
+        self.properties.write().unwrap().remove(key)
+    }
+
+    /// Registers a Rust function as the implementation of a native method, replacing any existing
+    /// implementation. The function is called with each argument converted with
+    /// [`FromJvm`](convert::FromJvm), starting with the receiver for instance methods.
+    ///
+    /// `class` may be `*` to match a method declared in any class. Natives registered for a
+    /// specific class take precedence.
+    ///
+    /// The descriptor is given explicitly, since a parameter like `JvmValue` may stand for any
+    /// type. [`Vm::register_fn`] and [`Vm::register_method`] derive it instead.
+    ///
+    /// An error is returned if the descriptor doesn't match the function's types. A `JvmValue`
+    /// matches any type, a [`Reference`](convert::Reference) any reference type, and an `i32` any
+    /// type which is represented as an int, like `char` or `boolean`.
+    pub fn register_native<A: 'a>(
+        &self,
+        class: &str,
+        name: &str,
+        descriptor: &str,
+        f: impl NativeFn<'a, A>,
+    ) -> Result<()> {
+        check_native_fn(class, name, descriptor, &f)?;
+        self.register_raw_native(class, name, descriptor, move |vm, args| f.call(vm, args));
+        Ok(())
+    }
+
+    /// Registers the implementation of a native method which takes its arguments unconverted,
+    /// e.g. so that one implementation can be shared by several overloads.
+    pub fn register_raw_native(
+        &self,
+        class: &str,
+        name: &str,
+        descriptor: &str,
+        method: impl Fn(&Vm<'a>, &[JvmValue<'a>]) -> Result<Option<JvmValue<'a>>> + 'a,
+    ) {
+        let id = NativeId {
+            class: class.to_owned(),
+            name: name.to_owned(),
+            descriptor: descriptor.to_owned(),
+        };
+
+        self.natives.borrow_mut().insert(id, Rc::new(method));
+    }
+
+    /// Registers a Rust function as the implementation of a static native method, whose
+    /// descriptor is derived from the function's parameter and return types.
+    pub fn register_fn<A: 'a>(&self, class: &str, name: &str, f: impl NativeFn<'a, A>) {
+        let descriptor = native_fn_descriptor(&f, 0);
+        self.register_raw_native(class, name, &descriptor, move |vm, args| f.call(vm, args));
+    }
+
+    /// Registers a Rust function as the implementation of an instance native method. The
+    /// function's first parameter is the receiver, which isn't part of the method's descriptor.
+    pub fn register_method<A: 'a>(&self, class: &str, name: &str, f: impl NativeFn<'a, A>) {
+        let descriptor = native_fn_descriptor(&f, 1);
+        self.register_raw_native(class, name, &descriptor, move |vm, args| f.call(vm, args));
+    }
+
+    /// Finds the implementation of a native method.
+    pub(crate) fn native(
+        &self,
+        class: &str,
+        name: &str,
+        descriptor: &str,
+    ) -> Option<Rc<NativeMethod<'a>>> {
+        self.intrinsic(class, name, descriptor).or_else(|| {
+            let id = NativeIdRef {
+                class: "*",
+                name,
+                descriptor,
+            };
+
+            self.natives.borrow().get(&id).cloned()
+        })
+    }
+
+    /// Finds a native registered specifically for the given class, which should be used in
+    /// place of the method's bytecode.
+    pub(crate) fn intrinsic(
+        &self,
+        class: &str,
+        name: &str,
+        descriptor: &str,
+    ) -> Option<Rc<NativeMethod<'a>>> {
+        let id = NativeIdRef {
+            class,
+            name,
+            descriptor,
+        };
+
+        self.natives.borrow().get(&id).cloned()
+    }
+
+    /// Returns the registered implementation of a method. This is required for native methods,
+    /// but one can also be registered to replace the bytecode of a non-native method.
+    pub(crate) fn resolve_native(
+        &self,
+        class: &'a Class<'a>,
+        method: &'a Method<'a>,
+        name: &str,
+        descriptor: &str,
+    ) -> Result<Option<Rc<NativeMethod<'a>>>> {
+        if !method.access_flags.contains(MethodAccessFlags::NATIVE) {
+            return Ok(self.intrinsic(class.name(), name, descriptor));
+        }
+
+        let native = self.native(class.name(), name, descriptor).ok_or_else(|| {
+            JavaException::new(
+                "java/lang/UnsatisfiedLinkError",
+                format!("'{}.{name}{descriptor}'", class.name().replace('/', ".")),
+            )
+        })?;
+
+        Ok(Some(native))
+    }
+
+    /// Calls an instance method, selecting the implementation from the object's class.
+    pub(crate) fn invoke_virtual(
+        &self,
+        object: usize,
+        name: &str,
+        descriptor: &str,
+        args: &[JvmValue<'a>],
+    ) -> Result<Option<JvmValue<'a>>> {
+        if object == 0 {
+            bail!(JavaException::new(
+                "java/lang/NullPointerException",
+                format!("Cannot invoke \"{name}{descriptor}\" on null")
+            ));
+        }
+
+        let receiver = JvmValue::Reference(object);
+        let (class, method) = self.find_method(self.runtime_class(&receiver)?, name, descriptor)?;
+        let args = iter::once(receiver).chain(args.iter().cloned());
+
+        self.invoke_method(class, method, name, descriptor, args)
+    }
+
+    /// Calls a method without any method selection. `args` includes the receiver for instance
+    /// methods.
+    pub(crate) fn invoke_method(
+        &self,
+        class: &'a Class<'a>,
+        method: &'a Method<'a>,
+        name: &str,
+        descriptor: &str,
+        args: impl Iterator<Item = JvmValue<'a>>,
+    ) -> Result<Option<JvmValue<'a>>> {
+        match self.resolve_native(class, method, name, descriptor)? {
+            Some(native) => self.call_native(&*native, class, method, &args.collect::<Vec<_>>()),
+            None => CallFrame::new(class, method, args, self)?.execute(),
+        }
+    }
+
+    /// Calls a method's bytecode, even if a native has been registered to replace it, so that
+    /// the native can fall back to it.
+    pub(crate) fn invoke_bytecode(
+        &self,
+        class_name: &str,
+        name: &str,
+        descriptor: &str,
+        args: &[JvmValue<'a>],
+    ) -> Result<Option<JvmValue<'a>>> {
+        let class = self.load_class_file(class_name)?;
+        let method = class
+            .method(name, descriptor)
+            .wrap_err_with(|| format_err!("method not found: {class_name}.{name}{descriptor}"))?;
+        CallFrame::new(class, method, args.iter().cloned(), self)?.execute()
+    }
+
+    /// Calls the implementation of a native method, and any hooks around it.
+    pub(crate) fn call_native(
+        &self,
+        native: &NativeMethod<'a>,
+        class: &'a Class<'a>,
+        method: &'a Method<'a>,
+        args: &[JvmValue<'a>],
+    ) -> Result<Option<JvmValue<'a>>> {
+        let args = &*self.native_args(args)?;
+        if self.hooks.is_empty() {
+            return native(self, args);
+        }
+
+        let method = MethodRef { class, method };
+        for hook in &self.hooks {
+            hook.on_method_enter(self, method, args);
+        }
+
+        let result = native(self, args);
+        for hook in &self.hooks {
+            hook.on_method_exit(self, method, &result);
+        }
+
+        result
+    }
+
+    /// Finds a method declared by a class or its superclasses, returning it along with the class
+    /// which declares it.
+    pub(crate) fn find_method(
+        &self,
+        mut class: &'a Class<'a>,
+        name: &str,
+        descriptor: &str,
+    ) -> Result<(&'a Class<'a>, &'a Method<'a>)> {
+        loop {
+            if let Some(method) = class.method(name, descriptor) {
+                return Ok((class, method));
+            }
+            class = class
+                .super_class()
+                .wrap_err_with(|| format_err!("method not found: {name}{descriptor}"))?;
+        }
+    }
+
+    /// Returns the interfaces implemented by a class, including those implemented by its
+    /// superclasses and the superinterfaces of each interface. Each interface comes before its
+    /// superinterfaces.
+    pub(crate) fn superinterfaces(&self, class: &'a Class<'a>) -> Result<Vec<&'a Class<'a>>> {
+        fn visit<'a>(
+            vm: &Vm<'a>,
+            class: &'a Class<'a>,
+            interfaces: &mut Vec<&'a Class<'a>>,
+        ) -> Result<()> {
+            for name in class.interfaces() {
+                let interface = vm.load_class(name)?;
+                if !interfaces
+                    .iter()
+                    .any(|i| symbols::same(i.name(), interface.name()))
+                {
+                    interfaces.push(interface);
+                    visit(vm, interface, interfaces)?;
+                }
+            }
+            Ok(())
+        }
+
+        let mut interfaces = vec![];
+        let mut class = Some(class);
+        while let Some(current) = class {
+            visit(self, current, &mut interfaces)?;
+            class = current.super_class();
+        }
+
+        Ok(interfaces)
+    }
+
+    /// Returns a class's interface method table for an interface, or `None` if the class doesn't
+    /// implement it. The tables for all of the class's interfaces are built on the first call, so
+    /// that later interface calls don't need to search the class hierarchy.
+    pub(crate) fn itable(
+        &self,
+        class: &'a Class<'a>,
+        interface: &str,
+    ) -> Result<Option<&'a Itable<'a>>> {
+        if class.itables().get().is_none() {
+            let mut itables = hashbrown::HashMap::new();
+            let interfaces = self.superinterfaces(class)?;
+
+            for interface in &interfaces {
+                let itable = interface
+                    .declared_methods()
+                    .map(|(name, descriptor, method)| {
+                        if method
+                            .access_flags
+                            .intersects(MethodAccessFlags::STATIC | MethodAccessFlags::PRIVATE)
+                        {
+                            return None;
+                        }
+                        select_interface_method(class, &interfaces, name, descriptor)
+                    })
+                    .collect();
+
+                itables.insert(interface.name(), itable);
+            }
+
+            // Nothing else builds the tables, so they haven't been set since they were checked
+            let _ = class.itables().set(itables);
+        }
+
+        Ok(class.itables().get().unwrap().get(interface))
+    }
+
+    /// Returns the class of an object, which is used to select instance methods. Methods of
+    /// arrays are those of `java.lang.Object`.
+    pub(crate) fn runtime_class(&self, object: &JvmValue<'a>) -> Result<&'a Class<'a>> {
+        match object {
+            JvmValue::StringConst(_) => self.load_class_file("java/lang/String"),
+            JvmValue::Reference(0) => bail!(JavaException::null_pointer()),
+            JvmValue::Reference(object) => match unsafe { &*(*object as *const RefTypeHeader) } {
+                RefTypeHeader::Object(ObjectHeader { class }) => {
+                    Ok(unsafe { class.cast().as_ref() })
+                }
+                RefTypeHeader::Array(_) => self.load_class_file("java/lang/Object"),
+            },
+            value => bail!("expected reference, found {value:?}"),
+        }
+    }
+
+    /// Finds a static field referred to through a class, along with the class which declares it.
+    /// Fields are looked up in the class, then its interfaces, and then its super class, as
+    /// described in JVMS 5.4.3.2.
+    pub(crate) fn find_static_field(
+        &self,
+        class: &'a Class<'a>,
+        name: &'a str,
+        descriptor: &'a str,
+    ) -> Result<Option<(&'a Class<'a>, &'a Mutex<JvmValue<'a>>)>> {
+        if let Some(field) = class.static_field(name, descriptor) {
+            return Ok(Some((class, field)));
+        }
+
+        for interface in class.interfaces() {
+            let interface = self.load_class(interface)?;
+            if let Some(field) = self.find_static_field(interface, name, descriptor)? {
+                return Ok(Some(field));
+            }
+        }
+
+        match class.super_class() {
+            Some(super_class) => self.find_static_field(super_class, name, descriptor),
+            None => Ok(None),
+        }
+    }
+
+    /// Describes an exception object thrown by `athrow`, by its class and detail message.
+    pub(crate) fn thrown_exception(&self, object: usize) -> Result<JavaException> {
+        let class = self.runtime_class(&JvmValue::Reference(object))?;
+        let message = self.get_field(object, "detailMessage", "Ljava/lang/String;")?;
+
+        Ok(JavaException {
+            class_name: class.name().to_owned(),
+            message: self.string_value(&message)?.map(str::to_owned),
+            object: Some(object),
+        })
+    }
+
+    /// Returns the object of an exception which is being caught, creating it if the exception
+    /// was raised by the vm rather than thrown by `athrow`.
+    pub(crate) fn exception_object(&self, exception: &JavaException) -> Result<usize> {
+        if let Some(object) = exception.object {
+            return Ok(object);
+        }
+
+        let object = match &exception.message {
+            Some(message) => self.new_object(
+                &exception.class_name,
+                "(Ljava/lang/String;)V",
+                (message.as_str(),),
+            )?,
+            None => self.new_object(&exception.class_name, "()V", ())?,
+        };
+
+        Ok(object.reference())
+    }
+
+    /// Returns whether a value is an instance of the named class, interface or array type. Null
+    /// isn't an instance of any type.
+    pub(crate) fn is_instance_of(&self, value: &JvmValue<'a>, name: &str) -> Result<bool> {
+        let array = match value {
+            JvmValue::Reference(0) => return Ok(false),
+            JvmValue::Reference(object) => match unsafe { &*(*object as *const RefTypeHeader) } {
+                RefTypeHeader::Array(array) => Some(array),
+                RefTypeHeader::Object(_) => None,
+            },
+            _ => None,
+        };
+
+        if let Some(array) = array {
+            // The component types of reference arrays aren't tracked, so any reference array
+            // type is accepted for them
+            let array_type = match array.element_type {
+                ArrayElementType::Primitive(t) => Some(t.descriptor()),
+                ArrayElementType::Reference => None,
+            };
+
+            return Ok(match name {
+                "java/lang/Object" | "java/lang/Cloneable" | "java/io/Serializable" => true,
+                _ => match (array_type, name.strip_prefix('[')) {
+                    (Some(array_type), Some(component)) => {
+                        component.len() == 1 && component.starts_with(array_type)
+                    }
+                    (None, Some(component)) => component.starts_with(['L', '[']),
+                    (_, None) => false,
+                },
+            });
+        }
+
+        self.is_subclass_of(self.runtime_class(value)?, name)
+    }
+
+    /// Returns whether a class is, extends or implements the named class or interface.
+    pub(crate) fn is_subclass_of(&self, class: &'a Class<'a>, name: &str) -> Result<bool> {
+        if class.name() == name {
+            return Ok(true);
+        }
+
+        for interface in class.interfaces() {
+            if self.is_subclass_of(self.load_class(interface)?, name)? {
+                return Ok(true);
+            }
+        }
+
+        match class.super_class() {
+            Some(super_class) => self.is_subclass_of(super_class, name),
+            None => Ok(false),
+        }
+    }
+
+    /// Returns the `java.lang.Class` instance for the named class, array or primitive type.
+    pub(crate) fn class_mirror(&self, name: &str) -> Result<usize> {
+        if let Some(&mirror) = self.class_mirrors.lock().unwrap().by_name.get(name) {
+            return Ok(mirror);
+        }
+
+        // Loading java.lang.Class may run arbitrary code, so the lock can't be held here.
+        let mirror = self.alloc_object(self.load_class_file("java/lang/Class")?)?;
+        let name = self.alloc_str(name);
+
+        let mut mirrors = self.class_mirrors.lock().unwrap();
+        let mirror = *mirrors.by_name.entry(name).or_insert(mirror);
+        mirrors.names.insert(mirror, name);
+
+        Ok(mirror)
+    }
+
+    /// Returns the name of the class represented by a `java.lang.Class` instance.
+    pub(crate) fn class_mirror_name(&self, mirror: usize) -> Option<&'a str> {
+        self.class_mirrors
+            .lock()
+            .unwrap()
+            .names
+            .get(&mirror)
+            .copied()
+    }
+
+    /// Returns the `java.lang.Thread` instance for the host thread, named `main`, creating it the
+    /// first time it's needed.
+    pub(crate) fn current_thread(&self) -> Result<usize> {
+        if let Some(thread) = self.threads.borrow().current {
+            return Ok(thread);
+        }
+
+        let thread = self.alloc_object(self.load_class_file("java/lang/Thread")?)?;
+        self.init_thread(thread, Some("main"), 0)?;
+        // JVMTI_THREAD_STATE_ALIVE | JVMTI_THREAD_STATE_RUNNABLE
+        self.set_field(thread, "threadStatus", "I", JvmValue::Int(0x0005))?;
+
+        self.threads.borrow_mut().current = Some(thread);
+
+        Ok(thread)
+    }
+
+    /// Initializes the fields of a newly allocated `java.lang.Thread`, as its constructors would.
+    /// Threads created without a name are numbered, e.g. `Thread-0`.
+    pub(crate) fn init_thread(
+        &self,
+        thread: usize,
+        name: Option<&'a str>,
+        target: usize,
+    ) -> Result<()> {
+        let (tid, name) = {
+            let mut threads = self.threads.borrow_mut();
+
+            threads.next_tid += 1;
+
+            let name = match name {
+                Some(name) => name,
+                None => {
+                    let number = threads.next_number;
+                    threads.next_number += 1;
+                    self.alloc_str(&format!("Thread-{number}"))
+                }
+            };
+
+            (threads.next_tid, name)
+        };
+
+        self.set_field(
+            thread,
+            "name",
+            "Ljava/lang/String;",
+            JvmValue::StringConst(name),
+        )?;
+        self.set_field(thread, "tid", "J", JvmValue::Long(tid))?;
+        self.set_field(thread, "priority", "I", JvmValue::Int(5))?;
+        self.set_field(
+            thread,
+            "target",
+            "Ljava/lang/Runnable;",
+            JvmValue::Reference(target),
+        )?;
+
+        Ok(())
+    }
+
+    /// Returns whether a `java.lang.Thread` instance is the one for the host thread.
+    pub(crate) fn is_current_thread(&self, thread: usize) -> bool {
+        self.threads.borrow().current == Some(thread)
+    }
+
+    /// Returns a handle which other host threads can use to interrupt the Java code running in
+    /// the vm.
+    pub fn handle(&self) -> VmHandle {
+        VmHandle {
+            signals: self.signals.clone(),
+        }
+    }
+
+    /// Sets the interrupt status of the vm's thread, as if by `Thread.interrupt`.
+    pub(crate) fn interrupt(&self) {
+        self.signals.interrupt();
+    }
+
+    /// Fails with [`Error::Stopped`] if the vm has been stopped by [`VmHandle::stop`]. This is
+    /// checked whenever a method is called and on backward branches, so that no loop can keep
+    /// running.
+    pub(crate) fn check_stopped(&self) -> Result<()> {
+        match self.signals.stopped.load(Ordering::Relaxed) {
+            true => Err(Error::Stopped),
+            false => Ok(()),
+        }
+    }
+
+    /// Clears the interrupt status of the vm's thread, returning the previous status.
+    pub(crate) fn take_interrupted(&self) -> bool {
+        mem::take(&mut *self.signals.interrupted.lock().unwrap())
+    }
+
+    /// Blocks the current thread for a duration, or until it's interrupted or the vm is stopped.
+    /// Returns whether it was interrupted, in which case its interrupt status is cleared.
+    pub(crate) fn sleep(&self, duration: Duration) -> bool {
+        let deadline = Instant::now().checked_add(duration);
+        let mut interrupted = self.signals.interrupted.lock().unwrap();
+
+        while !*interrupted && !self.signals.stopped.load(Ordering::Relaxed) {
+            let remaining = match deadline {
+                Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+                None => Duration::MAX,
+            };
+            if remaining.is_zero() {
+                break;
+            }
+
+            interrupted = self
+                .signals
+                .wake
+                .wait_timeout(interrupted, remaining)
+                .unwrap()
+                .0;
+        }
+
+        mem::take(&mut *interrupted)
+    }
+
+    /// Allocates zeroed memory on the heap, throwing `OutOfMemoryError` if the heap is full.
+    pub(crate) fn alloc(&self, layout: Layout) -> Result<NonNull<u8>> {
+        if let Some(budget) = &self.budget {
+            budget.record_allocation(layout.size())?;
+        }
+
+        let ptr = self
+            .heap
+            .lock()
+            .unwrap()
+            .try_alloc_layout(layout)
+            .map_err(|_| JavaException::new("java/lang/OutOfMemoryError", "Java heap space"))?;
+        // SAFETY: The allocation is valid for `layout.size()` bytes.
+        unsafe { std::ptr::write_bytes(ptr.as_ptr(), 0, layout.size()) };
+        Ok(ptr)
+    }
+
+    /// Returns whether assertions should be enabled for a class, which is only true for classes
+    /// outside of the JDK.
+    pub(crate) fn assertions_enabled(&self, class: &Class) -> bool {
+        self.assertions
+            && (self.defining_loader(class) != 0 || self.class_path.find(class.name()).is_some())
+    }
+
+    /// Allocates a new instance of a class, with all fields set to their default values.
+    pub(crate) fn alloc_object(&self, class: &'a Class<'a>) -> Result<usize> {
+        let fields_layout = Layout::array::<JvmValue>(class.fields().len())?;
+        let (object_layout, fields_offset) =
+            Layout::new::<RefTypeHeader>().extend(fields_layout)?;
+
+        let ptr = self.alloc(object_layout.pad_to_align())?;
+
+        unsafe {
+            ptr.as_ptr()
+                .cast::<RefTypeHeader>()
+                .write(RefTypeHeader::Object(ObjectHeader {
+                    class: NonNull::from(class).cast(),
+                }));
+
+            let fields = ptr.as_ptr().add(fields_offset).cast::<JvmValue>();
+
+            for (i, field) in class.fields().iter().enumerate() {
+                fields.add(i).write(match &field.descriptor.field_type {
+                    FieldType::Base(t) => match t {
+                        // Stored as ints, as they are on the operand stack
+                        BaseType::Byte
+                        | BaseType::Char
+                        | BaseType::Int
+                        | BaseType::Short
+                        | BaseType::Boolean => JvmValue::Int(0),
+                        BaseType::Double => JvmValue::Double(0.0),
+                        BaseType::Float => JvmValue::Float(0.0),
+                        BaseType::Long => JvmValue::Long(0),
+                        BaseType::Object(_) => JvmValue::Reference(0),
+                    },
+                    FieldType::Array(_, _) => JvmValue::Reference(0),
+                });
+            }
+        }
+
+        let reference = ptr.as_ptr() as usize;
+        self.record_allocation(Allocation {
+            reference,
+            size: object_layout.pad_to_align().size(),
+            kind: AllocationKind::Object(class),
+        });
+
+        Ok(reference)
+    }
+
+    /// Sets the value of an instance field of an object.
+    pub(crate) fn set_field(
+        &self,
+        object: usize,
+        name: &str,
+        descriptor: &str,
+        value: JvmValue<'a>,
+    ) -> Result<()> {
+        let header =
+            unsafe { (object as *mut RefTypeHeader).as_mut() }.wrap_err("object is null")?;
+
+        let RefTypeHeader::Object(ObjectHeader { class }) = header else {
+            bail!("expected an object");
+        };
+
+        let class = unsafe { class.as_ref() };
+        let ordinal = class
+            .field_ordinal(name, descriptor)
+            .wrap_err_with(|| format_err!("field not found: {}.{name}", class.name()))?;
+
+        unsafe { header.object_data()?[ordinal] = value };
+
+        Ok(())
+    }
+
+    /// Returns the value of an instance field of an object.
+    pub(crate) fn get_field(
+        &self,
+        object: usize,
+        name: &str,
+        descriptor: &str,
+    ) -> Result<JvmValue<'a>> {
+        let header =
+            unsafe { (object as *mut RefTypeHeader).as_mut() }.wrap_err("object is null")?;
+
+        let RefTypeHeader::Object(ObjectHeader { class }) = header else {
+            bail!("expected an object");
+        };
+
+        let class = unsafe { class.as_ref() };
+        let ordinal = class
+            .field_ordinal(name, descriptor)
+            .wrap_err_with(|| format_err!("field not found: {}.{name}", class.name()))?;
+
+        Ok(unsafe { header.object_data()?[ordinal].clone() })
+    }
+
+    /// Allocates a new array, with all elements set to their default values.
+    pub(crate) fn alloc_array(
+        &self,
+        element_type: ArrayElementType,
+        length: usize,
+    ) -> Result<usize> {
+        let element_layout = element_type.element_layout();
+        let array_data_layout =
+            Layout::from_size_align(element_layout.size() * length, element_layout.align())?;
+        let (array_layout, offset) = Layout::new::<RefTypeHeader>().extend(array_data_layout)?;
+
+        let ptr = self.alloc(array_layout.pad_to_align())?;
+
+        unsafe {
+            ptr.as_ptr()
+                .cast::<RefTypeHeader>()
+                .write(RefTypeHeader::Array(ArrayHeader {
+                    element_type,
+                    length,
+                }));
+
+            // Primitive elements are already zeroed
+            if let ArrayElementType::Reference = element_type {
+                let elements = ptr.as_ptr().add(offset).cast::<JvmValue>();
+                for i in 0..length {
+                    elements.add(i).write(JvmValue::Reference(0));
+                }
+            }
+        }
+
+        let reference = ptr.as_ptr() as usize;
+        self.record_allocation(Allocation {
+            reference,
+            size: array_layout.pad_to_align().size(),
+            kind: AllocationKind::Array {
+                element_type: match element_type {
+                    ArrayElementType::Primitive(t) => Some(t),
+                    ArrayElementType::Reference => None,
+                },
+                length,
+            },
+        });
+
+        Ok(reference)
+    }
+
+    fn record_allocation(&self, allocation: Allocation<'a>) {
+        for hook in &self.hooks {
+            hook.on_allocation(self, &allocation);
+        }
+    }
+
+    /// Returns the contents of `java.lang.StringBuilder` instances, keyed by object.
+    pub(crate) fn string_builders(&self) -> MutexGuard<HashMap<usize, String>> {
+        self.string_builders.lock().unwrap()
+    }
+
+    /// Returns the input which `java.util.Scanner` instances have read but not consumed yet,
+    /// keyed by object.
+    pub(crate) fn scanner_input(&self) -> MutexGuard<HashMap<usize, String>> {
+        self.scanner_input.lock().unwrap()
+    }
+
+    /// Moves the contents of a `java.lang.StringBuilder` from the StringBuilder intrinsic into
+    /// the builder's fields, so that the JDK implementation can read them. The JDK
+    /// implementation is used for the builder from then on. This does nothing if the builder's
+    /// contents are already in its fields.
+    pub(crate) fn materialize_string_builder(&self, object: usize) -> Result<()> {
+        let Some(contents) = self.string_builders().remove(&object) else {
+            return Ok(());
+        };
+
+        let (coder, mut bytes) = compact_string(&contents);
+        let count = bytes.len() >> coder;
+
+        // Leaves room to append to the builder, like the JDK's default capacity
+        bytes.resize(bytes.len() + (16 << coder), 0);
+
+        let value = self.alloc_array(ArrayElementType::Primitive(ArrayType::Byte), bytes.len())?;
+        unsafe { (*(value as *mut RefTypeHeader)).array_data::<u8>()? }.copy_from_slice(&bytes);
+
+        self.set_field(object, "value", "[B", JvmValue::Reference(value))?;
+        self.set_field(object, "coder", "B", JvmValue::Int(coder))?;
+        self.set_field(object, "count", "I", JvmValue::Int(count.try_into()?))?;
+
+        Ok(())
+    }
+
+    /// Returns the cache of boxed primitives, keyed by wrapper class name and value.
+    pub(crate) fn boxes(&self) -> MutexGuard<HashMap<(&'static str, i64), usize>> {
+        self.boxes.lock().unwrap()
+    }
+
+    /// Returns the canonical instance of a string, which is identical to the instances returned
+    /// for any equal strings.
+    pub(crate) fn intern(&self, s: &str) -> &'a str {
+        let mut interned = self.interned.lock().unwrap();
+        if let Some(s) = interned.get(s) {
+            return s;
+        }
+
+        let s = self.alloc_str(s);
+        interned.insert(s);
+        s
+    }
+
+    /// Returns the UTF-16 code units of a string, which is how Java code indexes it. They're only
+    /// encoded once for each string, so that indexing a string in a loop doesn't take time
+    /// proportional to its length on every iteration.
+    pub(crate) fn string_utf16(&self, s: &'a str) -> &'a [u16] {
+        let mut string_objects = self.string_objects.lock().unwrap();
+        string_objects
+            .utf16
+            .entry((s.as_ptr() as usize, s.len()))
+            .or_insert_with(|| {
+                self.arena
+                    .alloc_slice_copy(&s.encode_utf16().collect::<Vec<_>>())
+            })
+    }
+
+    /// Copies a string into the arena, so that it lives as long as the vm.
+    pub(crate) fn alloc_str(&self, s: &str) -> &'a str {
+        self.arena.alloc_str(s)
+    }
+
+    pub(crate) fn stdout(&self) -> MutexGuard<Box<dyn io::Write + Send + 'a>> {
+        self.stdout.lock().unwrap()
+    }
+
+    /// Reads from one of the standard streams, identified by its file descriptor. Returns the
+    /// number of bytes read, which is 0 at the end of the stream.
+    pub(crate) fn read_fd(&self, fd: i32, buf: &mut [u8]) -> Result<usize> {
+        match fd {
+            0 => loop {
+                match self.stdin.lock().unwrap().read(buf) {
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    result => break Ok(result?),
+                }
+            },
+            _ => bail!(JavaException::new(
+                "java/io/IOException",
+                "Bad file descriptor"
+            )),
+        }
+    }
+
+    /// Writes to one of the standard streams, identified by its file descriptor.
+    pub(crate) fn write_fd(&self, fd: i32, bytes: &[u8]) -> Result<()> {
+        match fd {
+            1 => self.stdout.lock().unwrap().write_all(bytes)?,
+            2 => self.stderr.lock().unwrap().write_all(bytes)?,
+            _ => bail!(JavaException::new(
+                "java/io/IOException",
+                "Bad file descriptor"
+            )),
+        }
+        Ok(())
+    }
+
+    /// Flushes one of the standard streams, identified by its file descriptor.
+    pub(crate) fn flush_fd(&self, fd: i32) -> Result<()> {
+        match fd {
+            1 => self.stdout.lock().unwrap().flush()?,
+            2 => self.stderr.lock().unwrap().flush()?,
+            _ => bail!(JavaException::new(
+                "java/io/IOException",
+                "Bad file descriptor"
+            )),
+        }
+        Ok(())
+    }
+
+    /// Writes an archive of the JDK classes that have been loaded, which can be used to load them
+    /// faster in later runs with [`Vm::with_class_archive`].
+    pub fn write_class_archive(&self, path: impl AsRef<Path>) -> Result<()> {
+        let (Some(java_home), Some(jimage)) = (&self.java_home, self.jimage()?) else {
+            bail!("class archives can only be created with a JDK");
+        };
+
+        // Classes defined by the bootstrap loader at runtime, rather than read from the jimage,
+        // can't be archived
+        let class_files = self.class_files.lock().unwrap().clone();
+        let mut classes = Vec::new();
+        for (class, bytes) in class_files {
+            if self.defining_loader(class) == 0 && jimage.find_class(class.name())?.is_some() {
+                classes.push((class, bytes));
+            }
+        }
+        classes.sort_by_key(|(class, _)| class.name());
+
+        ClassArchive::write(
+            path.as_ref(),
+            JImageStamp::new(&jimage::jimage_path(java_home))?,
+            classes.iter().map(|(class, bytes)| {
+                let mut code = vec![None; class.class_file().methods.len()];
+                for (_, _, method) in class.declared_methods() {
+                    code[method.slot] = method
+                        .body
+                        .as_ref()
+                        .map(|body| (&body.code[..], &body.offsets[..]));
+                }
+                (class.name(), *bytes, class.class_file(), code)
+            }),
+        )
+    }
+
+    /// Takes a snapshot of the loaded classes, their static fields and the objects reachable from
+    /// them, which can be restored into a new vm with [`Vm::restore_snapshot`]. Java code mustn't
+    /// be running in the vm while the snapshot is taken.
+    pub fn snapshot(&self) -> Result<Snapshot> {
+        let initialized = {
+            let initialization = self.initialization.borrow();
+            if initialization
+                .values()
+                .any(|state| matches!(state, Initialization::InProgress))
+            {
+                bail!("can't take a snapshot while a class is being initialized");
+            }
+            initialization.keys().copied().collect::<HashSet<_>>()
+        };
+
+        let class_files = self.class_files.lock().unwrap().clone();
+        let interned = self.interned.lock().unwrap();
+        let mut builder = SnapshotBuilder::new(&class_files, &interned);
+
+        for (index, (class, _)) in class_files.iter().enumerate() {
+            let loader = builder.reference(self.defining_loader(class));
+
+            let mut static_fields = Vec::new();
+            for (name, descriptor, value) in class.static_fields() {
+                let value = builder.value(&value.lock().unwrap())?;
+                static_fields.push((name.to_owned(), descriptor.to_owned(), value));
+            }
+
+            let snapshot_class = &mut builder.snapshot().classes[index];
+            snapshot_class.loader = loader;
+            snapshot_class.initialized = initialized.contains(class.name());
+            snapshot_class.static_fields = static_fields;
+        }
+
+        for (name, mirror) in &self.class_mirrors.lock().unwrap().by_name {
+            let mirror = builder.reference(*mirror);
+            builder
+                .snapshot()
+                .class_mirrors
+                .push((name.to_string(), mirror));
+        }
+
+        for ((class, value), object) in self.boxes.lock().unwrap().iter() {
+            let object = builder.reference(*object);
+            builder
+                .snapshot()
+                .boxes
+                .push((class.to_string(), *value, object));
+        }
+
+        if let Some(loaders) = *self.builtin_loaders.lock().unwrap() {
+            let loaders = (
+                builder.reference(loaders.platform),
+                builder.reference(loaders.app),
+            );
+            builder.snapshot().builtin_loaders = Some(loaders);
+        }
+
+        for hook in self.shutdown_hooks.lock().unwrap().iter() {
+            let hook = builder.reference(*hook);
+            builder.snapshot().shutdown_hooks.push(hook);
+        }
+
+        for (object, contents) in self.string_builders.lock().unwrap().iter() {
+            let object = builder.reference(*object);
+            builder
+                .snapshot()
+                .string_builders
+                .push((object, contents.clone()));
+        }
+
+        {
+            let threads = self.threads.borrow();
+            let current_thread = threads.current;
+            let snapshot = builder.snapshot();
+            snapshot.next_tid = threads.next_tid;
+            snapshot.next_thread_number = threads.next_number;
+            drop(threads);
+
+            if let Some(thread) = current_thread {
+                let thread = builder.reference(thread);
+                builder.snapshot().current_thread = thread;
+            }
+        }
+
+        builder.finish()
+    }
+
+    /// Writes a heap dump in HotSpot's HPROF format, which can be opened in tools like Eclipse MAT
+    /// and VisualVM. Like a snapshot, it has the objects reachable from the loaded classes, so
+    /// objects which are only referenced by running methods are left out.
+    pub fn write_heap_dump(&self, out: impl Write) -> Result<()> {
+        let snapshot = self.snapshot()?;
+        let classes = snapshot
+            .classes
+            .iter()
+            .map(|class| {
+                self.find_class(&class.name)
+                    .wrap_err_with(|| format_err!("class isn't loaded: {}", class.name))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        hprof::write_heap_dump(&snapshot, &classes, self.time.system_time(), out)
+    }
+
+    /// Restores a snapshot taken with [`Vm::snapshot`], which has to be done before the vm loads
+    /// any classes. Classes which had been initialized in the snapshot aren't initialized again.
+    ///
+    /// The vm keeps its own configuration, like its class path and system properties. Of the
+    /// threads that had called into the vm, only the one which took the snapshot is restored, as
+    /// the thread which restores it.
+    pub fn restore_snapshot(&self, snapshot: &Snapshot) -> Result<()> {
+        if !self.classes.borrow().is_empty() {
+            bail!("a snapshot can only be restored before any classes are loaded");
+        }
+
+        let strings = snapshot
+            .strings
+            .iter()
+            .map(|string| match string.interned {
+                true => self.intern(&string.value),
+                false => self.alloc_str(&string.value),
+            })
+            .collect::<Vec<_>>();
+
+        let mut classes = Vec::with_capacity(snapshot.classes.len());
+        for snapshot_class in &snapshot.classes {
+            let name = &snapshot_class.name;
+            let bytes = self.arena.alloc_slice_copy(&snapshot_class.bytes);
+            let class_file = self
+                .read_class_file(bytes)
+                .wrap_err_with(|| format_err!("failed to read class file '{name}'"))?;
+
+            if class_file.this_class_name() != Some(name.as_str()) {
+                bail!("class file of {name} is for another class");
+            }
+
+            // Super classes are loaded first, so they come before their subclasses
+            let super_class = class_file
+                .super_class_name()
+                .map(|super_name| {
+                    self.find_class(super_name).wrap_err_with(|| {
+                        format_err!("super class of {name} isn't in the snapshot")
+                    })
+                })
+                .transpose()?;
+
+            classes.push(self.add_class(class_file, bytes, None, super_class, 0, "snapshot")?);
+        }
+
+        let mut addresses = Vec::with_capacity(snapshot.objects.len());
+        for object in &snapshot.objects {
+            addresses.push(match object {
+                SnapshotObject::Object { class, .. } => {
+                    self.alloc_object(classes[*class as usize])?
+                }
+                SnapshotObject::PrimitiveArray {
+                    element_type,
+                    length,
+                    ..
+                } => self.alloc_array(ArrayElementType::Primitive(*element_type), *length)?,
+                SnapshotObject::ReferenceArray(elements) => {
+                    self.alloc_array(ArrayElementType::Reference, elements.len())?
+                }
+            });
+        }
+
+        let reference = |r| snapshot::address(&addresses, r);
+        let value = |value: &snapshot::Value| value.to_jvm(&addresses, &strings);
+
+        for (object, &address) in snapshot.objects.iter().zip(&addresses) {
+            let header = unsafe { &mut *(address as *mut RefTypeHeader) };
+            match object {
+                SnapshotObject::Object { class, fields } => {
+                    let data = unsafe { header.object_data()? };
+                    if data.len() != fields.len() {
+                        bail!(
+                            "expected {} fields in instance of {}, found {}",
+                            data.len(),
+                            classes[*class as usize].name(),
+                            fields.len()
+                        );
+                    }
+                    for (slot, field) in data.iter_mut().zip(fields) {
+                        *slot = value(field);
+                    }
+                }
+                SnapshotObject::PrimitiveArray { bytes, .. } => unsafe {
+                    // The length was checked against the element type when the snapshot was read
+                    ptr::copy_nonoverlapping(bytes.as_ptr(), header.array_data_ptr()?, bytes.len());
+                },
+                SnapshotObject::ReferenceArray(elements) => {
+                    let data = unsafe { header.array_data::<JvmValue>()? };
+                    for (slot, element) in data.iter_mut().zip(elements) {
+                        *slot = value(element);
+                    }
+                }
+            }
+        }
+
+        {
+            let mut initialization = self.initialization.borrow_mut();
+            let mut defining_loaders = self.defining_loaders.write().unwrap();
+
+            for (class, snapshot_class) in classes.iter().zip(&snapshot.classes) {
+                for (name, descriptor, field) in class.static_fields() {
+                    let snapshot_field = snapshot_class
+                        .static_fields
+                        .iter()
+                        .find(|(n, d, _)| n == name && d == descriptor);
+                    if let Some((_, _, snapshot_value)) = snapshot_field {
+                        *field.lock().unwrap() = value(snapshot_value);
+                    }
+                }
+
+                if snapshot_class.initialized {
+                    initialization.insert(class.name(), Initialization::Done);
+                }
+
+                if snapshot_class.loader != 0 {
+                    defining_loaders.insert(class.name(), reference(snapshot_class.loader));
+                }
+            }
+        }
+
+        {
+            let mut mirrors = self.class_mirrors.lock().unwrap();
+            for (name, mirror) in &snapshot.class_mirrors {
+                let name = self.alloc_str(name);
+                let mirror = reference(*mirror);
+                mirrors.by_name.insert(name, mirror);
+                mirrors.names.insert(mirror, name);
+            }
+        }
+
+        {
+            let mut boxes = self.boxes.lock().unwrap();
+            for (class, value, object) in &snapshot.boxes {
+                let class = natives::wrapper_class_name(class)
+                    .wrap_err_with(|| format_err!("not a wrapper class: {class}"))?;
+                boxes.insert((class, *value), reference(*object));
+            }
+        }
+
+        *self.builtin_loaders.lock().unwrap() =
+            snapshot
+                .builtin_loaders
+                .map(|(platform, app)| BuiltinLoaders {
+                    platform: reference(platform),
+                    app: reference(app),
+                });
+
+        self.shutdown_hooks
+            .lock()
+            .unwrap()
+            .extend(snapshot.shutdown_hooks.iter().map(|hook| reference(*hook)));
+
+        self.string_builders.lock().unwrap().extend(
+            snapshot
+                .string_builders
+                .iter()
+                .map(|(object, contents)| (reference(*object), contents.clone())),
+        );
+
+        let mut threads = self.threads.borrow_mut();
+        if snapshot.current_thread != 0 {
+            threads.current = Some(reference(snapshot.current_thread));
+        }
+        threads.next_tid = snapshot.next_tid;
+        threads.next_number = snapshot.next_thread_number;
+
+        Ok(())
+    }
+
+    /// Returns the class archive, if there is one and it was created from the vm's JDK.
+    fn class_archive(&self) -> Option<&ClassArchive> {
+        let archive = self.class_archive.as_ref()?;
+        let java_home = self.java_home.as_ref()?;
+
+        let valid = *self.class_archive_valid.get_or_init(|| {
+            JImageStamp::new(&jimage::jimage_path(java_home))
+                .is_ok_and(|stamp| stamp == archive.jimage_stamp())
+        });
+
+        valid.then_some(archive)
+    }
+
+    /// Returns the jimage of the JDK, or `None` if no JDK was found.
+    fn jimage(&self) -> Result<Option<&JImage>> {
+        if let Some(jimage) = self.jimage.get() {
+            return Ok(Some(jimage));
+        }
+
+        let Some(java_home) = &self.java_home else {
+            return Ok(None);
+        };
+
+        let jimage = JImage::open_java_home(java_home)?;
+
+        Ok(Some(self.jimage.get_or_init(|| jimage)))
+    }
+}
+
+/// The standard system properties which are always available.
+fn default_properties() -> HashMap<String, String> {
+    let os_name = match std::env::consts::OS {
+        "linux" => "Linux",
+        "macos" => "Mac OS X",
+        "windows" => "Windows",
+        os => os,
+    };
+
+    let line_separator = if cfg!(windows) { "\r\n" } else { "\n" };
+    let path_separator = if cfg!(windows) { ";" } else { ":" };
+
+    let user_dir = std::env::current_dir()
+        .map(|dir| dir.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    [
+        ("java.version", "17"),
+        ("java.specification.version", "17"),
+        ("os.name", os_name),
+        ("os.arch", std::env::consts::ARCH),
+        ("line.separator", line_separator),
+        ("file.separator", std::path::MAIN_SEPARATOR_STR),
+        ("path.separator", path_separator),
+        ("file.encoding", "UTF-8"),
+        ("user.dir", &user_dir),
+        // Overridden by `Vm::with_class_path`
+        ("java.class.path", "."),
+    ]
+    .into_iter()
+    .map(|(k, v)| (k.to_owned(), v.to_owned()))
+    .collect()
+}
+
+/// Returns whether an error was caused by a class not existing, rather than failing to load.
+pub(crate) fn is_missing_class(error: &Error, name: &str) -> bool {
+    matches!(error.root(), Error::ClassNotFound { name: missing, .. } if **missing == *name)
+}
+
+/// Returns the descriptor of the method implemented by a Rust function, skipping its first
+/// `receivers` parameters.
+fn native_fn_descriptor<'a, A, F: NativeFn<'a, A>>(_: &F, receivers: usize) -> String {
+    let (params, ret) = F::descriptors();
+    let params: String = params.iter().skip(receivers).map(|p| &**p).collect();
+    format!("({params}){ret}")
+}
+
+/// Checks that a native method's descriptor matches the types of the Rust function which
+/// implements it, which has an extra parameter for the receiver if the method isn't static.
+pub(crate) fn check_native_fn<'a, A, F: NativeFn<'a, A>>(
+    class: &str,
+    name: &str,
+    descriptor: &str,
+    f: &F,
+) -> Result<()> {
+    let (mut params, ret) = split_method_descriptor(descriptor)
+        .wrap_err_with(|| format!("invalid descriptor for native {class}.{name}: {descriptor}"))?;
+
+    if !F::matches(&params, ret) {
+        let receiver = match class {
+            "*" => "Ljava/lang/Object;".to_owned(),
+            class => format!("L{class};"),
+        };
+        params.insert(0, &receiver);
+
+        if !F::matches(&params, ret) {
+            bail!(
+                "native {class}.{name}{descriptor} doesn't match its function, which has type {}",
+                native_fn_descriptor(f, 0)
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Splits a method descriptor into the descriptors of its parameters and its return type.
+fn split_method_descriptor(descriptor: &str) -> Option<(Vec<&str>, &str)> {
+    fn field_type(descriptor: &str) -> Option<usize> {
+        let dimensions = descriptor.bytes().take_while(|&b| b == b'[').count();
+        let len = match descriptor.as_bytes().get(dimensions)? {
+            b'L' => descriptor[dimensions..].find(';')? + 1,
+            b'B' | b'C' | b'D' | b'F' | b'I' | b'J' | b'S' | b'Z' => 1,
+            _ => return None,
+        };
+        Some(dimensions + len)
+    }
+
+    let (mut params, ret) = descriptor.strip_prefix('(')?.split_once(')')?;
+
+    let mut param_descriptors = Vec::new();
+    while !params.is_empty() {
+        let (param, rest) = params.split_at(field_type(params)?);
+        param_descriptors.push(param);
+        params = rest;
+    }
+
+    if ret != "V" && field_type(ret)? != ret.len() {
+        return None;
+    }
+
+    Some((param_descriptors, ret))
+}
+
+/// Selects the method which implements an interface method for a class (JVMS §5.4.6). Methods
+/// declared by the class or its superclasses take priority over default methods, and default
+/// methods of subinterfaces over those of their superinterfaces.
+fn select_interface_method<'a>(
+    class: &'a Class<'a>,
+    interfaces: &[&'a Class<'a>],
+    name: &str,
+    descriptor: &str,
+) -> Option<ItableEntry<'a>> {
+    let is_instance_method = |method: &Method| {
+        !method
+            .access_flags
+            .intersects(MethodAccessFlags::STATIC | MethodAccessFlags::PRIVATE)
+    };
+
+    let mut current = Some(class);
+    while let Some(class) = current {
+        if let Some(method) = class
+            .method(name, descriptor)
+            .filter(|method| is_instance_method(method))
+        {
+            return Some(ItableEntry { class, method });
+        }
+        current = class.super_class();
+    }
+
+    interfaces.iter().find_map(|interface| {
+        let method = interface.method(name, descriptor)?;
+        let is_default = is_instance_method(method)
+            && !method.access_flags.contains(MethodAccessFlags::ABSTRACT);
+        is_default.then_some(ItableEntry {
+            class: interface,
+            method,
+        })
+    })
 }
@@ -0,0 +1,630 @@
+use std::io;
+
+use crate::class_file::constant_pool::{ConstantInfo, ConstantPool};
+use crate::class_file::{
+    Annotation, AttributeInfo, ClassFile, ElementValue, FieldInfo, MethodInfo, ModuleExports,
+    StackMapFrame, TargetInfo, TypeAnnotation, VerificationTypeInfo,
+};
+use crate::error::{bail, format_err, Context, Result};
+
+/// Serializes a [`ClassFile`], so that a class file which has been read (and possibly modified)
+/// can be written back out. Reading a class file and writing it again produces the same bytes.
+pub struct ClassWriter<W> {
+    writer: W,
+}
+
+impl<W: io::Write> ClassWriter<W> {
+    pub fn new(writer: W) -> ClassWriter<W> {
+        ClassWriter { writer }
+    }
+
+    pub fn write_class_file(&mut self, class_file: &ClassFile) -> Result<()> {
+        let constant_pool = &class_file.constant_pool;
+
+        self.write_u32(0xcafebabe)?;
+        self.write_u16(class_file.minor_version)?;
+        self.write_u16(class_file.major_version)?;
+        self.write_constant_pool(constant_pool)?;
+        self.write_u16(class_file.access_flags.bits())?;
+        self.write_u16(class_file.this_class)?;
+        self.write_u16(class_file.super_class)?;
+        self.write_u16_array(&class_file.interfaces)?;
+
+        self.write_length(class_file.fields.len())?;
+        for field in &class_file.fields {
+            self.write_field_info(constant_pool, field)?;
+        }
+
+        self.write_length(class_file.methods.len())?;
+        for method in &class_file.methods {
+            self.write_method_info(constant_pool, method)?;
+        }
+
+        self.write_attributes(constant_pool, &class_file.attributes)?;
+
+        Ok(())
+    }
+
+    fn write_constant_pool(&mut self, constant_pool: &ConstantPool) -> Result<()> {
+        // Longs and doubles are followed by an unused entry, which is counted but not written
+        self.write_length(constant_pool.0.len() + 1)?;
+
+        for constant in &constant_pool.0 {
+            match constant {
+                ConstantInfo::Unused => {}
+                ConstantInfo::Utf8(string) => {
+                    self.write_u8(1)?;
+                    self.write_utf8(string)?;
+                }
+                ConstantInfo::Integer(value) => {
+                    self.write_u8(3)?;
+                    self.write_u32(*value as u32)?;
+                }
+                ConstantInfo::Float(value) => {
+                    self.write_u8(4)?;
+                    self.write_u32(value.to_bits())?;
+                }
+                ConstantInfo::Long(value) => {
+                    self.write_u8(5)?;
+                    self.write_u64(*value as u64)?;
+                }
+                ConstantInfo::Double(value) => {
+                    self.write_u8(6)?;
+                    self.write_u64(value.to_bits())?;
+                }
+                ConstantInfo::Class(class) => {
+                    self.write_u8(7)?;
+                    self.write_u16(class.name_index)?;
+                }
+                ConstantInfo::String(string) => {
+                    self.write_u8(8)?;
+                    self.write_u16(string.string_index)?;
+                }
+                ConstantInfo::FieldRef(field_ref) => {
+                    self.write_u8(9)?;
+                    self.write_u16(field_ref.class_index)?;
+                    self.write_u16(field_ref.name_and_type_index)?;
+                }
+                ConstantInfo::MethodRef(method_ref) => {
+                    self.write_u8(10)?;
+                    self.write_u16(method_ref.class_index)?;
+                    self.write_u16(method_ref.name_and_type_index)?;
+                }
+                ConstantInfo::InterfaceMethodRef(method_ref) => {
+                    self.write_u8(11)?;
+                    self.write_u16(method_ref.class_index)?;
+                    self.write_u16(method_ref.name_and_type_index)?;
+                }
+                ConstantInfo::NameAndType(name_and_type) => {
+                    self.write_u8(12)?;
+                    self.write_u16(name_and_type.name_index)?;
+                    self.write_u16(name_and_type.descriptor_index)?;
+                }
+                ConstantInfo::MethodHandle(method_handle) => {
+                    self.write_u8(15)?;
+                    self.write_u8(method_handle.reference_kind)?;
+                    self.write_u16(method_handle.reference_index)?;
+                }
+                ConstantInfo::MethodType(method_type) => {
+                    self.write_u8(16)?;
+                    self.write_u16(method_type.descriptor_index)?;
+                }
+                ConstantInfo::Dynamic(dynamic) => {
+                    self.write_u8(17)?;
+                    self.write_u16(dynamic.bootstrap_method_attr_index)?;
+                    self.write_u16(dynamic.name_and_type_index)?;
+                }
+                ConstantInfo::InvokeDynamic(invoke_dynamic) => {
+                    self.write_u8(18)?;
+                    self.write_u16(invoke_dynamic.bootstrap_method_attr_index)?;
+                    self.write_u16(invoke_dynamic.name_and_type_index)?;
+                }
+                ConstantInfo::Module(module) => {
+                    self.write_u8(19)?;
+                    self.write_u16(module.name_index)?;
+                }
+                ConstantInfo::Package(package) => {
+                    self.write_u8(20)?;
+                    self.write_u16(package.name_index)?;
+                }
+                ConstantInfo::Unknown(tag) => bail!("can't write unknown constant pool tag: {tag}"),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_utf8(&mut self, string: &str) -> Result<()> {
+        // Strings without nul or supplementary characters are the same in UTF-8 and modified
+        // UTF-8, which is most of them
+        let bytes = if string.chars().all(|c| c != '\0' && c <= '\u{ffff}') {
+            string.as_bytes().to_vec()
+        } else {
+            encode_modified_utf8(string)
+        };
+
+        let length = u16::try_from(bytes.len())
+            .wrap_err_with(|| format_err!("string is too long: {} bytes", bytes.len()))?;
+
+        self.write_u16(length)?;
+        self.write_bytes(&bytes)?;
+
+        Ok(())
+    }
+
+    fn write_field_info(&mut self, constant_pool: &ConstantPool, field: &FieldInfo) -> Result<()> {
+        self.write_u16(field.access_flags.bits())?;
+        self.write_u16(field.name_index)?;
+        self.write_u16(field.descriptor_index)?;
+        self.write_attributes(constant_pool, &field.attributes)
+    }
+
+    fn write_method_info(
+        &mut self,
+        constant_pool: &ConstantPool,
+        method: &MethodInfo,
+    ) -> Result<()> {
+        self.write_u16(method.access_flags.bits())?;
+        self.write_u16(method.name_index)?;
+        self.write_u16(method.descriptor_index)?;
+        self.write_attributes(constant_pool, &method.attributes)
+    }
+
+    fn write_attributes(
+        &mut self,
+        constant_pool: &ConstantPool,
+        attributes: &[AttributeInfo],
+    ) -> Result<()> {
+        self.write_length(attributes.len())?;
+        for attribute in attributes {
+            self.write_attribute_info(constant_pool, attribute)?;
+        }
+        Ok(())
+    }
+
+    fn write_attribute_info(
+        &mut self,
+        constant_pool: &ConstantPool,
+        attribute: &AttributeInfo,
+    ) -> Result<()> {
+        // The length comes before the attribute, so it's written to a buffer first to find it
+        let mut info = ClassWriter::new(vec![]);
+
+        let name = match attribute {
+            AttributeInfo::Code(code) => {
+                info.write_u16(code.max_stack)?;
+                info.write_u16(code.max_locals)?;
+                info.write_u32(code.code.len() as u32)?;
+                info.write_bytes(code.code)?;
+                info.write_length(code.exception_table.len())?;
+                for entry in &code.exception_table {
+                    info.write_u16(entry.start_pc)?;
+                    info.write_u16(entry.end_pc)?;
+                    info.write_u16(entry.handler_pc)?;
+                    info.write_u16(entry.catch_type)?;
+                }
+                info.write_attributes(constant_pool, &code.attributes)?;
+                "Code"
+            }
+            AttributeInfo::LineNumberTable(table) => {
+                info.write_length(table.line_number_table.len())?;
+                for entry in &table.line_number_table {
+                    info.write_u16(entry.start_pc)?;
+                    info.write_u16(entry.line_number)?;
+                }
+                "LineNumberTable"
+            }
+            AttributeInfo::StackMapTable(table) => {
+                info.write_length(table.entries.len())?;
+                for frame in &table.entries {
+                    info.write_stack_map_frame(frame)?;
+                }
+                "StackMapTable"
+            }
+            AttributeInfo::BootstrapMethods(bootstrap_methods) => {
+                info.write_length(bootstrap_methods.bootstrap_methods.len())?;
+                for method in &bootstrap_methods.bootstrap_methods {
+                    info.write_u16(method.bootstrap_method_ref)?;
+                    info.write_u16_array(&method.bootstrap_arguments)?;
+                }
+                "BootstrapMethods"
+            }
+            AttributeInfo::InnerClasses(inner_classes) => {
+                info.write_length(inner_classes.classes.len())?;
+                for class in &inner_classes.classes {
+                    info.write_u16(class.inner_class_info_index)?;
+                    info.write_u16(class.outer_class_info_index)?;
+                    info.write_u16(class.inner_name_index)?;
+                    info.write_u16(class.inner_class_access_flags.bits())?;
+                }
+                "InnerClasses"
+            }
+            AttributeInfo::SourceFile(source_file) => {
+                info.write_u16(source_file.sourcefile_index)?;
+                "SourceFile"
+            }
+            AttributeInfo::Signature(signature) => {
+                info.write_u16(signature.signature_index)?;
+                "Signature"
+            }
+            AttributeInfo::RuntimeVisibleAnnotations(attribute) => {
+                info.write_annotations(&attribute.annotations)?;
+                "RuntimeVisibleAnnotations"
+            }
+            AttributeInfo::RuntimeInvisibleAnnotations(attribute) => {
+                info.write_annotations(&attribute.annotations)?;
+                "RuntimeInvisibleAnnotations"
+            }
+            AttributeInfo::RuntimeVisibleParameterAnnotations(attribute) => {
+                info.write_parameter_annotations(&attribute.parameter_annotations)?;
+                "RuntimeVisibleParameterAnnotations"
+            }
+            AttributeInfo::RuntimeInvisibleParameterAnnotations(attribute) => {
+                info.write_parameter_annotations(&attribute.parameter_annotations)?;
+                "RuntimeInvisibleParameterAnnotations"
+            }
+            AttributeInfo::RuntimeVisibleTypeAnnotations(attribute) => {
+                info.write_type_annotations(&attribute.annotations)?;
+                "RuntimeVisibleTypeAnnotations"
+            }
+            AttributeInfo::RuntimeInvisibleTypeAnnotations(attribute) => {
+                info.write_type_annotations(&attribute.annotations)?;
+                "RuntimeInvisibleTypeAnnotations"
+            }
+            AttributeInfo::Module(module) => {
+                info.write_u16(module.module_name_index)?;
+                info.write_u16(module.module_flags.bits())?;
+                info.write_u16(module.module_version_index)?;
+                info.write_length(module.requires.len())?;
+                for requires in &module.requires {
+                    info.write_u16(requires.requires_index)?;
+                    info.write_u16(requires.requires_flags.bits())?;
+                    info.write_u16(requires.requires_version_index)?;
+                }
+                info.write_module_exports(&module.exports)?;
+                info.write_module_exports(&module.opens)?;
+                info.write_u16_array(&module.uses)?;
+                info.write_length(module.provides.len())?;
+                for provides in &module.provides {
+                    info.write_u16(provides.provides_index)?;
+                    info.write_u16_array(&provides.provides_with_index)?;
+                }
+                "Module"
+            }
+            AttributeInfo::ModulePackages(module_packages) => {
+                info.write_u16_array(&module_packages.package_index)?;
+                "ModulePackages"
+            }
+            AttributeInfo::ModuleMainClass(module_main_class) => {
+                info.write_u16(module_main_class.main_class_index)?;
+                "ModuleMainClass"
+            }
+            AttributeInfo::Synthetic => "Synthetic",
+            AttributeInfo::Deprecated => "Deprecated",
+            AttributeInfo::Custom(custom) => {
+                return self.write_raw_attribute(custom.attribute_name_index, custom.info);
+            }
+            AttributeInfo::Malformed(malformed) => {
+                return self.write_raw_attribute(malformed.attribute_name_index, malformed.info);
+            }
+        };
+
+        let attribute_name_index = find_utf8(constant_pool, name)
+            .wrap_err_with(|| format_err!("failed to write {name} attribute"))?;
+
+        self.write_raw_attribute(attribute_name_index, &info.writer)
+    }
+
+    fn write_raw_attribute(&mut self, attribute_name_index: u16, info: &[u8]) -> Result<()> {
+        let length = u32::try_from(info.len())?;
+        self.write_u16(attribute_name_index)?;
+        self.write_u32(length)?;
+        self.write_bytes(info)?;
+        Ok(())
+    }
+
+    /// Writes the `exports` or `opens` directives of a module, which have the same layout.
+    fn write_module_exports(&mut self, exports: &[ModuleExports]) -> Result<()> {
+        self.write_length(exports.len())?;
+        for export in exports {
+            self.write_u16(export.package_index)?;
+            self.write_u16(export.flags.bits())?;
+            self.write_u16_array(&export.to_index)?;
+        }
+        Ok(())
+    }
+
+    fn write_parameter_annotations(
+        &mut self,
+        parameter_annotations: &[bumpalo::collections::Vec<Annotation>],
+    ) -> Result<()> {
+        let length = u8::try_from(parameter_annotations.len())?;
+        self.write_u8(length)?;
+        for annotations in parameter_annotations {
+            self.write_annotations(annotations)?;
+        }
+        Ok(())
+    }
+
+    fn write_type_annotations(&mut self, annotations: &[TypeAnnotation]) -> Result<()> {
+        self.write_length(annotations.len())?;
+        for annotation in annotations {
+            self.write_type_annotation(annotation)?;
+        }
+        Ok(())
+    }
+
+    fn write_type_annotation(&mut self, annotation: &TypeAnnotation) -> Result<()> {
+        self.write_u8(annotation.target_type)?;
+
+        match &annotation.target_info {
+            TargetInfo::TypeParameter {
+                type_parameter_index,
+            } => self.write_u8(*type_parameter_index)?,
+            TargetInfo::Supertype { supertype_index } => self.write_u16(*supertype_index)?,
+            TargetInfo::TypeParameterBound {
+                type_parameter_index,
+                bound_index,
+            } => {
+                self.write_u8(*type_parameter_index)?;
+                self.write_u8(*bound_index)?;
+            }
+            TargetInfo::Empty => {}
+            TargetInfo::FormalParameter {
+                formal_parameter_index,
+            } => self.write_u8(*formal_parameter_index)?,
+            TargetInfo::Throws { throws_type_index } => self.write_u16(*throws_type_index)?,
+            TargetInfo::LocalVar { table } => {
+                self.write_length(table.len())?;
+                for entry in table {
+                    self.write_u16(entry.start_pc)?;
+                    self.write_u16(entry.length)?;
+                    self.write_u16(entry.index)?;
+                }
+            }
+            TargetInfo::Catch {
+                exception_table_index,
+            } => self.write_u16(*exception_table_index)?,
+            TargetInfo::Offset { offset } => self.write_u16(*offset)?,
+            TargetInfo::TypeArgument {
+                offset,
+                type_argument_index,
+            } => {
+                self.write_u16(*offset)?;
+                self.write_u8(*type_argument_index)?;
+            }
+        }
+
+        let length = u8::try_from(annotation.target_path.len())?;
+        self.write_u8(length)?;
+        for entry in &annotation.target_path {
+            self.write_u8(entry.type_path_kind)?;
+            self.write_u8(entry.type_argument_index)?;
+        }
+
+        self.write_annotation(&annotation.annotation)
+    }
+
+    fn write_stack_map_frame(&mut self, frame: &StackMapFrame) -> Result<()> {
+        match frame {
+            StackMapFrame::Same { offset_delta } => {
+                if *offset_delta > 63 {
+                    bail!("offset delta too large for same frame: {offset_delta}");
+                }
+                self.write_u8(*offset_delta as u8)?;
+            }
+            StackMapFrame::SameLocals1StackItem {
+                offset_delta,
+                stack,
+            } => {
+                if *offset_delta > 63 {
+                    bail!(
+                        "offset delta too large for same_locals_1_stack_item frame: {offset_delta}"
+                    );
+                }
+                self.write_u8(64 + *offset_delta as u8)?;
+                self.write_verification_type_info(stack)?;
+            }
+            StackMapFrame::SameLocals1StackItemExtended {
+                offset_delta,
+                stack,
+            } => {
+                self.write_u8(247)?;
+                self.write_u16(*offset_delta)?;
+                self.write_verification_type_info(stack)?;
+            }
+            StackMapFrame::Chop { k, offset_delta } => {
+                if !(1..=3).contains(k) {
+                    bail!("invalid number of chopped locals: {k}");
+                }
+                self.write_u8(251 - k)?;
+                self.write_u16(*offset_delta)?;
+            }
+            StackMapFrame::SameExtended { offset_delta } => {
+                self.write_u8(251)?;
+                self.write_u16(*offset_delta)?;
+            }
+            StackMapFrame::Append {
+                offset_delta,
+                locals,
+            } => {
+                if !(1..=3).contains(&locals.len()) {
+                    bail!("invalid number of appended locals: {}", locals.len());
+                }
+                self.write_u8(251 + locals.len() as u8)?;
+                self.write_u16(*offset_delta)?;
+                for local in locals {
+                    self.write_verification_type_info(local)?;
+                }
+            }
+            StackMapFrame::Full {
+                offset_delta,
+                locals,
+                stack,
+            } => {
+                self.write_u8(255)?;
+                self.write_u16(*offset_delta)?;
+                self.write_length(locals.len())?;
+                for local in locals {
+                    self.write_verification_type_info(local)?;
+                }
+                self.write_length(stack.len())?;
+                for value in stack {
+                    self.write_verification_type_info(value)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_verification_type_info(&mut self, info: &VerificationTypeInfo) -> io::Result<()> {
+        match info {
+            VerificationTypeInfo::Top => self.write_u8(0),
+            VerificationTypeInfo::Integer => self.write_u8(1),
+            VerificationTypeInfo::Float => self.write_u8(2),
+            VerificationTypeInfo::Double => self.write_u8(3),
+            VerificationTypeInfo::Long => self.write_u8(4),
+            VerificationTypeInfo::Null => self.write_u8(5),
+            VerificationTypeInfo::UninitializedThis => self.write_u8(6),
+            VerificationTypeInfo::Object(index) => {
+                self.write_u8(7)?;
+                self.write_u16(*index)
+            }
+            VerificationTypeInfo::Uninitialized(offset) => {
+                self.write_u8(8)?;
+                self.write_u16(*offset)
+            }
+        }
+    }
+
+    fn write_annotations(&mut self, annotations: &[Annotation]) -> Result<()> {
+        self.write_length(annotations.len())?;
+        for annotation in annotations {
+            self.write_annotation(annotation)?;
+        }
+        Ok(())
+    }
+
+    fn write_annotation(&mut self, annotation: &Annotation) -> Result<()> {
+        self.write_u16(annotation.type_index)?;
+        self.write_length(annotation.element_value_pairs.len())?;
+        for pair in &annotation.element_value_pairs {
+            self.write_u16(pair.element_name_index)?;
+            self.write_element_value(&pair.value)?;
+        }
+        Ok(())
+    }
+
+    fn write_element_value(&mut self, value: &ElementValue) -> Result<()> {
+        match value {
+            ElementValue::Byte(index) => self.write_const_element_value(b'B', *index),
+            ElementValue::Char(index) => self.write_const_element_value(b'C', *index),
+            ElementValue::Double(index) => self.write_const_element_value(b'D', *index),
+            ElementValue::Float(index) => self.write_const_element_value(b'F', *index),
+            ElementValue::Int(index) => self.write_const_element_value(b'I', *index),
+            ElementValue::Long(index) => self.write_const_element_value(b'J', *index),
+            ElementValue::Short(index) => self.write_const_element_value(b'S', *index),
+            ElementValue::Boolean(index) => self.write_const_element_value(b'Z', *index),
+            ElementValue::String(index) => self.write_const_element_value(b's', *index),
+            ElementValue::Enum {
+                type_name_index,
+                const_name_index,
+            } => {
+                self.write_u8(b'e')?;
+                self.write_u16(*type_name_index)?;
+                self.write_u16(*const_name_index)?;
+                Ok(())
+            }
+            ElementValue::Class(index) => self.write_const_element_value(b'c', *index),
+            ElementValue::Annotation(annotation) => {
+                self.write_u8(b'@')?;
+                self.write_annotation(annotation)
+            }
+            ElementValue::Array(values) => {
+                self.write_u8(b'[')?;
+                self.write_length(values.len())?;
+                for value in values {
+                    self.write_element_value(value)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn write_const_element_value(&mut self, tag: u8, index: u16) -> Result<()> {
+        self.write_u8(tag)?;
+        self.write_u16(index)?;
+        Ok(())
+    }
+
+    /// Writes a table of constant pool indices, prefixed with its length.
+    fn write_u16_array(&mut self, values: &[u16]) -> Result<()> {
+        self.write_length(values.len())?;
+        for value in values {
+            self.write_u16(*value)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the length of a table, which must fit in a `u16`.
+    fn write_length(&mut self, length: usize) -> Result<()> {
+        let length =
+            u16::try_from(length).wrap_err_with(|| format_err!("table is too long: {length}"))?;
+        self.write_u16(length)?;
+        Ok(())
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.writer.write_all(bytes)
+    }
+
+    fn write_u8(&mut self, value: u8) -> io::Result<()> {
+        self.write_bytes(&value.to_be_bytes())
+    }
+
+    fn write_u16(&mut self, value: u16) -> io::Result<()> {
+        self.write_bytes(&value.to_be_bytes())
+    }
+
+    fn write_u32(&mut self, value: u32) -> io::Result<()> {
+        self.write_bytes(&value.to_be_bytes())
+    }
+
+    fn write_u64(&mut self, value: u64) -> io::Result<()> {
+        self.write_bytes(&value.to_be_bytes())
+    }
+}
+
+/// Finds the index of a `Utf8` constant, e.g. for the name of an attribute.
+fn find_utf8(constant_pool: &ConstantPool, string: &str) -> Result<u16> {
+    let index = constant_pool
+        .0
+        .iter()
+        .position(|constant| matches!(constant, ConstantInfo::Utf8(s) if *s == string))
+        .ok_or_else(|| format_err!("{string:?} is missing from the constant pool"))?;
+
+    Ok(index as u16 + 1)
+}
+
+/// Encodes modified UTF-8, the inverse of [`crate::reader`]'s decoding. Nul is encoded as two
+/// bytes, and supplementary characters as surrogate pairs with three bytes for each surrogate.
+fn encode_modified_utf8(string: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(string.len());
+
+    for unit in string.encode_utf16() {
+        match unit {
+            0x0001..=0x007f => bytes.push(unit as u8),
+            0x0000 | 0x0080..=0x07ff => {
+                bytes.push(0xc0 | (unit >> 6) as u8);
+                bytes.push(0x80 | (unit & 0x3f) as u8);
+            }
+            _ => {
+                bytes.push(0xe0 | (unit >> 12) as u8);
+                bytes.push(0x80 | ((unit >> 6) & 0x3f) as u8);
+                bytes.push(0x80 | (unit & 0x3f) as u8);
+            }
+        }
+    }
+
+    bytes
+}